@@ -0,0 +1,8 @@
+#!/bin/bash
+
+mkdir -p out
+
+# The binary doesn't actually occupy 8 GiB; the far section just has a high address.
+gcc -g -O1 -static -nostdlib -no-pie -fno-asynchronous-unwind-tables \
+    -Wl,--section-start=fartext=0x200001000 \
+    src/main.c -o out/main
@@ -0,0 +1,13 @@
+int low_function(int x) { return x + 1; }
+
+// Placed in its own section, which build.sh links 8 GiB above the
+// first segment, so that its relative address doesn't fit into a u32.
+__attribute__((section("fartext"))) int far_function(int x) {
+  return low_function(x) * 2;
+}
+
+void _start(void) {
+  far_function(3);
+  for (;;) {
+  }
+}
@@ -0,0 +1,6 @@
+#!/bin/bash
+
+mkdir -p out
+
+# The debug info says that this was compiled in /build/prefix-map.
+gcc -g -O1 -fdebug-prefix-map="$(pwd)"=/build/prefix-map src/main.c -o out/main
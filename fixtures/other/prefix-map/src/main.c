@@ -0,0 +1,7 @@
+__attribute__((noinline)) int add_one(int x) {
+  return x + 1;
+}
+
+int main(int argc, char** argv) {
+  return add_one(argc);
+}
@@ -0,0 +1,18 @@
+#!/bin/bash
+
+mkdir -p out
+
+# out/split is a stripped binary, and out/split.debug has the symbol table and
+# the DWARF. There's no .gnu_debuglink, so the two files can only be matched
+# up by their build ID.
+gcc -g -O1 -Wl,--build-id src/main.c -o out/split-unstripped
+objcopy --only-keep-debug out/split-unstripped out/split.debug
+strip --strip-all out/split-unstripped -o out/split
+rm out/split-unstripped
+
+# Debug files are also commonly found by build ID, in a .build-id directory.
+BUILD_ID=$(readelf -n out/split | sed -n 's/.*Build ID: //p')
+mkdir -p out/.build-id/${BUILD_ID:0:2}
+# Copy the file instead of symlinking it, because git checks out symlinks as
+# text files on Windows.
+cp out/split.debug out/.build-id/${BUILD_ID:0:2}/${BUILD_ID:2}.debug
@@ -0,0 +1,10 @@
+#include <stdio.h>
+
+__attribute__((noinline)) static int internal_function(int x) {
+  return x * 3 + 1;
+}
+
+int main(int argc, char** argv) {
+  printf("%d\n", internal_function(argc));
+  return 0;
+}
@@ -43,6 +43,10 @@ pub struct CategoryHandle(pub(crate) u16);
 impl CategoryHandle {
     /// The "Other" category. All profiles have this category.
     pub const OTHER: Self = CategoryHandle(0);
+
+    /// Not a real category; frames with this category inherit the category of
+    /// their caller. Serialized as null.
+    pub(crate) const INHERITED: Self = CategoryHandle(u16::MAX);
 }
 
 impl IntoSubcategoryHandle for CategoryHandle {
@@ -95,6 +99,21 @@ impl SubcategoryIndex {
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct SubcategoryHandle(pub(crate) CategoryHandle, pub(crate) SubcategoryIndex);
 
+impl SubcategoryHandle {
+    /// Makes a frame inherit the category and subcategory of its caller frame,
+    /// e.g. so that native frames called from a "GC" label frame are shown in
+    /// the "GC" category. Root frames with this subcategory end up in the
+    /// "Other" category.
+    pub const INHERIT: Self = SubcategoryHandle(CategoryHandle::INHERITED, SubcategoryIndex::OTHER);
+
+    /// Returns the category and subcategory, or `None` if the category is
+    /// inherited from the caller frame.
+    pub(crate) fn resolved(&self) -> Option<(CategoryHandle, SubcategoryIndex)> {
+        let SubcategoryHandle(category, subcategory) = *self;
+        (category != CategoryHandle::INHERITED).then_some((category, subcategory))
+    }
+}
+
 impl IntoSubcategoryHandle for SubcategoryHandle {
     fn into_subcategory_handle(self, _profile: &mut Profile) -> SubcategoryHandle {
         self
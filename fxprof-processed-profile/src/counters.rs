@@ -1,5 +1,9 @@
+use std::ops::Range;
+
 use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_derive::Serialize;
 
+use crate::sample_table::retain_by_index;
 use crate::serialization_helpers::SliceWithPermutation;
 use crate::timestamp::{
     SerializableTimestampSliceAsDeltas, SerializableTimestampSliceAsDeltasWithPermutation,
@@ -10,6 +14,20 @@ use crate::{GraphColor, ProcessHandle, Timestamp};
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct CounterHandle(pub(crate) usize);
 
+/// How a counter's graph is drawn, see
+/// [`Profile::set_counter_graph_type`](crate::Profile::set_counter_graph_type).
+#[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CounterGraphType {
+    /// The accumulated value as a filled area, e.g. for memory usage.
+    Stack,
+    /// The accumulated value as a line.
+    LineAccumulated,
+    /// The rate of change of the value as a line, e.g. for power usage from
+    /// energy deltas.
+    LineRate,
+}
+
 #[derive(Debug)]
 pub struct Counter {
     name: String,
@@ -19,6 +37,7 @@ pub struct Counter {
     pid: String,
     samples: CounterSamples,
     color: Option<GraphColor>,
+    graph_type: Option<CounterGraphType>,
 }
 
 impl Counter {
@@ -37,6 +56,7 @@ impl Counter {
             pid: pid.to_owned(),
             samples: CounterSamples::new(),
             color: None,
+            graph_type: None,
         }
     }
 
@@ -54,10 +74,25 @@ impl Counter {
             .add_sample(timestamp, value_delta, number_of_operations_delta)
     }
 
+    /// Removes the samples outside `range`, and makes the remaining sample
+    /// times relative to the start of the range.
+    pub fn trim(&mut self, range: &Range<Timestamp>) {
+        self.samples.retain_by_time(|time| range.contains(&time));
+        self.samples.rebase_times(range.start);
+    }
+
     pub fn set_color(&mut self, color: GraphColor) {
         self.color = Some(color);
     }
 
+    pub fn set_description(&mut self, description: &str) {
+        self.description = description.to_owned();
+    }
+
+    pub fn set_graph_type(&mut self, graph_type: CounterGraphType) {
+        self.graph_type = Some(graph_type);
+    }
+
     pub fn as_serializable(&self, main_thread_index: usize) -> impl Serialize + '_ {
         SerializableCounter {
             counter: self,
@@ -84,6 +119,9 @@ impl Serialize for SerializableCounter<'_> {
         if let Some(color) = self.counter.color {
             map.serialize_entry("color", &color)?;
         }
+        if let Some(graph_type) = self.counter.graph_type {
+            map.serialize_entry("graphType", &graph_type)?;
+        }
         map.end()
     }
 }
@@ -127,6 +165,22 @@ impl CounterSamples {
     }
 }
 
+impl CounterSamples {
+    pub fn retain_by_time(&mut self, mut keep: impl FnMut(Timestamp) -> bool) {
+        let keep: Vec<bool> = self.time.iter().map(|t| keep(*t)).collect();
+        retain_by_index(&mut self.time, &keep);
+        retain_by_index(&mut self.number, &keep);
+        retain_by_index(&mut self.count, &keep);
+    }
+
+    pub fn rebase_times(&mut self, new_reference: Timestamp) {
+        for time in &mut self.time {
+            *time = time.rebased_to(new_reference);
+        }
+        self.last_sample_timestamp = self.last_sample_timestamp.rebased_to(new_reference);
+    }
+}
+
 impl Serialize for CounterSamples {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         let len = self.time.len();
@@ -142,7 +196,9 @@ impl Serialize for CounterSamples {
             )?;
         } else {
             let mut indexes: Vec<usize> = (0..self.time.len()).collect();
-            indexes.sort_unstable_by_key(|index| self.time[*index]);
+            // A stable sort, so that samples with the same timestamp stay in the
+            // order in which they were added.
+            indexes.sort_by_key(|index| self.time[*index]);
             map.serialize_entry("count", &SliceWithPermutation(&self.count, &indexes))?;
             map.serialize_entry("number", &SliceWithPermutation(&self.number, &indexes))?;
             map.serialize_entry(
@@ -9,52 +9,105 @@ use serde::ser::{Serialize, Serializer};
 /// A thread only runs on one CPU at any time, and can get scheduled off and on
 /// the CPU between two samples. The CPU delta is the accumulation of time it
 /// was running on the CPU.
+///
+/// The unit in which CPU deltas are stored in the profile is set with
+/// [`Profile::set_cpu_delta_unit`](crate::Profile::set_cpu_delta_unit).
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct CpuDelta {
-    micros: u64,
+    /// Nanoseconds, or CPU cycles for deltas created with [`CpuDelta::from_cycles`].
+    value: u64,
 }
 
 impl From<Duration> for CpuDelta {
     fn from(duration: Duration) -> Self {
         Self {
-            micros: duration.as_micros() as u64,
+            value: duration.as_nanos() as u64,
         }
     }
 }
 
 impl CpuDelta {
     /// A CPU delta of zero.
-    pub const ZERO: Self = Self { micros: 0 };
+    pub const ZERO: Self = Self { value: 0 };
 
     /// Create a CPU delta from integer nanoseconds.
     pub fn from_nanos(nanos: u64) -> Self {
-        Self {
-            micros: nanos / 1000,
-        }
+        Self { value: nanos }
     }
 
     /// Create a CPU delta from integer microseconds.
     pub fn from_micros(micros: u64) -> Self {
-        Self { micros }
+        Self {
+            value: micros * 1000,
+        }
     }
 
     /// Create a CPU delta from float milliseconds.
     pub fn from_millis(millis: f64) -> Self {
         Self {
-            micros: (millis * 1_000.0) as u64,
+            value: (millis * 1_000_000.0) as u64,
         }
     }
 
+    /// Create a CPU delta from a number of CPU cycles. Only use this if the
+    /// profile's CPU delta unit is [`CpuDeltaUnit::Cycles`].
+    pub fn from_cycles(cycles: u64) -> Self {
+        Self { value: cycles }
+    }
+
     /// Whether the CPU delta is zero.
     pub fn is_zero(&self) -> bool {
-        self.micros == 0
+        self.value == 0
+    }
+
+    /// The value of this delta in the given unit.
+    pub(crate) fn value_in(&self, unit: CpuDeltaUnit) -> u64 {
+        match unit {
+            CpuDeltaUnit::Microseconds => self.value / 1000,
+            CpuDeltaUnit::Nanoseconds | CpuDeltaUnit::Cycles => self.value,
+        }
+    }
+}
+
+impl std::ops::Add for CpuDelta {
+    type Output = CpuDelta;
+
+    fn add(self, rhs: CpuDelta) -> CpuDelta {
+        Self {
+            value: self.value + rhs.value,
+        }
     }
 }
 
 impl Serialize for CpuDelta {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        // CPU deltas are serialized as float microseconds, because
-        // we set profile.meta.sampleUnits.threadCPUDelta to "µs".
-        self.micros.serialize(serializer)
+        self.value_in(CpuDeltaUnit::Microseconds)
+            .serialize(serializer)
+    }
+}
+
+/// The unit of the CPU deltas of all samples in the profile, used in
+/// [`Profile::set_cpu_delta_unit`](crate::Profile::set_cpu_delta_unit).
+#[derive(Debug, Default, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub enum CpuDeltaUnit {
+    /// CPU time in microseconds.
+    #[default]
+    Microseconds,
+    /// CPU time in nanoseconds, for CPU time measurements which are precise
+    /// enough.
+    Nanoseconds,
+    /// CPU cycles, which measure the work done by the CPU rather than time,
+    /// e.g. from a cycles performance counter. Use [`CpuDelta::from_cycles`].
+    Cycles,
+}
+
+impl CpuDeltaUnit {
+    /// The unit's name in `meta.sampleUnits.threadCPUDelta`.
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            CpuDeltaUnit::Microseconds => "µs",
+            CpuDeltaUnit::Nanoseconds => "ns",
+            CpuDeltaUnit::Cycles => "variable CPU cycles",
+        }
     }
 }
@@ -1,3 +1,6 @@
+//! The hash maps used by this crate. They use the Fx hasher, which has no random
+//! seed, so that profiles serialize to the same bytes for the same input.
+
 use indexmap::IndexSet;
 use rustc_hash::{FxHashMap, FxHashSet};
 
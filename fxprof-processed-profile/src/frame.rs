@@ -55,5 +55,40 @@ bitflags! {
         /// Set on frames which are not strictly JavaScript functions but which
         /// should be included in the JS-only call tree, such as DOM API calls.
         const IS_RELEVANT_FOR_JS = 0b00000010;
+
+        /// Set on JS or other managed-runtime frames which were executed by
+        /// an interpreter. Serialized as the "interpreter" implementation.
+        const IS_INTERPRETED = 0b00000100;
+
+        /// Set on frames which were executed by a baseline (non-optimizing)
+        /// JIT tier. Serialized as the "baseline" implementation.
+        const IS_BASELINE_JIT = 0b00001000;
+
+        /// Set on frames which were executed by an optimizing JIT tier.
+        /// Serialized as the "ion" implementation, the name of Firefox's
+        /// optimizing JIT.
+        const IS_OPTIMIZED_JIT = 0b00010000;
+    }
+}
+
+impl FrameFlags {
+    /// The flags which describe the function, rather than how this frame of
+    /// the function was executed.
+    pub(crate) const FUNC_FLAGS: FrameFlags =
+        FrameFlags::IS_JS.union(FrameFlags::IS_RELEVANT_FOR_JS);
+
+    /// The frame table's "implementation" string for frames with these flags,
+    /// which the profiler uses to break down JS frames by JIT tier. If more
+    /// than one tier is set, the most optimized one wins.
+    pub(crate) fn implementation(&self) -> Option<&'static str> {
+        if self.contains(FrameFlags::IS_OPTIMIZED_JIT) {
+            Some("ion")
+        } else if self.contains(FrameFlags::IS_BASELINE_JIT) {
+            Some("baseline")
+        } else if self.contains(FrameFlags::IS_INTERPRETED) {
+            Some("interpreter")
+        } else {
+            None
+        }
     }
 }
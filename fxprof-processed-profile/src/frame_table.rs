@@ -8,7 +8,7 @@ use crate::global_lib_table::{GlobalLibIndex, UsedLibraryAddressesCollector};
 use crate::native_symbols::NativeSymbolIndex;
 use crate::resource_table::ResourceTable;
 use crate::serialization_helpers::SerializableSingleValueColumn;
-use crate::string_table::StringHandle;
+use crate::string_table::{ProfileStringTable, StringHandle};
 use crate::SourceLocation;
 
 #[derive(Debug, Clone, Default)]
@@ -56,7 +56,10 @@ impl FrameInterner {
         self.contains_js_frame
     }
 
-    pub fn create_tables(&self) -> (FrameTable, FuncTable, ResourceTable) {
+    pub fn create_tables(
+        &self,
+        string_table: &ProfileStringTable,
+    ) -> (FrameTable, FuncTable, ResourceTable) {
         let len = self.frame_key_set.len();
         let mut func_col = Vec::with_capacity(len);
         let mut category_col = Vec::with_capacity(len);
@@ -66,6 +69,7 @@ impl FrameInterner {
         let mut address_col = Vec::with_capacity(len);
         let mut native_symbol_col = Vec::with_capacity(len);
         let mut inline_depth_col = Vec::with_capacity(len);
+        let mut implementation_col = Vec::with_capacity(len);
 
         let mut func_table = FuncTable::default();
         let mut resource_table = ResourceTable::default();
@@ -75,11 +79,19 @@ impl FrameInterner {
             let func = func_table.index_for_func(func_key, &mut resource_table);
 
             func_col.push(func);
-            let SubcategoryHandle(category, subcategory) = frame.subcategory;
-            category_col.push(category);
-            subcategory_col.push(subcategory);
+            let resolved = frame.subcategory.resolved();
+            category_col.push(resolved.map(|(category, _)| category));
+            subcategory_col.push(resolved.map(|(_, subcategory)| subcategory));
             line_col.push(frame.source_location.line);
             column_col.push(frame.source_location.col);
+            // The implementation strings are added to the string table when
+            // the frames are created, see Profile::intern_frame_implementation.
+            implementation_col.push(
+                frame
+                    .flags
+                    .implementation()
+                    .and_then(|s| string_table.existing_index_for_string(s)),
+            );
 
             match frame.variant {
                 InternalFrameVariant::Label => {
@@ -109,6 +121,7 @@ impl FrameInterner {
             address_col,
             native_symbol_col,
             inline_depth_col,
+            implementation_col,
         };
 
         (frame_table, func_table, resource_table)
@@ -117,13 +130,14 @@ impl FrameInterner {
 
 pub struct FrameTable {
     func_col: Vec<FuncIndex>,
-    category_col: Vec<CategoryHandle>,
-    subcategory_col: Vec<SubcategoryIndex>,
+    category_col: Vec<Option<CategoryHandle>>,
+    subcategory_col: Vec<Option<SubcategoryIndex>>,
     line_col: Vec<Option<u32>>,
     column_col: Vec<Option<u32>>,
     address_col: Vec<Option<u32>>,
     native_symbol_col: Vec<Option<NativeSymbolIndex>>,
     inline_depth_col: Vec<u16>,
+    implementation_col: Vec<Option<StringHandle>>,
 }
 
 impl Serialize for FrameTable {
@@ -142,6 +156,9 @@ impl Serialize for FrameTable {
         )?;
         map.serialize_entry("nativeSymbol", &self.native_symbol_col)?;
         map.serialize_entry("inlineDepth", &self.inline_depth_col)?;
+        if self.implementation_col.iter().any(Option::is_some) {
+            map.serialize_entry("implementation", &self.implementation_col)?;
+        }
         map.serialize_entry("innerWindowID", &SerializableSingleValueColumn(0, len))?;
         map.end()
     }
@@ -202,7 +219,8 @@ impl InternalFrame {
             name,
             file_path,
             lib,
-            flags,
+            // Frames executed by different JIT tiers belong to the same function.
+            flags: flags & FrameFlags::FUNC_FLAGS,
         }
     }
 }
@@ -5,7 +5,7 @@ use serde::ser::{Serialize, Serializer};
 
 use crate::fast_hash_map::{FastHashMap, FastIndexSet};
 use crate::string_table::ProfileStringTable;
-use crate::{LibraryInfo, StringHandle, SymbolTable};
+use crate::{LibraryBuildInfo, LibraryInfo, StringHandle, SymbolTable};
 
 #[derive(Debug)]
 pub struct GlobalLibTable {
@@ -14,6 +14,8 @@ pub struct GlobalLibTable {
     all_libs: FastIndexSet<LibraryInfo>, // append-only for stable LibraryHandles
     /// Any symbol tables for libraries in all_libs
     symbol_tables: FastHashMap<LibraryHandle, Arc<SymbolTable>>,
+    /// Any build information for libraries in all_libs
+    build_infos: FastHashMap<LibraryHandle, LibraryBuildInfo>,
     /// Indexed by `GlobalLibIndex.0`.
     used_libs: Vec<LibraryHandle>, // append-only for stable GlobalLibIndexes
     used_lib_map: FastHashMap<LibraryHandle, GlobalLibIndex>,
@@ -34,6 +36,7 @@ impl GlobalLibTable {
         Self {
             all_libs: FastIndexSet::default(),
             symbol_tables: FastHashMap::default(),
+            build_infos: FastHashMap::default(),
             used_libs: Vec::new(),
             used_lib_map: FastHashMap::default(),
         }
@@ -47,10 +50,18 @@ impl GlobalLibTable {
         self.all_libs.get_index(handle.0).unwrap()
     }
 
+    pub fn lib_count(&self) -> usize {
+        self.all_libs.len()
+    }
+
     pub fn set_lib_symbol_table(&mut self, library: LibraryHandle, symbol_table: Arc<SymbolTable>) {
         self.symbol_tables.insert(library, symbol_table);
     }
 
+    pub fn set_lib_build_info(&mut self, library: LibraryHandle, build_info: LibraryBuildInfo) {
+        self.build_infos.insert(library, build_info);
+    }
+
     pub fn index_for_used_lib(
         &mut self,
         lib_handle: LibraryHandle,
@@ -105,7 +116,22 @@ impl UsedLibraryAddressesCollector {
 
 impl Serialize for GlobalLibTable {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.collect_seq(self.used_libs.iter().map(|handle| &self.all_libs[handle.0]))
+        serializer.collect_seq(self.used_libs.iter().map(|handle| SerializableLib {
+            lib: &self.all_libs[handle.0],
+            build_info: self.build_infos.get(handle),
+        }))
+    }
+}
+
+struct SerializableLib<'a> {
+    lib: &'a LibraryInfo,
+    build_info: Option<&'a LibraryBuildInfo>,
+}
+
+impl Serialize for SerializableLib<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.lib
+            .serialize_with_build_info(self.build_info, serializer)
     }
 }
 
@@ -7,6 +7,12 @@
 //! information into it. To convert it to JSON, use [`serde_json`], for
 //! example [`serde_json::to_writer`] or [`serde_json::to_string`].
 //!
+//! The serialized profile only depends on the calls that were made to build it,
+//! and on their order: building the same profile twice produces byte-identical
+//! JSON. Tables like the string table and the frame table are ordered by
+//! first use, and threads, processes and samples with equal sort keys or
+//! timestamps keep the order in which they were added.
+//!
 //! ## Example
 //!
 //! ```
@@ -74,24 +80,24 @@ pub use category::{
     Category, CategoryHandle, IntoSubcategoryHandle, Subcategory, SubcategoryHandle,
 };
 pub use category_color::CategoryColor;
-pub use counters::CounterHandle;
-pub use cpu_delta::CpuDelta;
+pub use counters::{CounterGraphType, CounterHandle};
+pub use cpu_delta::{CpuDelta, CpuDeltaUnit};
 pub use frame::{FrameAddress, FrameFlags};
 pub use global_lib_table::LibraryHandle;
 pub use lib_mappings::LibMappings;
-pub use library_info::{LibraryInfo, Symbol, SymbolTable};
+pub use library_info::{LibraryBuildInfo, LibraryInfo, Symbol, SymbolTable};
 pub use markers::{
     DynamicSchemaMarker, DynamicSchemaMarkerField, DynamicSchemaMarkerFieldFormat,
-    DynamicSchemaMarkerGraph, DynamicSchemaMarkerSchema, FlowId, GraphColor, Marker, MarkerField,
-    MarkerFieldKind, MarkerFlowFieldFormat, MarkerGraph, MarkerGraphType, MarkerHandle,
-    MarkerLocations, MarkerNumberFieldFormat, MarkerStringFieldFormat, MarkerTiming,
+    DynamicSchemaMarkerGraph, DynamicSchemaMarkerSchema, FlowId, GraphColor, IpcMessageMarker,
+    Marker, MarkerField, MarkerFieldKind, MarkerFlowFieldFormat, MarkerGraph, MarkerGraphType,
+    MarkerHandle, MarkerLocations, MarkerNumberFieldFormat, MarkerStringFieldFormat, MarkerTiming,
     MarkerTypeHandle, Schema,
 };
 pub use native_symbols::NativeSymbolHandle;
 pub use process::ThreadHandle;
 pub use profile::{
-    FrameHandle, FrameSymbolInfo, Profile, SamplingInterval, SourceLocation, StackHandle,
-    TimelineUnit,
+    FrameHandle, FrameSymbolInfo, Profile, ProfileStats, SamplingInterval, SourceLocation,
+    StackHandle, TimelineUnit,
 };
 pub use reference_timestamp::{PlatformSpecificReferenceTimestamp, ReferenceTimestamp};
 pub use sample_table::WeightType;
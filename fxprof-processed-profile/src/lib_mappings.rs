@@ -94,6 +94,21 @@ impl<T> LibMappings<T> {
             .map(|m| (m.relative_address_at_start, m.value))
     }
 
+    /// Remove all mappings which lie entirely within the given address range,
+    /// for example after a `munmap` of that range. Mappings which only partially
+    /// overlap with the range are kept.
+    pub fn remove_mappings_in_range(&mut self, start_avma: u64, end_avma: u64) {
+        let contained_keys: Vec<u64> = self
+            .map
+            .range(start_avma..end_avma)
+            .filter(|(_, mapping)| mapping.end_avma <= end_avma)
+            .map(|(start_avma, _)| *start_avma)
+            .collect();
+        for key in contained_keys {
+            self.map.remove(&key);
+        }
+    }
+
     /// Clear all mappings.
     pub fn clear(&mut self) {
         self.map.clear();
@@ -157,5 +172,10 @@ mod test {
         assert_eq!(m.lookup(149), Some(&"100..150"));
         assert_eq!(m.lookup(200), Some(&"180..220"));
         assert_eq!(m.lookup(260), Some(&"255..270"));
+        m.remove_mappings_in_range(180, 260);
+        assert_eq!(m.lookup(200), None);
+        assert_eq!(m.lookup(230), None);
+        assert_eq!(m.lookup(260), Some(&"255..270"));
+        assert_eq!(m.lookup(149), Some(&"100..150"));
     }
 }
@@ -40,8 +40,12 @@ pub struct LibraryInfo {
     pub arch: Option<String>,
 }
 
-impl Serialize for LibraryInfo {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+impl LibraryInfo {
+    pub(crate) fn serialize_with_build_info<S: Serializer>(
+        &self,
+        build_info: Option<&LibraryBuildInfo>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
         let breakpad_id = self.debug_id.breakpad().to_string();
         let code_id = self.code_id.as_ref().map(|cid| cid.to_string());
         let mut map = serializer.serialize_map(None)?;
@@ -52,10 +56,37 @@ impl Serialize for LibraryInfo {
         map.serialize_entry("breakpadId", &breakpad_id)?;
         map.serialize_entry("codeId", &code_id)?;
         map.serialize_entry("arch", &self.arch)?;
+        if let Some(build_info) = build_info {
+            if let Some(compiler) = &build_info.compiler {
+                map.serialize_entry("compiler", compiler)?;
+            }
+            if let Some(opt_level) = &build_info.opt_level {
+                map.serialize_entry("optLevel", opt_level)?;
+            }
+        }
         map.end()
     }
 }
 
+impl Serialize for LibraryInfo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.serialize_with_build_info(None, serializer)
+    }
+}
+
+/// Information about how a library was built, which is stored with the library
+/// in the profile so that regressions can be traced back to specific builds.
+/// Set it with [`Profile::set_lib_build_info`](crate::Profile::set_lib_build_info).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct LibraryBuildInfo {
+    /// The compiler which built this library, for example
+    /// "rustc version 1.80.0 (051478957 2024-07-21)" or "GCC: (GNU) 13.2.0".
+    pub compiler: Option<String>,
+    /// The optimization level which the library was built with, for example
+    /// "-O2", if the library records it.
+    pub opt_level: Option<String>,
+}
+
 /// A symbol table which contains a list of [`Symbol`]s, used in [`LibraryInfo`].
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SymbolTable {
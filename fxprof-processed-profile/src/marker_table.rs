@@ -1,6 +1,9 @@
+use std::ops::Range;
+
 use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
 
 use crate::markers::{InternalMarkerSchema, MarkerFieldValueConsumer};
+use crate::sample_table::retain_by_index;
 use crate::serialization_helpers::SerializableOptionalTimestampColumn;
 use crate::string_table::{ProfileStringTable, StringHandle};
 use crate::{
@@ -17,6 +20,9 @@ pub struct MarkerTable {
     marker_phases: Vec<Phase>,
     marker_type_handles: Vec<MarkerTypeHandle>,
     marker_stacks: Vec<Option<usize>>,
+    /// The time at which each marker's stack was captured, if it was set with
+    /// [`MarkerTable::set_marker_cause_time`].
+    marker_cause_times: Vec<Option<Timestamp>>,
     /// The field values for any marker fields of [kind](`MarkerFieldFormat::kind`) [`MarkerFieldFormatKind::Number`].
     ///
     /// This Vec can contain zero or more values per marker, depending on the marker's
@@ -45,6 +51,13 @@ pub struct MarkerTable {
     ///
     /// Flow identifiers are serialized as string indexes.
     marker_field_flow_values: Vec<StringHandle>,
+    /// The marker index for each [`MarkerHandle`] which was handed out before
+    /// the last [`MarkerTable::trim`] call, or `None` if the trim removed the
+    /// marker. Handles of markers which were added after the trim are past the
+    /// end of this Vec.
+    trimmed_marker_indexes: Vec<Option<usize>>,
+    /// The number of markers which were kept by the last trim.
+    marker_count_after_trim: usize,
 }
 
 impl MarkerTable {
@@ -52,6 +65,10 @@ impl MarkerTable {
         Default::default()
     }
 
+    pub fn marker_count(&self) -> usize {
+        self.marker_name_string_indexes.len()
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn add_marker<T: DynamicSchemaMarker>(
         &mut self,
@@ -75,6 +92,7 @@ impl MarkerTable {
         self.marker_phases.push(phase);
         self.marker_type_handles.push(marker_type_handle);
         self.marker_stacks.push(None);
+        self.marker_cause_times.push(None);
 
         let MarkerTable {
             marker_field_string_values,
@@ -90,11 +108,36 @@ impl MarkerTable {
             string_table,
         });
 
-        MarkerHandle(self.marker_categories.len() - 1)
+        MarkerHandle(self.handle_count() - 1)
+    }
+
+    fn handle_count(&self) -> usize {
+        self.trimmed_marker_indexes.len() + self.marker_count() - self.marker_count_after_trim
+    }
+
+    /// Returns the index of the marker with the given handle, or `None` if the
+    /// marker has been removed by [`MarkerTable::trim`].
+    fn marker_index(&self, marker: MarkerHandle) -> Option<usize> {
+        match self.trimmed_marker_indexes.get(marker.0) {
+            Some(index) => *index,
+            None => {
+                Some(marker.0 - self.trimmed_marker_indexes.len() + self.marker_count_after_trim)
+            }
+        }
     }
 
+    /// Does nothing if the marker has been removed by [`MarkerTable::trim`].
     pub fn set_marker_stack(&mut self, marker: MarkerHandle, stack_index: Option<usize>) {
-        self.marker_stacks[marker.0] = stack_index;
+        if let Some(index) = self.marker_index(marker) {
+            self.marker_stacks[index] = stack_index;
+        }
+    }
+
+    /// Does nothing if the marker has been removed by [`MarkerTable::trim`].
+    pub fn set_marker_cause_time(&mut self, marker: MarkerHandle, time: Timestamp) {
+        if let Some(index) = self.marker_index(marker) {
+            self.marker_cause_times[index] = Some(time);
+        }
     }
 
     pub fn with_remapped_stacks(mut self, old_stack_to_new_stack: &[Option<usize>]) -> Self {
@@ -106,6 +149,94 @@ impl MarkerTable {
         self
     }
 
+    /// Removes the markers which don't overlap `range`, clamps the start and
+    /// end times of the remaining markers to `range`, and makes all marker times
+    /// relative to the start of the range. Instant markers are kept if they're
+    /// inside the range, including at its end.
+    ///
+    /// Existing [`MarkerHandle`]s stay valid: handles of removed markers are
+    /// ignored by [`MarkerTable::set_marker_stack`] and
+    /// [`MarkerTable::set_marker_cause_time`].
+    pub fn trim(&mut self, range: &Range<Timestamp>, schemas: &[InternalMarkerSchema]) {
+        let len = self.marker_count();
+        let mut keep = Vec::with_capacity(len);
+        let mut new_indexes = Vec::with_capacity(len);
+        let mut kept_count = 0;
+        for i in 0..len {
+            let start = self.marker_starts[i];
+            let end = self.marker_ends[i];
+            let is_kept = match self.marker_phases[i] {
+                Phase::Instant => start.map_or(false, |s| range.start <= s && s <= range.end),
+                _ => {
+                    start.map_or(true, |s| s < range.end) && end.map_or(true, |e| e >= range.start)
+                }
+            };
+            if is_kept {
+                let rebase = |t: Timestamp| t.clamp(range.start, range.end).rebased_to(range.start);
+                self.marker_starts[i] = start.map(rebase);
+                self.marker_ends[i] = end.map(rebase);
+                self.marker_cause_times[i] =
+                    self.marker_cause_times[i].map(|t| t.rebased_to(range.start));
+                new_indexes.push(Some(kept_count));
+                kept_count += 1;
+            } else {
+                new_indexes.push(None);
+            }
+            keep.push(is_kept);
+        }
+
+        self.trimmed_marker_indexes = (0..self.handle_count())
+            .map(|handle| {
+                self.marker_index(MarkerHandle(handle))
+                    .and_then(|index| new_indexes[index])
+            })
+            .collect();
+        self.marker_count_after_trim = kept_count;
+
+        let field_keep = |field_count: fn(&InternalMarkerSchema) -> usize| -> Vec<bool> {
+            self.marker_type_handles
+                .iter()
+                .zip(&keep)
+                .flat_map(|(handle, keep)| {
+                    std::iter::repeat(*keep).take(field_count(&schemas[handle.0]))
+                })
+                .collect()
+        };
+        let string_field_keep = field_keep(InternalMarkerSchema::string_field_count);
+        let number_field_keep = field_keep(InternalMarkerSchema::number_field_count);
+        let flow_field_keep = field_keep(InternalMarkerSchema::flow_field_count);
+        retain_by_index(&mut self.marker_field_string_values, &string_field_keep);
+        retain_by_index(&mut self.marker_field_number_values, &number_field_keep);
+        retain_by_index(&mut self.marker_field_flow_values, &flow_field_keep);
+
+        retain_by_index(&mut self.marker_categories, &keep);
+        retain_by_index(&mut self.marker_name_string_indexes, &keep);
+        retain_by_index(&mut self.marker_starts, &keep);
+        retain_by_index(&mut self.marker_ends, &keep);
+        retain_by_index(&mut self.marker_phases, &keep);
+        retain_by_index(&mut self.marker_type_handles, &keep);
+        retain_by_index(&mut self.marker_stacks, &keep);
+        retain_by_index(&mut self.marker_cause_times, &keep);
+    }
+
+    /// Returns the start and end time of each marker with the given name.
+    /// Instant markers end at their start time, and markers which are missing
+    /// their start are skipped.
+    pub fn spans_with_name<'a>(
+        &'a self,
+        string_table: &'a ProfileStringTable,
+        name: &'a str,
+    ) -> impl Iterator<Item = (Timestamp, Timestamp)> + 'a {
+        self.marker_name_string_indexes
+            .iter()
+            .zip(self.marker_starts.iter().zip(&self.marker_ends))
+            .filter(move |(name_index, _)| string_table.get_string(**name_index) == name)
+            .filter_map(|(_, (start, end))| {
+                let start = (*start)?;
+                Some((start, end.unwrap_or(start)))
+            })
+    }
+
     pub fn as_serializable<'a>(
         &'a self,
         schemas: &'a [InternalMarkerSchema],
@@ -185,7 +316,10 @@ impl Serialize for SerializableMarkerTableDataColumn<'_> {
         let mut remaining_flow_fields = &marker_table.marker_field_flow_values[..];
         for i in 0..len {
             let marker_type_handle = marker_table.marker_type_handles[i];
-            let stack_index = marker_table.marker_stacks[i];
+            let cause = marker_table.marker_stacks[i].map(|stack_index| SerializableMarkerCause {
+                stack_index,
+                time: marker_table.marker_cause_times[i],
+            });
             let schema = &schemas[marker_type_handle.0];
             let string_fields;
             let number_fields;
@@ -198,7 +332,7 @@ impl Serialize for SerializableMarkerTableDataColumn<'_> {
                 remaining_flow_fields.split_at(schema.flow_field_count());
             seq.serialize_element(&SerializableMarkerDataElement {
                 string_table,
-                stack_index,
+                cause,
                 schema,
                 string_fields,
                 number_fields,
@@ -211,7 +345,7 @@ impl Serialize for SerializableMarkerTableDataColumn<'_> {
 
 struct SerializableMarkerDataElement<'a> {
     string_table: &'a ProfileStringTable,
-    stack_index: Option<usize>,
+    cause: Option<SerializableMarkerCause>,
     schema: &'a InternalMarkerSchema,
     string_fields: &'a [StringHandle],
     number_fields: &'a [f64],
@@ -222,7 +356,7 @@ impl Serialize for SerializableMarkerDataElement<'_> {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         let Self {
             string_table,
-            stack_index,
+            cause,
             schema,
             mut string_fields,
             mut number_fields,
@@ -230,8 +364,8 @@ impl Serialize for SerializableMarkerDataElement<'_> {
         } = self;
         let mut map = serializer.serialize_map(None)?;
         map.serialize_entry("type", &schema.type_name())?;
-        if let Some(stack_index) = stack_index {
-            map.serialize_entry("cause", &SerializableMarkerCause(*stack_index))?;
+        if let Some(cause) = cause {
+            map.serialize_entry("cause", cause)?;
         }
         for field in schema.fields() {
             match &field.format {
@@ -262,11 +396,18 @@ impl Serialize for SerializableMarkerDataElement<'_> {
     }
 }
 
-struct SerializableMarkerCause(usize);
+struct SerializableMarkerCause {
+    stack_index: usize,
+    time: Option<Timestamp>,
+}
+
 impl Serialize for SerializableMarkerCause {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut map = serializer.serialize_map(Some(1))?;
-        map.serialize_entry("stack", &self.0)?;
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("stack", &self.stack_index)?;
+        if let Some(time) = &self.time {
+            map.serialize_entry("time", time)?;
+        }
         map.end()
     }
 }
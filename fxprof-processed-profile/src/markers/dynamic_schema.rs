@@ -47,7 +47,10 @@ pub trait DynamicSchemaMarker {
 /// marker's implementation of [`push_field_values`](DynamicSchemaMarker::push_field_values).
 ///
 /// Used with runtime-generated marker schemas. Use [`MarkerField`](super::static_schema::MarkerField) when using [`Marker`](super::static_schema::Marker).
+///
+/// Create instances with [`DynamicSchemaMarkerField::new`].
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct DynamicSchemaMarkerField {
     /// The field key. Must not be `type` or `cause`.
     pub key: String,
@@ -57,6 +60,33 @@ pub struct DynamicSchemaMarkerField {
 
     /// The format of this field.
     pub format: DynamicSchemaMarkerFieldFormat,
+
+    /// If true, this field is not shown in the marker tooltip or in the sidebar.
+    /// It can still be used in labels and is still searchable.
+    pub hidden: bool,
+}
+
+impl DynamicSchemaMarkerField {
+    /// Creates a visible field with the given key, label and format.
+    pub fn new(
+        key: impl Into<String>,
+        label: impl Into<String>,
+        format: impl Into<DynamicSchemaMarkerFieldFormat>,
+    ) -> Self {
+        Self {
+            key: key.into(),
+            label: label.into(),
+            format: format.into(),
+            hidden: false,
+        }
+    }
+
+    /// Hides this field from the marker tooltip and the sidebar. The field
+    /// value can still be used in labels and is still searchable.
+    pub fn hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
 }
 
 /// Describes a marker type, including the names and types of the marker's fields.
@@ -82,26 +112,10 @@ pub struct DynamicSchemaMarkerField {
 ///     tooltip_label: Some("Custom {marker.name} marker".into()),
 ///     table_label: Some("{marker.name} - {marker.data.eventName} with allocation size {marker.data.allocationSize} (latency: {marker.data.latency})".into()),
 ///     fields: vec![
-///         DynamicSchemaMarkerField {
-///             key: "eventName".into(),
-///             label: "Event name".into(),
-///             format: MarkerStringFieldFormat::String.into(),
-///         },
-///         DynamicSchemaMarkerField {
-///             key: "allocationSize".into(),
-///             label: "Allocation size".into(),
-///             format: MarkerNumberFieldFormat::Bytes.into(),
-///         },
-///         DynamicSchemaMarkerField {
-///             key: "url".into(),
-///             label: "URL".into(),
-///             format: MarkerStringFieldFormat::Url.into(),
-///         },
-///         DynamicSchemaMarkerField {
-///             key: "latency".into(),
-///             label: "Latency".into(),
-///             format: MarkerNumberFieldFormat::Duration.into(),
-///         },
+///         DynamicSchemaMarkerField::new("eventName", "Event name", MarkerStringFieldFormat::String),
+///         DynamicSchemaMarkerField::new("allocationSize", "Allocation size", MarkerNumberFieldFormat::Bytes),
+///         DynamicSchemaMarkerField::new("url", "URL", MarkerStringFieldFormat::Url),
+///         DynamicSchemaMarkerField::new("latency", "Latency", MarkerNumberFieldFormat::Duration),
 ///     ],
 ///     description: Some("This is a test marker with a custom schema.".into()),
 ///     graphs: vec![],
@@ -0,0 +1,59 @@
+use crate::{Category, CategoryColor, Profile, StringHandle};
+
+use super::static_schema::{FlowId, Marker, MarkerField, Schema};
+use super::types::MarkerLocations;
+
+/// A marker for one side of a message between two threads, such as an IPC
+/// message or a request from a client to a server. The threads can be in
+/// different processes.
+///
+/// Both sides of a message share the same correlation id, which the front-end
+/// uses to connect the two markers with an arrow. Markers for both sides are
+/// added by [`Profile::add_ipc_marker_pair`].
+#[derive(Debug, Clone)]
+pub struct IpcMessageMarker {
+    /// The marker name, e.g. the message type.
+    pub name: StringHandle,
+    /// "sending" or "receiving".
+    pub direction: StringHandle,
+    /// The tid of the sending thread.
+    pub sender_tid: StringHandle,
+    /// The tid of the receiving thread.
+    pub receiver_tid: StringHandle,
+    /// Identifies the message. Shared by the sending and the receiving marker.
+    pub correlation_id: FlowId,
+}
+
+impl Marker for IpcMessageMarker {
+    type FieldsType = (StringHandle, StringHandle, StringHandle, FlowId);
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "IpcMessage";
+    const CATEGORY: Category<'static> = Category("IPC", CategoryColor::LightBlue);
+    const LOCATIONS: MarkerLocations = MarkerLocations::MARKER_CHART
+        .union(MarkerLocations::MARKER_TABLE)
+        .union(MarkerLocations::TIMELINE_OVERVIEW);
+    const CHART_LABEL: Option<&'static str> = Some("{marker.name}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("{marker.name} ({marker.data.direction})");
+    const TABLE_LABEL: Option<&'static str> = Some(
+        "{marker.name} ({marker.data.direction}) {marker.data.senderTid} → {marker.data.receiverTid}",
+    );
+    const FIELDS: Schema<Self::FieldsType> = Schema((
+        MarkerField::string("direction", "Direction"),
+        MarkerField::string("senderTid", "Sender thread"),
+        MarkerField::string("receiverTid", "Receiver thread"),
+        MarkerField::flow("correlationId", "Correlation ID"),
+    ));
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn field_values(&self) -> Self::FieldsType {
+        (
+            self.direction,
+            self.sender_tid,
+            self.receiver_tid,
+            self.correlation_id,
+        )
+    }
+}
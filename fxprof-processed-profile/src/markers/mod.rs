@@ -2,6 +2,7 @@ mod dynamic_schema;
 mod field_format;
 mod field_kind_counts;
 mod internal;
+mod ipc;
 mod serialization;
 mod static_schema;
 mod types;
@@ -9,5 +10,6 @@ mod types;
 pub use dynamic_schema::*;
 pub use field_format::*;
 pub use internal::*;
+pub use ipc::*;
 pub use static_schema::*;
 pub use types::*;
@@ -16,6 +16,9 @@ impl Serialize for SerializableSchemaField<'_> {
             map.serialize_entry("label", &self.0.label)?;
         }
         map.serialize_entry("format", &self.0.format)?;
+        if self.0.hidden {
+            map.serialize_entry("hidden", &true)?;
+        }
         map.end()
     }
 }
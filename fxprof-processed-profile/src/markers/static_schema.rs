@@ -300,6 +300,9 @@ pub struct MarkerField<T: MarkerFieldValueType> {
 
     /// The format of this field.
     format: <T as MarkerFieldValueType>::FormatEnum,
+
+    /// Whether this field is hidden from the tooltip and the sidebar.
+    hidden: bool,
 }
 
 impl<T: MarkerFieldValueType> MarkerField<T> {
@@ -308,7 +311,19 @@ impl<T: MarkerFieldValueType> MarkerField<T> {
         label: &'static str,
         format: <T as MarkerFieldValueType>::FormatEnum,
     ) -> Self {
-        Self { key, label, format }
+        Self {
+            key,
+            label,
+            format,
+            hidden: false,
+        }
+    }
+
+    /// Hides this field from the marker tooltip and the sidebar. The field
+    /// value can still be used in labels and is still searchable.
+    pub const fn hidden(mut self) -> Self {
+        self.hidden = true;
+        self
     }
 }
 
@@ -382,6 +397,7 @@ impl<T: MarkerFieldValueType> From<&MarkerField<T>> for DynamicSchemaMarkerField
             key: schema.key.into(),
             label: schema.label.into(),
             format: schema.format.clone().into(),
+            hidden: schema.hidden,
         }
     }
 }
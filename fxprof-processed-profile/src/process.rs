@@ -1,11 +1,12 @@
 use std::cmp::Ordering;
 use std::hash::Hash;
+use std::ops::Range;
 
 use crate::frame_table::InternalFrameAddress;
 use crate::global_lib_table::{GlobalLibTable, LibraryHandle};
 use crate::lib_mappings::LibMappings;
 use crate::string_table::ProfileStringTable;
-use crate::Timestamp;
+use crate::{ProcessHandle, Timestamp};
 
 /// A thread. Can be created with [`Profile::add_thread`](crate::Profile::add_thread).
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
@@ -19,6 +20,8 @@ pub struct Process {
     main_thread: Option<ThreadHandle>,
     start_time: Timestamp,
     end_time: Option<Timestamp>,
+    parent: Option<ProcessHandle>,
+    sort_key: i64,
     libs: LibMappings<LibraryHandle>,
 }
 
@@ -31,6 +34,8 @@ impl Process {
             libs: LibMappings::new(),
             start_time,
             end_time: None,
+            parent: None,
+            sort_key: 0,
             name: name.to_owned(),
         }
     }
@@ -55,6 +60,18 @@ impl Process {
         self.end_time
     }
 
+    /// Clamps the process's start and end time to `range`, and makes them
+    /// relative to the start of the range.
+    pub fn trim(&mut self, range: &Range<Timestamp>) {
+        self.start_time = self
+            .start_time
+            .clamp(range.start, range.end)
+            .rebased_to(range.start);
+        self.end_time = self
+            .end_time
+            .map(|e| e.clamp(range.start, range.end).rebased_to(range.start));
+    }
+
     pub fn set_name(&mut self, name: &str) {
         self.name = name.to_string();
     }
@@ -63,6 +80,18 @@ impl Process {
         &self.name
     }
 
+    pub fn set_parent(&mut self, parent: Option<ProcessHandle>) {
+        self.parent = parent;
+    }
+
+    pub fn parent(&self) -> Option<ProcessHandle> {
+        self.parent
+    }
+
+    pub fn set_sort_key(&mut self, sort_key: i64) {
+        self.sort_key = sort_key;
+    }
+
     pub fn add_thread(&mut self, thread: ThreadHandle, is_main: bool) {
         self.threads.push(thread);
         if is_main && self.main_thread.is_none() {
@@ -70,11 +99,23 @@ impl Process {
         }
     }
 
+    pub fn set_thread_is_main(&mut self, thread: ThreadHandle, is_main: bool) {
+        if is_main {
+            self.main_thread = Some(thread);
+        } else if self.main_thread == Some(thread) {
+            self.main_thread = None;
+        }
+    }
+
     pub fn pid(&self) -> &str {
         &self.pid
     }
 
     pub fn cmp_for_json_order(&self, other: &Process) -> Ordering {
+        let ordering = self.sort_key.cmp(&other.sort_key);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
         if let Some(ordering) = self.start_time.partial_cmp(&other.start_time) {
             if ordering != Ordering::Equal {
                 return ordering;
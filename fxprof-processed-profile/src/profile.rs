@@ -1,18 +1,20 @@
 use std::collections::hash_map::Entry;
 use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
 use std::sync::Arc;
 use std::time::Duration;
 
 use indexmap::set::MutableValues;
 use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
-use serde_json::json;
+use serde_derive::Serialize;
+use serde_json::{json, Value};
 
 use crate::category::{
     Category, CategoryHandle, InternalCategory, IntoSubcategoryHandle, SubcategoryHandle,
 };
 use crate::category_color::CategoryColor;
-use crate::counters::{Counter, CounterHandle};
-use crate::cpu_delta::CpuDelta;
+use crate::counters::{Counter, CounterGraphType, CounterHandle};
+use crate::cpu_delta::{CpuDelta, CpuDeltaUnit};
 use crate::fast_hash_map::{FastHashMap, FastHashSet, FastIndexSet};
 use crate::frame::FrameAddress;
 use crate::frame_table::{
@@ -20,10 +22,11 @@ use crate::frame_table::{
 };
 use crate::global_lib_table::{GlobalLibIndex, GlobalLibTable, LibraryHandle};
 use crate::lib_mappings::LibMappings;
-use crate::library_info::{LibraryInfo, SymbolTable};
+use crate::library_info::{LibraryBuildInfo, LibraryInfo, SymbolTable};
 use crate::markers::{
-    DynamicSchemaMarker, DynamicSchemaMarkerSchema, GraphColor, InternalMarkerSchema, Marker,
-    MarkerHandle, MarkerTiming, MarkerTypeHandle,
+    DynamicSchemaMarker, DynamicSchemaMarkerSchema, FlowId, GraphColor, InternalMarkerSchema,
+    IpcMessageMarker, Marker, MarkerHandle, MarkerNumberFieldFormat, MarkerTiming,
+    MarkerTypeHandle,
 };
 use crate::native_symbols::NativeSymbolHandle;
 use crate::process::{Process, ThreadHandle};
@@ -169,8 +172,10 @@ pub enum TimelineUnit {
 pub struct Profile {
     pub(crate) product: String,
     pub(crate) os_name: Option<String>,
+    pub(crate) system_info: SystemInfo,
     pub(crate) interval: SamplingInterval,
     pub(crate) timeline_unit: TimelineUnit,
+    pub(crate) cpu_delta_unit: CpuDeltaUnit,
     pub(crate) global_libs: GlobalLibTable,
     pub(crate) kernel_libs: LibMappings<LibraryHandle>,
     pub(crate) categories: FastIndexSet<InternalCategory>, // append-only for stable CategoryHandles
@@ -179,14 +184,29 @@ pub struct Profile {
     pub(crate) threads: Vec<Thread>, // append-only for stable ThreadHandles
     pub(crate) initial_visible_threads: Vec<ThreadHandle>,
     pub(crate) initial_selected_threads: Vec<ThreadHandle>,
+    pub(crate) keep_thread_order: bool,
     pub(crate) reference_timestamp: ReferenceTimestamp,
     pub(crate) platform_specific_reference_timestamp: Option<PlatformSpecificReferenceTimestamp>,
     pub(crate) string_table: ProfileStringTable,
     pub(crate) marker_schemas: Vec<InternalMarkerSchema>,
     static_schema_marker_types: FastHashMap<&'static str, MarkerTypeHandle>,
     pub(crate) symbolicated: bool,
+    pub(crate) extra_info: Vec<ExtraInfoSection>,
+    pub(crate) paused_ranges: Vec<PausedRange>,
     used_pids: FastHashMap<u32, u32>,
     used_tids: FastHashMap<u32, u32>,
+    thread_name_filter: Option<ThreadNameFilter>,
+}
+
+type ThreadNameFilterFn = dyn Fn(Option<&str>) -> bool + Send + Sync;
+
+/// The filter passed to [`Profile::set_thread_name_filter`].
+struct ThreadNameFilter(Box<ThreadNameFilterFn>);
+
+impl std::fmt::Debug for ThreadNameFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ThreadNameFilter")
+    }
 }
 
 impl Profile {
@@ -207,10 +227,13 @@ impl Profile {
             interval,
             product: product.to_string(),
             os_name: None,
+            system_info: SystemInfo::default(),
             timeline_unit: TimelineUnit::Milliseconds,
+            cpu_delta_unit: CpuDeltaUnit::Microseconds,
             threads: Vec::new(),
             initial_visible_threads: Vec::new(),
             initial_selected_threads: Vec::new(),
+            keep_thread_order: false,
             global_libs: GlobalLibTable::new(),
             kernel_libs: LibMappings::new(),
             reference_timestamp,
@@ -221,8 +244,11 @@ impl Profile {
             categories,
             static_schema_marker_types: FastHashMap::default(),
             symbolicated: false,
+            extra_info: Vec::new(),
+            paused_ranges: Vec::new(),
             used_pids: FastHashMap::default(),
             used_tids: FastHashMap::default(),
+            thread_name_filter: None,
             counters: Vec::new(),
         }
     }
@@ -255,6 +281,106 @@ impl Profile {
         self.os_name = Some(os_name.to_string());
     }
 
+    /// Set the brand name of the CPU, e.g. "Intel(R) Core(TM) i7-8700K CPU @ 3.70GHz".
+    pub fn set_cpu_name(&mut self, cpu_name: &str) {
+        self.system_info.cpu_name = Some(cpu_name.to_string());
+    }
+
+    /// Set the number of physical CPU cores and of logical CPUs (hardware
+    /// threads) of the machine.
+    pub fn set_cpu_counts(&mut self, physical_cpus: u32, logical_cpus: u32) {
+        self.system_info.physical_cpus = Some(physical_cpus);
+        self.system_info.logical_cpus = Some(logical_cpus);
+    }
+
+    /// Set the size of the machine's physical memory, in bytes.
+    pub fn set_main_memory(&mut self, bytes: u64) {
+        self.system_info.main_memory = Some(bytes);
+    }
+
+    /// Set the name of the device, e.g. the phone model for Android profiles.
+    pub fn set_device_name(&mut self, device_name: &str) {
+        self.system_info.device = Some(device_name.to_string());
+    }
+
+    /// Set the version of the profiler or application which produced the
+    /// profile. The profiler shows it as the "Build ID" in the profile info
+    /// panel.
+    pub fn set_profiler_version(&mut self, version: &str) {
+        self.system_info.app_build_id = Some(version.to_string());
+    }
+
+    /// Mark the time between `start` and `end` as paused, for example because
+    /// the user paused the recording. The profiler doesn't expect any samples
+    /// in paused ranges, so the missing samples aren't mistaken for idle time.
+    pub fn add_paused_range(&mut self, start: Timestamp, end: Timestamp) {
+        self.paused_ranges.push(PausedRange {
+            start_time: start,
+            end_time: end,
+            reason: "profiler-paused",
+        });
+    }
+
+    /// Add a string entry to the profile's extra information, which the profiler
+    /// shows in the profile info panel. Entries are grouped into sections by
+    /// `section_label`, in the order in which they were added.
+    pub fn add_extra_info_string(&mut self, section_label: &str, label: &str, value: &str) {
+        self.add_extra_info_entry(section_label, label, json!("string"), json!(value));
+    }
+
+    /// Add a number entry to the profile's extra information. The value is
+    /// displayed according to `format`, for example as bytes or as a duration.
+    /// See [`Profile::add_extra_info_string`].
+    pub fn add_extra_info_number(
+        &mut self,
+        section_label: &str,
+        label: &str,
+        format: MarkerNumberFieldFormat,
+        value: f64,
+    ) {
+        self.add_extra_info_entry(section_label, label, json!(format), json!(value));
+    }
+
+    /// Look up a number entry of the profile's extra information, as added
+    /// with [`Profile::add_extra_info_number`].
+    pub fn get_extra_info_number(&self, section_label: &str, label: &str) -> Option<f64> {
+        let section = self
+            .extra_info
+            .iter()
+            .find(|section| section.label == section_label)?;
+        section
+            .entries
+            .iter()
+            .find(|entry| entry["label"] == label)
+            .and_then(|entry| entry["value"].as_f64())
+    }
+
+    fn add_extra_info_entry(
+        &mut self,
+        section_label: &str,
+        label: &str,
+        format: Value,
+        value: Value,
+    ) {
+        let section_index = match self
+            .extra_info
+            .iter()
+            .position(|section| section.label == section_label)
+        {
+            Some(index) => index,
+            None => {
+                self.extra_info.push(ExtraInfoSection {
+                    label: section_label.to_string(),
+                    entries: Vec::new(),
+                });
+                self.extra_info.len() - 1
+            }
+        };
+        self.extra_info[section_index]
+            .entries
+            .push(json!({ "label": label, "format": format, "value": value }));
+    }
+
     /// Set the unit that the timeline should display. Default is [`TimelineUnit::Milliseconds`].
     ///
     /// If this is set to [`TimelineUnit::Bytes`], then the sample [`Timestamp`]s are interpreted
@@ -265,6 +391,14 @@ impl Profile {
         self.timeline_unit = timeline_unit;
     }
 
+    /// Set the unit of the CPU deltas of all samples. Default is
+    /// [`CpuDeltaUnit::Microseconds`].
+    ///
+    /// The CPU deltas are shown in the CPU usage graph of each thread.
+    pub fn set_cpu_delta_unit(&mut self, cpu_delta_unit: CpuDeltaUnit) {
+        self.cpu_delta_unit = cpu_delta_unit;
+    }
+
     /// Get or create a handle for a category.
     ///
     /// Categories are used for stack frames and markers.
@@ -367,6 +501,18 @@ impl Profile {
         self.counters[counter.0].set_color(color);
     }
 
+    /// Change the description of the counter, which is shown in the counter's
+    /// tooltip.
+    pub fn set_counter_description(&mut self, counter: CounterHandle, description: &str) {
+        self.counters[counter.0].set_description(description);
+    }
+
+    /// Set how the counter's graph is drawn. If not called, the profiler picks
+    /// a graph type based on the counter's category.
+    pub fn set_counter_graph_type(&mut self, counter: CounterHandle, graph_type: CounterGraphType) {
+        self.counters[counter.0].set_graph_type(graph_type);
+    }
+
     /// Change the start time of a process.
     pub fn set_process_start_time(&mut self, process: ProcessHandle, start_time: Timestamp) {
         self.processes[process.0].set_start_time(start_time);
@@ -377,6 +523,24 @@ impl Profile {
         self.processes[process.0].set_end_time(end_time);
     }
 
+    /// Set the parent process of a process, e.g. the process which forked it.
+    ///
+    /// Child processes are listed right after their parent process in the
+    /// timeline, instead of being ordered among all processes.
+    pub fn set_process_parent(&mut self, process: ProcessHandle, parent: ProcessHandle) {
+        if process != parent {
+            self.processes[process.0].set_parent(Some(parent));
+        }
+    }
+
+    /// Set a key which determines the order of this process among its sibling
+    /// processes in the timeline. Processes with lower keys come first. The
+    /// default key is 0; processes with the same key are ordered by start time
+    /// and then by pid.
+    pub fn set_process_sort_key(&mut self, process: ProcessHandle, sort_key: i64) {
+        self.processes[process.0].set_sort_key(sort_key);
+    }
+
     /// Change the name of a process.
     pub fn set_process_name(&mut self, process: ProcessHandle, name: &str) {
         self.processes[process.0].set_name(name);
@@ -391,6 +555,17 @@ impl Profile {
         self.global_libs.handle_for_lib(library)
     }
 
+    /// Count the threads, samples, markers and libraries in this profile, for
+    /// example to print a summary at the end of a recording.
+    pub fn stats(&self) -> ProfileStats {
+        ProfileStats {
+            thread_count: self.retained_threads().count(),
+            sample_count: self.retained_threads().map(Thread::sample_count).sum(),
+            marker_count: self.retained_threads().map(Thread::marker_count).sum(),
+            lib_count: self.global_libs.lib_count(),
+        }
+    }
+
     /// Look up the [`LibraryInfo`] for a [`LibraryHandle`].
     pub fn get_library_info(&self, handle: LibraryHandle) -> &LibraryInfo {
         self.global_libs.get_lib(handle)
@@ -417,6 +592,12 @@ impl Profile {
         self.global_libs.set_lib_symbol_table(library, symbol_table);
     }
 
+    /// Set information about how a library was built, such as the compiler
+    /// version. It is stored with the library in the profile's libs table.
+    pub fn set_lib_build_info(&mut self, library: LibraryHandle, build_info: LibraryBuildInfo) {
+        self.global_libs.set_lib_build_info(library, build_info);
+    }
+
     /// For a given process, define where in the virtual memory of this process the given library
     /// is mapped.
     ///
@@ -490,24 +671,93 @@ impl Profile {
         self.threads
             .push(Thread::new(process, tid, start_time, is_main));
         self.processes[process.0].add_thread(handle, is_main);
+        self.apply_thread_name_filter(handle);
         handle
     }
 
     /// Change the name of a thread.
     pub fn set_thread_name(&mut self, thread: ThreadHandle, name: &str) {
         self.threads[thread.0].set_name(name);
+        self.apply_thread_name_filter(thread);
     }
 
-    /// Change the start time of a thread.
+    /// Only keep the threads whose names are accepted by `filter`, for example
+    /// to record just the interesting threads of an application with many
+    /// thread pool threads. `filter` is called with the thread's name, or
+    /// `None` if no name was set.
+    ///
+    /// Unlike [`Profile::retain_threads`], this is meant to be called before
+    /// any threads are added. The filter is checked whenever a thread is added
+    /// or renamed. While a thread's name is not accepted, the thread is
+    /// removed: the samples which are added to it are discarded, and it is
+    /// left out when the profile is serialized.
+    pub fn set_thread_name_filter(
+        &mut self,
+        filter: impl Fn(Option<&str>) -> bool + Send + Sync + 'static,
+    ) {
+        self.thread_name_filter = Some(ThreadNameFilter(Box::new(filter)));
+        for thread in 0..self.threads.len() {
+            self.apply_thread_name_filter(ThreadHandle(thread));
+        }
+    }
+
+    /// Returns whether the thread was removed, with [`Profile::retain_threads`]
+    /// or because its name is not accepted by the filter from
+    /// [`Profile::set_thread_name_filter`]. Callers can use this to skip the
+    /// work of collecting samples for removed threads.
+    pub fn thread_is_removed(&self, thread: ThreadHandle) -> bool {
+        self.threads[thread.0].is_removed()
+    }
+
+    fn apply_thread_name_filter(&mut self, thread: ThreadHandle) {
+        if let Some(ThreadNameFilter(filter)) = &self.thread_name_filter {
+            let thread = &mut self.threads[thread.0];
+            let is_accepted = filter(thread.name());
+            thread.set_is_removed(!is_accepted);
+        }
+    }
+
+    /// Change the start time of a thread. This is the thread's registration
+    /// time in the profile; the thread's track starts at this time.
     pub fn set_thread_start_time(&mut self, thread: ThreadHandle, start_time: Timestamp) {
         self.threads[thread.0].set_start_time(start_time);
     }
 
-    /// Set the end time of a thread.
+    /// Set the end time of a thread. This is the thread's unregistration time
+    /// in the profile; the thread's track ends at this time.
     pub fn set_thread_end_time(&mut self, thread: ThreadHandle, end_time: Timestamp) {
         self.threads[thread.0].set_end_time(end_time);
     }
 
+    /// Set whether a thread is the main thread of its process.
+    ///
+    /// The main thread is the first thread of its process in the timeline, and
+    /// it is named after the process. Allocation samples for a process are
+    /// added to its main thread.
+    ///
+    /// A process has at most one main thread. Making a thread the main thread
+    /// demotes the process's previous main thread.
+    pub fn set_thread_is_main(&mut self, thread: ThreadHandle, is_main: bool) {
+        let process = &mut self.processes[self.threads[thread.0].process().0];
+        if is_main {
+            for other in process.threads() {
+                if *other != thread {
+                    self.threads[other.0].set_is_main(false);
+                }
+            }
+        }
+        process.set_thread_is_main(thread, is_main);
+        self.threads[thread.0].set_is_main(is_main);
+    }
+
+    /// Set a key which determines the order of this thread within its process
+    /// in the timeline. Threads with lower keys come first. The default key is
+    /// 0; threads with the same key are ordered with the main thread first, and
+    /// then by start time, name and tid.
+    pub fn set_thread_sort_key(&mut self, thread: ThreadHandle, sort_key: i64) {
+        self.threads[thread.0].set_sort_key(sort_key);
+    }
+
     /// Set the tid (thread ID) of a thread.
     pub fn set_thread_tid(&mut self, thread: ThreadHandle, tid: u32) {
         let tid = self.make_unique_tid(tid);
@@ -530,6 +780,126 @@ impl Profile {
         self.threads[thread.0].set_samples_weight_type(t);
     }
 
+    /// Remove all threads for which `keep` returns false. `keep` is called with
+    /// the thread's name, or `None` if no name was set.
+    ///
+    /// This is meant to be called once the profile is complete, for example to
+    /// drop uninteresting threads before the profile is serialized. Counters of
+    /// processes which no longer have any threads are removed as well.
+    ///
+    /// Removed threads are only left out when the profile is serialized, so all
+    /// handles stay valid. Samples which are added to a removed thread
+    /// afterwards are discarded.
+    pub fn retain_threads(&mut self, mut keep: impl FnMut(Option<&str>) -> bool) {
+        for thread in &mut self.threads {
+            if !thread.is_removed() && !keep(thread.name()) {
+                thread.set_is_removed(true);
+            }
+        }
+    }
+
+    /// Returns the start and end time of every marker named `name`, across all
+    /// threads, sorted by start time. Instant markers end at their start time.
+    pub fn marker_spans_with_name(&self, name: &str) -> Vec<(Timestamp, Timestamp)> {
+        let mut spans: Vec<(Timestamp, Timestamp)> = self
+            .retained_threads()
+            .flat_map(|thread| thread.marker_spans_with_name(&self.string_table, name))
+            .collect();
+        spans.sort();
+        spans
+    }
+
+    /// Remove all samples and allocation samples, on all threads, for which
+    /// `keep` returns false. `keep` is called with the sample's timestamp.
+    ///
+    /// Like [`Profile::retain_threads`], this is meant to be called once the
+    /// profile is complete, for example to only keep the samples from an
+    /// interesting time range. Markers and counters are not affected.
+    pub fn retain_samples(&mut self, mut keep: impl FnMut(Timestamp) -> bool) {
+        for thread in &mut self.threads {
+            thread.retain_samples_by_time(&mut keep);
+        }
+    }
+
+    /// Cut the profile down to the time range `range`, for example to only keep
+    /// the interesting seconds of a long recording.
+    ///
+    /// Samples, allocation samples, markers, counter samples and paused ranges
+    /// outside the range are removed. Instant markers exactly at the end of the
+    /// range are kept. Interval markers, paused ranges and the lifetimes of
+    /// threads and processes which extend past the range are clamped to it.
+    /// Counter samples are deltas, so accumulated counter values start from zero
+    /// at the beginning of the range.
+    ///
+    /// The start of the range becomes the new zero: all remaining times are
+    /// shifted so that they're relative to it, and the reference timestamp is
+    /// moved forward by the same amount. A platform-specific reference timestamp
+    /// in nanoseconds is moved forward as well; a
+    /// [`PlatformSpecificReferenceTimestamp::QueryPerformanceCounterValue`]
+    /// can't be converted without the counter frequency, so it is removed.
+    ///
+    /// Existing [`MarkerHandle`]s stay valid. Calling
+    /// [`Profile::set_marker_stack`] or [`Profile::set_marker_cause`] with the
+    /// handle of a marker which was removed does nothing.
+    ///
+    /// Like [`Profile::retain_threads`], this is meant to be called once the
+    /// profile is complete. Times passed to the profile afterwards need to be
+    /// relative to the new reference timestamp.
+    pub fn trim(&mut self, range: Range<Timestamp>) {
+        for thread in &mut self.threads {
+            thread.trim(&range, &self.marker_schemas);
+        }
+        for process in &mut self.processes {
+            process.trim(&range);
+        }
+        for counter in &mut self.counters {
+            counter.trim(&range);
+        }
+        self.paused_ranges
+            .retain(|paused| paused.start_time < range.end && paused.end_time > range.start);
+        for paused in &mut self.paused_ranges {
+            paused.start_time = paused.start_time.max(range.start).rebased_to(range.start);
+            paused.end_time = paused.end_time.min(range.end).rebased_to(range.start);
+        }
+
+        self.reference_timestamp = self.reference_timestamp.advanced_by(range.start);
+        let offset_nanos = range.start.nanos();
+        self.platform_specific_reference_timestamp = match self
+            .platform_specific_reference_timestamp
+        {
+            Some(PlatformSpecificReferenceTimestamp::ClockMonotonicNanosecondsSinceBoot(nanos)) => {
+                Some(
+                    PlatformSpecificReferenceTimestamp::ClockMonotonicNanosecondsSinceBoot(
+                        nanos + offset_nanos,
+                    ),
+                )
+            }
+            Some(PlatformSpecificReferenceTimestamp::MachAbsoluteTimeNanoseconds(nanos)) => Some(
+                PlatformSpecificReferenceTimestamp::MachAbsoluteTimeNanoseconds(
+                    nanos + offset_nanos,
+                ),
+            ),
+            Some(PlatformSpecificReferenceTimestamp::QueryPerformanceCounterValue(_)) | None => {
+                None
+            }
+        };
+    }
+
+    /// Reduce the number of samples on each thread by merging every `factor`
+    /// consecutive samples into one. The last sample of each group is kept, and
+    /// its weight and CPU delta are the sums of those of the whole group, so
+    /// that the totals in the call tree stay the same.
+    ///
+    /// This is meant to be called once the profile is complete, to make a
+    /// profile with too many samples smaller. Allocation samples, markers and
+    /// counters are not affected.
+    pub fn merge_consecutive_samples(&mut self, factor: usize) {
+        assert!(factor > 0);
+        for thread in &mut self.threads {
+            thread.merge_consecutive_samples(factor);
+        }
+    }
+
     /// Add a thread as initially visible in the UI.
     ///
     /// If not called, the UI uses its own ranking heuristic to choose which
@@ -556,6 +926,17 @@ impl Profile {
         self.initial_selected_threads.clear();
     }
 
+    /// Ask the UI to show the tracks in the order of the threads in the profile,
+    /// instead of ranking them by their CPU usage.
+    ///
+    /// The order of the threads in the profile can be influenced with
+    /// [`Profile::set_process_sort_key`] and [`Profile::set_thread_sort_key`].
+    /// Threads which aren't in the initially visible threads (see
+    /// [`Profile::add_initial_visible_thread`]) still start out hidden.
+    pub fn set_keep_thread_order(&mut self, keep_thread_order: bool) {
+        self.keep_thread_order = keep_thread_order;
+    }
+
     /// Get or create the [`StringHandle`] for a string.
     pub fn handle_for_string(&mut self, s: &str) -> StringHandle {
         self.string_table.index_for_string(s)
@@ -597,6 +978,15 @@ impl Profile {
         self.handle_for_frame_with_address_internal(thread, frame_address, subcategory, flags)
     }
 
+    /// Adds the frame table's "implementation" string for frames with these
+    /// flags to the string table, so that it can be found when the profile is
+    /// serialized.
+    fn intern_frame_implementation(&mut self, flags: FrameFlags) {
+        if let Some(implementation) = flags.implementation() {
+            self.string_table.index_for_string(implementation);
+        }
+    }
+
     fn handle_for_frame_with_address_internal(
         &mut self,
         thread: ThreadHandle,
@@ -604,6 +994,7 @@ impl Profile {
         subcategory: SubcategoryHandle,
         flags: FrameFlags,
     ) -> FrameHandle {
+        self.intern_frame_implementation(flags);
         let thread_handle = thread;
         let thread = &mut self.threads[thread_handle.0];
         let process = &mut self.processes[thread.process().0];
@@ -688,6 +1079,7 @@ impl Profile {
         subcategory: SubcategoryHandle,
         flags: FrameFlags,
     ) -> FrameHandle {
+        self.intern_frame_implementation(flags);
         let thread_handle = thread;
         let thread = &mut self.threads[thread_handle.0];
         let name = label;
@@ -735,6 +1127,7 @@ impl Profile {
         subcategory: SubcategoryHandle,
         flags: FrameFlags,
     ) -> FrameHandle {
+        self.intern_frame_implementation(flags);
         let thread_handle = thread;
         let FrameSymbolInfo {
             name,
@@ -870,6 +1263,39 @@ impl Profile {
         Some(StackHandle(thread_handle, stack_index))
     }
 
+    /// Get the [`StackHandle`] for a stack which interleaves label frames with
+    /// native frames, the way the Gecko profiler merges its label stack into
+    /// the native stack.
+    ///
+    /// Both lists are ordered from caller-most to callee-most, and every frame
+    /// comes with a stack pointer. The stack is assumed to grow downwards, so
+    /// callers have higher stack pointers than their callees. A label frame is
+    /// placed before (i.e. as a caller of) all native frames whose stack pointer
+    /// is lower than the label frame's stack pointer; this means that a label
+    /// frame which was pushed by a native function, using the stack pointer of
+    /// that function's frame, ends up below that function.
+    ///
+    /// Returns `None` if both lists are empty.
+    pub fn handle_for_stack_with_label_frames(
+        &mut self,
+        thread: ThreadHandle,
+        native_frames: &[(FrameHandle, u64)],
+        label_frames: &[(FrameHandle, u64)],
+    ) -> Option<StackHandle> {
+        let mut native_frames = native_frames.iter().peekable();
+        let mut label_frames = label_frames.iter().peekable();
+        self.handle_for_stack_frames(thread, |_| {
+            let next = match (native_frames.peek(), label_frames.peek()) {
+                (Some((_, native_sp)), Some((_, label_sp))) if label_sp > native_sp => {
+                    label_frames.next()
+                }
+                (Some(_), _) => native_frames.next(),
+                (None, _) => label_frames.next(),
+            };
+            next.map(|(frame, _)| *frame)
+        })
+    }
+
     /// Add a sample to the given thread.
     ///
     /// The sample has a timestamp, a stack, a CPU delta, and a weight.
@@ -900,6 +1326,23 @@ impl Profile {
         stack: Option<StackHandle>,
         cpu_delta: CpuDelta,
         weight: i32,
+    ) {
+        self.add_sample_with_fractional_weight(thread, timestamp, stack, cpu_delta, weight.into());
+    }
+
+    /// Add a sample with a fractional weight to the given thread.
+    ///
+    /// This is like [`Profile::add_sample`], for threads whose weight type,
+    /// set with [`Profile::set_thread_samples_weight_type`], is not just a
+    /// sample count. For example, with [`WeightType::TracingMs`] the weight is
+    /// the duration of the sample in milliseconds.
+    pub fn add_sample_with_fractional_weight(
+        &mut self,
+        thread: ThreadHandle,
+        timestamp: Timestamp,
+        stack: Option<StackHandle>,
+        cpu_delta: CpuDelta,
+        weight: f64,
     ) {
         let stack_index = match stack {
             Some(StackHandle(stack_thread_handle, stack_index)) => {
@@ -911,7 +1354,10 @@ impl Profile {
             }
             None => None,
         };
-        self.threads[thread.0].add_sample(timestamp, stack_index, cpu_delta, weight);
+        let thread = &mut self.threads[thread.0];
+        if !thread.is_removed() {
+            thread.add_sample(timestamp, stack_index, cpu_delta, weight);
+        }
     }
 
     /// Add a sample with a CPU delta of zero. Internally, multiple consecutive
@@ -923,7 +1369,10 @@ impl Profile {
         timestamp: Timestamp,
         weight: i32,
     ) {
-        self.threads[thread.0].add_sample_same_stack_zero_cpu(timestamp, weight);
+        let thread = &mut self.threads[thread.0];
+        if !thread.is_removed() {
+            thread.add_sample_same_stack_zero_cpu(timestamp, weight.into());
+        }
     }
 
     /// Add an allocation or deallocation sample to the *main* thread of the given
@@ -979,6 +1428,90 @@ impl Profile {
         stack: Option<StackHandle>,
         allocation_address: u64,
         allocation_size: i64,
+    ) {
+        self.add_allocation_sample_with_thread_id(
+            process,
+            timestamp,
+            stack,
+            allocation_address,
+            allocation_size,
+            0,
+        );
+    }
+
+    /// Add an allocation or deallocation sample which was made on the given thread.
+    ///
+    /// This is like [`Profile::add_allocation_sample`], but also records the tid of
+    /// the thread which made the allocation or deallocation. The sample is still
+    /// added to the main thread of the thread's process, so `stack` must be a stack
+    /// handle of that main thread.
+    pub fn add_allocation_sample_on_thread(
+        &mut self,
+        thread: ThreadHandle,
+        timestamp: Timestamp,
+        stack: Option<StackHandle>,
+        allocation_address: u64,
+        allocation_size: i64,
+    ) {
+        let thread = &self.threads[thread.0];
+        let (process, thread_id) = (thread.process(), thread.numeric_tid());
+        self.add_allocation_sample_with_thread_id(
+            process,
+            timestamp,
+            stack,
+            allocation_address,
+            allocation_size,
+            thread_id,
+        );
+    }
+
+    /// Add a sample for a JavaScript (or other garbage-collected) object
+    /// allocation to the thread's JS allocations table. Unlike native
+    /// allocations, these are never matched with deallocations.
+    ///
+    /// The profiler shows JS allocation samples in their own call tree, next to
+    /// the thread's regular samples, so a thread can have both time samples and
+    /// allocation samples.
+    ///
+    /// `class_name` is the name of the allocated object's class, and
+    /// `in_nursery` is whether the object was allocated in the nursery (young
+    /// generation) of a generational garbage collector.
+    pub fn add_js_allocation_sample(
+        &mut self,
+        thread: ThreadHandle,
+        timestamp: Timestamp,
+        stack: Option<StackHandle>,
+        class_name: StringHandle,
+        allocation_size: u64,
+        in_nursery: bool,
+    ) {
+        let stack_index = match stack {
+            Some(StackHandle(stack_thread, stack_index)) => {
+                assert_eq!(
+                    stack_thread, thread,
+                    "StackHandle from different thread passed to Profile::add_js_allocation_sample"
+                );
+                Some(stack_index)
+            }
+            None => None,
+        };
+        self.threads[thread.0].add_js_allocation_sample(
+            timestamp,
+            stack_index,
+            class_name,
+            allocation_size,
+            in_nursery,
+        );
+    }
+
+    fn add_allocation_sample_with_thread_id(
+        &mut self,
+        process: ProcessHandle,
+        timestamp: Timestamp,
+        stack: Option<StackHandle>,
+        allocation_address: u64,
+        allocation_size: i64,
+        thread_id: u32,
     ) {
         let process = &self.processes[process.0];
         let Some(allocation_thread) = process.thread_handle_for_allocations() else {
@@ -999,6 +1532,7 @@ impl Profile {
             stack_index,
             allocation_address,
             allocation_size,
+            thread_id,
         );
     }
 
@@ -1106,6 +1640,45 @@ impl Profile {
         )
     }
 
+    /// Adds a pair of [`IpcMessageMarker`]s for a message which was sent by the
+    /// `sender` thread and received by the `receiver` thread, e.g. an IPC message
+    /// or a request from a client to a server. The threads can be in different
+    /// processes.
+    ///
+    /// The front-end connects the two markers with an arrow, based on the shared
+    /// `correlation_id`. Each correlation id should only be used for one message.
+    ///
+    /// Returns the handles of the sending and of the receiving marker.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_ipc_marker_pair(
+        &mut self,
+        name: &str,
+        correlation_id: u64,
+        sender: ThreadHandle,
+        send_timing: MarkerTiming,
+        receiver: ThreadHandle,
+        receive_timing: MarkerTiming,
+    ) -> (MarkerHandle, MarkerHandle) {
+        let name = self.handle_for_string(name);
+        let sender_tid = self
+            .string_table
+            .index_for_string(self.threads[sender.0].tid());
+        let receiver_tid = self
+            .string_table
+            .index_for_string(self.threads[receiver.0].tid());
+        let mut marker = IpcMessageMarker {
+            name,
+            direction: self.handle_for_string("sending"),
+            sender_tid,
+            receiver_tid,
+            correlation_id: FlowId(correlation_id),
+        };
+        let send_marker = self.add_marker(sender, send_timing, marker.clone());
+        marker.direction = self.handle_for_string("receiving");
+        let receive_marker = self.add_marker(receiver, receive_timing, marker);
+        (send_marker, receive_marker)
+    }
+
     /// Sets a marker's stack. Every marker can have an optional stack, regardless
     /// of its marker type.
     ///
@@ -1133,6 +1706,24 @@ impl Profile {
         self.threads[thread.0].set_marker_stack(marker, stack_index);
     }
 
+    /// Sets a marker's cause: the stack at which the marked operation was
+    /// initiated, and the time at which that stack was captured.
+    ///
+    /// This is like [`Profile::set_marker_stack`], for markers whose stack was
+    /// captured before the marker was added, for example at the start of an IO
+    /// operation which is only added as a marker once it completes. The tooltip
+    /// shows the time at which the stack was captured.
+    pub fn set_marker_cause(
+        &mut self,
+        thread: ThreadHandle,
+        marker: MarkerHandle,
+        stack: StackHandle,
+        time: Timestamp,
+    ) {
+        self.set_marker_stack(thread, marker, Some(stack));
+        self.threads[thread.0].set_marker_cause_time(marker, time);
+    }
+
     /// Add a data point to a counter. For a memory counter, `value_delta` is the number
     /// of bytes that have been allocated / deallocated since the previous counter sample, and
     /// `number_of_operations` is the number of `malloc` / `free` calls since the previous
@@ -1233,8 +1824,10 @@ impl Profile {
         let Profile {
             product,
             os_name,
+            system_info,
             interval,
             timeline_unit,
+            cpu_delta_unit,
             global_libs,
             kernel_libs,
             categories,
@@ -1243,14 +1836,18 @@ impl Profile {
             threads,
             initial_visible_threads,
             initial_selected_threads,
+            keep_thread_order,
             reference_timestamp,
             platform_specific_reference_timestamp,
             mut string_table,
             marker_schemas,
             static_schema_marker_types,
             symbolicated,
+            extra_info,
+            paused_ranges,
             used_pids,
             used_tids,
+            thread_name_filter,
         } = self;
 
         let mut strings = StringTableAdapter::new(symbol_string_table, &mut string_table);
@@ -1264,8 +1861,10 @@ impl Profile {
         Profile {
             product,
             os_name,
+            system_info,
             interval,
             timeline_unit,
+            cpu_delta_unit,
             global_libs,
             kernel_libs,
             categories,
@@ -1274,14 +1873,18 @@ impl Profile {
             threads,
             initial_visible_threads,
             initial_selected_threads,
+            keep_thread_order,
             reference_timestamp,
             platform_specific_reference_timestamp,
             string_table,
             marker_schemas,
             static_schema_marker_types,
             symbolicated,
+            extra_info,
+            paused_ranges,
             used_pids,
             used_tids,
+            thread_name_filter,
         }
     }
 
@@ -1361,15 +1964,16 @@ impl Profile {
         self.symbolicated = v;
     }
 
-    /// Returns a flattened list of `ThreadHandle`s in the right order.
+    /// Returns a flattened list of `ThreadHandle`s in the right order, without
+    /// the threads which were removed with [`Profile::retain_threads`].
     ///
     // The processed profile format has all threads from all processes in a flattened threads list.
     // Each thread duplicates some information about its process, which allows the Firefox Profiler
     // UI to group threads from the same process.
-    fn sorted_threads(&self) -> (Vec<ThreadHandle>, Vec<usize>, Vec<usize>) {
-        let mut sorted_threads = Vec::with_capacity(self.threads.len());
+    fn sorted_threads(&self) -> (Vec<ThreadHandle>, Vec<usize>, Vec<Option<usize>>) {
+        let mut sorted_threads: Vec<ThreadHandle> = Vec::with_capacity(self.threads.len());
         let mut first_thread_index_per_process = vec![0; self.processes.len()];
-        let mut new_thread_indices = vec![0; self.threads.len()];
+        let mut new_thread_indices = vec![None; self.threads.len()];
 
         let mut sorted_processes: Vec<_> = (0..self.processes.len()).map(ProcessHandle).collect();
         sorted_processes.sort_by(|a_handle, b_handle| {
@@ -1378,10 +1982,15 @@ impl Profile {
             a.cmp_for_json_order(b)
         });
 
-        for process in sorted_processes {
+        for process in self.processes_in_tree_order(&sorted_processes) {
             let prev_len = sorted_threads.len();
             first_thread_index_per_process[process.0] = prev_len;
-            sorted_threads.extend_from_slice(self.processes[process.0].threads());
+            sorted_threads.extend(
+                self.processes[process.0]
+                    .threads()
+                    .iter()
+                    .filter(|thread| !self.threads[thread.0].is_removed()),
+            );
 
             let sorted_threads_for_this_process = &mut sorted_threads[prev_len..];
             sorted_threads_for_this_process.sort_by(|a_handle, b_handle| {
@@ -1391,7 +2000,7 @@ impl Profile {
             });
 
             for (i, v) in sorted_threads_for_this_process.iter().enumerate() {
-                new_thread_indices[v.0] = prev_len + i;
+                new_thread_indices[v.0] = Some(prev_len + i);
             }
         }
 
@@ -1402,6 +2011,35 @@ impl Profile {
         )
     }
 
+    /// Reorders the sorted processes so that each process is followed by its
+    /// child processes, recursively. Processes whose parent chain loops are
+    /// treated like processes without a parent.
+    fn processes_in_tree_order(&self, sorted_processes: &[ProcessHandle]) -> Vec<ProcessHandle> {
+        let mut children: Vec<Vec<ProcessHandle>> = vec![Vec::new(); self.processes.len()];
+        let mut roots = Vec::new();
+        for &process in sorted_processes {
+            match self.processes[process.0].parent() {
+                Some(parent) => children[parent.0].push(process),
+                None => roots.push(process),
+            }
+        }
+
+        let mut ordered = Vec::with_capacity(sorted_processes.len());
+        let mut visited = vec![false; self.processes.len()];
+        // Processes in a parent loop are only reached after all roots.
+        for root in roots.into_iter().chain(sorted_processes.iter().copied()) {
+            let mut stack = vec![root];
+            while let Some(process) = stack.pop() {
+                if std::mem::replace(&mut visited[process.0], true) {
+                    continue;
+                }
+                ordered.push(process);
+                stack.extend(children[process.0].iter().rev());
+            }
+        }
+        ordered
+    }
+
     fn serializable_threads<'a>(
         &'a self,
         sorted_threads: &'a [ThreadHandle],
@@ -1412,6 +2050,8 @@ impl Profile {
             sorted_threads,
             marker_schemas: &self.marker_schemas,
             string_table: &self.string_table,
+            paused_ranges: &self.paused_ranges,
+            cpu_delta_unit: self.cpu_delta_unit,
         }
     }
 
@@ -1419,14 +2059,27 @@ impl Profile {
         &'a self,
         first_thread_index_per_process: &'a [usize],
     ) -> SerializableProfileCountersProperty<'a> {
+        // Drop the counters of processes whose threads were all removed.
+        let counters = self
+            .counters
+            .iter()
+            .filter(|counter| {
+                let threads = self.processes[counter.process().0].threads();
+                threads.is_empty() || threads.iter().any(|t| !self.threads[t.0].is_removed())
+            })
+            .collect();
         SerializableProfileCountersProperty {
-            counters: &self.counters,
+            counters,
             first_thread_index_per_process,
         }
     }
 
+    fn retained_threads(&self) -> impl Iterator<Item = &Thread> {
+        self.threads.iter().filter(|thread| !thread.is_removed())
+    }
+
     fn contains_js_frame(&self) -> bool {
-        self.threads.iter().any(|t| t.contains_js_frame())
+        self.retained_threads().any(|t| t.contains_js_frame())
     }
 }
 
@@ -1449,7 +2102,7 @@ impl Serialize for Profile {
     }
 }
 
-struct SerializableProfileMeta<'a>(&'a Profile, &'a [usize]);
+struct SerializableProfileMeta<'a>(&'a Profile, &'a [Option<usize>]);
 
 impl Serialize for SerializableProfileMeta<'_> {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
@@ -1472,6 +2125,25 @@ impl Serialize for SerializableProfileMeta<'_> {
         if let Some(os_name) = &self.0.os_name {
             map.serialize_entry("oscpu", os_name)?;
         }
+        let system_info = &self.0.system_info;
+        if let Some(cpu_name) = &system_info.cpu_name {
+            map.serialize_entry("CPUName", cpu_name)?;
+        }
+        if let Some(physical_cpus) = system_info.physical_cpus {
+            map.serialize_entry("physicalCPUs", &physical_cpus)?;
+        }
+        if let Some(logical_cpus) = system_info.logical_cpus {
+            map.serialize_entry("logicalCPUs", &logical_cpus)?;
+        }
+        if let Some(main_memory) = system_info.main_memory {
+            map.serialize_entry("mainMemory", &main_memory)?;
+        }
+        if let Some(device) = &system_info.device {
+            map.serialize_entry("device", device)?;
+        }
+        if let Some(app_build_id) = &system_info.app_build_id {
+            map.serialize_entry("appBuildID", app_build_id)?;
+        }
         let time_unit = match self.0.timeline_unit {
             TimelineUnit::Milliseconds => "ms",
             TimelineUnit::Bytes => "bytes",
@@ -1481,7 +2153,7 @@ impl Serialize for SerializableProfileMeta<'_> {
             &json!({
                 "time": &time_unit,
                 "eventDelay": "ms",
-                "threadCPUDelta": "µs",
+                "threadCPUDelta": self.0.cpu_delta_unit.as_str(),
             }),
         )?;
         map.serialize_entry("startTime", &self.0.reference_timestamp)?;
@@ -1498,10 +2170,13 @@ impl Serialize for SerializableProfileMeta<'_> {
             None => {}
         }
         map.serialize_entry("symbolicated", &self.0.symbolicated)?;
-        map.serialize_entry("pausedRanges", &[] as &[()])?;
+        map.serialize_entry("pausedRanges", &self.0.paused_ranges)?;
         map.serialize_entry("version", &24)?; // this version is ignored, only "preprocessedProfileVersion" is used
         map.serialize_entry("usesOnlyOneStackType", &(!self.0.contains_js_frame()))?;
         map.serialize_entry("sourceCodeIsNotOnSearchfox", &true)?;
+        if !self.0.extra_info.is_empty() {
+            map.serialize_entry("extra", &self.0.extra_info)?;
+        }
 
         let mut marker_schemas: Vec<InternalMarkerSchema> = self.0.marker_schemas.clone();
         marker_schemas.sort_by(|a, b| a.type_name().cmp(b.type_name()));
@@ -1514,7 +2189,7 @@ impl Serialize for SerializableProfileMeta<'_> {
                     .0
                     .initial_visible_threads
                     .iter()
-                    .map(|x| self.1[x.0])
+                    .filter_map(|x| self.1[x.0])
                     .collect::<Vec<_>>(),
             )?;
         }
@@ -1526,15 +2201,60 @@ impl Serialize for SerializableProfileMeta<'_> {
                     .0
                     .initial_selected_threads
                     .iter()
-                    .map(|x| self.1[x.0])
+                    .filter_map(|x| self.1[x.0])
                     .collect::<Vec<_>>(),
             )?;
         };
 
+        if self.0.keep_thread_order {
+            map.serialize_entry("keepProfileThreadOrder", &true)?;
+        }
+
         map.end()
     }
 }
 
+/// The sizes of a profile, see [`Profile::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProfileStats {
+    /// The number of threads, including threads without samples.
+    pub thread_count: usize,
+    /// The number of samples, summed over all threads.
+    pub sample_count: usize,
+    /// The number of markers, summed over all threads.
+    pub marker_count: usize,
+    /// The number of libraries which were added with [`Profile::add_lib`].
+    pub lib_count: usize,
+}
+
+/// Information about the machine and the profiler, see [`Profile::set_cpu_name`]
+/// and the other setters next to it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SystemInfo {
+    cpu_name: Option<String>,
+    physical_cpus: Option<u32>,
+    logical_cpus: Option<u32>,
+    main_memory: Option<u64>,
+    device: Option<String>,
+    app_build_id: Option<String>,
+}
+
+/// A section of the profile's extra information, see [`Profile::add_extra_info_string`].
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ExtraInfoSection {
+    label: String,
+    entries: Vec<Value>,
+}
+
+/// A time range without samples, see [`Profile::add_paused_range`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PausedRange {
+    start_time: Timestamp,
+    end_time: Timestamp,
+    reason: &'static str,
+}
+
 struct SerializableProfileShared<'a>(&'a Profile);
 
 impl Serialize for SerializableProfileShared<'_> {
@@ -1551,11 +2271,13 @@ struct SerializableProfileThreadsProperty<'a> {
     sorted_threads: &'a [ThreadHandle],
     marker_schemas: &'a [InternalMarkerSchema],
     string_table: &'a ProfileStringTable,
+    paused_ranges: &'a [PausedRange],
+    cpu_delta_unit: CpuDeltaUnit,
 }
 
 impl Serialize for SerializableProfileThreadsProperty<'_> {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut seq = serializer.serialize_seq(Some(self.threads.len()))?;
+        let mut seq = serializer.serialize_seq(Some(self.sorted_threads.len()))?;
 
         for thread in self.sorted_threads {
             let thread = &self.threads[thread.0];
@@ -1567,6 +2289,8 @@ impl Serialize for SerializableProfileThreadsProperty<'_> {
                 thread,
                 marker_schemas,
                 string_table,
+                self.paused_ranges,
+                self.cpu_delta_unit,
             ))?;
         }
 
@@ -1575,7 +2299,7 @@ impl Serialize for SerializableProfileThreadsProperty<'_> {
 }
 
 struct SerializableProfileCountersProperty<'a> {
-    counters: &'a [Counter],
+    counters: Vec<&'a Counter>,
     first_thread_index_per_process: &'a [usize],
 }
 
@@ -1583,7 +2307,7 @@ impl Serialize for SerializableProfileCountersProperty<'_> {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         let mut seq = serializer.serialize_seq(Some(self.counters.len()))?;
 
-        for counter in self.counters {
+        for counter in &self.counters {
             let main_thread_index = self.first_thread_index_per_process[counter.process().0];
             seq.serialize_element(&counter.as_serializable(main_thread_index))?;
         }
@@ -1597,11 +2321,20 @@ struct SerializableProfileThread<'a>(
     &'a Thread,
     &'a [InternalMarkerSchema],
     &'a ProfileStringTable,
+    &'a [PausedRange],
+    CpuDeltaUnit,
 );
 
 impl Serialize for SerializableProfileThread<'_> {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let SerializableProfileThread(process, thread, marker_schemas, string_table) = self;
+        let SerializableProfileThread(
+            process,
+            thread,
+            marker_schemas,
+            string_table,
+            paused_ranges,
+            cpu_delta_unit,
+        ) = self;
         let process_start_time = process.start_time();
         let process_end_time = process.end_time();
         let process_name = process.name();
@@ -1614,6 +2347,8 @@ impl Serialize for SerializableProfileThread<'_> {
             pid,
             marker_schemas,
             string_table,
+            paused_ranges,
+            *cpu_delta_unit,
         )
     }
 }
@@ -2,6 +2,8 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use serde::ser::{Serialize, Serializer};
 
+use crate::Timestamp;
+
 /// A timestamp which anchors the profile in absolute time.
 ///
 /// In the profile JSON, this uses a UNIX timestamp.
@@ -25,6 +27,13 @@ impl ReferenceTimestamp {
         }
     }
 
+    /// Returns the reference timestamp which is `timestamp` later than this one.
+    pub(crate) fn advanced_by(self, timestamp: Timestamp) -> Self {
+        Self::from_millis_since_unix_epoch(
+            self.ms_since_unix_epoch + timestamp.nanos() as f64 / 1_000_000.0,
+        )
+    }
+
     /// Create a reference timestamp from a [`SystemTime`].
     pub fn from_system_time(system_time: SystemTime) -> Self {
         Self::from_duration_since_unix_epoch(system_time.duration_since(UNIX_EPOCH).unwrap())
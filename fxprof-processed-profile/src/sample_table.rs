@@ -2,8 +2,9 @@ use std::fmt::{Display, Formatter};
 
 use serde::ser::{Serialize, SerializeMap, Serializer};
 
-use crate::cpu_delta::CpuDelta;
+use crate::cpu_delta::{CpuDelta, CpuDeltaUnit};
 use crate::serialization_helpers::{SerializableSingleValueColumn, SliceWithPermutation};
+use crate::string_table::{ProfileStringTable, StringHandle};
 use crate::timestamp::{
     SerializableTimestampSliceAsDeltas, SerializableTimestampSliceAsDeltasWithPermutation,
     Timestamp,
@@ -17,7 +18,7 @@ use crate::timestamp::{
 #[derive(Debug, Clone)]
 pub struct SampleTable {
     sample_weight_type: WeightType,
-    sample_weights: Vec<i32>,
+    sample_weights: Vec<SampleWeight>,
     sample_timestamps: Vec<Timestamp>,
     /// An index into the thread's stack table for each sample. `None` means the empty stack.
     sample_stack_indexes: Vec<Option<usize>>,
@@ -31,7 +32,8 @@ pub struct SampleTable {
 #[derive(Debug, Clone)]
 pub enum WeightType {
     /// The weight is an integer multiplier. For example, "this stack was
-    /// observed n times when sampling at the specified interval."
+    /// observed n times when sampling at the specified interval." This is also
+    /// the weight type for event counts, e.g. "this stack caused n cache misses".
     ///
     /// This affects the total + self score of each call node in the call tree,
     /// and the order in the tree because the tree is ordered from large "totals"
@@ -40,9 +42,8 @@ pub enum WeightType {
     Samples,
     /// The weight is a duration in (fractional) milliseconds.
     ///
-    /// Note that, since [`Profile::add_sample`](crate::Profile::add_sample) currently
-    /// only accepts integer weight values, the usefulness of `TracingMs` is
-    /// currently limited.
+    /// Use [`Profile::add_sample_with_fractional_weight`](crate::Profile::add_sample_with_fractional_weight)
+    /// to add samples with fractional weights.
     TracingMs,
     /// The weight of each sample is a value in bytes.
     ///
@@ -71,6 +72,21 @@ impl Serialize for WeightType {
     }
 }
 
+/// The weight of a sample. Integral weights are serialized as integers.
+#[derive(Debug, Clone, Copy)]
+struct SampleWeight(f64);
+
+impl Serialize for SampleWeight {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let SampleWeight(weight) = *self;
+        if weight.fract() == 0.0 && weight.abs() < (1u64 << 53) as f64 {
+            serializer.serialize_i64(weight as i64)
+        } else {
+            serializer.serialize_f64(weight)
+        }
+    }
+}
+
 impl SampleTable {
     pub fn new() -> Self {
         Self {
@@ -84,14 +100,18 @@ impl SampleTable {
         }
     }
 
+    pub fn sample_count(&self) -> usize {
+        self.sample_timestamps.len()
+    }
+
     pub fn add_sample(
         &mut self,
         timestamp: Timestamp,
         stack_index: Option<usize>,
         cpu_delta: CpuDelta,
-        weight: i32,
+        weight: f64,
     ) {
-        self.sample_weights.push(weight);
+        self.sample_weights.push(SampleWeight(weight));
         self.sample_timestamps.push(timestamp);
         self.sample_stack_indexes.push(stack_index);
         self.sample_cpu_deltas.push(cpu_delta);
@@ -105,8 +125,8 @@ impl SampleTable {
         self.sample_weight_type = t;
     }
 
-    pub fn modify_last_sample(&mut self, timestamp: Timestamp, weight: i32) {
-        *self.sample_weights.last_mut().unwrap() += weight;
+    pub fn modify_last_sample(&mut self, timestamp: Timestamp, weight: f64) {
+        self.sample_weights.last_mut().unwrap().0 += weight;
         *self.sample_timestamps.last_mut().unwrap() = timestamp;
     }
 
@@ -118,44 +138,114 @@ impl SampleTable {
             .collect();
         self
     }
+
+    pub fn retain_by_time(&mut self, mut keep: impl FnMut(Timestamp) -> bool) {
+        let keep: Vec<bool> = self.sample_timestamps.iter().map(|t| keep(*t)).collect();
+        retain_by_index(&mut self.sample_weights, &keep);
+        retain_by_index(&mut self.sample_timestamps, &keep);
+        retain_by_index(&mut self.sample_stack_indexes, &keep);
+        retain_by_index(&mut self.sample_cpu_deltas, &keep);
+    }
+
+    pub fn rebase_times(&mut self, new_reference: Timestamp) {
+        for time in &mut self.sample_timestamps {
+            *time = time.rebased_to(new_reference);
+        }
+        self.last_sample_timestamp = self.last_sample_timestamp.rebased_to(new_reference);
+    }
+
+    /// Merges each group of `factor` consecutive samples into the last sample
+    /// of the group, which gets the summed weight and CPU delta of the group.
+    pub fn merge_consecutive(&mut self, factor: usize) {
+        let len = self.sample_timestamps.len();
+        let keep: Vec<bool> = (1..=len).map(|n| n % factor == 0 || n == len).collect();
+        let mut merged_weight = 0.0;
+        let mut merged_cpu_delta = CpuDelta::ZERO;
+        for (i, keep) in keep.iter().enumerate() {
+            merged_weight += self.sample_weights[i].0;
+            merged_cpu_delta = merged_cpu_delta + self.sample_cpu_deltas[i];
+            if *keep {
+                self.sample_weights[i] = SampleWeight(std::mem::take(&mut merged_weight));
+                self.sample_cpu_deltas[i] =
+                    std::mem::replace(&mut merged_cpu_delta, CpuDelta::ZERO);
+            }
+        }
+        retain_by_index(&mut self.sample_weights, &keep);
+        retain_by_index(&mut self.sample_timestamps, &keep);
+        retain_by_index(&mut self.sample_stack_indexes, &keep);
+        retain_by_index(&mut self.sample_cpu_deltas, &keep);
+    }
+}
+
+pub(crate) fn retain_by_index<T>(column: &mut Vec<T>, keep: &[bool]) {
+    let mut index = 0;
+    column.retain(|_| {
+        index += 1;
+        keep[index - 1]
+    });
+}
+
+impl SampleTable {
+    pub fn as_serializable(&self, cpu_delta_unit: CpuDeltaUnit) -> impl Serialize + '_ {
+        SerializableSampleTable {
+            table: self,
+            cpu_delta_unit,
+        }
+    }
+}
+
+struct SerializableSampleTable<'a> {
+    table: &'a SampleTable,
+    cpu_delta_unit: CpuDeltaUnit,
 }
 
-impl Serialize for SampleTable {
+impl Serialize for SerializableSampleTable<'_> {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let len = self.sample_timestamps.len();
+        let Self {
+            table: this,
+            cpu_delta_unit,
+        } = self;
+        let cpu_deltas: Vec<u64> = this
+            .sample_cpu_deltas
+            .iter()
+            .map(|delta| delta.value_in(*cpu_delta_unit))
+            .collect();
+        let len = this.sample_timestamps.len();
         let mut map = serializer.serialize_map(None)?;
         map.serialize_entry("length", &len)?;
-        map.serialize_entry("weightType", &self.sample_weight_type.to_string())?;
+        map.serialize_entry("weightType", &this.sample_weight_type.to_string())?;
 
-        if self.is_sorted_by_time {
-            map.serialize_entry("stack", &self.sample_stack_indexes)?;
+        if this.is_sorted_by_time {
+            map.serialize_entry("stack", &this.sample_stack_indexes)?;
             map.serialize_entry(
                 "timeDeltas",
-                &SerializableTimestampSliceAsDeltas(&self.sample_timestamps),
+                &SerializableTimestampSliceAsDeltas(&this.sample_timestamps),
             )?;
-            map.serialize_entry("weight", &self.sample_weights)?;
-            map.serialize_entry("threadCPUDelta", &self.sample_cpu_deltas)?;
+            map.serialize_entry("weight", &this.sample_weights)?;
+            map.serialize_entry("threadCPUDelta", &cpu_deltas)?;
         } else {
-            let mut indexes: Vec<usize> = (0..self.sample_timestamps.len()).collect();
-            indexes.sort_unstable_by_key(|index| self.sample_timestamps[*index]);
+            let mut indexes: Vec<usize> = (0..this.sample_timestamps.len()).collect();
+            // A stable sort, so that samples with the same timestamp stay in the
+            // order in which they were added.
+            indexes.sort_by_key(|index| this.sample_timestamps[*index]);
             map.serialize_entry(
                 "stack",
-                &SliceWithPermutation(&self.sample_stack_indexes, &indexes),
+                &SliceWithPermutation(&this.sample_stack_indexes, &indexes),
             )?;
             map.serialize_entry(
                 "timeDeltas",
                 &SerializableTimestampSliceAsDeltasWithPermutation(
-                    &self.sample_timestamps,
+                    &this.sample_timestamps,
                     &indexes,
                 ),
             )?;
             map.serialize_entry(
                 "weight",
-                &SliceWithPermutation(&self.sample_weights, &indexes),
+                &SliceWithPermutation(&this.sample_weights, &indexes),
             )?;
             map.serialize_entry(
                 "threadCPUDelta",
-                &SliceWithPermutation(&self.sample_cpu_deltas, &indexes),
+                &SliceWithPermutation(&cpu_deltas, &indexes),
             )?;
         }
         map.end()
@@ -201,6 +291,9 @@ pub struct NativeAllocationsTable {
     allocation_size: Vec<i64>,
     /// The memory address of the allocation for each sample
     allocation_address: Vec<u64>,
+    /// The tid of the thread which made the allocation or deallocation, or 0
+    /// if unknown, for each sample
+    thread_id: Vec<u32>,
 }
 
 impl NativeAllocationsTable {
@@ -211,11 +304,13 @@ impl NativeAllocationsTable {
         stack_index: Option<usize>,
         allocation_address: u64,
         allocation_size: i64,
+        thread_id: u32,
     ) {
         self.time.push(timestamp);
         self.stack.push(stack_index);
         self.allocation_address.push(allocation_address);
         self.allocation_size.push(allocation_size);
+        self.thread_id.push(thread_id);
     }
 
     pub fn with_remapped_stacks(mut self, old_stack_to_new_stack: &[Option<usize>]) -> Self {
@@ -226,6 +321,21 @@ impl NativeAllocationsTable {
             .collect();
         self
     }
+
+    pub fn retain_by_time(&mut self, mut keep: impl FnMut(Timestamp) -> bool) {
+        let keep: Vec<bool> = self.time.iter().map(|t| keep(*t)).collect();
+        retain_by_index(&mut self.time, &keep);
+        retain_by_index(&mut self.stack, &keep);
+        retain_by_index(&mut self.allocation_size, &keep);
+        retain_by_index(&mut self.allocation_address, &keep);
+        retain_by_index(&mut self.thread_id, &keep);
+    }
+
+    pub fn rebase_times(&mut self, new_reference: Timestamp) {
+        for time in &mut self.time {
+            *time = time.rebased_to(new_reference);
+        }
+    }
 }
 
 impl Serialize for NativeAllocationsTable {
@@ -237,17 +347,129 @@ impl Serialize for NativeAllocationsTable {
         map.serialize_entry("weightType", &WeightType::Bytes)?;
         map.serialize_entry("stack", &self.stack)?;
         map.serialize_entry("memoryAddress", &self.allocation_address)?;
-
         // The threadId column is currently unused by the Firefox Profiler.
-        // Fill the column with zeros because the type definitions require it to be a number.
-        // A better alternative would be to use thread indexes or the threads' string TIDs.
-        map.serialize_entry("threadId", &SerializableSingleValueColumn(0, len))?;
+        map.serialize_entry("threadId", &self.thread_id)?;
 
         map.serialize_entry("length", &len)?;
         map.end()
     }
 }
 
+/// JS documentation of the JS allocations table:
+///
+/// ```ignore
+/// export type JsAllocationsTable = {|
+///   time: Milliseconds[],
+///   className: string[],
+///   typeName: string[], // Currently only 'JSObject'
+///   coarseType: string[], // Currently only 'Object',
+///   // "weight" is used here rather than "bytes", so that this type will match the
+///   // SamplesLikeTableShape.
+///   weight: Bytes[],
+///   weightType: 'bytes',
+///   inNursery: boolean[],
+///   stack: Array<IndexIntoStackTable | null>,
+///   length: number,
+/// |};
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct JsAllocationsTable {
+    /// The timestamps for each sample
+    time: Vec<Timestamp>,
+    /// The stack index for each sample
+    stack: Vec<Option<usize>>,
+    /// The class name of the allocated object for each sample
+    class_name: Vec<StringHandle>,
+    /// The size in bytes of the allocated object for each sample
+    allocation_size: Vec<u64>,
+    /// Whether the object was allocated in the nursery, for each sample
+    in_nursery: Vec<bool>,
+}
+
+impl JsAllocationsTable {
+    pub fn add_sample(
+        &mut self,
+        timestamp: Timestamp,
+        stack_index: Option<usize>,
+        class_name: StringHandle,
+        allocation_size: u64,
+        in_nursery: bool,
+    ) {
+        self.time.push(timestamp);
+        self.stack.push(stack_index);
+        self.class_name.push(class_name);
+        self.allocation_size.push(allocation_size);
+        self.in_nursery.push(in_nursery);
+    }
+
+    pub fn with_remapped_stacks(mut self, old_stack_to_new_stack: &[Option<usize>]) -> Self {
+        self.stack = self
+            .stack
+            .into_iter()
+            .map(|stack| stack.and_then(|s| old_stack_to_new_stack[s]))
+            .collect();
+        self
+    }
+
+    pub fn retain_by_time(&mut self, mut keep: impl FnMut(Timestamp) -> bool) {
+        let keep: Vec<bool> = self.time.iter().map(|t| keep(*t)).collect();
+        retain_by_index(&mut self.time, &keep);
+        retain_by_index(&mut self.stack, &keep);
+        retain_by_index(&mut self.class_name, &keep);
+        retain_by_index(&mut self.allocation_size, &keep);
+        retain_by_index(&mut self.in_nursery, &keep);
+    }
+
+    pub fn rebase_times(&mut self, new_reference: Timestamp) {
+        for time in &mut self.time {
+            *time = time.rebased_to(new_reference);
+        }
+    }
+
+    pub fn as_serializable<'a>(
+        &'a self,
+        string_table: &'a ProfileStringTable,
+    ) -> impl Serialize + 'a {
+        SerializableJsAllocationsTable {
+            table: self,
+            string_table,
+        }
+    }
+}
+
+struct SerializableJsAllocationsTable<'a> {
+    table: &'a JsAllocationsTable,
+    string_table: &'a ProfileStringTable,
+}
+
+impl Serialize for SerializableJsAllocationsTable<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Self {
+            table,
+            string_table,
+        } = self;
+        let len = table.time.len();
+        // Unlike most other string columns, these contain the strings themselves
+        // rather than string indexes.
+        let class_names: Vec<&str> = table
+            .class_name
+            .iter()
+            .map(|name| string_table.get_string(*name))
+            .collect();
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("time", &table.time)?;
+        map.serialize_entry("className", &class_names)?;
+        map.serialize_entry("typeName", &SerializableSingleValueColumn("JSObject", len))?;
+        map.serialize_entry("coarseType", &SerializableSingleValueColumn("Object", len))?;
+        map.serialize_entry("weight", &table.allocation_size)?;
+        map.serialize_entry("weightType", &WeightType::Bytes)?;
+        map.serialize_entry("inNursery", &table.in_nursery)?;
+        map.serialize_entry("stack", &table.stack)?;
+        map.serialize_entry("length", &len)?;
+        map.end()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use assert_json_diff::assert_json_eq;
@@ -325,6 +547,7 @@ mod tests {
             None,
             5969772544,
             147456,
+            0,
         );
 
         assert_json_eq!(
@@ -24,6 +24,10 @@ impl StringTable {
         }
     }
 
+    pub fn existing_index_for_string(&self, s: &str) -> Option<StringIndex> {
+        self.index.get(s).copied()
+    }
+
     pub fn get_string(&self, index: StringIndex) -> &str {
         &self.strings[index.0 as usize]
     }
@@ -54,6 +58,11 @@ impl ProfileStringTable {
         StringHandle(self.table.index_for_string(s))
     }
 
+    /// Looks up a string without adding it to the table.
+    pub fn existing_index_for_string(&self, s: &str) -> Option<StringHandle> {
+        self.table.existing_index_for_string(s).map(StringHandle)
+    }
+
     // Fast path with separate cache for strings of the shape 0xabc123
     pub fn index_for_hex_address_string(&mut self, a: u64) -> StringHandle {
         *self.hex_address_strings.entry(a).or_insert_with(|| {
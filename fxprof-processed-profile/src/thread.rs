@@ -1,18 +1,20 @@
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
+use std::ops::Range;
 
 use serde::ser::{SerializeMap, Serializer};
 
-use crate::cpu_delta::CpuDelta;
+use crate::cpu_delta::{CpuDelta, CpuDeltaUnit};
 use crate::fast_hash_map::FastHashSet;
 use crate::frame_table::{FrameInterner, InternalFrame};
 use crate::global_lib_table::{GlobalLibIndex, UsedLibraryAddressesCollector};
 use crate::marker_table::MarkerTable;
 use crate::markers::InternalMarkerSchema;
 use crate::native_symbols::{NativeSymbolIndex, NativeSymbols};
+use crate::profile::PausedRange;
 use crate::profile_symbol_info::LibSymbolInfo;
-use crate::sample_table::{NativeAllocationsTable, SampleTable, WeightType};
+use crate::sample_table::{JsAllocationsTable, NativeAllocationsTable, SampleTable, WeightType};
 use crate::stack_table::StackTable;
 use crate::string_table::{ProfileStringTable, StringHandle};
 use crate::symbolication::{apply_symbol_information, StringTableAdapter};
@@ -30,15 +32,18 @@ pub struct Thread {
     start_time: Timestamp,
     end_time: Option<Timestamp>,
     is_main: bool,
+    sort_key: i64,
     stack_table: StackTable,
     frame_interner: FrameInterner,
     samples: SampleTable,
     native_allocations: Option<NativeAllocationsTable>,
+    js_allocations: Option<JsAllocationsTable>,
     markers: MarkerTable,
     native_symbols: NativeSymbols,
     last_sample_stack: Option<usize>,
     last_sample_was_zero_cpu: bool,
     show_markers_in_timeline: bool,
+    is_removed: bool,
 }
 
 impl Thread {
@@ -50,15 +55,18 @@ impl Thread {
             start_time,
             end_time: None,
             is_main,
+            sort_key: 0,
             stack_table: StackTable::new(),
             frame_interner: FrameInterner::new(),
             samples: SampleTable::new(),
             native_allocations: None,
+            js_allocations: None,
             markers: MarkerTable::new(),
             native_symbols: NativeSymbols::new(),
             last_sample_stack: None,
             last_sample_was_zero_cpu: false,
             show_markers_in_timeline: false,
+            is_removed: false,
         }
     }
 
@@ -66,6 +74,20 @@ impl Thread {
         self.name = Some(name.to_string());
     }
 
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Removed threads keep their data, so that their handles stay valid, but
+    /// they are left out when the profile is serialized.
+    pub fn set_is_removed(&mut self, is_removed: bool) {
+        self.is_removed = is_removed;
+    }
+
+    pub fn is_removed(&self) -> bool {
+        self.is_removed
+    }
+
     pub fn set_start_time(&mut self, start_time: Timestamp) {
         self.start_time = start_time;
     }
@@ -74,6 +96,14 @@ impl Thread {
         self.end_time = Some(end_time);
     }
 
+    pub fn set_is_main(&mut self, is_main: bool) {
+        self.is_main = is_main;
+    }
+
+    pub fn set_sort_key(&mut self, sort_key: i64) {
+        self.sort_key = sort_key;
+    }
+
     pub fn set_tid(&mut self, tid: String) {
         self.tid = tid;
     }
@@ -82,10 +112,28 @@ impl Thread {
         self.show_markers_in_timeline = v;
     }
 
+    pub fn tid(&self) -> &str {
+        &self.tid
+    }
+
+    /// The numeric tid, without the suffix which makes reused tids unique.
+    pub fn numeric_tid(&self) -> u32 {
+        let tid = self.tid.split('.').next().unwrap_or_default();
+        tid.parse().unwrap_or(0)
+    }
+
     pub fn process(&self) -> ProcessHandle {
         self.process
     }
 
+    pub fn sample_count(&self) -> usize {
+        self.samples.sample_count()
+    }
+
+    pub fn marker_count(&self) -> usize {
+        self.markers.marker_count()
+    }
+
     pub fn native_symbol_index_and_string_index_for_symbol(
         &mut self,
         lib_index: GlobalLibIndex,
@@ -125,7 +173,7 @@ impl Thread {
         timestamp: Timestamp,
         stack_index: Option<usize>,
         cpu_delta: CpuDelta,
-        weight: i32,
+        weight: f64,
     ) {
         self.samples
             .add_sample(timestamp, stack_index, cpu_delta, weight);
@@ -139,15 +187,40 @@ impl Thread {
         stack_index: Option<usize>,
         allocation_address: u64,
         allocation_size: i64,
+        thread_id: u32,
     ) {
         // Create allocations table, if it doesn't exist yet.
         let allocations = self.native_allocations.get_or_insert_with(Default::default);
 
         // Add the allocation sample.
-        allocations.add_sample(timestamp, stack_index, allocation_address, allocation_size);
+        allocations.add_sample(
+            timestamp,
+            stack_index,
+            allocation_address,
+            allocation_size,
+            thread_id,
+        );
+    }
+
+    pub fn add_js_allocation_sample(
+        &mut self,
+        timestamp: Timestamp,
+        stack_index: Option<usize>,
+        class_name: StringHandle,
+        allocation_size: u64,
+        in_nursery: bool,
+    ) {
+        let allocations = self.js_allocations.get_or_insert_with(Default::default);
+        allocations.add_sample(
+            timestamp,
+            stack_index,
+            class_name,
+            allocation_size,
+            in_nursery,
+        );
     }
 
-    pub fn add_sample_same_stack_zero_cpu(&mut self, timestamp: Timestamp, weight: i32) {
+    pub fn add_sample_same_stack_zero_cpu(&mut self, timestamp: Timestamp, weight: f64) {
         if self.last_sample_was_zero_cpu {
             self.samples.modify_last_sample(timestamp, weight);
         } else {
@@ -186,6 +259,54 @@ impl Thread {
         self.markers.set_marker_stack(marker, stack_index);
     }
 
+    pub fn set_marker_cause_time(&mut self, marker: MarkerHandle, time: Timestamp) {
+        self.markers.set_marker_cause_time(marker, time);
+    }
+
+    pub fn marker_spans_with_name<'a>(
+        &'a self,
+        string_table: &'a ProfileStringTable,
+        name: &'a str,
+    ) -> impl Iterator<Item = (Timestamp, Timestamp)> + 'a {
+        self.markers.spans_with_name(string_table, name)
+    }
+
+    pub fn retain_samples_by_time(&mut self, mut keep: impl FnMut(Timestamp) -> bool) {
+        self.samples.retain_by_time(&mut keep);
+        if let Some(allocations) = &mut self.native_allocations {
+            allocations.retain_by_time(&mut keep);
+        }
+        if let Some(allocations) = &mut self.js_allocations {
+            allocations.retain_by_time(&mut keep);
+        }
+    }
+
+    /// Removes the samples, allocation samples and markers outside `range`,
+    /// clamps the thread's lifetime and its remaining markers to `range`, and
+    /// makes all times relative to the start of the range.
+    pub fn trim(&mut self, range: &Range<Timestamp>, schemas: &[InternalMarkerSchema]) {
+        self.retain_samples_by_time(|time| range.contains(&time));
+        self.samples.rebase_times(range.start);
+        if let Some(allocations) = &mut self.native_allocations {
+            allocations.rebase_times(range.start);
+        }
+        if let Some(allocations) = &mut self.js_allocations {
+            allocations.rebase_times(range.start);
+        }
+        self.markers.trim(range, schemas);
+        self.start_time = self
+            .start_time
+            .clamp(range.start, range.end)
+            .rebased_to(range.start);
+        self.end_time = self
+            .end_time
+            .map(|e| e.clamp(range.start, range.end).rebased_to(range.start));
+    }
+
+    pub fn merge_consecutive_samples(&mut self, factor: usize) {
+        self.samples.merge_consecutive(factor);
+    }
+
     pub fn contains_js_frame(&self) -> bool {
         self.frame_interner.contains_js_frame()
     }
@@ -195,6 +316,10 @@ impl Thread {
     }
 
     pub fn cmp_for_json_order(&self, other: &Thread) -> Ordering {
+        let ordering = self.sort_key.cmp(&other.sort_key);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
         let ordering = (!self.is_main).cmp(&(!other.is_main));
         if ordering != Ordering::Equal {
             return ordering;
@@ -224,15 +349,18 @@ impl Thread {
             start_time,
             end_time,
             is_main,
+            sort_key,
             stack_table,
             frame_interner,
             samples,
             native_allocations,
+            js_allocations,
             markers,
             native_symbols,
             last_sample_stack,
             last_sample_was_zero_cpu,
             show_markers_in_timeline,
+            is_removed,
         } = self;
 
         let (frame_interner, native_symbols, stack_table, old_stack_to_new_stack) =
@@ -249,6 +377,8 @@ impl Thread {
         let native_allocations = native_allocations.map(|native_allocations| {
             native_allocations.with_remapped_stacks(&old_stack_to_new_stack)
         });
+        let js_allocations = js_allocations
+            .map(|js_allocations| js_allocations.with_remapped_stacks(&old_stack_to_new_stack));
         let markers = markers.with_remapped_stacks(&old_stack_to_new_stack);
         let last_sample_stack = last_sample_stack
             .and_then(|last_sample_stack| old_stack_to_new_stack[last_sample_stack]);
@@ -260,15 +390,18 @@ impl Thread {
             start_time,
             end_time,
             is_main,
+            sort_key,
             stack_table,
             frame_interner,
             samples,
             native_allocations,
+            js_allocations,
             markers,
             native_symbols,
             last_sample_stack,
             last_sample_was_zero_cpu,
             show_markers_in_timeline,
+            is_removed,
         }
     }
 
@@ -282,6 +415,8 @@ impl Thread {
         pid: &str,
         marker_schemas: &[InternalMarkerSchema],
         string_table: &ProfileStringTable,
+        paused_ranges: &[PausedRange],
+        cpu_delta_unit: CpuDeltaUnit,
     ) -> Result<S::Ok, S::Error> {
         let thread_name: Cow<str> = match (self.is_main, &self.name) {
             (true, _) => process_name.into(),
@@ -292,7 +427,8 @@ impl Thread {
         let thread_register_time = self.start_time;
         let thread_unregister_time = self.end_time;
 
-        let (frame_table, func_table, resource_table) = self.frame_interner.create_tables();
+        let (frame_table, func_table, resource_table) =
+            self.frame_interner.create_tables(string_table);
 
         let mut map = serializer.serialize_map(None)?;
         map.serialize_entry("frameTable", &frame_table)?;
@@ -304,7 +440,7 @@ impl Thread {
         map.serialize_entry("name", &thread_name)?;
         map.serialize_entry("isMainThread", &self.is_main)?;
         map.serialize_entry("nativeSymbols", &self.native_symbols)?;
-        map.serialize_entry("pausedRanges", &[] as &[()])?;
+        map.serialize_entry("pausedRanges", paused_ranges)?;
         map.serialize_entry("pid", &pid)?;
         map.serialize_entry("processName", process_name)?;
         map.serialize_entry("processShutdownTime", &process_end_time)?;
@@ -312,10 +448,13 @@ impl Thread {
         map.serialize_entry("processType", &"default")?;
         map.serialize_entry("registerTime", &thread_register_time)?;
         map.serialize_entry("resourceTable", &resource_table)?;
-        map.serialize_entry("samples", &self.samples)?;
+        map.serialize_entry("samples", &self.samples.as_serializable(cpu_delta_unit))?;
         if let Some(allocations) = &self.native_allocations {
             map.serialize_entry("nativeAllocations", &allocations)?;
         }
+        if let Some(allocations) = &self.js_allocations {
+            map.serialize_entry("jsAllocations", &allocations.as_serializable(string_table))?;
+        }
         map.serialize_entry("stackTable", &self.stack_table)?;
         map.serialize_entry("tid", &self.tid)?;
         map.serialize_entry("unregisterTime", &thread_unregister_time)?;
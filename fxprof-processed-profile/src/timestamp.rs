@@ -18,6 +18,28 @@ impl Timestamp {
             nanos: (millis * 1_000_000.0) as u64,
         }
     }
+
+    pub(crate) fn nanos(self) -> u64 {
+        self.nanos
+    }
+
+    /// Returns this timestamp relative to `new_reference`, or zero if it's
+    /// before `new_reference`.
+    pub(crate) fn rebased_to(self, new_reference: Timestamp) -> Timestamp {
+        Self {
+            nanos: self.nanos.saturating_sub(new_reference.nanos),
+        }
+    }
+}
+
+impl std::ops::Add<std::time::Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn add(self, rhs: std::time::Duration) -> Timestamp {
+        Self {
+            nanos: self.nanos.saturating_add(rhs.as_nanos() as u64),
+        }
+    }
 }
 
 impl Serialize for Timestamp {
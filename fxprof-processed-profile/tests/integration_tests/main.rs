@@ -4,10 +4,11 @@ use std::time::Duration;
 use assert_json_diff::assert_json_eq;
 use debugid::DebugId;
 use fxprof_processed_profile::{
-    Category, CategoryColor, CpuDelta, FlowId, FrameAddress, FrameFlags, GraphColor, LibraryInfo,
-    Marker, MarkerField, MarkerGraph, MarkerGraphType, MarkerLocations, MarkerTiming, Profile,
-    ReferenceTimestamp, SamplingInterval, Schema, StringHandle, Symbol, SymbolTable, Timestamp,
-    WeightType,
+    Category, CategoryColor, CategoryHandle, CounterGraphType, CpuDelta, CpuDeltaUnit, FlowId,
+    FrameAddress, FrameFlags, GraphColor, LibraryBuildInfo, LibraryInfo, Marker, MarkerField,
+    MarkerGraph, MarkerGraphType, MarkerLocations, MarkerNumberFieldFormat, MarkerTiming, Profile,
+    ProfileStats, ReferenceTimestamp, SamplingInterval, Schema, StringHandle, Subcategory,
+    SubcategoryHandle, Symbol, SymbolTable, Timestamp, WeightType,
 };
 use serde_json::json;
 
@@ -1724,3 +1725,1235 @@ fn test_flow_marker_fields() {
         )
     );
 }
+
+#[test]
+fn retain_threads_by_name() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process0 = profile.add_process("browser", 123, Timestamp::from_millis_since_reference(0.0));
+    let process1 = profile.add_process("pool", 456, Timestamp::from_millis_since_reference(1.0));
+    let main_thread = profile.add_thread(
+        process0,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    profile.set_thread_name(main_thread, "Renderer");
+    let pool_thread = profile.add_thread(
+        process0,
+        124,
+        Timestamp::from_millis_since_reference(0.0),
+        false,
+    );
+    profile.set_thread_name(pool_thread, "Worker");
+    let other_thread = profile.add_thread(
+        process1,
+        456,
+        Timestamp::from_millis_since_reference(1.0),
+        true,
+    );
+    profile.set_thread_name(other_thread, "Worker");
+    profile.add_counter(process1, "malloc", "Memory", "Amount of allocated memory");
+    profile.add_initial_visible_thread(main_thread);
+    profile.add_initial_visible_thread(pool_thread);
+
+    profile.retain_threads(|name| name == Some("Renderer"));
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let threads = json["threads"].as_array().unwrap();
+    assert_eq!(threads.len(), 1);
+    assert_eq!(threads[0]["tid"], "123");
+    assert_eq!(threads[0]["pid"], "123");
+    assert_eq!(json["meta"]["initialVisibleThreads"], json!([0]));
+    assert_eq!(json["counters"], json!([]));
+}
+
+#[test]
+fn thread_handles_stay_valid_after_retain_threads() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let removed_thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    profile.set_thread_name(removed_thread, "Main");
+    let kept_thread = profile.add_thread(
+        process,
+        124,
+        Timestamp::from_millis_since_reference(0.0),
+        false,
+    );
+    profile.set_thread_name(kept_thread, "Worker");
+    profile.add_initial_selected_thread(kept_thread);
+
+    profile.retain_threads(|name| name == Some("Worker"));
+
+    // The kept thread's handle still refers to the kept thread, and adding
+    // data to the removed thread doesn't show up in the profile.
+    let timestamp = Timestamp::from_millis_since_reference(1.0);
+    profile.add_sample(kept_thread, timestamp, None, CpuDelta::ZERO, 3);
+    profile.add_sample(removed_thread, timestamp, None, CpuDelta::ZERO, 5);
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let threads = json["threads"].as_array().unwrap();
+    assert_eq!(threads.len(), 1);
+    assert_eq!(threads[0]["tid"], "124");
+    assert_eq!(threads[0]["samples"]["weight"], json!([3]));
+    assert_eq!(json["meta"]["initialSelectedThreads"], json!([0]));
+    assert_eq!(profile.stats().thread_count, 1);
+}
+
+#[test]
+fn thread_name_filter() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    profile.set_thread_name_filter(|name| name.is_some_and(|name| name.starts_with("Renderer")));
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let main_thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    profile.set_thread_name(main_thread, "Main");
+    let renderer_thread = profile.add_thread(
+        process,
+        124,
+        Timestamp::from_millis_since_reference(0.0),
+        false,
+    );
+    assert!(profile.thread_is_removed(renderer_thread));
+
+    // Samples are discarded while the thread's name doesn't match.
+    profile.add_sample(
+        renderer_thread,
+        Timestamp::from_millis_since_reference(1.0),
+        None,
+        CpuDelta::ZERO,
+        1,
+    );
+    profile.set_thread_name(renderer_thread, "Renderer");
+    assert!(!profile.thread_is_removed(renderer_thread));
+    profile.add_sample(
+        renderer_thread,
+        Timestamp::from_millis_since_reference(2.0),
+        None,
+        CpuDelta::ZERO,
+        2,
+    );
+    profile.add_sample(
+        main_thread,
+        Timestamp::from_millis_since_reference(2.0),
+        None,
+        CpuDelta::ZERO,
+        3,
+    );
+
+    assert!(profile.thread_is_removed(main_thread));
+    assert_eq!(profile.stats().sample_count, 1);
+    let json = serde_json::to_value(&profile).unwrap();
+    let threads = json["threads"].as_array().unwrap();
+    assert_eq!(threads.len(), 1);
+    assert_eq!(threads[0]["name"], "Renderer");
+    assert_eq!(threads[0]["samples"]["weight"], json!([2]));
+}
+
+#[test]
+fn retain_samples_between_markers() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let main_thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let other_thread = profile.add_thread(
+        process,
+        124,
+        Timestamp::from_millis_since_reference(0.0),
+        false,
+    );
+    for ms in 0..10 {
+        let timestamp = Timestamp::from_millis_since_reference(ms as f64);
+        profile.add_sample(main_thread, timestamp, None, CpuDelta::ZERO, 1);
+        profile.add_sample(other_thread, timestamp, None, CpuDelta::ZERO, 1);
+    }
+    let start_marker = TextMarker {
+        name: profile.handle_for_string("Start"),
+        text: profile.handle_for_string(""),
+    };
+    profile.add_marker(
+        other_thread,
+        MarkerTiming::Interval(
+            Timestamp::from_millis_since_reference(6.0),
+            Timestamp::from_millis_since_reference(7.0),
+        ),
+        start_marker.clone(),
+    );
+    profile.add_marker(
+        main_thread,
+        MarkerTiming::Instant(Timestamp::from_millis_since_reference(2.0)),
+        start_marker,
+    );
+
+    let spans = profile.marker_spans_with_name("Start");
+    assert_eq!(
+        spans,
+        vec![
+            (
+                Timestamp::from_millis_since_reference(2.0),
+                Timestamp::from_millis_since_reference(2.0)
+            ),
+            (
+                Timestamp::from_millis_since_reference(6.0),
+                Timestamp::from_millis_since_reference(7.0)
+            ),
+        ]
+    );
+    assert!(profile.marker_spans_with_name("Stop").is_empty());
+
+    let start = spans[0].0;
+    let end = start + Duration::from_millis(3);
+    profile.retain_samples(|timestamp| start <= timestamp && timestamp < end);
+
+    let json = serde_json::to_value(&profile).unwrap();
+    for thread in json["threads"].as_array().unwrap() {
+        assert_eq!(thread["samples"]["length"], 3);
+        assert_eq!(thread["samples"]["timeDeltas"], json!([2.0, 1.0, 1.0]));
+    }
+    assert_eq!(json["threads"][0]["markers"]["length"], 1);
+}
+
+#[test]
+fn merge_consecutive_samples() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    for ms in 0..5 {
+        profile.add_sample(
+            thread,
+            Timestamp::from_millis_since_reference(ms as f64),
+            None,
+            CpuDelta::from_micros(100 * ms),
+            1,
+        );
+    }
+
+    assert_eq!(profile.stats().sample_count, 5);
+    profile.merge_consecutive_samples(2);
+    assert_eq!(
+        profile.stats(),
+        ProfileStats {
+            thread_count: 1,
+            sample_count: 3,
+            marker_count: 0,
+            lib_count: 0,
+        }
+    );
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let samples = &json["threads"][0]["samples"];
+    assert_eq!(samples["length"], 3);
+    assert_eq!(samples["timeDeltas"], json!([1.0, 2.0, 1.0]));
+    assert_eq!(samples["weight"], json!([2, 2, 1]));
+    assert_eq!(samples["threadCPUDelta"], json!([100, 500, 400]));
+}
+
+#[test]
+fn paused_ranges() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    profile.add_paused_range(
+        Timestamp::from_millis_since_reference(2.0),
+        Timestamp::from_millis_since_reference(5.0),
+    );
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let expected = json!([{ "startTime": 2.0, "endTime": 5.0, "reason": "profiler-paused" }]);
+    assert_eq!(json["meta"]["pausedRanges"], expected);
+    assert_eq!(json["threads"][0]["pausedRanges"], expected);
+}
+
+#[test]
+fn extra_info_sections() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let json = serde_json::to_value(&profile).unwrap();
+    assert!(json["meta"].get("extra").is_none());
+
+    profile.add_extra_info_string("Exit", "Exit status", "exit code 0");
+    profile.add_extra_info_number("Exit", "Max RSS", MarkerNumberFieldFormat::Bytes, 4096.0);
+    profile.add_extra_info_number("Other", "Count", MarkerNumberFieldFormat::Integer, 3.0);
+
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(
+        json["meta"]["extra"],
+        json!([
+            {
+                "label": "Exit",
+                "entries": [
+                    { "label": "Exit status", "format": "string", "value": "exit code 0" },
+                    { "label": "Max RSS", "format": "bytes", "value": 4096.0 },
+                ],
+            },
+            {
+                "label": "Other",
+                "entries": [{ "label": "Count", "format": "integer", "value": 3.0 }],
+            },
+        ])
+    );
+    assert_eq!(
+        profile.get_extra_info_number("Exit", "Max RSS"),
+        Some(4096.0)
+    );
+    assert_eq!(profile.get_extra_info_number("Exit", "Exit status"), None);
+    assert_eq!(profile.get_extra_info_number("Other", "Max RSS"), None);
+}
+
+#[test]
+fn hidden_marker_fields() {
+    #[derive(Debug, Clone)]
+    pub struct RequestMarker {
+        pub url: StringHandle,
+        pub request_id: f64,
+    }
+
+    impl Marker for RequestMarker {
+        type FieldsType = (StringHandle, f64);
+        const UNIQUE_MARKER_TYPE_NAME: &'static str = "Request";
+        const TABLE_LABEL: Option<&'static str> =
+            Some("{marker.data.url} (request {marker.data.requestId})");
+        const FIELDS: Schema<Self::FieldsType> = Schema((
+            MarkerField::url("url", "URL"),
+            MarkerField::integer("requestId", "Request ID").hidden(),
+        ));
+
+        fn name(&self, profile: &mut Profile) -> StringHandle {
+            profile.handle_for_string("Request")
+        }
+
+        fn field_values(&self) -> Self::FieldsType {
+            (self.url, self.request_id)
+        }
+    }
+
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        12345,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let url = profile.handle_for_string("https://example.com/");
+    profile.add_marker(
+        thread,
+        MarkerTiming::Instant(Timestamp::from_millis_since_reference(1.0)),
+        RequestMarker {
+            url,
+            request_id: 7.0,
+        },
+    );
+
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(
+        json["meta"]["markerSchema"][0]["fields"],
+        json!([
+            { "key": "url", "label": "URL", "format": "url" },
+            { "key": "requestId", "label": "Request ID", "format": "integer", "hidden": true },
+        ])
+    );
+}
+
+#[test]
+fn process_and_thread_order() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let t = Timestamp::from_millis_since_reference;
+    let parent = profile.add_process("parent", 100, t(0.0));
+    let other = profile.add_process("other", 200, t(1.0));
+    let child = profile.add_process("child", 101, t(2.0));
+    let late = profile.add_process("late", 300, t(3.0));
+    profile.set_process_parent(child, parent);
+    profile.set_process_sort_key(late, -1);
+
+    profile.add_thread(parent, 100, t(0.0), true);
+    profile.add_thread(other, 200, t(1.0), true);
+    profile.add_thread(child, 101, t(2.0), true);
+    profile.add_thread(late, 300, t(3.0), true);
+    let worker = profile.add_thread(child, 102, t(2.0), false);
+    profile.set_thread_name(worker, "Worker");
+    profile.set_thread_sort_key(worker, -1);
+    let helper = profile.add_thread(other, 201, t(1.0), false);
+    profile.set_thread_name(helper, "Helper");
+    profile.set_thread_is_main(helper, true);
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let threads: Vec<_> = json["threads"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|thread| {
+            (
+                thread["tid"].as_str().unwrap(),
+                thread["isMainThread"].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        threads,
+        [
+            ("300", json!(true)),
+            ("100", json!(true)),
+            ("102", json!(false)),
+            ("101", json!(true)),
+            ("201", json!(true)),
+            ("200", json!(false)),
+        ]
+    );
+    assert_eq!(json["meta"].get("keepProfileThreadOrder"), None);
+
+    // Each process has exactly one main thread.
+    let mut main_thread_pids: Vec<_> = json["threads"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|thread| thread["isMainThread"] == json!(true))
+        .map(|thread| thread["pid"].as_str().unwrap())
+        .collect();
+    main_thread_pids.sort_unstable();
+    assert_eq!(main_thread_pids, ["100", "101", "200", "300"]);
+
+    profile.set_keep_thread_order(true);
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(json["meta"]["keepProfileThreadOrder"], json!(true));
+}
+
+#[test]
+fn inherited_frame_categories() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let gc_label = profile.handle_for_string("GC");
+    let gc_frame = profile.handle_for_frame_with_label(
+        thread,
+        gc_label,
+        Subcategory(Category("GC", CategoryColor::Orange), "Sweeping"),
+        FrameFlags::empty(),
+    );
+    let sweep_label = profile.handle_for_string("sweep");
+    let sweep_frame = profile.handle_for_frame_with_label(
+        thread,
+        sweep_label,
+        SubcategoryHandle::INHERIT,
+        FrameFlags::empty(),
+    );
+    let gc_stack = profile.handle_for_stack(thread, gc_frame, None);
+    let sweep_stack = profile.handle_for_stack(thread, sweep_frame, Some(gc_stack));
+    profile.add_sample(
+        thread,
+        Timestamp::from_millis_since_reference(1.0),
+        Some(sweep_stack),
+        CpuDelta::ZERO,
+        1,
+    );
+
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(
+        json["meta"]["categories"][1],
+        json!({ "name": "GC", "color": "orange", "subcategories": ["Other", "Sweeping"] })
+    );
+    let frame_table = &json["threads"][0]["frameTable"];
+    assert_eq!(frame_table["category"], json!([1, null]));
+    assert_eq!(frame_table["subcategory"], json!([1, null]));
+}
+
+#[test]
+fn label_frames_interleaved_with_native_frames() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let label_frame = |profile: &mut Profile, label: &str| {
+        let label = profile.handle_for_string(label);
+        profile.handle_for_frame_with_label(
+            thread,
+            label,
+            CategoryHandle::OTHER,
+            FrameFlags::empty(),
+        )
+    };
+    let main = label_frame(&mut profile, "main");
+    let dispatch = label_frame(&mut profile, "dispatch");
+    let paint = label_frame(&mut profile, "paint");
+    let event = label_frame(&mut profile, "Event: click");
+    let layer = label_frame(&mut profile, "Layer: compositing");
+
+    let stack = profile.handle_for_stack_with_label_frames(
+        thread,
+        &[(main, 0x1000), (dispatch, 0xf00), (paint, 0xe00)],
+        &[(event, 0xf00), (layer, 0xe80)],
+    );
+    profile.add_sample(
+        thread,
+        Timestamp::from_millis_since_reference(1.0),
+        stack,
+        CpuDelta::ZERO,
+        1,
+    );
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let thread_json = &json["threads"][0];
+    // Frames were created in the order main, dispatch, paint, event, layer.
+    assert_eq!(thread_json["stackTable"]["frame"], json!([0, 1, 3, 4, 2]));
+    assert_eq!(
+        thread_json["stackTable"]["prefix"],
+        json!([null, 0, 1, 2, 3])
+    );
+}
+
+#[test]
+fn fractional_sample_weights() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    profile.set_thread_samples_weight_type(thread, WeightType::TracingMs);
+    for (time, weight) in [(1.0, 0.25), (2.0, 2.0), (3.0, 1.5)] {
+        profile.add_sample_with_fractional_weight(
+            thread,
+            Timestamp::from_millis_since_reference(time),
+            None,
+            CpuDelta::ZERO,
+            weight,
+        );
+    }
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let samples = &json["threads"][0]["samples"];
+    assert_eq!(samples["weightType"], json!("tracing-ms"));
+    assert_eq!(samples["weight"], json!([0.25, 2, 1.5]));
+
+    profile.merge_consecutive_samples(2);
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(json["threads"][0]["samples"]["weight"], json!([2.25, 1.5]));
+}
+
+#[test]
+fn allocation_samples_with_thread_ids() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 100, Timestamp::from_millis_since_reference(0.0));
+    let main_thread = profile.add_thread(
+        process,
+        100,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let worker = profile.add_thread(
+        process,
+        101,
+        Timestamp::from_millis_since_reference(0.0),
+        false,
+    );
+    let label = profile.handle_for_string("alloc");
+    let frame = profile.handle_for_frame_with_label(
+        main_thread,
+        label,
+        CategoryHandle::OTHER,
+        FrameFlags::empty(),
+    );
+    let stack = profile.handle_for_stack(main_thread, frame, None);
+    profile.add_allocation_sample_on_thread(
+        worker,
+        Timestamp::from_millis_since_reference(1.0),
+        Some(stack),
+        0x7f00_0000,
+        4096,
+    );
+    profile.add_allocation_sample(
+        process,
+        Timestamp::from_millis_since_reference(2.0),
+        None,
+        0x7f00_0000,
+        -4096,
+    );
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let allocations = &json["threads"][0]["nativeAllocations"];
+    assert_eq!(allocations["weight"], json!([4096, -4096]));
+    assert_eq!(allocations["stack"], json!([0, null]));
+    assert_eq!(
+        allocations["memoryAddress"],
+        json!([0x7f00_0000, 0x7f00_0000])
+    );
+    assert_eq!(allocations["threadId"], json!([101, 0]));
+    assert_eq!(allocations["length"], json!(2));
+    assert!(json["threads"][1].get("nativeAllocations").is_none());
+}
+
+#[test]
+fn marker_cause() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let label = profile.handle_for_string("read_file");
+    let frame = profile.handle_for_frame_with_label(
+        thread,
+        label,
+        CategoryHandle::OTHER,
+        FrameFlags::empty(),
+    );
+    let stack = profile.handle_for_stack(thread, frame, None);
+    let name = profile.handle_for_string("IO read");
+    let text = profile.handle_for_string("12ms");
+    let with_cause = profile.add_marker(
+        thread,
+        MarkerTiming::Interval(
+            Timestamp::from_millis_since_reference(2.0),
+            Timestamp::from_millis_since_reference(14.0),
+        ),
+        TextMarker { name, text },
+    );
+    profile.set_marker_cause(
+        thread,
+        with_cause,
+        stack,
+        Timestamp::from_millis_since_reference(2.0),
+    );
+    let with_stack = profile.add_marker(
+        thread,
+        MarkerTiming::Instant(Timestamp::from_millis_since_reference(15.0)),
+        TextMarker { name, text },
+    );
+    profile.set_marker_stack(thread, with_stack, Some(stack));
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let data = &json["threads"][0]["markers"]["data"];
+    assert_eq!(data[0]["cause"], json!({ "stack": 0, "time": 2.0 }));
+    assert_eq!(data[1]["cause"], json!({ "stack": 0 }));
+}
+
+#[test]
+fn ipc_marker_pair() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let client_process =
+        profile.add_process("client", 100, Timestamp::from_millis_since_reference(0.0));
+    let client = profile.add_thread(
+        client_process,
+        100,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let server_process =
+        profile.add_process("server", 200, Timestamp::from_millis_since_reference(0.0));
+    let server = profile.add_thread(
+        server_process,
+        201,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    profile.add_ipc_marker_pair(
+        "GetConfig",
+        0x2a,
+        client,
+        MarkerTiming::Instant(Timestamp::from_millis_since_reference(1.0)),
+        server,
+        MarkerTiming::Interval(
+            Timestamp::from_millis_since_reference(1.5),
+            Timestamp::from_millis_since_reference(3.0),
+        ),
+    );
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let strings = json["shared"]["stringArray"].as_array().unwrap();
+    let marker_data = |thread: usize| {
+        let data = &json["threads"][thread]["markers"]["data"][0];
+        let string = |key: &str| strings[data[key].as_u64().unwrap() as usize].clone();
+        json!({
+            "type": data["type"],
+            "direction": string("direction"),
+            "senderTid": string("senderTid"),
+            "receiverTid": string("receiverTid"),
+            "correlationId": string("correlationId"),
+        })
+    };
+    let expected = |direction: &str| {
+        json!({
+            "type": "IpcMessage",
+            "direction": direction,
+            "senderTid": "100",
+            "receiverTid": "201",
+            "correlationId": "2a",
+        })
+    };
+    assert_eq!(marker_data(0), expected("sending"));
+    assert_eq!(marker_data(1), expected("receiving"));
+    assert_eq!(json["threads"][1]["markers"]["endTime"], json!([3.0]));
+}
+
+#[test]
+fn cpu_delta_units() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    for (time, cpu_delta) in [
+        (1.0, CpuDelta::from_nanos(1500)),
+        (2.0, CpuDelta::from_micros(3)),
+    ] {
+        profile.add_sample(
+            thread,
+            Timestamp::from_millis_since_reference(time),
+            None,
+            cpu_delta,
+            1,
+        );
+    }
+
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(json["meta"]["sampleUnits"]["threadCPUDelta"], json!("µs"));
+    assert_eq!(
+        json["threads"][0]["samples"]["threadCPUDelta"],
+        json!([1, 3])
+    );
+
+    profile.set_cpu_delta_unit(CpuDeltaUnit::Nanoseconds);
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(json["meta"]["sampleUnits"]["threadCPUDelta"], json!("ns"));
+    assert_eq!(
+        json["threads"][0]["samples"]["threadCPUDelta"],
+        json!([1500, 3000])
+    );
+}
+
+#[test]
+fn trim_to_time_range() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let counter = profile.add_counter(process, "malloc", "Memory", "Amount of allocated memory");
+    let mut markers = Vec::new();
+    for time in [1.0, 2.0, 3.0, 4.0, 5.0] {
+        let timestamp = Timestamp::from_millis_since_reference(time);
+        profile.add_sample(thread, timestamp, None, CpuDelta::ZERO, 1);
+        profile.add_counter_sample(counter, timestamp, time, 1);
+    }
+    for (name, timing) in [
+        (
+            "before",
+            MarkerTiming::Instant(Timestamp::from_millis_since_reference(0.5)),
+        ),
+        (
+            "straddling",
+            MarkerTiming::Interval(
+                Timestamp::from_millis_since_reference(1.0),
+                Timestamp::from_millis_since_reference(3.0),
+            ),
+        ),
+        (
+            "inside",
+            MarkerTiming::Instant(Timestamp::from_millis_since_reference(3.5)),
+        ),
+        (
+            "at end",
+            MarkerTiming::Instant(Timestamp::from_millis_since_reference(4.5)),
+        ),
+        (
+            "after",
+            MarkerTiming::Instant(Timestamp::from_millis_since_reference(5.0)),
+        ),
+    ] {
+        let marker = TextMarker {
+            name: profile.handle_for_string(name),
+            text: profile.handle_for_string(name),
+        };
+        markers.push(profile.add_marker(thread, timing, marker));
+    }
+
+    profile.trim(
+        Timestamp::from_millis_since_reference(2.0)..Timestamp::from_millis_since_reference(4.5),
+    );
+
+    // Handles from before the trim still refer to the same markers, and the
+    // handles of removed markers are ignored.
+    let label = profile.handle_for_string("frame");
+    let frame = profile.handle_for_frame_with_label(
+        thread,
+        label,
+        CategoryHandle::OTHER,
+        FrameFlags::empty(),
+    );
+    let stack = profile.handle_for_stack(thread, frame, None);
+    profile.set_marker_stack(thread, markers[0], Some(stack));
+    profile.set_marker_cause(
+        thread,
+        markers[2],
+        stack,
+        Timestamp::from_millis_since_reference(3.0),
+    );
+    let name = profile.handle_for_string("added after trim");
+    let added_after_trim = profile.add_marker(
+        thread,
+        MarkerTiming::Instant(Timestamp::from_millis_since_reference(2.0)),
+        TextMarker { name, text: name },
+    );
+    profile.set_marker_stack(thread, added_after_trim, Some(stack));
+
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(json["meta"]["startTime"], json!(1636162232629.0));
+    let thread_json = &json["threads"][0];
+    assert_eq!(thread_json["registerTime"], json!(0.0));
+    assert_eq!(thread_json["samples"]["timeDeltas"], json!([0.0, 1.0, 1.0]));
+    let markers = &thread_json["markers"];
+    assert_eq!(markers["length"], json!(4));
+    assert_eq!(markers["startTime"], json!([0.0, 1.5, 2.5, 2.0]));
+    assert_eq!(markers["endTime"][0], json!(1.0));
+    assert_eq!(markers["data"][0]["name"], markers["name"][0]);
+    assert_eq!(markers["data"][0].get("cause"), None);
+    assert_eq!(markers["data"][1]["cause"]["time"], json!(3.0));
+    assert_eq!(markers["data"][2].get("cause"), None);
+    assert_eq!(markers["data"][3]["cause"]["stack"], json!(0));
+    assert_eq!(
+        json["counters"][0]["samples"]["count"],
+        json!([2.0, 3.0, 4.0])
+    );
+}
+
+#[test]
+fn system_info_meta() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(json["meta"].get("CPUName"), None);
+
+    profile.set_os_name("Android 14");
+    profile.set_cpu_name("Cortex-A78");
+    profile.set_cpu_counts(8, 8);
+    profile.set_main_memory(8 * 1024 * 1024 * 1024);
+    profile.set_device_name("Pixel 8");
+    profile.set_profiler_version("samply 0.13.1");
+    profile.add_extra_info_string("Recording", "Command", "app --flag");
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let meta = &json["meta"];
+    assert_eq!(meta["oscpu"], json!("Android 14"));
+    assert_eq!(meta["CPUName"], json!("Cortex-A78"));
+    assert_eq!(meta["physicalCPUs"], json!(8));
+    assert_eq!(meta["logicalCPUs"], json!(8));
+    assert_eq!(meta["mainMemory"], json!(8589934592u64));
+    assert_eq!(meta["device"], json!("Pixel 8"));
+    assert_eq!(meta["appBuildID"], json!("samply 0.13.1"));
+    assert_eq!(meta["extra"][0]["entries"][0]["value"], json!("app --flag"));
+}
+
+#[test]
+fn counter_graph_type_and_description() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let memory_counter =
+        profile.add_counter(process, "malloc", "Memory", "Amount of allocated memory");
+    let power_counter = profile.add_counter(process, "cpu-power", "power", "CPU power");
+    profile.set_counter_graph_type(memory_counter, CounterGraphType::Stack);
+    profile.set_counter_graph_type(power_counter, CounterGraphType::LineRate);
+    profile.set_counter_description(power_counter, "Power used by the CPU package");
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let counters = &json["counters"];
+    assert_eq!(counters[0]["graphType"], json!("stack"));
+    assert_eq!(
+        counters[0]["description"],
+        json!("Amount of allocated memory")
+    );
+    assert_eq!(counters[1]["graphType"], json!("line-rate"));
+    assert_eq!(
+        counters[1]["description"],
+        json!("Power used by the CPU package")
+    );
+
+    profile.add_counter(process, "other", "Other", "");
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(json["counters"][2].get("graphType"), None);
+}
+
+#[test]
+fn frame_implementations() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let label = profile.handle_for_string("run");
+    for (time, tier) in [
+        (1.0, FrameFlags::IS_INTERPRETED),
+        (2.0, FrameFlags::IS_BASELINE_JIT),
+        (3.0, FrameFlags::IS_OPTIMIZED_JIT),
+    ] {
+        let frame = profile.handle_for_frame_with_label(
+            thread,
+            label,
+            CategoryHandle::OTHER,
+            FrameFlags::IS_JS | tier,
+        );
+        let stack = profile.handle_for_stack(thread, frame, None);
+        profile.add_sample(
+            thread,
+            Timestamp::from_millis_since_reference(time),
+            Some(stack),
+            CpuDelta::ZERO,
+            1,
+        );
+    }
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let thread_json = &json["threads"][0];
+    let implementations: Vec<&str> = thread_json["frameTable"]["implementation"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| {
+            json["shared"]["stringArray"][s.as_u64().unwrap() as usize]
+                .as_str()
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(implementations, ["interpreter", "baseline", "ion"]);
+    // All three frames belong to the same JS function.
+    assert_eq!(thread_json["frameTable"]["func"], json!([0, 0, 0]));
+    assert_eq!(thread_json["funcTable"]["isJS"], json!([true]));
+}
+
+#[test]
+fn js_allocation_samples() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let label = profile.handle_for_string("allocate");
+    let frame = profile.handle_for_frame_with_label(
+        thread,
+        label,
+        CategoryHandle::OTHER,
+        FrameFlags::IS_JS,
+    );
+    let stack = profile.handle_for_stack(thread, frame, None);
+    profile.add_sample(
+        thread,
+        Timestamp::from_millis_since_reference(1.0),
+        Some(stack),
+        CpuDelta::ZERO,
+        1,
+    );
+    let array = profile.handle_for_string("Array");
+    let object = profile.handle_for_string("Object");
+    profile.add_js_allocation_sample(
+        thread,
+        Timestamp::from_millis_since_reference(1.5),
+        Some(stack),
+        array,
+        64,
+        true,
+    );
+    profile.add_js_allocation_sample(
+        thread,
+        Timestamp::from_millis_since_reference(2.0),
+        None,
+        object,
+        32,
+        false,
+    );
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let thread_json = &json["threads"][0];
+    assert_eq!(thread_json["samples"]["length"], json!(1));
+    assert_eq!(
+        thread_json["jsAllocations"],
+        json!({
+            "time": [1.5, 2.0],
+            "className": ["Array", "Object"],
+            "typeName": ["JSObject", "JSObject"],
+            "coarseType": ["Object", "Object"],
+            "weight": [64, 32],
+            "weightType": "bytes",
+            "inNursery": [true, false],
+            "stack": [0, null],
+            "length": 2
+        })
+    );
+    assert_eq!(thread_json.get("nativeAllocations"), None);
+}
+
+#[test]
+fn serialization_is_deterministic() {
+    fn build_profile() -> Profile {
+        let mut profile = Profile::new(
+            "test",
+            ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+            SamplingInterval::from_millis(1),
+        );
+        let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+        for tid in [125, 124, 123] {
+            let thread = profile.add_thread(
+                process,
+                tid,
+                Timestamp::from_millis_since_reference(0.0),
+                false,
+            );
+            for (time, name) in [(2.0, "b"), (1.0, "a"), (2.0, "c"), (1.0, "d")] {
+                let label = profile.handle_for_string(name);
+                let frame = profile.handle_for_frame_with_label(
+                    thread,
+                    label,
+                    CategoryHandle::OTHER,
+                    FrameFlags::empty(),
+                );
+                let stack = profile.handle_for_stack(thread, frame, None);
+                profile.add_sample(
+                    thread,
+                    Timestamp::from_millis_since_reference(time),
+                    Some(stack),
+                    CpuDelta::ZERO,
+                    1,
+                );
+                let marker = TextMarker {
+                    name: label,
+                    text: label,
+                };
+                profile.add_marker(
+                    thread,
+                    MarkerTiming::Instant(Timestamp::from_millis_since_reference(time)),
+                    marker,
+                );
+            }
+        }
+        profile.add_extra_info_string("Section", "Key", "Value");
+        profile
+    }
+
+    let first = serde_json::to_string(&build_profile()).unwrap();
+    let second = serde_json::to_string(&build_profile()).unwrap();
+    assert_eq!(first, second);
+
+    // Samples with equal timestamps keep the order in which they were added.
+    let json: serde_json::Value = serde_json::from_str(&first).unwrap();
+    assert_eq!(json["threads"][0]["samples"]["stack"], json!([1, 3, 0, 2]));
+}
+
+#[test]
+fn lib_build_info() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let add_lib = |profile: &mut Profile, name: &str, start_avma: u64| {
+        let lib = profile.add_lib(LibraryInfo {
+            name: name.to_string(),
+            debug_name: name.to_string(),
+            path: format!("/usr/lib/{name}"),
+            code_id: None,
+            debug_path: format!("/usr/lib/{name}"),
+            debug_id: DebugId::nil(),
+            arch: None,
+        });
+        profile.add_lib_mapping(process, lib, start_avma, start_avma + 0x1000, 0);
+        lib
+    };
+    let rust_lib = add_lib(&mut profile, "librust.so", 0x10000);
+    let gcc_lib = add_lib(&mut profile, "libgcc.so", 0x20000);
+    add_lib(&mut profile, "libplain.so", 0x30000);
+    profile.set_lib_build_info(
+        rust_lib,
+        LibraryBuildInfo {
+            compiler: Some("rustc version 1.70.0 (90c541806 2023-05-31)".to_string()),
+            opt_level: None,
+        },
+    );
+    profile.set_lib_build_info(
+        gcc_lib,
+        LibraryBuildInfo {
+            compiler: Some("GCC: (GNU) 13.2.0".to_string()),
+            opt_level: Some("-O2".to_string()),
+        },
+    );
+
+    let mut frames = [0x30010, 0x20010, 0x10010].into_iter();
+    let stack = profile.handle_for_stack_frames(thread, |p| {
+        Some(p.handle_for_frame_with_address(
+            thread,
+            FrameAddress::ReturnAddress(frames.next()?),
+            CategoryHandle::OTHER,
+            FrameFlags::empty(),
+        ))
+    });
+    profile.add_sample(
+        thread,
+        Timestamp::from_millis_since_reference(1.0),
+        stack,
+        CpuDelta::ZERO,
+        1,
+    );
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let build_infos: Vec<_> = json["libs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|lib| {
+            (
+                lib["name"].as_str().unwrap(),
+                lib.get("compiler").cloned(),
+                lib.get("optLevel").cloned(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        build_infos,
+        [
+            ("libplain.so", None, None),
+            (
+                "libgcc.so",
+                Some(json!("GCC: (GNU) 13.2.0")),
+                Some(json!("-O2"))
+            ),
+            (
+                "librust.so",
+                Some(json!("rustc version 1.70.0 (90c541806 2023-05-31)")),
+                None
+            ),
+        ]
+    );
+}
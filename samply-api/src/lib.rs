@@ -238,6 +238,9 @@ impl<'a, H: FileAndPathHelper + 'static> Api<'a, H> {
     /// The following "URLs" are supported:
     ///  - `/symbolicate/v5`: This API is documented at <https://tecken.readthedocs.io/en/latest/symbolication.html>.
     ///    The returned data has two extra fields: inlines (per address) and module_errors (per job).
+    ///    Libraries in the memory map can also carry a code ID, as a third array element or in
+    ///    object form (`{ "debugName", "breakpadId", "codeId" }`). ELF libraries can be given
+    ///    by their build ID alone.
     ///  - `/source/v1`: Experimental API. Symbolicates an address and lets you read one of the files in the
     ///    symbol information for that address.
     ///  - `/asm/v1`: Experimental API. Symbolicates an address and lets you read one of the files in the
@@ -1,9 +1,9 @@
 use std::collections::BTreeMap;
 
-use samply_symbols::{FrameDebugInfo, FunctionNameHandle, SymbolInfo};
+use samply_symbols::{FrameDebugInfo, FunctionNameHandle, SymbolInfo64};
 
 pub struct AddressResult {
-    pub symbol: SymbolInfo,
+    pub symbol: SymbolInfo64,
     /// The function name from debug info, if available. This may be more
     /// accurate than the symbol name from the symbol table.
     pub function_name: Option<FunctionNameHandle>,
@@ -11,7 +11,7 @@ pub struct AddressResult {
 }
 
 impl AddressResult {
-    pub fn new(symbol: SymbolInfo) -> Self {
+    pub fn new(symbol: SymbolInfo64) -> Self {
         Self {
             symbol,
             function_name: None,
@@ -27,4 +27,4 @@ impl AddressResult {
     }
 }
 
-pub type AddressResults = BTreeMap<u32, Option<AddressResult>>;
+pub type AddressResults = BTreeMap<u64, Option<AddressResult>>;
@@ -1,9 +1,11 @@
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
 
+use samply_symbols::debugid::DebugId;
 use samply_symbols::{
-    AccessPatternHint, FileAndPathHelper, FramesLookupResult, LibraryInfo, LookupAddress,
-    SymbolManager,
+    AccessPatternHint, CodeId, DebugIdExt, FileAndPathHelper, FramesLookupResult, LibraryInfo,
+    LookupAddress64, SymbolManager,
 };
 
 use crate::error::Error;
@@ -51,7 +53,7 @@ impl<'a, H: FileAndPathHelper + 'static> SymbolicateApi<'a, H> {
 
     async fn symbolicate_requested_addresses(
         &self,
-        requested_addresses: HashMap<Lib, Vec<u32>>,
+        requested_addresses: HashMap<Lib, Vec<u64>>,
     ) -> HashMap<Lib, Result<LibSymbols<H>, samply_symbols::Error>> {
         let mut symbolicated_addresses = HashMap::new();
         for (lib, addresses) in requested_addresses.into_iter() {
@@ -66,13 +68,25 @@ impl<'a, H: FileAndPathHelper + 'static> SymbolicateApi<'a, H> {
     async fn symbolicate_requested_addresses_for_lib(
         &self,
         lib: &Lib,
-        addresses: &[u32],
+        addresses: &[u64],
     ) -> Result<LibSymbols<H>, samply_symbols::Error> {
-        let debug_id = to_debug_id(&lib.breakpad_id)?;
+        let code_id = lib
+            .code_id
+            .as_deref()
+            .and_then(|code_id| CodeId::from_str(code_id).ok());
+        let debug_id = match &code_id {
+            // The debug ID of an ELF binary is derived from its build ID. This
+            // assumes a little-endian binary, which is the common case.
+            Some(CodeId::ElfBuildId(build_id)) if lib.breakpad_id.is_empty() => {
+                DebugId::from_identifier(&build_id.0, true)
+            }
+            _ => to_debug_id(&lib.breakpad_id)?,
+        };
 
         let info = LibraryInfo {
-            debug_name: Some(lib.debug_name.to_string()),
+            debug_name: (!lib.debug_name.is_empty()).then(|| lib.debug_name.to_string()),
             debug_id: Some(debug_id),
+            code_id,
             ..Default::default()
         };
         let symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
@@ -89,7 +103,7 @@ impl<'a, H: FileAndPathHelper + 'static> SymbolicateApi<'a, H> {
         let mut external_addresses = Vec::new();
 
         for (&address, address_result) in &mut address_results {
-            let Some(address_info) = symbol_map.lookup_sync(LookupAddress::Relative(address))
+            let Some(address_info) = symbol_map.lookup_sync_u64(LookupAddress64::Relative(address))
             else {
                 continue;
             };
@@ -129,10 +143,10 @@ impl<'a, H: FileAndPathHelper + 'static> SymbolicateApi<'a, H> {
 
 fn gather_requested_addresses(
     request: &request_json::Request,
-) -> Result<HashMap<Lib, Vec<u32>>, Error> {
-    let mut requested_addresses: HashMap<Lib, Vec<u32>> = HashMap::new();
+) -> Result<HashMap<Lib, Vec<u64>>, Error> {
+    let mut requested_addresses: HashMap<Lib, Vec<u64>> = HashMap::new();
     for job in request.jobs() {
-        let mut requested_addresses_by_module_index: HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut requested_addresses_by_module_index: HashMap<u32, Vec<u64>> = HashMap::new();
         for stack in &job.stacks {
             for frame in &stack.0 {
                 requested_addresses_by_module_index
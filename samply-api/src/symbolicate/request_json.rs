@@ -23,10 +23,21 @@ pub struct Job {
     pub stacks: Vec<RequestStack>,
 }
 
+/// A library in the memory map. This can be given as an array
+/// `[debugName, breakpadId]` or `[debugName, breakpadId, codeId]`, or as an
+/// object with `debugName`, `breakpadId` and `codeId` properties.
+///
+/// The debug name and the breakpad ID can be empty for ELF libraries with a
+/// known build ID, which is then used as the code ID.
 #[derive(Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Lib {
+    #[serde(default)]
     pub debug_name: String,
+    #[serde(default)]
     pub breakpad_id: String,
+    #[serde(default)]
+    pub code_id: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -37,7 +48,7 @@ pub struct RequestFrame {
     /// index into memory_map
     pub module_index: u32,
     /// lib-relative memory offset
-    pub address: u32,
+    pub address: u64,
 }
 
 pub enum JobIterator<'a> {
@@ -120,4 +131,30 @@ mod test {
         assert_eq!(r.jobs().count(), 1);
         Ok(())
     }
+
+    #[test]
+    fn parse_libs_with_code_id() -> Result<()> {
+        let data = r#"
+        {
+            "memoryMap": [
+              ["libxul.so", "8B2050FC954C95F6E212DFCF8B7F810F0"],
+              ["libxul.so", "8B2050FC954C95F6E212DFCF8B7F810F0", "fc50208b4c95f695e212dfcf8b7f810f56514918"],
+              { "codeId": "fc50208b4c95f695e212dfcf8b7f810f56514918" }
+            ],
+            "stacks": [[[0, 11723767], [1, 65802], [2, 65802]]]
+          }
+          "#;
+
+        let r: Request = serde_json::from_str(data)?;
+        let memory_map = &r.jobs().next().unwrap().memory_map;
+        assert_eq!(memory_map[0].code_id, None);
+        assert_eq!(
+            memory_map[1].code_id.as_deref(),
+            Some("fc50208b4c95f695e212dfcf8b7f810f56514918")
+        );
+        assert_eq!(memory_map[2].debug_name, "");
+        assert_eq!(memory_map[2].breakpad_id, "");
+        assert_eq!(memory_map[1].code_id, memory_map[2].code_id);
+        Ok(())
+    }
 }
@@ -6,8 +6,8 @@ use assert_json_diff::assert_json_eq;
 pub use samply_api::debugid::DebugId;
 use samply_api::samply_symbols;
 use samply_symbols::{
-    CandidatePathInfo, FileAndPathHelper, FileAndPathHelperResult, FileLocation, LibraryInfo,
-    OptionallySendFuture, SymbolManager,
+    CandidatePathInfo, CodeId, FileAndPathHelper, FileAndPathHelperResult, FileLocation,
+    LibraryInfo, OptionallySendFuture, SymbolManager,
 };
 
 #[derive(serde_derive::Serialize)]
@@ -36,13 +36,26 @@ impl FileAndPathHelper for Helper {
         &self,
         library_info: &LibraryInfo,
     ) -> FileAndPathHelperResult<Vec<CandidatePathInfo<FileLocationType>>> {
+        let mut paths = vec![];
+
+        // Check the .build-id directory for ELF debug files.
+        if let Some(CodeId::ElfBuildId(build_id)) = &library_info.code_id {
+            let build_id = build_id.to_string();
+            if build_id.len() > 2 {
+                paths.push(CandidatePathInfo::SingleFile(FileLocationType(
+                    self.symbol_directory
+                        .join(".build-id")
+                        .join(&build_id[..2])
+                        .join(format!("{}.debug", &build_id[2..])),
+                )));
+            }
+        }
+
         let debug_name = match library_info.debug_name.as_deref() {
             Some(debug_name) => debug_name,
-            None => return Ok(Vec::new()),
+            None => return Ok(paths),
         };
 
-        let mut paths = vec![];
-
         // Check .so.dbg files in the symbol directory.
         if debug_name.ends_with(".so") {
             let debug_debug_name = format!("{debug_name}.dbg");
@@ -344,6 +357,57 @@ fn android32_v5_local() {
     );
 }
 
+#[test]
+fn elf_build_id_without_debug_name() {
+    let output = futures::executor::block_on(crate::query_api(
+        "/symbolicate/v5",
+        r#"{
+                "memoryMap": [
+                  { "codeId": "fc50208b4c95f695e212dfcf8b7f810f56514918" }
+                ],
+                "stacks": [
+                  [
+                    [0, 4409]
+                  ]
+                ]
+              }"#,
+        fixtures_dir().join("other").join("split-debug").join("out"),
+    ));
+    let output: serde_json::Value =
+        serde_json::from_str(&serde_json::to_string(&output).unwrap()).unwrap();
+    let frame = &output["results"][0]["stacks"][0][0];
+    assert_eq!(frame["function"], "internal_function");
+    assert_eq!(frame["line"], 4);
+}
+
+#[test]
+fn address_beyond_4_gib() {
+    // far_function is at the relative address 0x1ffc01000.
+    let output = futures::executor::block_on(crate::query_api(
+        "/symbolicate/v5",
+        r#"{
+                "memoryMap": [
+                  ["main", "", "fa00dbf89758acd80c3f11cb21864b760c1798c3"]
+                ],
+                "stacks": [
+                  [
+                    [0, 8585744386]
+                  ]
+                ]
+              }"#,
+        fixtures_dir()
+            .join("other")
+            .join("large-module")
+            .join("out"),
+    ));
+    let output: serde_json::Value =
+        serde_json::from_str(&serde_json::to_string(&output).unwrap()).unwrap();
+    let frame = &output["results"][0]["stacks"][0][0];
+    assert_eq!(frame["module_offset"], "0x1ffc01002");
+    assert_eq!(frame["function"], "far_function");
+    assert_eq!(frame["function_offset"], "0x2");
+}
+
 #[test]
 fn stripped_macos() {
     // The address 232505 (0x38c39) is inside the __stub_helper section.
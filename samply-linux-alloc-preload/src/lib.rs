@@ -0,0 +1,536 @@
+//! Allocation sampling for `samply record --alloc` on Linux.
+//!
+//! This library is loaded into the launched command with `LD_PRELOAD`. It
+//! overrides `malloc`, `calloc`, `realloc` and `free` and forwards them to the
+//! next definition of these functions, which is usually the one in libc.
+//!
+//! Whenever a thread has allocated `SAMPLY_ALLOC_SAMPLE_INTERVAL` bytes, the
+//! allocation which crossed the threshold is sampled: its stack is captured,
+//! and a record is appended to `$SAMPLY_ALLOC_OUTPUT_DIR/alloc-<pid>.bin`. The
+//! record is weighted with all the bytes that the thread allocated since its
+//! previous sample, so that the weights add up to the total number of allocated
+//! bytes. When a sampled allocation is freed, a record with the negated weight
+//! is written.
+//!
+//! Each record consists of native-endian 64 bit values:
+//!
+//! - the CLOCK_MONOTONIC timestamp in nanoseconds
+//! - the weight in bytes, negative for frees
+//! - the address of the allocation
+//! - the number of stack frames which follow (zero for frees)
+//! - the return addresses, starting with the caller of the allocation function
+//!
+//! Nothing in here may use the Rust heap, because the Rust heap is backed by
+//! malloc.
+
+use std::cell::{Cell, UnsafeCell};
+use std::ffi::{c_int, c_void, CStr};
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+type MallocFn = unsafe extern "C" fn(usize) -> *mut c_void;
+type CallocFn = unsafe extern "C" fn(usize, usize) -> *mut c_void;
+type ReallocFn = unsafe extern "C" fn(*mut c_void, usize) -> *mut c_void;
+type FreeFn = unsafe extern "C" fn(*mut c_void);
+
+const DEFAULT_SAMPLE_INTERVAL: u64 = 128 * 1024;
+const MAX_FRAMES: usize = 128;
+/// The frames for `write_record` and for the overridden allocation function.
+const FRAMES_TO_SKIP: usize = 2;
+const OUTPUT_DIR_CAPACITY: usize = 4096;
+const BOOTSTRAP_BUFFER_SIZE: usize = 16 * 1024;
+const SAMPLED_ALLOCATIONS_CAPACITY: usize = 1 << 16;
+
+static REAL_MALLOC: AtomicUsize = AtomicUsize::new(0);
+static REAL_CALLOC: AtomicUsize = AtomicUsize::new(0);
+static REAL_REALLOC: AtomicUsize = AtomicUsize::new(0);
+static REAL_FREE: AtomicUsize = AtomicUsize::new(0);
+static IS_RESOLVING: AtomicBool = AtomicBool::new(false);
+
+/// Zero until the settings have been read from the environment, and zero if
+/// allocation sampling is disabled.
+static SAMPLE_INTERVAL: AtomicU64 = AtomicU64::new(0);
+static OUTPUT_DIR: StaticBuffer<OUTPUT_DIR_CAPACITY> = StaticBuffer::new();
+static OUTPUT_DIR_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// `(pid << 32) | fd` for the output file of the current process, or zero if
+/// no file has been opened yet. After a fork, the pid doesn't match anymore
+/// and the child opens its own file.
+static OUTPUT_FILE: AtomicU64 = AtomicU64::new(0);
+static OUTPUT_FILE_LOCK: SpinLock = SpinLock::new();
+
+/// Memory which is handed out while the real allocation functions are being
+/// looked up, because dlsym can call calloc. It is never reused, so it stays
+/// zeroed until it is handed out.
+static BOOTSTRAP_BUFFER: StaticBuffer<BOOTSTRAP_BUFFER_SIZE> = StaticBuffer::new();
+static BOOTSTRAP_BUFFER_USED: AtomicUsize = AtomicUsize::new(0);
+
+static SAMPLED_ALLOCATIONS: SampledAllocations = SampledAllocations::new();
+
+thread_local! {
+    /// Set while this thread is running our own code, so that the allocations
+    /// made by dlsym or backtrace are passed through without being sampled.
+    static IS_IN_HOOK: Cell<bool> = const { Cell::new(false) };
+    /// The number of bytes which this thread allocated since its last sample.
+    static BYTES_SINCE_LAST_SAMPLE: Cell<u64> = const { Cell::new(0) };
+}
+
+#[used]
+#[link_section = ".init_array"]
+static INIT: extern "C" fn() = init;
+
+extern "C" fn init() {
+    let Some(_guard) = HookGuard::enter() else {
+        return;
+    };
+    resolve_real_functions();
+
+    // Safety: Constructors run before the program's main function, so nobody
+    // is calling setenv on another thread.
+    let (output_dir, interval) = unsafe {
+        let output_dir = libc::getenv(c"SAMPLY_ALLOC_OUTPUT_DIR".as_ptr());
+        if output_dir.is_null() {
+            return;
+        }
+        let interval = libc::getenv(c"SAMPLY_ALLOC_SAMPLE_INTERVAL".as_ptr());
+        let interval = if interval.is_null() {
+            None
+        } else {
+            parse_u64(CStr::from_ptr(interval).to_bytes())
+        };
+        (CStr::from_ptr(output_dir).to_bytes(), interval)
+    };
+    if output_dir.is_empty() || output_dir.len() > OUTPUT_DIR_CAPACITY {
+        return;
+    }
+    // Safety: Nothing reads the output dir before SAMPLE_INTERVAL is set.
+    unsafe { (&mut *OUTPUT_DIR.0.get())[..output_dir.len()].copy_from_slice(output_dir) };
+    OUTPUT_DIR_LEN.store(output_dir.len(), Ordering::Relaxed);
+
+    // The first call to backtrace loads libgcc_s, which allocates. Get that out
+    // of the way while we're still single-threaded.
+    let mut frame = [null_mut(); 1];
+    unsafe { libc::backtrace(frame.as_mut_ptr(), 1) };
+
+    let interval = interval.unwrap_or(DEFAULT_SAMPLE_INTERVAL).max(1);
+    SAMPLE_INTERVAL.store(interval, Ordering::Release);
+}
+
+/// # Safety
+///
+/// Same contract as the libc function of the same name.
+#[no_mangle]
+pub unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+    let Some(real_malloc) = real_function::<MallocFn>(&REAL_MALLOC) else {
+        return bootstrap_alloc(size);
+    };
+    let ptr = real_malloc(size);
+    on_alloc(ptr, size);
+    ptr
+}
+
+/// # Safety
+///
+/// Same contract as the libc function of the same name.
+#[no_mangle]
+pub unsafe extern "C" fn calloc(count: usize, size: usize) -> *mut c_void {
+    let Some(real_calloc) = real_function::<CallocFn>(&REAL_CALLOC) else {
+        return match count.checked_mul(size) {
+            Some(total_size) => bootstrap_alloc(total_size),
+            None => null_mut(),
+        };
+    };
+    let ptr = real_calloc(count, size);
+    on_alloc(ptr, count.saturating_mul(size));
+    ptr
+}
+
+/// # Safety
+///
+/// Same contract as the libc function of the same name.
+#[no_mangle]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+    if is_bootstrap_pointer(ptr) {
+        // We don't know the old size, so copy as much as there could have been.
+        let new_ptr = malloc(size);
+        if !new_ptr.is_null() {
+            let available =
+                BOOTSTRAP_BUFFER.0.get() as usize + BOOTSTRAP_BUFFER_SIZE - ptr as usize;
+            std::ptr::copy_nonoverlapping(
+                ptr as *const u8,
+                new_ptr as *mut u8,
+                size.min(available),
+            );
+        }
+        return new_ptr;
+    }
+    let Some(real_realloc) = real_function::<ReallocFn>(&REAL_REALLOC) else {
+        return bootstrap_alloc(size);
+    };
+    // A realloc is treated as a free of the old allocation followed by a new
+    // allocation. The free has to be recorded before the old address can be
+    // handed out again.
+    if !ptr.is_null() {
+        on_free(ptr);
+    }
+    let new_ptr = real_realloc(ptr, size);
+    on_alloc(new_ptr, size);
+    new_ptr
+}
+
+/// # Safety
+///
+/// Same contract as the libc function of the same name.
+#[no_mangle]
+pub unsafe extern "C" fn free(ptr: *mut c_void) {
+    if ptr.is_null() || is_bootstrap_pointer(ptr) {
+        return;
+    }
+    on_free(ptr);
+    if let Some(real_free) = real_function::<FreeFn>(&REAL_FREE) {
+        real_free(ptr);
+    }
+}
+
+#[inline(always)]
+fn on_alloc(ptr: *mut c_void, size: usize) {
+    let interval = SAMPLE_INTERVAL.load(Ordering::Relaxed);
+    if interval == 0 || ptr.is_null() {
+        return;
+    }
+    let weight = BYTES_SINCE_LAST_SAMPLE.with(|bytes| {
+        let new_bytes = bytes.get().saturating_add(size as u64);
+        if new_bytes < interval {
+            bytes.set(new_bytes);
+            None
+        } else {
+            bytes.set(0);
+            Some(new_bytes)
+        }
+    });
+    let Some(weight) = weight else {
+        return;
+    };
+    let Some(_guard) = HookGuard::enter() else {
+        return;
+    };
+    if SAMPLED_ALLOCATIONS.insert(ptr as u64, weight) {
+        write_record(ptr as u64, weight as i64, true);
+    }
+}
+
+#[inline(always)]
+fn on_free(ptr: *mut c_void) {
+    if SAMPLED_ALLOCATIONS.is_empty() {
+        return;
+    }
+    let Some(_guard) = HookGuard::enter() else {
+        return;
+    };
+    if let Some(weight) = SAMPLED_ALLOCATIONS.remove(ptr as u64) {
+        write_record(ptr as u64, -(weight as i64), false);
+    }
+}
+
+#[inline(never)]
+fn write_record(address: u64, weight: i64, with_stack: bool) {
+    let Some(fd) = output_fd() else {
+        return;
+    };
+    let mut record = [0u64; 4 + MAX_FRAMES + FRAMES_TO_SKIP];
+    let frame_count = if with_stack {
+        let frames = &mut record[4..];
+        let count = unsafe {
+            libc::backtrace(
+                frames.as_mut_ptr() as *mut *mut c_void,
+                frames.len() as c_int,
+            )
+        };
+        let count = count.max(0) as usize;
+        let skipped = count.min(FRAMES_TO_SKIP);
+        frames.copy_within(skipped..count, 0);
+        count - skipped
+    } else {
+        0
+    };
+    record[0] = monotonic_nanos();
+    record[1] = weight as u64;
+    record[2] = address;
+    record[3] = frame_count as u64;
+    // The file is opened with O_APPEND, so records from different threads
+    // don't interleave.
+    let len = (4 + frame_count) * 8;
+    unsafe { libc::write(fd, record.as_ptr() as *const c_void, len) };
+}
+
+fn output_fd() -> Option<c_int> {
+    let pid = unsafe { libc::getpid() } as u32;
+    let current = OUTPUT_FILE.load(Ordering::Acquire);
+    if current != 0 && (current >> 32) as u32 == pid {
+        return Some(current as u32 as c_int);
+    }
+
+    let _lock = OUTPUT_FILE_LOCK.lock();
+    let current = OUTPUT_FILE.load(Ordering::Acquire);
+    if current != 0 {
+        if (current >> 32) as u32 == pid {
+            return Some(current as u32 as c_int);
+        }
+        // We've been forked. Close the parent's file.
+        unsafe { libc::close(current as u32 as c_int) };
+    }
+
+    let dir_len = OUTPUT_DIR_LEN.load(Ordering::Relaxed);
+    let mut path = [0u8; OUTPUT_DIR_CAPACITY + 32];
+    // Safety: The output dir is only written before SAMPLE_INTERVAL is set.
+    path[..dir_len].copy_from_slice(unsafe { &(&*OUTPUT_DIR.0.get())[..dir_len] });
+    let mut len = dir_len;
+    for part in [&b"/alloc-"[..], format_u32(pid, &mut [0; 10]), b".bin\0"] {
+        path[len..][..part.len()].copy_from_slice(part);
+        len += part.len();
+    }
+    let fd = unsafe {
+        libc::open(
+            path.as_ptr() as *const libc::c_char,
+            libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND | libc::O_CLOEXEC,
+            0o600,
+        )
+    };
+    if fd < 0 {
+        // Don't try again for every sample.
+        SAMPLE_INTERVAL.store(0, Ordering::Relaxed);
+        return None;
+    }
+    OUTPUT_FILE.store(((pid as u64) << 32) | fd as u32 as u64, Ordering::Release);
+    Some(fd)
+}
+
+/// Looks up the allocation functions which we're overriding. Returns false if
+/// this is a nested call from inside dlsym.
+fn resolve_real_functions() -> bool {
+    if REAL_FREE.load(Ordering::Acquire) != 0 {
+        return true;
+    }
+    if IS_RESOLVING.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+    unsafe {
+        let lookup = |name: &CStr| libc::dlsym(libc::RTLD_NEXT, name.as_ptr()) as usize;
+        REAL_MALLOC.store(lookup(c"malloc"), Ordering::Relaxed);
+        REAL_CALLOC.store(lookup(c"calloc"), Ordering::Relaxed);
+        REAL_REALLOC.store(lookup(c"realloc"), Ordering::Relaxed);
+        REAL_FREE.store(lookup(c"free"), Ordering::Release);
+    }
+    IS_RESOLVING.store(false, Ordering::Release);
+    REAL_FREE.load(Ordering::Acquire) != 0
+}
+
+/// `F` must be the function pointer type which matches the stored function.
+unsafe fn real_function<F: Copy>(function: &AtomicUsize) -> Option<F> {
+    if !resolve_real_functions() {
+        return None;
+    }
+    let address = function.load(Ordering::Relaxed);
+    if address == 0 {
+        return None;
+    }
+    Some(std::mem::transmute_copy::<usize, F>(&address))
+}
+
+fn bootstrap_alloc(size: usize) -> *mut c_void {
+    let size = size.checked_add(15).map_or(usize::MAX, |size| size & !15);
+    let offset = BOOTSTRAP_BUFFER_USED.fetch_add(size, Ordering::Relaxed);
+    if offset.saturating_add(size) > BOOTSTRAP_BUFFER_SIZE {
+        return null_mut();
+    }
+    unsafe { (BOOTSTRAP_BUFFER.0.get() as *mut u8).add(offset) as *mut c_void }
+}
+
+fn is_bootstrap_pointer(ptr: *mut c_void) -> bool {
+    let start = BOOTSTRAP_BUFFER.0.get() as usize;
+    (start..start + BOOTSTRAP_BUFFER_SIZE).contains(&(ptr as usize))
+}
+
+fn monotonic_nanos() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+fn parse_u64(s: &[u8]) -> Option<u64> {
+    if s.is_empty() {
+        return None;
+    }
+    s.iter().try_fold(0u64, |value, &c| {
+        if !c.is_ascii_digit() {
+            return None;
+        }
+        value.checked_mul(10)?.checked_add((c - b'0') as u64)
+    })
+}
+
+fn format_u32(mut value: u32, buf: &mut [u8; 10]) -> &[u8] {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    &buf[start..]
+}
+
+struct HookGuard;
+
+impl HookGuard {
+    fn enter() -> Option<Self> {
+        IS_IN_HOOK.with(|is_in_hook| {
+            if is_in_hook.get() {
+                None
+            } else {
+                is_in_hook.set(true);
+                Some(HookGuard)
+            }
+        })
+    }
+}
+
+impl Drop for HookGuard {
+    fn drop(&mut self) {
+        IS_IN_HOOK.with(|is_in_hook| is_in_hook.set(false));
+    }
+}
+
+#[repr(C, align(16))]
+struct StaticBuffer<const N: usize>(UnsafeCell<[u8; N]>);
+
+unsafe impl<const N: usize> Sync for StaticBuffer<N> {}
+
+impl<const N: usize> StaticBuffer<N> {
+    const fn new() -> Self {
+        Self(UnsafeCell::new([0; N]))
+    }
+}
+
+struct SpinLock(AtomicBool);
+
+struct SpinLockGuard<'a>(&'a SpinLock);
+
+impl SpinLock {
+    const fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    fn lock(&self) -> SpinLockGuard<'_> {
+        while self
+            .0
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::hint::spin_loop();
+        }
+        SpinLockGuard(self)
+    }
+}
+
+impl Drop for SpinLockGuard<'_> {
+    fn drop(&mut self) {
+        self.0 .0.store(false, Ordering::Release);
+    }
+}
+
+/// The addresses and weights of the sampled allocations which haven't been
+/// freed yet, in a fixed-size hash table with linear probing. Zero marks an
+/// empty slot.
+struct SampledAllocations {
+    lock: SpinLock,
+    len: AtomicUsize,
+    entries: UnsafeCell<[(u64, u64); SAMPLED_ALLOCATIONS_CAPACITY]>,
+}
+
+unsafe impl Sync for SampledAllocations {}
+
+impl SampledAllocations {
+    const fn new() -> Self {
+        Self {
+            lock: SpinLock::new(),
+            len: AtomicUsize::new(0),
+            entries: UnsafeCell::new([(0, 0); SAMPLED_ALLOCATIONS_CAPACITY]),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len.load(Ordering::Relaxed) == 0
+    }
+
+    fn home_slot(address: u64) -> usize {
+        ((address >> 4).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 48) as usize
+            & (SAMPLED_ALLOCATIONS_CAPACITY - 1)
+    }
+
+    /// Returns false if the table is too full, in which case the allocation
+    /// shouldn't be sampled.
+    fn insert(&self, address: u64, weight: u64) -> bool {
+        let _lock = self.lock.lock();
+        let len = self.len.load(Ordering::Relaxed);
+        if len >= SAMPLED_ALLOCATIONS_CAPACITY * 3 / 4 {
+            return false;
+        }
+        // Safety: We hold the lock.
+        let entries = unsafe { &mut *self.entries.get() };
+        let mut slot = Self::home_slot(address);
+        while entries[slot].0 != 0 && entries[slot].0 != address {
+            slot = (slot + 1) & (SAMPLED_ALLOCATIONS_CAPACITY - 1);
+        }
+        if entries[slot].0 == 0 {
+            self.len.store(len + 1, Ordering::Relaxed);
+        }
+        entries[slot] = (address, weight);
+        true
+    }
+
+    fn remove(&self, address: u64) -> Option<u64> {
+        let _lock = self.lock.lock();
+        // Safety: We hold the lock.
+        let entries = unsafe { &mut *self.entries.get() };
+        let mask = SAMPLED_ALLOCATIONS_CAPACITY - 1;
+        let mut slot = Self::home_slot(address);
+        while entries[slot].0 != address {
+            if entries[slot].0 == 0 {
+                return None;
+            }
+            slot = (slot + 1) & mask;
+        }
+        let weight = entries[slot].1;
+
+        // Shift the following entries of the probe sequence back into the
+        // hole, so that lookups don't stop early.
+        let mut hole = slot;
+        let mut next = slot;
+        loop {
+            next = (next + 1) & mask;
+            let (next_address, _) = entries[next];
+            if next_address == 0 {
+                break;
+            }
+            let home = Self::home_slot(next_address);
+            let stays = if hole <= next {
+                hole < home && home <= next
+            } else {
+                hole < home || home <= next
+            };
+            if !stays {
+                entries[hole] = entries[next];
+                hole = next;
+            }
+        }
+        entries[hole] = (0, 0);
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        Some(weight)
+    }
+}
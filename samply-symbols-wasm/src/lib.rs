@@ -0,0 +1,289 @@
+//! WebAssembly bindings for [`samply-symbols`](https://docs.rs/samply-symbols)
+//! and [`samply-api`](https://docs.rs/samply-api), for the Firefox profiler and
+//! other JavaScript consumers.
+//!
+//! The bindings don't access files themselves. The caller passes an object
+//! which implements the `FileAndPathHelper` interface from the TypeScript
+//! definitions: it lists candidate paths for a library and reads files.
+//!
+//! ```js
+//! import init, { queryAPI, getCompactSymbolTable } from "samply-symbols-wasm";
+//!
+//! await init();
+//! const helper = {
+//!   getCandidatePathsForDebugFile: (info) => [`/symbols/${info.debugName}`],
+//!   getCandidatePathsForBinary: (info) => [],
+//!   readFile: async (path) => new Uint8Array(await (await fetch(path)).arrayBuffer()),
+//! };
+//! const response = JSON.parse(await queryAPI("/symbolicate/v5", requestJson, helper));
+//! const [addrs, index, buffer] = await getCompactSymbolTable("xul.pdb", breakpadId, helper);
+//! ```
+
+use std::fmt::Display;
+use std::path::Path;
+
+use js_sys::{Array, Promise, Uint32Array, Uint8Array};
+use samply_api::samply_symbols::debugid::DebugId;
+use samply_api::samply_symbols::{
+    self, CandidatePathInfo, CompactSymbolTable, FileAndPathHelper, FileAndPathHelperError,
+    FileAndPathHelperResult, FileLocation, LibraryInfo, OptionallySendFuture, SymbolManager,
+};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_TYPES: &str = r#"
+/** Information about a library, as passed to the FileAndPathHelper methods. */
+export interface LibraryInfo {
+  debugName?: string;
+  breakpadId?: string;
+  debugPath?: string;
+  name?: string;
+  codeId?: string;
+  path?: string;
+  arch?: string;
+}
+
+/**
+ * Gives the bindings access to files. Paths are only passed back to readFile,
+ * so they can be any strings the helper understands, for example URLs.
+ */
+export interface FileAndPathHelper {
+  /** The paths which may have symbol information for the library, in order of preference. */
+  getCandidatePathsForDebugFile(info: LibraryInfo): string[];
+  /** The paths at which the library's binary may be found, in order of preference. */
+  getCandidatePathsForBinary(info: LibraryInfo): string[];
+  /** Reads the entire file. Rejects if the file can't be read. */
+  readFile(path: string): Promise<Uint8Array | ArrayBuffer>;
+}
+
+/**
+ * The sorted symbol addresses, the start of each symbol's name in the buffer
+ * (plus one extra entry for the end of the buffer), and the UTF-8 names.
+ */
+export type CompactSymbolTable = [Uint32Array, Uint32Array, Uint8Array];
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "FileAndPathHelper")]
+    pub type JsFileAndPathHelper;
+
+    #[wasm_bindgen(catch, method, js_name = getCandidatePathsForDebugFile)]
+    fn get_candidate_paths_for_debug_file(
+        this: &JsFileAndPathHelper,
+        info: &JsValue,
+    ) -> Result<Array, JsValue>;
+
+    #[wasm_bindgen(catch, method, js_name = getCandidatePathsForBinary)]
+    fn get_candidate_paths_for_binary(
+        this: &JsFileAndPathHelper,
+        info: &JsValue,
+    ) -> Result<Array, JsValue>;
+
+    #[wasm_bindgen(catch, method, js_name = readFile)]
+    fn read_file(this: &JsFileAndPathHelper, path: &str) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(typescript_type = "Promise<string>")]
+    pub type StringPromise;
+
+    #[wasm_bindgen(typescript_type = "Promise<CompactSymbolTable>")]
+    pub type CompactSymbolTablePromise;
+}
+
+/// Runs a query against the symbolication JSON API, e.g. "/symbolicate/v5",
+/// and resolves to the JSON response. See `samply_api::Api::query_api` for
+/// the supported URLs.
+#[wasm_bindgen(js_name = queryAPI)]
+pub fn query_api(
+    request_url: String,
+    request_json: String,
+    helper: JsFileAndPathHelper,
+) -> StringPromise {
+    future_to_promise(async move {
+        let symbol_manager = SymbolManager::with_helper(Helper(helper));
+        let api = samply_api::Api::new(&symbol_manager);
+        let response = api.query_api(&request_url, &request_json).await;
+        let response_json =
+            serde_json::to_string(&response).map_err(|e| js_error("JsonError", e))?;
+        Ok(response_json.into())
+    })
+    .unchecked_into()
+}
+
+/// Loads the symbol table of the library with the given debug name and
+/// breakpad ID, e.g. "xul.pdb" and "B1E5C4B3A9E14A4C9CE5FFA7F5D1E2F31".
+///
+/// Rejects with an Error whose name is the `samply_symbols::Error` variant,
+/// e.g. "NoCandidatePathForDebugFile", if the symbols can't be loaded.
+#[wasm_bindgen(js_name = getCompactSymbolTable)]
+pub fn get_compact_symbol_table(
+    debug_name: String,
+    breakpad_id: String,
+    helper: JsFileAndPathHelper,
+) -> CompactSymbolTablePromise {
+    future_to_promise(async move {
+        let debug_id = DebugId::from_breakpad(&breakpad_id).map_err(|_| {
+            let error = samply_symbols::Error::InvalidBreakpadId(breakpad_id.clone());
+            js_error(error.enum_as_string(), error)
+        })?;
+        let info = LibraryInfo {
+            debug_name: Some(debug_name),
+            debug_id: Some(debug_id),
+            ..Default::default()
+        };
+        let symbol_manager = SymbolManager::with_helper(Helper(helper));
+        let symbol_map = symbol_manager
+            .load_symbol_map(&info)
+            .await
+            .map_err(|e| js_error(e.enum_as_string(), e))?;
+        let table = CompactSymbolTable::from_symbol_map(&symbol_map);
+        let tuple = Array::of3(
+            &Uint32Array::from(&table.addr[..]),
+            &Uint32Array::from(&table.index[..]),
+            &Uint8Array::from(&table.buffer[..]),
+        );
+        Ok(tuple.into())
+    })
+    .unchecked_into()
+}
+
+fn js_error(name: &str, message: impl Display) -> JsValue {
+    let error = js_sys::Error::new(&message.to_string());
+    error.set_name(name);
+    error.into()
+}
+
+struct Helper(JsFileAndPathHelper);
+
+impl FileAndPathHelper for Helper {
+    type F = Vec<u8>;
+    type FL = WasmFileLocation;
+
+    fn get_candidate_paths_for_debug_file(
+        &self,
+        info: &LibraryInfo,
+    ) -> FileAndPathHelperResult<Vec<CandidatePathInfo<WasmFileLocation>>> {
+        let paths = self
+            .0
+            .get_candidate_paths_for_debug_file(&library_info_to_js(info))
+            .map_err(helper_error)?;
+        Ok(candidate_paths(paths))
+    }
+
+    fn get_candidate_paths_for_binary(
+        &self,
+        info: &LibraryInfo,
+    ) -> FileAndPathHelperResult<Vec<CandidatePathInfo<WasmFileLocation>>> {
+        let paths = self
+            .0
+            .get_candidate_paths_for_binary(&library_info_to_js(info))
+            .map_err(helper_error)?;
+        Ok(candidate_paths(paths))
+    }
+
+    fn get_dyld_shared_cache_paths(
+        &self,
+        _arch: Option<&str>,
+    ) -> FileAndPathHelperResult<Vec<WasmFileLocation>> {
+        Ok(Vec::new())
+    }
+
+    fn load_file(
+        &self,
+        location: WasmFileLocation,
+    ) -> std::pin::Pin<Box<dyn OptionallySendFuture<Output = FileAndPathHelperResult<Vec<u8>>> + '_>>
+    {
+        Box::pin(async move {
+            // FileContents must be Send and Sync, which JS objects aren't, so
+            // the whole file is copied into wasm memory.
+            let promise = self.0.read_file(&location.0).map_err(helper_error)?;
+            let contents = JsFuture::from(promise).await.map_err(helper_error)?;
+            Ok(Uint8Array::new(&contents).to_vec())
+        })
+    }
+}
+
+fn library_info_to_js(info: &LibraryInfo) -> JsValue {
+    let object = js_sys::Object::new();
+    let fields = [
+        ("debugName", info.debug_name.clone()),
+        (
+            "breakpadId",
+            info.debug_id.map(|id| id.breakpad().to_string()),
+        ),
+        ("debugPath", info.debug_path.clone()),
+        ("name", info.name.clone()),
+        ("codeId", info.code_id.as_ref().map(ToString::to_string)),
+        ("path", info.path.clone()),
+        ("arch", info.arch.clone()),
+    ];
+    for (key, value) in fields {
+        if let Some(value) = value {
+            let _ = js_sys::Reflect::set(&object, &key.into(), &value.into());
+        }
+    }
+    object.into()
+}
+
+fn candidate_paths(paths: Array) -> Vec<CandidatePathInfo<WasmFileLocation>> {
+    paths
+        .iter()
+        .filter_map(|path| path.as_string())
+        .map(|path| CandidatePathInfo::SingleFile(WasmFileLocation(path)))
+        .collect()
+}
+
+fn helper_error(value: JsValue) -> FileAndPathHelperError {
+    let message = match value.dyn_ref::<js_sys::Error>() {
+        Some(error) => String::from(error.message()),
+        None => value.as_string().unwrap_or_else(|| format!("{value:?}")),
+    };
+    message.into()
+}
+
+/// A path or URL string, as understood by the JS helper.
+#[derive(Clone, Debug)]
+struct WasmFileLocation(String);
+
+impl Display for WasmFileLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FileLocation for WasmFileLocation {
+    fn location_for_dyld_subcache(&self, suffix: &str) -> Option<Self> {
+        Some(Self(format!("{}{suffix}", self.0)))
+    }
+
+    fn location_for_external_object_file(&self, object_file: &str) -> Option<Self> {
+        Some(Self(object_file.to_owned()))
+    }
+
+    fn location_for_pdb_from_binary(&self, pdb_path_in_binary: &str) -> Option<Self> {
+        Some(Self(pdb_path_in_binary.to_owned()))
+    }
+
+    fn location_for_source_file(&self, source_file_path: &str) -> Option<Self> {
+        Some(Self(source_file_path.to_owned()))
+    }
+
+    fn location_for_breakpad_symindex(&self) -> Option<Self> {
+        let path = Path::new(&self.0).with_extension("symindex");
+        Some(Self(path.to_str()?.to_owned()))
+    }
+
+    fn location_for_dwo(&self, comp_dir: &str, path: &str) -> Option<Self> {
+        if path.starts_with('/') {
+            return Some(Self(path.to_owned()));
+        }
+        let comp_dir = comp_dir.trim_end_matches('/');
+        Some(Self(format!("{comp_dir}/{path}")))
+    }
+
+    fn location_for_dwp(&self) -> Option<Self> {
+        Some(Self(format!("{}.dwp", self.0)))
+    }
+}
@@ -0,0 +1,80 @@
+use object::{ObjectSection, SectionFlags, SectionKind};
+
+/// What kind of memory a relative address points into, based on the sections
+/// of the binary.
+///
+/// Profilers can use this to discard bogus frames, for example data pointers
+/// which were picked up from the stack, before paying for a symbol lookup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AddressKind {
+    /// The address is in a section with executable code.
+    Text,
+    /// The address is in a section with data, for example `.data`, `.rodata`
+    /// or `.bss`.
+    Data,
+    /// The address is outside of all loaded sections.
+    Unknown,
+}
+
+/// The SVMA ranges of the loaded sections of a binary, with their kind.
+#[derive(Debug, Clone, Default)]
+pub struct SectionKindRanges {
+    ranges: Vec<(u64, u64, AddressKind)>,
+}
+
+impl SectionKindRanges {
+    pub fn from_object<'data, O: object::Object<'data>>(object: &O) -> Self {
+        let mut ranges: Vec<_> = object
+            .sections()
+            .filter_map(|section| {
+                let kind = address_kind_for_section(&section)?;
+                let start = section.address();
+                let end = start.checked_add(section.size())?;
+                (start < end).then_some((start, end, kind))
+            })
+            .collect();
+        ranges.sort_unstable();
+        Self { ranges }
+    }
+
+    pub fn classify_svma(&self, svma: u64) -> AddressKind {
+        // Sections don't overlap, so the section containing svma is the
+        // last one starting at or before it.
+        let index = self.ranges.partition_point(|(start, _, _)| *start <= svma);
+        match index.checked_sub(1).map(|i| self.ranges[i]) {
+            Some((_start, end, kind)) if svma < end => kind,
+            _ => AddressKind::Unknown,
+        }
+    }
+}
+
+fn address_kind_for_section<'data>(section: &impl ObjectSection<'data>) -> Option<AddressKind> {
+    let kind = match (section.kind(), section.flags()) {
+        (SectionKind::Text, _) => AddressKind::Text,
+
+        // Sections in debug files which correspond to executable sections in the
+        // original binary.
+        (SectionKind::UninitializedData, SectionFlags::Elf { sh_flags })
+            if sh_flags & u64::from(object::elf::SHF_EXECINSTR) != 0 =>
+        {
+            AddressKind::Text
+        }
+
+        (
+            SectionKind::Data
+            | SectionKind::ReadOnlyData
+            | SectionKind::ReadOnlyDataWithRel
+            | SectionKind::ReadOnlyString
+            | SectionKind::UninitializedData
+            | SectionKind::Common
+            | SectionKind::Tls
+            | SectionKind::UninitializedTls
+            | SectionKind::TlsVariables,
+            _,
+        ) => AddressKind::Data,
+
+        // Debug info, notes, metadata etc. are not part of the loaded image.
+        _ => return None,
+    };
+    Some(kind)
+}
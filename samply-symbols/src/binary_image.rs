@@ -6,12 +6,14 @@ use object::{FileKind, Object, ReadRef};
 use samply_debugid::{CodeId, ElfBuildId, PeCodeId};
 use samply_object::{code_id_for_object, debug_id_for_object, relative_address_base};
 
+use crate::address_kind::{AddressKind, SectionKindRanges};
 use crate::error::Error;
 use crate::jitdump::{debug_id_and_code_id_for_jitdump, JitDumpIndex};
 use crate::macho::{DyldCacheFileData, MachOData, MachOFatArchiveMemberData};
 use crate::shared::{
     FileAndPathHelperError, FileContents, FileContentsWrapper, LibraryInfo, RangeReadRef,
 };
+use crate::windows;
 
 #[derive(thiserror::Error, Debug)]
 pub enum CodeByteReadingError {
@@ -67,6 +69,12 @@ impl<F: FileContents + 'static> BinaryImage<F> {
         self.info.code_id.clone()
     }
 
+    /// The GNU build ID which MinGW / GCC and Rust toolchains can embed in
+    /// PE binaries. For ELF binaries, the build ID is the [`code_id`](Self::code_id).
+    pub fn gnu_build_id(&self) -> Option<&ElfBuildId> {
+        self.info.gnu_build_id.as_ref()
+    }
+
     pub fn path(&self) -> Option<&str> {
         self.info.path.as_deref()
     }
@@ -91,6 +99,26 @@ impl<F: FileContents + 'static> BinaryImage<F> {
         self.inner
             .read_bytes_at_relative_address(start_address, size)
     }
+
+    /// Says whether the relative address is in a code section, a data section,
+    /// or outside of all sections.
+    pub fn classify_relative_address(&self, address: u32) -> AddressKind {
+        match &self.inner {
+            BinaryImageInner::JitDump(_data, index) => {
+                match index.lookup_relative_address(address) {
+                    Some(_) => AddressKind::Text,
+                    None => AddressKind::Unknown,
+                }
+            }
+            _ => {
+                let Some(object) = self.make_object() else {
+                    return AddressKind::Unknown;
+                };
+                let svma = relative_address_base(&object) + u64::from(address);
+                SectionKindRanges::from_object(&object).classify_svma(svma)
+            }
+        }
+    }
 }
 
 pub enum BinaryImageInner<F: FileContents + 'static> {
@@ -106,6 +134,7 @@ impl<F: FileContents> BinaryImageInner<F> {
         name: Option<String>,
         path: Option<String>,
     ) -> Result<LibraryInfo, Error> {
+        let mut gnu_build_id = None;
         let (debug_id, code_id, debug_path, debug_name, arch) = match self {
             BinaryImageInner::Normal(file, file_kind) => {
                 let data = file.full_range();
@@ -114,14 +143,26 @@ impl<F: FileContents> BinaryImageInner<F> {
                 let debug_id = debug_id_for_object(&object);
                 match file_kind {
                     FileKind::Pe32 | FileKind::Pe64 => {
-                        let (code_id, debug_path, debug_name) =
-                            if let Ok(pe) = PeFile64::parse(file) {
-                                pe_info(&pe).into_tuple()
-                            } else if let Ok(pe) = PeFile32::parse(file) {
-                                pe_info(&pe).into_tuple()
-                            } else {
-                                (None, None, None)
-                            };
+                        let pe_info = if let Ok(pe) = PeFile64::parse(file) {
+                            Some(pe_info(&pe))
+                        } else if let Ok(pe) = PeFile32::parse(file) {
+                            Some(pe_info(&pe))
+                        } else {
+                            None
+                        };
+                        let (code_id, mut debug_path, mut debug_name) = match pe_info {
+                            Some(pe_info) => {
+                                gnu_build_id = pe_info.gnu_build_id;
+                                (Some(pe_info.code_id), pe_info.pdb_path, pe_info.pdb_name)
+                            }
+                            None => (None, None, None),
+                        };
+                        if debug_name.is_none()
+                            && windows::pe_has_dwarf_debug_info(file, *file_kind)
+                        {
+                            // There is no PDB; the debug info is in the binary itself.
+                            (debug_path, debug_name) = (path.clone(), name.clone());
+                        }
                         let arch =
                             object_arch_to_string(object.architecture()).map(ToOwned::to_owned);
                         (debug_id, code_id, debug_path, debug_name, arch)
@@ -188,6 +229,7 @@ impl<F: FileContents> BinaryImageInner<F> {
             code_id,
             path,
             arch,
+            gnu_build_id,
         };
         Ok(info)
     }
@@ -298,12 +340,7 @@ struct PeInfo {
     code_id: CodeId,
     pdb_path: Option<String>,
     pdb_name: Option<String>,
-}
-
-impl PeInfo {
-    pub fn into_tuple(self) -> (Option<CodeId>, Option<String>, Option<String>) {
-        (Some(self.code_id), self.pdb_path, self.pdb_name)
-    }
+    gnu_build_id: Option<ElfBuildId>,
 }
 
 fn pe_info<'a, Pe: ImageNtHeaders, R: ReadRef<'a>>(pe: &PeFile<'a, Pe, R>) -> PeInfo {
@@ -323,7 +360,9 @@ fn pe_info<'a, Pe: ImageNtHeaders, R: ReadRef<'a>>(pe: &PeFile<'a, Pe, R>) -> Pe
 
     let pdb_path: Option<String> = pe.pdb_info().ok().and_then(|pdb_info| {
         let pdb_path = std::str::from_utf8(pdb_info?.path()).ok()?;
-        Some(pdb_path.to_string())
+        // GNU ld writes a CodeView record with an empty path when it is
+        // asked for a build ID.
+        (!pdb_path.is_empty()).then(|| pdb_path.to_string())
     });
 
     let pdb_name = pdb_path
@@ -333,13 +372,74 @@ fn pe_info<'a, Pe: ImageNtHeaders, R: ReadRef<'a>>(pe: &PeFile<'a, Pe, R>) -> Pe
             None => pdb_path.to_string(),
         });
 
+    let gnu_build_id = pe_gnu_build_id(pe).map(ElfBuildId);
+
     PeInfo {
         code_id,
         pdb_path,
         pdb_name,
+        gnu_build_id,
     }
 }
 
+/// Finds the GNU build ID of a PE binary.
+///
+/// The build ID is either stored in a `.note.gnu.build-id` section, in the
+/// same format as in ELF files, or, if the binary was linked by GNU ld or lld
+/// with `--build-id`, as the signature of a CodeView record without a PDB path.
+fn pe_gnu_build_id<'a, Pe: ImageNtHeaders, R: ReadRef<'a>>(
+    pe: &PeFile<'a, Pe, R>,
+) -> Option<Vec<u8>> {
+    use object::{Object, ObjectSection};
+    if let Some(note) = pe
+        .section_by_name(".note.gnu.build-id")
+        .and_then(|section| section.data().ok())
+        .and_then(parse_gnu_build_id_note)
+    {
+        return Some(note.to_vec());
+    }
+    let pdb_info = pe.pdb_info().ok()??;
+    if !pdb_info.path().is_empty() {
+        return None;
+    }
+    // The GUID fields are stored in little endian. The build ID uses the
+    // big-endian GUID byte order, matching what binutils reports.
+    let guid = pdb_info.guid();
+    let mut build_id = guid.to_vec();
+    build_id[0..4].reverse();
+    build_id[4..6].reverse();
+    build_id[6..8].reverse();
+    Some(build_id)
+}
+
+/// Returns the descriptor of the `NT_GNU_BUILD_ID` note in `data`, which
+/// contains a sequence of little-endian ELF notes.
+fn parse_gnu_build_id_note(mut data: &[u8]) -> Option<&[u8]> {
+    const NT_GNU_BUILD_ID: u32 = 3;
+    fn align4(size: usize) -> Option<usize> {
+        size.checked_add(3).map(|size| size & !3)
+    }
+    fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+        let bytes = data.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    }
+    while data.len() >= 12 {
+        let name_size = read_u32(data, 0)? as usize;
+        let desc_size = read_u32(data, 4)? as usize;
+        let note_type = read_u32(data, 8)?;
+        let name_end = 12usize.checked_add(name_size)?;
+        let desc_start = align4(name_end)?;
+        let desc_end = desc_start.checked_add(desc_size)?;
+        let name = data.get(12..name_end)?;
+        let desc = data.get(desc_start..desc_end)?;
+        if note_type == NT_GNU_BUILD_ID && name == b"GNU\0" {
+            return Some(desc);
+        }
+        data = data.get(align4(desc_end)?.min(data.len())..)?;
+    }
+    None
+}
+
 fn object_arch_to_string(arch: object::Architecture) -> Option<&'static str> {
     let s = match arch {
         object::Architecture::Arm => "arm",
@@ -361,3 +461,26 @@ fn elf_machine_arch_to_string(elf_machine_arch: u32) -> Option<&'static str> {
     };
     Some(s)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_gnu_build_id_notes() {
+        let mut data = vec![];
+        // Some other note first, with a name which needs padding.
+        data.extend_from_slice(&[5, 0, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0]);
+        data.extend_from_slice(b"Go\0\0\0\0\0\0");
+        data.extend_from_slice(&[1, 2, 3, 4]);
+        data.extend_from_slice(&[4, 0, 0, 0, 3, 0, 0, 0, 3, 0, 0, 0]);
+        data.extend_from_slice(b"GNU\0");
+        data.extend_from_slice(&[0xab, 0xcd, 0xef]);
+        assert_eq!(
+            parse_gnu_build_id_note(&data),
+            Some(&[0xab, 0xcd, 0xef][..])
+        );
+        assert_eq!(parse_gnu_build_id_note(&data[..data.len() - 1]), None);
+        assert_eq!(parse_gnu_build_id_note(&data[..20]), None);
+    }
+}
@@ -598,6 +598,11 @@ impl BreakpadIndexCreator {
         line_buffer.consume(chunk, |offset, line| inner.process_line(offset, line));
     }
 
+    /// The number of FUNC and PUBLIC records in the chunks consumed so far.
+    pub fn symbol_count(&self) -> usize {
+        self.inner.symbols.len()
+    }
+
     pub fn finish(mut self) -> Result<OwnedBreakpadIndex, BreakpadParseError> {
         let inner = &mut self.inner;
         let final_offset = self
@@ -856,6 +861,9 @@ pub enum BreakpadParseError {
 
     #[error("The Breakpad sym file did not start with a valid MODULE line")]
     NoModuleInfoInSymFile,
+
+    #[error("The records in the Breakpad sym file are not in the expected order")]
+    UnexpectedRecordOrder,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -998,7 +1006,7 @@ fn non_space(input: &[u8]) -> IResult<&[u8], &[u8]> {
 }
 
 // Matches a MODULE record.
-fn module_line(input: &[u8]) -> IResult<&[u8], (&str, &str, DebugId, &str)> {
+pub fn module_line(input: &[u8]) -> IResult<&[u8], (&str, &str, DebugId, &str)> {
     let (input, _) = terminated(tag("MODULE"), space1)(input)?;
     let (input, (os, cpu, debug_id, name)) = cut(tuple((
         terminated(map_res(non_space, str::from_utf8), space1), // os
@@ -1023,7 +1031,7 @@ fn info_code_id_line(input: &[u8]) -> IResult<&[u8], (&str, Option<&str>)> {
 }
 
 // Matches a FILE record.
-fn file_line(input: &[u8]) -> Result<(u32, &[u8]), ()> {
+pub fn file_line(input: &[u8]) -> Result<(u32, &[u8]), ()> {
     let mut t = Tokenizer::new(input);
     t.consume_token(b"FILE")?;
     t.consume_space1()?;
@@ -1034,7 +1042,7 @@ fn file_line(input: &[u8]) -> Result<(u32, &[u8]), ()> {
 }
 
 // Matches an INLINE_ORIGIN record.
-fn inline_origin_line(input: &[u8]) -> Result<(u32, &[u8]), ()> {
+pub fn inline_origin_line(input: &[u8]) -> Result<(u32, &[u8]), ()> {
     let mut t = Tokenizer::new(input);
     t.consume_token(b"INLINE_ORIGIN")?;
     t.consume_space1()?;
@@ -1045,7 +1053,7 @@ fn inline_origin_line(input: &[u8]) -> Result<(u32, &[u8]), ()> {
 }
 
 // Matches a PUBLIC record.
-fn public_line(input: &[u8]) -> IResult<&[u8], (u32, &[u8])> {
+pub fn public_line(input: &[u8]) -> IResult<&[u8], (u32, &[u8])> {
     let (input, _) = terminated(tag("PUBLIC"), space1)(input)?;
     let (input, (_multiple, address, _parameter_size, name)) = cut(tuple((
         opt(terminated(tag("m"), space1)),
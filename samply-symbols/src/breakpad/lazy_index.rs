@@ -0,0 +1,642 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::str;
+use std::sync::{Mutex, OnceLock};
+
+use debugid::DebugId;
+use memchr::memchr;
+
+use super::index::{
+    file_line, func_line, inline_origin_line, module_line, public_line, BreakpadParseError,
+    BreakpadSymbolEntry, StringLocation, Tokenizer, SYMBOL_ENTRY_KIND_FUNC,
+    SYMBOL_ENTRY_KIND_PUBLIC,
+};
+use crate::{Error, FileContents, FileContentsWrapper};
+
+/// The number of bytes which are read at once when looking for the end of a line.
+const LINE_READ_CHUNK_SIZE: u64 = 4096;
+
+/// Once a bisection has narrowed its range down to this many bytes, the
+/// remaining records are read one by one, and their order is checked.
+const LINEAR_SCAN_SIZE: u64 = 4096;
+
+/// The parts of a Breakpad sym file's structure which are needed to look up
+/// records in a [`BreakpadLazyIndex`].
+///
+/// Computing the layout only reads the lines around a few dozen offsets in
+/// the file: It finds the section boundaries by bisecting the file, relying
+/// on the record order in files produced by `dump_syms`: MODULE, INFO, FILE
+/// and INLINE_ORIGIN records with ascending indexes, then the FUNC and PUBLIC
+/// records, then STACK records.
+///
+/// Nothing checks this order up front. Instead, each bisection checks that
+/// the records it reads are in order, see [`RecordReader::find_last_record`].
+#[derive(Debug, Clone)]
+pub struct BreakpadLazyIndexLayout {
+    pub debug_id: DebugId,
+    /// The offset of the first FUNC or PUBLIC record, or of the first record
+    /// after the FILE and INLINE_ORIGIN records if there are no symbols.
+    symbols_start: u64,
+    /// The byte ranges of the FUNC and PUBLIC records. The records in each
+    /// range are sorted by address.
+    ///
+    /// There is either a single range, if all symbol records are sorted by
+    /// address (like in the output of Mozilla's `dump_syms`), or two ranges
+    /// if all FUNC records are followed by all PUBLIC records, each sorted
+    /// separately (like in the output of Breakpad's `dump_syms`).
+    symbol_ranges: Vec<Range<u64>>,
+}
+
+impl BreakpadLazyIndexLayout {
+    /// Returns `None` if the bisection for the section boundaries finds
+    /// records in an unexpected order. Such files need the full index.
+    pub fn new<T: FileContents>(data: &FileContentsWrapper<T>) -> Result<Option<Self>, Error> {
+        match Self::find(data) {
+            Err(Error::BreakpadParsing(BreakpadParseError::UnexpectedRecordOrder)) => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    fn find<T: FileContents>(data: &FileContentsWrapper<T>) -> Result<Self, Error> {
+        let reader = RecordReader { data };
+        let len = data.len();
+        let rank = |record: &Record| Some(record.kind.rank());
+
+        // Every file must start with a "MODULE " line.
+        let (first_line, _) = reader.read_line(0)?;
+        let (_rest, (_os, _arch, debug_id, _debug_name)) =
+            module_line(first_line).map_err(|_| BreakpadParseError::NoModuleInfoInSymFile)?;
+
+        // Find the first record after the file header.
+        let last_header_record = reader
+            .find_last_record(0..len, rank, &RecordKind::InlineOrigin.rank())?
+            .ok_or(BreakpadParseError::NoModuleInfoInSymFile)?;
+        let first_symbol = match reader.next_record(last_header_record.offset + 1, len)? {
+            Some(record) if record.kind.rank() == RecordKind::Func.rank() => record,
+            Some(record) => {
+                return Ok(Self {
+                    debug_id,
+                    symbols_start: record.offset,
+                    symbol_ranges: Vec::new(),
+                })
+            }
+            None => {
+                return Ok(Self {
+                    debug_id,
+                    symbols_start: len,
+                    symbol_ranges: Vec::new(),
+                })
+            }
+        };
+
+        // Find the end of the symbols, i.e. the first STACK record or the end of the file.
+        let symbols_start = first_symbol.offset;
+        let last_symbol = reader
+            .find_last_record(symbols_start..len, rank, &RecordKind::Func.rank())?
+            .unwrap_or(first_symbol);
+        let symbols_end = match reader.next_record(last_symbol.offset + 1, len)? {
+            Some(record) => record.offset,
+            None => len,
+        };
+
+        // Check whether the PUBLIC records come after all FUNC records, with
+        // addresses starting over from the beginning. If FUNC and PUBLIC
+        // records are interleaved, the bisection for the last FUNC record
+        // fails, and all symbols are in one range.
+        let mut public_start = None;
+        if first_symbol.kind == RecordKind::Func {
+            let last_func = match reader.find_last_record(
+                symbols_start..symbols_end,
+                |record| Some(record.kind != RecordKind::Func),
+                &false,
+            ) {
+                Err(Error::BreakpadParsing(BreakpadParseError::UnexpectedRecordOrder)) => None,
+                result => result?,
+            };
+            if let Some(last_func) = last_func {
+                if let Some(public) = reader.next_record(last_func.offset + 1, symbols_end)? {
+                    if let (RecordKind::Public, Some(func_address), Some(public_address)) = (
+                        public.kind,
+                        last_func.symbol_address(),
+                        public.symbol_address(),
+                    ) {
+                        if public_address < func_address {
+                            public_start = Some(public.offset);
+                        }
+                    }
+                }
+            }
+        }
+        let mut symbol_ranges = Vec::new();
+        match public_start {
+            Some(public_start) => {
+                symbol_ranges.push(symbols_start..public_start);
+                symbol_ranges.push(public_start..symbols_end);
+            }
+            None => symbol_ranges.push(symbols_start..symbols_end),
+        }
+
+        Ok(Self {
+            debug_id,
+            symbols_start,
+            symbol_ranges,
+        })
+    }
+}
+
+/// A symbol found by [`BreakpadLazyIndex::lookup`].
+#[derive(Debug, Clone)]
+pub struct BreakpadLazySymbol {
+    /// The index of this symbol among the symbols that have been found so far.
+    pub index: u32,
+    pub address: u32,
+    pub next_symbol_address: Option<u32>,
+    pub entry: BreakpadSymbolEntry,
+}
+
+/// An index for Breakpad sym files which finds records on demand, by
+/// bisecting the file, rather than by indexing the entire file up front.
+///
+/// This makes the first lookup in very large sym files fast, and keeps the
+/// memory usage proportional to the number of looked-up symbols.
+///
+/// The lookups return [`BreakpadParseError::UnexpectedRecordOrder`] if they
+/// find records which are not in the order that bisecting relies on. The
+/// caller then needs to use the full index instead.
+pub struct BreakpadLazyIndex<'a, T: FileContents> {
+    reader: RecordReader<'a, T>,
+    layout: &'a BreakpadLazyIndexLayout,
+    state: Mutex<BreakpadLazyIndexState>,
+    symbol_count: OnceLock<Option<usize>>,
+}
+
+#[derive(Debug, Default)]
+struct BreakpadLazyIndexState {
+    /// The symbols that have been found so far, in the order in which they were found.
+    symbols: Vec<BreakpadLazySymbol>,
+    /// Maps the address of each symbol in `symbols` to its index.
+    symbol_indexes_by_address: BTreeMap<u32, u32>,
+    files: HashMap<u32, StringLocation>,
+    inline_origins: HashMap<u32, StringLocation>,
+}
+
+impl<'a, T: FileContents> BreakpadLazyIndex<'a, T> {
+    pub fn new(data: &'a FileContentsWrapper<T>, layout: &'a BreakpadLazyIndexLayout) -> Self {
+        Self {
+            reader: RecordReader { data },
+            layout,
+            state: Mutex::new(BreakpadLazyIndexState::default()),
+            symbol_count: OnceLock::new(),
+        }
+    }
+
+    pub fn debug_id(&self) -> DebugId {
+        self.layout.debug_id
+    }
+
+    /// Returns the symbol with the largest address that is less than or
+    /// equal to `address`.
+    pub fn lookup(&self, address: u32) -> Result<Option<BreakpadLazySymbol>, Error> {
+        {
+            let state = self.state.lock().unwrap();
+            if let Some((_, &index)) = state.symbol_indexes_by_address.range(..=address).last() {
+                let symbol = &state.symbols[index as usize];
+                if symbol
+                    .next_symbol_address
+                    .map_or(true, |next| address < next)
+                {
+                    return Ok(Some(symbol.clone()));
+                }
+            }
+        }
+
+        let mut best: Option<(Record<'a>, u64)> = None;
+        let mut next_symbol_address: Option<u32> = None;
+        for range in &self.layout.symbol_ranges {
+            let found =
+                self.reader
+                    .find_last_record(range.clone(), Record::symbol_address, &address)?;
+            let next_search_start = match &found {
+                Some(record) => record.offset + 1,
+                None => range.start,
+            };
+            let next = self.reader.next_record(next_search_start, range.end)?;
+            if let Some(next_address) = next.as_ref().and_then(Record::symbol_address) {
+                next_symbol_address = Some(match next_symbol_address {
+                    Some(a) => a.min(next_address),
+                    None => next_address,
+                });
+            }
+            if let Some(found) = found {
+                // On ties, prefer the FUNC record, which comes first.
+                let is_better = match &best {
+                    Some((best, _)) => found.symbol_address() > best.symbol_address(),
+                    None => true,
+                };
+                if is_better {
+                    let end = next.map_or(range.end, |next| next.offset);
+                    best = Some((found, end));
+                }
+            }
+        }
+        let Some((record, end)) = best else {
+            return Ok(None);
+        };
+        let Some(symbol_address) = record.symbol_address() else {
+            return Ok(None);
+        };
+        let entry = record.symbol_entry(end);
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(&index) = state.symbol_indexes_by_address.get(&symbol_address) {
+            return Ok(Some(state.symbols[index as usize].clone()));
+        }
+        let symbol = BreakpadLazySymbol {
+            index: state.symbols.len() as u32,
+            address: symbol_address,
+            next_symbol_address,
+            entry,
+        };
+        state
+            .symbol_indexes_by_address
+            .insert(symbol_address, symbol.index);
+        state.symbols.push(symbol.clone());
+        Ok(Some(symbol))
+    }
+
+    /// Returns the addresses and entries of all symbols, sorted by address.
+    ///
+    /// The records are read from the file during the iteration, and nothing
+    /// is kept. Like in the full index, there is only one symbol per address;
+    /// FUNC records take precedence over PUBLIC records.
+    pub fn iter_symbols(&self) -> impl Iterator<Item = (u32, BreakpadSymbolEntry)> + '_ {
+        let mut ranges: Vec<_> = self
+            .layout
+            .symbol_ranges
+            .iter()
+            .map(|range| SymbolRecords::new(&self.reader, range.clone()).peekable())
+            .collect();
+        let mut last_address = None;
+        std::iter::from_fn(move || loop {
+            // Take the symbol with the lowest address. On ties, take the one
+            // from the first range, which has the FUNC records.
+            let (range_index, _) = ranges
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(i, range)| Some((i, range.peek()?.0)))
+                .min_by_key(|&(i, address)| (address, i))?;
+            let (address, entry) = ranges[range_index].next()?;
+            if last_address != Some(address) {
+                last_address = Some(address);
+                return Some((address, entry));
+            }
+        })
+    }
+
+    /// The number of symbols, see [`iter_symbols`](Self::iter_symbols). The
+    /// first call reads all symbol records and checks that they are sorted
+    /// by address, which `iter_symbols` relies on.
+    pub fn symbol_count(&self) -> Result<usize, Error> {
+        self.symbol_count
+            .get_or_init(|| {
+                self.check_symbol_order().ok()?;
+                Some(self.iter_symbols().count())
+            })
+            .ok_or(BreakpadParseError::UnexpectedRecordOrder.into())
+    }
+
+    fn check_symbol_order(&self) -> Result<(), Error> {
+        for range in &self.layout.symbol_ranges {
+            let mut last_address = None;
+            let mut offset = range.start;
+            while let Some(record) = self.reader.next_record(offset, range.end)? {
+                let address = record.symbol_address();
+                if address.is_none() || address < last_address {
+                    return Err(BreakpadParseError::UnexpectedRecordOrder.into());
+                }
+                last_address = address;
+                offset = record.offset + 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the entry of a symbol that was returned from [`lookup`](Self::lookup).
+    pub fn symbol_entry(&self, index: u32) -> Option<BreakpadSymbolEntry> {
+        let state = self.state.lock().unwrap();
+        let symbol = state.symbols.get(index as usize)?;
+        Some(symbol.entry.clone())
+    }
+
+    pub fn file(&self, index: u32) -> Result<Option<&'a str>, Error> {
+        let location = self.state.lock().unwrap().files.get(&index).copied();
+        let s = match location {
+            Some(location) => location.get(self.reader.data),
+            None => {
+                let record = self.reader.find_last_record(
+                    0..self.layout.symbols_start,
+                    |record| match record.kind {
+                        RecordKind::Header => Some((0, 0)),
+                        RecordKind::File => file_line(record.line).ok().map(|(i, _)| (1, i)),
+                        RecordKind::InlineOrigin => Some((2, 0)),
+                        _ => None,
+                    },
+                    &(1, index),
+                )?;
+                let Some(record) = record else {
+                    return Ok(None);
+                };
+                let Ok((i, s)) = file_line(record.line) else {
+                    return Ok(None);
+                };
+                if i != index {
+                    return Ok(None);
+                }
+                if let Ok(location) = StringLocation::from_refs(record.offset, record.line, s) {
+                    self.state.lock().unwrap().files.insert(index, location);
+                }
+                Some(s)
+            }
+        };
+        Ok(s.and_then(|s| str::from_utf8(s).ok()))
+    }
+
+    pub fn inline_origin(&self, index: u32) -> Result<Option<&'a str>, Error> {
+        let location = self
+            .state
+            .lock()
+            .unwrap()
+            .inline_origins
+            .get(&index)
+            .copied();
+        let s = match location {
+            Some(location) => location.get(self.reader.data),
+            None => {
+                let record = self.reader.find_last_record(
+                    0..self.layout.symbols_start,
+                    |record| match record.kind {
+                        RecordKind::Header => Some((0, 0)),
+                        RecordKind::File => Some((1, 0)),
+                        RecordKind::InlineOrigin => {
+                            inline_origin_line(record.line).ok().map(|(i, _)| (2, i))
+                        }
+                        _ => None,
+                    },
+                    &(2, index),
+                )?;
+                let Some(record) = record else {
+                    return Ok(None);
+                };
+                let Ok((i, s)) = inline_origin_line(record.line) else {
+                    return Ok(None);
+                };
+                if i != index {
+                    return Ok(None);
+                }
+                if let Ok(location) = StringLocation::from_refs(record.offset, record.line, s) {
+                    self.state
+                        .lock()
+                        .unwrap()
+                        .inline_origins
+                        .insert(index, location);
+                }
+                Some(s)
+            }
+        };
+        Ok(s.and_then(|s| str::from_utf8(s).ok()))
+    }
+}
+
+/// The top-level record types, in the order in which they appear in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecordKind {
+    /// MODULE and INFO records
+    Header,
+    File,
+    InlineOrigin,
+    Func,
+    Public,
+    Stack,
+}
+
+impl RecordKind {
+    /// Returns `None` for lines which are not top-level records, such as
+    /// the line records and INLINE records which follow a FUNC record.
+    fn of_line(line: &[u8]) -> Option<Self> {
+        let keyword = &line[..memchr(b' ', line)?];
+        match keyword {
+            b"MODULE" | b"INFO" => Some(Self::Header),
+            b"FILE" => Some(Self::File),
+            b"INLINE_ORIGIN" => Some(Self::InlineOrigin),
+            b"FUNC" => Some(Self::Func),
+            b"PUBLIC" => Some(Self::Public),
+            b"STACK" => Some(Self::Stack),
+            _ => None,
+        }
+    }
+
+    /// The position of this kind in the record order. FUNC and PUBLIC
+    /// records can be interleaved.
+    fn rank(self) -> u8 {
+        match self {
+            Self::Header => 0,
+            Self::File => 1,
+            Self::InlineOrigin => 2,
+            Self::Func | Self::Public => 3,
+            Self::Stack => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Record<'a> {
+    offset: u64,
+    /// The line, excluding the line break.
+    line: &'a [u8],
+    kind: RecordKind,
+}
+
+impl<'a> Record<'a> {
+    /// The entry for a FUNC or PUBLIC record. `end` is the offset of the next
+    /// top-level record, which is where the block of a FUNC record ends.
+    fn symbol_entry(&self, end: u64) -> BreakpadSymbolEntry {
+        match self.kind {
+            RecordKind::Func => BreakpadSymbolEntry {
+                kind: SYMBOL_ENTRY_KIND_FUNC.into(),
+                offset: self.offset.into(),
+                line_or_block_len: ((end - self.offset) as u32).into(),
+            },
+            _ => BreakpadSymbolEntry {
+                kind: SYMBOL_ENTRY_KIND_PUBLIC.into(),
+                offset: self.offset.into(),
+                line_or_block_len: (self.line.len() as u32).into(),
+            },
+        }
+    }
+
+    fn symbol_address(&self) -> Option<u32> {
+        match self.kind {
+            RecordKind::Func => {
+                let (address, _size, _name) = func_line(&mut Tokenizer::new(self.line)).ok()?;
+                Some(address)
+            }
+            RecordKind::Public => {
+                let (_rest, (address, _name)) = public_line(self.line).ok()?;
+                Some(address)
+            }
+            _ => None,
+        }
+    }
+}
+
+struct RecordReader<'a, T: FileContents> {
+    data: &'a FileContentsWrapper<T>,
+}
+
+/// The FUNC and PUBLIC records in a symbol range, with their addresses.
+struct SymbolRecords<'r, 'a, T: FileContents> {
+    reader: &'r RecordReader<'a, T>,
+    next: Option<Record<'a>>,
+    end: u64,
+}
+
+impl<'r, 'a, T: FileContents> SymbolRecords<'r, 'a, T> {
+    fn new(reader: &'r RecordReader<'a, T>, range: Range<u64>) -> Self {
+        let next = reader.next_record(range.start, range.end).ok().flatten();
+        Self {
+            reader,
+            next,
+            end: range.end,
+        }
+    }
+}
+
+impl<T: FileContents> Iterator for SymbolRecords<'_, '_, T> {
+    type Item = (u32, BreakpadSymbolEntry);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.next.take()?;
+        // Stop at read errors, like the lookups do.
+        self.next = self.reader.next_record(record.offset + 1, self.end).ok()?;
+        let end = self.next.map_or(self.end, |next| next.offset);
+        Some((record.symbol_address()?, record.symbol_entry(end)))
+    }
+}
+
+impl<'a, T: FileContents> RecordReader<'a, T> {
+    /// Returns the line starting at `offset`, excluding trailing `\r*\n`,
+    /// and the offset of the next line.
+    fn read_line(&self, offset: u64) -> Result<(&'a [u8], u64), Error> {
+        let remaining_len = self.data.len() - offset;
+        let mut len = LINE_READ_CHUNK_SIZE.min(remaining_len);
+        loop {
+            let bytes = self.data.read_bytes_at(offset, len).map_err(|e| {
+                Error::HelperErrorDuringFileReading("Breakpad sym file".to_string(), e)
+            })?;
+            let (mut line, next_line_offset) = match memchr(b'\n', bytes) {
+                Some(line_break) => (&bytes[..line_break], offset + line_break as u64 + 1),
+                None if len == remaining_len => (bytes, offset + len),
+                None => {
+                    len = (len * 2).min(remaining_len);
+                    continue;
+                }
+            };
+            while line.last() == Some(&b'\r') {
+                line = &line[..(line.len() - 1)];
+            }
+            return Ok((line, next_line_offset));
+        }
+    }
+
+    /// Returns the first top-level record which starts at or after `offset`
+    /// and before `end`.
+    fn next_record(&self, offset: u64, end: u64) -> Result<Option<Record<'a>>, Error> {
+        let mut line_start = if offset == 0 {
+            0
+        } else {
+            // Skip to the start of the next line, unless `offset` is already at a line start.
+            self.read_line(offset - 1)?.1
+        };
+        while line_start < end {
+            let (line, next_line_start) = self.read_line(line_start)?;
+            if let Some(kind) = RecordKind::of_line(line) {
+                return Ok(Some(Record {
+                    offset: line_start,
+                    line,
+                    kind,
+                }));
+            }
+            line_start = next_line_start;
+        }
+        Ok(None)
+    }
+
+    /// Returns the last top-level record in `range` whose key is at or before
+    /// `target`. The keys of the records in the range must be in ascending
+    /// order.
+    ///
+    /// This order is checked for the records which are read: each record
+    /// which the bisection looks at, and all records in the final
+    /// [`LINEAR_SCAN_SIZE`] bytes. Records without a key, or records which
+    /// are out of order, make this return
+    /// [`BreakpadParseError::UnexpectedRecordOrder`].
+    fn find_last_record<K: Ord + Copy>(
+        &self,
+        range: Range<u64>,
+        key: impl Fn(&Record<'a>) -> Option<K>,
+        target: &K,
+    ) -> Result<Option<Record<'a>>, Error> {
+        let key = |record: &Record<'a>| {
+            key(record).ok_or(Error::from(BreakpadParseError::UnexpectedRecordOrder))
+        };
+        let mut found = None;
+        // The keys of the closest records which were read before and after
+        // the part of the range which is left to search.
+        let mut lower_key = None;
+        let mut upper_key = None;
+        // Invariant: All records which start before `start` match, and no
+        // record which starts at or after `end` matches.
+        let mut start = range.start;
+        let mut end = range.end;
+        while end - start > LINEAR_SCAN_SIZE {
+            let mid = start + (end - start) / 2;
+            let Some(record) = self.next_record(mid, end)? else {
+                end = mid;
+                continue;
+            };
+            let record_key = key(&record)?;
+            check_order(lower_key, record_key, upper_key)?;
+            if record_key <= *target {
+                start = record.offset + 1;
+                found = Some(record);
+                lower_key = Some(record_key);
+            } else {
+                end = mid;
+                upper_key = Some(record_key);
+            }
+        }
+
+        // Read the remaining records one by one.
+        let mut offset = start;
+        while let Some(record) = self.next_record(offset, end)? {
+            let record_key = key(&record)?;
+            check_order(lower_key, record_key, upper_key)?;
+            if record_key <= *target {
+                found = Some(record);
+            }
+            lower_key = Some(record_key);
+            offset = record.offset + 1;
+        }
+        Ok(found)
+    }
+}
+
+/// Checks that `key` is between the keys of the records around it.
+fn check_order<K: Ord>(lower_key: Option<K>, key: K, upper_key: Option<K>) -> Result<(), Error> {
+    let is_ordered = lower_key.map_or(true, |lower_key| lower_key <= key)
+        && upper_key.map_or(true, |upper_key| key <= upper_key);
+    if !is_ordered {
+        return Err(BreakpadParseError::UnexpectedRecordOrder.into());
+    }
+    Ok(())
+}
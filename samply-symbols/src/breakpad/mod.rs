@@ -1,4 +1,5 @@
 mod index;
+mod lazy_index;
 mod symbol_map;
 
 pub use index::{
@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Mutex, OnceLock};
 
 use yoke::Yoke;
 use yoke_derive::Yokeable;
@@ -13,31 +13,45 @@ use crate::breakpad::index::{
     func_line, BreakpadSymbolEntry, Inlinee, OwnedBreakpadIndex, SourceLine, StringLocation,
     Tokenizer,
 };
+use crate::breakpad::lazy_index::{BreakpadLazyIndex, BreakpadLazyIndexLayout};
 use crate::generation::SymbolMapGeneration;
+use crate::limits::ParseLimits;
 use crate::shared::{FunctionNameIndex, SymbolNameIndex};
 use crate::source_file_path::SourceFilePathHandle;
 use crate::symbol_map::{GetInnerSymbolMap, SymbolMapTrait};
 use crate::{
     AccessPatternHint, BreakpadParseError, Error, FileContents, FileContentsWrapper,
     FrameDebugInfo, FramesLookupResult, FunctionNameHandle, LookupAddress, SourceFilePath,
-    SymbolInfo, SymbolNameHandle, SyncAddressInfo,
+    SymbolInfo, SymbolKind, SymbolNameHandle, SyncAddressInfo,
 };
 
+/// Sym files of at least this size which don't come with a symindex file are
+/// not indexed up front. Instead, records are found on demand by bisecting the
+/// file, see [`BreakpadLazyIndex`]. Once the lazy index finds records which are
+/// not in the order that this needs, the full index is used instead.
+const LAZY_INDEX_MIN_FILE_SIZE: u64 = 64 * 1024 * 1024; // 64MB
+
 pub fn get_symbol_map_for_breakpad_sym<FC: FileContents + 'static>(
     file_contents: FileContentsWrapper<FC>,
     index_file_contents: Option<FileContentsWrapper<FC>>,
+    limits: ParseLimits,
 ) -> Result<BreakpadSymbolMap<FC>, Error> {
-    let outer = BreakpadSymbolMapOuter::new(file_contents, index_file_contents)?;
-    let symbol_map = BreakpadSymbolMap(Yoke::attach_to_cart(Box::new(outer), |outer| {
-        outer.make_symbol_map()
-    }));
-    Ok(symbol_map)
+    let outer = BreakpadSymbolMapOuter::new(file_contents, index_file_contents, limits)?;
+    Ok(BreakpadSymbolMap::from_outer(outer))
 }
 
 pub struct BreakpadSymbolMap<T: FileContents + 'static>(
     Yoke<BreakpadSymbolMapInnerWrapper<'static>, Box<BreakpadSymbolMapOuter<T>>>,
 );
 
+impl<T: FileContents> BreakpadSymbolMap<T> {
+    fn from_outer(outer: BreakpadSymbolMapOuter<T>) -> Self {
+        Self(Yoke::attach_to_cart(Box::new(outer), |outer| {
+            outer.make_symbol_map()
+        }))
+    }
+}
+
 impl<T: FileContents> GetInnerSymbolMap for BreakpadSymbolMap<T> {
     fn get_inner_symbol_map<'a>(&'a self) -> &'a (dyn SymbolMapTrait + 'a) {
         self.0.get().0.as_ref()
@@ -47,61 +61,71 @@ impl<T: FileContents> GetInnerSymbolMap for BreakpadSymbolMap<T> {
 enum IndexStorage<T: FileContents> {
     File(FileContentsWrapper<T>),
     Owned(Box<OwnedBreakpadIndex>),
+    Lazy(BreakpadLazyIndexLayout),
 }
 
 pub struct BreakpadSymbolMapOuter<T: FileContents> {
     data: FileContentsWrapper<T>,
     index: IndexStorage<T>,
+    limits: ParseLimits,
 }
 
 impl<T: FileContents> BreakpadSymbolMapOuter<T> {
     pub fn new(
         data: FileContentsWrapper<T>,
         index_data: Option<FileContentsWrapper<T>>,
+        limits: ParseLimits,
+    ) -> Result<Self, Error> {
+        Self::with_lazy_index_min_file_size(data, index_data, limits, LAZY_INDEX_MIN_FILE_SIZE)
+    }
+
+    fn with_lazy_index_min_file_size(
+        data: FileContentsWrapper<T>,
+        index_data: Option<FileContentsWrapper<T>>,
+        limits: ParseLimits,
+        lazy_index_min_file_size: u64,
     ) -> Result<Self, Error> {
-        let index = Self::make_index_storage(&data, index_data)?;
-        Ok(Self { data, index })
+        let index = Self::make_index_storage(&data, index_data, &limits, lazy_index_min_file_size)?;
+        Ok(Self {
+            data,
+            index,
+            limits,
+        })
     }
 
     fn make_index_storage(
         data: &FileContentsWrapper<T>,
         index_data: Option<FileContentsWrapper<T>>,
+        limits: &ParseLimits,
+        lazy_index_min_file_size: u64,
     ) -> Result<IndexStorage<T>, Error> {
         if let Some(index_data) = index_data {
-            if BreakpadIndex::parse_symindex_file(&index_data).is_ok() {
+            if let Ok(index) = BreakpadIndex::parse_symindex_file(&index_data) {
+                limits.check_symbol_count(index.symbol_addresses.len())?;
                 return Ok(IndexStorage::File(index_data));
             }
         }
-        const CHUNK_SIZE: u64 = 1024 * 1024; // 1MB
-        let mut buffer = Vec::with_capacity(CHUNK_SIZE as usize);
-        let mut index_parser = BreakpadIndexCreator::new();
-
-        // Read the entire thing in chunks to build an index.
-        let len = data.len();
-        let mut offset = 0;
-        while offset < len {
-            let chunk_len = CHUNK_SIZE.min(len - offset);
-            data.read_bytes_into(&mut buffer, offset, chunk_len as usize)
-                .map_err(|e| {
-                    Error::HelperErrorDuringFileReading(
-                        "BreakpadBreakpadSymbolMapData".to_string(),
-                        e,
-                    )
-                })?;
-            index_parser.consume(&buffer);
-            buffer.clear();
-            offset += CHUNK_SIZE;
+        if data.len() >= lazy_index_min_file_size {
+            if let Some(layout) = BreakpadLazyIndexLayout::new(data)? {
+                return Ok(IndexStorage::Lazy(layout));
+            }
         }
-        let index = index_parser.finish()?;
+        let index = create_index(data, limits)?;
         Ok(IndexStorage::Owned(Box::new(index)))
     }
 
     pub fn make_symbol_map(&self) -> BreakpadSymbolMapInnerWrapper<'_> {
         let index = match &self.index {
             IndexStorage::File(index_data) => {
-                BreakpadIndex::parse_symindex_file(index_data).unwrap()
+                SymbolMapIndex::Full(BreakpadIndex::parse_symindex_file(index_data).unwrap())
             }
-            IndexStorage::Owned(owned_index) => owned_index.index(),
+            IndexStorage::Owned(owned_index) => SymbolMapIndex::Full(owned_index.index()),
+            IndexStorage::Lazy(layout) => SymbolMapIndex::Lazy {
+                index: BreakpadLazyIndex::new(&self.data, layout),
+                full_index: OnceLock::new(),
+                data: &self.data,
+                limits: self.limits,
+            },
         };
         let cache = Mutex::new(BreakpadSymbolMapCache::new());
         let inner_impl = BreakpadSymbolMapInner {
@@ -114,16 +138,70 @@ impl<T: FileContents> BreakpadSymbolMapOuter<T> {
     }
 }
 
+/// Builds an index of the entire file, by parsing it in chunks.
+///
+/// Fails as soon as a chunk brings the number of symbols over the limit.
+fn create_index<T: FileContents>(
+    data: &FileContentsWrapper<T>,
+    limits: &ParseLimits,
+) -> Result<OwnedBreakpadIndex, Error> {
+    const CHUNK_SIZE: u64 = 1024 * 1024; // 1MB
+    let mut buffer = Vec::with_capacity(CHUNK_SIZE as usize);
+    let mut index_parser = BreakpadIndexCreator::new();
+
+    // Read the entire thing in chunks to build an index.
+    let len = data.len();
+    let mut offset = 0;
+    while offset < len {
+        let chunk_len = CHUNK_SIZE.min(len - offset);
+        data.read_bytes_into(&mut buffer, offset, chunk_len as usize)
+            .map_err(|e| {
+                Error::HelperErrorDuringFileReading("BreakpadBreakpadSymbolMapData".to_string(), e)
+            })?;
+        index_parser.consume(&buffer);
+        limits.check_symbol_count(index_parser.symbol_count())?;
+        buffer.clear();
+        offset += CHUNK_SIZE;
+    }
+    let index = index_parser.finish()?;
+    limits.check_symbol_count(index.index().symbol_addresses.len())?;
+    Ok(index)
+}
+
 #[derive(Yokeable)]
 pub struct BreakpadSymbolMapInnerWrapper<'a>(Box<dyn SymbolMapTrait + Send + Sync + 'a>);
 
 struct BreakpadSymbolMapInner<'a, T: FileContents> {
     data: &'a FileContentsWrapper<T>,
-    index: BreakpadIndex<'a>,
+    index: SymbolMapIndex<'a, T>,
     cache: Mutex<BreakpadSymbolMapCache<'a>>,
     generation: SymbolMapGeneration,
 }
 
+enum SymbolMapIndex<'a, T: FileContents> {
+    /// An index of all symbols, either from a symindex file or from parsing the
+    /// entire sym file.
+    Full(BreakpadIndex<'a>),
+    /// An index which only knows about the symbols which have been looked up.
+    Lazy {
+        index: BreakpadLazyIndex<'a, T>,
+        /// Created once the lazy index finds records in an unexpected order,
+        /// see `replace_lazy_index`, and used instead of it from then on.
+        full_index: OnceLock<Option<Box<OwnedBreakpadIndex>>>,
+        data: &'a FileContentsWrapper<T>,
+        limits: ParseLimits,
+    },
+}
+
+/// With a lazy index, the number of symbols isn't known, so the function name
+/// indexes for inline origins start at a fixed offset.
+const LAZY_INDEX_INLINE_ORIGIN_FUNCTION_NAME_INDEX_START: u32 = 1 << 31;
+
+/// Once a lazy index has been replaced by the full index, the indexes of the
+/// symbols from the full index start at this offset, so that they don't
+/// collide with the indexes of the symbols from the lazy index.
+const LAZY_INDEX_FULL_INDEX_SYMBOL_INDEX_START: u32 = 1 << 30;
+
 #[derive(Debug)]
 struct BreakpadSymbolMapCache<'a> {
     symbols: BreakpadSymbolMapSymbolCache<'a>,
@@ -241,42 +319,254 @@ impl<'a> BreakpadSymbolMapSymbolCache<'a> {
     }
 }
 
+impl<'a, T: FileContents> SymbolMapIndex<'a, T> {
+    fn debug_id(&self) -> debugid::DebugId {
+        match self {
+            SymbolMapIndex::Full(index) => index.debug_id,
+            SymbolMapIndex::Lazy { index, .. } => index.debug_id(),
+        }
+    }
+
+    /// Returns the index, address, next symbol address and entry of the
+    /// symbol which covers `address`.
+    fn lookup(&self, address: u32) -> Option<(u32, u32, Option<u32>, BreakpadSymbolEntry)> {
+        match self {
+            SymbolMapIndex::Full(index) => lookup_in_full_index(index, 0, address),
+            SymbolMapIndex::Lazy {
+                index, full_index, ..
+            } => {
+                if let Some(full_index) = full_index.get() {
+                    let full_index = full_index.as_ref()?.index();
+                    return lookup_in_full_index(
+                        &full_index,
+                        LAZY_INDEX_FULL_INDEX_SYMBOL_INDEX_START,
+                        address,
+                    );
+                }
+                match index.lookup(address) {
+                    Ok(symbol) => {
+                        let symbol = symbol?;
+                        Some((
+                            symbol.index,
+                            symbol.address,
+                            symbol.next_symbol_address,
+                            symbol.entry,
+                        ))
+                    }
+                    Err(e) => lookup_in_full_index(
+                        &self.replace_lazy_index(e)?,
+                        LAZY_INDEX_FULL_INDEX_SYMBOL_INDEX_START,
+                        address,
+                    ),
+                }
+            }
+        }
+    }
+
+    fn symbol_entry(&self, symbol_entry_index: u32) -> Option<BreakpadSymbolEntry> {
+        match self {
+            SymbolMapIndex::Full(index) => index
+                .symbol_entries
+                .get(symbol_entry_index as usize)
+                .cloned(),
+            SymbolMapIndex::Lazy {
+                index, full_index, ..
+            } => match symbol_entry_index.checked_sub(LAZY_INDEX_FULL_INDEX_SYMBOL_INDEX_START) {
+                Some(i) => full_index
+                    .get()?
+                    .as_ref()?
+                    .index()
+                    .symbol_entries
+                    .get(i as usize)
+                    .cloned(),
+                None => index.symbol_entry(symbol_entry_index),
+            },
+        }
+    }
+
+    fn inline_origin_function_name_index_start(&self) -> u32 {
+        match self {
+            SymbolMapIndex::Full(index) => index.symbol_entries.len() as u32,
+            SymbolMapIndex::Lazy { .. } => LAZY_INDEX_INLINE_ORIGIN_FUNCTION_NAME_INDEX_START,
+        }
+    }
+
+    fn inline_origin(
+        &self,
+        inline_origin_index: u32,
+        data: &'a FileContentsWrapper<T>,
+    ) -> Option<&'a str> {
+        let get = |index: &BreakpadIndex| {
+            index
+                .inline_origins
+                .get(inline_origin_index, data)
+                .and_then(|s| std::str::from_utf8(s).ok())
+        };
+        match self {
+            SymbolMapIndex::Full(index) => get(index),
+            SymbolMapIndex::Lazy {
+                index, full_index, ..
+            } => {
+                if let Some(full_index) = full_index.get() {
+                    return get(&full_index.as_ref()?.index());
+                }
+                match index.inline_origin(inline_origin_index) {
+                    Ok(s) => s,
+                    Err(e) => get(&self.replace_lazy_index(e)?),
+                }
+            }
+        }
+    }
+
+    fn file(&self, file_index: u32, data: &'a FileContentsWrapper<T>) -> Option<&'a str> {
+        let get = |index: &BreakpadIndex| {
+            index
+                .files
+                .get(file_index, data)
+                .and_then(|s| std::str::from_utf8(s).ok())
+        };
+        match self {
+            SymbolMapIndex::Full(index) => get(index),
+            SymbolMapIndex::Lazy {
+                index, full_index, ..
+            } => {
+                if let Some(full_index) = full_index.get() {
+                    return get(&full_index.as_ref()?.index());
+                }
+                match index.file(file_index) {
+                    Ok(s) => s,
+                    Err(e) => get(&self.replace_lazy_index(e)?),
+                }
+            }
+        }
+    }
+
+    /// Replaces a lazy index with the full index if `error` is about records
+    /// in an unexpected order, and returns the full index. The full index is
+    /// created the first time this is called.
+    fn replace_lazy_index(&self, error: Error) -> Option<BreakpadIndex<'_>> {
+        let SymbolMapIndex::Lazy {
+            full_index,
+            data,
+            limits,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        if !matches!(
+            error,
+            Error::BreakpadParsing(BreakpadParseError::UnexpectedRecordOrder)
+        ) {
+            return None;
+        }
+        let full_index = full_index
+            .get_or_init(|| create_index(data, limits).ok().map(Box::new))
+            .as_ref()?;
+        Some(full_index.index())
+    }
+}
+
+/// Returns the index, address, next symbol address and entry of the symbol
+/// which covers `address`. The returned index is offset by `index_start`.
+fn lookup_in_full_index(
+    index: &BreakpadIndex,
+    index_start: u32,
+    address: u32,
+) -> Option<(u32, u32, Option<u32>, BreakpadSymbolEntry)> {
+    let i = match index
+        .symbol_addresses
+        .binary_search_by_key(&address, |a| a.get())
+    {
+        Ok(i) => i,
+        Err(0) => return None,
+        Err(i) => i - 1,
+    };
+    let symbol_address = index.symbol_addresses[i].get();
+    let next_symbol_address = index.symbol_addresses.get(i + 1).map(|a| a.get());
+    let entry = index.symbol_entries[i].clone();
+    Some((
+        index_start + i as u32,
+        symbol_address,
+        next_symbol_address,
+        entry,
+    ))
+}
+
 impl<'object, T: FileContents> BreakpadSymbolMapInner<'object, T> {
+    /// The symbols of a full index, whose symbol indexes are offset by
+    /// `index_start`.
+    fn iter_full_index_symbols<'s>(
+        &'s self,
+        index: BreakpadIndex<'s>,
+        index_start: u32,
+    ) -> Box<dyn Iterator<Item = (u32, Cow<'s, str>)> + 's> {
+        let iter = (0..index.symbol_addresses.len()).filter_map(move |i| {
+            let address = index.symbol_addresses[i].get();
+            let entry = &index.symbol_entries[i];
+            let mut cache = self.cache.lock().unwrap();
+            let name = cache
+                .symbols
+                .get_name(index_start + i as u32, entry, self.data)
+                .ok()??;
+            Some((address, Cow::Borrowed(name)))
+        });
+        Box::new(iter)
+    }
+
     fn resolve_symbol_entry_name(&self, symbol_entry_index: u32) -> Option<&'object str> {
-        let entry = &self.index.symbol_entries.get(symbol_entry_index as usize)?;
+        let entry = self.index.symbol_entry(symbol_entry_index)?;
         let mut cache = self.cache.lock().unwrap();
         cache
             .symbols
-            .get_name(symbol_entry_index, entry, self.data)
+            .get_name(symbol_entry_index, &entry, self.data)
             .ok()?
     }
-
-    fn resolve_inline_origin_name(&self, inline_origin_index: u32) -> Option<&'object str> {
-        self.index
-            .inline_origins
-            .get(inline_origin_index, self.data)
-            .and_then(|s| std::str::from_utf8(s).ok())
-    }
 }
 
 impl<'object, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'object, T> {
     fn debug_id(&self) -> debugid::DebugId {
-        self.index.debug_id
+        self.index.debug_id()
     }
 
     fn symbol_count(&self) -> usize {
-        self.index.symbol_addresses.len()
+        match &self.index {
+            SymbolMapIndex::Full(index) => index.symbol_addresses.len(),
+            SymbolMapIndex::Lazy { index, .. } => match index.symbol_count() {
+                Ok(count) => count,
+                Err(e) => self
+                    .index
+                    .replace_lazy_index(e)
+                    .map_or(0, |index| index.symbol_addresses.len()),
+            },
+        }
     }
 
     fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_> {
-        let iter = (0..self.symbol_count()).filter_map(move |i| {
-            let address = self.index.symbol_addresses[i].get();
-            let mut cache = self.cache.lock().unwrap();
-            let entry = &self.index.symbol_entries[i];
-            let name = cache.symbols.get_name(i as u32, entry, self.data).ok()??;
-            Some((address, Cow::Borrowed(name)))
-        });
-        Box::new(iter)
+        match &self.index {
+            SymbolMapIndex::Full(index) => self.iter_full_index_symbols(index.clone(), 0),
+            SymbolMapIndex::Lazy { index, .. } => {
+                // The lazy iteration relies on sorted symbol records, which
+                // counting the symbols checks.
+                if let Err(e) = index.symbol_count() {
+                    return match self.index.replace_lazy_index(e) {
+                        Some(full_index) => self.iter_full_index_symbols(
+                            full_index,
+                            LAZY_INDEX_FULL_INDEX_SYMBOL_INDEX_START,
+                        ),
+                        None => Box::new(std::iter::empty()),
+                    };
+                }
+                let iter = index.iter_symbols().filter_map(|(address, entry)| {
+                    // The cache is keyed by the lazy index's symbol indexes, so it can't be used here.
+                    let name = BreakpadSymbolMapSymbolCache::default()
+                        .get_name(0, &entry, self.data)
+                        .ok()??;
+                    Some((address, Cow::Borrowed(name)))
+                });
+                Box::new(iter)
+            }
+        }
     }
 
     fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
@@ -291,21 +581,8 @@ impl<'object, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'object
                 return None;
             }
         };
-        let index = match self
-            .index
-            .symbol_addresses
-            .binary_search_by_key(&address, |a| a.get())
-        {
-            Ok(i) => i,
-            Err(0) => return None,
-            Err(i) => i - 1,
-        };
-        let symbol_name = self
-            .generation
-            .symbol_name_handle(SymbolNameIndex(index as u32));
-        let symbol_address = self.index.symbol_addresses[index].get();
-        let next_symbol_address = self.index.symbol_addresses.get(index + 1).map(|a| a.get());
-        let entry = &self.index.symbol_entries[index];
+        let (index, symbol_address, next_symbol_address, entry) = self.index.lookup(address)?;
+        let symbol_name = self.generation.symbol_name_handle(SymbolNameIndex(index));
         let kind = entry.kind.get();
         match kind {
             SYMBOL_ENTRY_KIND_PUBLIC => Some(SyncAddressInfo {
@@ -315,13 +592,14 @@ impl<'object, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'object
                         next_symbol_address.checked_sub(symbol_address)
                     }),
                     name: symbol_name,
+                    kind: SymbolKind::Function,
                 },
                 frames: None,
             }),
             SYMBOL_ENTRY_KIND_FUNC => {
                 let mut cache = self.cache.lock().unwrap();
                 let symbols = &mut cache.symbols;
-                let info = symbols.get_func_info(index as u32, entry, self.data).ok()?;
+                let info = symbols.get_func_info(index, &entry, self.data).ok()?;
                 let symbols = &*symbols;
                 let func_end_addr = symbol_address + info.size;
                 if address >= func_end_addr {
@@ -332,7 +610,7 @@ impl<'object, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'object
                 let mut depth = 0;
                 let mut name = self
                     .generation
-                    .function_name_handle(FunctionNameIndex(index as u32));
+                    .function_name_handle(FunctionNameIndex(index));
                 while let Some(inlinee) =
                     info.get_inlinee_at_depth(depth, address, &symbols.inlinees)
                 {
@@ -342,7 +620,7 @@ impl<'object, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'object
                         line_number: Some(inlinee.call_line),
                     });
                     name = self.generation.function_name_handle(FunctionNameIndex(
-                        self.index.symbol_entries.len() as u32 + inlinee.origin_id,
+                        self.index.inline_origin_function_name_index_start() + inlinee.origin_id,
                     ));
                     depth += 1;
                 }
@@ -365,6 +643,7 @@ impl<'object, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'object
                         address: symbol_address,
                         size: Some(info.size),
                         name: symbol_name,
+                        kind: SymbolKind::Function,
                     },
                     frames: Some(FramesLookupResult::Available(frames)),
                 })
@@ -375,8 +654,11 @@ impl<'object, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'object
 
     fn resolve_function_name(&self, handle: FunctionNameHandle) -> Cow<'object, str> {
         let index = self.generation.unwrap_function_name_index(handle);
-        let s = match index.0.checked_sub(self.index.symbol_entries.len() as u32) {
-            Some(inline_index) => self.resolve_inline_origin_name(inline_index),
+        let s = match index
+            .0
+            .checked_sub(self.index.inline_origin_function_name_index_start())
+        {
+            Some(inline_index) => self.index.inline_origin(inline_index, self.data),
             None => self.resolve_symbol_entry_name(index.0),
         };
         Cow::Borrowed(s.unwrap_or("<missing>"))
@@ -390,12 +672,7 @@ impl<'object, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'object
 
     fn resolve_source_file_path(&self, handle: SourceFilePathHandle) -> SourceFilePath<'object> {
         let index = self.generation.unwrap_source_file_index(handle);
-        let s = self
-            .index
-            .files
-            .get(index.0, self.data)
-            .and_then(|s| std::str::from_utf8(s).ok())
-            .unwrap_or("<missing>");
+        let s = self.index.file(index.0, self.data).unwrap_or("<missing>");
         SourceFilePath::BreakpadSpecialPathStr(Cow::Borrowed(s))
     }
 
@@ -415,7 +692,7 @@ mod test {
     fn overeager_demangle() {
         let sym = b"MODULE Linux x86_64 BE4E976C325246EE9D6B7847A670B2A90 example-linux\nFILE 0 filename\nFUNC 1160 45 0 f\n1160 c 16 0";
         let fc = FileContentsWrapper::new(&sym[..]);
-        let symbol_map = get_symbol_map_for_breakpad_sym(fc, None).unwrap();
+        let symbol_map = get_symbol_map_for_breakpad_sym(fc, None, ParseLimits::default()).unwrap();
         let symbol_map = symbol_map.get_inner_symbol_map();
         let symbol = symbol_map
             .lookup_sync(LookupAddress::Relative(0x1160))
@@ -424,6 +701,22 @@ mod test {
         assert_eq!(symbol_map.resolve_symbol_name(symbol.name), "f");
     }
 
+    #[test]
+    fn symbol_count_limit() {
+        let sym = b"MODULE Linux x86_64 BE4E976C325246EE9D6B7847A670B2A90 example-linux\nFUNC 1160 45 0 f\nFUNC 11a5 10 0 g\nPUBLIC 11b5 0 h\n";
+        let limits = |max_symbol_count| ParseLimits {
+            max_symbol_count: Some(max_symbol_count),
+            ..Default::default()
+        };
+        let fc = FileContentsWrapper::new(&sym[..]);
+        assert!(get_symbol_map_for_breakpad_sym(fc, None, limits(3)).is_ok());
+        let fc = FileContentsWrapper::new(&sym[..]);
+        assert!(matches!(
+            get_symbol_map_for_breakpad_sym(fc, None, limits(2)),
+            Err(Error::ParseLimitExceeded("Symbol count", _, 2))
+        ));
+    }
+
     #[test]
     fn lookup_with_index() {
         // This test simulates the case where an index is created independently, for
@@ -453,7 +746,9 @@ mod test {
         let full_sym_contents = data_slices.concat();
         let sym_fc = FileContentsWrapper::new(full_sym_contents);
         let symindex_fc = FileContentsWrapper::new(index_bytes);
-        let symbol_map = get_symbol_map_for_breakpad_sym(sym_fc, Some(symindex_fc)).unwrap();
+        let symbol_map =
+            get_symbol_map_for_breakpad_sym(sym_fc, Some(symindex_fc), ParseLimits::default())
+                .unwrap();
         let symbol_map = symbol_map.get_inner_symbol_map();
 
         assert_eq!(
@@ -516,4 +811,331 @@ mod test {
         );
         assert_eq!(frames[3].line_number, Some(665));
     }
+
+    fn uses_lazy_index(data: &[u8]) -> bool {
+        let fc = FileContentsWrapper::new(data.to_vec());
+        let outer = BreakpadSymbolMapOuter::with_lazy_index_min_file_size(
+            fc,
+            None,
+            ParseLimits::default(),
+            0,
+        )
+        .unwrap();
+        matches!(outer.index, IndexStorage::Lazy(_))
+    }
+
+    /// Whether the lazy index rejects the layout of `data`, or finds records
+    /// in an unexpected order when looking up `addresses` and the first files.
+    fn lazy_index_finds_unexpected_order(
+        data: &[u8],
+        addresses: impl IntoIterator<Item = u32>,
+    ) -> bool {
+        let fc = FileContentsWrapper::new(data.to_vec());
+        let Some(layout) = BreakpadLazyIndexLayout::new(&fc).unwrap() else {
+            return true;
+        };
+        let index = BreakpadLazyIndex::new(&fc, &layout);
+        let is_unexpected_order = |e: Error| {
+            matches!(
+                e,
+                Error::BreakpadParsing(BreakpadParseError::UnexpectedRecordOrder)
+            )
+        };
+        addresses
+            .into_iter()
+            .any(|address| index.lookup(address).is_err_and(is_unexpected_order))
+            || (0..2).any(|i| index.file(i).is_err_and(is_unexpected_order))
+    }
+
+    fn get_symbol_map_with_lazy_index(data: &[u8]) -> BreakpadSymbolMap<Vec<u8>> {
+        let fc = FileContentsWrapper::new(data.to_vec());
+        let outer = BreakpadSymbolMapOuter::with_lazy_index_min_file_size(
+            fc,
+            None,
+            ParseLimits::default(),
+            0,
+        )
+        .unwrap();
+        BreakpadSymbolMap::from_outer(outer)
+    }
+
+    fn describe_lookup(symbol_map: &dyn SymbolMapTrait, address: u32) -> Option<String> {
+        let info = symbol_map.lookup_sync(LookupAddress::Relative(address))?;
+        let mut s = format!(
+            "{} {:#x} {:?}",
+            symbol_map.resolve_symbol_name(info.symbol.name),
+            info.symbol.address,
+            info.symbol.size
+        );
+        if let Some(FramesLookupResult::Available(frames)) = info.frames {
+            for frame in frames {
+                let function = symbol_map.resolve_function_name(frame.function.unwrap());
+                let file = frame.file_path.map(|file| {
+                    symbol_map
+                        .resolve_source_file_path(file)
+                        .raw_path()
+                        .to_owned()
+                });
+                s += &format!(" | {function} {file:?} {:?}", frame.line_number);
+            }
+        }
+        Some(s)
+    }
+
+    #[test]
+    fn lazy_index_matches_full_index() {
+        const HEADER: &str =
+            "MODULE Linux x86_64 BE4E976C325246EE9D6B7847A670B2A90 example-linux\n\
+            INFO CODE_ID 6C974EBE5232EE469D6B7847A670B2A9\n\
+            FILE 0 a.c\n\
+            FILE 1 b.h\n\
+            INLINE_ORIGIN 0 inlined_a\n\
+            INLINE_ORIGIN 1 inlined_b\n";
+        const F1: &str = "FUNC 1000 20 0 f1\n1000 10 3 0\n1010 10 4 0\n";
+        const F2: &str = "FUNC m 1040 30 0 f2\n\
+            INLINE 0 10 0 1 1048 8\n\
+            1040 8 7 0\n\
+            1048 8 20 1\n\
+            1050 20 8 0\n";
+        const P1: &str = "PUBLIC 900 0 pub_before\n";
+        const P2: &str = "PUBLIC 1020 0 pub_between\n";
+        const P3: &str = "PUBLIC m 1100 0 pub_after\n";
+        const STACK: &str = "STACK CFI INIT 1000 20 .cfa: $rsp 8 + .ra: .cfa -8 + ^\n\
+            STACK CFI 1001 .cfa: $rsp 16 +\n";
+
+        // All FUNC records followed by all PUBLIC records, like in Breakpad's dump_syms output.
+        let separate = [HEADER, F1, F2, P1, P2, P3, STACK].concat();
+        // All symbols sorted by address, like in Mozilla's dump_syms output.
+        let interleaved = [HEADER, P1, F1, P2, F2, P3, STACK].concat();
+        let crlf_without_trailing_line_break =
+            [HEADER, F1, F2].concat().trim_end().replace('\n', "\r\n");
+        let only_publics = [HEADER, P1, P2, P3].concat();
+        let no_symbols = [HEADER, STACK].concat();
+
+        for sym in [
+            separate,
+            interleaved,
+            crlf_without_trailing_line_break,
+            only_publics,
+            no_symbols,
+        ] {
+            let full_fc = FileContentsWrapper::new(sym.as_bytes().to_vec());
+            let full_symbol_map =
+                get_symbol_map_for_breakpad_sym(full_fc, None, ParseLimits::default()).unwrap();
+            let full_symbol_map = full_symbol_map.get_inner_symbol_map();
+            assert!(uses_lazy_index(sym.as_bytes()), "{sym}");
+            assert!(
+                !lazy_index_finds_unexpected_order(sym.as_bytes(), 0x800..0x1200),
+                "{sym}"
+            );
+            let lazy_symbol_map = get_symbol_map_with_lazy_index(sym.as_bytes());
+            let lazy_symbol_map = lazy_symbol_map.get_inner_symbol_map();
+
+            assert_eq!(lazy_symbol_map.debug_id(), full_symbol_map.debug_id());
+            // Look up addresses in a different order than the file order, so
+            // that the lazy index is filled out of order.
+            for address in (0x800..0x1200).rev().chain(0x800..0x1200) {
+                assert_eq!(
+                    describe_lookup(lazy_symbol_map, address),
+                    describe_lookup(full_symbol_map, address),
+                    "address {address:#x} in:\n{sym}"
+                );
+            }
+            assert_eq!(
+                lazy_symbol_map.iter_symbols().collect::<Vec<_>>(),
+                full_symbol_map.iter_symbols().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                lazy_symbol_map.symbol_count(),
+                full_symbol_map.symbol_count()
+            );
+        }
+
+        let lazy_symbol_map =
+            get_symbol_map_with_lazy_index([HEADER, F1, F2, P1, P2, P3, STACK].concat().as_bytes());
+        assert_eq!(
+            describe_lookup(lazy_symbol_map.get_inner_symbol_map(), 0x104c).unwrap(),
+            "f2 0x1040 Some(48) | inlined_b Some(\"b.h\") Some(20) | f2 Some(\"a.c\") Some(10)"
+        );
+        assert_eq!(
+            describe_lookup(lazy_symbol_map.get_inner_symbol_map(), 0x1025).unwrap(),
+            "pub_between 0x1020 Some(32)"
+        );
+    }
+
+    #[test]
+    fn unsorted_records_use_full_index() {
+        const MODULE: &str =
+            "MODULE Linux x86_64 BE4E976C325246EE9D6B7847A670B2A90 example-linux\n";
+        const FILES: &str = "FILE 0 a.c\nFILE 1 b.h\n";
+        const F1: &str = "FUNC 1000 20 0 f1\n1000 10 3 1\n1010 10 4 0\n";
+        const F2: &str = "FUNC 1040 30 0 f2\n1040 30 7 0\n";
+        const P1: &str = "PUBLIC 900 0 pub_before\n";
+        const P2: &str = "PUBLIC 1100 0 pub_after\n";
+
+        for sym in [
+            // FUNC records which are not sorted by address.
+            [MODULE, FILES, F2, F1, P1, P2].concat(),
+            // PUBLIC records followed by FUNC records.
+            [MODULE, FILES, P1, P2, F1, F2].concat(),
+            // Addresses which start over more than once.
+            [MODULE, FILES, F2, P2, F1, P1].concat(),
+            // FILE records which are not sorted by index.
+            [MODULE, "FILE 1 b.h\nFILE 0 a.c\n", F1, F2].concat(),
+            // FILE records after the symbols.
+            [MODULE, F1, F2, FILES].concat(),
+        ] {
+            assert!(
+                lazy_index_finds_unexpected_order(sym.as_bytes(), 0x800..0x1200),
+                "{sym}"
+            );
+            let full_fc = FileContentsWrapper::new(sym.as_bytes().to_vec());
+            let full_symbol_map =
+                get_symbol_map_for_breakpad_sym(full_fc, None, ParseLimits::default()).unwrap();
+            let full_symbol_map = full_symbol_map.get_inner_symbol_map();
+            let lazy_symbol_map = get_symbol_map_with_lazy_index(sym.as_bytes());
+            let lazy_symbol_map = lazy_symbol_map.get_inner_symbol_map();
+            for address in 0x800..0x1200 {
+                assert_eq!(
+                    describe_lookup(lazy_symbol_map, address),
+                    describe_lookup(full_symbol_map, address),
+                    "address {address:#x} in:\n{sym}"
+                );
+            }
+            assert_eq!(
+                lazy_symbol_map.iter_symbols().collect::<Vec<_>>(),
+                full_symbol_map.iter_symbols().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                lazy_symbol_map.symbol_count(),
+                full_symbol_map.symbol_count()
+            );
+        }
+
+        // The symbols count and iteration check the order, too.
+        let sym = [MODULE, FILES, F2, F1, P1, P2].concat();
+        let full_fc = FileContentsWrapper::new(sym.as_bytes().to_vec());
+        let full_symbol_map =
+            get_symbol_map_for_breakpad_sym(full_fc, None, ParseLimits::default()).unwrap();
+        let lazy_symbol_map = get_symbol_map_with_lazy_index(sym.as_bytes());
+        assert_eq!(
+            lazy_symbol_map.get_inner_symbol_map().symbol_count(),
+            full_symbol_map.get_inner_symbol_map().symbol_count()
+        );
+        let lazy_symbol_map = get_symbol_map_with_lazy_index(sym.as_bytes());
+        assert_eq!(
+            lazy_symbol_map
+                .get_inner_symbol_map()
+                .iter_symbols()
+                .collect::<Vec<_>>(),
+            full_symbol_map
+                .get_inner_symbol_map()
+                .iter_symbols()
+                .collect::<Vec<_>>()
+        );
+
+        // f1 and f2 are found in the unsorted file.
+        let lazy_symbol_map =
+            get_symbol_map_with_lazy_index([MODULE, FILES, F2, F1].concat().as_bytes());
+        assert_eq!(
+            describe_lookup(lazy_symbol_map.get_inner_symbol_map(), 0x1005).unwrap(),
+            "f1 0x1000 Some(32) | f1 Some(\"b.h\") Some(3)"
+        );
+        assert_eq!(
+            describe_lookup(lazy_symbol_map.get_inner_symbol_map(), 0x1045).unwrap(),
+            "f2 0x1040 Some(48) | f2 Some(\"a.c\") Some(7)"
+        );
+    }
+
+    /// A sym file with `count` FUNC records, each with a line record, followed
+    /// by a PUBLIC record for every other function. The records for the
+    /// functions in `swapped` are swapped.
+    fn large_sym(count: u32, swapped: Option<(u32, u32)>) -> String {
+        let func = |i: u32| {
+            let address = 0x1000 + i * 0x10;
+            format!("FUNC {address:x} 10 0 f{i}\n{address:x} 10 {i} 0\n")
+        };
+        let mut sym =
+            "MODULE Linux x86_64 BE4E976C325246EE9D6B7847A670B2A90 example-linux\nFILE 0 a.c\n"
+                .to_string();
+        for i in 0..count {
+            let i = match swapped {
+                Some((a, b)) if i == a => b,
+                Some((a, b)) if i == b => a,
+                _ => i,
+            };
+            sym += &func(i);
+        }
+        for i in (0..count).step_by(2) {
+            sym += &format!("PUBLIC {:x} 0 p{i}\n", 0x1008 + i * 0x10);
+        }
+        sym
+    }
+
+    #[test]
+    fn large_file_lazy_index_matches_full_index() {
+        let sym = large_sym(5000, None);
+        assert!(sym.len() > 32 * 4096);
+        let addresses = || (0xff0..0x1000 + 5000 * 0x10 + 0x10).step_by(0x35);
+        assert!(uses_lazy_index(sym.as_bytes()));
+        assert!(!lazy_index_finds_unexpected_order(
+            sym.as_bytes(),
+            addresses()
+        ));
+
+        let full_fc = FileContentsWrapper::new(sym.as_bytes().to_vec());
+        let full_symbol_map =
+            get_symbol_map_for_breakpad_sym(full_fc, None, ParseLimits::default()).unwrap();
+        let full_symbol_map = full_symbol_map.get_inner_symbol_map();
+        let lazy_symbol_map = get_symbol_map_with_lazy_index(sym.as_bytes());
+        let lazy_symbol_map = lazy_symbol_map.get_inner_symbol_map();
+        for address in addresses() {
+            assert_eq!(
+                describe_lookup(lazy_symbol_map, address),
+                describe_lookup(full_symbol_map, address),
+                "address {address:#x}"
+            );
+        }
+        assert_eq!(
+            lazy_symbol_map.symbol_count(),
+            full_symbol_map.symbol_count()
+        );
+    }
+
+    #[test]
+    fn large_file_with_unsorted_records() {
+        // The swapped records are found by the lookups which bisect the
+        // ranges around them, and then the full index takes over.
+        let sym = large_sym(5000, Some((2500, 2600)));
+        let swapped_addresses = || (0x1000 + 2490 * 0x10..0x1000 + 2610 * 0x10).step_by(3);
+        assert!(uses_lazy_index(sym.as_bytes()));
+        assert!(lazy_index_finds_unexpected_order(
+            sym.as_bytes(),
+            swapped_addresses()
+        ));
+
+        let full_fc = FileContentsWrapper::new(sym.as_bytes().to_vec());
+        let full_symbol_map =
+            get_symbol_map_for_breakpad_sym(full_fc, None, ParseLimits::default()).unwrap();
+        let full_symbol_map = full_symbol_map.get_inner_symbol_map();
+        let lazy_symbol_map = get_symbol_map_with_lazy_index(sym.as_bytes());
+        let lazy_symbol_map = lazy_symbol_map.get_inner_symbol_map();
+
+        // A symbol found before the fallback still resolves afterwards.
+        let early = lazy_symbol_map
+            .lookup_sync(LookupAddress::Relative(0x1005))
+            .unwrap();
+        for address in swapped_addresses() {
+            assert_eq!(
+                describe_lookup(lazy_symbol_map, address),
+                describe_lookup(full_symbol_map, address),
+                "address {address:#x}"
+            );
+        }
+        assert_eq!(lazy_symbol_map.resolve_symbol_name(early.symbol.name), "f0");
+        assert_eq!(
+            describe_lookup(lazy_symbol_map, 0x1005),
+            describe_lookup(full_symbol_map, 0x1005)
+        );
+    }
 }
@@ -7,7 +7,7 @@ use gimli::{DwarfPackage, EndianSlice, Reader, RunTimeEndian, SectionId};
 use object::read::ReadRef;
 use object::CompressionFormat;
 
-use crate::{demangle, Error, FrameDebugInfo, SymbolMapStringInterner};
+use crate::{demangle, Error, FrameDebugInfo, ParseLimits, SymbolMapStringInterner};
 
 pub fn get_frames<R: Reader>(
     address: u64,
@@ -77,32 +77,42 @@ pub fn try_get_section_data<'data, O, T>(
     file: &O,
     section_id: SectionId,
     is_for_dwo_dwp: bool,
-) -> Option<SingleSectionData<'data, T>>
+    limits: &ParseLimits,
+) -> Result<Option<SingleSectionData<'data, T>>, Error>
 where
     O: object::Object<'data>,
     T: ReadRef<'data>,
 {
     use object::ObjectSection;
     let section_name = if is_for_dwo_dwp {
-        section_id.dwo_name()?
+        match section_id.dwo_name() {
+            Some(section_name) => section_name,
+            None => return Ok(None),
+        }
     } else {
         section_id.name()
     };
-    let section = file.section_by_name(section_name)?;
-    let file_range = section.compressed_file_range().ok()?;
-    match file_range.format {
+    let Some(section) = file.section_by_name(section_name) else {
+        return Ok(None);
+    };
+    let Ok(file_range) = section.compressed_file_range() else {
+        return Ok(None);
+    };
+    limits.check_section_size(file_range.uncompressed_size)?;
+    let section_data = match file_range.format {
         CompressionFormat::None => Some(SingleSectionData::View {
             data,
             offset: file_range.offset,
             size: file_range.uncompressed_size,
             _phantom: PhantomData,
         }),
-        _ => {
-            let compressed = file_range.data(data).ok()?;
-            let decompressed = compressed.decompress().ok()?;
-            Some(SingleSectionData::Owned(decompressed.into_owned()))
-        }
-    }
+        _ => file_range
+            .data(data)
+            .ok()
+            .and_then(|compressed| compressed.decompress().ok())
+            .map(|decompressed| SingleSectionData::Owned(decompressed.into_owned())),
+    };
+    Ok(section_data)
 }
 
 /// Holds on to section data so that we can create an addr2line::Context for that
@@ -112,15 +122,26 @@ where
 /// See addr2line::Context::new for details.
 pub struct Addr2lineContextData {
     uncompressed_section_data: FrozenVec<Vec<u8>>,
+    limits: ParseLimits,
 }
 
 impl Addr2lineContextData {
     pub fn new() -> Self {
+        Self::with_parse_limits(ParseLimits::default())
+    }
+
+    /// Makes sections which exceed the limits fail the context creation.
+    pub fn with_parse_limits(limits: ParseLimits) -> Self {
         Self {
             uncompressed_section_data: FrozenVec::new(),
+            limits,
         }
     }
 
+    pub fn parse_limits(&self) -> ParseLimits {
+        self.limits
+    }
+
     fn sect<'data, 'ctxdata, O, R>(
         &'ctxdata self,
         data: R,
@@ -128,13 +149,15 @@ impl Addr2lineContextData {
         section_id: SectionId,
         endian: RunTimeEndian,
         is_for_dwo_dwp: bool,
-    ) -> EndianSlice<'ctxdata, RunTimeEndian>
+    ) -> Result<EndianSlice<'ctxdata, RunTimeEndian>, Error>
     where
         'data: 'ctxdata,
         O: object::Object<'data>,
         R: ReadRef<'data>,
     {
-        let slice: &[u8] = match try_get_section_data(data, obj, section_id, is_for_dwo_dwp) {
+        let section_data =
+            try_get_section_data(data, obj, section_id, is_for_dwo_dwp, &self.limits)?;
+        let slice: &[u8] = match section_data {
             Some(SingleSectionData::Owned(section_data)) => {
                 self.uncompressed_section_data.push_get(section_data)
             }
@@ -143,7 +166,7 @@ impl Addr2lineContextData {
             }) => data.read_bytes_at(offset, size).unwrap_or(&[]),
             None => &[],
         };
-        EndianSlice::new(slice, endian)
+        Ok(EndianSlice::new(slice, endian))
     }
 
     pub fn make_context<'data, 'ctxdata, O, R>(
@@ -163,12 +186,9 @@ impl Addr2lineContextData {
         } else {
             gimli::RunTimeEndian::Big
         };
-        let mut dwarf = gimli::Dwarf::load(|s| Ok(self.sect(data, obj, s, e, false)))
-            .map_err(Error::Addr2lineContextCreationError)?;
+        let mut dwarf = gimli::Dwarf::load(|s| self.sect(data, obj, s, e, false))?;
         if let (Some(sup_obj), Some(sup_data)) = (sup_obj, sup_data) {
-            dwarf
-                .load_sup(|s| Ok(self.sect(sup_data, sup_obj, s, e, false)))
-                .map_err(Error::Addr2lineContextCreationError)?;
+            dwarf.load_sup(|s| self.sect(sup_data, sup_obj, s, e, false))?;
         }
         let context =
             addr2line::Context::from_dwarf(dwarf).map_err(Error::Addr2lineContextCreationError)?;
@@ -192,18 +212,21 @@ impl Addr2lineContextData {
         } else {
             gimli::RunTimeEndian::Big
         };
+        // Sections which exceed the limits are treated as missing, like
+        // unparsable packages.
+        let empty = EndianSlice::new(&[], e);
         let mut package = None;
         if let (Some(dwp_obj), Some(dwp_data)) = (dwp_obj, dwp_data) {
             package = DwarfPackage::load::<_, gimli::Error>(
-                |s| Ok(self.sect(dwp_data, dwp_obj, s, e, true)),
-                EndianSlice::new(&[], e),
+                |s| Ok(self.sect(dwp_data, dwp_obj, s, e, true).unwrap_or(empty)),
+                empty,
             )
             .ok();
         }
         if package.is_none() && obj.section_by_name(".debug_cu_index").is_some() {
             package = DwarfPackage::load::<_, gimli::Error>(
-                |s| Ok(self.sect(data, obj, s, e, true)),
-                EndianSlice::new(&[], e),
+                |s| Ok(self.sect(data, obj, s, e, true).unwrap_or(empty)),
+                empty,
             )
             .ok();
         }
@@ -225,8 +248,7 @@ impl Addr2lineContextData {
         } else {
             gimli::RunTimeEndian::Big
         };
-        let dwarf = gimli::Dwarf::load(|s| Ok(self.sect(data, obj, s, e, true)))
-            .map_err(Error::Addr2lineContextCreationError)?;
+        let dwarf = gimli::Dwarf::load(|s| self.sect(data, obj, s, e, true))?;
         Ok(dwarf)
     }
 }
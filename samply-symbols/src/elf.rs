@@ -12,6 +12,7 @@ use yoke_derive::Yokeable;
 
 use crate::dwarf::Addr2lineContextData;
 use crate::error::Error;
+use crate::limits::ParseLimits;
 use crate::shared::{FileAndPathHelper, FileContents, FileContentsWrapper, FileLocation};
 use crate::symbol_map::SymbolMap;
 use crate::symbol_map_object::{
@@ -23,14 +24,21 @@ pub async fn load_symbol_map_for_elf<H: FileAndPathHelper>(
     file_contents: FileContentsWrapper<H::F>,
     file_kind: FileKind,
     helper: Arc<H>,
+    limits: ParseLimits,
 ) -> Result<SymbolMap<H>, Error> {
     let elf_file =
         File::parse(&file_contents).map_err(|e| Error::ObjectParseError(file_kind, e))?;
 
-    if let Some(symbol_map) =
-        try_to_get_symbol_map_from_debug_link(&file_location, &elf_file, file_kind, &*helper).await
+    if let Some(symbol_map) = try_to_get_symbol_map_from_debug_link(
+        &file_location,
+        &elf_file,
+        file_kind,
+        &*helper,
+        limits,
+    )
+    .await
     {
-        return Ok(symbol_map);
+        return symbol_map;
     }
 
     let dwp_file_contents = if let Some(dwp_file_location) = file_location.location_for_dwp() {
@@ -50,8 +58,10 @@ pub async fn load_symbol_map_for_elf<H: FileAndPathHelper>(
             file_contents,
             Some(supplementary_file),
             dwp_file_contents,
+            None,
             file_kind,
             None,
+            limits,
         )?;
         let symbol_map = ObjectSymbolMap::new(owner)?;
         return Ok(SymbolMap::new_plain(file_location, Box::new(symbol_map)));
@@ -59,13 +69,20 @@ pub async fn load_symbol_map_for_elf<H: FileAndPathHelper>(
 
     // If this file has a .gnu_debugdata section, use the uncompressed object from that section instead.
     if let Some(symbol_map) =
-        try_get_symbol_map_from_mini_debug_info(&elf_file, file_kind, &file_location)
+        try_get_symbol_map_from_mini_debug_info(&elf_file, file_kind, &file_location, limits)?
     {
         return Ok(symbol_map);
     }
 
-    let owner =
-        ElfSymbolMapDataAndObjects::new(file_contents, None, dwp_file_contents, file_kind, None)?;
+    let owner = ElfSymbolMapDataAndObjects::new(
+        file_contents,
+        None,
+        dwp_file_contents,
+        None,
+        file_kind,
+        None,
+        limits,
+    )?;
     let symbol_map = ObjectSymbolMap::new(owner)?;
     Ok(SymbolMap::new_with_external_file_support(
         file_location,
@@ -74,12 +91,78 @@ pub async fn load_symbol_map_for_elf<H: FileAndPathHelper>(
     ))
 }
 
+/// For an ELF file with the given debug ID, returns whether it has a full
+/// symbol table (`.symtab`) or DWARF debug info. Stripped binaries only have
+/// the dynamic symbols. Returns `None` for all other files.
+pub fn matching_elf_has_full_symbols<F: FileContents>(
+    file_contents: &FileContentsWrapper<F>,
+    debug_id: DebugId,
+) -> Option<bool> {
+    let elf_file = File::parse(file_contents).ok()?;
+    if debug_id_for_object(&elf_file)? != debug_id {
+        return None;
+    }
+    let has_section = |name| {
+        elf_file
+            .section_by_name(name)
+            .is_some_and(|section| section.size() != 0)
+    };
+    Some(has_section(".symtab") || has_section(".debug_info"))
+}
+
+/// Creates a symbol map which takes the symbols and the DWARF from an
+/// unstripped file (e.g. a `.debug` file), and the section layout and the
+/// unwind info from the stripped binary with the same debug ID. In a debug
+/// file which was created with `objcopy --only-keep-debug`, the sections
+/// with the code and the `.eh_frame` section are empty.
+pub async fn load_symbol_map_for_elf_with_layout_file<H: FileAndPathHelper>(
+    debug_file_location: H::FL,
+    debug_file_contents: FileContentsWrapper<H::F>,
+    layout_file_contents: FileContentsWrapper<H::F>,
+    helper: Arc<H>,
+    limits: ParseLimits,
+) -> Result<SymbolMap<H>, Error> {
+    let file_kind = FileKind::parse(&debug_file_contents)
+        .map_err(|_| Error::InvalidInputError("Unrecognized file"))?;
+    let debug_file =
+        File::parse(&debug_file_contents).map_err(|e| Error::ObjectParseError(file_kind, e))?;
+    let supplementary_file =
+        try_to_load_supplementary_file(&debug_file_location, &debug_file, &*helper).await;
+    let dwp_file_contents = if let Some(dwp_file_location) = debug_file_location.location_for_dwp()
+    {
+        helper
+            .load_file(dwp_file_location)
+            .await
+            .ok()
+            .map(FileContentsWrapper::new)
+    } else {
+        None
+    };
+
+    let owner = ElfSymbolMapDataAndObjects::new(
+        debug_file_contents,
+        supplementary_file,
+        dwp_file_contents,
+        Some(layout_file_contents),
+        file_kind,
+        None,
+        limits,
+    )?;
+    let symbol_map = ObjectSymbolMap::new(owner)?;
+    Ok(SymbolMap::new_with_external_file_support(
+        debug_file_location,
+        Box::new(symbol_map),
+        helper,
+    ))
+}
+
 async fn try_to_get_symbol_map_from_debug_link<'data, H, R>(
     original_file_location: &H::FL,
     elf_file: &File<'data, R>,
     file_kind: FileKind,
     helper: &H,
-) -> Option<SymbolMap<H>>
+    limits: ParseLimits,
+) -> Option<Result<SymbolMap<H>, Error>>
 where
     R: ReadRef<'data>,
     H: FileAndPathHelper,
@@ -99,10 +182,13 @@ where
             crc,
             file_kind,
             helper,
+            limits,
         )
         .await;
-        if let Ok(symbol_map) = symbol_map {
-            return Some(symbol_map);
+        match symbol_map {
+            Ok(symbol_map) => return Some(Ok(symbol_map)),
+            Err(e @ Error::ParseLimitExceeded(..)) => return Some(Err(e)),
+            Err(_) => {}
         }
     }
 
@@ -116,6 +202,7 @@ async fn get_symbol_map_for_debug_link_candidate<H>(
     expected_crc: u32,
     file_kind: FileKind,
     helper: &H,
+    limits: ParseLimits,
 ) -> Result<SymbolMap<H>, Error>
 where
     H: FileAndPathHelper,
@@ -144,8 +231,10 @@ where
         file_contents,
         None,
         dwp_file_contents,
+        None,
         file_kind,
         Some(debug_id),
+        limits,
     )?;
     let symbol_map = ObjectSymbolMap::new(owner)?;
     Ok(SymbolMap::new_plain(
@@ -242,19 +331,73 @@ fn try_get_symbol_map_from_mini_debug_info<'data, R: ReadRef<'data>, H: FileAndP
     elf_file: &File<'data, R>,
     file_kind: FileKind,
     debug_file_location: &H::FL,
-) -> Option<SymbolMap<H>> {
-    let debugdata = elf_file.section_by_name(".gnu_debugdata")?;
-    let data = debugdata.data().ok()?;
+    limits: ParseLimits,
+) -> Result<Option<SymbolMap<H>>, Error> {
+    let Some(data) = elf_file
+        .section_by_name(".gnu_debugdata")
+        .and_then(|debugdata| debugdata.data().ok())
+    else {
+        return Ok(None);
+    };
     let mut cursor = Cursor::new(data);
-    let mut objdata = Vec::new();
-    lzma_rs::xz_decompress(&mut cursor, &mut objdata).ok()?;
-    let file_contents = FileContentsWrapper::new(objdata);
-    let owner = ElfSymbolMapDataAndObjects::new(file_contents, None, None, file_kind, None).ok()?;
-    let symbol_map = ObjectSymbolMap::new(owner).ok()?;
-    Some(SymbolMap::new_plain(
+    let mut writer = SectionSizeLimitedWriter::new(limits);
+    if lzma_rs::xz_decompress(&mut cursor, &mut writer).is_err() {
+        return match writer.limit_error {
+            Some(e) => Err(e),
+            None => Ok(None),
+        };
+    }
+    let file_contents = FileContentsWrapper::new(writer.data);
+    let Ok(owner) =
+        ElfSymbolMapDataAndObjects::new(file_contents, None, None, None, file_kind, None, limits)
+    else {
+        return Ok(None);
+    };
+    let symbol_map = match ObjectSymbolMap::new(owner) {
+        Ok(symbol_map) => symbol_map,
+        Err(e @ Error::ParseLimitExceeded(..)) => return Err(e),
+        Err(_) => return Ok(None),
+    };
+    Ok(Some(SymbolMap::new_plain(
         debug_file_location.clone(),
         Box::new(symbol_map),
-    ))
+    )))
+}
+
+/// Collects decompressed data, and fails once it exceeds the section size limit.
+struct SectionSizeLimitedWriter {
+    data: Vec<u8>,
+    limits: ParseLimits,
+    limit_error: Option<Error>,
+}
+
+impl SectionSizeLimitedWriter {
+    fn new(limits: ParseLimits) -> Self {
+        Self {
+            data: Vec::new(),
+            limits,
+            limit_error: None,
+        }
+    }
+}
+
+impl std::io::Write for SectionSizeLimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let new_len = self.data.len() as u64 + buf.len() as u64;
+        if let Err(e) = self.limits.check_section_size(new_len) {
+            self.limit_error = Some(e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "section size limit exceeded",
+            ));
+        }
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 struct ElfSymbolMapData<T>
@@ -264,6 +407,8 @@ where
     file_data: FileContentsWrapper<T>,
     supplementary_file_data: Option<FileContentsWrapper<T>>,
     dwp_file_data: Option<FileContentsWrapper<T>>,
+    /// The stripped binary, if the symbols come from a separate debug file.
+    layout_file_data: Option<FileContentsWrapper<T>>,
     dwo_file_data: FrozenVec<Box<FileContentsWrapper<T>>>,
 }
 
@@ -284,6 +429,7 @@ struct ElfObjects<'data, T: FileContents> {
     object: File<'data, &'data FileContentsWrapper<T>>,
     supplementary_object: Option<File<'data, &'data FileContentsWrapper<T>>>,
     dwp_object: Option<File<'data, &'data FileContentsWrapper<T>>>,
+    layout_object: Option<File<'data, &'data FileContentsWrapper<T>>>,
 }
 
 impl<'data, T: FileContents + 'static> ElfObjects<'data, T> {
@@ -331,7 +477,7 @@ impl<'data, T: FileContents + 'static> ElfObjects<'data, T> {
     }
 
     fn function_addresses(&self) -> (Option<Vec<u32>>, Option<Vec<u32>>) {
-        compute_function_addresses_elf(&self.object)
+        compute_function_addresses_elf(self.layout_object.as_ref().unwrap_or(&self.object))
     }
 }
 
@@ -356,15 +502,22 @@ impl<T: FileContents + 'static> ElfObjectsTrait<T> for ElfObjects<'_, T> {
         };
         let (function_starts, function_ends) = self.function_addresses();
 
+        let context = match self.make_addr2line_context() {
+            Ok(context) => Some(context),
+            Err(e @ Error::ParseLimitExceeded(..)) => return Err(e),
+            Err(_) => None,
+        };
         let inner = ObjectSymbolMapInnerWrapper::new(
             &self.object,
-            self.make_addr2line_context().ok(),
+            context,
             self.make_dwp_package().ok().flatten(),
             debug_id,
             function_starts.as_deref(),
             function_ends.as_deref(),
+            self.layout_object.as_ref(),
             self,
-        );
+            &self.addr2line_context_data.parse_limits(),
+        )?;
 
         Ok(inner)
     }
@@ -379,13 +532,16 @@ impl<T: FileContents + 'static> ElfSymbolMapDataAndObjects<T> {
         file_data: FileContentsWrapper<T>,
         supplementary_file_data: Option<FileContentsWrapper<T>>,
         dwp_file_data: Option<FileContentsWrapper<T>>,
+        layout_file_data: Option<FileContentsWrapper<T>>,
         file_kind: FileKind,
         override_debug_id: Option<DebugId>,
+        limits: ParseLimits,
     ) -> Result<Self, Error> {
         let data = ElfSymbolMapData {
             file_data,
             supplementary_file_data,
             dwp_file_data,
+            layout_file_data,
             dwo_file_data: FrozenVec::new(),
         };
         let data_and_objects = Yoke::try_attach_to_cart(
@@ -407,16 +563,24 @@ impl<T: FileContents + 'static> ElfSymbolMapDataAndObjects<T> {
                     ),
                     None => None,
                 };
+                let layout_object = match data.layout_file_data.as_ref() {
+                    Some(layout_file_data) => Some(
+                        File::parse(layout_file_data)
+                            .map_err(|e| Error::ObjectParseError(file_kind, e))?,
+                    ),
+                    None => None,
+                };
                 let elf_objects = ElfObjects {
                     object,
                     supplementary_object,
                     dwp_object,
+                    layout_object,
                     dwo_file_data: &data.dwo_file_data,
                     file_data: &data.file_data,
                     supplementary_file_data: data.supplementary_file_data.as_ref(),
                     dwp_file_data: data.dwp_file_data.as_ref(),
                     override_debug_id,
-                    addr2line_context_data: Addr2lineContextData::new(),
+                    addr2line_context_data: Addr2lineContextData::with_parse_limits(limits),
                 };
                 Ok(ElfObjectsWrapper(Box::new(elf_objects)))
             },
@@ -90,6 +90,9 @@ pub enum Error {
     #[error("Invalid input: {0}")]
     InvalidInputError(&'static str),
 
+    #[error("{0} {1} exceeds the limit of {2}")]
+    ParseLimitExceeded(&'static str, u64, u64),
+
     #[error("Object could not parse the file as {0:?}: {1}")]
     ObjectParseError(object::read::FileKind, #[source] object::read::Error),
 
@@ -260,6 +263,7 @@ impl Error {
             Error::PdbError(_, _) => "PdbError",
             Error::PdbAddr2lineErrorWithContext(_, _) => "PdbAddr2lineErrorWithContext",
             Error::InvalidInputError(_) => "InvalidInputError",
+            Error::ParseLimitExceeded(_, _, _) => "ParseLimitExceeded",
             Error::DyldCacheParseError(_) => "DyldCacheParseError",
             Error::NoMatchingDyldCacheImagePath(_) => "NoMatchingDyldCacheImagePath",
             Error::ObjectParseError(_, _) => "ObjectParseError",
@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use object::read::archive::ArchiveFile;
 use object::{File, FileKind, ReadRef};
@@ -31,25 +31,19 @@ where
 }
 
 struct ExternalFileOuter<F: FileContents> {
-    file_path: String,
     file_contents: FileContentsWrapper<F>,
     addr2line_context_data: Addr2lineContextData,
 }
 
 impl<F: FileContents> ExternalFileOuter<F> {
-    pub fn new(file_path: &str, file: F) -> Self {
+    pub fn new(file: F) -> Self {
         let file_contents = FileContentsWrapper::new(file);
         Self {
-            file_path: file_path.to_owned(),
             file_contents,
             addr2line_context_data: Addr2lineContextData::new(),
         }
     }
 
-    pub fn file_path(&self) -> &str {
-        &self.file_path
-    }
-
     fn make_member_context(
         &self,
         offset_and_size: (u64, u64),
@@ -222,13 +216,28 @@ impl ExternalFileMemberContext<'_> {
     }
 }
 
-pub struct ExternalFileSymbolMap<F: FileContents + 'static>(
-    Yoke<ExternalFileInnerWrapper<'static>, Box<ExternalFileOuter<F>>>,
-);
+/// Cloning an `ExternalFileSymbolMap` is cheap: the clones share the parsed
+/// debug info.
+pub struct ExternalFileSymbolMap<F: FileContents + 'static> {
+    file_path: Arc<str>,
+    file_len: u64,
+    inner: Arc<Mutex<Yoke<ExternalFileInnerWrapper<'static>, Box<ExternalFileOuter<F>>>>>,
+}
+
+impl<F: FileContents + 'static> Clone for ExternalFileSymbolMap<F> {
+    fn clone(&self) -> Self {
+        Self {
+            file_path: self.file_path.clone(),
+            file_len: self.file_len,
+            inner: self.inner.clone(),
+        }
+    }
+}
 
 impl<F: FileContents + 'static> ExternalFileSymbolMap<F> {
     pub fn new(file_path: &str, file: F) -> Result<Self, Error> {
-        let outer = ExternalFileOuter::new(file_path, file);
+        let outer = ExternalFileOuter::new(file);
+        let file_len = outer.file_contents.len();
         let inner = Yoke::try_attach_to_cart(
             Box::new(outer),
             |outer| -> Result<ExternalFileInnerWrapper<'_>, Error> {
@@ -236,13 +245,22 @@ impl<F: FileContents + 'static> ExternalFileSymbolMap<F> {
                 Ok(ExternalFileInnerWrapper(Box::new(inner)))
             },
         )?;
-        Ok(Self(inner))
+        Ok(Self {
+            file_path: file_path.into(),
+            file_len,
+            inner: Arc::new(Mutex::new(inner)),
+        })
     }
 
     /// The string which identifies this external file. This is usually an absolute
     /// path.
     pub fn file_path(&self) -> &str {
-        self.0.backing_cart().file_path()
+        &self.file_path
+    }
+
+    /// The size of the file, in bytes.
+    pub(crate) fn file_len(&self) -> u64 {
+        self.file_len
     }
 
     /// Look up the debug info for the given [`ExternalFileAddressInFileRef`].
@@ -251,7 +269,9 @@ impl<F: FileContents + 'static> ExternalFileSymbolMap<F> {
         external_file_address: &ExternalFileAddressInFileRef,
         string_interner: &mut SymbolMapStringInterner,
     ) -> Option<Vec<FrameDebugInfo>> {
-        self.0
+        self.inner
+            .lock()
+            .unwrap()
             .get()
             .0
             .lookup(external_file_address, string_interner)
@@ -15,9 +15,10 @@ use yoke_derive::Yokeable;
 
 use crate::error::Error;
 use crate::generation::SymbolMapGeneration;
+use crate::limits::ParseLimits;
 use crate::shared::{
     FileContents, FileContentsCursor, FileContentsWrapper, FrameDebugInfo, FramesLookupResult,
-    LookupAddress, SymbolInfo,
+    LookupAddress, SymbolInfo, SymbolKind,
 };
 use crate::symbol_map::{GetInnerSymbolMap, SymbolMap, SymbolMapTrait};
 use crate::{FileAndPathHelper, SourceFilePath, SourceFilePathHandle, SyncAddressInfo};
@@ -57,9 +58,12 @@ pub struct JitDumpIndex {
 }
 
 impl JitDumpIndex {
+    /// Fails with [`Error::ParseLimitExceeded`] as soon as the file has more
+    /// code load records than `limits` allow.
     pub fn from_reader<R: std::io::Read + std::io::Seek>(
         mut reader: JitDumpReader<R>,
-    ) -> Result<Self, std::io::Error> {
+        limits: &ParseLimits,
+    ) -> Result<Self, Error> {
         let header = reader.header();
         let (debug_id, _code_id_bytes) =
             debug_id_and_code_id_for_jitdump(header.pid, header.timestamp, header.elf_machine_arch);
@@ -70,14 +74,21 @@ impl JitDumpIndex {
         let mut relative_addresses = Vec::new();
         let mut cumulative_address = 0;
         let mut offset_and_len_of_pending_debug_record = None;
-        while let Some(record_header) = reader.next_record_header()? {
+        while let Some(record_header) = reader
+            .next_record_header()
+            .map_err(Error::JitDumpFileReading)?
+        {
             match record_header.record_type {
                 JitDumpRecordType::JIT_CODE_LOAD => {
                     // Read the full record.
-                    let Some(raw_record) = reader.next_record()? else {
+                    let Some(raw_record) =
+                        reader.next_record().map_err(Error::JitDumpFileReading)?
+                    else {
                         break;
                     };
-                    let JitDumpRecord::CodeLoad(record) = raw_record.parse()? else {
+                    let JitDumpRecord::CodeLoad(record) =
+                        raw_record.parse().map_err(Error::JitDumpFileReading)?
+                    else {
                         panic!()
                     };
                     let code_debug_info_record_offset_and_len =
@@ -94,10 +105,14 @@ impl JitDumpIndex {
                         code_bytes_len: record.code_bytes.len() as u64,
                     });
                     relative_addresses.push(relative_address);
+                    limits.check_symbol_count(entries.len())?;
                 }
                 JitDumpRecordType::JIT_CODE_DEBUG_INFO => {
                     let offset = reader.next_record_offset();
-                    if !reader.skip_next_record()? {
+                    if !reader
+                        .skip_next_record()
+                        .map_err(Error::JitDumpFileReading)?
+                    {
                         break;
                     }
                     offset_and_len_of_pending_debug_record =
@@ -105,7 +120,10 @@ impl JitDumpIndex {
                 }
                 _ => {
                     // Skip other record types.
-                    if !reader.skip_next_record()? {
+                    if !reader
+                        .skip_next_record()
+                        .map_err(Error::JitDumpFileReading)?
+                    {
                         break;
                     }
                 }
@@ -168,8 +186,9 @@ pub struct JitDumpIndexEntry {
 pub fn get_symbol_map_for_jitdump<H: FileAndPathHelper>(
     file_contents: FileContentsWrapper<H::F>,
     file_location: H::FL,
+    limits: ParseLimits,
 ) -> Result<SymbolMap<H>, Error> {
-    let outer = JitDumpSymbolMapOuter::new(file_contents)?;
+    let outer = JitDumpSymbolMapOuter::new(file_contents, &limits)?;
     let symbol_map = JitDumpSymbolMap(Yoke::attach_to_cart(Box::new(outer), |outer| {
         outer.make_symbol_map()
     }));
@@ -192,10 +211,10 @@ pub struct JitDumpSymbolMapOuter<T: FileContents> {
 }
 
 impl<T: FileContents> JitDumpSymbolMapOuter<T> {
-    pub fn new(data: FileContentsWrapper<T>) -> Result<Self, Error> {
+    pub fn new(data: FileContentsWrapper<T>, limits: &ParseLimits) -> Result<Self, Error> {
         let cursor = FileContentsCursor::new(&data);
         let reader = JitDumpReader::new(cursor)?;
-        let index = JitDumpIndex::from_reader(reader).map_err(Error::JitDumpFileReading)?;
+        let index = JitDumpIndex::from_reader(reader, limits)?;
         Ok(Self { data, index })
     }
 
@@ -295,6 +314,7 @@ impl<'a, T: FileContents> JitDumpSymbolMapInner<'a, T> {
             address: symbol_address,
             size: Some(self.index.entries[index].code_bytes_len as u32),
             name: name.into(),
+            kind: SymbolKind::Function,
         };
         let Some(debug_info) = cache.get_debug_info(index) else {
             return Some(SyncAddressInfo {
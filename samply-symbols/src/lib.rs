@@ -207,9 +207,11 @@
 //! }
 //! ```
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use binary_image::BinaryImageInner;
+use debugid::DebugId;
 use jitdump::JitDumpIndex;
 use linux_perf_data::jitdump::JitDumpReader;
 use object::read::FileKind;
@@ -219,6 +221,7 @@ pub use samply_object::{debug_id_for_object, relative_address_base};
 use shared::FileContentsCursor;
 pub use {debugid, object};
 
+mod address_kind;
 mod binary_image;
 mod breakpad;
 mod cache;
@@ -232,15 +235,19 @@ mod error;
 mod external_file;
 mod generation;
 mod jitdump;
+mod limits;
 mod macho;
 mod mapped_path;
 mod shared;
 mod source_file_path;
+mod source_path_prefix_map;
+mod symbol_cache;
 mod symbol_map;
 mod symbol_map_object;
 mod symbol_map_string_interner;
 mod windows;
 
+pub use crate::address_kind::AddressKind;
 pub use crate::binary_image::{BinaryImage, CodeByteReadingError};
 pub use crate::breakpad::{
     BreakpadIndex, BreakpadIndexCreator, BreakpadParseError, BreakpadSymindexParseError,
@@ -253,21 +260,33 @@ pub use crate::error::Error;
 pub use crate::external_file::{load_external_file, ExternalFileSymbolMap};
 pub use crate::generation::SymbolMapGeneration;
 pub use crate::jitdump::debug_id_and_code_id_for_jitdump;
-pub use crate::macho::FatArchiveMember;
-pub use crate::mapped_path::MappedPath;
+pub use crate::limits::ParseLimits;
+pub use crate::macho::{DyldCacheImage, FatArchiveMember};
+pub use crate::mapped_path::{MappedPath, MappedPathRules};
 pub use crate::shared::{
-    AddressInfo, CandidatePathInfo, ExternalFileAddressInFileRef, ExternalFileAddressRef,
-    ExternalFileRef, FileAndPathHelper, FileAndPathHelperError, FileAndPathHelperResult,
-    FileContents, FileContentsWrapper, FileLocation, FrameDebugInfo, FramesLookupResult,
-    FunctionNameHandle, FunctionNameIndex, LibraryInfo, LookupAddress, MultiArchDisambiguator,
-    OptionallySendFuture, SymbolInfo, SymbolNameHandle, SymbolNameIndex, SyncAddressInfo,
+    AddressInfo, AddressInfo64, CandidatePathInfo, ExternalFileAddressInFileRef,
+    ExternalFileAddressRef, ExternalFileRef, FileAndPathHelper, FileAndPathHelperError,
+    FileAndPathHelperResult, FileContents, FileContentsWrapper, FileLocation, FrameDebugInfo,
+    FramesLookupResult, FunctionNameHandle, FunctionNameIndex, LibraryInfo, LookupAddress,
+    LookupAddress64, MultiArchDisambiguator, OptionallySendFuture, SymbolInfo, SymbolInfo64,
+    SymbolKind, SymbolNameHandle, SymbolNameIndex, SyncAddressInfo, SyncAddressInfo64,
 };
 pub use crate::source_file_path::{SourceFilePath, SourceFilePathHandle, SourceFilePathIndex};
+pub use crate::source_path_prefix_map::SourcePathPrefixMap;
+pub use crate::symbol_cache::SymbolCache;
 pub use crate::symbol_map::{AccessPatternHint, SymbolMap, SymbolMapTrait};
 pub use crate::symbol_map_string_interner::SymbolMapStringInterner;
 
+/// A symbol map from [`SymbolManager::register_symbol_map`], with its debug file location.
+type RegisteredSymbolMap<FL> = (FL, Arc<dyn SymbolMapTrait + Send + Sync>);
+
 pub struct SymbolManager<H: FileAndPathHelper> {
     helper: Arc<H>,
+    symbol_cache: Option<Arc<SymbolCache<H>>>,
+    parse_limits: ParseLimits,
+    source_path_prefix_map: Option<Arc<SourcePathPrefixMap>>,
+    mapped_path_rules: Option<Arc<MappedPathRules>>,
+    registered_symbol_maps: HashMap<(String, DebugId), RegisteredSymbolMap<H::FL>>,
 }
 
 impl<H, F, FL> SymbolManager<H>
@@ -280,9 +299,104 @@ where
     pub fn with_helper(helper: H) -> Self {
         Self {
             helper: Arc::new(helper),
+            symbol_cache: None,
+            parse_limits: ParseLimits::default(),
+            source_path_prefix_map: None,
+            mapped_path_rules: None,
+            registered_symbol_maps: HashMap::new(),
         }
     }
 
+    /// Keep up to `capacity` of the most recently used symbol maps in memory,
+    /// so that `load_symbol_map` calls for these libraries return right away.
+    ///
+    /// This is useful for long-running processes which symbolicate many
+    /// requests for the same libraries. By default, no symbol maps are kept.
+    ///
+    /// This is a shorthand for [`set_symbol_cache`](Self::set_symbol_cache)
+    /// with a [`SymbolCache`] of its own, and replaces any cache which was set
+    /// before.
+    pub fn set_symbol_map_cache_capacity(&mut self, capacity: usize) {
+        self.symbol_cache = match capacity {
+            0 => None,
+            capacity => Some(Arc::new(SymbolCache::with_max_count(capacity))),
+        };
+    }
+
+    /// Keep parsed symbol maps and external object files in `cache`, which
+    /// can be shared with other `SymbolManager`s. Symbol maps found in the
+    /// cache are used with this manager's helper and settings.
+    pub fn set_symbol_cache(&mut self, cache: Arc<SymbolCache<H>>) {
+        self.symbol_cache = Some(cache);
+    }
+
+    /// Limit the resources which parsing symbol files may use, so that
+    /// malformed files fail to load instead of exhausting memory. By default,
+    /// there are no limits.
+    pub fn set_parse_limits(&mut self, limits: ParseLimits) {
+        self.parse_limits = limits;
+    }
+
+    /// Replace path prefixes in the source file paths of the symbol maps which
+    /// are loaded from now on, e.g. to map paths from a build machine to the
+    /// local checkout. By default, paths are returned as found in the debug info.
+    pub fn set_source_path_prefix_map(&mut self, map: SourcePathPrefixMap) {
+        self.source_path_prefix_map = (!map.is_empty()).then(|| Arc::new(map));
+    }
+
+    /// Add custom rules for creating mapped paths from the raw source file
+    /// paths of the symbol maps which are loaded from now on. These rules are
+    /// applied to the paths from the debug info, before the source path prefix
+    /// map, and take precedence over the built-in rules.
+    pub fn set_mapped_path_rules(&mut self, rules: MappedPathRules) {
+        self.mapped_path_rules = (!rules.is_empty()).then(|| Arc::new(rules));
+    }
+
+    /// Register a symbol map for the library with the given `debug_name` and
+    /// `debug_id`, for example one which reads symbols from a custom format.
+    /// `load_symbol_map` returns this symbol map for the library instead of
+    /// loading symbol files, and `debug_file_location` is used as the symbol
+    /// map's debug file location.
+    ///
+    /// Requests which don't specify a debug name match a registered symbol map
+    /// by its debug ID alone.
+    pub fn register_symbol_map(
+        &mut self,
+        debug_name: &str,
+        debug_id: DebugId,
+        debug_file_location: FL,
+        symbol_map: Arc<dyn SymbolMapTrait + Send + Sync>,
+    ) {
+        self.registered_symbol_maps.insert(
+            (debug_name.to_owned(), debug_id),
+            (debug_file_location, symbol_map),
+        );
+    }
+
+    /// Remove a symbol map which was registered with
+    /// [`register_symbol_map`](Self::register_symbol_map).
+    pub fn unregister_symbol_map(&mut self, debug_name: &str, debug_id: DebugId) {
+        self.registered_symbol_maps
+            .remove(&(debug_name.to_owned(), debug_id));
+    }
+
+    fn get_registered_symbol_map(
+        &self,
+        library_info: &LibraryInfo,
+    ) -> Option<RegisteredSymbolMap<FL>> {
+        let debug_id = library_info.debug_id?;
+        let entry = match &library_info.debug_name {
+            Some(debug_name) => self
+                .registered_symbol_maps
+                .get(&(debug_name.clone(), debug_id))?,
+            None => self
+                .registered_symbol_maps
+                .iter()
+                .find_map(|((_, id), entry)| (*id == debug_id).then_some(entry))?,
+        };
+        Some(entry.clone())
+    }
+
     /// Exposes the helper.
     pub fn helper(&self) -> Arc<H> {
         self.helper.clone()
@@ -311,7 +425,15 @@ where
 
     /// Obtain a symbol map for the library, given the (partial) `LibraryInfo`.
     /// At least the debug_id has to be given.
+    ///
+    /// Symbol maps which were registered with [`register_symbol_map`](Self::register_symbol_map)
+    /// or which are provided by [`FileAndPathHelper::get_symbol_map_for_library`]
+    /// take precedence over symbol files.
     pub async fn load_symbol_map(&self, library_info: &LibraryInfo) -> Result<SymbolMap<H>, Error> {
+        if let Some((fl, symbol_map)) = self.get_registered_symbol_map(library_info) {
+            return Ok(self.apply_settings(SymbolMap::with_symbol_map_trait(fl, symbol_map)));
+        }
+
         if let Some((fl, symbol_map)) = self
             .helper()
             .as_ref()
@@ -325,6 +447,35 @@ where
             None => return Err(Error::NotEnoughInformationToIdentifySymbolMap),
         };
 
+        let symbol_map = match self
+            .symbol_cache
+            .as_ref()
+            .and_then(|cache| cache.get_symbol_map(debug_id))
+        {
+            Some(mut symbol_map) => {
+                symbol_map.replace_helper(self.helper());
+                self.apply_settings(symbol_map)
+            }
+            None => {
+                let (symbol_map, file_size) = self
+                    .load_symbol_map_uncached(library_info, debug_id)
+                    .await?;
+                if let Some(cache) = &self.symbol_cache {
+                    cache.insert_symbol_map(debug_id, symbol_map.clone(), file_size);
+                }
+                symbol_map
+            }
+        };
+        Ok(symbol_map)
+    }
+
+    /// Also returns the size of the files which the symbol map was loaded
+    /// from, if it was loaded from files of its own.
+    async fn load_symbol_map_uncached(
+        &self,
+        library_info: &LibraryInfo,
+        debug_id: DebugId,
+    ) -> Result<(SymbolMap<H>, Option<u64>), Error> {
         let candidate_paths = self
             .helper
             .get_candidate_paths_for_debug_file(library_info)
@@ -336,30 +487,78 @@ where
             })?;
 
         let mut all_errors = Vec::new();
+
+        // A stripped ELF binary with the right debug ID. We only use its symbols
+        // if none of the other candidates is an unstripped file with the same
+        // debug ID, e.g. a .debug file.
+        let mut stripped_elf: Option<(FL, FileContentsWrapper<F>)> = None;
+
         for candidate_info in candidate_paths {
-            let symbol_map = match candidate_info {
+            let (symbol_map, file_size) = match candidate_info {
                 CandidatePathInfo::SingleFile(file_location) => {
-                    self.load_symbol_map_from_location(
-                        file_location,
-                        Some(MultiArchDisambiguator::DebugId(debug_id)),
-                    )
-                    .await
+                    let file_contents = match self.helper.load_file(file_location.clone()).await {
+                        Ok(file_contents) => FileContentsWrapper::new(file_contents),
+                        Err(e) => {
+                            all_errors.push(Error::HelperErrorDuringOpenFile(
+                                file_location.to_string(),
+                                e,
+                            ));
+                            continue;
+                        }
+                    };
+                    let mut file_size = file_contents.len();
+                    let symbol_map =
+                        match elf::matching_elf_has_full_symbols(&file_contents, debug_id) {
+                            Some(false) => {
+                                if stripped_elf.is_none() {
+                                    stripped_elf = Some((file_location, file_contents));
+                                }
+                                continue;
+                            }
+                            Some(true) if stripped_elf.is_some() => {
+                                let (_, layout_file_contents) = stripped_elf.take().unwrap();
+                                file_size += layout_file_contents.len();
+                                elf::load_symbol_map_for_elf_with_layout_file(
+                                    file_location,
+                                    file_contents,
+                                    layout_file_contents,
+                                    self.helper(),
+                                    self.parse_limits,
+                                )
+                                .await
+                                .map(|symbol_map| self.apply_settings(symbol_map))
+                            }
+                            _ => self
+                                .load_symbol_map_from_contents(
+                                    file_location,
+                                    file_contents,
+                                    Some(MultiArchDisambiguator::DebugId(debug_id)),
+                                )
+                                .await
+                                .map(|symbol_map| self.apply_settings(symbol_map)),
+                        };
+                    (symbol_map, Some(file_size))
                 }
                 CandidatePathInfo::InDyldCache {
                     dyld_cache_path,
                     dylib_path,
                 } => {
-                    macho::load_symbol_map_for_dyld_cache(
+                    let symbol_map = macho::load_symbol_map_for_dyld_cache(
                         dyld_cache_path,
                         dylib_path,
                         &*self.helper,
+                        self.parse_limits,
                     )
                     .await
+                    .map(|symbol_map| self.apply_settings(symbol_map));
+                    (symbol_map, None)
                 }
             };
 
             match symbol_map {
-                Ok(symbol_map) if symbol_map.debug_id() == debug_id => return Ok(symbol_map),
+                Ok(symbol_map) if symbol_map.debug_id() == debug_id => {
+                    return Ok((symbol_map, file_size))
+                }
                 Ok(symbol_map) => {
                     all_errors.push(Error::UnmatchedDebugId(symbol_map.debug_id(), debug_id));
                 }
@@ -368,6 +567,23 @@ where
                 }
             }
         }
+
+        if let Some((file_location, file_contents)) = stripped_elf {
+            let file_size = file_contents.len();
+            match self
+                .load_symbol_map_from_contents(
+                    file_location,
+                    file_contents,
+                    Some(MultiArchDisambiguator::DebugId(debug_id)),
+                )
+                .await
+                .map(|symbol_map| self.apply_settings(symbol_map))
+            {
+                Ok(symbol_map) => return Ok((symbol_map, Some(file_size))),
+                Err(e) => all_errors.push(e),
+            }
+        }
+
         let err = match all_errors.len() {
             0 => Error::NoCandidatePathForDebugFile(Box::new(library_info.clone())),
             1 => all_errors.pop().unwrap(),
@@ -395,8 +611,26 @@ where
         let external_file_location = debug_file_location
             .location_for_external_object_file(external_file_path)
             .ok_or(Error::FileLocationRefusedExternalObjectLocation)?;
-        external_file::load_external_file(&*self.helper, external_file_location, external_file_path)
-            .await
+        let Some(cache) = &self.symbol_cache else {
+            return external_file::load_external_file(
+                &*self.helper,
+                external_file_location,
+                external_file_path,
+            )
+            .await;
+        };
+        let key = external_file_location.to_string();
+        if let Some(external_file) = cache.get_external_file(&key) {
+            return Ok(external_file);
+        }
+        let external_file = external_file::load_external_file(
+            &*self.helper,
+            external_file_location,
+            external_file_path,
+        )
+        .await?;
+        cache.insert_external_file(key, external_file.clone());
+        Ok(external_file)
     }
 
     async fn load_binary_from_dyld_cache(
@@ -533,8 +767,10 @@ where
                 dyld_cache_path,
                 dylib_path.to_owned(),
                 &*self.helper,
+                self.parse_limits,
             )
-            .await;
+            .await
+            .map(|symbol_map| self.apply_settings(symbol_map));
             match (&multi_arch_disambiguator, symbol_map_res) {
                 (Some(MultiArchDisambiguator::DebugId(expected_debug_id)), Ok(symbol_map)) => {
                     if &symbol_map.debug_id() == expected_debug_id {
@@ -552,10 +788,64 @@ where
         Err(err.unwrap_or(Error::NoCandidatePathForDyldCache))
     }
 
+    /// Lists the members of the fat archive ("universal binary") at
+    /// `file_location`, with their architecture, UUID and file range.
+    pub async fn list_fat_archive_members(
+        &self,
+        file_location: FL,
+    ) -> Result<Vec<FatArchiveMember>, Error> {
+        let file_contents = self
+            .helper
+            .load_file(file_location.clone())
+            .await
+            .map_err(|e| Error::HelperErrorDuringOpenFile(file_location.to_string(), e))?;
+        let file_contents = FileContentsWrapper::new(file_contents);
+        let file_kind = FileKind::parse(&file_contents)
+            .map_err(|_| Error::InvalidInputError("Unrecognized file"))?;
+        match file_kind {
+            FileKind::MachOFat32 | FileKind::MachOFat64 => {
+                macho::get_fat_archive_members(&file_contents, file_kind)
+            }
+            _ => Err(Error::InvalidInputError("Not a fat archive")),
+        }
+    }
+
+    /// Lists the images in the dyld shared cache at `dyld_cache_path`, with
+    /// their path, architecture, UUID and location in the cache files. The
+    /// subcache files are found next to the main cache file.
+    pub async fn list_dyld_cache_images(
+        &self,
+        dyld_cache_path: FL,
+    ) -> Result<Vec<DyldCacheImage>, Error> {
+        macho::list_dyld_cache_images(dyld_cache_path, &*self.helper).await
+    }
+
     pub async fn load_symbol_map_from_location(
         &self,
         file_location: FL,
         multi_arch_disambiguator: Option<MultiArchDisambiguator>,
+    ) -> Result<SymbolMap<H>, Error> {
+        let symbol_map = self
+            .load_symbol_map_from_location_without_settings(file_location, multi_arch_disambiguator)
+            .await?;
+        Ok(self.apply_settings(symbol_map))
+    }
+
+    /// Makes the lookups of a freshly loaded symbol map respect the inline
+    /// depth limit and the source path settings. The other parse limits are
+    /// enforced by the parsers.
+    fn apply_settings(&self, mut symbol_map: SymbolMap<H>) -> SymbolMap<H> {
+        symbol_map.set_max_inline_depth(self.parse_limits.max_inline_depth);
+        symbol_map.set_source_path_prefix_map(self.source_path_prefix_map.clone());
+        symbol_map.set_mapped_path_rules(self.mapped_path_rules.clone());
+        symbol_map.set_symbol_cache(self.symbol_cache.as_ref().map(Arc::downgrade));
+        symbol_map
+    }
+
+    async fn load_symbol_map_from_location_without_settings(
+        &self,
+        file_location: FL,
+        multi_arch_disambiguator: Option<MultiArchDisambiguator>,
     ) -> Result<SymbolMap<H>, Error> {
         let file_contents = self
             .helper
@@ -564,7 +854,16 @@ where
             .map_err(|e| Error::HelperErrorDuringOpenFile(file_location.to_string(), e))?;
 
         let file_contents = FileContentsWrapper::new(file_contents);
+        self.load_symbol_map_from_contents(file_location, file_contents, multi_arch_disambiguator)
+            .await
+    }
 
+    async fn load_symbol_map_from_contents(
+        &self,
+        file_location: FL,
+        file_contents: FileContentsWrapper<F>,
+        multi_arch_disambiguator: Option<MultiArchDisambiguator>,
+    ) -> Result<SymbolMap<H>, Error> {
         if let Ok(file_kind) = FileKind::parse(&file_contents) {
             match file_kind {
                 FileKind::Elf32 | FileKind::Elf64 => {
@@ -573,6 +872,7 @@ where
                         file_contents,
                         file_kind,
                         self.helper(),
+                        self.parse_limits,
                     )
                     .await
                 }
@@ -587,10 +887,27 @@ where
                         file_contents,
                         member,
                         self.helper(),
+                        self.parse_limits,
                     )
                 }
-                FileKind::MachO32 | FileKind::MachO64 => {
-                    macho::get_symbol_map_for_macho(file_location, file_contents, self.helper())
+                FileKind::MachO32 | FileKind::MachO64 => macho::get_symbol_map_for_macho(
+                    file_location,
+                    file_contents,
+                    self.helper(),
+                    self.parse_limits,
+                ),
+                FileKind::Pe32 | FileKind::Pe64
+                    if windows::pe_has_dwarf_debug_info(&file_contents, file_kind) =>
+                {
+                    // MinGW binaries have their DWARF debug info inside the PE
+                    // and no PDB file, so there's no point in looking for one.
+                    windows::get_symbol_map_for_pe(
+                        file_contents,
+                        file_kind,
+                        file_location,
+                        self.helper(),
+                        self.parse_limits,
+                    )
                 }
                 FileKind::Pe32 | FileKind::Pe64 => {
                     match windows::load_symbol_map_for_pdb_corresponding_to_binary(
@@ -598,15 +915,18 @@ where
                         &file_contents,
                         file_location.clone(),
                         &*self.helper,
+                        self.parse_limits,
                     )
                     .await
                     {
                         Ok(symbol_map) => Ok(symbol_map),
+                        Err(e @ Error::ParseLimitExceeded(..)) => Err(e),
                         Err(_) => windows::get_symbol_map_for_pe(
                             file_contents,
                             file_kind,
                             file_location,
                             self.helper(),
+                            self.parse_limits,
                         ),
                     }
                 }
@@ -615,7 +935,7 @@ where
                 )),
             }
         } else if windows::is_pdb_file(&file_contents) {
-            windows::get_symbol_map_for_pdb(file_contents, file_location)
+            windows::get_symbol_map_for_pdb(file_contents, file_location, self.parse_limits)
         } else if breakpad::is_breakpad_file(&file_contents) {
             let index_file_contents =
                 if let Some(index_file_location) = file_location.location_for_breakpad_symindex() {
@@ -627,11 +947,14 @@ where
                 } else {
                     None
                 };
-            let symbol_map =
-                breakpad::get_symbol_map_for_breakpad_sym(file_contents, index_file_contents)?;
+            let symbol_map = breakpad::get_symbol_map_for_breakpad_sym(
+                file_contents,
+                index_file_contents,
+                self.parse_limits,
+            )?;
             Ok(SymbolMap::new_plain(file_location, Box::new(symbol_map)))
         } else if jitdump::is_jitdump_file(&file_contents) {
-            jitdump::get_symbol_map_for_jitdump(file_contents, file_location)
+            jitdump::get_symbol_map_for_jitdump(file_contents, file_location, self.parse_limits)
         } else {
             Err(Error::InvalidInputError(
             "The file does not have a known format; PDB::open was not able to parse it and object::FileKind::parse was not able to detect the format.",
@@ -659,7 +982,7 @@ where
             Err(_) if jitdump::is_jitdump_file(&file_contents) => {
                 let cursor = FileContentsCursor::new(&file_contents);
                 let reader = JitDumpReader::new(cursor)?;
-                let index = JitDumpIndex::from_reader(reader).map_err(Error::JitDumpFileReading)?;
+                let index = JitDumpIndex::from_reader(reader, &self.parse_limits)?;
                 let inner = BinaryImageInner::JitDump(file_contents, index);
                 return BinaryImage::new(inner, name, path);
             }
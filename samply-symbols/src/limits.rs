@@ -0,0 +1,102 @@
+use crate::{Error, FrameDebugInfo};
+
+/// Limits for the resources which parsing a symbol file may use.
+///
+/// Malformed files can declare absurd sizes and counts, for example a
+/// compressed DWARF section which claims to decompress to terabytes. With
+/// limits, such files make loading the symbol map fail with
+/// [`Error::ParseLimitExceeded`] instead of allocating unbounded memory. This
+/// is important for servers which symbolicate files from untrusted sources.
+///
+/// The default has no limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// The maximum number of symbols in a symbol map. Symbols are counted
+    /// while the symbol table is read, and loading fails as soon as there are
+    /// more.
+    pub max_symbol_count: Option<usize>,
+    /// The maximum size of a single section or stream, in bytes, after
+    /// decompression. This applies to DWARF sections, to the compressed
+    /// `.gnu_debugdata` object in ELF files, and to PDB streams.
+    pub max_section_size: Option<u64>,
+    /// The maximum number of frames which a lookup returns for an address,
+    /// including inlined frames. Deeper inline stacks are cut off: the
+    /// innermost frames and the outermost function are kept.
+    pub max_inline_depth: Option<usize>,
+}
+
+impl ParseLimits {
+    pub(crate) fn check_symbol_count(&self, count: usize) -> Result<(), Error> {
+        match self.max_symbol_count {
+            Some(max) if count > max => Err(Error::ParseLimitExceeded(
+                "Symbol count",
+                count as u64,
+                max as u64,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_section_size(&self, size: u64) -> Result<(), Error> {
+        match self.max_section_size {
+            Some(max) if size > max => Err(Error::ParseLimitExceeded("Section size", size, max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Cuts off the inline stack `frames`, which starts with the innermost
+/// frame, at `max_depth` frames, keeping the outermost function.
+pub(crate) fn truncate_frames(frames: &mut Vec<FrameDebugInfo>, max_depth: usize) {
+    if frames.len() <= max_depth {
+        return;
+    }
+    let outer = frames.pop();
+    frames.truncate(max_depth.saturating_sub(1));
+    if max_depth != 0 {
+        frames.extend(outer);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frames(lines: &[u32]) -> Vec<FrameDebugInfo> {
+        lines
+            .iter()
+            .map(|line| FrameDebugInfo {
+                function: None,
+                file_path: None,
+                line_number: Some(*line),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn truncates_inline_stacks() {
+        let mut f = frames(&[1, 2, 3, 4]);
+        truncate_frames(&mut f, 3);
+        assert_eq!(f, frames(&[1, 2, 4]));
+        truncate_frames(&mut f, 3);
+        assert_eq!(f, frames(&[1, 2, 4]));
+        truncate_frames(&mut f, 1);
+        assert_eq!(f, frames(&[4]));
+    }
+
+    #[test]
+    fn checks_limits() {
+        let limits = ParseLimits {
+            max_symbol_count: Some(10),
+            max_section_size: Some(1000),
+            max_inline_depth: None,
+        };
+        assert!(limits.check_symbol_count(10).is_ok());
+        assert!(matches!(
+            limits.check_symbol_count(11),
+            Err(Error::ParseLimitExceeded("Symbol count", 11, 10))
+        ));
+        assert!(limits.check_section_size(1001).is_err());
+        assert!(ParseLimits::default().check_section_size(u64::MAX).is_ok());
+    }
+}
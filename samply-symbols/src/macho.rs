@@ -5,11 +5,11 @@ use debugid::DebugId;
 use macho_unwind_info::UnwindInfo;
 use object::macho::{self, LinkeditDataCommand, MachHeader32, MachHeader64};
 use object::read::macho::{
-    FatArch, LoadCommandIterator, MachHeader, MachOFatFile32, MachOFatFile64,
+    DyldCache, FatArch, LoadCommandIterator, MachHeader, MachOFatFile32, MachOFatFile64, Nlist,
 };
 use object::read::{File, Object, ObjectSection};
 use object::{Endianness, FileKind, ReadRef};
-use samply_object::debug_id_for_object;
+use samply_object::{debug_id_for_object, relative_address_base};
 use uuid::Uuid;
 use yoke::Yoke;
 use yoke_derive::Yokeable;
@@ -17,6 +17,7 @@ use yoke_derive::Yokeable;
 use crate::binary_image::{BinaryImage, BinaryImageInner};
 use crate::dwarf::Addr2lineContextData;
 use crate::error::Error;
+use crate::limits::ParseLimits;
 use crate::shared::{
     FileAndPathHelper, FileContents, FileContentsWrapper, FileLocation, MultiArchDisambiguator,
     RangeReadRef,
@@ -130,6 +131,11 @@ pub struct FatArchiveMember {
 }
 
 impl FatArchiveMember {
+    /// The debug ID of the member, which is derived from its mach-O UUID.
+    pub fn debug_id(&self) -> Option<DebugId> {
+        self.uuid.map(DebugId::from_uuid)
+    }
+
     /// Returns `None` if it doesn't match.
     /// Returns `Some(_)` if there is a match, and lower values are better.
     pub fn match_score_for_disambiguator(
@@ -257,35 +263,77 @@ where
         let suffix = format!(".{subcache_index}");
         let suffix2 = format!(".{subcache_index:02}");
         let subcache = match dcl.load_subcache(&suffix).await {
-            Ok(subcache) => subcache,
+            Ok(subcache) => (suffix, subcache),
             Err(_) => match dcl.load_subcache(&suffix2).await {
-                Ok(subcache) => subcache,
+                Ok(subcache) => (suffix2, subcache),
                 Err(_) => break,
             },
         };
-        subcache_contents.push(FileContentsWrapper::new(subcache));
+        subcache_contents.push(subcache);
     }
     if let Ok(subcache) = dcl.load_subcache(".symbols").await {
-        subcache_contents.push(FileContentsWrapper::new(subcache));
+        subcache_contents.push((".symbols".to_string(), subcache));
     };
+    let (subcache_suffixes, subcache_contents) = subcache_contents
+        .into_iter()
+        .map(|(suffix, subcache)| (suffix, FileContentsWrapper::new(subcache)))
+        .unzip();
 
     Ok(DyldCacheFileData::new(
         root_contents,
         subcache_contents,
+        subcache_suffixes,
         dylib_path,
     ))
 }
 
+/// An image in a dyld shared cache.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DyldCacheImage {
+    /// The path of the dylib, e.g. "/usr/lib/libSystem.B.dylib".
+    pub path: String,
+    /// The address of the image's mach-O header, before the cache is slid.
+    pub address: u64,
+    /// The suffix of the cache file which contains the image's mach-O header,
+    /// e.g. ".01", or the empty string for the main cache file.
+    pub subcache_suffix: String,
+    /// The offset of the image's mach-O header in that file.
+    pub file_offset: u64,
+    pub arch: Option<String>,
+    pub uuid: Option<Uuid>,
+}
+
+impl DyldCacheImage {
+    /// The debug ID of the image, which is derived from its mach-O UUID.
+    pub fn debug_id(&self) -> Option<DebugId> {
+        self.uuid.map(DebugId::from_uuid)
+    }
+}
+
+/// Lists the images in the dyld shared cache at `dyld_cache_path`, in the
+/// order of the cache's image list.
+pub async fn list_dyld_cache_images<H>(
+    dyld_cache_path: H::FL,
+    helper: &H,
+) -> Result<Vec<DyldCacheImage>, Error>
+where
+    H: FileAndPathHelper,
+{
+    let file_data = load_file_data_for_dyld_cache(dyld_cache_path, String::new(), helper).await?;
+    file_data.images()
+}
+
 pub async fn load_symbol_map_for_dyld_cache<H>(
     dyld_cache_path: H::FL,
     dylib_path: String,
     helper: &H,
+    limits: ParseLimits,
 ) -> Result<SymbolMap<H>, Error>
 where
     H: FileAndPathHelper,
 {
     let owner = load_file_data_for_dyld_cache(dyld_cache_path.clone(), dylib_path, helper).await?;
-    let owner = FileDataAndObject::new(Box::new(owner))?;
+    let owner = FileDataAndObject::new(Box::new(owner), limits)?;
     let symbol_map = ObjectSymbolMap::new(owner)?;
     Ok(SymbolMap::new_plain(dyld_cache_path, Box::new(symbol_map)))
 }
@@ -296,6 +344,8 @@ where
 {
     root_file_data: FileContentsWrapper<T>,
     subcache_file_data: Vec<FileContentsWrapper<T>>,
+    /// The file name suffix of each subcache, e.g. ".01" or ".symbols".
+    subcache_suffixes: Vec<String>,
     dylib_path: String,
 }
 
@@ -320,6 +370,12 @@ impl<'data, T: FileContents + 'static> ObjectAndMachOData<'data, T> {
         }
     }
 
+    /// Makes DWARF sections which exceed the limits fail the symbol map creation.
+    pub fn with_parse_limits(mut self, limits: ParseLimits) -> Self {
+        self.addr2line_context = Addr2lineContextData::with_parse_limits(limits);
+        self
+    }
+
     pub fn into_parts(
         self,
     ) -> (
@@ -339,27 +395,63 @@ impl<T: FileContents + 'static> DyldCacheFileData<T> {
     pub fn new(
         root_file_data: FileContentsWrapper<T>,
         subcache_file_data: Vec<FileContentsWrapper<T>>,
+        subcache_suffixes: Vec<String>,
         dylib_path: String,
     ) -> Self {
         Self {
             root_file_data,
             subcache_file_data,
+            subcache_suffixes,
             dylib_path,
         }
     }
 
-    pub fn make_object(&self) -> Result<ObjectAndMachOData<'_, T>, Error> {
+    fn parse_cache(&self) -> Result<DyldCache<'_, Endianness, FileContentsRange<'_, T>>, Error> {
         let rootcache_range = self.root_file_data.full_range();
         let subcache_ranges: Vec<_> = self
             .subcache_file_data
             .iter()
             .map(FileContentsWrapper::full_range)
             .collect();
-        let cache = object::read::macho::DyldCache::<Endianness, _>::parse(
-            rootcache_range,
-            &subcache_ranges,
-        )
-        .map_err(Error::DyldCacheParseError)?;
+        DyldCache::parse(rootcache_range, &subcache_ranges).map_err(Error::DyldCacheParseError)
+    }
+
+    pub fn images(&self) -> Result<Vec<DyldCacheImage>, Error> {
+        let cache = self.parse_cache()?;
+        let endian = cache.endianness();
+        let mut images = Vec::new();
+        for image in cache.images() {
+            let path = image.path().map_err(Error::DyldCacheParseError)?;
+            let (data, header_offset) = image
+                .image_data_and_offset()
+                .map_err(Error::MachOHeaderParseError)?;
+            let subcache_suffix = self
+                .subcache_file_data
+                .iter()
+                .position(|subcache| std::ptr::eq(subcache, data.original_readref()))
+                .map(|index| self.subcache_suffixes[index].clone())
+                .unwrap_or_default();
+            let uuid = image
+                .parse_object()
+                .ok()
+                .and_then(|object| object.mach_uuid().ok().flatten())
+                .map(Uuid::from_bytes);
+            let is_64 = cache.architecture().address_size() == Some(object::AddressSize::U64);
+            let arch = MachOData::new(data, header_offset, is_64).get_arch();
+            images.push(DyldCacheImage {
+                path: path.to_owned(),
+                address: image.info().address.get(endian),
+                subcache_suffix,
+                file_offset: data.range_start() + header_offset,
+                arch: arch.map(ToString::to_string),
+                uuid,
+            });
+        }
+        Ok(images)
+    }
+
+    pub fn make_object(&self) -> Result<ObjectAndMachOData<'_, T>, Error> {
+        let cache = self.parse_cache()?;
 
         let image = match cache
             .images()
@@ -391,8 +483,14 @@ struct FileDataAndObject<T: FileContents + 'static>(
 );
 
 impl<T: FileContents + 'static> FileDataAndObject<T> {
-    pub fn new(data: Box<dyn MakeMachObject<T> + Send + Sync>) -> Result<Self, Error> {
-        let owner_and_object = Yoke::try_attach_to_cart(data, |data| data.make_dependent_object())?;
+    pub fn new(
+        data: Box<dyn MakeMachObject<T> + Send + Sync>,
+        limits: ParseLimits,
+    ) -> Result<Self, Error> {
+        let owner_and_object = Yoke::try_attach_to_cart(data, |data| {
+            data.make_dependent_object()
+                .map(|object| object.with_parse_limits(limits))
+        })?;
         Ok(Self(owner_and_object))
     }
 }
@@ -407,17 +505,22 @@ impl<T: FileContents + 'static> ObjectSymbolMapOuter<T> for FileDataAndObject<T>
         let (function_starts, function_ends) = compute_function_addresses_macho(macho_data, object);
         let debug_id = debug_id_for_object(object)
             .ok_or(Error::InvalidInputError("debug ID cannot be read"))?;
+        let context = match addr2line_context.make_context(macho_data.data, object, None, None) {
+            Ok(context) => Some(context),
+            Err(e @ Error::ParseLimitExceeded(..)) => return Err(e),
+            Err(_) => None,
+        };
         let symbol_map = ObjectSymbolMapInnerWrapper::new(
             object,
-            addr2line_context
-                .make_context(macho_data.data, object, None, None)
-                .ok(),
+            context,
             None,
             debug_id,
             function_starts.as_deref(),
             function_ends.as_deref(),
+            None,
             &(),
-        );
+            &addr2line_context.parse_limits(),
+        )?;
 
         Ok(symbol_map)
     }
@@ -427,8 +530,9 @@ pub fn get_symbol_map_for_macho<H: FileAndPathHelper>(
     debug_file_location: H::FL,
     file_contents: FileContentsWrapper<H::F>,
     helper: Arc<H>,
+    limits: ParseLimits,
 ) -> Result<SymbolMap<H>, Error> {
-    let owner = FileDataAndObject::new(Box::new(MachSymbolMapData(file_contents)))?;
+    let owner = FileDataAndObject::new(Box::new(MachSymbolMapData(file_contents)), limits)?;
     let symbol_map = ObjectSymbolMap::new(owner)?;
     Ok(SymbolMap::new_with_external_file_support(
         debug_file_location,
@@ -442,11 +546,12 @@ pub fn get_symbol_map_for_fat_archive_member<H: FileAndPathHelper>(
     file_contents: FileContentsWrapper<H::F>,
     member: FatArchiveMember,
     helper: Arc<H>,
+    limits: ParseLimits,
 ) -> Result<SymbolMap<H>, Error> {
     let (start_offset, range_size) = member.offset_and_size;
     let owner =
         MachOFatArchiveMemberData::new(file_contents, start_offset, range_size, member.arch);
-    let owner = FileDataAndObject::new(Box::new(owner))?;
+    let owner = FileDataAndObject::new(Box::new(owner), limits)?;
     let symbol_map = ObjectSymbolMap::new(owner)?;
     Ok(SymbolMap::new_with_external_file_support(
         debug_file_location,
@@ -558,7 +663,25 @@ where
         }
     }
 
-    (function_starts, None)
+    // Get function end addresses from the N_FUN stabs entries, which the
+    // linker writes for functions with debug info. Without them, symbols on
+    // mach-O don't have a size and would extend to the next known function.
+    let base_address = relative_address_base(object_file);
+    let function_ends = macho_data
+        .get_stabs_function_ranges()
+        .ok()
+        .map(|ranges| {
+            ranges
+                .into_iter()
+                .filter_map(|(start, size)| {
+                    let end = start.checked_add(size)?.checked_sub(base_address)?;
+                    u32::try_from(end).ok()
+                })
+                .collect::<Vec<u32>>()
+        })
+        .filter(|function_ends| !function_ends.is_empty());
+
+    (function_starts, function_ends)
 }
 
 #[derive(Clone, Copy)]
@@ -613,6 +736,49 @@ impl<'data, R: ReadRef<'data>> MachOData<'data, R> {
         Ok(Some(function_starts))
     }
 
+    /// Read the (start address, size) pairs of the functions from the N_FUN
+    /// stabs entries in the symbol table. Each function has two N_FUN entries:
+    /// one with the function name and the start address, and one without a
+    /// section whose value is the size of the function.
+    pub fn get_stabs_function_ranges(&self) -> Result<Vec<(u64, u64)>, Error> {
+        // The symbol table offsets of images in the dyld shared cache don't
+        // refer to the file which contains the mach-O header. These images
+        // don't have stabs entries anyway.
+        if self.header_offset != 0 {
+            return Ok(Vec::new());
+        }
+        let ranges = if self.is_64 {
+            self.stabs_function_ranges_impl::<MachHeader64<Endianness>>()
+        } else {
+            self.stabs_function_ranges_impl::<MachHeader32<Endianness>>()
+        };
+        ranges.map_err(Error::MachOHeaderParseError)
+    }
+
+    fn stabs_function_ranges_impl<M: MachHeader>(&self) -> object::read::Result<Vec<(u64, u64)>> {
+        let (endian, mut commands) = self.load_command_iter::<M>()?;
+        let mut ranges = Vec::new();
+        while let Some(command) = commands.next()? {
+            let Some(symtab) = command.symtab()? else {
+                continue;
+            };
+            let symbols = symtab.symbols::<M, R>(endian, self.data)?;
+            let mut function_start = None;
+            for nlist in symbols.iter() {
+                if nlist.n_type() != macho::N_FUN {
+                    continue;
+                }
+                let value: u64 = nlist.n_value(endian).into();
+                if nlist.n_sect() != macho::NO_SECT {
+                    function_start = Some(value);
+                } else if let Some(start) = function_start.take() {
+                    ranges.push((start, value));
+                }
+            }
+        }
+        Ok(ranges)
+    }
+
     pub fn get_arch(&self) -> Option<&'static str> {
         if self.is_64 {
             self.get_arch_impl::<MachHeader64<Endianness>>()
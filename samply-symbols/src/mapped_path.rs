@@ -139,6 +139,132 @@ impl<'a> MappedPath<'a> {
     }
 }
 
+/// User-defined rules which map raw source file paths to mapped paths, in
+/// addition to the built-in rules for the Rust standard library and for
+/// cargo dependencies.
+///
+/// Each rule consists of a path prefix and a template. The template is a
+/// "special path" string (see [`MappedPath`]) in which `{path}` is replaced
+/// with the rest of the raw path after the prefix. A `*` in the prefix matches
+/// any part of a single path component. For example, this rule maps files in
+/// a Bazel execroot to a git repository:
+///
+/// ```
+/// # use samply_symbols::MappedPathRules;
+/// let mut rules = MappedPathRules::new();
+/// rules
+///     .add_rule(
+///         "/home/*/.cache/bazel/_bazel_*/*/execroot/_main/",
+///         "git:github.com/example/monorepo:{path}:main",
+///     )
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MappedPathRules {
+    rules: Vec<(String, String)>,
+}
+
+impl MappedPathRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule. Rules are tried in the order in which they were added,
+    /// and only the first matching rule is applied.
+    ///
+    /// Fails if the template doesn't contain `{path}` or isn't a valid special
+    /// path string.
+    pub fn add_rule(
+        &mut self,
+        prefix: impl Into<String>,
+        template: impl Into<String>,
+    ) -> Result<(), crate::Error> {
+        let template = template.into();
+        if !template.contains("{path}") {
+            return Err(crate::Error::InvalidInputError(
+                "The mapped path template doesn't contain {path}",
+            ));
+        }
+        if MappedPath::from_special_path_str(&template.replace("{path}", "file")).is_none() {
+            return Err(crate::Error::InvalidInputError(
+                "The mapped path template is not a valid special path string",
+            ));
+        }
+        self.rules.push((prefix.into(), template));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns the special path string from the first rule which matches
+    /// `raw_path`.
+    pub fn map_raw_path(&self, raw_path: &str) -> Option<String> {
+        self.rules.iter().find_map(|(prefix, template)| {
+            let rest = strip_path_prefix_pattern(raw_path, prefix)?;
+            let path = rest.trim_start_matches(['/', '\\']).replace('\\', "/");
+            Some(template.replace("{path}", &path))
+        })
+    }
+
+    pub(crate) fn map_source_file_path<'a>(
+        &self,
+        path: crate::SourceFilePath<'a>,
+    ) -> crate::SourceFilePath<'a> {
+        match path {
+            crate::SourceFilePath::RawPath(raw) => match self.map_raw_path(&raw) {
+                Some(special_path) => {
+                    crate::SourceFilePath::RawPathAndSpecialPathStr(raw, special_path.into())
+                }
+                None => crate::SourceFilePath::RawPath(raw),
+            },
+            path => path,
+        }
+    }
+}
+
+/// Strips `pattern` from the start of `path`, where a `*` in the pattern
+/// matches any run of characters other than path separators. The pattern
+/// is matched against whole path components.
+fn strip_path_prefix_pattern<'a>(path: &'a str, pattern: &str) -> Option<&'a str> {
+    let is_separator = |c: char| c == '/' || c == '\\';
+    let pattern = pattern.trim_end_matches(is_separator);
+    let mut rest = path;
+    for (i, pattern_component) in pattern.split(is_separator).enumerate() {
+        if i != 0 {
+            rest = rest.strip_prefix(is_separator)?;
+        }
+        let component_len = rest.find(is_separator).unwrap_or(rest.len());
+        if !component_matches(&rest[..component_len], pattern_component) {
+            return None;
+        }
+        rest = &rest[component_len..];
+    }
+    Some(rest)
+}
+
+/// Matches a single path component against a pattern in which `*` matches
+/// any run of characters.
+fn component_matches(component: &str, pattern: &str) -> bool {
+    let mut pieces = pattern.split('*');
+    let Some(rest) = component.strip_prefix(pieces.next().unwrap_or("")) else {
+        return false;
+    };
+    let mut rest = rest;
+    let mut pieces = pieces.peekable();
+    while let Some(piece) = pieces.next() {
+        if pieces.peek().is_none() {
+            return rest.ends_with(piece);
+        }
+        match rest.find(piece) {
+            Some(pos) => rest = &rest[pos + piece.len()..],
+            None => return false,
+        }
+    }
+    rest.is_empty()
+}
+
 fn parse_git_path(input: &str) -> Option<MappedPath<'_>> {
     let input = input.strip_prefix("git:")?;
     let (repo, input) = input.split_once(':')?;
@@ -328,6 +454,43 @@ fn parse_gitiles_url(input: &str) -> Option<MappedPath<'_>> {
 mod test {
     use super::*;
 
+    #[test]
+    fn custom_rules() {
+        let mut rules = MappedPathRules::new();
+        rules
+            .add_rule(
+                "/home/*/.cache/bazel/_bazel_*/*/execroot/_main",
+                "git:github.com/example/monorepo:{path}:main",
+            )
+            .unwrap();
+        rules
+            .add_rule(r"C:\build\", "hg:hg.example.com/repo:{path}:abc123")
+            .unwrap();
+        assert!(rules.add_rule("/build", "git:github.com/a/b").is_err());
+        assert!(rules.add_rule("/build", "{path}").is_err());
+
+        assert_eq!(
+            rules.map_raw_path(
+                "/home/me/.cache/bazel/_bazel_me/0123abcd/execroot/_main/src/lib/util.cc"
+            ),
+            Some("git:github.com/example/monorepo:src/lib/util.cc:main".to_string())
+        );
+        assert_eq!(
+            rules.map_raw_path(
+                "/home/me/.cache/bazel/_bazel_me/0123abcd/execroot/_main_other/util.cc"
+            ),
+            None
+        );
+        assert_eq!(
+            rules.map_raw_path("/home/me/.cache/bazel/_bazel_me/a/b/execroot/_main/util.cc"),
+            None
+        );
+        assert_eq!(
+            rules.map_raw_path(r"C:\build\src\main.cpp"),
+            Some("hg:hg.example.com/repo:src/main.cpp:abc123".to_string())
+        );
+    }
+
     #[test]
     fn parse_hg_paths() {
         assert_eq!(
@@ -108,6 +108,46 @@ pub enum LookupAddress {
     FileOffset(u64),
 }
 
+/// Like [`LookupAddress`], but with a `u64` relative address.
+///
+/// Relative addresses in modules which are larger than 4 GiB don't fit into a
+/// `u32`. Use this type with [`SymbolMap::lookup_u64`](crate::SymbolMap::lookup_u64)
+/// and the other `_u64` methods for symbol maps which report
+/// [`has_u64_relative_addresses`](crate::SymbolMapTrait::has_u64_relative_addresses).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LookupAddress64 {
+    /// A relative address, see [`LookupAddress::Relative`].
+    Relative(u64),
+    /// A "stated virtual memory address", see [`LookupAddress::Svma`].
+    Svma(u64),
+    /// A raw file offset, see [`LookupAddress::FileOffset`].
+    FileOffset(u64),
+}
+
+impl LookupAddress64 {
+    /// Converts this address into a [`LookupAddress`], if the relative address
+    /// fits into a `u32`.
+    pub fn to_lookup_address(self) -> Option<LookupAddress> {
+        match self {
+            LookupAddress64::Relative(address) => {
+                u32::try_from(address).ok().map(LookupAddress::Relative)
+            }
+            LookupAddress64::Svma(svma) => Some(LookupAddress::Svma(svma)),
+            LookupAddress64::FileOffset(offset) => Some(LookupAddress::FileOffset(offset)),
+        }
+    }
+}
+
+impl From<LookupAddress> for LookupAddress64 {
+    fn from(address: LookupAddress) -> Self {
+        match address {
+            LookupAddress::Relative(address) => LookupAddress64::Relative(address.into()),
+            LookupAddress::Svma(svma) => LookupAddress64::Svma(svma),
+            LookupAddress::FileOffset(offset) => LookupAddress64::FileOffset(offset),
+        }
+    }
+}
+
 /// In case the loaded binary contains multiple architectures, this specifies
 /// how to resolve the ambiguity. This is only needed on macOS.
 #[derive(Debug, Clone)]
@@ -154,6 +194,9 @@ pub struct LibraryInfo {
     pub code_id: Option<CodeId>,
     pub path: Option<String>,
     pub arch: Option<String>,
+    /// The GNU build ID of a PE binary, if it has one. For ELF binaries, the
+    /// build ID is in `code_id` instead.
+    pub gnu_build_id: Option<ElfBuildId>,
 }
 
 impl LibraryInfo {
@@ -184,6 +227,18 @@ impl LibraryInfo {
         if self.arch.is_none() && other.arch.is_some() {
             self.arch.clone_from(&other.arch);
         }
+        if self.gnu_build_id.is_none() && other.gnu_build_id.is_some() {
+            self.gnu_build_id.clone_from(&other.gnu_build_id);
+        }
+    }
+
+    /// The ELF-style build ID of this library: the code ID of an ELF binary,
+    /// or the GNU build ID of a PE binary.
+    pub fn elf_build_id(&self) -> Option<&ElfBuildId> {
+        match &self.code_id {
+            Some(CodeId::ElfBuildId(build_id)) => Some(build_id),
+            _ => self.gnu_build_id.as_ref(),
+        }
     }
 }
 
@@ -416,6 +471,28 @@ impl SymbolMapGeneration {
     }
 }
 
+/// What a symbol describes. Most symbols are functions, but lookups can also
+/// hit other things, which consumers may want to filter out or label.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SymbolKind {
+    /// A function.
+    #[default]
+    Function,
+    /// A data object inside a code section, for example a jump table or a
+    /// literal pool (ELF `STT_OBJECT`).
+    DataObject,
+    /// A stub which jumps to a function in a different library, in the ELF
+    /// `.plt` sections or in the mach-O `__stubs` sections.
+    PltStub,
+    /// A thunk which the Windows linker generates for an imported function
+    /// or for incremental linking, from the `S_THUNK32` records in the PDB.
+    ImportThunk,
+    /// A placeholder for a function which has no symbol. Its start address
+    /// is known from unwind info (`.pdata` or `.eh_frame`) or from the entry
+    /// point, and its name is made up.
+    SectionPlaceholder,
+}
+
 /// The symbol for a function.
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct SymbolInfo {
@@ -425,6 +502,45 @@ pub struct SymbolInfo {
     pub size: Option<u32>,
     /// The function name, demangled.
     pub name: SymbolNameHandle,
+    /// What the symbol describes.
+    pub kind: SymbolKind,
+}
+
+/// Like [`SymbolInfo`], but with a `u64` relative address and size.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct SymbolInfo64 {
+    /// The function's address. This is a relative address.
+    pub address: u64,
+    /// The function size, in bytes. May have been approximated from neighboring symbols.
+    pub size: Option<u64>,
+    /// The function name, demangled.
+    pub name: SymbolNameHandle,
+    /// What the symbol describes.
+    pub kind: SymbolKind,
+}
+
+impl From<SymbolInfo> for SymbolInfo64 {
+    fn from(symbol: SymbolInfo) -> Self {
+        Self {
+            address: symbol.address.into(),
+            size: symbol.size.map(Into::into),
+            name: symbol.name,
+            kind: symbol.kind,
+        }
+    }
+}
+
+impl SymbolInfo64 {
+    /// Converts this into a [`SymbolInfo`], if the address fits into a `u32`.
+    /// Sizes which don't fit are dropped.
+    pub fn to_symbol_info(&self) -> Option<SymbolInfo> {
+        Some(SymbolInfo {
+            address: u32::try_from(self.address).ok()?,
+            size: self.size.and_then(|size| u32::try_from(size).ok()),
+            name: self.name,
+            kind: self.kind,
+        })
+    }
 }
 
 /// The lookup result for an address.
@@ -444,6 +560,16 @@ pub struct AddressInfo {
     pub frames: Option<Vec<FrameDebugInfo>>,
 }
 
+/// The lookup result from `lookup_u64`.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct AddressInfo64 {
+    /// Information about the symbol which contains the looked up address.
+    pub symbol: SymbolInfo64,
+    /// Information about the frames at the looked up address, if found in the debug info.
+    /// See [`AddressInfo::frames`].
+    pub frames: Option<Vec<FrameDebugInfo>>,
+}
+
 /// The lookup result from `lookup_sync`.
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct SyncAddressInfo {
@@ -453,6 +579,24 @@ pub struct SyncAddressInfo {
     pub frames: Option<FramesLookupResult>,
 }
 
+/// The lookup result from `lookup_sync_u64`.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct SyncAddressInfo64 {
+    /// Information about the symbol which contains the looked up address.
+    pub symbol: SymbolInfo64,
+    /// Information about the frames at the looked up address, from the debug info.
+    pub frames: Option<FramesLookupResult>,
+}
+
+impl From<SyncAddressInfo> for SyncAddressInfo64 {
+    fn from(address_info: SyncAddressInfo) -> Self {
+        Self {
+            symbol: address_info.symbol.into(),
+            frames: address_info.frames,
+        }
+    }
+}
+
 /// Contains address debug info (inlined functions, file names, line numbers) if
 /// available.
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -23,6 +23,9 @@ pub enum SourceFilePath<'a> {
     RawPath(Cow<'a, str>),
     BreakpadSpecialPathStr(Cow<'a, str>),
     RawPathAndUrl(Cow<'a, str>, Cow<'a, str>),
+    /// A raw path and the special path string which a [`MappedPathRules`](crate::MappedPathRules)
+    /// rule created for it.
+    RawPathAndSpecialPathStr(Cow<'a, str>, Cow<'a, str>),
 }
 
 impl<'a> SourceFilePath<'a> {
@@ -45,11 +48,15 @@ impl<'a> SourceFilePath<'a> {
                 r.as_ref().to_owned().into(),
                 u.as_ref().to_owned().into(),
             ),
+            Self::RawPathAndSpecialPathStr(r, bsp) => SourceFilePath::RawPathAndSpecialPathStr(
+                r.as_ref().to_owned().into(),
+                bsp.as_ref().to_owned().into(),
+            ),
         }
     }
 
     pub fn special_path_str(&self) -> Option<Cow<'_, str>> {
-        if let Self::BreakpadSpecialPathStr(bsp) = self {
+        if let Self::BreakpadSpecialPathStr(bsp) | Self::RawPathAndSpecialPathStr(_, bsp) = self {
             return Some(Cow::Borrowed(bsp));
         }
         if let Some(bsp) = self.mapped_path().special_path_str() {
@@ -91,6 +98,7 @@ impl<'a> SourceFilePath<'a> {
             SourceFilePath::RawPath(raw) => raw,
             SourceFilePath::BreakpadSpecialPathStr(bsp) => bsp, // is this ok?
             SourceFilePath::RawPathAndUrl(raw, _) => raw,
+            SourceFilePath::RawPathAndSpecialPathStr(raw, _) => raw,
         }
     }
 
@@ -100,6 +108,7 @@ impl<'a> SourceFilePath<'a> {
             SourceFilePath::RawPath(raw) => raw,
             SourceFilePath::BreakpadSpecialPathStr(bsp) => bsp, // is this ok?
             SourceFilePath::RawPathAndUrl(raw, _) => raw,
+            SourceFilePath::RawPathAndSpecialPathStr(raw, _) => raw,
         }
     }
 
@@ -120,6 +129,9 @@ impl<'a> SourceFilePath<'a> {
                 UnparsedMappedPath::BreakpadSpecialPath(bsp.clone())
             }
             SourceFilePath::RawPathAndUrl(_, url) => UnparsedMappedPath::Url(url.clone()),
+            SourceFilePath::RawPathAndSpecialPathStr(_, bsp) => {
+                UnparsedMappedPath::BreakpadSpecialPath(bsp.clone())
+            }
         }
     }
 }
@@ -0,0 +1,109 @@
+use std::borrow::Cow;
+
+use crate::SourceFilePath;
+
+/// Replaces path prefixes in the source file paths from the debug info.
+///
+/// This is the reverse of `-fdebug-prefix-map` / `--remap-path-prefix`: It
+/// is useful when the code was compiled in a different directory than where
+/// its sources are on this machine, for example on a build machine or inside
+/// a container.
+///
+/// Relative paths in DWARF are already resolved against the `DW_AT_comp_dir`
+/// of their compilation unit, so the rules are usually applied to absolute
+/// paths.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourcePathPrefixMap {
+    rules: Vec<(String, String)>,
+}
+
+impl SourcePathPrefixMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule which replaces the prefix `from` with `to`. `from` only
+    /// matches whole path components, so `/build` matches `/build/src/main.c`
+    /// but not `/buildroot/main.c`.
+    ///
+    /// Rules are tried in the order in which they were added, and only the
+    /// first matching rule is applied.
+    pub fn add_rule(&mut self, from: impl Into<String>, to: impl Into<String>) {
+        self.rules.push((from.into(), to.into()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Applies the first matching rule to `path`.
+    pub fn remap<'a>(&self, path: Cow<'a, str>) -> Cow<'a, str> {
+        for (from, to) in &self.rules {
+            let Some(rest) = path.strip_prefix(from.as_str()) else {
+                continue;
+            };
+            if !(rest.is_empty() || from.ends_with(is_separator) || rest.starts_with(is_separator))
+            {
+                continue;
+            }
+            if to.ends_with(is_separator) && rest.starts_with(is_separator) {
+                return format!("{to}{}", &rest[1..]).into();
+            }
+            return format!("{to}{rest}").into();
+        }
+        path
+    }
+
+    pub(crate) fn remap_source_file_path<'a>(
+        &self,
+        path: SourceFilePath<'a>,
+    ) -> SourceFilePath<'a> {
+        match path {
+            SourceFilePath::RawPath(raw) => SourceFilePath::RawPath(self.remap(raw)),
+            SourceFilePath::RawPathAndUrl(raw, url) => {
+                SourceFilePath::RawPathAndUrl(self.remap(raw), url)
+            }
+            SourceFilePath::RawPathAndSpecialPathStr(raw, bsp) => {
+                SourceFilePath::RawPathAndSpecialPathStr(self.remap(raw), bsp)
+            }
+            path @ SourceFilePath::BreakpadSpecialPathStr(_) => path,
+        }
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn remap(map: &SourcePathPrefixMap, path: &str) -> String {
+        map.remap(path.into()).into_owned()
+    }
+
+    #[test]
+    fn remap_prefixes() {
+        let mut map = SourcePathPrefixMap::new();
+        map.add_rule("/build", "/home/me/src");
+        map.add_rule("/ci/work/", "/home/me/ci/");
+        map.add_rule(r"C:\agent", r"D:\code");
+
+        assert_eq!(remap(&map, "/build/main.c"), "/home/me/src/main.c");
+        assert_eq!(remap(&map, "/build"), "/home/me/src");
+        assert_eq!(remap(&map, "/buildroot/main.c"), "/buildroot/main.c");
+        assert_eq!(remap(&map, "/ci/work/a/b.rs"), "/home/me/ci/a/b.rs");
+        assert_eq!(remap(&map, r"C:\agent\s\main.cpp"), r"D:\code\s\main.cpp");
+        assert_eq!(remap(&map, "relative/main.c"), "relative/main.c");
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let mut map = SourcePathPrefixMap::new();
+        map.add_rule("/build/third_party", "/vendor");
+        map.add_rule("/build", "/src");
+        assert_eq!(remap(&map, "/build/third_party/x.c"), "/vendor/x.c");
+        assert_eq!(remap(&map, "/build/x.c"), "/src/x.c");
+    }
+}
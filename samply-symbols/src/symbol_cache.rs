@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use debugid::DebugId;
+
+use crate::{ExternalFileSymbolMap, FileAndPathHelper, SymbolMap};
+
+/// A cache for parsed symbol maps and external object files which can be
+/// shared between multiple [`SymbolManager`](crate::SymbolManager)s, see
+/// [`SymbolManager::set_symbol_cache`](crate::SymbolManager::set_symbol_cache).
+///
+/// This is useful for servers which create a short-lived `SymbolManager` for
+/// each request: With a shared cache, the parse work for a library is reused
+/// by all requests which need it.
+///
+/// The cache can be limited by the total size of its entries, by the number
+/// of entries, or both. The size of an entry is the size of the file it was
+/// loaded from. Once either limit is exceeded, the least recently used entries
+/// are evicted. Entries which are larger than the maximum size are not cached.
+/// Symbol maps for images in the dyld shared cache don't have a file of their
+/// own, so they are only cached if the cache has no size limit.
+///
+/// Symbol maps are keyed by debug ID, and external files by the string
+/// representation of their file location.
+pub struct SymbolCache<H: FileAndPathHelper> {
+    max_size: u64,
+    max_count: usize,
+    state: Mutex<SymbolCacheState<H>>,
+}
+
+struct SymbolCacheState<H: FileAndPathHelper> {
+    size: u64,
+    /// Ordered from least recently used to most recently used.
+    entries: VecDeque<(SymbolCacheKey, u64, SymbolCacheEntry<H>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SymbolCacheKey {
+    SymbolMap(DebugId),
+    ExternalFile(String),
+}
+
+enum SymbolCacheEntry<H: FileAndPathHelper> {
+    SymbolMap(SymbolMap<H>),
+    ExternalFile(ExternalFileSymbolMap<H::F>),
+}
+
+impl<H: FileAndPathHelper> SymbolCache<H> {
+    /// Creates an empty cache which holds entries with a total size of up to
+    /// `max_size` bytes.
+    pub fn new(max_size: u64) -> Self {
+        Self::with_limits(Some(max_size), None)
+    }
+
+    /// Creates an empty cache which holds up to `max_count` entries of any
+    /// size.
+    pub fn with_max_count(max_count: usize) -> Self {
+        Self::with_limits(None, Some(max_count))
+    }
+
+    /// Creates an empty cache which holds entries with a total size of up to
+    /// `max_size` bytes, and up to `max_count` entries. `None` means no limit.
+    pub fn with_limits(max_size: Option<u64>, max_count: Option<usize>) -> Self {
+        Self {
+            max_size: max_size.unwrap_or(u64::MAX),
+            max_count: max_count.unwrap_or(usize::MAX),
+            state: Mutex::new(SymbolCacheState {
+                size: 0,
+                entries: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// The maximum total size of the cached entries, in bytes. This is
+    /// `u64::MAX` if the size is not limited.
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// The maximum number of cached entries. This is `usize::MAX` if the
+    /// number is not limited.
+    pub fn max_count(&self) -> usize {
+        self.max_count
+    }
+
+    /// The current number of cached entries.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// Whether the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The current total size of the cached entries, in bytes.
+    pub fn size(&self) -> u64 {
+        self.state.lock().unwrap().size
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.size = 0;
+    }
+
+    pub(crate) fn get_symbol_map(&self, debug_id: DebugId) -> Option<SymbolMap<H>> {
+        match self.get(&SymbolCacheKey::SymbolMap(debug_id))? {
+            SymbolCacheEntry::SymbolMap(symbol_map) => Some(symbol_map),
+            SymbolCacheEntry::ExternalFile(_) => None,
+        }
+    }
+
+    /// `size` is `None` if the symbol map wasn't loaded from a file of its own.
+    pub(crate) fn insert_symbol_map(
+        &self,
+        debug_id: DebugId,
+        symbol_map: SymbolMap<H>,
+        size: Option<u64>,
+    ) {
+        let size = match size {
+            Some(size) => size,
+            None if self.max_size == u64::MAX => 0,
+            None => return,
+        };
+        self.insert(
+            SymbolCacheKey::SymbolMap(debug_id),
+            size,
+            SymbolCacheEntry::SymbolMap(symbol_map),
+        );
+    }
+
+    pub(crate) fn get_external_file(&self, location: &str) -> Option<ExternalFileSymbolMap<H::F>> {
+        match self.get(&SymbolCacheKey::ExternalFile(location.to_owned()))? {
+            SymbolCacheEntry::ExternalFile(external_file) => Some(external_file),
+            SymbolCacheEntry::SymbolMap(_) => None,
+        }
+    }
+
+    pub(crate) fn insert_external_file(
+        &self,
+        location: String,
+        external_file: ExternalFileSymbolMap<H::F>,
+    ) {
+        self.insert(
+            SymbolCacheKey::ExternalFile(location),
+            external_file.file_len(),
+            SymbolCacheEntry::ExternalFile(external_file),
+        );
+    }
+
+    fn get(&self, key: &SymbolCacheKey) -> Option<SymbolCacheEntry<H>> {
+        let mut state = self.state.lock().unwrap();
+        let index = state.entries.iter().position(|(k, _, _)| k == key)?;
+        let entry = state.entries.remove(index)?;
+        let value = entry.2.clone();
+        state.entries.push_back(entry);
+        Some(value)
+    }
+
+    fn insert(&self, key: SymbolCacheKey, size: u64, entry: SymbolCacheEntry<H>) {
+        if size > self.max_size || self.max_count == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if let Some(index) = state.entries.iter().position(|(k, _, _)| *k == key) {
+            let (_, old_size, _) = state.entries.remove(index).unwrap();
+            state.size -= old_size;
+        }
+        while state.size + size > self.max_size || state.entries.len() >= self.max_count {
+            let (_, evicted_size, _) = state.entries.pop_front().unwrap();
+            state.size -= evicted_size;
+        }
+        state.size += size;
+        state.entries.push_back((key, size, entry));
+    }
+}
+
+impl<H: FileAndPathHelper> Clone for SymbolCacheEntry<H> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::SymbolMap(symbol_map) => Self::SymbolMap(symbol_map.clone()),
+            Self::ExternalFile(external_file) => Self::ExternalFile(external_file.clone()),
+        }
+    }
+}
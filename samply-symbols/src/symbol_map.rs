@@ -1,12 +1,18 @@
 use std::borrow::Cow;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
 use debugid::DebugId;
 
+use crate::address_kind::AddressKind;
+use crate::limits::truncate_frames;
+use crate::mapped_path::MappedPathRules;
+use crate::source_path_prefix_map::SourcePathPrefixMap;
+use crate::symbol_cache::SymbolCache;
 use crate::{
-    AddressInfo, ExternalFileAddressRef, ExternalFileRef, FileAndPathHelper, FileLocation,
-    FrameDebugInfo, FramesLookupResult, FunctionNameHandle, LookupAddress, SourceFilePath,
-    SourceFilePathHandle, SymbolNameHandle, SyncAddressInfo,
+    load_external_file, AddressInfo, AddressInfo64, ExternalFileAddressRef, ExternalFileRef,
+    ExternalFileSymbolMap, FileAndPathHelper, FileContents, FileLocation, FrameDebugInfo,
+    FramesLookupResult, FunctionNameHandle, LookupAddress, LookupAddress64, SourceFilePath,
+    SourceFilePathHandle, SymbolNameHandle, SyncAddressInfo, SyncAddressInfo64,
 };
 
 pub trait SymbolMapTrait {
@@ -30,6 +36,38 @@ pub trait SymbolMapTrait {
     fn resolve_source_file_path(&self, handle: SourceFilePathHandle) -> SourceFilePath<'_>;
 
     fn set_access_pattern_hint(&self, _hint: AccessPatternHint) {}
+
+    /// Whether this symbol map contains relative addresses which don't fit into
+    /// a `u32`. This is only the case for modules which are larger than 4 GiB.
+    ///
+    /// If this returns true, use the `_u64` methods; the `u32` methods skip
+    /// symbols and addresses beyond 4 GiB.
+    fn has_u64_relative_addresses(&self) -> bool {
+        false
+    }
+
+    /// Like [`iter_symbols`](Self::iter_symbols), but with `u64` relative addresses.
+    fn iter_symbols_u64(&self) -> Box<dyn Iterator<Item = (u64, Cow<'_, str>)> + '_> {
+        Box::new(
+            self.iter_symbols()
+                .map(|(address, name)| (u64::from(address), name)),
+        )
+    }
+
+    /// Like [`lookup_sync`](Self::lookup_sync), but with `u64` relative addresses.
+    fn lookup_sync_u64(&self, address: LookupAddress64) -> Option<SyncAddressInfo64> {
+        let address_info = self.lookup_sync(address.to_lookup_address()?)?;
+        Some(address_info.into())
+    }
+
+    /// Says whether the address is in a code section, a data section, or
+    /// outside of all sections. This is much cheaper than a lookup.
+    ///
+    /// Returns `None` if the symbol map doesn't know about the sections of the
+    /// binary, for example for PDB files and Breakpad files.
+    fn classify_address(&self, _address: LookupAddress64) -> Option<AddressKind> {
+        None
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -52,6 +90,16 @@ pub trait SymbolMapTraitWithExternalFileSupport<FC>: SymbolMapTrait {
         external: &ExternalFileAddressRef,
         file_contents: Option<FC>,
     ) -> Option<FramesLookupResult>;
+    fn add_external_file(&self, external_file: ExternalFileSymbolMap<FC>)
+    where
+        FC: FileContents + 'static;
+    fn lookup_with_external_file(
+        &self,
+        external: &ExternalFileAddressRef,
+        external_file: &ExternalFileSymbolMap<FC>,
+    ) -> Option<Vec<FrameDebugInfo>>
+    where
+        FC: FileContents + 'static;
 }
 
 pub trait GetInnerSymbolMap {
@@ -65,15 +113,45 @@ pub trait GetInnerSymbolMapWithLookupFramesExt<FC> {
 }
 
 enum InnerSymbolMap<FC> {
-    WithoutAddFile(Box<dyn GetInnerSymbolMap + Send + Sync>),
-    WithAddFile(Box<dyn GetInnerSymbolMapWithLookupFramesExt<FC> + Send + Sync>),
+    WithoutAddFile(Arc<dyn GetInnerSymbolMap + Send + Sync>),
+    WithAddFile(Arc<dyn GetInnerSymbolMapWithLookupFramesExt<FC> + Send + Sync>),
     Direct(Arc<dyn SymbolMapTrait + Send + Sync>),
 }
 
+impl<FC> Clone for InnerSymbolMap<FC> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::WithoutAddFile(inner) => Self::WithoutAddFile(inner.clone()),
+            Self::WithAddFile(inner) => Self::WithAddFile(inner.clone()),
+            Self::Direct(inner) => Self::Direct(inner.clone()),
+        }
+    }
+}
+
 pub struct SymbolMap<H: FileAndPathHelper> {
     debug_file_location: H::FL,
     inner: InnerSymbolMap<H::F>,
     helper: Option<Arc<H>>,
+    max_inline_depth: Option<usize>,
+    source_path_prefix_map: Option<Arc<SourcePathPrefixMap>>,
+    mapped_path_rules: Option<Arc<MappedPathRules>>,
+    symbol_cache: Option<Weak<SymbolCache<H>>>,
+}
+
+/// Cloning a `SymbolMap` is cheap: the clones share the parsed symbol data,
+/// including any external files which were already loaded into it.
+impl<H: FileAndPathHelper> Clone for SymbolMap<H> {
+    fn clone(&self) -> Self {
+        Self {
+            debug_file_location: self.debug_file_location.clone(),
+            inner: self.inner.clone(),
+            helper: self.helper.clone(),
+            max_inline_depth: self.max_inline_depth,
+            source_path_prefix_map: self.source_path_prefix_map.clone(),
+            mapped_path_rules: self.mapped_path_rules.clone(),
+            symbol_cache: self.symbol_cache.clone(),
+        }
+    }
 }
 
 impl<H: FileAndPathHelper> SymbolMap<H> {
@@ -83,8 +161,12 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
     ) -> Self {
         Self {
             debug_file_location,
-            inner: InnerSymbolMap::WithoutAddFile(inner),
+            inner: InnerSymbolMap::WithoutAddFile(inner.into()),
             helper: None,
+            max_inline_depth: None,
+            source_path_prefix_map: None,
+            mapped_path_rules: None,
+            symbol_cache: None,
         }
     }
 
@@ -95,8 +177,12 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
     ) -> Self {
         Self {
             debug_file_location,
-            inner: InnerSymbolMap::WithAddFile(inner),
+            inner: InnerSymbolMap::WithAddFile(inner.into()),
             helper: Some(helper),
+            max_inline_depth: None,
+            source_path_prefix_map: None,
+            mapped_path_rules: None,
+            symbol_cache: None,
         }
     }
 
@@ -108,9 +194,51 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
             debug_file_location,
             inner: InnerSymbolMap::Direct(inner),
             helper: None,
+            max_inline_depth: None,
+            source_path_prefix_map: None,
+            mapped_path_rules: None,
+            symbol_cache: None,
         }
     }
 
+    /// Cuts off the inline stacks returned by lookups at `max_depth` frames.
+    pub(crate) fn set_max_inline_depth(&mut self, max_depth: Option<usize>) {
+        self.max_inline_depth = max_depth;
+    }
+
+    /// Replaces path prefixes in the source file paths returned by
+    /// [`resolve_source_file_path`](Self::resolve_source_file_path).
+    pub(crate) fn set_source_path_prefix_map(&mut self, map: Option<Arc<SourcePathPrefixMap>>) {
+        self.source_path_prefix_map = map;
+    }
+
+    /// Creates mapped paths with custom rules in
+    /// [`resolve_source_file_path`](Self::resolve_source_file_path).
+    pub(crate) fn set_mapped_path_rules(&mut self, rules: Option<Arc<MappedPathRules>>) {
+        self.mapped_path_rules = rules;
+    }
+
+    /// Keeps the external object files which are loaded during lookups in the
+    /// shared `cache`, and looks for them there first.
+    pub(crate) fn set_symbol_cache(&mut self, cache: Option<Weak<SymbolCache<H>>>) {
+        self.symbol_cache = cache;
+    }
+
+    /// Makes this symbol map load external files with `helper`, if it loads
+    /// external files at all.
+    pub(crate) fn replace_helper(&mut self, helper: Arc<H>) {
+        if self.helper.is_some() {
+            self.helper = Some(helper);
+        }
+    }
+
+    fn limit_inline_depth(&self, mut frames: Vec<FrameDebugInfo>) -> Vec<FrameDebugInfo> {
+        if let Some(max_depth) = self.max_inline_depth {
+            truncate_frames(&mut frames, max_depth);
+        }
+        frames
+    }
+
     fn inner(&self) -> &dyn SymbolMapTrait {
         match &self.inner {
             InnerSymbolMap::WithoutAddFile(inner) => inner.get_inner_symbol_map(),
@@ -125,19 +253,36 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
 
     pub async fn lookup(&self, address: LookupAddress) -> Option<AddressInfo> {
         let address_info = self.inner().lookup_sync(address)?;
-        let symbol = address_info.symbol;
-        let (mut external, inner) = match (address_info.frames, &self.inner) {
+        let frames = self.resolve_frames(address_info.frames).await;
+        Some(AddressInfo {
+            symbol: address_info.symbol,
+            frames,
+        })
+    }
+
+    /// Like [`lookup`](Self::lookup), but with `u64` relative addresses.
+    /// See [`SymbolMapTrait::has_u64_relative_addresses`].
+    pub async fn lookup_u64(&self, address: LookupAddress64) -> Option<AddressInfo64> {
+        let address_info = self.inner().lookup_sync_u64(address)?;
+        let frames = self.resolve_frames(address_info.frames).await;
+        Some(AddressInfo64 {
+            symbol: address_info.symbol,
+            frames,
+        })
+    }
+
+    /// Loads any external files needed to turn the frames from `lookup_sync`
+    /// into debug info.
+    async fn resolve_frames(
+        &self,
+        frames: Option<FramesLookupResult>,
+    ) -> Option<Vec<FrameDebugInfo>> {
+        let (mut external, inner) = match (frames, &self.inner) {
             (Some(FramesLookupResult::Available(frames)), _) => {
-                return Some(AddressInfo {
-                    symbol,
-                    frames: Some(frames),
-                });
+                return Some(self.limit_inline_depth(frames));
             }
             (None, _) | (_, InnerSymbolMap::WithoutAddFile(_)) | (_, InnerSymbolMap::Direct(_)) => {
-                return Some(AddressInfo {
-                    symbol,
-                    frames: None,
-                });
+                return None;
             }
             (Some(FramesLookupResult::External(external)), InnerSymbolMap::WithAddFile(inner)) => {
                 (external, inner.get_inner_symbol_map())
@@ -145,6 +290,14 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
         };
         let helper = self.helper.as_deref()?;
         loop {
+            if let Some(cache) = self.symbol_cache.as_ref().and_then(Weak::upgrade) {
+                if let ExternalFileRef::MachoExternalObject { file_path } = &external.file_ref {
+                    let frames = self
+                        .lookup_with_cached_external_file(&cache, inner, &external, file_path)
+                        .await?;
+                    return Some(self.limit_inline_depth(frames));
+                }
+            }
             let maybe_file_location = match &external.file_ref {
                 ExternalFileRef::MachoExternalObject { file_path } => self
                     .debug_file_location
@@ -161,17 +314,9 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
                 inner.try_lookup_external_with_file_contents(&external, file_contents);
             external = match lookup_result {
                 Some(FramesLookupResult::Available(frames)) => {
-                    return Some(AddressInfo {
-                        symbol,
-                        frames: Some(frames),
-                    });
-                }
-                None => {
-                    return Some(AddressInfo {
-                        symbol,
-                        frames: None,
-                    });
+                    return Some(self.limit_inline_depth(frames));
                 }
+                None => return None,
                 Some(FramesLookupResult::External(external)) => external,
             };
         }
@@ -197,10 +342,20 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
         let mut lookup_result: Option<FramesLookupResult> = inner.try_lookup_external(external);
         loop {
             let external = match lookup_result {
-                Some(FramesLookupResult::Available(frames)) => return Some(frames),
+                Some(FramesLookupResult::Available(frames)) => {
+                    return Some(self.limit_inline_depth(frames))
+                }
                 None => return None,
                 Some(FramesLookupResult::External(external)) => external,
             };
+            if let Some(cache) = self.symbol_cache.as_ref().and_then(Weak::upgrade) {
+                if let ExternalFileRef::MachoExternalObject { file_path } = &external.file_ref {
+                    let frames = self
+                        .lookup_with_cached_external_file(&cache, inner, &external, file_path)
+                        .await?;
+                    return Some(self.limit_inline_depth(frames));
+                }
+            }
             let maybe_file_location = match &external.file_ref {
                 ExternalFileRef::MachoExternalObject { file_path } => self
                     .debug_file_location
@@ -217,6 +372,48 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
         }
     }
 
+    /// Looks up `external` in the external object file at `file_path`, which
+    /// is taken from the shared cache, or loaded and added to it.
+    async fn lookup_with_cached_external_file(
+        &self,
+        cache: &SymbolCache<H>,
+        inner: &(dyn SymbolMapTraitWithExternalFileSupport<H::F> + Send + Sync + '_),
+        external: &ExternalFileAddressRef,
+        file_path: &str,
+    ) -> Option<Vec<FrameDebugInfo>> {
+        let helper = self.helper.as_deref()?;
+        let location = self
+            .debug_file_location
+            .location_for_external_object_file(file_path)?;
+        let key = location.to_string();
+        let external_file = match cache.get_external_file(&key) {
+            Some(external_file) => external_file,
+            None => {
+                let external_file = load_external_file(helper, location, file_path).await.ok()?;
+                cache.insert_external_file(key, external_file.clone());
+                external_file
+            }
+        };
+        inner.lookup_with_external_file(external, &external_file)
+    }
+
+    /// Register an external file which was loaded ahead of time, for example
+    /// with [`SymbolManager::load_external_file`](crate::SymbolManager::load_external_file).
+    ///
+    /// Lookups which need this file, including [`SymbolMap::lookup_sync`], then use
+    /// it directly and never ask the helper to load it. Preloaded files are kept
+    /// for the lifetime of the symbol map, unlike the single most recently loaded
+    /// external file which is cached by `lookup_external`.
+    ///
+    /// This does nothing for symbol maps which don't reference external files.
+    pub fn add_external_file(&self, external_file: ExternalFileSymbolMap<H::F>) {
+        if let InnerSymbolMap::WithAddFile(inner) = &self.inner {
+            inner
+                .get_inner_symbol_map()
+                .add_external_file(external_file);
+        }
+    }
+
     pub fn debug_id(&self) -> debugid::DebugId {
         self.inner().debug_id()
     }
@@ -230,7 +427,33 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
     }
 
     pub fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
-        self.inner().lookup_sync(address)
+        let mut address_info = self.inner().lookup_sync(address)?;
+        if let Some(FramesLookupResult::Available(frames)) = address_info.frames {
+            let frames = self.limit_inline_depth(frames);
+            address_info.frames = Some(FramesLookupResult::Available(frames));
+        }
+        Some(address_info)
+    }
+
+    pub fn has_u64_relative_addresses(&self) -> bool {
+        self.inner().has_u64_relative_addresses()
+    }
+
+    pub fn classify_address(&self, address: impl Into<LookupAddress64>) -> Option<AddressKind> {
+        self.inner().classify_address(address.into())
+    }
+
+    pub fn iter_symbols_u64(&self) -> Box<dyn Iterator<Item = (u64, Cow<'_, str>)> + '_> {
+        self.inner().iter_symbols_u64()
+    }
+
+    pub fn lookup_sync_u64(&self, address: LookupAddress64) -> Option<SyncAddressInfo64> {
+        let mut address_info = self.inner().lookup_sync_u64(address)?;
+        if let Some(FramesLookupResult::Available(frames)) = address_info.frames {
+            let frames = self.limit_inline_depth(frames);
+            address_info.frames = Some(FramesLookupResult::Available(frames));
+        }
+        Some(address_info)
     }
 
     pub fn resolve_function_name(&self, handle: FunctionNameHandle) -> Cow<'_, str> {
@@ -242,7 +465,14 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
     }
 
     pub fn resolve_source_file_path(&self, handle: SourceFilePathHandle) -> SourceFilePath<'_> {
-        self.inner().resolve_source_file_path(handle)
+        let mut path = self.inner().resolve_source_file_path(handle);
+        if let Some(rules) = &self.mapped_path_rules {
+            path = rules.map_source_file_path(path);
+        }
+        match &self.source_path_prefix_map {
+            Some(map) => map.remap_source_file_path(path),
+            None => path,
+        }
     }
 
     pub fn set_access_pattern_hint(&self, hint: AccessPatternHint) {
@@ -279,6 +509,22 @@ impl<H: FileAndPathHelper> SymbolMapTrait for SymbolMap<H> {
         self.resolve_source_file_path(handle)
     }
 
+    fn has_u64_relative_addresses(&self) -> bool {
+        self.has_u64_relative_addresses()
+    }
+
+    fn iter_symbols_u64(&self) -> Box<dyn Iterator<Item = (u64, Cow<'_, str>)> + '_> {
+        self.iter_symbols_u64()
+    }
+
+    fn lookup_sync_u64(&self, address: LookupAddress64) -> Option<SyncAddressInfo64> {
+        self.lookup_sync_u64(address)
+    }
+
+    fn classify_address(&self, address: LookupAddress64) -> Option<AddressKind> {
+        self.classify_address(address)
+    }
+
     fn set_access_pattern_hint(&self, hint: AccessPatternHint) {
         self.set_access_pattern_hint(hint);
     }
@@ -1,23 +1,23 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::marker::PhantomData;
-use std::slice;
 use std::sync::{Arc, Mutex};
 
 use addr2line::{LookupResult, SplitDwarfLoad};
 use debugid::DebugId;
 use gimli::{EndianSlice, RunTimeEndian};
-use object::{
-    ObjectMap, ObjectSection, ObjectSegment, SectionFlags, SectionIndex, SectionKind, SymbolKind,
-};
+use object::{ObjectMap, ObjectSection, ObjectSegment, SectionFlags, SectionIndex, SectionKind};
 use samply_object::relative_address_base;
 use yoke::Yoke;
 use yoke_derive::Yokeable;
 
+use crate::address_kind::{AddressKind, SectionKindRanges};
 use crate::dwarf::convert_frames;
 use crate::generation::SymbolMapGeneration;
+use crate::limits::ParseLimits;
 use crate::shared::{
-    ExternalFileAddressInFileRef, ExternalFileAddressRef, ExternalFileRef, FramesLookupResult,
-    LookupAddress, SymbolInfo,
+    ExternalFileAddressInFileRef, ExternalFileAddressRef, ExternalFileRef, FrameDebugInfo,
+    FramesLookupResult, LookupAddress, LookupAddress64, SymbolInfo64, SymbolKind,
 };
 use crate::symbol_map::{
     GetInnerSymbolMap, GetInnerSymbolMapWithLookupFramesExt, SymbolMapTrait,
@@ -26,6 +26,7 @@ use crate::symbol_map::{
 use crate::{
     demangle, Error, ExternalFileSymbolMap, FileContents, FunctionNameHandle, SourceFilePath,
     SourceFilePathHandle, SymbolMapStringInterner, SymbolNameHandle, SyncAddressInfo,
+    SyncAddressInfo64,
 };
 
 enum FullSymbolListEntry<'a, Symbol> {
@@ -34,7 +35,11 @@ enum FullSymbolListEntry<'a, Symbol> {
     Synthesized,
     /// A synthesized symbol for the entry point of the object.
     SynthesizedEntryPoint,
-    Symbol(Symbol),
+    /// A synthesized symbol for the start of a section with stubs for
+    /// calls into other libraries, e.g. `.plt` or `__stubs`. The stubs
+    /// themselves usually don't have symbols.
+    StubSection(&'a str),
+    Symbol(Symbol, SymbolKind),
     Export(object::Export<'a>),
     EndAddress,
 }
@@ -44,9 +49,11 @@ impl<'a, Symbol: object::ObjectSymbol<'a>> std::fmt::Debug for FullSymbolListEnt
         match self {
             Self::Synthesized => write!(f, "Synthesized"),
             Self::SynthesizedEntryPoint => write!(f, "SynthesizedEntryPoint"),
-            Self::Symbol(arg0) => f
+            Self::StubSection(arg0) => f.debug_tuple("StubSection").field(arg0).finish(),
+            Self::Symbol(arg0, arg1) => f
                 .debug_tuple("Symbol")
                 .field(&arg0.name().unwrap())
+                .field(arg1)
                 .finish(),
             Self::Export(arg0) => f
                 .debug_tuple("Export")
@@ -58,12 +65,13 @@ impl<'a, Symbol: object::ObjectSymbol<'a>> std::fmt::Debug for FullSymbolListEnt
 }
 
 impl<'a, Symbol: object::ObjectSymbol<'a>> FullSymbolListEntry<'a, Symbol> {
-    fn name(&self, addr: u32) -> Option<Cow<'a, str>> {
+    fn name(&self, addr: u64) -> Option<Cow<'a, str>> {
         let name = match self {
             FullSymbolListEntry::EndAddress => return None,
             FullSymbolListEntry::Synthesized => format!("fun_{addr:x}").into(),
             FullSymbolListEntry::SynthesizedEntryPoint => "EntryPoint".into(),
-            FullSymbolListEntry::Symbol(symbol) => {
+            FullSymbolListEntry::StubSection(section_name) => (*section_name).into(),
+            FullSymbolListEntry::Symbol(symbol, _) => {
                 String::from_utf8_lossy(symbol.name_bytes().ok()?)
             }
             FullSymbolListEntry::Export(export) => String::from_utf8_lossy(export.name()),
@@ -71,18 +79,70 @@ impl<'a, Symbol: object::ObjectSymbol<'a>> FullSymbolListEntry<'a, Symbol> {
         Some(name)
     }
 
+    fn kind(&self) -> SymbolKind {
+        match self {
+            FullSymbolListEntry::Symbol(_, kind) => *kind,
+            FullSymbolListEntry::StubSection(_) => SymbolKind::PltStub,
+            FullSymbolListEntry::Synthesized | FullSymbolListEntry::SynthesizedEntryPoint => {
+                SymbolKind::SectionPlaceholder
+            }
+            FullSymbolListEntry::Export(_) | FullSymbolListEntry::EndAddress => {
+                SymbolKind::Function
+            }
+        }
+    }
+
     fn counts_as_proper_symbol(&self) -> bool {
         match self {
-            FullSymbolListEntry::Symbol(_) | FullSymbolListEntry::Export(_) => true,
+            FullSymbolListEntry::Symbol(..) | FullSymbolListEntry::Export(_) => true,
             FullSymbolListEntry::EndAddress
             | FullSymbolListEntry::Synthesized
-            | FullSymbolListEntry::SynthesizedEntryPoint => false,
+            | FullSymbolListEntry::SynthesizedEntryPoint
+            | FullSymbolListEntry::StubSection(_) => false,
         }
     }
 }
 
 struct SymbolList<'a, Symbol> {
-    entries: Vec<(u32, FullSymbolListEntry<'a, Symbol>)>,
+    /// The sorted relative addresses of the entries.
+    addresses: SymbolListAddresses,
+    /// The entries, at the same indexes as their addresses.
+    entries: Vec<FullSymbolListEntry<'a, Symbol>>,
+}
+
+/// The relative addresses of the entries in a [`SymbolList`]. They are only
+/// stored as `u64` for modules which are larger than 4 GiB, because the
+/// addresses take up a large part of the list's memory.
+enum SymbolListAddresses {
+    U32(Vec<u32>),
+    U64(Vec<u64>),
+}
+
+impl SymbolListAddresses {
+    /// `addresses` must be sorted.
+    fn new(addresses: Vec<u64>) -> Self {
+        match addresses.last() {
+            Some(&last) if last > u64::from(u32::MAX) => Self::U64(addresses),
+            _ => Self::U32(addresses.into_iter().map(|a| a as u32).collect()),
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<u64> {
+        match self {
+            Self::U32(addresses) => addresses.get(index).copied().map(u64::from),
+            Self::U64(addresses) => addresses.get(index).copied(),
+        }
+    }
+
+    fn binary_search(&self, address: u64) -> Result<usize, usize> {
+        match self {
+            Self::U32(addresses) => match u32::try_from(address) {
+                Ok(address) => addresses.binary_search(&address),
+                Err(_) => Err(addresses.len()),
+            },
+            Self::U64(addresses) => addresses.binary_search(&address),
+        }
+    }
 }
 
 impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
@@ -91,13 +151,19 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
         base_address: u64,
         function_start_addresses: Option<&[u32]>,
         function_end_addresses: Option<&[u32]>,
-    ) -> Self
+        limits: &ParseLimits,
+    ) -> Result<Self, Error>
     where
         'a: 'file,
         O: object::Object<'a, Symbol<'file> = Symbol>,
     {
         let mut entries: Vec<_> = Vec::new();
 
+        // The number of symbols and exports which have been collected so far.
+        // Checked against the limit as we go, so that files with absurd symbol
+        // tables fail early.
+        let mut symbol_count = 0;
+
         // Compute the executable sections upfront. This will be used to filter out uninteresting symbols.
         let executable_sections: Vec<SectionIndex> = object_file
             .sections()
@@ -117,55 +183,85 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
             })
             .collect();
 
+        // Sections which contain stubs for calls into other libraries.
+        let stub_sections: Vec<_> = object_file
+            .sections()
+            .filter(|section| executable_sections.contains(&section.index()))
+            .filter_map(|section| {
+                let name = section.name().ok()?;
+                is_stub_section_name(name).then_some((section.index(), name, section.address()))
+            })
+            .collect();
+
         // Build a list of symbol start and end entries. We add entries in the order "best to worst".
 
         // 1. Normal symbols
         // 2. Dynamic symbols (only used by ELF files, I think)
-        entries.extend(
-            object_file
-                .symbols()
-                .chain(object_file.dynamic_symbols())
-                .filter(|symbol| {
-                    // Filter out symbols with no address.
-                    if symbol.address() == 0 {
-                        return false;
-                    }
+        let symbols = object_file
+            .symbols()
+            .chain(object_file.dynamic_symbols())
+            .filter(|symbol| {
+                // Filter out symbols with no address.
+                if symbol.address() == 0 {
+                    return false;
+                }
 
-                    // Filter out non-Text symbols which don't have a symbol size.
-                    match symbol.kind() {
-                        SymbolKind::Text => {
-                            // Keep. This is a regular function symbol. On mach-O these don't have sizes.
-                        }
-                        SymbolKind::Label if symbol.size() != 0 => {
-                            // Keep. This catches some useful kernel symbols, e.g. asm_exc_page_fault,
-                            // which is a NOTYPE symbol (= SymbolKind::Label).
-                            //
-                            // We require a non-zero symbol size in this case, in order to filter out some
-                            // bad symbols in the middle of functions. For example, the android32-local/libmozglue.so
-                            // fixture has a NOTYPE symbol with zero size at 0x9850f.
-                        }
-                        _ => return false, // Cull.
+                // Filter out non-Text symbols which don't have a symbol size.
+                match symbol.kind() {
+                    object::SymbolKind::Text => {
+                        // Keep. This is a regular function symbol. On mach-O these don't have sizes.
                     }
-
-                    // Filter out symbols from non-executable sections.
-                    match symbol.section_index() {
-                        Some(section_index) => executable_sections.contains(&section_index),
-                        _ => false,
+                    object::SymbolKind::Data if symbol.size() != 0 => {
+                        // Keep. This is a data object inside a code section, e.g. a jump
+                        // table. Addresses inside of it should not be attributed to the
+                        // preceding function.
                     }
-                })
-                .filter_map(|symbol| {
-                    Some((
-                        u32::try_from(symbol.address().checked_sub(base_address)?).ok()?,
-                        FullSymbolListEntry::Symbol(symbol),
-                    ))
-                }),
-        );
+                    object::SymbolKind::Label if symbol.size() != 0 => {
+                        // Keep. This catches some useful kernel symbols, e.g. asm_exc_page_fault,
+                        // which is a NOTYPE symbol (= SymbolKind::Label).
+                        //
+                        // We require a non-zero symbol size in this case, in order to filter out some
+                        // bad symbols in the middle of functions. For example, the android32-local/libmozglue.so
+                        // fixture has a NOTYPE symbol with zero size at 0x9850f.
+                    }
+                    _ => return false, // Cull.
+                }
+
+                // Filter out symbols from non-executable sections.
+                match symbol.section_index() {
+                    Some(section_index) => executable_sections.contains(&section_index),
+                    _ => false,
+                }
+            })
+            .filter_map(|symbol| {
+                let in_stub_section = stub_sections
+                    .iter()
+                    .any(|(index, _, _)| symbol.section_index() == Some(*index));
+                let kind = if in_stub_section {
+                    SymbolKind::PltStub
+                } else if symbol.kind() == object::SymbolKind::Data {
+                    SymbolKind::DataObject
+                } else {
+                    SymbolKind::Function
+                };
+                Some((
+                    symbol.address().checked_sub(base_address)?,
+                    FullSymbolListEntry::Symbol(symbol, kind),
+                ))
+            });
+        for entry in symbols {
+            symbol_count += 1;
+            limits.check_symbol_count(symbol_count)?;
+            entries.push(entry);
+        }
 
         // 3. Exports (only used by exe / dll objects)
         if let Ok(exports) = object_file.exports() {
             for export in exports {
+                symbol_count += 1;
+                limits.check_symbol_count(symbol_count)?;
                 entries.push((
-                    (export.address() - base_address) as u32,
+                    export.address() - base_address,
                     FullSymbolListEntry::Export(export),
                 ));
             }
@@ -181,19 +277,28 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
             entries.extend(
                 function_start_addresses
                     .iter()
-                    .map(|address| (*address, FullSymbolListEntry::Synthesized)),
+                    .map(|address| (u64::from(*address), FullSymbolListEntry::Synthesized)),
             );
         }
 
         // 5. A placeholder symbol for the entry point.
         if let Some(entry_point) = object_file.entry().checked_sub(base_address) {
-            entries.push((
-                entry_point as u32,
-                FullSymbolListEntry::SynthesizedEntryPoint,
-            ));
+            entries.push((entry_point, FullSymbolListEntry::SynthesizedEntryPoint));
         }
 
-        // 6. End addresses from text section ends
+        // 6. Placeholder symbols for the start of stub sections.
+        entries.extend(
+            stub_sections
+                .iter()
+                .filter_map(|(_, section_name, section_address)| {
+                    Some((
+                        section_address.checked_sub(base_address)?,
+                        FullSymbolListEntry::StubSection(section_name),
+                    ))
+                }),
+        );
+
+        // 7. End addresses from text section ends
         // These entries serve to "terminate" the last function of each section,
         // so that addresses in the following section are not considered
         // to be part of the last function of that previous section.
@@ -204,41 +309,44 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
                 .filter_map(|section| {
                     let vma_end_address = section.address().checked_add(section.size())?;
                     let end_address = vma_end_address.checked_sub(base_address)?;
-                    let end_address = u32::try_from(end_address).ok()?;
                     Some((end_address, FullSymbolListEntry::EndAddress))
                 }),
         );
 
-        // 7. End addresses for sized symbols
-        // These addresses serve to "terminate" functions symbols.
+        // 8. End addresses for sized symbols
+        // These addresses serve to "terminate" functions symbols and data objects.
         entries.extend(
             object_file
                 .symbols()
                 .filter(|symbol| {
-                    symbol.kind() == SymbolKind::Text && symbol.address() != 0 && symbol.size() != 0
+                    let is_code_or_data_in_code = match symbol.kind() {
+                        object::SymbolKind::Text => true,
+                        object::SymbolKind::Data => symbol
+                            .section_index()
+                            .is_some_and(|index| executable_sections.contains(&index)),
+                        _ => false,
+                    };
+                    is_code_or_data_in_code && symbol.address() != 0 && symbol.size() != 0
                 })
                 .filter_map(|symbol| {
                     Some((
-                        u32::try_from(
-                            symbol
-                                .address()
-                                .checked_add(symbol.size())?
-                                .checked_sub(base_address)?,
-                        )
-                        .ok()?,
+                        symbol
+                            .address()
+                            .checked_add(symbol.size())?
+                            .checked_sub(base_address)?,
                         FullSymbolListEntry::EndAddress,
                     ))
                 }),
         );
 
-        // 8. End addresses for known functions ends
+        // 9. End addresses for known functions ends
         // These addresses serve to "terminate" functions from function_start_addresses.
         // They come from .eh_frame or .pdata info, which has the function size.
         if let Some(function_end_addresses) = function_end_addresses {
             entries.extend(
                 function_end_addresses
                     .iter()
-                    .map(|address| (*address, FullSymbolListEntry::EndAddress)),
+                    .map(|address| (u64::from(*address), FullSymbolListEntry::EndAddress)),
             );
         }
 
@@ -253,32 +361,51 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
         entries.sort_by_key(|(address, _)| *address);
         entries.dedup_by_key(|(address, _)| *address);
 
-        Self { entries }
+        let (addresses, entries) = entries.into_iter().unzip();
+        Ok(Self {
+            addresses: SymbolListAddresses::new(addresses),
+            entries,
+        })
+    }
+
+    pub fn has_u64_addresses(&self) -> bool {
+        matches!(self.addresses, SymbolListAddresses::U64(_))
     }
 
-    pub fn lookup_relative_address(&self, address: u32) -> Option<(u32, u32, Cow<'a, str>)> {
-        let index = match self
-            .entries
-            .binary_search_by_key(&address, |&(addr, _)| addr)
-        {
+    pub fn lookup_relative_address(
+        &self,
+        address: u64,
+    ) -> Option<(u64, u64, Cow<'a, str>, SymbolKind)> {
+        let index = match self.addresses.binary_search(address) {
             Err(0) => return None,
             Ok(i) => i,
             Err(i) => i - 1,
         };
-        let (start_addr, entry) = &self.entries[index];
-        let (end_addr, _next_entry) = self.entries.get(index + 1)?;
+        let start_addr = self.addresses.get(index)?;
+        let end_addr = self.addresses.get(index + 1)?;
+        let entry = &self.entries[index];
         let name = match entry {
             FullSymbolListEntry::EndAddress => {
                 // If the found entry is an EndAddress entry, this means that `address` falls
                 // in the dead space between known functions, and we consider it to be not found.
                 return None;
             }
-            _ => entry.name(*start_addr)?,
+            _ => entry.name(start_addr)?,
         };
-        Some((*start_addr, *end_addr, name))
+        Some((start_addr, end_addr, name, entry.kind()))
     }
 }
 
+/// Whether this is the name of a section containing stubs which jump into
+/// other libraries, on ELF or mach-O.
+fn is_stub_section_name(name: &str) -> bool {
+    matches!(
+        name,
+        ".plt" | ".plt.sec" | ".plt.got" | ".iplt" | "__stubs" | "__auth_stubs" | "__stub_helper"
+    ) || name.starts_with("__symbol_stub")
+        || name.starts_with("__picsymbolstub")
+}
+
 // A file range in an object file, such as a segment or a section,
 // for which we know the corresponding Stated Virtual Memory Address (SVMA).
 #[derive(Clone)]
@@ -362,9 +489,11 @@ pub struct ObjectSymbolMapInner<'a, Symbol, FC: FileContents + 'static, DDM> {
     dwp_package:
         Option<addr2line::gimli::DwarfPackage<gimli::EndianSlice<'a, gimli::RunTimeEndian>>>,
     svma_file_ranges: SvmaFileRanges,
+    section_kind_ranges: SectionKindRanges,
     image_base_address: u64,
     dwo_dwarf_maker: &'a DDM,
     cached_external_file: Mutex<Option<ExternalFileSymbolMap<FC>>>,
+    preloaded_external_files: Mutex<HashMap<String, ExternalFileSymbolMap<FC>>>,
     string_interner: Mutex<SymbolMapStringInterner<'a>>,
     _phantom: PhantomData<FC>,
 }
@@ -375,6 +504,20 @@ where
     FC: FileContents + 'static,
     DDM: DwoDwarfMaker<FC>,
 {
+    fn svma_and_relative_address(&self, address: LookupAddress64) -> Option<(u64, u64)> {
+        match address {
+            LookupAddress64::Relative(relative_address) => Some((
+                self.image_base_address.checked_add(relative_address)?,
+                relative_address,
+            )),
+            LookupAddress64::Svma(svma) => Some((svma, svma.checked_sub(self.image_base_address)?)),
+            LookupAddress64::FileOffset(offset) => {
+                let svma = self.svma_file_ranges.file_offset_to_svma(offset)?;
+                Some((svma, svma.checked_sub(self.image_base_address)?))
+            }
+        }
+    }
+
     fn frames_lookup_for_object_map_references(&self, svma: u64) -> Option<FramesLookupResult> {
         let entry = self.object_map.get(svma)?;
         let object_map_file = entry.object(&self.object_map);
@@ -416,6 +559,13 @@ where
         let mut string_interner = self.string_interner.lock().unwrap();
         match &external.file_ref {
             ExternalFileRef::MachoExternalObject { file_path } => {
+                if let Some(external_file) =
+                    self.preloaded_external_files.lock().unwrap().get(file_path)
+                {
+                    return external_file
+                        .lookup(&external.address_in_file, &mut string_interner)
+                        .map(FramesLookupResult::Available);
+                }
                 {
                     let cached_external_file = self.cached_external_file.lock().unwrap();
                     match &*cached_external_file {
@@ -508,36 +658,47 @@ where
 
     fn symbol_count(&self) -> usize {
         let iter = self.list.entries.iter();
-        iter.filter(|&(_, entry)| entry.counts_as_proper_symbol())
-            .count()
+        iter.filter(|entry| entry.counts_as_proper_symbol()).count()
     }
 
     fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_> {
+        Box::new(
+            self.iter_symbols_u64()
+                .map_while(|(address, name)| Some((u32::try_from(address).ok()?, name))),
+        )
+    }
+
+    fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
+        let address_info = self.lookup_sync_u64(address.into())?;
+        Some(SyncAddressInfo {
+            symbol: address_info.symbol.to_symbol_info()?,
+            frames: address_info.frames,
+        })
+    }
+
+    fn has_u64_relative_addresses(&self) -> bool {
+        self.list.has_u64_addresses()
+    }
+
+    fn iter_symbols_u64(&self) -> Box<dyn Iterator<Item = (u64, Cow<'_, str>)> + '_> {
         Box::new(SymbolMapIter {
-            inner: self.list.entries.iter(),
+            list: &self.list,
+            index: 0,
         })
     }
 
-    fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
-        let (svma, relative_address) = match address {
-            LookupAddress::Relative(relative_address) => (
-                self.image_base_address
-                    .checked_add(u64::from(relative_address))?,
-                relative_address,
-            ),
-            LookupAddress::Svma(svma) => (
-                svma,
-                u32::try_from(svma.checked_sub(self.image_base_address)?).ok()?,
-            ),
-            LookupAddress::FileOffset(offset) => {
-                let svma = self.svma_file_ranges.file_offset_to_svma(offset)?;
-                (
-                    svma,
-                    u32::try_from(svma.checked_sub(self.image_base_address)?).ok()?,
-                )
-            }
+    fn classify_address(&self, address: LookupAddress64) -> Option<AddressKind> {
+        let kind = match self.svma_and_relative_address(address) {
+            Some((svma, _)) => self.section_kind_ranges.classify_svma(svma),
+            None => AddressKind::Unknown,
         };
-        let (start_addr, end_addr, name) = self.list.lookup_relative_address(relative_address)?;
+        Some(kind)
+    }
+
+    fn lookup_sync_u64(&self, address: LookupAddress64) -> Option<SyncAddressInfo64> {
+        let (svma, relative_address) = self.svma_and_relative_address(address)?;
+        let (start_addr, end_addr, name, kind) =
+            self.list.lookup_relative_address(relative_address)?;
         let function_size = end_addr - start_addr;
         let name = demangle::demangle_any(&name);
 
@@ -546,10 +707,11 @@ where
             string_interner.intern_owned(&name).into()
         };
 
-        let symbol = SymbolInfo {
+        let symbol = SymbolInfo64 {
             address: start_addr,
             size: Some(function_size),
             name: name_handle,
+            kind,
         };
 
         let mut frames = None;
@@ -595,7 +757,7 @@ where
                 ExternalLookupRequest::ReplyIfYouHaveOrTellMeWhatYouNeed,
             );
         }
-        Some(SyncAddressInfo { symbol, frames })
+        Some(SyncAddressInfo64 { symbol, frames })
     }
 
     fn resolve_function_name(&self, handle: FunctionNameHandle) -> Cow<'_, str> {
@@ -620,21 +782,24 @@ where
 }
 
 pub struct SymbolMapIter<'data, 'map, Symbol: object::ObjectSymbol<'data>> {
-    inner: slice::Iter<'map, (u32, FullSymbolListEntry<'data, Symbol>)>,
+    list: &'map SymbolList<'data, Symbol>,
+    index: usize,
 }
 
 impl<'data, 'map, Symbol: object::ObjectSymbol<'data>> Iterator
     for SymbolMapIter<'data, 'map, Symbol>
 {
-    type Item = (u32, Cow<'map, str>);
+    type Item = (u64, Cow<'map, str>);
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let (address, entry) = self.inner.next()?;
-            let Some(name) = entry.name(*address) else {
+            let entry = self.list.entries.get(self.index)?;
+            let address = self.list.addresses.get(self.index)?;
+            self.index += 1;
+            let Some(name) = entry.name(address) else {
                 continue;
             };
-            return Some((*address, name));
+            return Some((address, name));
         }
     }
 }
@@ -681,6 +846,7 @@ pub struct ObjectSymbolMapInnerWrapper<'data, FC>(
 );
 
 impl<'a, FC: FileContents + 'static> ObjectSymbolMapInnerWrapper<'a, FC> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<'file, O, Symbol, DDM>(
         object_file: &'file O,
         addr2line_context: Option<addr2line::Context<EndianSlice<'a, RunTimeEndian>>>,
@@ -688,8 +854,10 @@ impl<'a, FC: FileContents + 'static> ObjectSymbolMapInnerWrapper<'a, FC> {
         debug_id: DebugId,
         function_start_addresses: Option<&[u32]>,
         function_end_addresses: Option<&[u32]>,
+        layout_object_file: Option<&'file O>,
         dwo_dwarf_maker: &'a DDM,
-    ) -> Self
+        limits: &ParseLimits,
+    ) -> Result<Self, Error>
     where
         'a: 'file,
         O: object::Object<'a, Symbol<'file> = Symbol>,
@@ -702,7 +870,12 @@ impl<'a, FC: FileContents + 'static> ObjectSymbolMapInnerWrapper<'a, FC> {
             base_address,
             function_start_addresses,
             function_end_addresses,
-        );
+            limits,
+        )?;
+
+        // The section layout can come from a different file than the symbols,
+        // e.g. from the stripped binary if the symbols are from a debug file.
+        let layout_object_file = layout_object_file.unwrap_or(object_file);
 
         let inner = ObjectSymbolMapInner {
             list,
@@ -711,13 +884,15 @@ impl<'a, FC: FileContents + 'static> ObjectSymbolMapInnerWrapper<'a, FC> {
             context: addr2line_context.map(Mutex::new),
             dwp_package,
             image_base_address: base_address,
-            svma_file_ranges: SvmaFileRanges::from_object(object_file),
+            svma_file_ranges: SvmaFileRanges::from_object(layout_object_file),
+            section_kind_ranges: SectionKindRanges::from_object(layout_object_file),
             dwo_dwarf_maker,
             cached_external_file: Mutex::new(None),
+            preloaded_external_files: Mutex::new(HashMap::new()),
             string_interner: Mutex::new(SymbolMapStringInterner::new(SymbolMapGeneration::new())),
             _phantom: PhantomData,
         };
-        Self(Box::new(inner))
+        Ok(Self(Box::new(inner)))
     }
 }
 
@@ -767,6 +942,23 @@ where
             ExternalLookupRequest::UseThisMaybeAndReplyOrTellMeWhatElseYouNeed(file_contents),
         )
     }
+
+    fn add_external_file(&self, external_file: ExternalFileSymbolMap<FC>) {
+        let file_path = external_file.file_path().to_owned();
+        self.preloaded_external_files
+            .lock()
+            .unwrap()
+            .insert(file_path, external_file);
+    }
+
+    fn lookup_with_external_file(
+        &self,
+        external: &ExternalFileAddressRef,
+        external_file: &ExternalFileSymbolMap<FC>,
+    ) -> Option<Vec<FrameDebugInfo>> {
+        let mut string_interner = self.string_interner.lock().unwrap();
+        external_file.lookup(&external.address_in_file, &mut string_interner)
+    }
 }
 
 impl ExternalFileAddressRef {
@@ -5,7 +5,7 @@ use std::sync::{Arc, Mutex};
 
 use debugid::DebugId;
 use object::{File, FileKind};
-use pdb::PDB;
+use pdb::{FallibleIterator, PDB};
 use pdb_addr2line::pdb;
 use samply_object::debug_id_for_object;
 use yoke::Yoke;
@@ -14,10 +14,11 @@ use yoke_derive::Yokeable;
 use crate::dwarf::Addr2lineContextData;
 use crate::error::{Context, Error};
 use crate::generation::SymbolMapGeneration;
+use crate::limits::ParseLimits;
 use crate::mapped_path::UnparsedMappedPath;
 use crate::shared::{
     FileAndPathHelper, FileContents, FileContentsWrapper, FileLocation, FrameDebugInfo,
-    FramesLookupResult, LookupAddress, SymbolInfo,
+    FramesLookupResult, LookupAddress, SymbolInfo, SymbolKind,
 };
 use crate::symbol_map::{GetInnerSymbolMap, SymbolMap, SymbolMapTrait};
 use crate::symbol_map_object::{
@@ -33,6 +34,7 @@ pub async fn load_symbol_map_for_pdb_corresponding_to_binary<H: FileAndPathHelpe
     file_contents: &FileContentsWrapper<H::F>,
     file_location: H::FL,
     helper: &H,
+    limits: ParseLimits,
 ) -> Result<SymbolMap<H>, Error> {
     use object::Object;
     let pe =
@@ -53,7 +55,8 @@ pub async fn load_symbol_map_for_pdb_corresponding_to_binary<H: FileAndPathHelpe
         .load_file(pdb_location)
         .await
         .map_err(|e| Error::HelperErrorDuringOpenFile(pdb_path_str.to_string(), e))?;
-    let symbol_map = get_symbol_map_for_pdb(FileContentsWrapper::new(pdb_file), file_location)?;
+    let symbol_map =
+        get_symbol_map_for_pdb(FileContentsWrapper::new(pdb_file), file_location, limits)?;
     if symbol_map.debug_id() != binary_debug_id {
         return Err(Error::UnmatchedDebugId(
             binary_debug_id,
@@ -63,13 +66,33 @@ pub async fn load_symbol_map_for_pdb_corresponding_to_binary<H: FileAndPathHelpe
     Ok(symbol_map)
 }
 
+/// Returns whether the PE binary contains DWARF debug info.
+///
+/// MinGW / GCC toolchains don't produce PDB files. Instead, they put the DWARF
+/// sections into the PE binary itself, using long section names like `.debug_info`.
+pub fn pe_has_dwarf_debug_info<F: FileContents>(
+    file_contents: &FileContentsWrapper<F>,
+    file_kind: FileKind,
+) -> bool {
+    use object::{Object, ObjectSection};
+    if !matches!(file_kind, FileKind::Pe32 | FileKind::Pe64) {
+        return false;
+    }
+    let Ok(pe) = object::File::parse(file_contents) else {
+        return false;
+    };
+    pe.section_by_name(".debug_info")
+        .is_some_and(|section| section.size() != 0)
+}
+
 pub fn get_symbol_map_for_pe<H: FileAndPathHelper>(
     file_contents: FileContentsWrapper<H::F>,
     file_kind: FileKind,
     file_location: H::FL,
     helper: Arc<H>,
+    limits: ParseLimits,
 ) -> Result<SymbolMap<H>, Error> {
-    let owner = PeSymbolMapDataAndObject::new(file_contents, file_kind)?;
+    let owner = PeSymbolMapDataAndObject::new(file_contents, file_kind, limits)?;
     let symbol_map = ObjectSymbolMap::new(owner)?;
     Ok(SymbolMap::new_with_external_file_support(
         file_location,
@@ -89,11 +112,12 @@ impl<'data, T: FileContents> PeObject<'data, T> {
     pub fn new(
         file_data: &'data FileContentsWrapper<T>,
         object: File<'data, &'data FileContentsWrapper<T>>,
+        limits: ParseLimits,
     ) -> Self {
         Self {
             file_data,
             object,
-            addr2line_context: Addr2lineContextData::new(),
+            addr2line_context: Addr2lineContextData::with_parse_limits(limits),
         }
     }
 }
@@ -102,13 +126,17 @@ struct PeSymbolMapDataAndObject<T: FileContents + 'static>(
     Yoke<PeObject<'static, T>, Box<FileContentsWrapper<T>>>,
 );
 impl<T: FileContents + 'static> PeSymbolMapDataAndObject<T> {
-    pub fn new(file_data: FileContentsWrapper<T>, file_kind: FileKind) -> Result<Self, Error> {
+    pub fn new(
+        file_data: FileContentsWrapper<T>,
+        file_kind: FileKind,
+        limits: ParseLimits,
+    ) -> Result<Self, Error> {
         let data_and_object = Yoke::try_attach_to_cart(
             Box::new(file_data),
             move |file_data| -> Result<PeObject<'_, T>, Error> {
                 let object =
                     File::parse(file_data).map_err(|e| Error::ObjectParseError(file_kind, e))?;
-                Ok(PeObject::new(file_data, object))
+                Ok(PeObject::new(file_data, object, limits))
             },
         )?;
         Ok(Self(data_and_object))
@@ -125,17 +153,22 @@ impl<T: FileContents + 'static> ObjectSymbolMapOuter<T> for PeSymbolMapDataAndOb
         let debug_id = debug_id_for_object(object)
             .ok_or(Error::InvalidInputError("debug ID cannot be read"))?;
         let (function_starts, function_ends) = compute_function_addresses_pe(object);
+        let context = match addr2line_context.make_context(*file_data, object, None, None) {
+            Ok(context) => Some(context),
+            Err(e @ Error::ParseLimitExceeded(..)) => return Err(e),
+            Err(_) => None,
+        };
         let symbol_map = ObjectSymbolMapInnerWrapper::new(
             object,
-            addr2line_context
-                .make_context(*file_data, object, None, None)
-                .ok(),
+            context,
             None,
             debug_id,
             function_starts.as_deref(),
             function_ends.as_deref(),
+            None,
             &(),
-        );
+            &addr2line_context.parse_limits(),
+        )?;
 
         Ok(symbol_map)
     }
@@ -162,9 +195,12 @@ pub fn is_pdb_file<F: FileContents>(file: &FileContentsWrapper<F>) -> bool {
 }
 
 struct PdbObject<'data, FC: FileContents + 'static> {
-    context_data: pdb_addr2line::ContextPdbData<'data, 'data, &'data FileContentsWrapper<FC>>,
+    context_data: pdb_addr2line::ContextPdbData<'data, 'data, LimitedPdbSource<'data, FC>>,
     debug_id: DebugId,
     srcsrv_stream: Option<Box<dyn Deref<Target = [u8]> + Send + 'data>>,
+    /// The sorted start RVAs of the thunks generated by the linker.
+    thunk_rvas: Vec<u32>,
+    limits: ParseLimits,
 }
 
 trait PdbObjectTrait {
@@ -177,6 +213,7 @@ pub struct PdbObjectWrapper<'data>(Box<dyn PdbObjectTrait + Send + 'data>);
 impl<FC: FileContents + 'static> PdbObjectTrait for PdbObject<'_, FC> {
     fn make_pdb_symbol_map(&self) -> Result<PdbSymbolMapInner<'_>, Error> {
         let context = self.make_context()?;
+        self.limits.check_symbol_count(context.function_count())?;
 
         let path_mapper = match &self.srcsrv_stream {
             Some(srcsrv_stream) => Some(SrcSrvPathMapper::new(srcsrv::SrcSrvStream::parse(
@@ -188,6 +225,7 @@ impl<FC: FileContents + 'static> PdbObjectTrait for PdbObject<'_, FC> {
         let symbol_map = PdbSymbolMapInner {
             context,
             debug_id: self.debug_id,
+            thunk_rvas: &self.thunk_rvas,
             path_mapper: Mutex::new(path_mapper),
             string_interner: Mutex::new(SymbolMapStringInterner::new(SymbolMapGeneration::new())),
         };
@@ -236,6 +274,7 @@ pub struct PdbSymbolMapInnerWrapper<'data>(Box<dyn SymbolMapTrait + Send + 'data
 struct PdbSymbolMapInner<'object> {
     context: Box<dyn PdbAddr2lineContextTrait + Send + 'object>,
     debug_id: DebugId,
+    thunk_rvas: &'object [u32],
     path_mapper: Mutex<Option<SrcSrvPathMapper<'object>>>,
     string_interner: Mutex<SymbolMapStringInterner<'object>>,
 }
@@ -287,10 +326,16 @@ impl<'object> SymbolMapTrait for PdbSymbolMapInner<'object> {
         let mut string_interner = self.string_interner.lock().unwrap();
         let symbol_name_handle = string_interner.intern_owned(&symbol_name).into();
 
+        let kind = if self.thunk_rvas.binary_search(&symbol_address).is_ok() {
+            SymbolKind::ImportThunk
+        } else {
+            SymbolKind::Function
+        };
         let symbol = SymbolInfo {
             address: symbol_address,
             size: function_size,
             name: symbol_name_handle,
+            kind,
         };
         let frames = if has_debug_info(&function_frames) {
             let frames: Vec<_> = function_frames
@@ -347,7 +392,31 @@ where
     Box::new(stream)
 }
 
-struct PdbFileData<T: FileContents + 'static>(FileContentsWrapper<T>);
+struct PdbFileData<T: FileContents + 'static> {
+    file_contents: FileContentsWrapper<T>,
+    limits: ParseLimits,
+    /// The size of the first stream which exceeded the limits, if any.
+    exceeding_stream_size: Mutex<Option<u64>>,
+}
+
+impl<T: FileContents + 'static> PdbFileData<T> {
+    fn new(file_contents: FileContentsWrapper<T>, limits: ParseLimits) -> Self {
+        Self {
+            file_contents,
+            limits,
+            exceeding_stream_size: Mutex::new(None),
+        }
+    }
+
+    /// Replaces the error by a [`Error::ParseLimitExceeded`] if it was caused
+    /// by a stream which exceeded the limits.
+    fn check_limit_error(&self, error: Error) -> Error {
+        match *self.exceeding_stream_size.lock().unwrap() {
+            Some(size) => self.limits.check_section_size(size).err().unwrap_or(error),
+            None => error,
+        }
+    }
+}
 
 pub struct PdbObjectWithFileData<T: FileContents + 'static>(
     Yoke<PdbObjectWrapper<'static>, Box<PdbFileData<T>>>,
@@ -356,31 +425,77 @@ pub struct PdbObjectWithFileData<T: FileContents + 'static>(
 impl<T: FileContents + 'static> PdbObjectWithFileData<T> {
     fn new(file_data: PdbFileData<T>) -> Result<Self, Error> {
         let data_and_object = Yoke::try_attach_to_cart(Box::new(file_data), |file_data| {
-            let mut pdb = PDB::open(&file_data.0)?;
-            let info = pdb.pdb_information().context("pdb_information")?;
-            let dbi = pdb.debug_information()?;
-            let age = dbi.age().unwrap_or(info.age);
-            let debug_id = DebugId::from_parts(info.guid, age);
-
-            let srcsrv_stream = match pdb.named_stream(b"srcsrv") {
-                Ok(stream) => Some(box_stream(stream)),
-                Err(pdb::Error::StreamNameNotFound | pdb::Error::StreamNotFound(_)) => None,
-                Err(e) => return Err(Error::PdbError("pdb.named_stream(srcsrv)", e)),
-            };
-
-            let context_data = pdb_addr2line::ContextPdbData::try_from_pdb(pdb)
-                .context("ContextConstructionData::try_from_pdb")?;
-
-            let pdb_object = PdbObject {
-                context_data,
-                debug_id,
-                srcsrv_stream,
-            };
-
-            Ok(PdbObjectWrapper(Box::new(pdb_object)))
+            Self::make_object(file_data).map_err(|e| file_data.check_limit_error(e))
         })?;
         Ok(PdbObjectWithFileData(data_and_object))
     }
+
+    fn make_object(file_data: &PdbFileData<T>) -> Result<PdbObjectWrapper<'_>, Error> {
+        let mut pdb = PDB::open(LimitedPdbSource(file_data))?;
+        let info = pdb.pdb_information().context("pdb_information")?;
+        let dbi = pdb.debug_information()?;
+        let age = dbi.age().unwrap_or(info.age);
+        let debug_id = DebugId::from_parts(info.guid, age);
+
+        let srcsrv_stream = match pdb.named_stream(b"srcsrv") {
+            Ok(stream) => Some(box_stream(stream)),
+            Err(pdb::Error::StreamNameNotFound | pdb::Error::StreamNotFound(_)) => None,
+            Err(e) => return Err(Error::PdbError("pdb.named_stream(srcsrv)", e)),
+        };
+
+        let thunk_rvas = linker_thunk_rvas(&mut pdb).unwrap_or_default();
+
+        let context_data = pdb_addr2line::ContextPdbData::try_from_pdb(pdb)
+            .context("ContextConstructionData::try_from_pdb")?;
+
+        let pdb_object = PdbObject {
+            context_data,
+            debug_id,
+            srcsrv_stream,
+            thunk_rvas,
+            limits: file_data.limits,
+        };
+
+        Ok(PdbObjectWrapper(Box::new(pdb_object)))
+    }
+}
+
+/// Collects the start RVAs of the `S_THUNK32` records in the modules which
+/// are created by the linker: the "* Linker *" module, which has the
+/// incremental linking thunks, and the import library modules, which are
+/// named after the DLL they import from.
+///
+/// Only these modules are read, because reading all module streams is slow
+/// for large PDBs.
+fn linker_thunk_rvas<'s, S: pdb::Source<'s> + 's>(
+    pdb: &mut PDB<'s, S>,
+) -> Result<Vec<u32>, pdb::Error> {
+    let address_map = pdb.address_map()?;
+    let dbi = pdb.debug_information()?;
+    let mut modules = dbi.modules()?;
+    let mut thunk_rvas = Vec::new();
+    while let Some(module) = modules.next()? {
+        let module_name = module.module_name();
+        let is_linker_module =
+            module_name == "* Linker *" || module_name.to_ascii_lowercase().ends_with(".dll");
+        if !is_linker_module {
+            continue;
+        }
+        let Some(module_info) = pdb.module_info(&module)? else {
+            continue;
+        };
+        let mut symbols = module_info.symbols()?;
+        while let Some(symbol) = symbols.next()? {
+            if let Ok(pdb::SymbolData::Thunk(thunk)) = symbol.parse() {
+                if let Some(rva) = thunk.offset.to_rva(&address_map) {
+                    thunk_rvas.push(rva.0);
+                }
+            }
+        }
+    }
+    thunk_rvas.sort_unstable();
+    thunk_rvas.dedup();
+    Ok(thunk_rvas)
 }
 
 pub struct PdbSymbolMap<T: FileContents + 'static>(
@@ -393,7 +508,9 @@ impl<T: FileContents> PdbSymbolMap<T> {
             Box::new(outer),
             |outer| -> Result<PdbSymbolMapInnerWrapper<'_>, Error> {
                 let maker = outer.0.get().0.as_ref();
-                let symbol_map = maker.make_pdb_symbol_map()?;
+                let symbol_map = maker
+                    .make_pdb_symbol_map()
+                    .map_err(|e| outer.0.backing_cart().check_limit_error(e))?;
                 Ok(PdbSymbolMapInnerWrapper(Box::new(symbol_map)))
             },
         )?;
@@ -454,8 +571,9 @@ impl<T: FileContents> SymbolMapTrait for PdbSymbolMap<T> {
 pub fn get_symbol_map_for_pdb<H: FileAndPathHelper>(
     file_contents: FileContentsWrapper<H::F>,
     debug_file_location: H::FL,
+    limits: ParseLimits,
 ) -> Result<SymbolMap<H>, Error> {
-    let file_data_and_object = PdbObjectWithFileData::new(PdbFileData(file_contents))?;
+    let file_data_and_object = PdbObjectWithFileData::new(PdbFileData::new(file_contents, limits))?;
     let symbol_map = PdbSymbolMap::new(file_data_and_object)?;
     Ok(SymbolMap::new_plain(
         debug_file_location,
@@ -615,6 +733,35 @@ impl<'s, F: FileContents> pdb::Source<'s> for &'s FileContentsWrapper<F> {
     }
 }
 
+/// A [`pdb::Source`] which refuses to read streams that exceed the section
+/// size limit, and remembers that it did.
+struct LimitedPdbSource<'s, F: FileContents + 'static>(&'s PdbFileData<F>);
+
+impl<F: FileContents> std::fmt::Debug for LimitedPdbSource<'_, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LimitedPdbSource({:?})", self.0.file_contents)
+    }
+}
+
+impl<'s, F: FileContents> pdb::Source<'s> for LimitedPdbSource<'s, F> {
+    fn view(
+        &mut self,
+        slices: &[pdb::SourceSlice],
+    ) -> std::result::Result<Box<dyn pdb::SourceView<'s> + Send + Sync>, std::io::Error> {
+        let len = slices.iter().fold(0, |acc, s| acc + s.size as u64);
+        if let Err(e) = self.0.limits.check_section_size(len) {
+            self.0
+                .exceeding_stream_size
+                .lock()
+                .unwrap()
+                .get_or_insert(len);
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, e));
+        }
+        let mut file_contents = &self.0.file_contents;
+        file_contents.view(slices)
+    }
+}
+
 /// Get the function start addresses (in rva form) from the .pdata section.
 /// This section has the addresses for functions with unwind info. That means
 /// it only covers a subset of functions; it does not include entries for
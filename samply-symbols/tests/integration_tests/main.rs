@@ -1,12 +1,17 @@
+use std::borrow::Cow;
 use std::fs::File;
 use std::io::{BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use samply_symbols::debugid::DebugId;
 use samply_symbols::{
-    self, CandidatePathInfo, CompactSymbolTable, Error, FileAndPathHelper, FileAndPathHelperResult,
-    FileLocation, LibraryInfo, LookupAddress, MultiArchDisambiguator, OptionallySendFuture,
-    SymbolManager, SymbolMap,
+    self, AddressKind, CandidatePathInfo, CompactSymbolTable, Error, FileAndPathHelper,
+    FileAndPathHelperResult, FileLocation, FramesLookupResult, FunctionNameHandle, LibraryInfo,
+    LookupAddress, LookupAddress64, MappedPathRules, MultiArchDisambiguator, OptionallySendFuture,
+    ParseLimits, SourceFilePath, SourceFilePathHandle, SourcePathPrefixMap, SymbolCache,
+    SymbolInfo, SymbolKind, SymbolManager, SymbolMap, SymbolMapGeneration, SymbolMapTrait,
+    SymbolNameHandle, SymbolNameIndex, SyncAddressInfo,
 };
 
 async fn get_symbol_map_with_dyld_cache_fallback(
@@ -170,11 +175,17 @@ impl FileAndPathHelper for Helper {
             )));
         }
 
-        // Finally, the file itself.
+        // Then the file itself.
         paths.push(CandidatePathInfo::SingleFile(FileLocationType(
             self.symbol_directory.join(debug_name),
         )));
 
+        // Finally, .debug files, which are usually only available after the
+        // binary itself, e.g. from debuginfod.
+        paths.push(CandidatePathInfo::SingleFile(FileLocationType(
+            self.symbol_directory.join(format!("{debug_name}.debug")),
+        )));
+
         Ok(paths)
     }
 
@@ -487,6 +498,79 @@ fn linux_nonzero_base_address() {
     );
 }
 
+#[test]
+fn classify_addresses() {
+    let dir = fixtures_dir().join("linux64-ci");
+    let helper = Helper {
+        symbol_directory: dir.clone(),
+    };
+    let symbol_manager = SymbolManager::with_helper(helper);
+    let location = FileLocationType(dir.join("firefox"));
+    let symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(location.clone(), None),
+    )
+    .unwrap();
+    let binary_image = futures::executor::block_on(
+        symbol_manager.load_binary_at_location(location, None, None, None),
+    )
+    .unwrap();
+
+    // The image base is 0x200000. .rodata is at 0x200730, .text at 0x2018a0,
+    // .data at 0x203d90, and .bss at 0x203df8.
+    for (relative_address, expected) in [
+        (0x740, AddressKind::Data),
+        (0x18a0, AddressKind::Text),
+        (0x1ad1, AddressKind::Text),
+        (0x1ad2, AddressKind::Unknown),
+        (0x3d90, AddressKind::Data),
+        (0x3df8, AddressKind::Data),
+        (0x10000, AddressKind::Unknown),
+    ] {
+        assert_eq!(
+            symbol_map.classify_address(LookupAddress::Relative(relative_address)),
+            Some(expected),
+            "{relative_address:#x}"
+        );
+        assert_eq!(
+            binary_image.classify_relative_address(relative_address),
+            expected,
+            "{relative_address:#x}"
+        );
+    }
+    assert_eq!(
+        symbol_map.classify_address(LookupAddress::Svma(0x2018a0)),
+        Some(AddressKind::Text)
+    );
+}
+
+#[test]
+fn symbol_kinds() {
+    let dir = fixtures_dir().join("linux64-ci");
+    let helper = Helper {
+        symbol_directory: dir.clone(),
+    };
+    let symbol_manager = SymbolManager::with_helper(helper);
+    let symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(FileLocationType(dir.join("firefox")), None),
+    )
+    .unwrap();
+
+    let result = symbol_map
+        .lookup_sync(LookupAddress::Relative(0x19a0))
+        .unwrap();
+    assert_eq!(symbol_map.resolve_symbol_name(result.symbol.name), "main");
+    assert_eq!(result.symbol.kind, SymbolKind::Function);
+
+    // The .plt section at 0x201b00 has no symbols for its stubs.
+    let result = symbol_map
+        .lookup_sync(LookupAddress::Relative(0x1b30))
+        .unwrap();
+    assert_eq!(symbol_map.resolve_symbol_name(result.symbol.name), ".plt");
+    assert_eq!(result.symbol.address, 0x1b00);
+    assert_eq!(result.symbol.size, Some(0x90));
+    assert_eq!(result.symbol.kind, SymbolKind::PltStub);
+}
+
 #[test]
 fn example_linux() {
     let helper = Helper {
@@ -536,6 +620,408 @@ fn example_linux_fallback() {
     assert_eq!(symbol_map.lookup_sync(LookupAddress::Relative(0x6)), None);
 }
 
+#[test]
+fn stripped_binary_with_separate_debug_file() {
+    let dir = fixtures_dir().join("other").join("split-debug").join("out");
+    let helper = Helper {
+        symbol_directory: dir.clone(),
+    };
+    let symbol_manager = SymbolManager::with_helper(helper);
+
+    // The stripped binary only has the dynamic symbols, so internal_function
+    // only gets a placeholder name from the unwind info.
+    let stripped_symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(FileLocationType(dir.join("split")), None),
+    )
+    .unwrap();
+    let result = stripped_symbol_map
+        .lookup_sync(LookupAddress::Relative(0x1139))
+        .unwrap();
+    assert_eq!(result.symbol.kind, SymbolKind::SectionPlaceholder);
+
+    // The binary comes before split.debug in the candidate list, but the
+    // symbols and the DWARF from split.debug should be used.
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map(&LibraryInfo {
+        debug_name: Some("split".to_string()),
+        debug_id: Some(stripped_symbol_map.debug_id()),
+        ..Default::default()
+    }))
+    .unwrap();
+    let result = symbol_map
+        .lookup_sync(LookupAddress::Relative(0x1139))
+        .unwrap();
+    assert_eq!(
+        symbol_map.resolve_symbol_name(result.symbol.name),
+        "internal_function"
+    );
+    let Some(FramesLookupResult::Available(frames)) = &result.frames else {
+        panic!("Expected debug info for internal_function");
+    };
+    assert_eq!(frames.last().unwrap().line_number, Some(4));
+    assert_eq!(
+        symbol_map.classify_address(LookupAddress::Relative(0x1139)),
+        Some(AddressKind::Text)
+    );
+}
+
+#[test]
+fn source_path_prefix_map() {
+    let dir = fixtures_dir().join("other").join("prefix-map").join("out");
+    let helper = Helper {
+        symbol_directory: dir.clone(),
+    };
+    let mut symbol_manager = SymbolManager::with_helper(helper);
+    let location = FileLocationType(dir.join("main"));
+
+    let resolve_path = |symbol_manager: &SymbolManager<Helper>| {
+        let symbol_map = futures::executor::block_on(
+            symbol_manager.load_symbol_map_from_location(location.clone(), None),
+        )
+        .unwrap();
+        let result = symbol_map
+            .lookup_sync(LookupAddress::Relative(0x1129))
+            .unwrap();
+        let Some(FramesLookupResult::Available(frames)) = &result.frames else {
+            panic!("Expected debug info for add_one");
+        };
+        let file_path = frames.last().unwrap().file_path.unwrap();
+        symbol_map
+            .resolve_source_file_path(file_path)
+            .raw_path()
+            .to_owned()
+    };
+
+    // The relative DW_AT_name is resolved against DW_AT_comp_dir.
+    assert_eq!(
+        resolve_path(&symbol_manager),
+        "/build/prefix-map/src/main.c"
+    );
+
+    let mut map = SourcePathPrefixMap::new();
+    map.add_rule("/build/prefix-map", "/home/user/prefix-map");
+    symbol_manager.set_source_path_prefix_map(map);
+    assert_eq!(
+        resolve_path(&symbol_manager),
+        "/home/user/prefix-map/src/main.c"
+    );
+}
+
+#[test]
+fn custom_mapped_path_rules() {
+    let dir = fixtures_dir().join("other").join("prefix-map").join("out");
+    let helper = Helper {
+        symbol_directory: dir.clone(),
+    };
+    let mut symbol_manager = SymbolManager::with_helper(helper);
+    let mut rules = MappedPathRules::new();
+    rules
+        .add_rule("/build/*", "git:github.com/example/prefix-map:{path}:main")
+        .unwrap();
+    symbol_manager.set_mapped_path_rules(rules);
+    let mut map = SourcePathPrefixMap::new();
+    map.add_rule("/build/prefix-map", "/home/user/prefix-map");
+    symbol_manager.set_source_path_prefix_map(map);
+
+    let symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(FileLocationType(dir.join("main")), None),
+    )
+    .unwrap();
+    let result = symbol_map
+        .lookup_sync(LookupAddress::Relative(0x1129))
+        .unwrap();
+    let Some(FramesLookupResult::Available(frames)) = &result.frames else {
+        panic!("Expected debug info for add_one");
+    };
+    let path = symbol_map.resolve_source_file_path(frames.last().unwrap().file_path.unwrap());
+
+    // The rules are matched against the original path, before the prefix map.
+    assert_eq!(path.raw_path(), "/home/user/prefix-map/src/main.c");
+    assert_eq!(
+        path.special_path_str().as_deref(),
+        Some("git:github.com/example/prefix-map:src/main.c:main")
+    );
+    assert_eq!(path.display_path(), "src/main.c");
+}
+
+#[test]
+fn shared_symbol_cache() {
+    let dir = fixtures_dir().join("other").join("split-debug").join("out");
+    let library_info = LibraryInfo {
+        debug_name: Some("split".to_string()),
+        debug_id: Some(DebugId::from_breakpad("8B2050FC954C95F6E212DFCF8B7F810F0").unwrap()),
+        ..Default::default()
+    };
+    let file_size = |name: &str| std::fs::metadata(dir.join(name)).unwrap().len();
+    let cache = Arc::new(SymbolCache::new(100_000_000));
+
+    let mut symbol_manager = SymbolManager::with_helper(Helper {
+        symbol_directory: dir.clone(),
+    });
+    symbol_manager.set_symbol_cache(cache.clone());
+    let symbol_map =
+        futures::executor::block_on(symbol_manager.load_symbol_map(&library_info)).unwrap();
+    drop(symbol_manager);
+    drop(symbol_map);
+    assert_eq!(cache.size(), file_size("split") + file_size("split.debug"));
+
+    // This symbol manager can't find any files, so the symbol map has to come
+    // from the cache.
+    let mut symbol_manager = SymbolManager::with_helper(Helper {
+        symbol_directory: fixtures_dir().join("nonexistent"),
+    });
+    symbol_manager.set_symbol_cache(cache.clone());
+    let symbol_map =
+        futures::executor::block_on(symbol_manager.load_symbol_map(&library_info)).unwrap();
+    let result = symbol_map
+        .lookup_sync(LookupAddress::Relative(0x1139))
+        .unwrap();
+    assert_eq!(
+        symbol_map.resolve_symbol_name(result.symbol.name),
+        "internal_function"
+    );
+
+    cache.clear();
+    assert_eq!(cache.size(), 0);
+    assert!(futures::executor::block_on(symbol_manager.load_symbol_map(&library_info)).is_err());
+
+    // Files which are larger than the cache aren't cached.
+    let small_cache = Arc::new(SymbolCache::new(1000));
+    let mut symbol_manager = SymbolManager::with_helper(Helper {
+        symbol_directory: dir.clone(),
+    });
+    symbol_manager.set_symbol_cache(small_cache.clone());
+    futures::executor::block_on(symbol_manager.load_symbol_map(&library_info)).unwrap();
+    assert_eq!(small_cache.size(), 0);
+}
+
+#[test]
+fn symbol_cache_count_limit() {
+    let dir = fixtures_dir().join("win64-ci");
+    let firefox_info = LibraryInfo {
+        debug_name: Some("firefox.pdb".to_string()),
+        debug_id: Some(DebugId::from_breakpad("AA152DEB2D9B76084C4C44205044422E1").unwrap()),
+        ..Default::default()
+    };
+    let mozglue_info = LibraryInfo {
+        debug_name: Some("mozglue.pdb".to_string()),
+        debug_id: Some(DebugId::from_breakpad("63C609072D3499F64C4C44205044422E1").unwrap()),
+        ..Default::default()
+    };
+    let cache = Arc::new(SymbolCache::with_limits(Some(100_000_000), Some(1)));
+
+    let mut symbol_manager = SymbolManager::with_helper(Helper {
+        symbol_directory: dir,
+    });
+    symbol_manager.set_symbol_cache(cache.clone());
+    futures::executor::block_on(symbol_manager.load_symbol_map(&firefox_info)).unwrap();
+    assert_eq!(cache.len(), 1);
+    futures::executor::block_on(symbol_manager.load_symbol_map(&mozglue_info)).unwrap();
+    assert_eq!(cache.len(), 1);
+
+    // Only the most recently used symbol map is still in the cache.
+    let mut symbol_manager = SymbolManager::with_helper(Helper {
+        symbol_directory: fixtures_dir().join("nonexistent"),
+    });
+    symbol_manager.set_symbol_cache(cache.clone());
+    assert!(futures::executor::block_on(symbol_manager.load_symbol_map(&mozglue_info)).is_ok());
+    assert!(futures::executor::block_on(symbol_manager.load_symbol_map(&firefox_info)).is_err());
+}
+
+/// A symbol map with a single symbol, standing in for symbols from a custom
+/// format.
+struct SingleSymbolMap {
+    debug_id: DebugId,
+    generation: SymbolMapGeneration,
+}
+
+impl SymbolMapTrait for SingleSymbolMap {
+    fn debug_id(&self) -> DebugId {
+        self.debug_id
+    }
+
+    fn symbol_count(&self) -> usize {
+        1
+    }
+
+    fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_> {
+        Box::new(std::iter::once((0x1000, Cow::Borrowed("custom_symbol"))))
+    }
+
+    fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
+        let LookupAddress::Relative(0x1000..=0x10ff) = address else {
+            return None;
+        };
+        Some(SyncAddressInfo {
+            symbol: SymbolInfo {
+                address: 0x1000,
+                size: Some(0x100),
+                name: self.generation.symbol_name_handle(SymbolNameIndex(0)),
+                kind: SymbolKind::Function,
+            },
+            frames: None,
+        })
+    }
+
+    fn resolve_function_name(&self, _handle: FunctionNameHandle) -> Cow<'_, str> {
+        unreachable!()
+    }
+
+    fn resolve_symbol_name(&self, handle: SymbolNameHandle) -> Cow<'_, str> {
+        self.generation.unwrap_symbol_name_index(handle);
+        Cow::Borrowed("custom_symbol")
+    }
+
+    fn resolve_source_file_path(&self, _handle: SourceFilePathHandle) -> SourceFilePath<'_> {
+        unreachable!()
+    }
+}
+
+#[test]
+fn registered_symbol_map() {
+    let dir = fixtures_dir().join("other").join("split-debug").join("out");
+    let debug_id = DebugId::from_breakpad("8B2050FC954C95F6E212DFCF8B7F810F0").unwrap();
+    let mut symbol_manager = SymbolManager::with_helper(Helper {
+        symbol_directory: dir.clone(),
+    });
+    symbol_manager.register_symbol_map(
+        "split",
+        debug_id,
+        FileLocationType(dir.join("split.custom")),
+        Arc::new(SingleSymbolMap {
+            debug_id,
+            generation: SymbolMapGeneration::new(),
+        }),
+    );
+    let lookup = |symbol_manager: &SymbolManager<Helper>, library_info: LibraryInfo| {
+        let symbol_map =
+            futures::executor::block_on(symbol_manager.load_symbol_map(&library_info)).unwrap();
+        let result = symbol_map
+            .lookup_sync(LookupAddress::Relative(0x1020))
+            .unwrap();
+        symbol_map
+            .resolve_symbol_name(result.symbol.name)
+            .to_string()
+    };
+
+    // The registered symbol map is used instead of the files in the symbol directory.
+    let info = LibraryInfo {
+        debug_name: Some("split".to_string()),
+        debug_id: Some(debug_id),
+        ..Default::default()
+    };
+    assert_eq!(lookup(&symbol_manager, info.clone()), "custom_symbol");
+    let info_without_name = LibraryInfo {
+        debug_id: Some(debug_id),
+        ..Default::default()
+    };
+    assert_eq!(lookup(&symbol_manager, info_without_name), "custom_symbol");
+
+    symbol_manager.unregister_symbol_map("split", debug_id);
+    assert_ne!(lookup(&symbol_manager, info), "custom_symbol");
+}
+
+#[test]
+fn large_module_u64_relative_addresses() {
+    let dir = fixtures_dir()
+        .join("other")
+        .join("large-module")
+        .join("out");
+    let helper = Helper {
+        symbol_directory: dir.clone(),
+    };
+    let symbol_manager = SymbolManager::with_helper(helper);
+    let symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(FileLocationType(dir.join("main")), None),
+    )
+    .unwrap();
+    assert!(symbol_map.has_u64_relative_addresses());
+
+    // far_function is at SVMA 0x200001000, and the first segment is at 0x400000.
+    let far_address = 0x2_0000_1000 - 0x40_0000;
+    let result = symbol_map
+        .lookup_sync_u64(LookupAddress64::Relative(far_address + 2))
+        .unwrap();
+    assert_eq!(result.symbol.address, far_address);
+    assert_eq!(
+        symbol_map.resolve_symbol_name(result.symbol.name),
+        "far_function"
+    );
+    let Some(FramesLookupResult::Available(frames)) = &result.frames else {
+        panic!("Expected debug info for far_function");
+    };
+    assert_eq!(
+        symbol_map.resolve_function_name(frames.last().unwrap().function.unwrap()),
+        "far_function"
+    );
+    assert_eq!(
+        symbol_map
+            .lookup_sync_u64(LookupAddress64::Svma(0x2_0000_1002))
+            .unwrap(),
+        result
+    );
+    assert_eq!(
+        symbol_map.lookup_sync(LookupAddress::Svma(0x2_0000_1002)),
+        None
+    );
+
+    // Addresses below 4 GiB work with both APIs.
+    let result = symbol_map
+        .lookup_sync(LookupAddress::Relative(0x1000))
+        .unwrap();
+    assert_eq!(
+        symbol_map.resolve_symbol_name(result.symbol.name),
+        "low_function"
+    );
+
+    let symbols: Vec<_> = symbol_map.iter_symbols().map(|(_, name)| name).collect();
+    assert!(!symbols.contains(&"far_function".into()));
+    let symbols: Vec<_> = symbol_map
+        .iter_symbols_u64()
+        .map(|(address, name)| (address, name.into_owned()))
+        .collect();
+    assert!(symbols.contains(&(far_address, "far_function".to_string())));
+}
+
+#[test]
+fn section_size_limit() {
+    let load = |path: PathBuf, max_section_size: Option<u64>| {
+        let helper = Helper {
+            symbol_directory: path.parent().unwrap().to_path_buf(),
+        };
+        let mut symbol_manager = SymbolManager::with_helper(helper);
+        symbol_manager.set_parse_limits(ParseLimits {
+            max_section_size,
+            ..Default::default()
+        });
+        futures::executor::block_on(
+            symbol_manager.load_symbol_map_from_location(FileLocationType(path), None),
+        )
+    };
+
+    let pdb_path = fixtures_dir().join("win64-local").join("mozglue.pdb");
+    assert!(matches!(
+        load(pdb_path.clone(), Some(4096)),
+        Err(Error::ParseLimitExceeded("Section size", _, 4096))
+    ));
+    assert!(load(pdb_path, Some(64 * 1024 * 1024)).is_ok());
+
+    let dwarf_path = fixtures_dir()
+        .join("other")
+        .join("simple-example")
+        .join("out")
+        .join("mac-dsym")
+        .join("main.dSYM")
+        .join("Contents")
+        .join("Resources")
+        .join("DWARF")
+        .join("main");
+    assert!(matches!(
+        load(dwarf_path.clone(), Some(16)),
+        Err(Error::ParseLimitExceeded("Section size", _, 16))
+    ));
+    assert!(load(dwarf_path, None).is_ok());
+}
+
 #[test]
 fn compare_snapshot() {
     let table = futures::executor::block_on(crate::get_table(
@@ -4,14 +4,22 @@ use std::str::FromStr;
 use std::time::Duration;
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
 
-use super::cli_utils::{parse_time_range, split_at_first_equals};
-use super::server::{PortSelection, ServerProps};
+use super::cli_utils::{
+    parse_only_lib_filter, parse_sampling_period, parse_source_path_prefix_mapping,
+    parse_time_range, split_at_first_equals,
+};
+use super::merge::MergeTimeAlignment;
+use super::server::{CorsPolicy, PortSelection, ServerProps};
 use super::shared::included_processes::IncludedProcesses;
 use super::shared::prop_types::{
-    CoreClrProfileProps, ImportProps, ProcessLaunchProps, ProfileCreationProps, RecordingMode,
-    RecordingProps, SymbolProps,
+    CaptureWindowProps, CoreClrProfileProps, ImportProps, OnlyLibFilter, ProcessLaunchProps,
+    ProfileCreationProps, RecordingMode, RecordingProps, SamplingPeriod, SymbolProps, UnwindMode,
 };
+#[cfg(target_os = "linux")]
+use super::shared::prop_types::{PageFaultMode, SamplingClock};
+use super::shared::save_profile::ProfileFileFormat;
 
 #[derive(Debug, Parser)]
 #[command(
@@ -34,11 +42,59 @@ EXAMPLES:
 
     # Import perf.data files from Linux perf or Android simpleperf:
     samply import perf.data
+
+    # Import ETW traces captured with wpr or xperf (Windows only):
+    samply import trace.etl
+
+    # Import stacks aggregated by DTrace, e.g. with `-n 'profile-997 { @[ustack()] = count(); }'`:
+    samply import dtrace-output.txt
+
+    # Share a profile by uploading it to profiler.firefox.com:
+    samply upload prof.json
+
+    # Combine the profiles of several runs into one profile:
+    samply merge run1.json.gz run2.json.gz -o merged.json.gz
+
+    # Compare a profile from before an optimization with one from after:
+    samply diff before.json.gz after.json.gz
+
+    # Convert a profile into folded stacks for flamegraph.pl or inferno:
+    samply export --format=collapsed prof.json -o prof.folded
+
+    # Convert a profile for `go tool pprof`:
+    samply export --format=pprof prof.json -o prof.pb.gz
+
+    # Convert a profile for Perfetto UI or chrome://tracing:
+    samply export --format=chrome prof.json -o trace.json
+
+    # Print the function, file and line of addresses in a binary, like addr2line:
+    samply symbolicate -e ./yourcommand -f -i 0x1f3a 0x2b40
+
+    # Write a Breakpad .sym file for a binary, like dump_syms:
+    samply dump-syms ./yourcommand -o yourcommand.sym
 "#
 )]
 pub struct Opt {
     #[command(subcommand)]
     pub action: Action,
+
+    /// Read defaults for options like --rate, --symbol-server, --port and the
+    /// output directory from this TOML file, instead of from
+    /// ~/.config/samply/config.toml. Must come before the subcommand.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// How to print log messages. With "json", log messages are printed as
+    /// JSON objects, one per line, and `samply record` finishes with a JSON
+    /// summary of the recording, e.g. how many samples were captured or lost.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value = "text",
+        global = true
+    )]
+    pub log_format: LogFormatArg,
 }
 
 #[derive(Debug, Subcommand)]
@@ -52,12 +108,38 @@ pub enum Action {
     /// Record a profile and display it.
     Record(RecordArgs),
 
-    /// Load a profile from a file and display it.
+    /// Load one or more profiles from files and display them.
     Load(LoadArgs),
 
-    /// Import a perf.data file and display the profile.
+    /// Import a perf.data file, an ETW trace (.etl, Windows only) or DTrace stack
+    /// output and display the profile.
     Import(ImportArgs),
 
+    /// Upload a profile file to the Firefox Profiler and print the public URL.
+    Upload(UploadArgs),
+
+    /// Merge several profile files into one multi-process profile and display it.
+    Merge(MergeArgs),
+
+    /// Compare two profiles of the same program and print the functions whose
+    /// share of the samples changed the most.
+    Diff(DiffArgs),
+
+    /// Convert a profile into a format that other tools can consume.
+    Export(ExportArgs),
+
+    /// Run a symbol server which answers symbolication requests for any
+    /// profile until it is stopped with Ctrl+C.
+    SymbolServer(SymbolServerArgs),
+
+    /// Print the function, file and line for addresses in a binary, like
+    /// addr2line. The addresses are read from the command line or from stdin.
+    Symbolicate(SymbolicateArgs),
+
+    /// Write the symbols of a binary in the Breakpad .sym format, like
+    /// dump_syms.
+    DumpSyms(DumpSymsArgs),
+
     #[cfg(target_os = "windows")]
     #[clap(hide = true)]
     /// Used in the elevated helper process.
@@ -70,9 +152,156 @@ pub enum Action {
 
 #[derive(Debug, Args)]
 pub struct LoadArgs {
-    /// Path to the file that should be loaded.
+    /// Paths to the files that should be loaded. Each profile is served under
+    /// its own URL from the same server, and opened in its own browser tab.
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub server_args: ServerArgs,
+
+    #[command(flatten)]
+    pub symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct SymbolServerArgs {
+    /// Use this session token in the server URL instead of a random one, so
+    /// that the URL stays the same when the server is restarted. Must only
+    /// contain ASCII letters, digits, '-' and '_'.
+    #[arg(long)]
+    pub token: Option<String>,
+
+    /// How many loaded symbol files are kept in memory, so that further
+    /// requests for the same libraries are answered without loading them again.
+    #[arg(long, default_value = "64", value_name = "COUNT")]
+    pub symbol_map_cache_size: usize,
+
+    #[command(flatten)]
+    pub server_args: ServerArgs,
+
+    #[command(flatten)]
+    pub symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct SymbolicateArgs {
+    /// The binary or debug file which contains the addresses.
+    #[arg(
+        short = 'e',
+        long,
+        value_name = "PATH",
+        required_unless_present = "debug_name"
+    )]
+    pub exe: Option<PathBuf>,
+
+    /// Find the symbols by debug name instead, e.g. "xul.pdb" or "libxul.so",
+    /// in the symbol directories and on the symbol servers. The addresses are
+    /// relative to the image base address.
+    #[arg(long, requires = "debug_id", conflicts_with = "exe")]
+    pub debug_name: Option<String>,
+
+    /// The breakpad ID of the library given with --debug-name, e.g.
+    /// "B1E5C4B3A9E14A4C9CE5FFA7F5D1E2F31".
+    #[arg(long, value_name = "BREAKPAD_ID", requires = "debug_name")]
+    pub debug_id: Option<String>,
+
+    /// Print the function name for each address.
+    #[arg(short, long)]
+    pub functions: bool,
+
+    /// Also print the functions which were inlined at each address.
+    #[arg(short, long)]
+    pub inlines: bool,
+
+    /// Print each address before its location.
+    #[arg(short, long)]
+    pub addresses: bool,
+
+    /// Print all information for an address on one line.
+    #[arg(short, long = "pretty-print")]
+    pub pretty_print: bool,
+
+    /// Only print the file name of each source file, without its directory.
+    #[arg(short = 's', long)]
+    pub basenames: bool,
+
+    /// Accepted for compatibility with addr2line. Function names are always
+    /// demangled.
+    #[arg(short = 'C', long)]
+    pub demangle: bool,
+
+    /// The addresses are relative to the image base address, rather than
+    /// addresses in the binary's virtual address space.
+    #[arg(long)]
+    pub relative: bool,
+
+    /// The hex addresses to look up, e.g. "0x1f3a". If none are given, they
+    /// are read from stdin, one per line.
+    pub addrs: Vec<String>,
+
+    #[command(flatten)]
+    pub symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct DumpSymsArgs {
+    /// The binary or debug file to dump. Debug files for a binary are found
+    /// like for symbolication, e.g. PDB files next to the EXE or on a symbol
+    /// server.
+    pub binary: PathBuf,
+
+    /// The architecture to dump from a universal binary, e.g. "arm64" or
+    /// "x86_64".
+    #[arg(short, long)]
+    pub arch: Option<String>,
+
+    /// Write the .sym file to this path instead of stdout.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct UploadArgs {
+    /// Path to the profile file that should be uploaded.
     pub file: PathBuf,
 
+    /// Don't ask for confirmation before uploading.
+    #[arg(short = 'y', long)]
+    pub yes: bool,
+
+    #[command(flatten)]
+    pub symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    /// Paths to the profile files that should be merged.
+    #[arg(required = true, num_args = 2..)]
+    pub files: Vec<PathBuf>,
+
+    /// Shift the profiles so that they all start at the same time, instead of
+    /// keeping them at their original wall-clock times. This is useful for
+    /// comparing repeated runs.
+    #[arg(long)]
+    pub align_start: bool,
+
+    /// Do not run a local server after merging.
+    #[arg(short, long)]
+    pub save_only: bool,
+
+    /// Output filename.
+    #[arg(short, long, default_value = "merged.json.gz")]
+    pub output: PathBuf,
+
+    /// Output file format. If not specified, the format is picked based on
+    /// the output filename: names ending in ".gz" are gzip-compressed.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub output_format: Option<OutputFormatArg>,
+
     #[command(flatten)]
     pub server_args: ServerArgs,
 
@@ -80,6 +309,49 @@ pub struct LoadArgs {
     pub symbol_args: SymbolArgs,
 }
 
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// The profile to compare against, e.g. from before an optimization.
+    pub before: PathBuf,
+
+    /// The profile to compare, e.g. from after an optimization.
+    pub after: PathBuf,
+
+    /// How many functions to print.
+    #[arg(long, default_value = "30")]
+    pub top: usize,
+
+    /// Also write a profile which contains the threads of both profiles, with
+    /// negated sample weights for the "before" threads. Select the threads of
+    /// both profiles together in the Firefox Profiler to see the difference in
+    /// the call tree.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Output file format. If not specified, the format is picked based on
+    /// the output filename: names ending in ".gz" are gzip-compressed.
+    #[arg(long, value_enum, value_name = "FORMAT", requires = "output")]
+    pub output_format: Option<OutputFormatArg>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Path to the profile file that should be exported.
+    pub file: PathBuf,
+
+    /// The format to convert the profile to.
+    #[arg(long, value_enum)]
+    pub format: ExportFormatArg,
+
+    /// Where to write the exported profile. If not specified, the output is
+    /// written to stdout.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub symbol_args: SymbolArgs,
+}
+
 #[derive(Debug, Args)]
 pub struct ImportArgs {
     /// Path to the profile file that should be imported.
@@ -99,6 +371,11 @@ pub struct ImportArgs {
     #[arg(short, long, default_value = "profile.json.gz")]
     pub output: PathBuf,
 
+    /// Output file format. If not specified, the format is picked based on
+    /// the output filename: names ending in ".gz" are gzip-compressed.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub output_format: Option<OutputFormatArg>,
+
     #[command(flatten)]
     pub server_args: ServerArgs,
 
@@ -137,14 +414,43 @@ pub struct RecordArgs {
     #[arg(short, long)]
     pub duration: Option<f64>,
 
-    /// How many times to run the profiled command.
-    #[arg(long, default_value = "1")]
+    /// How many times to run the profiled command. Each run shows up as a
+    /// separate process; use --reuse-threads to merge them into one. On Linux,
+    /// each run also gets an "Iteration" marker covering its wall-clock time.
+    #[arg(long, visible_alias = "iterations", default_value = "1")]
     pub iteration_count: u32,
 
     /// Ignore exit code and continue running when iteration_count > 0
     #[arg(short, long)]
     pub ignore_exit_code: bool,
 
+    /// Copy each line that the launched command writes to stdout or stderr into
+    /// the profile, as a marker on the process's main thread (Linux only). The
+    /// output is still printed as usual.
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    pub capture_output: bool,
+
+    /// Record the launched command's heap allocations (Linux only). malloc,
+    /// calloc, realloc and free are intercepted through LD_PRELOAD, and one
+    /// allocation is sampled, with its stack, every BYTES allocated bytes
+    /// (default 131072). The profile gets an allocation track weighted by bytes
+    /// and a counter for the sampled memory which is still allocated.
+    ///
+    /// The preload library is not built or installed with samply. Build
+    /// libsamply_linux_alloc_preload.so from the samply-linux-alloc-preload
+    /// directory of the samply repository, and put it next to the samply
+    /// binary or set SAMPLY_ALLOC_PRELOAD_LIB to its path.
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[arg(
+        long,
+        value_name = "BYTES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "131072"
+    )]
+    pub alloc: Option<u64>,
+
     #[command(flatten)]
     pub profile_creation_args: ProfileCreationArgs,
 
@@ -156,6 +462,21 @@ pub struct RecordArgs {
     #[arg(short, long, default_value = "profile.json.gz")]
     pub output: PathBuf,
 
+    /// Output file format. If not specified, the format is picked based on
+    /// the output filename: names ending in ".gz" are gzip-compressed.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub output_format: Option<OutputFormatArg>,
+
+    /// Upload the profile to the Firefox Profiler after recording and print the
+    /// public URL. This implies --presymbolicate, so that the shared profile
+    /// contains function names.
+    #[arg(long)]
+    pub upload: bool,
+
+    /// Don't ask for confirmation before uploading the profile.
+    #[arg(long, requires = "upload")]
+    pub yes: bool,
+
     #[command(flatten)]
     pub server_args: ServerArgs,
 
@@ -196,6 +517,238 @@ pub struct RecordArgs {
     #[cfg(target_os = "windows")]
     #[arg(long)]
     pub keep_etl: bool,
+
+    /// Write the raw perf records to a temporary file while recording, and
+    /// convert them into a profile only once recording has stopped (Linux only).
+    /// This keeps samply's memory usage flat during long recordings.
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    pub spill_to_disk: bool,
+
+    /// Flight recorder mode: only keep the samples of the last SECONDS seconds
+    /// in memory. Whenever samply receives SIGUSR2, it writes a profile of that
+    /// window next to the output file, e.g. "profile-snapshot-1.json.gz".
+    /// The profile that is written when recording stops also only covers this
+    /// window (Linux only).
+    #[cfg(target_os = "linux")]
+    #[arg(long, value_name = "SECONDS", conflicts_with = "spill_to_disk")]
+    pub flight_recorder: Option<f64>,
+
+    /// Experimental: serve the profile while recording, e.g. "--live" or
+    /// "--live=5". Every SECONDS seconds (2 by default), the output file is replaced with a profile of the
+    /// last 30 seconds; reload the profiler tab to see the new data. Once recording stops, the output file
+    /// contains the entire recording (Linux only).
+    #[cfg(target_os = "linux")]
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "2",
+        conflicts_with_all = ["spill_to_disk", "flight_recorder", "save_only"]
+    )]
+    pub live: Option<f64>,
+
+    /// Also record the kernel part of the call stack of each sample (Linux only).
+    /// This requires /proc/sys/kernel/perf_event_paranoid to be set to 1 or
+    /// lower. Kernel frames are symbolicated with /proc/kallsyms, which needs
+    /// /proc/sys/kernel/kptr_restrict to be set to 0 for non-root users.
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    pub kernel_stacks: bool,
+
+    /// How to unwind the user part of each sampled stack (Linux only).
+    #[cfg(target_os = "linux")]
+    #[arg(long, value_enum, default_value_t = UnwindArg::Dwarf)]
+    pub unwind: UnwindArg,
+
+    /// Sample the CPU's energy counters every 100ms and add them to the profile
+    /// as power tracks (Linux and macOS only). On Linux, the values come from RAPL
+    /// via /sys/class/powercap, which usually can only be read as root. On macOS,
+    /// they come from IOReport's "Energy Model" channels, which are only
+    /// available on Apple Silicon.
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[arg(long)]
+    pub power: bool,
+
+    /// Trace syscalls and add a marker for each one, on the thread that made it
+    /// (Linux only). Takes a comma-separated list of syscall names, e.g.
+    /// --syscalls=read,write,futex, or "all". Without a list, common I/O,
+    /// polling, sleeping and futex syscalls are traced. This uses the
+    /// raw_syscalls tracepoints, which need root or perf_event_paranoid -1.
+    #[cfg(target_os = "linux")]
+    #[arg(
+        long,
+        value_name = "SYSCALLS",
+        value_delimiter = ',',
+        num_args = 0..=1,
+        require_equals = true
+    )]
+    pub syscalls: Option<Vec<String>>,
+
+    /// Record page faults with their stacks and add a marker for each one, on
+    /// the thread that faulted (Linux only). With --page-faults or
+    /// --page-faults=all, page faults are sampled at the sampling rate; with
+    /// --page-faults=major, every major fault, i.e. every fault which had to
+    /// wait for I/O, is recorded.
+    #[cfg(target_os = "linux")]
+    #[arg(
+        long,
+        value_enum,
+        value_name = "KIND",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "all"
+    )]
+    pub page_faults: Option<PageFaultArg>,
+
+    /// Also sample threads while they are off-CPU (Linux only). Stacks are
+    /// recorded when a thread blocks, and the samples for its off-CPU time get
+    /// a root frame which says whether it was blocked, blocked on I/O, or
+    /// waiting for a CPU. This uses the sched:sched_switch tracepoint, which
+    /// needs root or perf_event_paranoid -1.
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    pub wall_clock: bool,
+
+    /// Probe a function and add a marker with the stack whenever it's called,
+    /// on the thread that called it (Linux only). Takes LIB:SYMBOL for a
+    /// function in a library or executable, e.g. libc.so.6:malloc, or
+    /// kernel:SYMBOL for a kernel function, e.g. kernel:do_sys_openat2. LIB can
+    /// be a path or a file name. Can be given multiple times. This uses uprobes
+    /// and kprobes, which usually need root.
+    #[cfg(target_os = "linux")]
+    #[arg(long = "probe", value_name = "PROBE")]
+    pub probes: Vec<String>,
+
+    /// On CPUs with different core types, like Intel's P-cores and E-cores or
+    /// ARM's big and LITTLE cores, add a track for each core type with the
+    /// samples which ran on cores of that type (Linux only).
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    pub core_types: bool,
+
+    /// Sample a software clock instead of the hardware cycles event (Linux
+    /// only). By default, the cycles event is sampled, and cpu-clock is used if
+    /// the cycles event isn't available, for example in VMs.
+    #[cfg(target_os = "linux")]
+    #[arg(long, value_enum, value_name = "CLOCK")]
+    pub clock: Option<ClockArg>,
+
+    /// How often to sample, instead of --rate (Linux only): a frequency, e.g.
+    /// "4000hz", an interval in microseconds, e.g. "250us", or a number of
+    /// events between samples, e.g. "100000events". The events are cycles for
+    /// the cycles event and nanoseconds for the clock events. The effective
+    /// event and period are recorded in the profile's info.
+    #[cfg(target_os = "linux")]
+    #[arg(
+        long,
+        value_name = "PERIOD",
+        value_parser = parse_sampling_period,
+        conflicts_with = "rate"
+    )]
+    pub period: Option<SamplingPeriod>,
+
+    /// Use precise sampling (PEBS on Intel, IBS on AMD) for the hardware cycles
+    /// event, which reduces the skid between the instruction that overflowed
+    /// the counter and the sampled instruction (Linux only). LEVEL is perf's
+    /// precise_ip, from 0 to 3; --precise on its own asks for 3, zero skid.
+    /// Lower levels are tried if the CPU doesn't support the requested one.
+    /// The level that was used is recorded in the profile's info.
+    #[cfg(target_os = "linux")]
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        value_parser = clap::value_parser!(u8).range(0..=3),
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "3"
+    )]
+    pub precise: Option<u8>,
+
+    /// Drain the perf buffers on this many background threads, with the
+    /// buffers of each CPU drained by one of them (Linux only). By default the
+    /// buffers are drained on the thread which also processes the records. On
+    /// machines with many CPUs, that thread can fall behind, and the kernel
+    /// drops records once the buffers are full.
+    #[cfg(target_os = "linux")]
+    #[arg(long, value_name = "COUNT", value_parser = clap::value_parser!(u32).range(1..))]
+    pub reader_threads: Option<u32>,
+
+    /// With --all, only record the processes in this cgroup and its descendant
+    /// cgroups, e.g. a container's processes (Linux only). The path is relative
+    /// to /sys/fs/cgroup, e.g. system.slice/docker-<id>.scope, or absolute.
+    /// Binaries are found through /proc/<pid>/root, so that binaries inside
+    /// containers are symbolicated; this needs the processes to still be
+    /// running when the profile is symbolicated.
+    #[cfg(target_os = "linux")]
+    #[arg(long, value_name = "PATH", requires = "all")]
+    pub cgroup: Option<PathBuf>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PageFaultArg {
+    /// All page faults, sampled.
+    All,
+    /// Every major page fault.
+    Major,
+}
+
+#[cfg(target_os = "linux")]
+impl PageFaultArg {
+    fn page_fault_mode(self) -> PageFaultMode {
+        match self {
+            PageFaultArg::All => PageFaultMode::All,
+            PageFaultArg::Major => PageFaultMode::Major,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClockArg {
+    /// The task clock, which only runs while the recorded tasks are running.
+    TaskClock,
+    /// The CPU clock, a timer which runs per CPU.
+    CpuClock,
+}
+
+#[cfg(target_os = "linux")]
+impl ClockArg {
+    fn sampling_clock(self) -> SamplingClock {
+        match self {
+            ClockArg::TaskClock => SamplingClock::TaskClock,
+            ClockArg::CpuClock => SamplingClock::CpuClock,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnwindArg {
+    /// Frame pointer unwinding, done by the kernel. This is the cheapest option,
+    /// but only works for code that was compiled with frame pointers, e.g. with
+    /// -fno-omit-frame-pointer or -C force-frame-pointers=yes.
+    Fp,
+    /// DWARF unwinding of a copy of the stack taken with each sample. This works
+    /// for code without frame pointers, at a higher overhead.
+    Dwarf,
+    /// The call stack from the CPU's last branch record (LBR). This is cheap but
+    /// limited to the innermost 16 to 32 frames, and only available on Intel
+    /// CPUs which support it.
+    Lbr,
+}
+
+#[cfg(target_os = "linux")]
+impl UnwindArg {
+    fn unwind_mode(self) -> UnwindMode {
+        match self {
+            UnwindArg::Fp => UnwindMode::FramePointers,
+            UnwindArg::Dwarf => UnwindMode::Dwarf,
+            UnwindArg::Lbr => UnwindMode::Lbr,
+        }
+    }
 }
 
 #[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
@@ -220,6 +773,44 @@ impl std::fmt::Display for CoreClrArgs {
     }
 }
 
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormatArg {
+    /// Uncompressed JSON.
+    Json,
+    /// Gzip-compressed JSON.
+    JsonGz,
+}
+
+impl OutputFormatArg {
+    pub fn profile_file_format(output_format: Option<Self>, output: &Path) -> ProfileFileFormat {
+        match output_format {
+            Some(OutputFormatArg::Json) => ProfileFileFormat::Json,
+            Some(OutputFormatArg::JsonGz) => ProfileFileFormat::JsonGz,
+            None => ProfileFileFormat::from_path(output),
+        }
+    }
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LogFormatArg {
+    /// Human-readable text.
+    Text,
+    /// One JSON object per line, on stderr.
+    Json,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExportFormatArg {
+    /// Folded stacks with their sample weights, one line per stack, as consumed
+    /// by flamegraph.pl and inferno.
+    Collapsed,
+    /// Gzip-compressed pprof protobuf, as consumed by `go tool pprof`.
+    Pprof,
+    /// JSON in the Chrome Trace Event format, which can be opened in Perfetto UI
+    /// and chrome://tracing.
+    Chrome,
+}
+
 #[derive(Debug, Args)]
 pub struct ServerArgs {
     /// Do not open the profiler UI.
@@ -234,6 +825,33 @@ pub struct ServerArgs {
     #[arg(short = 'P', long, default_value = "3000+")]
     pub port: String,
 
+    /// Allow binding the web server to an address other than localhost.
+    ///
+    /// This makes the profile and your local symbol files available to
+    /// anyone on the network who knows the server URL, so it is off by default.
+    /// Use this together with --address, e.g. "--address 0.0.0.0 --allow-remote",
+    /// when the browser runs on a different machine.
+    #[arg(long)]
+    pub allow_remote: bool,
+
+    /// Only allow web pages from this origin to use the server from the
+    /// browser, e.g. "https://profiler.firefox.com" or the origin of a
+    /// self-hosted profiler. Can be given multiple times. By default, pages
+    /// from any origin which know the server URL may use it.
+    #[arg(long, value_name = "ORIGIN")]
+    pub cors_origin: Vec<String>,
+
+    /// How many seconds browsers may cache the answer to a CORS preflight request.
+    #[arg(long, default_value = "86400", value_name = "SECONDS")]
+    pub cors_max_age: u32,
+
+    /// Cache the results of symbolication requests in this directory, so that
+    /// libraries which were symbolicated before are symbolicated quickly, also
+    /// after the server has been restarted. The directory is never cleaned up
+    /// automatically.
+    #[arg(long, value_name = "DIR")]
+    pub symbolication_cache: Option<PathBuf>,
+
     /// Print debugging output.
     #[arg(short, long)]
     pub verbose: bool,
@@ -246,6 +864,21 @@ pub struct SymbolArgs {
     #[arg(long)]
     pub symbol_dir: Vec<PathBuf>,
 
+    /// Additional URLs of symbol servers. Each server is used for Windows
+    /// (PDB / DLL / EXE), Breakpad and debuginfod lookups.
+    #[arg(long, value_name = "URL")]
+    pub symbol_server: Vec<String>,
+
+    /// Overrides the default cache directory for symbol files which were downloaded
+    /// from a symbol server. Files in this directory are not cleaned up automatically.
+    #[arg(long, value_name = "DIR")]
+    pub symbol_cache: Option<PathBuf>,
+
+    /// Don't download symbols from symbol servers or from debuginfod; only use
+    /// local files.
+    #[arg(long)]
+    pub no_network_symbols: bool,
+
     /// Additional URLs of symbol servers serving PDB / DLL / EXE files
     #[arg(long)]
     pub windows_symbol_server: Vec<String>,
@@ -269,6 +902,12 @@ pub struct SymbolArgs {
     /// Extra directory containing symbol files, with the directory structure used by simpleperf's scripts
     #[arg(long)]
     pub simpleperf_binary_cache: Option<PathBuf>,
+
+    /// Replace a path prefix in the source file paths from the debug info, e.g.
+    /// "/build/firefox=/home/me/firefox" if the code was compiled in /build/firefox.
+    /// Can be specified multiple times; the first matching prefix is used.
+    #[arg(long, value_name = "FROM=TO", value_parser = parse_source_path_prefix_mapping)]
+    pub source_path_prefix_map: Vec<(String, String)>,
 }
 
 #[derive(Debug, Args, Clone)]
@@ -290,6 +929,34 @@ pub struct ProfileCreationArgs {
     #[arg(long)]
     pub main_thread_only: bool,
 
+    /// Only include threads whose name matches this regular expression,
+    /// e.g. --threads Renderer. Unnamed threads are dropped when this is set.
+    #[arg(long, value_name = "REGEX")]
+    pub threads: Option<Regex>,
+
+    /// Only keep the samples which were taken after a marker with this name,
+    /// e.g. one from a marker file. Each occurrence of the marker starts a new
+    /// capture window, which lasts until --capture-stop-marker or
+    /// --capture-duration, or until the end of the profile.
+    #[arg(long, value_name = "NAME")]
+    pub capture_start_marker: Option<String>,
+
+    /// End each capture window at the end of the next marker with this name.
+    #[arg(long, value_name = "NAME", requires = "capture_start_marker")]
+    pub capture_stop_marker: Option<String>,
+
+    /// End each capture window after this many seconds, if it hasn't been ended
+    /// by --capture-stop-marker yet.
+    #[arg(long, value_name = "SECONDS", requires = "capture_start_marker")]
+    pub capture_duration: Option<f64>,
+
+    /// Keep the profile JSON below roughly this many megabytes. Larger profiles
+    /// get fewer samples, by merging consecutive samples into one, while
+    /// markers and library information are kept. This keeps very long
+    /// recordings loadable in the profiler.
+    #[arg(long, value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_profile_size: Option<u64>,
+
     /// Merge non-overlapping threads of the same name.
     #[arg(long)]
     pub reuse_threads: bool,
@@ -298,6 +965,18 @@ pub struct ProfileCreationArgs {
     #[arg(long)]
     pub fold_recursive_prefix: bool,
 
+    /// Limit stacks to at most N frames. Deeper stacks keep their N innermost
+    /// frames and get a "(truncated)" frame at the root.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_stack_depth: Option<u64>,
+
+    /// Only keep the samples whose leaf frame is in this library, e.g.
+    /// --only-lib libxul.so, or in an address range like
+    /// 0x7f0000000000-0x7f0000100000. Only respected on Linux and for
+    /// imported perf.data files.
+    #[arg(long, value_name = "LIB|START-END", value_parser = parse_only_lib_filter)]
+    pub only_lib: Option<OnlyLibFilter>,
+
     /// If a process produces jitdump or marker files, unlink them after
     /// opening. This ensures that the files will not be left in /tmp,
     /// but it will also be impossible to look at JIT disassembly, and line
@@ -305,7 +984,9 @@ pub struct ProfileCreationArgs {
     #[arg(long)]
     pub unlink_aux_files: bool,
 
-    /// Create a separate thread for each CPU. Not supported on macOS
+    /// Create a separate thread for each CPU, and mark the samples at which a
+    /// thread migrated to a different CPU. Not supported on macOS, which doesn't
+    /// tell us which CPU a sampled thread was running on.
     #[arg(long)]
     pub per_cpu_threads: bool,
 
@@ -324,8 +1005,26 @@ pub struct ProfileCreationArgs {
     #[arg(long, default_value = "0", num_args=0..=1, require_equals = true, default_missing_value = "100")]
     pub include_args: usize,
 
+    /// Never include command line arguments in the profile, even with
+    /// --include-args, so that processes are only named by their executable.
+    /// Use this for profiles which will be shared, since arguments can contain
+    /// secrets such as tokens.
+    #[arg(long)]
+    pub redact_args: bool,
+
+    /// Redact the values of environment variable assignments, e.g.
+    /// TOKEN=<redacted>, in the command line arguments which --include-args
+    /// puts into process names. The environment of the recorded processes is
+    /// never stored in the profile.
+    #[arg(long)]
+    pub redact_env: bool,
+
     /// Generate a symbolicated profile file by looking up symbol information before writing out the file.
     ///
+    /// The written profile contains function names, file names, line numbers and
+    /// inlined frames, so it can be archived or opened on a machine without the
+    /// original binaries, and no symbol requests are needed when viewing it.
+    ///
     /// This is off by default because symbol resolution can take a long time, so
     /// the default behavior is to have the front-end apply symbols asynchronously.
     #[arg(long)]
@@ -360,6 +1059,90 @@ impl LoadArgs {
     }
 }
 
+impl SymbolServerArgs {
+    pub fn server_props(&self) -> ServerProps {
+        if let Some(token) = &self.token {
+            let is_valid = !token.is_empty()
+                && token
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !is_valid {
+                eprintln!(
+                    "Error: The token {token:?} must only contain ASCII letters, digits, '-' and '_'."
+                );
+                std::process::exit(1)
+            }
+        }
+        ServerProps {
+            token: self.token.clone(),
+            ..self.server_args.server_props()
+        }
+    }
+
+    pub fn symbol_props(&self) -> SymbolProps {
+        SymbolProps {
+            symbol_map_cache_size: self.symbol_map_cache_size,
+            ..self.symbol_args.symbol_props()
+        }
+    }
+}
+
+impl MergeArgs {
+    pub fn server_props(&self) -> Option<ServerProps> {
+        if self.save_only {
+            None
+        } else {
+            Some(self.server_args.server_props())
+        }
+    }
+
+    pub fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+
+    pub fn output_format(&self) -> ProfileFileFormat {
+        OutputFormatArg::profile_file_format(self.output_format, &self.output)
+    }
+
+    pub fn time_alignment(&self) -> MergeTimeAlignment {
+        if self.align_start {
+            MergeTimeAlignment::Start
+        } else {
+            MergeTimeAlignment::WallClock
+        }
+    }
+}
+
+impl DiffArgs {
+    pub fn output_format(&self, output: &Path) -> ProfileFileFormat {
+        OutputFormatArg::profile_file_format(self.output_format, output)
+    }
+}
+
+impl UploadArgs {
+    pub fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+}
+
+impl DumpSymsArgs {
+    pub fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+}
+
+impl SymbolicateArgs {
+    pub fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+}
+
+impl ExportArgs {
+    pub fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+}
+
 impl ImportArgs {
     pub fn server_props(&self) -> Option<ServerProps> {
         if self.save_only {
@@ -392,6 +1175,10 @@ impl ImportArgs {
         }
     }
 
+    pub fn output_format(&self) -> ProfileFileFormat {
+        OutputFormatArg::profile_file_format(self.output_format, &self.output)
+    }
+
     pub fn import_props(&self) -> ImportProps {
         ImportProps {
             profile_creation_props: self.profile_creation_props(),
@@ -418,6 +1205,10 @@ impl RecordArgs {
         self.symbol_args.symbol_props()
     }
 
+    pub fn output_format(&self) -> ProfileFileFormat {
+        OutputFormatArg::profile_file_format(self.output_format, &self.output)
+    }
+
     #[allow(unused)]
     pub fn recording_props(&self) -> RecordingProps {
         let time_limit = self.duration.map(Duration::from_secs_f64);
@@ -428,11 +1219,44 @@ impl RecordArgs {
             );
             std::process::exit(1);
         }
-        let interval = Duration::from_secs_f64(1.0 / self.rate);
+        #[cfg(target_os = "linux")]
+        let sampling_period = self.period;
+        #[cfg(not(target_os = "linux"))]
+        let sampling_period = None;
+        let interval = match sampling_period {
+            Some(SamplingPeriod::Frequency(frequency)) => {
+                Duration::from_secs_f64(1.0 / frequency as f64)
+            }
+            Some(SamplingPeriod::Interval(interval)) => interval,
+            Some(SamplingPeriod::Events(_)) | None => Duration::from_secs_f64(1.0 / self.rate),
+        };
+        #[cfg(target_os = "linux")]
+        if let Some(window) = self.flight_recorder {
+            if window <= 0.0 {
+                eprintln!(
+                    "Error: the flight recorder window must be greater than zero, got {window}"
+                );
+                std::process::exit(1);
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(live_interval) = self.live {
+            if live_interval <= 0.0 {
+                eprintln!(
+                    "Error: the live snapshot interval must be greater than zero, got {live_interval}"
+                );
+                std::process::exit(1);
+            }
+        }
         RecordingProps {
             output_file: self.output.clone(),
             time_limit,
             interval,
+            #[cfg(target_os = "linux")]
+            sampling_clock: self.clock.map(ClockArg::sampling_clock),
+            #[cfg(not(target_os = "linux"))]
+            sampling_clock: None,
+            sampling_period,
             gfx: self.gfx,
             browsers: self.browsers,
             #[cfg(target_os = "windows")]
@@ -443,6 +1267,62 @@ impl RecordArgs {
             keep_etl: self.keep_etl,
             #[cfg(not(target_os = "windows"))]
             keep_etl: false,
+            #[cfg(target_os = "linux")]
+            spill_to_disk: self.spill_to_disk,
+            #[cfg(not(target_os = "linux"))]
+            spill_to_disk: false,
+            #[cfg(target_os = "linux")]
+            flight_recorder_window: self.flight_recorder.map(Duration::from_secs_f64),
+            #[cfg(not(target_os = "linux"))]
+            flight_recorder_window: None,
+            #[cfg(target_os = "linux")]
+            live_snapshot_interval: self.live.map(Duration::from_secs_f64),
+            #[cfg(not(target_os = "linux"))]
+            live_snapshot_interval: None,
+            #[cfg(target_os = "linux")]
+            kernel_stacks: self.kernel_stacks,
+            #[cfg(not(target_os = "linux"))]
+            kernel_stacks: false,
+            #[cfg(target_os = "linux")]
+            unwind_mode: self.unwind.unwind_mode(),
+            #[cfg(not(target_os = "linux"))]
+            unwind_mode: UnwindMode::Dwarf,
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            power: self.power,
+            #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+            power: false,
+            #[cfg(target_os = "linux")]
+            syscalls: self.syscalls.clone(),
+            #[cfg(not(target_os = "linux"))]
+            syscalls: None,
+            #[cfg(target_os = "linux")]
+            page_faults: self.page_faults.map(PageFaultArg::page_fault_mode),
+            #[cfg(not(target_os = "linux"))]
+            page_faults: None,
+            #[cfg(target_os = "linux")]
+            wall_clock: self.wall_clock,
+            #[cfg(not(target_os = "linux"))]
+            wall_clock: false,
+            #[cfg(target_os = "linux")]
+            probes: self.probes.clone(),
+            #[cfg(not(target_os = "linux"))]
+            probes: Vec::new(),
+            #[cfg(target_os = "linux")]
+            core_types: self.core_types,
+            #[cfg(not(target_os = "linux"))]
+            core_types: false,
+            #[cfg(target_os = "linux")]
+            precise_ip: self.precise,
+            #[cfg(not(target_os = "linux"))]
+            precise_ip: None,
+            #[cfg(target_os = "linux")]
+            reader_threads: self.reader_threads.map(|count| count as usize),
+            #[cfg(not(target_os = "linux"))]
+            reader_threads: None,
+            #[cfg(target_os = "linux")]
+            cgroup: self.cgroup.clone(),
+            #[cfg(not(target_os = "linux"))]
+            cgroup: None,
         }
     }
 
@@ -476,6 +1356,14 @@ impl RecordArgs {
             args,
             iteration_count,
             ignore_exit_code: self.ignore_exit_code,
+            #[cfg(target_os = "linux")]
+            capture_output: self.capture_output,
+            #[cfg(not(target_os = "linux"))]
+            capture_output: false,
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            alloc_sample_interval: self.alloc,
+            #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+            alloc_sample_interval: None,
         };
 
         RecordingMode::Launch(launch_props)
@@ -492,8 +1380,12 @@ impl RecordArgs {
                 filename.to_string_lossy().into()
             }
         };
-        self.profile_creation_args
-            .profile_creation_props_with_fallback_name(fallback_profile_name)
+        let mut props = self
+            .profile_creation_args
+            .profile_creation_props_with_fallback_name(fallback_profile_name);
+        // Uploaded profiles can't be symbolicated by a local server later.
+        props.presymbolicate |= self.upload;
+        props
     }
 }
 
@@ -515,6 +1407,14 @@ impl ProfileCreationArgs {
         }
     }
 
+    pub fn capture_window_props(&self) -> Option<CaptureWindowProps> {
+        Some(CaptureWindowProps {
+            start_marker: self.capture_start_marker.clone()?,
+            stop_marker: self.capture_stop_marker.clone(),
+            max_duration: self.capture_duration.map(Duration::from_secs_f64),
+        })
+    }
+
     pub fn profile_creation_props_with_fallback_name(
         &self,
         fallback_profile_name: String,
@@ -523,11 +1423,21 @@ impl ProfileCreationArgs {
             profile_name: self.profile_name.clone(),
             fallback_profile_name,
             main_thread_only: self.main_thread_only,
+            thread_name_filter: self.threads.clone(),
+            capture_window: self.capture_window_props(),
+            max_profile_size: self.max_profile_size.map(|mb| mb * 1_000_000),
             reuse_threads: self.reuse_threads,
             fold_recursive_prefix: self.fold_recursive_prefix,
+            max_stack_depth: self.max_stack_depth.map(|depth| depth as usize),
+            only_lib: self.only_lib.clone(),
             unlink_aux_files: self.unlink_aux_files,
             create_per_cpu_threads: self.per_cpu_threads,
-            arg_count_to_include_in_process_name: self.include_args,
+            arg_count_to_include_in_process_name: if self.redact_args {
+                0
+            } else {
+                self.include_args
+            },
+            redact_env: self.redact_env,
             override_arch: None,
             presymbolicate: self.presymbolicate,
             should_emit_jit_markers: self.jit_markers,
@@ -567,11 +1477,24 @@ impl ServerArgs {
             }
         };
 
+        if !address.is_loopback() && !self.allow_remote {
+            eprintln!(
+                "Error: Refusing to bind the server to the non-localhost address {address} without --allow-remote."
+            );
+            std::process::exit(1)
+        }
+
         ServerProps {
             address,
             port_selection,
             verbose: self.verbose,
             open_in_browser,
+            cors: CorsPolicy {
+                allowed_origins: self.cors_origin.clone(),
+                preflight_max_age: self.cors_max_age,
+            },
+            token: None,
+            symbolication_cache_dir: self.symbolication_cache.clone(),
         }
     }
 }
@@ -580,12 +1503,17 @@ impl SymbolArgs {
     pub fn symbol_props(&self) -> SymbolProps {
         SymbolProps {
             symbol_dir: self.symbol_dir.clone(),
+            symbol_server: self.symbol_server.clone(),
+            symbol_cache: self.symbol_cache.clone(),
+            no_network_symbols: self.no_network_symbols,
             windows_symbol_server: self.windows_symbol_server.clone(),
             windows_symbol_cache: self.windows_symbol_cache.clone(),
             breakpad_symbol_server: self.breakpad_symbol_server.clone(),
             breakpad_symbol_dir: self.breakpad_symbol_dir.clone(),
             breakpad_symbol_cache: self.breakpad_symbol_cache.clone(),
             simpleperf_binary_cache: self.simpleperf_binary_cache.clone(),
+            source_path_prefix_map: self.source_path_prefix_map.clone(),
+            symbol_map_cache_size: 0,
         }
     }
 }
@@ -620,8 +1548,269 @@ mod test {
             "Arguments which come before the command name should be treated as samply arguments."
         );
 
+        let opt = Opt::parse_from(["samply", "record", "--iterations", "3", "rustup"]);
+        assert!(
+            matches!(opt.action, Action::Record(record_args) if record_args.iteration_count == 3)
+        );
+
         // Make sure you can't pass both a pid and a command name at the same time.
         let opt_res = Opt::try_parse_from(["samply", "record", "-p", "1234", "rustup"]);
         assert!(opt_res.is_err());
     }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn verify_cli_record_period() {
+        let opt = Opt::parse_from(["samply", "record", "--period", "250us", "rustup"]);
+        assert!(
+            matches!(opt.action, Action::Record(record_args) if record_args.period == Some(SamplingPeriod::Interval(Duration::from_micros(250))))
+        );
+
+        let opt = Opt::parse_from(["samply", "record", "--period", "4000Hz", "rustup"]);
+        assert!(
+            matches!(opt.action, Action::Record(record_args) if record_args.period == Some(SamplingPeriod::Frequency(4000)))
+        );
+
+        let opt = Opt::parse_from(["samply", "record", "--period", "100000events", "rustup"]);
+        assert!(
+            matches!(opt.action, Action::Record(record_args) if record_args.period == Some(SamplingPeriod::Events(100000)))
+        );
+
+        // The period needs a unit and must be greater than zero.
+        assert!(Opt::try_parse_from(["samply", "record", "--period", "250", "rustup"]).is_err());
+        assert!(Opt::try_parse_from(["samply", "record", "--period", "0us", "rustup"]).is_err());
+
+        // --period replaces --rate.
+        let opt_res = Opt::try_parse_from([
+            "samply", "record", "--rate", "100", "--period", "250us", "rustup",
+        ]);
+        assert!(opt_res.is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn verify_cli_record_live() {
+        let opt = Opt::parse_from(["samply", "record", "--live", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("Expected record action");
+        };
+        assert_eq!(
+            record_args.recording_props().live_snapshot_interval,
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(record_args.command, ["rustup"]);
+
+        let opt = Opt::parse_from(["samply", "record", "--live=5", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("Expected record action");
+        };
+        assert_eq!(
+            record_args.recording_props().live_snapshot_interval,
+            Some(Duration::from_secs(5))
+        );
+
+        // The live profile is served, so it can't be combined with --save-only.
+        let opt_res = Opt::try_parse_from(["samply", "record", "--live", "-s", "rustup"]);
+        assert!(opt_res.is_err());
+    }
+
+    #[test]
+    fn verify_cli_only_lib() {
+        let opt = Opt::parse_from(["samply", "record", "--only-lib", "libgtk-3.so.0", "rustup"]);
+        assert!(
+            matches!(opt.action, Action::Record(record_args) if record_args.profile_creation_args.only_lib == Some(OnlyLibFilter::Lib("libgtk-3.so.0".to_string())))
+        );
+
+        let opt = Opt::parse_from([
+            "samply",
+            "record",
+            "--only-lib",
+            "0x7f0000001000-0x7f0000002000",
+            "rustup",
+        ]);
+        assert!(
+            matches!(opt.action, Action::Record(record_args) if record_args.profile_creation_args.only_lib == Some(OnlyLibFilter::AddressRange(0x7f0000001000, 0x7f0000002000)))
+        );
+
+        assert!(
+            Opt::try_parse_from(["samply", "record", "--only-lib", "0x2000-0x1000", "rustup"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn verify_cli_redact_args() {
+        let opt = Opt::parse_from([
+            "samply",
+            "record",
+            "--include-args=2",
+            "--redact-args",
+            "rustup",
+        ]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected a record action");
+        };
+        let props = record_args.profile_creation_props();
+        assert_eq!(props.arg_count_to_include_in_process_name, 0);
+    }
+
+    #[test]
+    fn verify_cli_server_address() {
+        let opt = Opt::parse_from([
+            "samply",
+            "load",
+            "--address",
+            "0.0.0.0",
+            "--allow-remote",
+            "-P",
+            "4000",
+            "--cors-origin",
+            "https://profiler.example.com",
+            "prof.json",
+        ]);
+        let Action::Load(load_args) = opt.action else {
+            panic!("Expected load action");
+        };
+        let server_props = load_args.server_props();
+        assert_eq!(server_props.address, IpAddr::from([0, 0, 0, 0]));
+        assert!(matches!(
+            server_props.port_selection,
+            PortSelection::OnePort(4000)
+        ));
+        assert_eq!(
+            server_props.cors.allowed_origins,
+            ["https://profiler.example.com"]
+        );
+        assert_eq!(server_props.cors.preflight_max_age, 86400);
+    }
+
+    #[test]
+    fn verify_cli_symbol_server() {
+        let opt = Opt::parse_from([
+            "samply",
+            "symbol-server",
+            "-P",
+            "4000",
+            "--token",
+            "my-token",
+            "--symbol-dir",
+            "/tmp/symbols",
+            "--source-path-prefix-map",
+            "/build=/home/me/src",
+        ]);
+        let Action::SymbolServer(symbol_server_args) = opt.action else {
+            panic!("Expected symbol-server action");
+        };
+        let server_props = symbol_server_args.server_props();
+        assert!(matches!(
+            server_props.port_selection,
+            PortSelection::OnePort(4000)
+        ));
+        assert_eq!(server_props.token.as_deref(), Some("my-token"));
+        let symbol_props = symbol_server_args.symbol_props();
+        assert_eq!(symbol_props.symbol_dir, [PathBuf::from("/tmp/symbols")]);
+        assert_eq!(symbol_props.symbol_map_cache_size, 64);
+        assert_eq!(
+            symbol_props.source_path_prefix_map,
+            [("/build".to_string(), "/home/me/src".to_string())]
+        );
+    }
+
+    #[test]
+    fn verify_cli_symbolicate() {
+        let opt = Opt::parse_from([
+            "samply",
+            "symbolicate",
+            "-e",
+            "./a.out",
+            "-fip",
+            "0x1f3a",
+            "2b40",
+        ]);
+        let Action::Symbolicate(symbolicate_args) = opt.action else {
+            panic!("Expected symbolicate action");
+        };
+        assert_eq!(symbolicate_args.exe, Some(PathBuf::from("./a.out")));
+        assert!(symbolicate_args.functions && symbolicate_args.inlines);
+        assert!(symbolicate_args.pretty_print && !symbolicate_args.addresses);
+        assert_eq!(symbolicate_args.addrs, ["0x1f3a", "2b40"]);
+
+        // Without the binary, the library is identified by its debug name and ID.
+        assert!(Opt::try_parse_from(["samply", "symbolicate", "0x1f3a"]).is_err());
+        assert!(Opt::try_parse_from([
+            "samply",
+            "symbolicate",
+            "--debug-name",
+            "xul.pdb",
+            "0x1f3a",
+        ])
+        .is_err());
+        let opt = Opt::parse_from([
+            "samply",
+            "symbolicate",
+            "--debug-name",
+            "xul.pdb",
+            "--debug-id",
+            "B1E5C4B3A9E14A4C9CE5FFA7F5D1E2F31",
+        ]);
+        assert!(matches!(opt.action, Action::Symbolicate(_)));
+    }
+
+    #[test]
+    fn verify_cli_dump_syms() {
+        let opt = Opt::parse_from([
+            "samply",
+            "dump-syms",
+            "-a",
+            "arm64",
+            "/usr/lib/libfoo.dylib",
+            "-o",
+            "libfoo.sym",
+        ]);
+        let Action::DumpSyms(dump_syms_args) = opt.action else {
+            panic!("Expected dump-syms action");
+        };
+        assert_eq!(
+            dump_syms_args.binary,
+            PathBuf::from("/usr/lib/libfoo.dylib")
+        );
+        assert_eq!(dump_syms_args.arch.as_deref(), Some("arm64"));
+        assert_eq!(dump_syms_args.output, Some(PathBuf::from("libfoo.sym")));
+    }
+
+    #[test]
+    fn verify_cli_load_multiple() {
+        let opt = Opt::parse_from(["samply", "load", "a.json", "b.json.gz"]);
+        let Action::Load(load_args) = opt.action else {
+            panic!("Expected load action");
+        };
+        assert_eq!(
+            load_args.files,
+            [PathBuf::from("a.json"), PathBuf::from("b.json.gz")]
+        );
+    }
+
+    #[test]
+    fn verify_cli_symbol_sources() {
+        let opt = Opt::parse_from([
+            "samply",
+            "load",
+            "--symbol-server",
+            "https://symbols.example.com/",
+            "--symbol-cache",
+            "/tmp/symbols",
+            "--no-network-symbols",
+            "prof.json",
+        ]);
+        let Action::Load(load_args) = opt.action else {
+            panic!("Expected load action");
+        };
+        let symbol_props = load_args.symbol_props();
+        assert_eq!(symbol_props.symbol_server, ["https://symbols.example.com/"]);
+        assert_eq!(
+            symbol_props.symbol_cache,
+            Some(PathBuf::from("/tmp/symbols"))
+        );
+        assert!(symbol_props.no_network_symbols);
+    }
 }
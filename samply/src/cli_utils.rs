@@ -1,4 +1,7 @@
 use std::ffi::OsStr;
+use std::time::Duration;
+
+use super::shared::prop_types::{OnlyLibFilter, SamplingPeriod};
 
 #[allow(unused)]
 pub fn parse_time_range(
@@ -27,6 +30,58 @@ pub fn parse_time_range(
     Ok((start, if is_duration { start + end } else { end }))
 }
 
+/// Parses a sampling period like "4000hz", "250us" or "100000events".
+#[allow(unused)]
+pub fn parse_sampling_period(arg: &str) -> Result<SamplingPeriod, String> {
+    let arg = arg.trim().to_ascii_lowercase();
+    let (number, unit) = arg.split_at(arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len()));
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("expected a number followed by a unit, got \"{arg}\""))?;
+    if number == 0 {
+        return Err("the period must be greater than zero".to_string());
+    }
+    match unit {
+        "hz" => Ok(SamplingPeriod::Frequency(number)),
+        "us" => Ok(SamplingPeriod::Interval(Duration::from_micros(number))),
+        "events" => Ok(SamplingPeriod::Events(number)),
+        "" => Err(format!(
+            "missing a unit after \"{number}\", expected \"hz\", \"us\" or \"events\""
+        )),
+        _ => Err(format!(
+            "unknown unit \"{unit}\", expected \"hz\", \"us\" or \"events\""
+        )),
+    }
+}
+
+/// Parses an --only-lib argument, which is either an address range like
+/// "0x7f0000000000-0x7f0000100000" or the file name of a library.
+pub fn parse_only_lib_filter(arg: &str) -> Result<OnlyLibFilter, String> {
+    let Some((start, end)) = arg.split_once('-') else {
+        return Ok(OnlyLibFilter::Lib(arg.to_string()));
+    };
+    let (Some(start), Some(end)) = (start.strip_prefix("0x"), end.strip_prefix("0x")) else {
+        // Library names can contain dashes, e.g. "libgtk-3.so.0".
+        return Ok(OnlyLibFilter::Lib(arg.to_string()));
+    };
+    let parse_address = |address: &str| {
+        u64::from_str_radix(address, 16).map_err(|_| format!("invalid address \"0x{address}\""))
+    };
+    let (start, end) = (parse_address(start)?, parse_address(end)?);
+    if start >= end {
+        return Err(format!("the range \"{arg}\" is empty"));
+    }
+    Ok(OnlyLibFilter::AddressRange(start, end))
+}
+
+/// Parses a --source-path-prefix-map argument of the form "FROM=TO".
+pub fn parse_source_path_prefix_mapping(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((from, to)) if !from.is_empty() => Ok((from.to_string(), to.to_string())),
+        _ => Err(format!("expected FROM=TO, got \"{arg}\"")),
+    }
+}
+
 pub fn split_at_first_equals(s: &OsStr) -> Option<(&OsStr, &OsStr)> {
     let bytes = s.as_encoded_bytes();
     let pos = bytes.iter().position(|b| *b == b'=')?;
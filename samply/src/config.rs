@@ -0,0 +1,215 @@
+//! Defaults for command line options, read from a TOML file. This lets teams
+//! standardize settings like their symbol servers without long command lines.
+//!
+//! The file is `~/.config/samply/config.toml` on Linux (the platform's
+//! config directory elsewhere), or the file passed with `samply --config`.
+//! Options given on the command line override the values from the file.
+//!
+//! ```toml
+//! rate = 4000
+//! symbol-server = ["https://symbols.example.com/"]
+//! output-dir = "/tmp/profiles"
+//! port = 3000
+//! cors-origin = ["https://profiler.example.com"]
+//! ```
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use clap::{Arg, Command};
+use platform_dirs::AppDirs;
+use serde_derive::Deserialize;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// The default for --rate.
+    rate: Option<f64>,
+    /// The default for --symbol-server.
+    #[serde(default)]
+    symbol_server: Vec<String>,
+    /// The directory in which profiles are saved when no directory is given
+    /// with --output.
+    output_dir: Option<PathBuf>,
+    /// The default for --port.
+    port: Option<PortSetting>,
+    /// The default for --cors-origin.
+    #[serde(default)]
+    cors_origin: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PortSetting {
+    Number(u16),
+    /// E.g. "3000+" for the first free port from 3000.
+    Text(String),
+}
+
+impl Config {
+    /// Reads the config file given with `--config` in `args`, or the default
+    /// config file if it exists. Exits with an error if the file is invalid.
+    pub fn load(args: impl IntoIterator<Item = OsString>) -> Config {
+        let (path, is_explicit) = match config_path_from_args(args) {
+            Some(path) => (path, true),
+            None => match default_config_path() {
+                Some(path) => (path, false),
+                None => return Config::default(),
+            },
+        };
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !is_explicit => {
+                return Config::default();
+            }
+            Err(err) => {
+                eprintln!("Error: Couldn't read {}: {err}", path.display());
+                std::process::exit(1);
+            }
+        };
+        match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("Error: Invalid config file {}: {err}", path.display());
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Makes the values from the config file the defaults of the matching
+    /// arguments of all subcommands.
+    pub fn apply_to_command(&self, mut command: Command) -> Command {
+        let subcommand_names: Vec<String> = command
+            .get_subcommands()
+            .map(|subcommand| subcommand.get_name().to_owned())
+            .collect();
+        for name in subcommand_names {
+            command =
+                command.mut_subcommand(name, |subcommand| self.apply_to_subcommand(subcommand));
+        }
+        command
+    }
+
+    fn apply_to_subcommand(&self, mut subcommand: Command) -> Command {
+        if let Some(rate) = self.rate {
+            subcommand = mut_arg_if_present(subcommand, "rate", |arg| {
+                arg.default_value(leak(rate.to_string()))
+            });
+        }
+        if !self.symbol_server.is_empty() {
+            subcommand = mut_arg_if_present(subcommand, "symbol_server", |arg| {
+                arg.default_values(self.symbol_server.iter().cloned().map(leak))
+            });
+        }
+        if !self.cors_origin.is_empty() {
+            subcommand = mut_arg_if_present(subcommand, "cors_origin", |arg| {
+                arg.default_values(self.cors_origin.iter().cloned().map(leak))
+            });
+        }
+        if let Some(port) = &self.port {
+            let port = match port {
+                PortSetting::Number(port) => port.to_string(),
+                PortSetting::Text(port) => port.clone(),
+            };
+            subcommand =
+                mut_arg_if_present(subcommand, "port", |arg| arg.default_value(leak(port)));
+        }
+        if let Some(output_dir) = &self.output_dir {
+            subcommand = mut_arg_if_present(subcommand, "output", |arg| {
+                // Only arguments with a default file name, like "profile.json.gz",
+                // are moved into the output directory.
+                let Some(file_name) = arg.get_default_values().first() else {
+                    return arg;
+                };
+                let path = output_dir.join(Path::new(file_name));
+                arg.default_value(leak(path.to_string_lossy().into_owned()))
+            });
+        }
+        subcommand
+    }
+}
+
+fn mut_arg_if_present(command: Command, id: &str, f: impl FnOnce(Arg) -> Arg) -> Command {
+    if command.get_arguments().any(|arg| arg.get_id() == id) {
+        command.mut_arg(id, f)
+    } else {
+        command
+    }
+}
+
+/// clap only takes static strings as default values. The config is read once
+/// per run, so leaking its values is fine.
+fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+/// Finds `--config PATH` or `--config=PATH` among the options before the
+/// subcommand, so that the arguments of a recorded command are never taken
+/// for samply's own.
+fn config_path_from_args(args: impl IntoIterator<Item = OsString>) -> Option<PathBuf> {
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_str()?;
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+        if !arg.starts_with('-') {
+            // This is the subcommand.
+            return None;
+        }
+    }
+    None
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let dirs = AppDirs::new(Some("samply"), true)?;
+    Some(dirs.config_dir.join("config.toml"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn config_path() {
+        assert_eq!(
+            config_path_from_args(args(&["samply", "--config", "a.toml", "record", "ls"])),
+            Some(PathBuf::from("a.toml"))
+        );
+        assert_eq!(
+            config_path_from_args(args(&["samply", "--config=a.toml", "load", "p.json"])),
+            Some(PathBuf::from("a.toml"))
+        );
+        // The recorded command's arguments aren't samply's.
+        assert_eq!(
+            config_path_from_args(args(&["samply", "record", "ls", "--config", "a.toml"])),
+            None
+        );
+    }
+
+    #[test]
+    fn defaults_from_config() {
+        let config: Config = toml::from_str(
+            "rate = 4000\nsymbol-server = [\"https://symbols.example.com/\"]\noutput-dir = \"/tmp/profiles\"\nport = 4000\n",
+        )
+        .unwrap();
+        let command = config.apply_to_command(<crate::cli::Opt as clap::CommandFactory>::command());
+        let matches = command.get_matches_from(["samply", "load", "profile.json"]);
+        let (_, load_matches) = matches.subcommand().unwrap();
+        assert_eq!(load_matches.get_one::<String>("port").unwrap(), "4000");
+        assert_eq!(
+            load_matches
+                .get_many::<String>("symbol_server")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            ["https://symbols.example.com/"]
+        );
+    }
+}
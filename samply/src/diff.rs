@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::{json, Value};
+
+use crate::merge::{merge_profiles, MergeTimeAlignment};
+
+/// The accumulated sample weight of one function in one profile.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FunctionWeight {
+    pub self_weight: f64,
+    pub total_weight: f64,
+}
+
+/// Per-function sample weights of a profile, summed up across all threads.
+#[derive(Debug, Default)]
+pub struct FunctionWeights {
+    pub functions: HashMap<String, FunctionWeight>,
+    pub total_sample_weight: f64,
+}
+
+/// One row of the comparison between two profiles. The fractions are relative
+/// to the total sample weight of the respective profile, so that recordings of
+/// different lengths can be compared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDiff {
+    pub name: String,
+    pub before: FunctionWeight,
+    pub after: FunctionWeight,
+    pub self_fraction_delta: f64,
+    pub total_fraction_delta: f64,
+}
+
+/// Computes the self and total sample weight of every function in the profile.
+///
+/// Functions are identified by their name and the name of their library, so
+/// that the same function can be matched up across two profiles. This only
+/// gives useful results for symbolicated profiles.
+pub fn compute_function_weights(profile: &Value) -> FunctionWeights {
+    let strings = profile["shared"]["stringArray"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or(&[]);
+    let string = |index: &Value| -> &str {
+        index
+            .as_u64()
+            .and_then(|i| strings.get(i as usize))
+            .and_then(Value::as_str)
+            .unwrap_or("")
+    };
+
+    let mut result = FunctionWeights::default();
+    for thread in profile["threads"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or(&[])
+    {
+        let func_names = column(&thread["funcTable"], "name");
+        let func_resources = column(&thread["funcTable"], "resource");
+        let resource_names = column(&thread["resourceTable"], "name");
+        let func_keys: Vec<String> = func_names
+            .iter()
+            .enumerate()
+            .map(|(func, name)| {
+                let resource = func_resources
+                    .get(func)
+                    .and_then(Value::as_i64)
+                    .filter(|r| *r >= 0)
+                    .and_then(|r| resource_names.get(r as usize));
+                match resource {
+                    Some(resource) => format!("{} ({})", string(name), string(resource)),
+                    None => string(name).to_string(),
+                }
+            })
+            .collect();
+        let frame_funcs = column(&thread["frameTable"], "func");
+        let stack_prefixes = column(&thread["stackTable"], "prefix");
+        let stack_frames = column(&thread["stackTable"], "frame");
+        let stack_func = |stack: usize| -> Option<usize> {
+            let frame = stack_frames.get(stack)?.as_u64()? as usize;
+            Some(frame_funcs.get(frame)?.as_u64()? as usize)
+        };
+
+        // Sum up the sample weights per stack first; there are usually far fewer
+        // distinct stacks than samples.
+        let samples = &thread["samples"];
+        let sample_stacks = column(samples, "stack");
+        let sample_weights = column(samples, "weight");
+        let mut weight_per_stack: HashMap<usize, f64> = HashMap::new();
+        for (i, stack) in sample_stacks.iter().enumerate() {
+            let Some(stack) = stack.as_u64() else {
+                continue;
+            };
+            let weight = sample_weights.get(i).and_then(Value::as_f64).unwrap_or(1.0);
+            *weight_per_stack.entry(stack as usize).or_default() += weight;
+            result.total_sample_weight += weight;
+        }
+
+        for (stack, weight) in weight_per_stack {
+            if let Some(func) = stack_func(stack) {
+                let key = func_keys.get(func).cloned().unwrap_or_default();
+                result.functions.entry(key).or_default().self_weight += weight;
+            }
+            // Count each function only once per stack, even if it's recursive.
+            let mut seen_funcs = HashSet::new();
+            let mut current = Some(stack);
+            while let Some(stack) = current {
+                if let Some(func) = stack_func(stack) {
+                    if seen_funcs.insert(func) {
+                        let key = func_keys.get(func).cloned().unwrap_or_default();
+                        result.functions.entry(key).or_default().total_weight += weight;
+                    }
+                }
+                current = stack_prefixes
+                    .get(stack)
+                    .and_then(Value::as_u64)
+                    .map(|p| p as usize);
+            }
+        }
+    }
+    result
+}
+
+/// Compares the function weights of two profiles. The result is sorted by the
+/// magnitude of the change in self time, largest first.
+pub fn diff_function_weights(
+    before: &FunctionWeights,
+    after: &FunctionWeights,
+) -> Vec<FunctionDiff> {
+    let fraction = |weight: f64, total: f64| if total > 0.0 { weight / total } else { 0.0 };
+    let names: HashSet<&String> = before
+        .functions
+        .keys()
+        .chain(after.functions.keys())
+        .collect();
+    let mut diffs: Vec<FunctionDiff> = names
+        .into_iter()
+        .map(|name| {
+            let b = before.functions.get(name).copied().unwrap_or_default();
+            let a = after.functions.get(name).copied().unwrap_or_default();
+            FunctionDiff {
+                name: name.clone(),
+                before: b,
+                after: a,
+                self_fraction_delta: fraction(a.self_weight, after.total_sample_weight)
+                    - fraction(b.self_weight, before.total_sample_weight),
+                total_fraction_delta: fraction(a.total_weight, after.total_sample_weight)
+                    - fraction(b.total_weight, before.total_sample_weight),
+            }
+        })
+        .collect();
+    diffs.sort_by(|x, y| {
+        y.self_fraction_delta
+            .abs()
+            .total_cmp(&x.self_fraction_delta.abs())
+            .then_with(|| x.name.cmp(&y.name))
+    });
+    diffs
+}
+
+/// Prints the `limit` largest changes as a table.
+pub fn print_function_diffs(diffs: &[FunctionDiff], limit: usize) {
+    println!(
+        "{:>10} {:>10} {:>9} {:>10} {:>10} {:>9}  Function",
+        "Self A", "Self B", "Δ Self", "Total A", "Total B", "Δ Total"
+    );
+    for diff in diffs.iter().take(limit) {
+        println!(
+            "{:>10} {:>10} {:>8.2}% {:>10} {:>10} {:>8.2}%  {}",
+            diff.before.self_weight,
+            diff.after.self_weight,
+            diff.self_fraction_delta * 100.0,
+            diff.before.total_weight,
+            diff.after.total_weight,
+            diff.total_fraction_delta * 100.0,
+            diff.name
+        );
+    }
+}
+
+/// Creates a profile that contains the threads of both profiles, with the sample
+/// weights of the `before` profile negated. When the threads of both profiles
+/// are selected together in the Firefox Profiler, the call tree then shows the
+/// difference between the two profiles.
+pub fn make_diff_profile(before: Value, after: Value) -> Value {
+    let before_thread_count = before["threads"].as_array().map_or(0, Vec::len);
+    let mut profile = merge_profiles(vec![before, after], MergeTimeAlignment::Start);
+    if let Some(threads) = profile["threads"].as_array_mut() {
+        for thread in threads.iter_mut().take(before_thread_count) {
+            let samples = &mut thread["samples"];
+            let len = samples["length"]
+                .as_u64()
+                .unwrap_or_else(|| column(samples, "stack").len() as u64)
+                as usize;
+            let weights: Vec<Value> = (0..len)
+                .map(|i| {
+                    let weight = samples["weight"][i].as_f64().unwrap_or(1.0);
+                    json!(-weight)
+                })
+                .collect();
+            samples["weight"] = Value::Array(weights);
+        }
+    }
+    profile
+}
+
+fn column<'a>(table: &'a Value, key: &str) -> &'a [Value] {
+    table[key].as_array().map(Vec::as_slice).unwrap_or(&[])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_profile(samples: &[usize]) -> Value {
+        // Stacks: 0 = main, 1 = main -> foo, 2 = main -> bar
+        json!({
+            "meta": { "startTime": 0.0, "preprocessedProfileVersion": 57, "categories": [] },
+            "libs": [],
+            "shared": { "stringArray": ["main", "foo", "bar"] },
+            "threads": [{
+                "pid": "1",
+                "funcTable": { "name": [0, 1, 2], "resource": [-1, -1, -1] },
+                "resourceTable": { "name": [] },
+                "frameTable": { "func": [0, 1, 2] },
+                "stackTable": { "prefix": [null, 0, 0], "frame": [0, 1, 2] },
+                "samples": { "length": samples.len(), "stack": samples, "weight": null },
+            }],
+            "counters": [],
+        })
+    }
+
+    #[test]
+    fn diff_two_profiles() {
+        let before = compute_function_weights(&make_profile(&[1, 1, 2, 0]));
+        let after = compute_function_weights(&make_profile(&[1, 2, 2, 2]));
+        assert_eq!(
+            before.functions["main"],
+            FunctionWeight {
+                self_weight: 1.0,
+                total_weight: 4.0
+            }
+        );
+
+        let diffs = diff_function_weights(&before, &after);
+        assert_eq!(diffs[0].name, "bar");
+        assert_eq!(diffs[0].self_fraction_delta, 0.5);
+        assert_eq!(diffs[1].name, "foo");
+        assert_eq!(diffs[1].self_fraction_delta, -0.25);
+    }
+}
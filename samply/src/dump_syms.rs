@@ -0,0 +1,278 @@
+//! `samply dump-syms`, which writes the symbols of a binary as a Breakpad
+//! .sym file, following the conventions of Breakpad's and Mozilla's
+//! dump_syms, so that crash pipelines can produce their symbol files with the
+//! same tool that symbolicates profiles.
+//!
+//! The file has FUNC records with line records for the functions which are
+//! covered by debug info, and PUBLIC records for the other symbols. Inlined
+//! functions are not written, like with dump_syms without `--inlines`.
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use wholesym::{CodeId, LibraryInfo, LookupAddress, SymbolMap};
+
+/// The contents of a Breakpad .sym file.
+#[derive(Debug, Default)]
+pub struct BreakpadSymFile {
+    /// "windows", "mac" or "Linux", as in the MODULE record.
+    pub os: String,
+    pub arch: String,
+    /// The debug ID in breakpad format.
+    pub breakpad_id: String,
+    pub debug_name: String,
+    /// The code ID and binary name for the INFO CODE_ID record.
+    pub code_id: Option<(String, Option<String>)>,
+    pub files: Vec<String>,
+    pub functions: Vec<Function>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// The relative address of the function.
+    pub address: u32,
+    pub size: u32,
+    pub name: String,
+    /// Empty if the function is not covered by debug info, in which case it
+    /// is written as a PUBLIC record.
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub address: u32,
+    pub size: u32,
+    pub line: u32,
+    /// The index into [`BreakpadSymFile::files`].
+    pub file_index: usize,
+}
+
+impl BreakpadSymFile {
+    /// Collects the functions of the symbol map. Every address of a function
+    /// with debug info is looked up, so this takes a while for big binaries.
+    pub async fn from_symbol_map(library_info: &LibraryInfo, symbol_map: &SymbolMap) -> Self {
+        let arch = library_info.arch.as_deref();
+        let mut sym_file = BreakpadSymFile {
+            os: breakpad_os(library_info).to_string(),
+            arch: arch.map_or("unknown", breakpad_arch).to_string(),
+            breakpad_id: library_info
+                .debug_id
+                .unwrap_or_else(|| symbol_map.debug_id())
+                .breakpad()
+                .to_string(),
+            debug_name: library_info
+                .debug_name
+                .clone()
+                .or_else(|| library_info.name.clone())
+                .unwrap_or_default(),
+            code_id: library_info.code_id.as_ref().map(|code_id| match code_id {
+                // Windows symbol servers need the binary name to find the binary.
+                CodeId::PeCodeId(_) => (code_id.to_string(), library_info.name.clone()),
+                _ => (code_id.to_string().to_uppercase(), None),
+            }),
+            ..Default::default()
+        };
+
+        let mut symbols: Vec<(u32, String)> = symbol_map
+            .iter_symbols()
+            .map(|(address, name)| (address, name.into_owned()))
+            .collect();
+        symbols.sort_by_key(|(address, _)| *address);
+        symbols.dedup_by_key(|(address, _)| *address);
+
+        let step = instruction_alignment(arch);
+        let mut file_indexes = HashMap::new();
+        for (i, (address, symbol_name)) in symbols.iter().enumerate() {
+            let Some(info) = symbol_map.lookup(LookupAddress::Relative(*address)).await else {
+                continue;
+            };
+            let next_address = symbols.get(i + 1).map(|(next_address, _)| *next_address);
+            let Some(size) = info.symbol.size.or_else(|| Some(next_address? - address)) else {
+                // The size of the last symbol is unknown.
+                sym_file.functions.push(Function {
+                    address: *address,
+                    size: 0,
+                    name: symbol_name.clone(),
+                    lines: Vec::new(),
+                });
+                continue;
+            };
+            let outer_function = info
+                .frames
+                .as_ref()
+                .and_then(|frames| frames.last()?.function)
+                .map(|f| symbol_map.resolve_function_name(f).into_owned());
+            let mut lines: Vec<Line> = Vec::new();
+            if info.frames.is_some_and(|frames| !frames.is_empty()) {
+                let end = address.saturating_add(size);
+                for line_address in (*address..end).step_by(step as usize) {
+                    let location = symbol_map
+                        .lookup(LookupAddress::Relative(line_address))
+                        .await
+                        .filter(|info| info.symbol.address == *address)
+                        .and_then(|info| {
+                            let frame = info.frames?.into_iter().next()?;
+                            Some((frame.file_path?, frame.line_number?))
+                        });
+                    let Some((file_path, line_number)) = location else {
+                        continue;
+                    };
+                    let file = symbol_map
+                        .resolve_source_file_path(file_path)
+                        .display_path()
+                        .into_owned();
+                    let file_index = *file_indexes.entry(file).or_insert_with_key(|file| {
+                        sym_file.files.push(file.clone());
+                        sym_file.files.len() - 1
+                    });
+                    match lines.last_mut() {
+                        Some(last)
+                            if last.line == line_number
+                                && last.file_index == file_index
+                                && last.address + last.size == line_address =>
+                        {
+                            last.size += step;
+                        }
+                        _ => lines.push(Line {
+                            address: line_address,
+                            size: step,
+                            line: line_number,
+                            file_index,
+                        }),
+                    }
+                }
+            }
+            sym_file.functions.push(Function {
+                address: *address,
+                size,
+                name: outer_function.unwrap_or_else(|| symbol_name.clone()),
+                lines,
+            });
+        }
+        sym_file
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "MODULE {} {} {} {}",
+            self.os, self.arch, self.breakpad_id, self.debug_name
+        )?;
+        match &self.code_id {
+            Some((code_id, Some(name))) => writeln!(out, "INFO CODE_ID {code_id} {name}")?,
+            Some((code_id, None)) => writeln!(out, "INFO CODE_ID {code_id}")?,
+            None => {}
+        }
+        for (index, file) in self.files.iter().enumerate() {
+            writeln!(out, "FILE {index} {file}")?;
+        }
+        for function in &self.functions {
+            if function.lines.is_empty() {
+                writeln!(out, "PUBLIC {:x} 0 {}", function.address, function.name)?;
+                continue;
+            }
+            writeln!(
+                out,
+                "FUNC {:x} {:x} 0 {}",
+                function.address, function.size, function.name
+            )?;
+            for line in &function.lines {
+                writeln!(
+                    out,
+                    "{:x} {:x} {} {}",
+                    line.address, line.size, line.line, line.file_index
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The OS name of the MODULE record, which dump_syms derives from the file
+/// format.
+fn breakpad_os(library_info: &LibraryInfo) -> &'static str {
+    match &library_info.code_id {
+        Some(CodeId::PeCodeId(_)) => "windows",
+        Some(CodeId::MachoUuid(_)) => "mac",
+        Some(CodeId::ElfBuildId(_)) => "Linux",
+        None => match &library_info.debug_name {
+            Some(debug_name) if debug_name.to_ascii_lowercase().ends_with(".pdb") => "windows",
+            _ => "Linux",
+        },
+    }
+}
+
+/// Maps samply's architecture names to the ones which Breakpad uses.
+fn breakpad_arch(arch: &str) -> &str {
+    match arch {
+        "i386" | "i686" => "x86",
+        "x86_64h" => "x86_64",
+        "aarch64" => "arm64",
+        arch => arch,
+    }
+}
+
+/// The distance between addresses which may start an instruction.
+fn instruction_alignment(arch: Option<&str>) -> u32 {
+    match arch {
+        Some("arm64" | "arm64e" | "aarch64") => 4,
+        Some("arm") => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn writes_breakpad_format() {
+        let sym_file = BreakpadSymFile {
+            os: "windows".into(),
+            arch: "x86_64".into(),
+            breakpad_id: "B1E5C4B3A9E14A4C9CE5FFA7F5D1E2F31".into(),
+            debug_name: "firefox.pdb".into(),
+            code_id: Some(("5F3A1B2C4d000".into(), Some("firefox.exe".into()))),
+            files: vec!["C:\\src\\main.cpp".into()],
+            functions: vec![
+                Function {
+                    address: 0x1000,
+                    size: 0x20,
+                    name: "wmain".into(),
+                    lines: vec![
+                        Line {
+                            address: 0x1000,
+                            size: 0x8,
+                            line: 10,
+                            file_index: 0,
+                        },
+                        Line {
+                            address: 0x1008,
+                            size: 0x18,
+                            line: 12,
+                            file_index: 0,
+                        },
+                    ],
+                },
+                Function {
+                    address: 0x1020,
+                    size: 0x10,
+                    name: "memcpy".into(),
+                    lines: Vec::new(),
+                },
+            ],
+        };
+        let mut out = Vec::new();
+        sym_file.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "MODULE windows x86_64 B1E5C4B3A9E14A4C9CE5FFA7F5D1E2F31 firefox.pdb\n\
+             INFO CODE_ID 5F3A1B2C4d000 firefox.exe\n\
+             FILE 0 C:\\src\\main.cpp\n\
+             FUNC 1000 20 0 wmain\n\
+             1000 8 10 0\n\
+             1008 18 12 0\n\
+             PUBLIC 1020 0 memcpy\n"
+        );
+    }
+}
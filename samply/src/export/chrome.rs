@@ -0,0 +1,175 @@
+//! Export to the Chrome Trace Event format, which can be opened in Perfetto UI
+//! and in chrome://tracing.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use serde_json::{json, Map, Value};
+
+use super::{ExportMarkerTiming, ExportProfile};
+
+/// Writes the profile as a JSON trace in the Chrome Trace Event format.
+///
+/// Each thread gets process and thread name metadata events. Samples become
+/// sample events ("P") which refer to the shared "stackFrames" dictionary, and
+/// markers become complete ("X"), instant ("i") or begin / end ("B" / "E")
+/// events on the marker's thread.
+pub fn write_chrome_trace(profile: &ExportProfile, w: impl Write) -> std::io::Result<()> {
+    serde_json::to_writer(w, &build_chrome_trace(profile))?;
+    Ok(())
+}
+
+fn build_chrome_trace(profile: &ExportProfile) -> Value {
+    let mut ids = TraceIds::default();
+    let mut events = Vec::new();
+    let mut named_processes = HashSet::new();
+
+    for thread in &profile.threads {
+        let pid = ids.numeric_id(&thread.pid);
+        let tid = ids.numeric_id(&thread.tid);
+        if named_processes.insert(pid) {
+            events.push(json!({
+                "ph": "M", "name": "process_name", "pid": pid,
+                "args": { "name": thread.process_name },
+            }));
+        }
+        events.push(json!({
+            "ph": "M", "name": "thread_name", "pid": pid, "tid": tid,
+            "args": { "name": thread.name },
+        }));
+
+        for sample in &thread.samples {
+            let mut event = json!({
+                "ph": "P", "name": "sample", "cat": "samples",
+                "pid": pid, "tid": tid, "ts": micros(sample.time),
+                "args": { "weight": sample.weight },
+            });
+            if let Some(stack) = sample.stack {
+                event["sf"] = json!(stack.to_string());
+            }
+            events.push(event);
+        }
+
+        for marker in &thread.markers {
+            let mut event = json!({
+                "name": marker.name, "cat": marker.category, "pid": pid, "tid": tid,
+            });
+            match marker.timing {
+                ExportMarkerTiming::Instant(time) => {
+                    event["ph"] = json!("i");
+                    event["s"] = json!("t");
+                    event["ts"] = json!(micros(time));
+                }
+                ExportMarkerTiming::Interval(start, end) => {
+                    event["ph"] = json!("X");
+                    event["ts"] = json!(micros(start));
+                    event["dur"] = json!(micros(end - start));
+                }
+                ExportMarkerTiming::IntervalStart(start) => {
+                    event["ph"] = json!("B");
+                    event["ts"] = json!(micros(start));
+                }
+                ExportMarkerTiming::IntervalEnd(end) => {
+                    event["ph"] = json!("E");
+                    event["ts"] = json!(micros(end));
+                }
+            }
+            if let Value::Object(data) = &marker.data {
+                let args: Map<String, Value> = data
+                    .iter()
+                    .filter(|(key, _)| key.as_str() != "cause")
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                event["args"] = Value::Object(args);
+            }
+            events.push(event);
+        }
+    }
+
+    let stack_frames: Map<String, Value> = profile
+        .stacks
+        .iter()
+        .enumerate()
+        .map(|(index, stack)| {
+            let frame = &profile.frames[stack.frame];
+            let mut node = json!({
+                "name": frame.function,
+                "category": frame.lib.as_deref().unwrap_or(""),
+            });
+            if let Some(prefix) = stack.prefix {
+                node["parent"] = json!(prefix.to_string());
+            }
+            (index.to_string(), node)
+        })
+        .collect();
+
+    json!({
+        "traceEvents": events,
+        "stackFrames": stack_frames,
+        "displayTimeUnit": "ms",
+    })
+}
+
+/// Trace events need numeric pids and tids. Profiles can have non-numeric ones,
+/// for example after merging profiles with colliding pids, so those get
+/// synthetic ids which can't collide with real ones.
+#[derive(Default)]
+struct TraceIds {
+    synthetic_ids: HashMap<String, u64>,
+}
+
+impl TraceIds {
+    fn numeric_id(&mut self, id: &str) -> u64 {
+        if let Ok(id) = id.parse() {
+            return id;
+        }
+        let next_id = (1 << 32) + self.synthetic_ids.len() as u64;
+        *self.synthetic_ids.entry(id.to_string()).or_insert(next_id)
+    }
+}
+
+fn micros(millis: f64) -> f64 {
+    millis * 1000.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::export::symbols::FrameSymbols;
+
+    #[test]
+    fn chrome_trace_events() {
+        let profile = json!({
+            "meta": { "interval": 1.0, "categories": [{ "name": "Other" }] },
+            "libs": [],
+            "shared": { "stringArray": ["main", "foo", "Paint"] },
+            "threads": [{
+                "name": "main", "processName": "app", "pid": "1", "tid": "1.1",
+                "funcTable": { "name": [0, 1], "resource": [-1, -1] },
+                "resourceTable": { "lib": [] },
+                "frameTable": { "func": [0, 1], "address": [-1, -1] },
+                "stackTable": { "prefix": [null, 0], "frame": [0, 1] },
+                "samples": { "stack": [1], "timeDeltas": [2.5], "weight": null },
+                "markers": {
+                    "name": [2], "startTime": [1.0], "endTime": [3.0], "phase": [1],
+                    "category": [0], "data": [{ "type": "Paint", "cause": { "stack": 0 } }],
+                },
+            }],
+        });
+        let export_profile =
+            ExportProfile::from_processed_profile(&profile, &FrameSymbols::default());
+        let trace = build_chrome_trace(&export_profile);
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[1]["tid"], json!(1u64 << 32));
+        assert_eq!(events[2]["ph"], "P");
+        assert_eq!(events[2]["ts"], 2500.0);
+        assert_eq!(events[2]["sf"], "1");
+        assert_eq!(events[3]["ph"], "X");
+        assert_eq!(events[3]["dur"], 2000.0);
+        assert_eq!(events[3]["cat"], "Other");
+        assert_eq!(events[3]["args"], json!({ "type": "Paint" }));
+        assert_eq!(trace["stackFrames"]["1"]["name"], "foo");
+        assert_eq!(trace["stackFrames"]["1"]["parent"], "0");
+    }
+}
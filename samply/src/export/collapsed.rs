@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use super::ExportProfile;
+
+/// Writes the samples of all threads in the collapsed ("folded") stack format
+/// used by Brendan Gregg's flamegraph.pl and by inferno: one line per distinct
+/// stack, with the frames from root to leaf separated by semicolons, followed by
+/// the summed sample weight of that stack.
+pub fn write_collapsed_stacks(profile: &ExportProfile, mut w: impl Write) -> std::io::Result<()> {
+    let mut weight_per_stack: BTreeMap<usize, f64> = BTreeMap::new();
+    for thread in &profile.threads {
+        for sample in &thread.samples {
+            if let Some(stack) = sample.stack {
+                *weight_per_stack.entry(stack).or_default() += sample.weight;
+            }
+        }
+    }
+
+    let mut lines: Vec<(String, f64)> = weight_per_stack
+        .into_iter()
+        .filter(|(_, weight)| *weight != 0.0)
+        .map(|(stack, weight)| {
+            let frames: Vec<String> = profile
+                .stack_frames(stack)
+                .into_iter()
+                .map(|frame| sanitize_frame_name(&profile.frames[frame].function))
+                .collect();
+            (frames.join(";"), weight)
+        })
+        .collect();
+    lines.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (stack, weight) in lines {
+        writeln!(w, "{stack} {weight}")?;
+    }
+    Ok(())
+}
+
+/// Semicolons separate frames and newlines separate stacks, so neither can
+/// appear in a frame name.
+fn sanitize_frame_name(name: &str) -> String {
+    name.replace(';', ":").replace(['\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::export::symbols::FrameSymbols;
+
+    #[test]
+    fn collapse_stacks() {
+        let profile = json!({
+            "meta": { "interval": 1.0 },
+            "libs": [],
+            "shared": { "stringArray": ["main", "foo", "bar; baz"] },
+            "threads": [{
+                "name": "main", "processName": "app", "pid": "1", "tid": 1,
+                "funcTable": { "name": [0, 1, 2], "resource": [-1, -1, -1] },
+                "resourceTable": { "lib": [] },
+                "frameTable": { "func": [0, 1, 2], "address": [-1, -1, -1] },
+                "stackTable": { "prefix": [null, 0, 0], "frame": [0, 1, 2] },
+                "samples": { "stack": [1, 1, 2, 0, null], "timeDeltas": [0, 1, 1, 1, 1], "weight": null },
+            }],
+        });
+        let export_profile =
+            ExportProfile::from_processed_profile(&profile, &FrameSymbols::default());
+        let mut output = Vec::new();
+        write_collapsed_stacks(&export_profile, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "main 1\nmain;bar: baz 1\nmain;foo 2\n"
+        );
+    }
+}
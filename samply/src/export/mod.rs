@@ -0,0 +1,323 @@
+//! Conversion of processed profiles into formats that other tools understand.
+//!
+//! The exporters don't work on the processed profile JSON directly. Instead,
+//! the profile is first flattened into an [`ExportProfile`], which has one
+//! frame table and one stack table for all threads, and in which every frame
+//! has a function name, even if the profile itself is unsymbolicated.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+pub mod chrome;
+pub mod collapsed;
+pub mod pprof;
+pub mod symbols;
+
+use symbols::FrameSymbols;
+
+/// A single frame of an exported stack.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExportFrame {
+    pub function: String,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    /// The name of the library which contains this frame's code, if known.
+    pub lib: Option<String>,
+}
+
+/// An entry in the stack table. The stack consists of `frame`, called by the
+/// frames of the `prefix` stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExportStack {
+    pub prefix: Option<usize>,
+    pub frame: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportSample {
+    /// The sample time, in milliseconds since the start of the profile.
+    pub time: f64,
+    pub stack: Option<usize>,
+    pub weight: f64,
+}
+
+/// The timing of a marker, with times in milliseconds since the start of the
+/// profile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportMarkerTiming {
+    Instant(f64),
+    Interval(f64, f64),
+    IntervalStart(f64),
+    IntervalEnd(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportMarker {
+    pub name: String,
+    pub category: String,
+    pub timing: ExportMarkerTiming,
+    /// The marker's payload, if any, as stored in the processed profile.
+    pub data: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportThread {
+    pub name: String,
+    pub process_name: String,
+    pub pid: String,
+    pub tid: String,
+    pub samples: Vec<ExportSample>,
+    pub markers: Vec<ExportMarker>,
+}
+
+/// The samples of all threads of a profile, with stacks and frames shared
+/// across threads.
+#[derive(Debug, Default)]
+pub struct ExportProfile {
+    pub frames: Vec<ExportFrame>,
+    pub stacks: Vec<ExportStack>,
+    pub threads: Vec<ExportThread>,
+    /// The start time of the profile, in milliseconds since the Unix epoch.
+    pub start_time: f64,
+    /// The sampling interval, in milliseconds.
+    pub interval: f64,
+    /// The unit of the sample weights, e.g. "samples" or "tracing-ms".
+    pub weight_type: String,
+    frame_indexes: HashMap<ExportFrame, usize>,
+    stack_indexes: HashMap<ExportStack, usize>,
+}
+
+impl ExportProfile {
+    /// Flattens a processed profile. Frames with addresses for which `symbols`
+    /// has an entry use the symbolicated function names, including inlined
+    /// functions; all other frames use the names from the profile's function
+    /// table.
+    pub fn from_processed_profile(profile: &Value, symbols: &FrameSymbols) -> Self {
+        let strings = column(&profile["shared"], "stringArray");
+        let string = |index: &Value| -> Option<String> {
+            let s = strings.get(index.as_u64()? as usize)?.as_str()?;
+            Some(s.to_string())
+        };
+        let lib_names: Vec<Option<String>> = profile["libs"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+            .iter()
+            .map(|lib| lib["name"].as_str().map(ToString::to_string))
+            .collect();
+        let category_names: Vec<String> = profile["meta"]["categories"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+            .iter()
+            .map(|category| category["name"].as_str().unwrap_or_default().to_string())
+            .collect();
+
+        let mut export_profile = ExportProfile {
+            start_time: profile["meta"]["startTime"].as_f64().unwrap_or(0.0),
+            interval: profile["meta"]["interval"].as_f64().unwrap_or(1.0),
+            weight_type: String::from("samples"),
+            ..Default::default()
+        };
+
+        for thread in profile["threads"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+        {
+            let func_table = &thread["funcTable"];
+            let frame_table = &thread["frameTable"];
+            let stack_table = &thread["stackTable"];
+            let resource_libs = column(&thread["resourceTable"], "lib");
+
+            // For every frame in this thread's frame table, the list of exported
+            // frames it expands to, from the outer function to the innermost
+            // inlined function.
+            let frame_expansions: Vec<Vec<usize>> = column(frame_table, "func")
+                .iter()
+                .enumerate()
+                .map(|(frame, func)| {
+                    let func = func.as_u64().map(|f| f as usize);
+                    let lib = func
+                        .and_then(|f| func_table["resource"][f].as_i64())
+                        .filter(|r| *r >= 0)
+                        .and_then(|r| resource_libs.get(r as usize))
+                        .and_then(Value::as_u64)
+                        .map(|l| l as usize);
+                    let lib_name = lib.and_then(|l| lib_names.get(l).cloned().flatten());
+                    let address = frame_table["address"][frame]
+                        .as_i64()
+                        .filter(|a| *a >= 0)
+                        .map(|a| a as u64);
+
+                    if let (Some(lib), Some(address)) = (lib, address) {
+                        if let Some(symbolicated_frames) = symbols.lookup(lib, address) {
+                            return symbolicated_frames
+                                .iter()
+                                .map(|frame| {
+                                    export_profile.frame_index(ExportFrame {
+                                        lib: lib_name.clone(),
+                                        ..frame.clone()
+                                    })
+                                })
+                                .collect();
+                        }
+                    }
+
+                    let export_frame = ExportFrame {
+                        function: func
+                            .and_then(|f| string(&func_table["name"][f]))
+                            .unwrap_or_else(|| String::from("<unknown>")),
+                        file: func.and_then(|f| string(&func_table["fileName"][f])),
+                        line: frame_table["line"][frame]
+                            .as_u64()
+                            .or_else(|| func.and_then(|f| func_table["lineNumber"][f].as_u64()))
+                            .map(|l| l as u32),
+                        lib: lib_name,
+                    };
+                    vec![export_profile.frame_index(export_frame)]
+                })
+                .collect();
+
+            // Stack prefixes always come before the stacks that use them.
+            let stack_frames = column(stack_table, "frame");
+            let mut stack_map: Vec<Option<usize>> = Vec::with_capacity(stack_frames.len());
+            for (stack, frame) in stack_frames.iter().enumerate() {
+                let mut export_stack = stack_table["prefix"][stack]
+                    .as_u64()
+                    .and_then(|prefix| stack_map.get(prefix as usize).copied().flatten());
+                let expansion = frame
+                    .as_u64()
+                    .and_then(|frame| frame_expansions.get(frame as usize))
+                    .map(Vec::as_slice)
+                    .unwrap_or(&[]);
+                for frame in expansion {
+                    export_stack = Some(export_profile.stack_index(ExportStack {
+                        prefix: export_stack,
+                        frame: *frame,
+                    }));
+                }
+                stack_map.push(export_stack);
+            }
+
+            let samples = &thread["samples"];
+            if let Some(weight_type) = samples["weightType"].as_str() {
+                export_profile.weight_type = weight_type.to_string();
+            }
+            let sample_stacks = column(samples, "stack");
+            let times = sample_times(samples, sample_stacks.len());
+            let export_samples = sample_stacks
+                .iter()
+                .zip(times)
+                .enumerate()
+                .map(|(i, (stack, time))| ExportSample {
+                    time,
+                    stack: stack
+                        .as_u64()
+                        .and_then(|stack| stack_map.get(stack as usize).copied().flatten()),
+                    weight: samples["weight"][i].as_f64().unwrap_or(1.0),
+                })
+                .collect();
+
+            let marker_table = &thread["markers"];
+            let export_markers = column(marker_table, "name")
+                .iter()
+                .enumerate()
+                .filter_map(|(i, name)| {
+                    let start = marker_table["startTime"][i].as_f64();
+                    let end = marker_table["endTime"][i].as_f64();
+                    let timing = match (marker_table["phase"][i].as_u64()?, start, end) {
+                        (0, Some(start), _) => ExportMarkerTiming::Instant(start),
+                        (1, Some(start), Some(end)) => ExportMarkerTiming::Interval(start, end),
+                        (2, Some(start), _) => ExportMarkerTiming::IntervalStart(start),
+                        (3, _, Some(end)) => ExportMarkerTiming::IntervalEnd(end),
+                        _ => return None,
+                    };
+                    let category = marker_table["category"][i]
+                        .as_u64()
+                        .and_then(|c| category_names.get(c as usize))
+                        .cloned()
+                        .unwrap_or_default();
+                    Some(ExportMarker {
+                        name: string(name).unwrap_or_default(),
+                        category,
+                        timing,
+                        data: marker_table["data"][i].clone(),
+                    })
+                })
+                .collect();
+
+            export_profile.threads.push(ExportThread {
+                name: thread["name"].as_str().unwrap_or_default().to_string(),
+                process_name: thread["processName"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                pid: json_id(&thread["pid"]),
+                tid: json_id(&thread["tid"]),
+                samples: export_samples,
+                markers: export_markers,
+            });
+        }
+
+        export_profile
+    }
+
+    /// Returns the frames of the stack, ordered from the root to the leaf.
+    pub fn stack_frames(&self, stack: usize) -> Vec<usize> {
+        let mut frames = Vec::new();
+        let mut current = Some(stack);
+        while let Some(stack) = current {
+            frames.push(self.stacks[stack].frame);
+            current = self.stacks[stack].prefix;
+        }
+        frames.reverse();
+        frames
+    }
+
+    fn frame_index(&mut self, frame: ExportFrame) -> usize {
+        if let Some(index) = self.frame_indexes.get(&frame) {
+            return *index;
+        }
+        let index = self.frames.len();
+        self.frames.push(frame.clone());
+        self.frame_indexes.insert(frame, index);
+        index
+    }
+
+    fn stack_index(&mut self, stack: ExportStack) -> usize {
+        *self.stack_indexes.entry(stack).or_insert_with(|| {
+            self.stacks.push(stack);
+            self.stacks.len() - 1
+        })
+    }
+}
+
+/// Returns the absolute sample times, from either the "time" or the
+/// "timeDeltas" column.
+fn sample_times(samples: &Value, len: usize) -> Vec<f64> {
+    if let Some(times) = samples["time"].as_array() {
+        return times.iter().map(|t| t.as_f64().unwrap_or(0.0)).collect();
+    }
+    let mut time = 0.0;
+    (0..len)
+        .map(|i| {
+            time += samples["timeDeltas"][i].as_f64().unwrap_or(0.0);
+            time
+        })
+        .collect()
+}
+
+/// Pids and tids are strings in newer profiles and numbers in older ones.
+fn json_id(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn column<'a>(table: &'a Value, key: &str) -> &'a [Value] {
+    table[key].as_array().map(Vec::as_slice).unwrap_or(&[])
+}
@@ -0,0 +1,327 @@
+//! Export to the pprof format, as consumed by `go tool pprof` and other tools
+//! from the pprof ecosystem. The message types mirror pprof's profile.proto.
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use prost::Message;
+
+use super::ExportProfile;
+
+#[derive(Clone, PartialEq, Message)]
+struct Profile {
+    #[prost(message, repeated, tag = "1")]
+    sample_type: Vec<ValueType>,
+    #[prost(message, repeated, tag = "2")]
+    sample: Vec<Sample>,
+    #[prost(message, repeated, tag = "3")]
+    mapping: Vec<Mapping>,
+    #[prost(message, repeated, tag = "4")]
+    location: Vec<Location>,
+    #[prost(message, repeated, tag = "5")]
+    function: Vec<Function>,
+    #[prost(string, repeated, tag = "6")]
+    string_table: Vec<String>,
+    #[prost(int64, tag = "9")]
+    time_nanos: i64,
+    #[prost(int64, tag = "10")]
+    duration_nanos: i64,
+    #[prost(message, optional, tag = "11")]
+    period_type: Option<ValueType>,
+    #[prost(int64, tag = "12")]
+    period: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ValueType {
+    #[prost(int64, tag = "1")]
+    r#type: i64,
+    #[prost(int64, tag = "2")]
+    unit: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Sample {
+    /// Leaf first.
+    #[prost(uint64, repeated, tag = "1")]
+    location_id: Vec<u64>,
+    #[prost(int64, repeated, tag = "2")]
+    value: Vec<i64>,
+    #[prost(message, repeated, tag = "3")]
+    label: Vec<Label>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Label {
+    #[prost(int64, tag = "1")]
+    key: i64,
+    #[prost(int64, tag = "2")]
+    str: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Mapping {
+    #[prost(uint64, tag = "1")]
+    id: u64,
+    #[prost(int64, tag = "5")]
+    filename: i64,
+    #[prost(bool, tag = "7")]
+    has_functions: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Location {
+    #[prost(uint64, tag = "1")]
+    id: u64,
+    #[prost(uint64, tag = "2")]
+    mapping_id: u64,
+    #[prost(message, repeated, tag = "4")]
+    line: Vec<Line>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Line {
+    #[prost(uint64, tag = "1")]
+    function_id: u64,
+    #[prost(int64, tag = "2")]
+    line: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Function {
+    #[prost(uint64, tag = "1")]
+    id: u64,
+    #[prost(int64, tag = "2")]
+    name: i64,
+    #[prost(int64, tag = "3")]
+    system_name: i64,
+    #[prost(int64, tag = "4")]
+    filename: i64,
+}
+
+/// The pprof string table. Index 0 must be the empty string.
+struct StringTable {
+    strings: Vec<String>,
+    indexes: HashMap<String, i64>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        let mut table = StringTable {
+            strings: Vec::new(),
+            indexes: HashMap::new(),
+        };
+        table.index_for_string("");
+        table
+    }
+
+    fn index_for_string(&mut self, s: &str) -> i64 {
+        if let Some(index) = self.indexes.get(s) {
+            return *index;
+        }
+        let index = self.strings.len() as i64;
+        self.strings.push(s.to_string());
+        self.indexes.insert(s.to_string(), index);
+        index
+    }
+}
+
+/// Writes the profile as a gzip-compressed pprof protobuf.
+///
+/// Every exported frame becomes one location with one line. Samples with the
+/// same stack on the same thread are combined, and carry the thread name,
+/// pid and tid as labels, so that they can be filtered with pprof's -tagfocus.
+pub fn write_pprof(profile: &ExportProfile, w: impl Write) -> std::io::Result<()> {
+    let pprof = build_pprof_profile(profile);
+    let mut encoder = GzEncoder::new(w, Compression::default());
+    encoder.write_all(&pprof.encode_to_vec())?;
+    encoder.finish()?;
+    Ok(())
+}
+
+fn build_pprof_profile(profile: &ExportProfile) -> Profile {
+    let mut strings = StringTable::new();
+    let interval_nanos = (profile.interval * 1_000_000.0) as i64;
+
+    // With sample counts, also provide the estimated CPU time, which is what
+    // pprof users usually look at.
+    let value_types: &[(&str, &str)] = match profile.weight_type.as_str() {
+        "samples" => &[("samples", "count"), ("cpu", "nanoseconds")],
+        "tracing-ms" => &[("time", "milliseconds")],
+        "bytes" => &[("space", "bytes")],
+        other => &[(other, "count")],
+    };
+    let sample_type = value_types
+        .iter()
+        .map(|(ty, unit)| ValueType {
+            r#type: strings.index_for_string(ty),
+            unit: strings.index_for_string(unit),
+        })
+        .collect();
+
+    let mut mappings = Vec::new();
+    let mut mapping_ids: HashMap<&str, u64> = HashMap::new();
+    let mut functions = Vec::new();
+    let mut function_ids: HashMap<(&str, Option<&str>), u64> = HashMap::new();
+    // Location ids are frame indexes plus one, because id 0 is reserved.
+    let locations = profile
+        .frames
+        .iter()
+        .enumerate()
+        .map(|(frame_index, frame)| {
+            let mapping_id = match frame.lib.as_deref() {
+                Some(lib) => *mapping_ids.entry(lib).or_insert_with(|| {
+                    let id = mappings.len() as u64 + 1;
+                    mappings.push(Mapping {
+                        id,
+                        filename: strings.index_for_string(lib),
+                        has_functions: true,
+                    });
+                    id
+                }),
+                None => 0,
+            };
+            let function_id = *function_ids
+                .entry((frame.function.as_str(), frame.file.as_deref()))
+                .or_insert_with(|| {
+                    let id = functions.len() as u64 + 1;
+                    let name = strings.index_for_string(&frame.function);
+                    functions.push(Function {
+                        id,
+                        name,
+                        system_name: name,
+                        filename: strings.index_for_string(frame.file.as_deref().unwrap_or("")),
+                    });
+                    id
+                });
+            Location {
+                id: frame_index as u64 + 1,
+                mapping_id,
+                line: vec![Line {
+                    function_id,
+                    line: frame.line.map_or(0, i64::from),
+                }],
+            }
+        })
+        .collect();
+
+    let thread_key = strings.index_for_string("thread");
+    let pid_key = strings.index_for_string("pid");
+    let tid_key = strings.index_for_string("tid");
+    let mut samples = Vec::new();
+    let mut duration: f64 = 0.0;
+    for thread in &profile.threads {
+        let mut weight_per_stack: HashMap<usize, f64> = HashMap::new();
+        for sample in &thread.samples {
+            duration = duration.max(sample.time);
+            if let Some(stack) = sample.stack {
+                *weight_per_stack.entry(stack).or_default() += sample.weight;
+            }
+        }
+        let label = vec![
+            Label {
+                key: thread_key,
+                str: strings.index_for_string(&thread.name),
+            },
+            Label {
+                key: pid_key,
+                str: strings.index_for_string(&thread.pid),
+            },
+            Label {
+                key: tid_key,
+                str: strings.index_for_string(&thread.tid),
+            },
+        ];
+        let mut stacks: Vec<(usize, f64)> = weight_per_stack.into_iter().collect();
+        stacks.sort_by_key(|(stack, _)| *stack);
+        for (stack, weight) in stacks {
+            let mut location_id: Vec<u64> = profile
+                .stack_frames(stack)
+                .into_iter()
+                .map(|frame| frame as u64 + 1)
+                .collect();
+            location_id.reverse();
+            let mut value = vec![weight.round() as i64];
+            if value_types.len() == 2 {
+                value.push((weight * interval_nanos as f64).round() as i64);
+            }
+            samples.push(Sample {
+                location_id,
+                value,
+                label: label.clone(),
+            });
+        }
+    }
+
+    let period_type = ValueType {
+        r#type: strings.index_for_string("cpu"),
+        unit: strings.index_for_string("nanoseconds"),
+    };
+    Profile {
+        sample_type,
+        sample: samples,
+        mapping: mappings,
+        location: locations,
+        function: functions,
+        string_table: strings.strings,
+        time_nanos: (profile.start_time * 1_000_000.0) as i64,
+        duration_nanos: (duration * 1_000_000.0) as i64,
+        period_type: Some(period_type),
+        period: interval_nanos,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::export::symbols::FrameSymbols;
+
+    #[test]
+    fn pprof_profile_structure() {
+        let profile = json!({
+            "meta": { "interval": 1.0, "startTime": 1000.0 },
+            "libs": [{ "name": "app" }],
+            "shared": { "stringArray": ["main", "foo", "main.c"] },
+            "threads": [{
+                "name": "main", "processName": "app", "pid": "1", "tid": 1,
+                "funcTable": { "name": [0, 1], "resource": [0, 0], "fileName": [2, null], "lineNumber": [3, null] },
+                "resourceTable": { "lib": [0] },
+                "frameTable": { "func": [0, 1], "address": [-1, -1], "line": [null, null] },
+                "stackTable": { "prefix": [null, 0], "frame": [0, 1] },
+                "samples": { "stack": [1, 1, 0], "timeDeltas": [0, 1, 1], "weight": null },
+            }],
+        });
+        let export_profile =
+            ExportProfile::from_processed_profile(&profile, &FrameSymbols::default());
+        let pprof = build_pprof_profile(&export_profile);
+        let decoded = Profile::decode(pprof.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, pprof);
+
+        let string = |index: i64| pprof.string_table[index as usize].as_str();
+        assert_eq!(string(0), "");
+        assert_eq!(pprof.mapping.len(), 1);
+        assert_eq!(string(pprof.mapping[0].filename), "app");
+        assert_eq!(pprof.function.len(), 2);
+        assert_eq!(string(pprof.function[0].filename), "main.c");
+        assert_eq!(pprof.location[0].line[0].line, 3);
+        assert_eq!(pprof.time_nanos, 1_000_000_000);
+
+        let mut samples: Vec<(Vec<u64>, Vec<i64>)> = pprof
+            .sample
+            .iter()
+            .map(|s| (s.location_id.clone(), s.value.clone()))
+            .collect();
+        samples.sort();
+        assert_eq!(
+            samples,
+            vec![
+                (vec![1], vec![1, 1_000_000]),
+                (vec![2, 1], vec![2, 2_000_000])
+            ]
+        );
+    }
+}
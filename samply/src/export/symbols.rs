@@ -0,0 +1,192 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use futures_util::future::join_all;
+use serde_json::Value;
+use wholesym::debugid::DebugId;
+use wholesym::{LookupAddress64, SymbolManager};
+
+use super::ExportFrame;
+use crate::profile_json_preparse::parse_libinfo_map_from_profile_file;
+use crate::shared::prop_types::SymbolProps;
+use crate::shared::symbol_precog::PrecogSymbolInfo;
+use crate::symbols::create_symbol_manager_and_quota_manager;
+
+/// Symbol information for the frame addresses of a profile, keyed by the index
+/// of the library in the profile's lib table and the relative address.
+#[derive(Debug, Default)]
+pub struct FrameSymbols {
+    /// The frames for each address, from the outer function to the innermost
+    /// inlined function. The `lib` field of these frames is not set.
+    frames: HashMap<(usize, u64), Vec<ExportFrame>>,
+}
+
+impl FrameSymbols {
+    pub fn lookup(&self, lib: usize, address: u64) -> Option<&[ExportFrame]> {
+        self.frames.get(&(lib, address)).map(Vec::as_slice)
+    }
+
+    pub fn insert(&mut self, lib: usize, address: u64, frames: Vec<ExportFrame>) {
+        self.frames.insert((lib, address), frames);
+    }
+}
+
+/// Looks up the symbols for all frame addresses in an unsymbolicated profile,
+/// using the same symbol sources as `samply load`, including the sidecar
+/// .syms.json file written by --presymbolicate.
+///
+/// Profiles which are already symbolicated are left alone.
+pub fn symbolicate_profile(
+    profile: &Value,
+    profile_path: &Path,
+    symbol_props: SymbolProps,
+) -> FrameSymbols {
+    if profile["meta"]["symbolicated"] == true {
+        return FrameSymbols::default();
+    }
+
+    let addresses_per_lib = collect_addresses_per_lib(profile);
+    if addresses_per_lib.is_empty() {
+        return FrameSymbols::default();
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let (mut symbol_manager, quota_manager) =
+            create_symbol_manager_and_quota_manager(symbol_props, false);
+        if let Ok(libinfo_map) =
+            File::open(profile_path).and_then(parse_libinfo_map_from_profile_file)
+        {
+            for lib_info in libinfo_map.into_values() {
+                symbol_manager.add_known_library(lib_info);
+            }
+        }
+        let precog_path = profile_path.with_extension("syms.json");
+        if let Some(precog_info) = PrecogSymbolInfo::try_load(&precog_path) {
+            for symbol_map in precog_info.into_iter() {
+                let lib_info = symbol_map.library_info();
+                symbol_manager.add_known_library_symbols(lib_info, Arc::new(symbol_map));
+            }
+        }
+
+        let libs = profile["libs"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        let lookups = addresses_per_lib.into_iter().map(|(lib_index, addresses)| {
+            let lib = &libs[lib_index];
+            let symbol_manager = &symbol_manager;
+            async move {
+                let frames = lookup_lib_addresses(lib, &addresses, symbol_manager).await;
+                (lib_index, frames)
+            }
+        });
+        let results = join_all(lookups).await;
+
+        if let Some(quota_manager) = quota_manager {
+            quota_manager.finish().await;
+        }
+
+        let mut frame_symbols = FrameSymbols::default();
+        for (lib_index, frames) in results {
+            for (address, frames) in frames {
+                frame_symbols.insert(lib_index, address, frames);
+            }
+        }
+        frame_symbols
+    })
+}
+
+fn collect_addresses_per_lib(profile: &Value) -> HashMap<usize, BTreeSet<u64>> {
+    let lib_count = profile["libs"].as_array().map_or(0, Vec::len);
+    let mut addresses_per_lib: HashMap<usize, BTreeSet<u64>> = HashMap::new();
+    for thread in profile["threads"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or(&[])
+    {
+        let func_resources = &thread["funcTable"]["resource"];
+        let resource_libs = &thread["resourceTable"]["lib"];
+        let frame_table = &thread["frameTable"];
+        let frame_count = frame_table["func"].as_array().map_or(0, Vec::len);
+        for frame in 0..frame_count {
+            let Some(address) = frame_table["address"][frame].as_i64() else {
+                continue;
+            };
+            let lib = frame_table["func"][frame]
+                .as_u64()
+                .and_then(|func| func_resources[func as usize].as_i64())
+                .filter(|resource| *resource >= 0)
+                .and_then(|resource| resource_libs[resource as usize].as_u64())
+                .map(|lib| lib as usize);
+            if let (Some(lib), Ok(address)) = (lib, u64::try_from(address)) {
+                if lib < lib_count {
+                    addresses_per_lib.entry(lib).or_default().insert(address);
+                }
+            }
+        }
+    }
+    addresses_per_lib
+}
+
+async fn lookup_lib_addresses(
+    lib: &Value,
+    addresses: &BTreeSet<u64>,
+    symbol_manager: &SymbolManager,
+) -> Vec<(u64, Vec<ExportFrame>)> {
+    let (Some(debug_name), Some(breakpad_id)) =
+        (lib["debugName"].as_str(), lib["breakpadId"].as_str())
+    else {
+        return Vec::new();
+    };
+    let Ok(debug_id) = DebugId::from_breakpad(breakpad_id) else {
+        return Vec::new();
+    };
+    let Ok(symbol_map) = symbol_manager.load_symbol_map(debug_name, debug_id).await else {
+        return Vec::new();
+    };
+
+    let mut result = Vec::new();
+    for &address in addresses {
+        let Some(info) = symbol_map
+            .lookup_u64(LookupAddress64::Relative(address))
+            .await
+        else {
+            continue;
+        };
+        let symbol_name = symbol_map
+            .resolve_symbol_name(info.symbol.name)
+            .into_owned();
+        let frames: Vec<ExportFrame> = match &info.frames {
+            Some(frames) if !frames.is_empty() => frames
+                .iter()
+                .rev()
+                .map(|frame| ExportFrame {
+                    function: frame
+                        .function
+                        .map(|handle| symbol_map.resolve_function_name(handle).into_owned())
+                        .unwrap_or_else(|| symbol_name.clone()),
+                    file: frame.file_path.map(|handle| {
+                        let path = symbol_map.resolve_source_file_path(handle);
+                        path.special_path_str()
+                            .unwrap_or_else(|| path.raw_path().into())
+                            .into_owned()
+                    }),
+                    line: frame.line_number,
+                    lib: None,
+                })
+                .collect(),
+            _ => vec![ExportFrame {
+                function: symbol_name,
+                file: None,
+                line: None,
+                lib: None,
+            }],
+        };
+        result.push((address, frames));
+    }
+    result
+}
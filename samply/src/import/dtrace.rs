@@ -0,0 +1,583 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use fxprof_processed_profile::{
+    CategoryHandle, CpuDelta, FrameAddress, FrameFlags, FrameHandle, LibraryInfo, ProcessHandle,
+    Profile, ReferenceTimestamp, SamplingInterval, ThreadHandle, Timestamp,
+};
+use object::{BinaryFormat, Object, ObjectSegment, ObjectSymbol, SymbolKind};
+use samply_object::{code_id_for_object, debug_id_for_object, relative_address_base};
+
+use crate::shared::prop_types::ProfileCreationProps;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("The file does not contain any DTrace stacks")]
+    NoStacks,
+}
+
+/// One aggregated stack, with frames ordered from the root to the leaf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregatedStack {
+    pub frames: Vec<Frame>,
+    pub count: u64,
+}
+
+/// A frame of DTrace stack output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// "module`function+0x1a", or "module`function" if there is no offset,
+    /// e.g. in folded stacks.
+    Function {
+        module: String,
+        function: String,
+        offset: Option<u64>,
+    },
+    /// "module`0x401a2c", an absolute address in a module which DTrace couldn't
+    /// find a symbol for.
+    ModuleAddress { module: String, address: u64 },
+    /// "0x7fff2034a1c2", an address which DTrace couldn't attribute to a module.
+    Address(u64),
+    /// A frame without a module, e.g. "main" in folded stacks.
+    Label(String),
+}
+
+impl Frame {
+    fn parse(frame: &str) -> Option<Self> {
+        if frame.is_empty() || frame.contains(char::is_whitespace) {
+            return None;
+        }
+        if let Some(address) = parse_address(frame) {
+            return Some(Frame::Address(address));
+        }
+        let Some((module, symbol)) = frame.split_once('`') else {
+            return Some(Frame::Label(frame.to_string()));
+        };
+        let module = module.to_string();
+        if let Some(address) = parse_address(symbol) {
+            return Some(Frame::ModuleAddress { module, address });
+        }
+        let (function, offset) = match symbol.rsplit_once('+') {
+            Some((function, offset)) => match parse_address(offset) {
+                Some(offset) => (function, Some(offset)),
+                None => (symbol, None),
+            },
+            None => (symbol, None),
+        };
+        Some(Frame::Function {
+            module,
+            function: function.to_string(),
+            offset,
+        })
+    }
+
+    /// The label of the frame if it can't be resolved to an address in a
+    /// module. It leaves out the offset, so that all samples in the same
+    /// function share one frame.
+    fn label(&self) -> String {
+        match self {
+            Frame::Function {
+                module, function, ..
+            } => format!("{module}`{function}"),
+            Frame::ModuleAddress { module, address } => format!("{module}`{address:#x}"),
+            Frame::Address(address) => format!("{address:#x}"),
+            Frame::Label(label) => label.clone(),
+        }
+    }
+}
+
+/// Returns whether `data` looks like the start of DTrace stack output, either in
+/// the raw multi-line format printed for `ustack()` / `stack()` aggregations, or
+/// in the folded one-line-per-stack format.
+pub fn is_dtrace_data(data: &[u8]) -> bool {
+    let data = &data[..data.len().min(4096)];
+    if data.contains(&0) {
+        // Binary data, e.g. a perf.data file.
+        return false;
+    }
+    let Ok(text) = std::str::from_utf8(data) else {
+        return false;
+    };
+    // Check the first complete non-empty line, skipping DTrace's own status messages.
+    let mut lines = text.lines();
+    if data.len() == 4096 {
+        // The last line might be cut off.
+        lines.next_back();
+    }
+    lines
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with("dtrace:"))
+        .map(|line| {
+            // Raw stacks are indented, folded stacks are not.
+            if line.starts_with(char::is_whitespace) {
+                Frame::parse(line.trim()).is_some()
+            } else {
+                parse_folded_line(line).is_some()
+            }
+        })
+        .next()
+        .unwrap_or(false)
+}
+
+/// Parses DTrace stack output. Both the raw format, where each stack is printed
+/// leaf first with one frame per line and followed by its count, and the folded
+/// format, where each line is "root;...;leaf count", are supported.
+pub fn parse_stacks<R: BufRead>(reader: R) -> Result<Vec<AggregatedStack>, Error> {
+    let mut stacks = Vec::new();
+    let mut raw_frames = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with("dtrace:") {
+            continue;
+        }
+
+        if !line.starts_with(char::is_whitespace) {
+            // Folded stacks are not indented.
+            if let Some(stack) = parse_folded_line(line) {
+                stacks.push(stack);
+            }
+            continue;
+        }
+
+        let trimmed = line.trim();
+        match trimmed.parse::<u64>() {
+            Ok(count) => {
+                // The count terminates a raw stack.
+                if !raw_frames.is_empty() {
+                    raw_frames.reverse();
+                    stacks.push(AggregatedStack {
+                        frames: std::mem::take(&mut raw_frames),
+                        count,
+                    });
+                }
+            }
+            Err(_) => {
+                if let Some(frame) = Frame::parse(trimmed) {
+                    raw_frames.push(frame);
+                }
+            }
+        }
+    }
+    Ok(stacks)
+}
+
+fn parse_folded_line(line: &str) -> Option<AggregatedStack> {
+    let (stack, count) = line.rsplit_once(char::is_whitespace)?;
+    let count = count.parse::<u64>().ok()?;
+    let frames: Vec<Frame> = stack
+        .trim_end()
+        .split(';')
+        .filter_map(Frame::parse)
+        .collect();
+    if frames.is_empty() {
+        return None;
+    }
+    Some(AggregatedStack { frames, count })
+}
+
+/// Parses a hex number like "0x7fff2034a1c2".
+fn parse_address(s: &str) -> Option<u64> {
+    let hex = s.strip_prefix("0x")?;
+    u64::from_str_radix(hex, 16).ok()
+}
+
+/// Converts DTrace stack output into a profile.
+///
+/// DTrace aggregations don't have timestamps, so each aggregated stack becomes
+/// one sample whose weight is the stack's count. The call tree and flame graph
+/// are meaningful, but the timeline is not.
+///
+/// DTrace only prints the file names of modules. Modules whose binary is found
+/// in `binary_lookup_dirs` are added as libraries, and their frames get
+/// library-relative addresses, so that they're symbolicated with file and line
+/// information like the frames of recorded profiles. The other frames keep the
+/// names which DTrace printed.
+pub fn convert<R: BufRead>(
+    reader: R,
+    binary_lookup_dirs: Vec<PathBuf>,
+    profile_creation_props: ProfileCreationProps,
+) -> Result<Profile, Error> {
+    let stacks = parse_stacks(reader)?;
+    if stacks.is_empty() {
+        return Err(Error::NoStacks);
+    }
+
+    let mut profile = Profile::new(
+        profile_creation_props.profile_name(),
+        ReferenceTimestamp::from_system_time(SystemTime::now()),
+        SamplingInterval::from_millis(1),
+    );
+    profile_creation_props.apply_thread_name_filter(&mut profile);
+    let process = profile.add_process("dtrace", 0, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        0,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    profile.set_thread_name(thread, profile_creation_props.profile_name());
+    let category = CategoryHandle::OTHER;
+    let mut modules = Modules::new(binary_lookup_dirs);
+
+    for (i, stack) in stacks.iter().enumerate() {
+        let leaf_index = stack.frames.len() - 1;
+        let mut frames = stack.frames.iter().enumerate();
+        let stack_handle = profile.handle_for_stack_frames(thread, |profile| {
+            let (frame_index, frame) = frames.next()?;
+            let is_leaf = frame_index == leaf_index;
+            Some(frame_handle(
+                profile,
+                process,
+                thread,
+                &mut modules,
+                frame,
+                is_leaf,
+                category,
+            ))
+        });
+        profile.add_sample_with_fractional_weight(
+            thread,
+            Timestamp::from_millis_since_reference(i as f64),
+            stack_handle,
+            CpuDelta::ZERO,
+            stack.count as f64,
+        );
+    }
+
+    Ok(profile)
+}
+
+fn frame_handle(
+    profile: &mut Profile,
+    process: ProcessHandle,
+    thread: ThreadHandle,
+    modules: &mut Modules,
+    frame: &Frame,
+    is_leaf: bool,
+    category: CategoryHandle,
+) -> FrameHandle {
+    match modules.avma_for_frame(profile, process, frame) {
+        // DTrace prints the return addresses of the callers.
+        Some(address) => {
+            let address = if is_leaf {
+                FrameAddress::InstructionPointer(address)
+            } else {
+                FrameAddress::ReturnAddress(address)
+            };
+            profile.handle_for_frame_with_address(thread, address, category, FrameFlags::empty())
+        }
+        None => {
+            let label = profile.handle_for_string(&frame.label());
+            profile.handle_for_frame_with_label(thread, label, category, FrameFlags::empty())
+        }
+    }
+}
+
+/// The modules of the stacks whose binaries were found. DTrace output doesn't
+/// say where the modules were loaded, so each module is mapped at a made-up
+/// address in the process, and its frames get addresses in that range.
+struct Modules {
+    binary_lookup_dirs: Vec<PathBuf>,
+    /// None for modules whose binary wasn't found or couldn't be parsed.
+    modules: HashMap<String, Option<Module>>,
+    next_base_avma: u64,
+}
+
+struct Module {
+    /// The start of the module's made-up address range in the process.
+    base_avma: u64,
+    /// The size of the module's address range.
+    size: u64,
+    /// The vmaddr which relative addresses are relative to, see
+    /// [`relative_address_base`].
+    base_svma: u64,
+    /// The relative address of each function symbol, by name.
+    symbols: HashMap<String, u64>,
+}
+
+/// The alignment of the made-up module address ranges.
+const MODULE_ALIGNMENT: u64 = 0x1000_0000;
+
+impl Modules {
+    fn new(binary_lookup_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            binary_lookup_dirs,
+            modules: HashMap::new(),
+            next_base_avma: MODULE_ALIGNMENT,
+        }
+    }
+
+    /// Returns the address of the frame in the process, if it can be resolved
+    /// to a relative address in a module whose binary was found.
+    ///
+    /// "module`0x..." frames have absolute addresses, and DTrace doesn't say
+    /// where the module was loaded. They are only resolved if the address is
+    /// inside the module at its preferred address, which is the case for
+    /// executables which aren't position-independent.
+    fn avma_for_frame(
+        &mut self,
+        profile: &mut Profile,
+        process: ProcessHandle,
+        frame: &Frame,
+    ) -> Option<u64> {
+        let (module_name, relative_address) = match frame {
+            Frame::Function {
+                module,
+                function,
+                offset: Some(offset),
+            } => {
+                let module_info = self.get(profile, process, module)?;
+                let function_address = module_info.symbols.get(function.as_str())?;
+                (module, function_address + offset)
+            }
+            Frame::ModuleAddress { module, address } => {
+                let module_info = self.get(profile, process, module)?;
+                (module, address.checked_sub(module_info.base_svma)?)
+            }
+            _ => return None,
+        };
+        let module = self.modules.get(module_name)?.as_ref()?;
+        if relative_address >= module.size {
+            return None;
+        }
+        Some(module.base_avma + relative_address)
+    }
+
+    fn get(
+        &mut self,
+        profile: &mut Profile,
+        process: ProcessHandle,
+        name: &str,
+    ) -> Option<&Module> {
+        if !self.modules.contains_key(name) {
+            let module = self.load(profile, process, name);
+            self.modules.insert(name.to_string(), module);
+        }
+        self.modules.get(name)?.as_ref()
+    }
+
+    /// Finds and parses the module's binary, and adds it to the profile as a
+    /// library which is mapped into the process.
+    fn load(
+        &mut self,
+        profile: &mut Profile,
+        process: ProcessHandle,
+        name: &str,
+    ) -> Option<Module> {
+        // Only plain file names are looked up, not paths.
+        if name.contains(['/', '\\']) || name == "." || name == ".." {
+            return None;
+        }
+        let path = self
+            .binary_lookup_dirs
+            .iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())?;
+        let file = std::fs::File::open(&path).ok()?;
+        let mmap = unsafe { memmap2::Mmap::map(&file).ok()? };
+        let object = object::File::parse(&mmap[..]).ok()?;
+        let lib_info = library_info(name, &path, &object)?;
+
+        let base_svma = relative_address_base(&object);
+        let end_svma = object
+            .segments()
+            .map(|segment| segment.address() + segment.size())
+            .max()?;
+        let size = end_svma.checked_sub(base_svma)?;
+        let relative_address_at_start = 0;
+        let base_avma = self.next_base_avma;
+        self.next_base_avma = (base_avma + size).next_multiple_of(MODULE_ALIGNMENT);
+
+        let lib = profile.add_lib(lib_info);
+        profile.add_lib_mapping(
+            process,
+            lib,
+            base_avma,
+            base_avma + size,
+            relative_address_at_start,
+        );
+        Some(Module {
+            base_avma,
+            size,
+            base_svma,
+            symbols: function_symbols(&object, base_svma),
+        })
+    }
+}
+
+fn library_info(name: &str, path: &Path, object: &object::File) -> Option<LibraryInfo> {
+    let debug_id = debug_id_for_object(object)?;
+    let path = path.to_string_lossy().into_owned();
+    Some(LibraryInfo {
+        name: name.to_string(),
+        debug_name: name.to_string(),
+        path: path.clone(),
+        debug_path: path,
+        debug_id,
+        code_id: code_id_for_object(object).map(|code_id| code_id.to_string()),
+        arch: None,
+    })
+}
+
+/// The relative addresses of the binary's function symbols, by the names which
+/// DTrace prints for them. DTrace leaves out the leading underscore of mach-O
+/// symbol names.
+fn function_symbols(object: &object::File, base_svma: u64) -> HashMap<String, u64> {
+    let strip_underscore = object.format() == BinaryFormat::MachO;
+    object
+        .symbols()
+        .chain(object.dynamic_symbols())
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.is_definition())
+        .filter_map(|symbol| {
+            let name = symbol.name().ok()?;
+            let name = match strip_underscore {
+                true => name.strip_prefix('_').unwrap_or(name),
+                false => name,
+            };
+            let relative_address = symbol.address().checked_sub(base_svma)?;
+            Some((name.to_string(), relative_address))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::*;
+    use crate::cli::{Action, Opt};
+
+    fn function(module: &str, function: &str, offset: Option<u64>) -> Frame {
+        Frame::Function {
+            module: module.to_string(),
+            function: function.to_string(),
+            offset,
+        }
+    }
+
+    #[test]
+    fn parse_raw_and_folded_stacks() {
+        let raw = "dtrace: description 'profile-997 ' matched 1 probe\n\
+                   \n\
+                   \x20             libsystem_kernel.dylib`__psynch_cvwait+0xa\n\
+                   \x20             a.out`main+0x20\n\
+                   \x20             a.out`0x100003f4a\n\
+                   \x20             0x7fff2034a1c2\n\
+                   \x20              12\n\
+                   \n\
+                   \x20             a.out`main+0x24\n\
+                   \x20               3\n";
+        let stacks = parse_stacks(raw.as_bytes()).unwrap();
+        assert_eq!(
+            stacks,
+            vec![
+                AggregatedStack {
+                    frames: vec![
+                        Frame::Address(0x7fff2034a1c2),
+                        Frame::ModuleAddress {
+                            module: "a.out".to_string(),
+                            address: 0x100003f4a,
+                        },
+                        function("a.out", "main", Some(0x20)),
+                        function("libsystem_kernel.dylib", "__psynch_cvwait", Some(0xa)),
+                    ],
+                    count: 12,
+                },
+                AggregatedStack {
+                    frames: vec![function("a.out", "main", Some(0x24))],
+                    count: 3,
+                },
+            ]
+        );
+        assert!(is_dtrace_data(raw.as_bytes()));
+
+        let folded = "a.out`main;a.out`foo 5\nmain 2\n";
+        let stacks = parse_stacks(folded.as_bytes()).unwrap();
+        assert_eq!(stacks.len(), 2);
+        assert_eq!(
+            stacks[0].frames,
+            vec![
+                function("a.out", "main", None),
+                function("a.out", "foo", None)
+            ]
+        );
+        assert_eq!(stacks[0].count, 5);
+        assert_eq!(stacks[1].frames, vec![Frame::Label("main".to_string())]);
+        assert!(is_dtrace_data(folded.as_bytes()));
+        assert!(!is_dtrace_data(b"PERFILE2\x00\x01"));
+    }
+
+    #[test]
+    fn resolve_frames_in_found_binaries() {
+        // example-linux is a non-PIE executable, based at 0x400000, with main at
+        // 0x401130 and g at 0x4011b0.
+        let raw = "\x20             example-linux`0x4011b4\n\
+                   \x20             example-linux`main+0x10\n\
+                   \x20             libc.so.1`__libc_start_main+0xf3\n\
+                   \x20             0x7fff2034a1c2\n\
+                   \x20              5000000000\n";
+        let fixtures_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("fixtures")
+            .join("other");
+        let Action::Import(import_args) =
+            Opt::parse_from(["samply", "import", "stacks.txt"]).action
+        else {
+            unreachable!()
+        };
+        let profile = convert(
+            raw.as_bytes(),
+            vec![fixtures_dir],
+            import_args.import_props().profile_creation_props,
+        )
+        .unwrap();
+        let json = serde_json::to_value(&profile).unwrap();
+
+        assert_eq!(json["libs"].as_array().unwrap().len(), 1);
+        assert_eq!(json["libs"][0]["debugName"], "example-linux");
+        assert_eq!(
+            json["libs"][0]["breakpadId"],
+            "BE4E976C325246EE9D6B7847A670B2A90"
+        );
+        let thread = &json["threads"][0];
+        let strings = &json["shared"]["stringArray"];
+        let frame_table = &thread["frameTable"];
+        let func_names: Vec<&str> = (0..frame_table["length"].as_u64().unwrap() as usize)
+            .map(|frame| {
+                let func = frame_table["func"][frame].as_u64().unwrap() as usize;
+                let name = thread["funcTable"]["name"][func].as_u64().unwrap() as usize;
+                strings[name].as_str().unwrap()
+            })
+            .collect();
+        let addresses: Vec<i64> = frame_table["address"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|address| address.as_i64().unwrap())
+            .collect();
+        // From the root: the unknown address and the frame of a module which
+        // wasn't found keep their labels. The caller's return address is moved
+        // back into the call instruction, the leaf address is used as is.
+        assert_eq!(
+            func_names,
+            vec![
+                "0x7fff2034a1c2",
+                "libc.so.1`__libc_start_main",
+                "0x113f",
+                "0x11b4"
+            ]
+        );
+        assert_eq!(addresses, vec![-1, -1, 0x113f, 0x11b4]);
+        // The count doesn't fit into an i32, but is kept as the sample weight.
+        assert_eq!(
+            thread["samples"]["weight"],
+            serde_json::json!([5000000000u64])
+        );
+    }
+}
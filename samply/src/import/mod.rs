@@ -1 +1,2 @@
+pub mod dtrace;
 pub mod perf;
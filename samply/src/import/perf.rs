@@ -9,6 +9,7 @@ use fxprof_processed_profile::{Profile, ReferenceTimestamp};
 use linux_perf_data::{linux_perf_event_reader, DsoInfo, DsoKey, PerfFileReader, PerfFileRecord};
 use linux_perf_event_reader::EventRecord;
 
+use crate::linux_shared::lost_events::LostEventsTracker;
 use crate::linux_shared::{
     ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64, Converter, EventInterpretation, KnownEvent,
     MmapRangeOrVec,
@@ -188,6 +189,7 @@ where
     }
 
     let mut last_timestamp = 0;
+    let mut lost_events = LostEventsTracker::default();
 
     while let Ok(Some(record)) = record_iter.next_record(&mut perf_file) {
         let (record, parsed_record, attr_index) = match record {
@@ -204,6 +206,7 @@ where
                 );
             }
             last_timestamp = timestamp;
+            lost_events.handle_timestamp(timestamp);
         }
 
         match parsed_record {
@@ -216,6 +219,12 @@ where
 
                 match interpretation.known_event_indices.get(&attr_index) {
                     Some(KnownEvent::RssStat) => converter.handle_rss_stat_sample::<C>(&e),
+                    Some(KnownEvent::PageFaultSample) => {
+                        converter.handle_page_fault_sample::<C>(&e)
+                    }
+                    Some(KnownEvent::MunmapEnter) => converter.handle_munmap_sample(&e),
+                    Some(KnownEvent::SyscallEnter) => converter.handle_syscall_enter_sample(&e),
+                    Some(KnownEvent::SyscallExit) => converter.handle_syscall_exit_sample::<C>(&e),
                     _ => {
                         // the main event and sched_switch are already covered by regular samples so don't add other event markers
                         if !(attr_index == interpretation.main_event_attr_index
@@ -257,12 +266,16 @@ where
                 };
                 converter.handle_context_switch(e, common);
             }
+            EventRecord::Lost(e) => {
+                lost_events.handle_lost(e.count, last_timestamp);
+            }
             _ => {
                 // println!("{:?}", record.record_type);
             }
         }
     }
 
+    converter.add_lost_events(&lost_events.finish(last_timestamp));
     converter.finish()
 }
 
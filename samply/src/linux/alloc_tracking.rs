@@ -0,0 +1,267 @@
+//! Allocation sampling for `--alloc`. The launched command is run with
+//! libsamply_linux_alloc_preload.so in `LD_PRELOAD`, which writes the sampled
+//! allocations and frees of each process into a file in a temporary directory.
+//! See the samply-linux-alloc-preload crate for the record format.
+//!
+//! The files are read while recording, so that each process's allocations can
+//! be added to the profile before the process's exit is processed.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::{fs, io};
+
+use tempfile::TempDir;
+
+const PRELOAD_LIB_NAME: &str = "libsamply_linux_alloc_preload.so";
+
+/// A sampled allocation, or the free of a sampled allocation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllocationRecord {
+    pub pid: u32,
+    /// CLOCK_MONOTONIC timestamp in nanoseconds.
+    pub timestamp: u64,
+    pub address: u64,
+    /// The number of allocated bytes which this sample stands for; negative
+    /// for frees.
+    pub size: i64,
+    /// Return addresses, starting with the caller of the allocation function.
+    /// Empty for frees.
+    pub stack: Vec<u64>,
+}
+
+pub struct AllocTracking {
+    preload_lib_path: PathBuf,
+    output_dir: TempDir,
+    sample_interval: u64,
+    /// For each pid, how many bytes of its file have been read.
+    read_offsets: HashMap<u32, u64>,
+    /// Records which have been read but not taken yet, sorted by time.
+    pending_records: Vec<AllocationRecord>,
+}
+
+impl AllocTracking {
+    pub fn new(sample_interval: u64) -> io::Result<Self> {
+        let preload_lib_path = find_preload_lib().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Could not find {PRELOAD_LIB_NAME} next to the samply executable. Build it from the samply-linux-alloc-preload directory, or set SAMPLY_ALLOC_PRELOAD_LIB to its path."),
+            )
+        })?;
+        let output_dir = tempfile::tempdir()?;
+        Ok(AllocTracking {
+            preload_lib_path,
+            output_dir,
+            sample_interval,
+            read_offsets: HashMap::new(),
+            pending_records: Vec::new(),
+        })
+    }
+
+    /// Adds the environment variables which make the launched command load the
+    /// preload library. An existing `LD_PRELOAD` is kept.
+    pub fn add_env_vars(&self, env_vars: &mut Vec<(OsString, OsString)>) {
+        let existing_preload = env_vars
+            .iter()
+            .rev()
+            .find(|(name, _)| name == "LD_PRELOAD")
+            .map(|(_, val)| val.clone())
+            .or_else(|| std::env::var_os("LD_PRELOAD"));
+        let mut preload = self.preload_lib_path.clone().into_os_string();
+        if let Some(existing_preload) = existing_preload.filter(|p| !p.is_empty()) {
+            preload.push(":");
+            preload.push(existing_preload);
+        }
+        env_vars.push(("LD_PRELOAD".into(), preload));
+        env_vars.push((
+            "SAMPLY_ALLOC_OUTPUT_DIR".into(),
+            self.output_dir.path().into(),
+        ));
+        env_vars.push((
+            "SAMPLY_ALLOC_SAMPLE_INTERVAL".into(),
+            self.sample_interval.to_string().into(),
+        ));
+    }
+
+    /// Reads the records which were appended to the files of all processes
+    /// since the last call.
+    pub fn poll(&mut self) {
+        let Ok(entries) = fs::read_dir(self.output_dir.path()) else {
+            return;
+        };
+        let pending_count = self.pending_records.len();
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let Some(pid) = file_name
+                .to_str()
+                .and_then(|name| name.strip_prefix("alloc-"))
+                .and_then(|name| name.strip_suffix(".bin"))
+                .and_then(|pid| pid.parse().ok())
+            else {
+                continue;
+            };
+            let read_offset = self.read_offsets.entry(pid).or_default();
+            let mut data = Vec::new();
+            let read_result = File::open(entry.path()).and_then(|mut file| {
+                file.seek(SeekFrom::Start(*read_offset))?;
+                file.read_to_end(&mut data)
+            });
+            if let Err(err) = read_result {
+                eprintln!(
+                    "Could not read the allocations of process {pid} from {:?}: {err}",
+                    entry.path()
+                );
+                continue;
+            }
+            let (records, consumed_len) = parse_records(pid, &data);
+            *read_offset += consumed_len as u64;
+            self.pending_records.extend(records);
+        }
+        if self.pending_records.len() != pending_count {
+            self.pending_records.sort_by_key(|record| record.timestamp);
+        }
+    }
+
+    /// Takes the records which have been read so far and which happened at or
+    /// before `timestamp`, sorted by time.
+    pub fn take_records_until(
+        &mut self,
+        timestamp: u64,
+    ) -> impl Iterator<Item = AllocationRecord> + '_ {
+        let count = self
+            .pending_records
+            .partition_point(|record| record.timestamp <= timestamp);
+        self.pending_records.drain(..count)
+    }
+
+    /// Reads and returns all remaining records, sorted by time, and deletes the
+    /// temporary directory.
+    pub fn finish(mut self) -> Vec<AllocationRecord> {
+        self.poll();
+        self.pending_records
+    }
+}
+
+fn find_preload_lib() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("SAMPLY_ALLOC_PRELOAD_LIB") {
+        return Some(path.into());
+    }
+    let exe = std::env::current_exe().ok()?;
+    let path = exe.parent()?.join(PRELOAD_LIB_NAME);
+    path.exists().then_some(path)
+}
+
+/// Parses the records which the preload library wrote for one process, and
+/// returns them with the number of bytes they take up. A truncated record at
+/// the end, e.g. one which is still being written, is left for the next read.
+fn parse_records(pid: u32, data: &[u8]) -> (Vec<AllocationRecord>, usize) {
+    let mut values = data
+        .chunks_exact(8)
+        .map(|chunk| u64::from_ne_bytes(chunk.try_into().unwrap()));
+    let mut records = Vec::new();
+    let mut consumed_value_count = 0;
+    while let (Some(timestamp), Some(size), Some(address), Some(frame_count)) =
+        (values.next(), values.next(), values.next(), values.next())
+    {
+        let stack: Vec<u64> = values.by_ref().take(frame_count as usize).collect();
+        if stack.len() as u64 != frame_count {
+            break;
+        }
+        consumed_value_count += 4 + stack.len();
+        records.push(AllocationRecord {
+            pid,
+            timestamp,
+            address,
+            size: size as i64,
+            stack,
+        });
+    }
+    (records, consumed_value_count * 8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_allocation_records() {
+        let values: [u64; 11] = [
+            // An allocation of 4096 bytes at 0x1000 with two frames.
+            100,
+            4096,
+            0x1000,
+            2,
+            0x5555_0010,
+            0x5555_0020,
+            // Its free.
+            200,
+            (-4096i64) as u64,
+            0x1000,
+            0,
+            // A truncated record.
+            300,
+        ];
+        let data: Vec<u8> = values.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let (records, consumed_len) = parse_records(42, &data);
+        assert_eq!(consumed_len, 10 * 8);
+        assert_eq!(
+            records,
+            vec![
+                AllocationRecord {
+                    pid: 42,
+                    timestamp: 100,
+                    address: 0x1000,
+                    size: 4096,
+                    stack: vec![0x5555_0010, 0x5555_0020],
+                },
+                AllocationRecord {
+                    pid: 42,
+                    timestamp: 200,
+                    address: 0x1000,
+                    size: -4096,
+                    stack: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn poll_reads_appended_records() {
+        let mut alloc_tracking = AllocTracking {
+            preload_lib_path: PathBuf::new(),
+            output_dir: tempfile::tempdir().unwrap(),
+            sample_interval: 1,
+            read_offsets: HashMap::new(),
+            pending_records: Vec::new(),
+        };
+        let path = alloc_tracking.output_dir.path().join("alloc-7.bin");
+        let values: [u64; 9] = [100, 16, 0x1000, 1, 0x5555_0010, 200, 32, 0x2000, 1];
+        let data: Vec<u8> = values.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        fs::write(&path, &data[..data.len() - 4]).unwrap();
+
+        alloc_tracking.poll();
+        assert_eq!(alloc_tracking.take_records_until(50).count(), 0);
+        let records: Vec<_> = alloc_tracking.take_records_until(1000).collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].timestamp, 100);
+
+        // Finish the second record, which was only partially written before.
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut file, &data[data.len() - 4..]).unwrap();
+        std::io::Write::write_all(&mut file, &0x5555_0020u64.to_ne_bytes()).unwrap();
+
+        let records = alloc_tracking.finish();
+        assert_eq!(
+            records,
+            vec![AllocationRecord {
+                pid: 7,
+                timestamp: 200,
+                address: 0x2000,
+                size: 32,
+                stack: vec![0x5555_0020],
+            }]
+        );
+    }
+}
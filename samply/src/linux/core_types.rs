@@ -0,0 +1,112 @@
+//! Finds out which CPUs are performance cores and which are efficiency cores,
+//! for `--core-types`.
+//!
+//! On Intel hybrid CPUs, the kernel has a separate PMU for each core type,
+//! and /sys/devices/cpu_core/cpus and /sys/devices/cpu_atom/cpus list the
+//! P-cores and E-cores. On ARM, cores of different types have a different
+//! /sys/devices/system/cpu/cpuN/cpu_capacity, and the cores with the highest
+//! capacity are the performance cores.
+
+use std::fs;
+
+use crate::shared::per_cpu::CoreType;
+
+/// Returns the core type of each CPU, indexed by CPU number, or `None` if
+/// all cores are of the same type.
+pub fn detect_core_types() -> Option<Vec<Option<CoreType>>> {
+    if let (Ok(p_cores), Ok(e_cores)) = (
+        fs::read_to_string("/sys/devices/cpu_core/cpus"),
+        fs::read_to_string("/sys/devices/cpu_atom/cpus"),
+    ) {
+        let mut cpus: Vec<(u32, CoreType)> = parse_cpu_list(&p_cores)
+            .into_iter()
+            .map(|cpu| (cpu, CoreType::Performance))
+            .collect();
+        cpus.extend(
+            parse_cpu_list(&e_cores)
+                .into_iter()
+                .map(|cpu| (cpu, CoreType::Efficiency)),
+        );
+        return Some(core_types_by_cpu(&cpus));
+    }
+
+    let online_cpus = fs::read_to_string("/sys/devices/system/cpu/online").ok()?;
+    let capacities: Vec<(u32, u32)> = parse_cpu_list(&online_cpus)
+        .into_iter()
+        .filter_map(|cpu| {
+            let path = format!("/sys/devices/system/cpu/cpu{cpu}/cpu_capacity");
+            let capacity = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+            Some((cpu, capacity))
+        })
+        .collect();
+    core_types_from_capacities(&capacities)
+}
+
+fn core_types_from_capacities(capacities: &[(u32, u32)]) -> Option<Vec<Option<CoreType>>> {
+    let max_capacity = capacities.iter().map(|(_, capacity)| *capacity).max()?;
+    if capacities
+        .iter()
+        .all(|(_, capacity)| *capacity == max_capacity)
+    {
+        return None;
+    }
+    let cpus: Vec<(u32, CoreType)> = capacities
+        .iter()
+        .map(|&(cpu, capacity)| match capacity == max_capacity {
+            true => (cpu, CoreType::Performance),
+            false => (cpu, CoreType::Efficiency),
+        })
+        .collect();
+    Some(core_types_by_cpu(&cpus))
+}
+
+fn core_types_by_cpu(cpus: &[(u32, CoreType)]) -> Vec<Option<CoreType>> {
+    let len = cpus
+        .iter()
+        .map(|(cpu, _)| *cpu as usize + 1)
+        .max()
+        .unwrap_or(0);
+    let mut core_types = vec![None; len];
+    for &(cpu, core_type) in cpus {
+        core_types[cpu as usize] = Some(core_type);
+    }
+    core_types
+}
+
+/// Parses a CPU list like "0-3,8,10-11", as used in sysfs.
+fn parse_cpu_list(list: &str) -> Vec<u32> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        if let (Ok(start), Ok(end)) = (start.parse::<u32>(), end.parse::<u32>()) {
+            cpus.extend(start..=end);
+        }
+    }
+    cpus
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cpu_lists() {
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n"), [0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(parse_cpu_list("5"), [5]);
+        assert!(parse_cpu_list("\n").is_empty());
+    }
+
+    #[test]
+    fn capacities() {
+        assert_eq!(core_types_from_capacities(&[(0, 1024), (1, 1024)]), None);
+        assert_eq!(
+            core_types_from_capacities(&[(0, 446), (1, 446), (3, 1024)]),
+            Some(vec![
+                Some(CoreType::Efficiency),
+                Some(CoreType::Efficiency),
+                None,
+                Some(CoreType::Performance),
+            ])
+        );
+    }
+}
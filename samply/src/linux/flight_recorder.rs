@@ -0,0 +1,255 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use linux_perf_data::linux_perf_event_reader::{
+    RawData, RawEventRecord, RecordParseInfo, RecordType,
+};
+
+use super::record_spill::ExecNameAndCmdline;
+use crate::shared::prop_types::ProfileCreationProps;
+
+static SNAPSHOT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_snapshot_signal(_signal: libc::c_int) {
+    SNAPSHOT_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Makes SIGUSR2 request a flight recorder snapshot, instead of terminating the process.
+pub fn install_snapshot_signal_handler() {
+    unsafe {
+        libc::signal(
+            libc::SIGUSR2,
+            handle_snapshot_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+/// Returns whether SIGUSR2 was received since the last call.
+pub fn take_snapshot_request() -> bool {
+    SNAPSHOT_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+struct OwnedRecord {
+    seq: u64,
+    timestamp: Option<u64>,
+    record_type: RecordType,
+    misc: u16,
+    data: Vec<u8>,
+    parse_info: RecordParseInfo,
+    exec_name_and_cmdline: Option<ExecNameAndCmdline>,
+}
+
+impl OwnedRecord {
+    fn as_raw_record(&self) -> RawEventRecord<'_> {
+        RawEventRecord::new(
+            self.record_type,
+            self.misc,
+            RawData::Single(&self.data),
+            self.parse_info,
+        )
+    }
+}
+
+/// How much of the recording live snapshots cover. Live snapshots are
+/// converted from scratch, so they only cover the most recent activity, to
+/// keep the time it takes to create one bounded.
+const LIVE_SNAPSHOT_WINDOW: Duration = Duration::from_secs(30);
+
+/// Keeps the raw perf records of the last few seconds in memory, so that a
+/// profile of the most recent activity can be created on demand.
+///
+/// Samples and context switches are only kept for the duration of the window.
+/// All other records (mmaps, forks, execs, exits, thread renames) are kept for
+/// the entire recording, because they describe state which later samples
+/// depend on, and because they are rare compared to samples.
+pub struct FlightRecorder {
+    window_nanos: u64,
+    next_seq: u64,
+    latest_timestamp: u64,
+    context_records: Vec<Arc<OwnedRecord>>,
+    recent_records: VecDeque<Arc<OwnedRecord>>,
+    interval: Duration,
+    profile_creation_props: ProfileCreationProps,
+    /// The pid of the process we attached to, if any. The process existed before
+    /// recording started, so its mappings need to be read from /proc.
+    attached_pid: Option<u32>,
+    output_file: PathBuf,
+    snapshot_count: u32,
+    /// In live mode, how often the output file is replaced with a snapshot
+    /// of the most recent activity.
+    live_snapshot_interval: Option<Duration>,
+    last_live_snapshot: Instant,
+}
+
+/// The records which a [`FlightRecorder`] held at the time of the snapshot.
+/// The records are shared with the flight recorder, so taking a snapshot is
+/// cheap and the snapshot can be converted on a different thread.
+pub struct FlightRecorderSnapshot {
+    context_records: Vec<Arc<OwnedRecord>>,
+    recent_records: Vec<Arc<OwnedRecord>>,
+    pub interval: Duration,
+    pub profile_creation_props: ProfileCreationProps,
+    pub attached_pid: Option<u32>,
+}
+
+impl FlightRecorder {
+    pub fn new(
+        window: Duration,
+        interval: Duration,
+        profile_creation_props: ProfileCreationProps,
+        attached_pid: Option<u32>,
+        output_file: &Path,
+    ) -> Self {
+        Self {
+            window_nanos: window.as_nanos() as u64,
+            next_seq: 0,
+            latest_timestamp: 0,
+            context_records: Vec::new(),
+            recent_records: VecDeque::new(),
+            interval,
+            profile_creation_props,
+            attached_pid,
+            output_file: output_file.to_owned(),
+            snapshot_count: 0,
+            live_snapshot_interval: None,
+            last_live_snapshot: Instant::now(),
+        }
+    }
+
+    /// Creates a flight recorder for live mode, which asks for a snapshot of
+    /// the most recent activity every `snapshot_interval`. In live mode, the
+    /// records are also converted as they arrive, for the final profile.
+    pub fn new_live(
+        snapshot_interval: Duration,
+        interval: Duration,
+        profile_creation_props: ProfileCreationProps,
+        attached_pid: Option<u32>,
+        output_file: &Path,
+    ) -> Self {
+        Self {
+            live_snapshot_interval: Some(snapshot_interval),
+            ..Self::new(
+                LIVE_SNAPSHOT_WINDOW,
+                interval,
+                profile_creation_props,
+                attached_pid,
+                output_file,
+            )
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.live_snapshot_interval.is_some()
+    }
+
+    /// Returns whether the next live snapshot is due, and if so, starts the
+    /// wait for the one after it.
+    pub fn take_live_snapshot_request(&mut self) -> bool {
+        let Some(snapshot_interval) = self.live_snapshot_interval else {
+            return false;
+        };
+        if self.last_live_snapshot.elapsed() < snapshot_interval {
+            return false;
+        }
+        self.last_live_snapshot = Instant::now();
+        true
+    }
+
+    /// The file which live snapshots replace, i.e. the regular output file.
+    pub fn live_snapshot_path(&self) -> &Path {
+        &self.output_file
+    }
+
+    pub fn push_record(
+        &mut self,
+        record: &RawEventRecord,
+        exec_name_and_cmdline: Option<ExecNameAndCmdline>,
+    ) {
+        let timestamp = record.timestamp();
+        let owned_record = Arc::new(OwnedRecord {
+            seq: self.next_seq,
+            timestamp,
+            record_type: record.record_type,
+            misc: record.misc,
+            data: record.data.as_slice().into_owned(),
+            parse_info: record.parse_info,
+            exec_name_and_cmdline,
+        });
+        self.next_seq += 1;
+
+        if record.record_type == RecordType::SAMPLE
+            || record.record_type == RecordType::SWITCH
+            || record.record_type == RecordType::SWITCH_CPU_WIDE
+        {
+            self.recent_records.push_back(owned_record);
+        } else {
+            self.context_records.push(owned_record);
+        }
+
+        if let Some(timestamp) = timestamp {
+            self.latest_timestamp = self.latest_timestamp.max(timestamp);
+        }
+        let window_start = self.latest_timestamp.saturating_sub(self.window_nanos);
+        while let Some(front) = self.recent_records.front() {
+            match front.timestamp {
+                Some(timestamp) if timestamp >= window_start => break,
+                _ => {
+                    self.recent_records.pop_front();
+                }
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> FlightRecorderSnapshot {
+        FlightRecorderSnapshot {
+            context_records: self.context_records.clone(),
+            recent_records: self.recent_records.iter().cloned().collect(),
+            interval: self.interval,
+            profile_creation_props: self.profile_creation_props.clone(),
+            attached_pid: self.attached_pid,
+        }
+    }
+
+    /// Returns the path for the next snapshot, e.g. "profile-snapshot-1.json.gz"
+    /// for an output path of "profile.json.gz".
+    pub fn next_snapshot_path(&mut self) -> PathBuf {
+        self.snapshot_count += 1;
+        let file_name = self
+            .output_file
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let (stem, extensions) = match file_name.find('.') {
+            Some(pos) => file_name.split_at(pos),
+            None => (file_name.as_str(), ""),
+        };
+        let snapshot_file_name = format!("{stem}-snapshot-{}{extensions}", self.snapshot_count);
+        self.output_file.with_file_name(snapshot_file_name)
+    }
+}
+
+impl FlightRecorderSnapshot {
+    /// Calls `f` for every record in the snapshot, in the order in which the
+    /// records were originally received.
+    pub fn for_each_record(&self, mut f: impl FnMut(&RawEventRecord, Option<ExecNameAndCmdline>)) {
+        let mut context_records = self.context_records.iter().peekable();
+        let mut recent_records = self.recent_records.iter().peekable();
+        loop {
+            let record = match (context_records.peek(), recent_records.peek()) {
+                (Some(c), Some(r)) if c.seq < r.seq => context_records.next(),
+                (_, Some(_)) => recent_records.next(),
+                (Some(_), None) => context_records.next(),
+                (None, None) => break,
+            }
+            .unwrap();
+            f(
+                &record.as_raw_record(),
+                record.exec_name_and_cmdline.clone(),
+            );
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Support for Java programs which are launched via samply. JIT-compiled Java
+//! code has no frame pointers and no symbols by default, so when the launched
+//! command is `java`, we pass the JVM some extra flags in `JAVA_TOOL_OPTIONS`:
+//!
+//!  - `-XX:+PreserveFramePointer`, so that stacks can be walked through JIT
+//!    frames into the native code which called them, and
+//!  - `-XX:+DumpPerfMapAtExit` (JDK 17 and newer), so that the JVM writes
+//!    /tmp/perf-<pid>.map when it exits. This perf map is then used to
+//!    symbolicate the JIT frames, like any other perf map.
+//!
+//! `-XX:+IgnoreUnrecognizedVMOptions` keeps older JVMs from refusing to start
+//! because of the flags which they don't know about.
+
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+
+const JVM_FLAGS: &str = "-XX:+PreserveFramePointer -XX:+UnlockDiagnosticVMOptions -XX:+DumpPerfMapAtExit -XX:+IgnoreUnrecognizedVMOptions";
+
+/// Returns whether the launched command is the `java` launcher.
+pub fn is_java_command(command_name: &OsStr) -> bool {
+    Path::new(command_name)
+        .file_name()
+        .is_some_and(|name| name == "java")
+}
+
+/// Adds our flags to `JAVA_TOOL_OPTIONS`. The user's own options are kept and
+/// come after ours, so that they can override them, e.g. with
+/// `-XX:-PreserveFramePointer`.
+pub fn add_env_vars(env_vars: &mut Vec<(OsString, OsString)>) {
+    let existing_options = env_vars
+        .iter()
+        .rev()
+        .find(|(name, _)| name == "JAVA_TOOL_OPTIONS")
+        .map(|(_, val)| val.clone())
+        .or_else(|| std::env::var_os("JAVA_TOOL_OPTIONS"));
+    env_vars.push((
+        "JAVA_TOOL_OPTIONS".into(),
+        java_tool_options(existing_options.as_deref()),
+    ));
+}
+
+fn java_tool_options(existing_options: Option<&OsStr>) -> OsString {
+    let mut options = OsString::from(JVM_FLAGS);
+    if let Some(existing_options) = existing_options.filter(|o| !o.is_empty()) {
+        options.push(" ");
+        options.push(existing_options);
+    }
+    options
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn java_commands() {
+        assert!(is_java_command(OsStr::new("java")));
+        assert!(is_java_command(OsStr::new(
+            "/usr/lib/jvm/java-21-openjdk/bin/java"
+        )));
+        assert!(!is_java_command(OsStr::new("javac")));
+        assert!(!is_java_command(OsStr::new("./java-app")));
+    }
+
+    #[test]
+    fn user_options_come_last() {
+        assert_eq!(java_tool_options(None), JVM_FLAGS);
+        assert_eq!(
+            java_tool_options(Some(OsStr::new("-XX:-PreserveFramePointer"))),
+            format!("{JVM_FLAGS} -XX:-PreserveFramePointer").as_str()
+        );
+    }
+}
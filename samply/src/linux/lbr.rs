@@ -0,0 +1,92 @@
+use byteorder::NativeEndian;
+use linux_perf_data::linux_perf_event_reader::constants::PERF_CONTEXT_USER;
+use linux_perf_data::linux_perf_event_reader::{
+    BranchSampleFormat, CpuMode, RawEventRecord, RecordType, SampleFormat, SampleRecord,
+};
+
+/// Returns the call stack that the CPU's last branch record (LBR) captured for a
+/// sample, as the addresses of the call instructions, from the innermost caller
+/// outwards.
+///
+/// linux-perf-event-reader skips over the branch stack when it parses a sample,
+/// so this reads it from the raw record. Records which aren't samples, or
+/// which don't have a branch stack, return `None`.
+pub fn lbr_call_stack(record: &RawEventRecord) -> Option<Vec<u64>> {
+    let parse_info = &record.parse_info;
+    let sample_format = parse_info.sample_format;
+    if record.record_type != RecordType::SAMPLE
+        || !sample_format.contains(SampleFormat::BRANCH_STACK)
+    {
+        return None;
+    }
+
+    let mut cur = record.data;
+    let fixed_size_fields = [
+        SampleFormat::IDENTIFIER,
+        SampleFormat::IP,
+        SampleFormat::TID,
+        SampleFormat::TIME,
+        SampleFormat::ADDR,
+        SampleFormat::ID,
+        SampleFormat::STREAM_ID,
+        SampleFormat::CPU,
+        SampleFormat::PERIOD,
+    ];
+    for field in fixed_size_fields {
+        if sample_format.contains(field) {
+            cur.skip(8).ok()?;
+        }
+    }
+    if sample_format.contains(SampleFormat::READ) {
+        // We never request read values together with a branch stack.
+        return None;
+    }
+    if sample_format.contains(SampleFormat::CALLCHAIN) {
+        let len = cur.read_u64::<NativeEndian>().ok()?;
+        cur.skip(len as usize * 8).ok()?;
+    }
+    if sample_format.contains(SampleFormat::RAW) {
+        let size = cur.read_u32::<NativeEndian>().ok()?;
+        cur.skip(size as usize).ok()?;
+    }
+
+    let nr = cur.read_u64::<NativeEndian>().ok()?;
+    if parse_info
+        .branch_sample_format
+        .contains(BranchSampleFormat::HW_INDEX)
+    {
+        cur.skip(8).ok()?;
+    }
+    let mut call_stack = Vec::with_capacity(nr as usize);
+    for _ in 0..nr {
+        let from = cur.read_u64::<NativeEndian>().ok()?;
+        let _to = cur.read_u64::<NativeEndian>().ok()?;
+        let _flags = cur.read_u64::<NativeEndian>().ok()?;
+        if from != 0 {
+            call_stack.push(from);
+        }
+    }
+    Some(call_stack)
+}
+
+/// Builds a callchain, as native-endian bytes, which consists of the kernel part
+/// of the sample's callchain, if any, followed by the user stack from the LBR
+/// call stack. The converter can then treat the result like any other callchain.
+pub fn callchain_with_lbr_user_stack(sample: &SampleRecord, call_stack: &[u64]) -> Vec<u8> {
+    let mut callchain: Vec<u64> = Vec::new();
+    if let Some(kernel_callchain) = &sample.callchain {
+        callchain.extend((0..kernel_callchain.len()).filter_map(|i| kernel_callchain.get(i)));
+    }
+    callchain.push(PERF_CONTEXT_USER);
+    if let (CpuMode::User, Some(ip)) = (sample.cpu_mode, sample.ip) {
+        callchain.push(ip);
+    }
+    // Callchain addresses are return addresses, which get adjusted by one byte
+    // to land in the call instruction. LBR gives us the call instructions
+    // themselves, so compensate for that adjustment.
+    callchain.extend(call_stack.iter().map(|from| from + 1));
+    callchain
+        .iter()
+        .flat_map(|address| address.to_ne_bytes())
+        .collect()
+}
@@ -1,7 +1,19 @@
+mod alloc_tracking;
+mod core_types;
+mod flight_recorder;
+mod jvm;
+mod lbr;
+mod output_capture;
+mod pause;
 mod perf_event;
 mod perf_group;
+mod perf_readers;
+mod probes;
 mod proc_maps;
 mod process;
 pub mod profiler;
+mod rapl;
+mod record_spill;
 mod sorter;
 mod sys;
+mod syscall_tracing;
@@ -0,0 +1,128 @@
+//! Capturing of the launched command's stdout and stderr, for `--capture-output`.
+//! The output is passed through to our own stdout and stderr, and each line is
+//! also recorded with a timestamp so that it can be added to the profile.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use nix::fcntl::OFlag;
+
+use super::rapl::monotonic_nanos;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+/// One line of output from the launched command.
+#[derive(Debug, Clone)]
+pub struct CapturedLine {
+    /// The pid of the launched process which was running when the line was read.
+    pub pid: u32,
+    /// CLOCK_MONOTONIC timestamp in nanoseconds.
+    pub timestamp: u64,
+    pub stream: OutputStream,
+    pub line: String,
+}
+
+/// Owns the pipes which the launched command's stdout and stderr are redirected
+/// to, and the threads which read from them.
+pub struct OutputCapture {
+    stdout_write_end: OwnedFd,
+    stderr_write_end: OwnedFd,
+    current_pid: Arc<AtomicU32>,
+    threads: Vec<JoinHandle<Vec<CapturedLine>>>,
+}
+
+impl OutputCapture {
+    pub fn start() -> io::Result<Self> {
+        let current_pid = Arc::new(AtomicU32::new(0));
+        let (stdout_read_end, stdout_write_end) = nix::unistd::pipe2(OFlag::O_CLOEXEC)?;
+        let (stderr_read_end, stderr_write_end) = nix::unistd::pipe2(OFlag::O_CLOEXEC)?;
+        let threads = vec![
+            spawn_reader(stdout_read_end, OutputStream::Stdout, current_pid.clone()),
+            spawn_reader(stderr_read_end, OutputStream::Stderr, current_pid.clone()),
+        ];
+        Ok(OutputCapture {
+            stdout_write_end,
+            stderr_write_end,
+            current_pid,
+            threads,
+        })
+    }
+
+    /// The fds which the launched command should use as its stdout and stderr.
+    pub fn child_fds(&self) -> (RawFd, RawFd) {
+        (
+            self.stdout_write_end.as_raw_fd(),
+            self.stderr_write_end.as_raw_fd(),
+        )
+    }
+
+    /// Sets the pid which lines read from now on are attributed to.
+    pub fn set_pid(&self, pid: u32) {
+        self.current_pid.store(pid, Ordering::Relaxed);
+    }
+
+    /// Closes our write ends of the pipes and returns all captured lines,
+    /// sorted by time. This waits until every process which inherited the
+    /// pipes has closed them.
+    pub fn finish(self) -> Vec<CapturedLine> {
+        drop(self.stdout_write_end);
+        drop(self.stderr_write_end);
+        let mut lines: Vec<CapturedLine> = self
+            .threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap_or_default())
+            .collect();
+        lines.sort_by_key(|line| line.timestamp);
+        lines
+    }
+}
+
+fn spawn_reader(
+    read_end: OwnedFd,
+    stream: OutputStream,
+    current_pid: Arc<AtomicU32>,
+) -> JoinHandle<Vec<CapturedLine>> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(File::from(read_end));
+        let mut lines = Vec::new();
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            let timestamp = monotonic_nanos();
+            let _ = match stream {
+                OutputStream::Stdout => io::stdout().write_all(&buf),
+                OutputStream::Stderr => io::stderr().write_all(&buf),
+            };
+            let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            lines.push(CapturedLine {
+                pid: current_pid.load(Ordering::Relaxed),
+                timestamp,
+                stream,
+                line: String::from_utf8_lossy(line).into_owned(),
+            });
+        }
+        lines
+    })
+}
@@ -0,0 +1,110 @@
+//! Pausing and resuming the recording. Every SIGUSR1 toggles between recording
+//! and paused. When samply is attached to existing processes, pressing Enter
+//! does the same; a launched command keeps the terminal's input to itself.
+//!
+//! While paused, the perf events stay enabled, so that we still see new
+//! mappings, threads and processes, but their samples are dropped. The paused
+//! time ends up as paused ranges in the profile.
+
+use std::io::{BufRead, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static TOGGLE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_toggle_signal(_signal: libc::c_int) {
+    TOGGLE_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Makes SIGUSR1 pause or resume the recording, instead of terminating the process.
+pub fn install_pause_signal_handler() {
+    unsafe {
+        libc::signal(
+            libc::SIGUSR1,
+            handle_toggle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+/// Makes each press of Enter pause or resume the recording, if stdin is a terminal.
+pub fn spawn_pause_key_listener() {
+    if !std::io::stdin().is_terminal() {
+        return;
+    }
+    eprintln!("Press Enter to pause or resume the recording.");
+    std::thread::spawn(|| {
+        for line in std::io::stdin().lock().lines() {
+            if line.is_err() {
+                break;
+            }
+            TOGGLE_REQUESTED.store(true, Ordering::SeqCst);
+        }
+    });
+}
+
+/// Returns whether a pause or resume was requested since the last call.
+pub fn take_pause_toggle_request() -> bool {
+    TOGGLE_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// The times at which the recording was paused, as (start, end) timestamps on
+/// the perf clock. The last range has no end while the recording is paused.
+#[derive(Debug, Default)]
+pub struct PausedRanges {
+    ranges: Vec<(u64, Option<u64>)>,
+}
+
+impl PausedRanges {
+    pub fn is_paused(&self) -> bool {
+        matches!(self.ranges.last(), Some((_, None)))
+    }
+
+    /// Pauses or resumes at `now`.
+    pub fn toggle(&mut self, now: u64) {
+        match self.ranges.last_mut() {
+            Some((_, end @ None)) => *end = Some(now),
+            _ => self.ranges.push((now, None)),
+        }
+    }
+
+    /// Returns whether a sample at this time should be dropped. Records are
+    /// consumed with a delay, so this also needs to look at ranges which
+    /// have already ended.
+    pub fn contains(&self, timestamp: u64) -> bool {
+        self.ranges
+            .iter()
+            .rev()
+            .any(|&(start, end)| start <= timestamp && end.is_none_or(|end| timestamp < end))
+    }
+
+    /// Returns the finished ranges, ending a range which is still open at `now`.
+    pub fn finish(self, now: u64) -> impl Iterator<Item = (u64, u64)> {
+        self.ranges
+            .into_iter()
+            .map(move |(start, end)| (start, end.unwrap_or(now)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paused_ranges() {
+        let mut paused_ranges = PausedRanges::default();
+        assert!(!paused_ranges.is_paused());
+        paused_ranges.toggle(100);
+        assert!(paused_ranges.is_paused());
+        paused_ranges.toggle(200);
+        assert!(!paused_ranges.is_paused());
+        paused_ranges.toggle(300);
+
+        assert!(!paused_ranges.contains(50));
+        assert!(paused_ranges.contains(100));
+        assert!(paused_ranges.contains(150));
+        assert!(!paused_ranges.contains(250));
+        assert!(paused_ranges.contains(1000));
+
+        let ranges: Vec<_> = paused_ranges.finish(400).collect();
+        assert_eq!(ranges, vec![(100, 200), (300, 400)]);
+    }
+}
@@ -1,6 +1,7 @@
 use std::cell::RefCell;
 use std::cmp::max;
 use std::collections::BinaryHeap;
+use std::ffi::CString;
 use std::ops::Range;
 use std::os::unix::io::RawFd;
 use std::rc::Rc;
@@ -148,6 +149,34 @@ fn next_raw_event(
 pub enum EventSource {
     HwCpuCycles,
     SwCpuClock,
+    SwTaskClock,
+    /// Page faults, sampled at the configured frequency.
+    SwPageFaults,
+    /// Major page faults. Every one of them is sampled.
+    SwMajorPageFaults,
+    /// The tracepoint with this id, e.g. from
+    /// /sys/kernel/tracing/events/raw_syscalls/sys_enter/id. Every hit of the
+    /// tracepoint produces a sample with the tracepoint's raw data.
+    Tracepoint(u64),
+    /// A uprobe or kprobe, created with the PMU of this type. The probed
+    /// function is set with `PerfBuilder::probe_target`. Every hit produces a
+    /// sample, which carries the event's id.
+    Probe(u32),
+}
+
+impl EventSource {
+    /// The name which perf uses for this event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventSource::HwCpuCycles => "cycles",
+            EventSource::SwCpuClock => "cpu-clock",
+            EventSource::SwTaskClock => "task-clock",
+            EventSource::SwPageFaults => "page-faults",
+            EventSource::SwMajorPageFaults => "major-faults",
+            EventSource::Tracepoint(_) => "tracepoint",
+            EventSource::Probe(_) => "probe",
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -155,6 +184,7 @@ pub struct PerfBuilder {
     pid: u32,
     cpu: Option<u32>,
     frequency: u64,
+    sample_period: Option<u64>,
     stack_size: u32,
     reg_mask: u64,
     event_source: EventSource,
@@ -162,7 +192,14 @@ pub struct PerfBuilder {
     start_disabled: bool,
     enable_on_exec: bool,
     exclude_kernel: bool,
+    kernel_callchain: bool,
+    user_callchain: bool,
+    lbr_call_stack: bool,
     gather_context_switches: bool,
+    precise_ip: u8,
+    filter: Option<String>,
+    probe_target: Option<(CString, u64)>,
+    cgroup_fd: Option<RawFd>,
 }
 
 impl PerfBuilder {
@@ -171,6 +208,13 @@ impl PerfBuilder {
         self
     }
 
+    /// Monitors the tasks in the cgroup whose directory is open as `cgroup_fd`,
+    /// instead of a pid. Such events need to be bound to a CPU.
+    pub fn cgroup(mut self, cgroup_fd: RawFd) -> Self {
+        self.cgroup_fd = Some(cgroup_fd);
+        self
+    }
+
     pub fn only_cpu(mut self, cpu: u32) -> Self {
         self.cpu = Some(cpu);
         self
@@ -186,6 +230,14 @@ impl PerfBuilder {
         self
     }
 
+    /// Takes a sample every `sample_period` events, instead of adjusting the
+    /// period to match the frequency. For the clock events, the period is in
+    /// nanoseconds.
+    pub fn sample_period(mut self, sample_period: u64) -> Self {
+        self.sample_period = Some(sample_period);
+        self
+    }
+
     pub fn sample_user_stack(mut self, stack_size: u32) -> Self {
         self.stack_size = stack_size;
         self
@@ -202,6 +254,28 @@ impl PerfBuilder {
         self
     }
 
+    /// Records the kernel portion of the call stack of each sample.
+    pub fn sample_kernel_callchain(mut self) -> Self {
+        self.exclude_kernel = false;
+        self.kernel_callchain = true;
+        self
+    }
+
+    /// Records the user portion of the call stack of each sample, as unwound
+    /// by the kernel using frame pointers.
+    pub fn sample_user_callchain(mut self) -> Self {
+        self.user_callchain = true;
+        self
+    }
+
+    /// Records the user call stack which the CPU's last branch record keeps
+    /// track of. This needs a hardware event and a CPU with LBR call stack
+    /// support.
+    pub fn sample_lbr_call_stack(mut self) -> Self {
+        self.lbr_call_stack = true;
+        self
+    }
+
     pub fn event_source(mut self, event_source: EventSource) -> Self {
         self.event_source = event_source;
         self
@@ -227,6 +301,30 @@ impl PerfBuilder {
         self
     }
 
+    /// Asks for precise sampling (PEBS on Intel, IBS on AMD) of hardware
+    /// events, at perf's precise_ip level from 0 (arbitrary skid) to 3 (zero
+    /// skid). Software events and tracepoints ignore this.
+    pub fn precise_ip(mut self, precise_ip: u8) -> Self {
+        self.precise_ip = precise_ip.min(3);
+        self
+    }
+
+    /// Only records tracepoint hits which match this filter expression, e.g.
+    /// "id == 0 || id == 1". See the kernel's Documentation/trace/events.rst
+    /// for the syntax.
+    pub fn filter(mut self, filter: &str) -> Self {
+        self.filter = Some(filter.to_string());
+        self
+    }
+
+    /// Sets what a probe event probes: the binary's path and the file offset
+    /// of the function for uprobes, or the function name and an offset into
+    /// it for kprobes.
+    pub fn probe_target(mut self, target: CString, offset: u64) -> Self {
+        self.probe_target = Some((target, offset));
+        self
+    }
+
     pub fn open(self) -> io::Result<Perf> {
         let pid = self.pid;
         let cpu = self.cpu.map(|cpu| cpu as i32).unwrap_or(-1);
@@ -237,7 +335,24 @@ impl PerfBuilder {
         let inherit = self.inherit;
         let start_disabled = self.start_disabled;
         let exclude_kernel = self.exclude_kernel;
+        let kernel_callchain = self.kernel_callchain;
+        let user_callchain = self.user_callchain;
+        let lbr_call_stack = self.lbr_call_stack;
         let gather_context_switches = self.gather_context_switches;
+        let is_tracepoint = matches!(event_source, EventSource::Tracepoint(_));
+        let is_probe = matches!(event_source, EventSource::Probe(_));
+        let is_page_fault_event = matches!(
+            event_source,
+            EventSource::SwPageFaults | EventSource::SwMajorPageFaults
+        );
+        // Only the main sampling events generate side-band records like mmap
+        // and comm; the additional events would just duplicate them.
+        let is_main_event = !is_tracepoint && !is_probe && !is_page_fault_event;
+        let filter = self
+            .filter
+            .map(CString::new)
+            .transpose()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
 
         // debug!(
         //     "Opening perf events; pid={}, cpu={}, frequency={}, stack_size={}, reg_mask=0x{:016X}, event_source={:?}, inherit={}, start_disabled={}...",
@@ -252,7 +367,7 @@ impl PerfBuilder {
         // );
 
         let max_sample_rate = Perf::max_sample_rate();
-        if let Some(max_sample_rate) = max_sample_rate {
+        if let (Some(max_sample_rate), None) = (max_sample_rate, self.sample_period) {
             // debug!("Maximum sample rate: {}", max_sample_rate);
             if frequency > max_sample_rate {
                 let message = format!( "frequency can be at most {max_sample_rate} as configured in /proc/sys/kernel/perf_event_max_sample_rate" );
@@ -295,6 +410,36 @@ impl PerfBuilder {
                 attr.kind = PERF_TYPE_SOFTWARE;
                 attr.config = PERF_COUNT_SW_CPU_CLOCK;
             }
+            EventSource::SwTaskClock => {
+                attr.kind = PERF_TYPE_SOFTWARE;
+                attr.config = PERF_COUNT_SW_TASK_CLOCK;
+            }
+            EventSource::SwPageFaults => {
+                attr.kind = PERF_TYPE_SOFTWARE;
+                attr.config = PERF_COUNT_SW_PAGE_FAULTS;
+            }
+            EventSource::SwMajorPageFaults => {
+                attr.kind = PERF_TYPE_SOFTWARE;
+                attr.config = PERF_COUNT_SW_PAGE_FAULTS_MAJ;
+            }
+            EventSource::Tracepoint(id) => {
+                attr.kind = PERF_TYPE_TRACEPOINT;
+                attr.config = id;
+            }
+            EventSource::Probe(pmu_type) => {
+                let Some((target, offset)) = &self.probe_target else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "probe events need a probe target",
+                    ));
+                };
+                attr.kind = pmu_type;
+                attr.config = 0;
+                // The kernel reads the path or function name during the
+                // syscall, and `target` outlives it.
+                attr.bp_addr_or_config = target.as_ptr() as u64;
+                attr.bp_len_or_config = *offset;
+            }
         }
 
         attr.sample_type = PERF_SAMPLE_IP
@@ -311,20 +456,51 @@ impl PerfBuilder {
             attr.sample_type |= PERF_SAMPLE_STACK_USER;
         }
 
+        if kernel_callchain || user_callchain {
+            attr.sample_type |= PERF_SAMPLE_CALLCHAIN;
+        }
+
+        if is_tracepoint {
+            attr.sample_type |= PERF_SAMPLE_RAW;
+        }
+
+        if is_page_fault_event {
+            attr.sample_type |= PERF_SAMPLE_ADDR;
+        }
+
+        if is_probe {
+            attr.sample_type |= PERF_SAMPLE_ID;
+        }
+
+        if lbr_call_stack {
+            attr.sample_type |= PERF_SAMPLE_BRANCH_STACK;
+            attr.branch_sample_type = PERF_SAMPLE_BRANCH_USER | PERF_SAMPLE_BRANCH_CALL_STACK;
+        }
+
         attr.sample_regs_user = reg_mask;
         attr.sample_stack_user = stack_size;
         attr.sample_period_or_freq = frequency;
         attr.clock_id = libc::CLOCK_MONOTONIC;
 
-        attr.flags = PERF_ATTR_FLAG_DISABLED
-            | PERF_ATTR_FLAG_MMAP
-            | PERF_ATTR_FLAG_MMAP2
-            | PERF_ATTR_FLAG_MMAP_DATA
-            | PERF_ATTR_FLAG_COMM
-            | PERF_ATTR_FLAG_FREQ
-            | PERF_ATTR_FLAG_TASK
-            | PERF_ATTR_FLAG_SAMPLE_ID_ALL
-            | PERF_ATTR_FLAG_USE_CLOCKID;
+        attr.flags =
+            PERF_ATTR_FLAG_DISABLED | PERF_ATTR_FLAG_SAMPLE_ID_ALL | PERF_ATTR_FLAG_USE_CLOCKID;
+
+        if is_main_event {
+            attr.flags |= PERF_ATTR_FLAG_MMAP
+                | PERF_ATTR_FLAG_MMAP2
+                | PERF_ATTR_FLAG_MMAP_DATA
+                | PERF_ATTR_FLAG_COMM
+                | PERF_ATTR_FLAG_TASK;
+        }
+
+        if is_tracepoint || is_probe || event_source == EventSource::SwMajorPageFaults {
+            // Record every hit.
+            attr.sample_period_or_freq = 1;
+        } else if let Some(sample_period) = self.sample_period {
+            attr.sample_period_or_freq = sample_period;
+        } else {
+            attr.flags |= PERF_ATTR_FLAG_FREQ;
+        }
 
         if self.enable_on_exec {
             attr.flags |= PERF_ATTR_FLAG_ENABLE_ON_EXEC;
@@ -334,6 +510,14 @@ impl PerfBuilder {
             attr.flags |= PERF_ATTR_FLAG_EXCLUDE_KERNEL;
         }
 
+        if kernel_callchain && !user_callchain {
+            attr.flags |= PERF_ATTR_FLAG_EXCLUDE_CALLCHAIN_USER;
+        }
+
+        if user_callchain && !kernel_callchain {
+            attr.flags |= PERF_ATTR_FLAG_EXCLUDE_CALLCHAIN_KERNEL;
+        }
+
         if inherit {
             attr.flags |= PERF_ATTR_FLAG_INHERIT;
         }
@@ -342,7 +526,15 @@ impl PerfBuilder {
             attr.flags |= PERF_ATTR_FLAG_CONTEX_SWITCH;
         }
 
-        let fd = sys_perf_event_open(&attr, pid as pid_t, cpu as _, -1, PERF_FLAG_FD_CLOEXEC);
+        if event_source == EventSource::HwCpuCycles {
+            attr.flags |= (self.precise_ip as u64) << PERF_ATTR_PRECISE_IP_SHIFT;
+        }
+
+        let (pid, flags) = match self.cgroup_fd {
+            Some(cgroup_fd) => (cgroup_fd, PERF_FLAG_FD_CLOEXEC | PERF_FLAG_PID_CGROUP),
+            None => (pid as pid_t, PERF_FLAG_FD_CLOEXEC),
+        };
+        let fd = sys_perf_event_open(&attr, pid, cpu as _, -1, flags);
         if fd == -1 {
             let err = io::Error::last_os_error();
 
@@ -359,6 +551,19 @@ impl PerfBuilder {
             return Err(err);
         }
 
+        if let Some(filter) = &filter {
+            let result =
+                unsafe { libc::ioctl(fd, PERF_EVENT_IOC_SET_FILTER as _, filter.as_ptr()) };
+            if result == -1 {
+                let err = io::Error::last_os_error();
+                unsafe { libc::close(fd) };
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!("setting the tracepoint filter failed: {err}"),
+                ));
+            }
+        }
+
         const STACK_COUNT_PER_BUFFER: u32 = 32;
         let required_space = max(stack_size, 4096) * STACK_COUNT_PER_BUFFER;
         let page_size = 4096;
@@ -430,6 +635,7 @@ impl Perf {
             pid: 0,
             cpu: None,
             frequency: 0,
+            sample_period: None,
             stack_size: 0,
             reg_mask: 0,
             event_source: EventSource::SwCpuClock,
@@ -437,7 +643,14 @@ impl Perf {
             start_disabled: false,
             enable_on_exec: false,
             exclude_kernel: true,
+            kernel_callchain: false,
+            user_callchain: false,
+            lbr_call_stack: false,
             gather_context_switches: false,
+            precise_ip: 0,
+            filter: None,
+            probe_target: None,
+            cgroup_fd: None,
         }
     }
 
@@ -447,6 +660,12 @@ impl Perf {
         assert!(result != -1);
     }
 
+    pub fn disable(&mut self) {
+        // This can fail if the profiled process is already gone; there's nothing
+        // left to stop in that case.
+        let _ = unsafe { libc::ioctl(self.fd, PERF_EVENT_IOC_DISABLE as _) };
+    }
+
     #[inline]
     pub fn are_events_pending(&self) -> bool {
         let head = unsafe { read_head(self.buffer) };
@@ -458,6 +677,18 @@ impl Perf {
         self.fd
     }
 
+    /// The id of this event, which its samples carry if they include
+    /// PERF_SAMPLE_ID. Events which are inherited by child threads share the
+    /// id of their parent event.
+    pub fn id(&self) -> io::Result<u64> {
+        let mut id: u64 = 0;
+        let result = unsafe { libc::ioctl(self.fd, PERF_EVENT_IOC_ID as _, &mut id as *mut u64) };
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(id)
+    }
+
     #[inline]
     pub fn iter(&mut self) -> EventIter<'_> {
         EventIter::new(self)
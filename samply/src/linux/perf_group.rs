@@ -1,17 +1,22 @@
 use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::ops::{Deref, DerefMut};
-use std::os::unix::io::RawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
 use std::time::Duration;
 use std::{fs, io};
 
 use byteorder::LittleEndian;
-use linux_perf_data::linux_perf_event_reader::get_record_timestamp;
+use linux_perf_data::linux_perf_event_reader::{get_record_timestamp, RawEventRecord};
 use mio::unix::SourceFd;
 use mio::{Events, Interest, Poll, Token};
 
-use super::perf_event::{EventRef, EventSource, Perf};
+use super::perf_event::{EventRef, EventSource, Perf, PerfBuilder};
+use super::perf_readers::PerfReaders;
+use super::probes::Probe;
 use super::sorter::EventSorter;
+use super::syscall_tracing::{self, SyscallTracepoints};
+use crate::shared::prop_types::{PageFaultMode, UnwindMode};
 
 struct StoppedProcess(u32);
 
@@ -69,10 +74,28 @@ pub struct PerfGroup {
     poll: Poll,
     poll_events: Events,
     frequency: u32,
+    /// If set, the main sampling events use this fixed period instead of the
+    /// frequency.
+    sample_period: Option<u64>,
     stack_size: u32,
     regs_mask: u64,
+    /// The registers which are sampled for crash signals, so that they can be
+    /// shown on the crash marker.
+    crash_regs_mask: u64,
     event_source: EventSource,
+    kernel_stacks: bool,
+    unwind_mode: UnwindMode,
+    syscall_tracepoints: Option<SyscallTracepoints>,
+    page_faults: Option<PageFaultMode>,
+    probes: Vec<Probe>,
+    sched_switch_tracepoint_id: Option<u64>,
+    /// The precise_ip level of the main sampling events. It's lowered when
+    /// an event can't be opened with it.
+    precise_ip: u8,
     stopped_processes: Vec<StoppedProcess>,
+    /// If set, the ring buffers are drained on these reader threads instead of
+    /// in `consume_events`, and `members` stays empty.
+    readers: Option<PerfReaders>,
 }
 
 fn get_threads(pid: u32) -> Result<Vec<u32>, io::Error> {
@@ -98,33 +121,46 @@ pub enum AttachMode {
 }
 
 impl PerfGroup {
-    pub fn new(frequency: u32, stack_size: u32, regs_mask: u64, event_source: EventSource) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        frequency: u32,
+        sample_period: Option<u64>,
+        stack_size: u32,
+        regs_mask: u64,
+        crash_regs_mask: u64,
+        event_source: EventSource,
+        kernel_stacks: bool,
+        unwind_mode: UnwindMode,
+        syscall_tracepoints: Option<SyscallTracepoints>,
+        page_faults: Option<PageFaultMode>,
+        probes: Vec<Probe>,
+        sched_switch_tracepoint_id: Option<u64>,
+        precise_ip: u8,
+        reader_threads: Option<usize>,
+    ) -> Self {
         PerfGroup {
             event_sorter: EventSorter::new(),
             members: Default::default(),
             poll: Poll::new().unwrap(),
             poll_events: Events::with_capacity(16),
             frequency,
+            sample_period,
             stack_size,
             event_source,
             regs_mask,
+            crash_regs_mask,
+            kernel_stacks,
+            unwind_mode,
+            syscall_tracepoints,
+            page_faults,
+            probes,
+            sched_switch_tracepoint_id,
+            precise_ip,
             stopped_processes: Vec::new(),
+            readers: reader_threads.map(PerfReaders::new),
         }
     }
 
-    pub fn open(
-        pid: u32,
-        frequency: u32,
-        stack_size: u32,
-        event_source: EventSource,
-        regs_mask: u64,
-        attach_mode: AttachMode,
-    ) -> Result<Self, io::Error> {
-        let mut group = PerfGroup::new(frequency, stack_size, regs_mask, event_source);
-        group.open_process(pid, attach_mode)?;
-        Ok(group)
-    }
-
     pub fn open_process(&mut self, pid: u32, attach_mode: AttachMode) -> Result<(), io::Error> {
         if attach_mode == AttachMode::StopAttachEnableResume {
             self.stopped_processes.push(StoppedProcess::new(pid)?);
@@ -149,10 +185,29 @@ impl PerfGroup {
             if attach_mode == AttachMode::AttachWithEnableOnExec {
                 builder = builder.enable_on_exec();
             }
+            builder = self.configure_stack_sampling(builder);
 
-            let perf = builder.open()?;
+            let perf = self.open_main_event(builder)?;
 
             perf_events.push((Some(cpu), perf));
+            for perf in self.open_syscall_tracepoints(pid, Some(cpu), attach_mode)? {
+                perf_events.push((Some(cpu), perf));
+            }
+            if let Some(perf) = self.open_page_fault_event(pid, Some(cpu), attach_mode)? {
+                perf_events.push((Some(cpu), perf));
+            }
+            for perf in self.open_probe_events(pid, Some(cpu), attach_mode)? {
+                perf_events.push((Some(cpu), perf));
+            }
+            if let Some(perf) = self.open_munmap_tracepoint(pid, Some(cpu), attach_mode) {
+                perf_events.push((Some(cpu), perf));
+            }
+            if let Some(perf) = self.open_signal_deliver_tracepoint(pid, Some(cpu), attach_mode) {
+                perf_events.push((Some(cpu), perf));
+            }
+            if let Some(perf) = self.open_sched_switch_tracepoint(pid, Some(cpu), attach_mode)? {
+                perf_events.push((Some(cpu), perf));
+            }
         }
 
         if cpu_count * (threads.len() + 1) >= 1000 {
@@ -169,9 +224,28 @@ impl PerfGroup {
                 if attach_mode == AttachMode::AttachWithEnableOnExec {
                     builder = builder.enable_on_exec();
                 }
-                let perf = builder.open()?;
+                builder = self.configure_stack_sampling(builder);
+                let perf = self.open_main_event(builder)?;
 
                 perf_events.push((None, perf));
+                for perf in self.open_syscall_tracepoints(tid, None, attach_mode)? {
+                    perf_events.push((None, perf));
+                }
+                if let Some(perf) = self.open_page_fault_event(tid, None, attach_mode)? {
+                    perf_events.push((None, perf));
+                }
+                for perf in self.open_probe_events(tid, None, attach_mode)? {
+                    perf_events.push((None, perf));
+                }
+                if let Some(perf) = self.open_munmap_tracepoint(tid, None, attach_mode) {
+                    perf_events.push((None, perf));
+                }
+                if let Some(perf) = self.open_signal_deliver_tracepoint(tid, None, attach_mode) {
+                    perf_events.push((None, perf));
+                }
+                if let Some(perf) = self.open_sched_switch_tracepoint(tid, None, attach_mode)? {
+                    perf_events.push((None, perf));
+                }
             }
         } else {
             for cpu in 0..cpu_count as u32 {
@@ -190,15 +264,70 @@ impl PerfGroup {
                     if attach_mode == AttachMode::AttachWithEnableOnExec {
                         builder = builder.enable_on_exec();
                     }
-                    let perf = builder.open()?;
+                    builder = self.configure_stack_sampling(builder);
+                    let perf = self.open_main_event(builder)?;
 
                     perf_events.push((Some(cpu), perf));
+                    for perf in self.open_syscall_tracepoints(tid, Some(cpu), attach_mode)? {
+                        perf_events.push((Some(cpu), perf));
+                    }
+                    if let Some(perf) = self.open_page_fault_event(tid, Some(cpu), attach_mode)? {
+                        perf_events.push((Some(cpu), perf));
+                    }
+                    for perf in self.open_probe_events(tid, Some(cpu), attach_mode)? {
+                        perf_events.push((Some(cpu), perf));
+                    }
+                    if let Some(perf) = self.open_munmap_tracepoint(tid, Some(cpu), attach_mode) {
+                        perf_events.push((Some(cpu), perf));
+                    }
+                    if let Some(perf) =
+                        self.open_signal_deliver_tracepoint(tid, Some(cpu), attach_mode)
+                    {
+                        perf_events.push((Some(cpu), perf));
+                    }
+                    if let Some(perf) =
+                        self.open_sched_switch_tracepoint(tid, Some(cpu), attach_mode)?
+                    {
+                        perf_events.push((Some(cpu), perf));
+                    }
                 }
             }
         }
 
-        for (_cpu, perf) in perf_events {
+        self.add_members(perf_events)
+    }
+
+    /// Opens per-CPU sampling events for all tasks in the cgroup at this path,
+    /// e.g. /sys/fs/cgroup/system.slice/docker-1234.scope, and in its
+    /// descendant cgroups. Only the main sampling events are opened; syscalls,
+    /// page faults and off-CPU stacks are only recorded for processes.
+    pub fn open_cgroup(&mut self, cgroup_path: &Path) -> Result<(), io::Error> {
+        let cgroup_dir = fs::File::open(cgroup_path)?;
+        let mut perf_events = Vec::new();
+        for cpu in 0..num_cpus::get() as u32 {
+            let mut builder = Perf::build()
+                .cgroup(cgroup_dir.as_raw_fd())
+                .only_cpu(cpu)
+                .frequency(self.frequency as u64)
+                .sample_user_stack(self.stack_size)
+                .sample_user_regs(self.regs_mask)
+                .sample_kernel()
+                .gather_context_switches()
+                .event_source(self.event_source)
+                .start_disabled();
+            builder = self.configure_stack_sampling(builder);
+            perf_events.push((Some(cpu), self.open_main_event(builder)?));
+        }
+        self.add_members(perf_events)
+    }
+
+    fn add_members(&mut self, perf_events: Vec<(Option<u32>, Perf)>) -> Result<(), io::Error> {
+        for (cpu, perf) in perf_events {
             let fd = perf.fd();
+            if let Some(readers) = &mut self.readers {
+                readers.add_member(cpu, fd, perf);
+                continue;
+            }
             self.members.insert(fd, Member::new(perf));
             self.poll.registry().register(
                 &mut SourceFd(&fd),
@@ -210,11 +339,244 @@ impl PerfGroup {
         Ok(())
     }
 
+    /// Opens the sys_enter and sys_exit tracepoint events for this thread, if
+    /// syscalls are traced. Events which are bound to a CPU also follow the
+    /// thread's children, like the sampling events.
+    fn open_syscall_tracepoints(
+        &self,
+        tid: u32,
+        cpu: Option<u32>,
+        attach_mode: AttachMode,
+    ) -> Result<Vec<Perf>, io::Error> {
+        let Some(tracepoints) = &self.syscall_tracepoints else {
+            return Ok(Vec::new());
+        };
+        [tracepoints.enter_id, tracepoints.exit_id]
+            .into_iter()
+            .map(|tracepoint_id| {
+                let mut builder = Perf::build()
+                    .pid(tid)
+                    .event_source(EventSource::Tracepoint(tracepoint_id))
+                    .sample_kernel()
+                    .start_disabled();
+                builder = match cpu {
+                    Some(cpu) => builder.only_cpu(cpu).inherit_to_children(),
+                    None => builder.any_cpu(),
+                };
+                if attach_mode == AttachMode::AttachWithEnableOnExec {
+                    builder = builder.enable_on_exec();
+                }
+                if let Some(filter) = &tracepoints.filter {
+                    builder = builder.filter(filter);
+                }
+                builder.open()
+            })
+            .collect()
+    }
+
+    /// Opens the page fault event for this thread, if page faults are recorded.
+    /// Page fault samples get the same kind of stacks as regular samples.
+    fn open_page_fault_event(
+        &self,
+        tid: u32,
+        cpu: Option<u32>,
+        attach_mode: AttachMode,
+    ) -> Result<Option<Perf>, io::Error> {
+        let event_source = match self.page_faults {
+            Some(PageFaultMode::All) => EventSource::SwPageFaults,
+            Some(PageFaultMode::Major) => EventSource::SwMajorPageFaults,
+            None => return Ok(None),
+        };
+        let mut builder = Perf::build()
+            .pid(tid)
+            .frequency(self.frequency as u64)
+            .sample_user_stack(self.stack_size)
+            .sample_user_regs(self.regs_mask)
+            .event_source(event_source)
+            .start_disabled();
+        builder = match cpu {
+            Some(cpu) => builder.only_cpu(cpu).inherit_to_children(),
+            None => builder.any_cpu(),
+        };
+        if attach_mode == AttachMode::AttachWithEnableOnExec {
+            builder = builder.enable_on_exec();
+        }
+        builder = self.configure_stack_sampling(builder);
+        Ok(Some(builder.open()?))
+    }
+
+    /// Opens an event for each probe for this thread, if there are probes.
+    /// Probe samples get the same kind of stacks as regular samples.
+    fn open_probe_events(
+        &self,
+        tid: u32,
+        cpu: Option<u32>,
+        attach_mode: AttachMode,
+    ) -> Result<Vec<Perf>, io::Error> {
+        self.probes
+            .iter()
+            .map(|probe| {
+                let mut builder = Perf::build()
+                    .pid(tid)
+                    .sample_user_stack(self.stack_size)
+                    .sample_user_regs(self.regs_mask)
+                    .event_source(EventSource::Probe(probe.pmu_type))
+                    .probe_target(probe.target.clone(), probe.offset)
+                    .sample_kernel()
+                    .start_disabled();
+                builder = match cpu {
+                    Some(cpu) => builder.only_cpu(cpu).inherit_to_children(),
+                    None => builder.any_cpu(),
+                };
+                if attach_mode == AttachMode::AttachWithEnableOnExec {
+                    builder = builder.enable_on_exec();
+                }
+                builder = self.configure_stack_sampling(builder);
+                let perf = builder.open()?;
+                probe.register_event_id(perf.id()?);
+                Ok(perf)
+            })
+            .collect()
+    }
+
+    /// Opens the munmap tracepoint event for this thread, so that we know when
+    /// libraries are unloaded during the recording. This needs the same
+    /// privileges as syscall tracing, so it's skipped if it can't be opened.
+    fn open_munmap_tracepoint(
+        &self,
+        tid: u32,
+        cpu: Option<u32>,
+        attach_mode: AttachMode,
+    ) -> Option<Perf> {
+        let tracepoint_id = syscall_tracing::munmap_tracepoint_id()?;
+        let mut builder = Perf::build()
+            .pid(tid)
+            .event_source(EventSource::Tracepoint(tracepoint_id))
+            .sample_kernel()
+            .start_disabled();
+        builder = match cpu {
+            Some(cpu) => builder.only_cpu(cpu).inherit_to_children(),
+            None => builder.any_cpu(),
+        };
+        if attach_mode == AttachMode::AttachWithEnableOnExec {
+            builder = builder.enable_on_exec();
+        }
+        builder.open().ok()
+    }
+
+    /// Opens the signal_deliver tracepoint event for this thread, filtered to
+    /// crash signals, so that we get the stack of a crashing thread. Like the
+    /// munmap tracepoint, it's skipped if it can't be opened.
+    fn open_signal_deliver_tracepoint(
+        &self,
+        tid: u32,
+        cpu: Option<u32>,
+        attach_mode: AttachMode,
+    ) -> Option<Perf> {
+        let tracepoint_id = syscall_tracing::signal_deliver_tracepoint_id()?;
+        let mut builder = Perf::build()
+            .pid(tid)
+            .sample_user_stack(self.stack_size)
+            .sample_user_regs(self.regs_mask | self.crash_regs_mask)
+            .event_source(EventSource::Tracepoint(tracepoint_id))
+            .filter(&syscall_tracing::crash_signal_filter())
+            .sample_kernel()
+            .start_disabled();
+        builder = match cpu {
+            Some(cpu) => builder.only_cpu(cpu).inherit_to_children(),
+            None => builder.any_cpu(),
+        };
+        if attach_mode == AttachMode::AttachWithEnableOnExec {
+            builder = builder.enable_on_exec();
+        }
+        builder = self.configure_stack_sampling(builder);
+        builder.open().ok()
+    }
+
+    /// Opens the sched_switch tracepoint event for this thread, if off-CPU
+    /// stacks are recorded for `--wall-clock`. The samples are taken when the
+    /// thread goes off-CPU, and get the same kind of stacks as regular samples.
+    fn open_sched_switch_tracepoint(
+        &self,
+        tid: u32,
+        cpu: Option<u32>,
+        attach_mode: AttachMode,
+    ) -> Result<Option<Perf>, io::Error> {
+        let Some(tracepoint_id) = self.sched_switch_tracepoint_id else {
+            return Ok(None);
+        };
+        let mut builder = Perf::build()
+            .pid(tid)
+            .sample_user_stack(self.stack_size)
+            .sample_user_regs(self.regs_mask)
+            .event_source(EventSource::Tracepoint(tracepoint_id))
+            .sample_kernel()
+            .start_disabled();
+        builder = match cpu {
+            Some(cpu) => builder.only_cpu(cpu).inherit_to_children(),
+            None => builder.any_cpu(),
+        };
+        if attach_mode == AttachMode::AttachWithEnableOnExec {
+            builder = builder.enable_on_exec();
+        }
+        builder = self.configure_stack_sampling(builder);
+        Ok(Some(builder.open()?))
+    }
+
+    /// Opens a main sampling event, with the fixed sample period if there is
+    /// one. Not all CPUs and events support all precise_ip levels, so the
+    /// level is lowered until the event can be opened, and later events are
+    /// opened with the level that worked.
+    fn open_main_event(&mut self, mut builder: PerfBuilder) -> Result<Perf, io::Error> {
+        if let Some(sample_period) = self.sample_period {
+            builder = builder.sample_period(sample_period);
+        }
+        loop {
+            match builder.clone().precise_ip(self.precise_ip).open() {
+                Err(err)
+                    if self.precise_ip > 0
+                        && matches!(err.raw_os_error(), Some(libc::EINVAL | libc::EOPNOTSUPP)) =>
+                {
+                    self.precise_ip -= 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn configure_stack_sampling(&self, mut builder: PerfBuilder) -> PerfBuilder {
+        if self.kernel_stacks {
+            builder = builder.sample_kernel_callchain();
+        }
+        match self.unwind_mode {
+            // The user stack and registers are already being sampled.
+            UnwindMode::Dwarf => builder,
+            UnwindMode::FramePointers => builder.sample_user_callchain(),
+            UnwindMode::Lbr => builder.sample_lbr_call_stack(),
+        }
+    }
+
+    /// The event which the main sampling events count.
+    pub fn event_source(&self) -> EventSource {
+        self.event_source
+    }
+
+    /// The precise_ip level which the main sampling events were opened with.
+    pub fn precise_ip(&self) -> u8 {
+        self.precise_ip
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.members.is_empty()
+        match &self.readers {
+            Some(readers) => readers.is_empty(),
+            None => self.members.is_empty(),
+        }
     }
 
     pub fn enable(&mut self) {
+        if let Some(readers) = &mut self.readers {
+            readers.enable();
+        }
         for perf in self.members.values_mut() {
             perf.enable();
         }
@@ -222,7 +584,22 @@ impl PerfGroup {
         self.stopped_processes.clear();
     }
 
+    /// Stops the generation of new events. Events which are already in the
+    /// ring buffers can still be consumed afterwards.
+    pub fn disable(&mut self) {
+        if let Some(readers) = &mut self.readers {
+            readers.disable();
+        }
+        for perf in self.members.values_mut() {
+            perf.disable();
+        }
+    }
+
     pub fn wait(&mut self) {
+        if let Some(readers) = &mut self.readers {
+            readers.wait();
+            return;
+        }
         for member in self.members.values() {
             if member.are_events_pending() {
                 return;
@@ -245,13 +622,17 @@ impl PerfGroup {
         }
     }
 
-    pub fn consume_events(&mut self, cb: &mut impl FnMut(EventRef)) {
+    pub fn consume_events(&mut self, cb: &mut impl FnMut(&RawEventRecord)) {
+        if let Some(readers) = &mut self.readers {
+            readers.consume_records(cb);
+            return;
+        }
         let mut fds_to_remove = Vec::new();
         loop {
             for (&fd, member) in &mut self.members {
                 self.event_sorter.begin_group(fd);
                 while let Some(ev) = self.event_sorter.pop() {
-                    cb(ev);
+                    cb(&ev.get());
                 }
 
                 let perf = &mut member.perf;
@@ -277,7 +658,7 @@ impl PerfGroup {
 
             self.event_sorter.advance_round();
             while let Some(ev) = self.event_sorter.pop() {
-                cb(ev);
+                cb(&ev.get());
             }
 
             for fd in fds_to_remove.drain(..) {
@@ -0,0 +1,374 @@
+//! Drains the perf ring buffers on background threads, for `--reader-threads`.
+//!
+//! Without reader threads, the thread which converts the records also drains
+//! the ring buffers, so the buffers aren't drained while it's busy unwinding.
+//! On machines with many CPUs, the buffers fill up in the meantime and the
+//! kernel drops records. Here, each reader thread owns the events of some of
+//! the CPUs, copies their records out of the ring buffers as soon as they
+//! arrive, and sends them to the converting thread in batches.
+//!
+//! Every batch comes with a watermark, a timestamp before which the reader
+//! won't send any more records. The converting thread releases the records in
+//! timestamp order, up to the smallest watermark of all readers.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::os::unix::io::RawFd;
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, TryRecvError};
+use linux_perf_data::linux_perf_event_reader::{
+    RawData, RawEventRecord, RecordParseInfo, RecordType,
+};
+use mio::unix::SourceFd;
+use mio::{Events, Interest, Poll, Token};
+
+use super::perf_event::Perf;
+use super::rapl::monotonic_nanos;
+
+/// How far behind the current time the watermark is. Records are written to
+/// the ring buffer shortly after their timestamp was taken, so a record with a
+/// timestamp just before the watermark could still be in flight.
+const WATERMARK_SLACK_NANOS: u64 = 1_000_000;
+
+/// How long a reader waits for new records before it sends a batch anyway,
+/// to advance its watermark.
+const READER_POLL_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// A perf event which is sent to a reader thread.
+struct SendablePerf(Perf);
+
+// SAFETY: A Perf is only moved to a reader thread right after it was opened.
+// At that point no EventRefs exist, so the Rc which is shared with them isn't
+// shared yet; from then on, the Perf and its EventRefs only live on the
+// reader thread.
+unsafe impl Send for SendablePerf {}
+
+enum ReaderCommand {
+    AddMember(SendablePerf),
+    /// Enables all events and replies once they're enabled.
+    Enable(Sender<()>),
+    /// Disables all events and replies once they're disabled.
+    Disable(Sender<()>),
+}
+
+/// A record which was copied out of a ring buffer.
+struct OwnedRecord {
+    timestamp: u64,
+    /// Keeps records with the same timestamp in the order they were received.
+    /// Assigned by the converting thread.
+    seq: u64,
+    record_type: RecordType,
+    misc: u16,
+    data: Vec<u8>,
+    parse_info: RecordParseInfo,
+}
+
+impl OwnedRecord {
+    fn as_raw_record(&self) -> RawEventRecord<'_> {
+        RawEventRecord::new(
+            self.record_type,
+            self.misc,
+            RawData::Single(&self.data),
+            self.parse_info,
+        )
+    }
+}
+
+impl PartialEq for OwnedRecord {
+    fn eq(&self, other: &Self) -> bool {
+        (self.timestamp, self.seq) == (other.timestamp, other.seq)
+    }
+}
+
+impl Eq for OwnedRecord {}
+
+impl PartialOrd for OwnedRecord {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OwnedRecord {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.timestamp, self.seq).cmp(&(other.timestamp, other.seq))
+    }
+}
+
+struct RecordBatch {
+    reader_index: usize,
+    records: Vec<OwnedRecord>,
+    /// This reader won't send any records with an earlier timestamp.
+    watermark: u64,
+    /// How many of this reader's events have been closed so far.
+    removed_member_count: usize,
+}
+
+/// The reader threads, seen from the converting thread.
+pub struct PerfReaders {
+    command_senders: Vec<Sender<ReaderCommand>>,
+    batch_receiver: Receiver<RecordBatch>,
+    watermarks: Vec<u64>,
+    added_member_counts: Vec<usize>,
+    removed_member_counts: Vec<usize>,
+    records: BinaryHeap<Reverse<OwnedRecord>>,
+    next_seq: u64,
+    is_draining: bool,
+}
+
+impl PerfReaders {
+    pub fn new(thread_count: usize) -> Self {
+        let thread_count = thread_count.max(1);
+        let (batch_sender, batch_receiver) = crossbeam_channel::unbounded();
+        let command_senders = (0..thread_count)
+            .map(|reader_index| {
+                let (command_sender, command_receiver) = crossbeam_channel::unbounded();
+                let batch_sender = batch_sender.clone();
+                thread::Builder::new()
+                    .name(format!("samply perf reader {reader_index}"))
+                    .spawn(move || run_reader(reader_index, command_receiver, batch_sender))
+                    .expect("couldn't spawn a perf reader thread");
+                command_sender
+            })
+            .collect();
+        PerfReaders {
+            command_senders,
+            batch_receiver,
+            watermarks: vec![0; thread_count],
+            added_member_counts: vec![0; thread_count],
+            removed_member_counts: vec![0; thread_count],
+            records: BinaryHeap::new(),
+            next_seq: 0,
+            is_draining: false,
+        }
+    }
+
+    /// Hands an event to the reader thread for its CPU. Events which aren't
+    /// bound to a CPU are spread over the readers by their fd.
+    pub fn add_member(&mut self, cpu: Option<u32>, fd: RawFd, perf: Perf) {
+        let reader_index = match cpu {
+            Some(cpu) => cpu as usize,
+            None => fd as usize,
+        } % self.command_senders.len();
+        self.command_senders[reader_index]
+            .send(ReaderCommand::AddMember(SendablePerf(perf)))
+            .expect("perf reader thread is gone");
+        self.added_member_counts[reader_index] += 1;
+    }
+
+    pub fn enable(&mut self) {
+        self.send_command_and_wait(ReaderCommand::Enable);
+    }
+
+    /// Stops the generation of new events. The records which are still in the
+    /// ring buffers are delivered by the next call to `consume_records`.
+    pub fn disable(&mut self) {
+        self.send_command_and_wait(ReaderCommand::Disable);
+        self.is_draining = true;
+    }
+
+    fn send_command_and_wait(&mut self, make_command: impl Fn(Sender<()>) -> ReaderCommand) {
+        let replies: Vec<Receiver<()>> = self
+            .command_senders
+            .iter()
+            .map(|command_sender| {
+                let (reply_sender, reply_receiver) = crossbeam_channel::bounded(1);
+                command_sender
+                    .send(make_command(reply_sender))
+                    .expect("perf reader thread is gone");
+                reply_receiver
+            })
+            .collect();
+        for reply in replies {
+            reply.recv().expect("perf reader thread is gone");
+        }
+    }
+
+    /// Whether all events have been closed and all their records consumed.
+    pub fn is_empty(&self) -> bool {
+        self.added_member_counts == self.removed_member_counts && self.records.is_empty()
+    }
+
+    /// Waits until records can be released, or for at most 100ms.
+    pub fn wait(&mut self) {
+        if self.has_releasable_records() {
+            return;
+        }
+        match self.batch_receiver.recv_timeout(Duration::from_millis(100)) {
+            Ok(batch) => self.add_batch(batch),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => panic!("perf reader threads are gone"),
+        }
+    }
+
+    /// Calls `cb` for the received records, in timestamp order, up to the
+    /// smallest watermark. When draining, this waits for the final records of
+    /// all readers and calls `cb` for all of them.
+    pub fn consume_records(&mut self, cb: &mut impl FnMut(&RawEventRecord)) {
+        if self.is_draining {
+            while self
+                .watermarks
+                .iter()
+                .any(|&watermark| watermark != u64::MAX)
+            {
+                let batch = self
+                    .batch_receiver
+                    .recv()
+                    .expect("perf reader threads are gone");
+                self.add_batch(batch);
+            }
+        }
+        while let Ok(batch) = self.batch_receiver.try_recv() {
+            self.add_batch(batch);
+        }
+
+        let watermark = self.min_watermark();
+        while let Some(Reverse(record)) = self.records.peek() {
+            if record.timestamp > watermark {
+                break;
+            }
+            let Reverse(record) = self.records.pop().unwrap();
+            cb(&record.as_raw_record());
+        }
+    }
+
+    fn min_watermark(&self) -> u64 {
+        self.watermarks.iter().copied().min().unwrap_or(0)
+    }
+
+    fn has_releasable_records(&self) -> bool {
+        self.records
+            .peek()
+            .is_some_and(|Reverse(record)| record.timestamp <= self.min_watermark())
+    }
+
+    fn add_batch(&mut self, batch: RecordBatch) {
+        for mut record in batch.records {
+            record.seq = self.next_seq;
+            self.next_seq += 1;
+            self.records.push(Reverse(record));
+        }
+        self.watermarks[batch.reader_index] = batch.watermark;
+        self.removed_member_counts[batch.reader_index] = batch.removed_member_count;
+    }
+}
+
+struct ReaderMember {
+    perf: Perf,
+    is_closed: bool,
+}
+
+fn run_reader(
+    reader_index: usize,
+    command_receiver: Receiver<ReaderCommand>,
+    batch_sender: Sender<RecordBatch>,
+) {
+    let mut poll = Poll::new().expect("couldn't create a poll instance");
+    let mut poll_events = Events::with_capacity(16);
+    let mut members: BTreeMap<RawFd, ReaderMember> = BTreeMap::new();
+    let mut removed_member_count = 0;
+    let mut is_disabled = false;
+
+    loop {
+        loop {
+            match command_receiver.try_recv() {
+                Ok(ReaderCommand::AddMember(SendablePerf(perf))) => {
+                    let fd = perf.fd();
+                    if let Err(err) = poll.registry().register(
+                        &mut SourceFd(&fd),
+                        Token(fd as usize),
+                        Interest::READABLE,
+                    ) {
+                        eprintln!("register failed: {err}");
+                    }
+                    members.insert(
+                        fd,
+                        ReaderMember {
+                            perf,
+                            is_closed: false,
+                        },
+                    );
+                }
+                Ok(ReaderCommand::Enable(reply_sender)) => {
+                    for member in members.values_mut() {
+                        member.perf.enable();
+                    }
+                    let _ = reply_sender.send(());
+                }
+                Ok(ReaderCommand::Disable(reply_sender)) => {
+                    for member in members.values_mut() {
+                        member.perf.disable();
+                    }
+                    is_disabled = true;
+                    let _ = reply_sender.send(());
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+
+        // Everything which happened before this point is either in the ring
+        // buffers already, or within the slack. Once the events are disabled,
+        // this drain gets all the remaining records.
+        let watermark = match is_disabled {
+            true => u64::MAX,
+            false => monotonic_nanos().saturating_sub(WATERMARK_SLACK_NANOS),
+        };
+        let mut records = Vec::new();
+        let mut fds_to_remove = Vec::new();
+        for (&fd, member) in &mut members {
+            for event in member.perf.iter() {
+                let record = event.get();
+                let Some(timestamp) = record.timestamp() else {
+                    continue;
+                };
+                records.push(OwnedRecord {
+                    timestamp,
+                    seq: 0,
+                    record_type: record.record_type,
+                    misc: record.misc,
+                    data: record.data.as_slice().into_owned(),
+                    parse_info: record.parse_info,
+                });
+            }
+            if member.is_closed && !member.perf.are_events_pending() {
+                fds_to_remove.push(fd);
+            }
+        }
+        for fd in fds_to_remove {
+            let _ = poll.registry().deregister(&mut SourceFd(&fd));
+            members.remove(&fd);
+            removed_member_count += 1;
+        }
+
+        let batch = RecordBatch {
+            reader_index,
+            records,
+            watermark,
+            removed_member_count,
+        };
+        if batch_sender.send(batch).is_err() {
+            return;
+        }
+
+        if members
+            .values()
+            .any(|member| member.perf.are_events_pending())
+        {
+            continue;
+        }
+        if let Err(err) = poll.poll(&mut poll_events, Some(READER_POLL_TIMEOUT)) {
+            eprintln!("poll failed: {err}");
+            continue;
+        }
+        for ev in poll_events.iter() {
+            if ev.is_read_closed() {
+                let fd = ev.token().0 as RawFd;
+                if let Some(member) = members.get_mut(&fd) {
+                    member.is_closed = true;
+                }
+            }
+        }
+    }
+}
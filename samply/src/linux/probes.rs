@@ -0,0 +1,220 @@
+//! uprobes and kprobes for `--probe`. Every hit of a probed function produces
+//! a sample with the stack, which becomes an instant marker.
+//!
+//! The probes are created with the uprobe and kprobe PMUs, so they don't need
+//! to be registered in tracefs and disappear with their perf events. Their
+//! samples carry the id of their event, which tells us which probe was hit.
+
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{fs, io};
+
+use linux_perf_data::linux_perf_event_reader::SampleRecord;
+use object::{Object, ObjectSegment, ObjectSymbol};
+
+use super::proc_maps;
+use crate::linux_shared::mount_namespace;
+
+/// The probe names by the ids of their events.
+static PROBE_EVENT_IDS: Mutex<BTreeMap<u64, Arc<str>>> = Mutex::new(BTreeMap::new());
+
+/// A probed function, from a `--probe` argument.
+#[derive(Debug, Clone)]
+pub struct Probe {
+    /// The argument, e.g. "libc.so.6:malloc", which is also the marker name.
+    pub name: Arc<str>,
+    /// The type of the uprobe or kprobe PMU.
+    pub pmu_type: u32,
+    /// The path of the binary for uprobes, or the function name for kprobes.
+    pub target: CString,
+    /// The file offset of the function for uprobes, 0 for kprobes.
+    pub offset: u64,
+}
+
+impl Probe {
+    /// Sets up a probe from an argument of the form "LIB:SYMBOL", or
+    /// "kernel:SYMBOL" for a kernel function. LIB can be a path, or the file
+    /// name of a library which the process with this pid has loaded or which
+    /// is in one of the usual library directories.
+    pub fn new(arg: &str, pid: u32) -> io::Result<Self> {
+        let (lib, symbol) = parse_probe_arg(arg).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "expected LIB:SYMBOL or kernel:SYMBOL",
+            )
+        })?;
+        let name = Arc::from(arg);
+        let target_error = |err| io::Error::new(io::ErrorKind::InvalidInput, err);
+        if lib == "kernel" {
+            return Ok(Probe {
+                name,
+                pmu_type: pmu_type("kprobe")?,
+                target: CString::new(symbol).map_err(target_error)?,
+                offset: 0,
+            });
+        }
+        let path = find_library(lib, pid).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("couldn't find {lib}"))
+        })?;
+        let offset = symbol_file_offset(&path, symbol)?;
+        Ok(Probe {
+            name,
+            pmu_type: pmu_type("uprobe")?,
+            target: CString::new(path.into_os_string().into_encoded_bytes())
+                .map_err(target_error)?,
+            offset,
+        })
+    }
+
+    /// Remembers that samples with this event id are hits of this probe.
+    pub fn register_event_id(&self, id: u64) {
+        PROBE_EVENT_IDS
+            .lock()
+            .unwrap()
+            .insert(id, self.name.clone());
+    }
+}
+
+/// Returns the name of the probe which this sample is a hit of, if any.
+pub fn probe_name(e: &SampleRecord) -> Option<Arc<str>> {
+    let id = e.id?;
+    PROBE_EVENT_IDS.lock().unwrap().get(&id).cloned()
+}
+
+/// Splits "LIB:SYMBOL" at the first colon, so that C++ symbols like
+/// "ns::function" can be probed.
+fn parse_probe_arg(arg: &str) -> Option<(&str, &str)> {
+    let (lib, symbol) = arg.split_once(':')?;
+    if lib.is_empty() || symbol.is_empty() {
+        return None;
+    }
+    Some((lib, symbol))
+}
+
+/// Reads the type of a dynamic PMU, e.g. "uprobe", from sysfs.
+fn pmu_type(pmu: &str) -> io::Result<u32> {
+    let path = format!("/sys/bus/event_source/devices/{pmu}/type");
+    let pmu_type = fs::read_to_string(&path)?;
+    pmu_type
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("bad {path}")))
+}
+
+/// Whether a file name like "libc.so.6" is the library with this name, e.g.
+/// "libc.so.6" or "libc.so".
+fn is_library_name(file_name: &str, lib: &str) -> bool {
+    file_name == lib
+        || file_name
+            .strip_prefix(lib)
+            .is_some_and(|suffix| suffix.starts_with('.'))
+}
+
+fn find_library(lib: &str, pid: u32) -> Option<PathBuf> {
+    if lib.contains('/') {
+        return Some(PathBuf::from(lib));
+    }
+
+    // Prefer the libraries which the process has loaded already.
+    if let Ok(maps) = fs::read_to_string(format!("/proc/{pid}/maps")) {
+        let loaded = proc_maps::parse(&maps).into_iter().find(|region| {
+            Path::new(&region.name)
+                .file_name()
+                .is_some_and(|file_name| is_library_name(&file_name.to_string_lossy(), lib))
+        });
+        if let Some(region) = loaded {
+            let path = PathBuf::from(region.name);
+            // The paths in the maps of a process in a container are relative
+            // to the container's root.
+            if mount_namespace::is_in_other_mount_namespace(pid as i32) {
+                return mount_namespace::path_in_process_root(pid as i32, &path);
+            }
+            return Some(path);
+        }
+    }
+
+    let multiarch = format!("{}-linux-gnu", std::env::consts::ARCH);
+    let lib_dirs = [
+        "/lib64".to_string(),
+        "/usr/lib64".to_string(),
+        format!("/lib/{multiarch}"),
+        format!("/usr/lib/{multiarch}"),
+        "/lib".to_string(),
+        "/usr/lib".to_string(),
+        "/usr/local/lib".to_string(),
+    ];
+    let path_dirs = std::env::var("PATH").unwrap_or_default();
+    let dirs = lib_dirs
+        .iter()
+        .map(PathBuf::from)
+        .chain(std::env::split_paths(&path_dirs));
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut candidates: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|entry| is_library_name(&entry.file_name().to_string_lossy(), lib))
+            .map(|entry| entry.path())
+            .collect();
+        // Prefer the exact name, then the shortest versioned name.
+        candidates.sort_by_key(|path| path.as_os_str().len());
+        if let Some(path) = candidates.into_iter().next() {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Looks up the symbol in the binary's symbol table or dynamic symbol table,
+/// and returns the file offset of its code, which is what uprobes expect.
+fn symbol_file_offset(path: &Path, symbol: &str) -> io::Result<u64> {
+    let invalid_data = |err: String| io::Error::new(io::ErrorKind::InvalidData, err);
+    let file = fs::File::open(path)?;
+    let mmap = unsafe { memmap2::MmapOptions::new().map(&file) }?;
+    let obj = object::read::File::parse(&mmap[..])
+        .map_err(|err| invalid_data(format!("couldn't parse {}: {err}", path.display())))?;
+    let address = obj
+        .symbols()
+        .chain(obj.dynamic_symbols())
+        .find(|sym| sym.is_definition() && sym.name() == Ok(symbol))
+        .map(|sym| sym.address())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no symbol {symbol}", path.display()),
+            )
+        })?;
+    obj.segments()
+        .find_map(|segment| {
+            let (file_start, file_size) = segment.file_range();
+            let offset_in_segment = address.checked_sub(segment.address())?;
+            (offset_in_segment < file_size).then_some(file_start + offset_in_segment)
+        })
+        .ok_or_else(|| invalid_data(format!("{symbol} isn't in a segment of {}", path.display())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn probe_args() {
+        assert_eq!(
+            parse_probe_arg("libc.so.6:malloc"),
+            Some(("libc.so.6", "malloc"))
+        );
+        assert_eq!(
+            parse_probe_arg("libxul.so:mozilla::dom::Foo"),
+            Some(("libxul.so", "mozilla::dom::Foo"))
+        );
+        assert_eq!(parse_probe_arg("malloc"), None);
+        assert_eq!(parse_probe_arg(":malloc"), None);
+
+        assert!(is_library_name("libc.so.6", "libc.so"));
+        assert!(is_library_name("libc.so.6", "libc.so.6"));
+        assert!(!is_library_name("libcrypto.so.3", "libc"));
+    }
+}
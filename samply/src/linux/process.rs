@@ -1,10 +1,13 @@
 use std::collections::BTreeMap;
 use std::ffi::{CString, OsStr, OsString};
-use std::os::fd::OwnedFd;
+use std::os::fd::{OwnedFd, RawFd};
 use std::os::raw::c_char;
 use std::os::unix::prelude::OsStrExt;
+use std::time::Duration;
 
 use libc::{execvp, execvpe};
+use nix::errno::Errno;
+use nix::sys::wait::WaitStatus;
 use nix::unistd::Pid;
 
 /// Allows launching a command in a suspended state, so that we can know its
@@ -20,6 +23,7 @@ impl SuspendedLaunchedProcess {
         command_name: &OsStr,
         command_args: &[OsString],
         env_vars: &[(OsString, OsString)],
+        stdout_and_stderr: Option<(RawFd, RawFd)>,
     ) -> std::io::Result<Self> {
         let argv: Vec<CString> = std::iter::once(command_name)
             .chain(command_args.iter().map(|s| s.as_os_str()))
@@ -57,7 +61,7 @@ impl SuspendedLaunchedProcess {
                 // std::panic::always_abort();
                 drop(resume_sp);
                 drop(execerr_rp);
-                Self::run_child(resume_rp, execerr_sp, &argv, envp)
+                Self::run_child(resume_rp, execerr_sp, &argv, envp, stdout_and_stderr)
             }
             nix::unistd::ForkResult::Parent { child } => {
                 drop(resume_rp);
@@ -134,6 +138,7 @@ impl SuspendedLaunchedProcess {
         send_end_of_execerr_pipe: OwnedFd,
         argv: &[*const c_char],
         envp: Option<CStringArray>,
+        stdout_and_stderr: Option<(RawFd, RawFd)>,
     ) -> ! {
         // Wait for the parent to send us a byte through the pipe.
         // This will signal us to start executing.
@@ -154,6 +159,13 @@ impl SuspendedLaunchedProcess {
                 }
                 Ok(_) => {
                     // The parent signaled that we can start. Exec!
+                    if let Some((stdout_fd, stderr_fd)) = stdout_and_stderr {
+                        // dup2 clears O_CLOEXEC on the new fds, so they survive the exec.
+                        unsafe {
+                            libc::dup2(stdout_fd, libc::STDOUT_FILENO);
+                            libc::dup2(stderr_fd, libc::STDERR_FILENO);
+                        }
+                    }
                     if let Some(envp) = envp {
                         let _ = unsafe { execvpe(argv[0], argv.as_ptr(), envp.as_ptr()) };
                     } else {
@@ -194,9 +206,47 @@ pub struct RunningProcess {
     pid: Pid,
 }
 
+/// The resource usage of a process which has exited, as reported by wait4.
+#[derive(Debug, Clone)]
+pub struct ResourceUsage {
+    pub max_rss_bytes: u64,
+    pub user_time: Duration,
+    pub system_time: Duration,
+    pub voluntary_context_switches: u64,
+    pub involuntary_context_switches: u64,
+}
+
+impl ResourceUsage {
+    fn from_rusage(rusage: &libc::rusage) -> Self {
+        let duration =
+            |tv: libc::timeval| Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+        Self {
+            // ru_maxrss is in kilobytes on Linux.
+            max_rss_bytes: rusage.ru_maxrss as u64 * 1024,
+            user_time: duration(rusage.ru_utime),
+            system_time: duration(rusage.ru_stime),
+            voluntary_context_switches: rusage.ru_nvcsw as u64,
+            involuntary_context_switches: rusage.ru_nivcsw as u64,
+        }
+    }
+}
+
 impl RunningProcess {
-    pub fn wait(self) -> Result<nix::sys::wait::WaitStatus, nix::errno::Errno> {
-        nix::sys::wait::waitpid(self.pid, None)
+    /// Waits for the process to exit, and returns its exit status and resource usage.
+    pub fn wait(self) -> Result<(WaitStatus, ResourceUsage), Errno> {
+        loop {
+            let mut status = 0;
+            let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+            let res = unsafe { libc::wait4(self.pid.as_raw(), &mut status, 0, &mut rusage) };
+            if res == -1 {
+                match Errno::last() {
+                    Errno::EINTR => continue,
+                    err => return Err(err),
+                }
+            }
+            let wait_status = WaitStatus::from_raw(self.pid, status)?;
+            return Ok((wait_status, ResourceUsage::from_rusage(&rusage)));
+        }
     }
 }
 
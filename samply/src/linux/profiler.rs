@@ -1,31 +1,51 @@
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::os::unix::process::ExitStatusExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
-use std::thread;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, SystemTime};
 
+use byteorder::NativeEndian;
 use crossbeam_channel::{Receiver, Sender};
-use fxprof_processed_profile::{Profile, ReferenceTimestamp};
+use fxprof_processed_profile::{MarkerNumberFieldFormat, Profile, ReferenceTimestamp};
+use linux_perf_data::linux_perf_event_reader::constants::PERF_RECORD_MISC_COMM_EXEC;
 use linux_perf_data::linux_perf_event_reader::{
     CpuMode, Endianness, EventRecord, Mmap2FileId, Mmap2InodeAndVersion, Mmap2Record, RawData,
+    RawDataU64, RawEventRecord, RecordType,
 };
 use nix::sys::wait::WaitStatus;
 use tokio::sync::oneshot;
 
+use super::alloc_tracking::{AllocTracking, AllocationRecord};
+use super::core_types;
+use super::flight_recorder::{
+    install_snapshot_signal_handler, take_snapshot_request, FlightRecorder, FlightRecorderSnapshot,
+};
+use super::jvm;
+use super::lbr;
+use super::output_capture::{CapturedLine, OutputCapture};
+use super::pause::{self, PausedRanges};
 use super::perf_event::EventSource;
 use super::perf_group::{AttachMode, PerfGroup};
+use super::probes::{self, Probe};
 use super::proc_maps;
-use super::process::SuspendedLaunchedProcess;
+use super::process::{ResourceUsage, SuspendedLaunchedProcess};
+use super::rapl::{monotonic_nanos, PowerSampler};
+use super::record_spill::{ExecNameAndCmdline, RecordSpillWriter};
+use super::syscall_tracing::{self, SyscallEvent, SyscallTracepoints};
+use crate::linux_shared::lost_events::LostEventsTracker;
 use crate::linux_shared::vdso::VdsoObject;
 use crate::linux_shared::{
-    ConvertRegs, Converter, EventInterpretation, MmapRangeOrVec, OffCpuIndicator,
+    ConvertRegs, Converter, EventInterpretation, MmapRangeOrVec, OffCpuIndicator, SampleCpuTime,
 };
 use crate::shared::ctrl_c::CtrlC;
 use crate::shared::prop_types::{
-    ProcessLaunchProps, ProfileCreationProps, RecordingMode, RecordingProps,
+    PageFaultMode, ProcessLaunchProps, ProfileCreationProps, RecordingMode, RecordingProps,
+    SamplingClock, SamplingPeriod, UnwindMode,
 };
+use crate::shared::save_profile::{save_profile_to_file, ProfileFileFormat};
+use crate::shared::utils::microjoules_to_picowatt_hours;
 
 #[cfg(target_arch = "x86_64")]
 pub type ConvertRegsNative = crate::linux_shared::ConvertRegsX86_64;
@@ -40,9 +60,15 @@ pub fn run(
 ) -> Result<(Profile, ExitStatus), ()> {
     let process_launch_props = match recording_mode {
         RecordingMode::All => {
+            if let Some(cgroup) = recording_props.cgroup.clone() {
+                let profile =
+                    start_profiling_cgroup(&cgroup, recording_props, profile_creation_props);
+                return Ok((profile, ExitStatus::from_raw(0)));
+            }
             // TODO: Implement, by sudo launching a helper process which opens cpu-wide perf events
             eprintln!("Error: Profiling all processes is currently not supported on Linux.");
-            eprintln!("You can profile processes which you launch via samply, or attach to a single process.");
+            eprintln!("You can profile processes which you launch via samply, attach to a single process,");
+            eprintln!("or profile the processes of a cgroup with --all --cgroup <PATH>.");
             std::process::exit(1)
         }
         RecordingMode::Pid(pid) => {
@@ -60,6 +86,8 @@ pub fn run(
         args,
         iteration_count,
         ignore_exit_code,
+        capture_output,
+        alloc_sample_interval,
     } = process_launch_props;
 
     if profile_creation_props.coreclr.any_enabled() {
@@ -68,6 +96,19 @@ pub fn run(
         if !env_vars.iter().any(|p| p.0 == "DOTNET_PerfMapEnabled") {
             env_vars.push(("DOTNET_PerfMapEnabled".into(), "2".into()));
         }
+        // Append the optimization tier, e.g. "[Tier0]" or "[PreJIT]" for
+        // ReadyToRun code, to the method names, so that we can categorize them.
+        if !env_vars
+            .iter()
+            .any(|p| p.0 == "DOTNET_PerfMapShowOptimizationTiers")
+        {
+            env_vars.push(("DOTNET_PerfMapShowOptimizationTiers".into(), "1".into()));
+        }
+    }
+
+    if jvm::is_java_command(&command_name) {
+        // Make the JVM keep frame pointers in JIT code and write a perf map.
+        jvm::add_env_vars(&mut env_vars);
     }
 
     // Ignore Ctrl+C while the subcommand is running. The signal still reaches the process
@@ -75,12 +116,44 @@ pub fn run(
     // to all processes in the foreground process group).
     let mut ctrl_c_receiver = CtrlC::observe_oneshot();
 
+    let output_capture = if capture_output {
+        match OutputCapture::start() {
+            Ok(output_capture) => Some(output_capture),
+            Err(err) => {
+                eprintln!("Could not capture the output of the launched command: {err}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let child_stdout_and_stderr = output_capture.as_ref().map(OutputCapture::child_fds);
+
+    let alloc_tracking = match alloc_sample_interval.map(AllocTracking::new) {
+        Some(Ok(alloc_tracking)) => {
+            alloc_tracking.add_env_vars(&mut env_vars);
+            Some(alloc_tracking)
+        }
+        Some(Err(err)) => {
+            eprintln!("Could not record allocations: {err}");
+            None
+        }
+        None => None,
+    };
+
     // Start a new process for the launched command and get its pid.
     // The command will not start running until we tell it to.
-    let process =
-        SuspendedLaunchedProcess::launch_in_suspended_state(&command_name, &args, &env_vars)
-            .unwrap();
+    let process = SuspendedLaunchedProcess::launch_in_suspended_state(
+        &command_name,
+        &args,
+        &env_vars,
+        child_stdout_and_stderr,
+    )
+    .unwrap();
     let pid = process.pid();
+    if let Some(output_capture) = &output_capture {
+        output_capture.set_pid(pid);
+    }
 
     // Create a channel for the observer thread to notify the main thread once
     // profiling has been initialized and the launched process can start.
@@ -91,7 +164,21 @@ pub fn run(
 
     // Launch the observer thread. This thread will manage the perf events.
     let interval = recording_props.interval;
+    let sampling_clock = recording_props.sampling_clock;
+    let sampling_period = recording_props.sampling_period;
     let time_limit = recording_props.time_limit;
+    let spill_to_disk = recording_props.spill_to_disk;
+    let kernel_stacks = recording_props.kernel_stacks;
+    let unwind_mode = recording_props.unwind_mode;
+    let power = recording_props.power;
+    let syscalls = recording_props.syscalls.clone();
+    let page_faults = recording_props.page_faults;
+    let wall_clock = recording_props.wall_clock;
+    let probes = recording_props.probes.clone();
+    let core_types = recording_props.core_types;
+    let precise_ip = recording_props.precise_ip;
+    let reader_threads = recording_props.reader_threads;
+    let flight_recorder = make_flight_recorder(&recording_props, &profile_creation_props, None);
     let initial_exec_name = command_name.to_string_lossy().to_string();
     let initial_cmdline: Vec<String> = std::iter::once(initial_exec_name.clone())
         .chain(args.iter().map(|arg| arg.to_string_lossy().to_string()))
@@ -103,6 +190,9 @@ pub fn run(
     let initial_exec_name_and_cmdline = (initial_exec_name, initial_cmdline);
     let observer_thread = thread::spawn(move || {
         let mut converter = make_converter(interval, profile_creation_props);
+        if core_types {
+            set_up_core_type_threads(&mut converter);
+        }
 
         // Wait for the initial pid to profile.
         let SamplerRequest::StartProfilingAnotherProcess(pid, attach_mode) =
@@ -112,7 +202,24 @@ pub fn run(
         };
 
         // Create the perf events, setting ENABLE_ON_EXEC.
-        let perf_group = init_profiler(interval, pid, attach_mode, &mut converter);
+        let perf_group = init_profiler(
+            interval,
+            sampling_clock,
+            sampling_period,
+            pid,
+            attach_mode,
+            kernel_stacks,
+            unwind_mode,
+            syscalls.as_deref(),
+            page_faults,
+            wall_clock,
+            &probes,
+            precise_ip,
+            reader_threads,
+            None,
+            &mut converter,
+        );
+        let power_sampler = start_power_sampler(power, pid);
 
         // Tell the main thread to tell the child process to begin executing.
         profile_another_pid_reply_sender.send(true).unwrap();
@@ -133,6 +240,10 @@ pub fn run(
             profile_another_pid_reply_sender,
             stop_receiver,
             Some(initial_exec_name_and_cmdline),
+            spill_to_disk,
+            flight_recorder,
+            power_sampler,
+            alloc_tracking,
         )
     });
 
@@ -148,6 +259,7 @@ pub fn run(
     let _ = profile_another_pid_reply_receiver.recv().unwrap();
 
     // Now tell the child process to start executing.
+    let iteration_start = monotonic_nanos();
     let process = match process.unsuspend_and_run() {
         Ok(process) => process,
         Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
@@ -169,7 +281,12 @@ pub fn run(
 
     // Wait for the child process to quit.
     // This is where the main thread spends all its time during profiling.
-    let mut wait_status = process.wait().unwrap();
+    let (mut wait_status, mut resource_usage) = process.wait().unwrap();
+    let mut iterations = vec![IterationTiming {
+        pid,
+        start: iteration_start,
+        end: monotonic_nanos(),
+    }];
 
     for i in 2..=iteration_count {
         let previous_run_exited_with_success = match &wait_status {
@@ -183,10 +300,17 @@ pub fn run(
             break;
         }
         eprintln!("Running iteration {i} of {iteration_count}...");
-        let process =
-            SuspendedLaunchedProcess::launch_in_suspended_state(&command_name, &args, &env_vars)
-                .unwrap();
+        let process = SuspendedLaunchedProcess::launch_in_suspended_state(
+            &command_name,
+            &args,
+            &env_vars,
+            child_stdout_and_stderr,
+        )
+        .unwrap();
         let pid = process.pid();
+        if let Some(output_capture) = &output_capture {
+            output_capture.set_pid(pid);
+        }
 
         // Tell the sampler to start profiling another pid, and wait for it to signal us to go ahead.
         profile_another_pid_request_sender
@@ -201,6 +325,7 @@ pub fn run(
         }
 
         // Now tell the child process to start executing.
+        let iteration_start = monotonic_nanos();
         let process = match process.unsuspend_and_run() {
             Ok(process) => process,
             Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
@@ -214,7 +339,25 @@ pub fn run(
             }
         };
 
-        wait_status = process.wait().expect("couldn't wait for child");
+        (wait_status, resource_usage) = process.wait().expect("couldn't wait for child");
+        iterations.push(IterationTiming {
+            pid,
+            start: iteration_start,
+            end: monotonic_nanos(),
+        });
+    }
+
+    if iteration_count > 1 {
+        print_iteration_summary(&iterations);
+        profile_another_pid_request_sender
+            .send(SamplerRequest::AddIterations(iterations))
+            .unwrap();
+    }
+
+    if let Some(output_capture) = output_capture {
+        profile_another_pid_request_sender
+            .send(SamplerRequest::AddCapturedOutput(output_capture.finish()))
+            .unwrap();
     }
 
     profile_another_pid_request_sender
@@ -227,18 +370,71 @@ pub fn run(
     // Now wait for the observer thread to quit. It will keep running until all
     // perf events are closed, which happens if all processes which the events
     // are attached to have quit.
-    let profile = observer_thread
+    let mut profile = observer_thread
         .join()
         .expect("couldn't join observer thread");
+    add_exit_info_to_profile(&mut profile, &wait_status, &resource_usage);
 
     let exit_status = match wait_status {
         WaitStatus::Exited(_pid, exit_code) => ExitStatus::from_raw(exit_code),
+        WaitStatus::Signaled(_pid, signal, _core_dumped) => {
+            eprintln!(
+                "The profiled process was terminated by {signal}. Keeping the samples recorded up to that point."
+            );
+            ExitStatus::from_raw(signal as i32)
+        }
         _ => ExitStatus::default(),
     };
 
     Ok((profile, exit_status))
 }
 
+/// Records how the (last) launched process exited, and how many resources it
+/// used, in the profile's metadata.
+fn add_exit_info_to_profile(
+    profile: &mut Profile,
+    wait_status: &WaitStatus,
+    resource_usage: &ResourceUsage,
+) {
+    const SECTION: &str = "Process exit";
+    let exit_status = match wait_status {
+        WaitStatus::Exited(_pid, exit_code) => format!("Exited with code {exit_code}"),
+        WaitStatus::Signaled(_pid, signal, _core_dumped) => format!("Terminated by {signal}"),
+        other => format!("{other:?}"),
+    };
+    profile.add_extra_info_string(SECTION, "Exit status", &exit_status);
+    profile.add_extra_info_number(
+        SECTION,
+        "Max RSS",
+        MarkerNumberFieldFormat::Bytes,
+        resource_usage.max_rss_bytes as f64,
+    );
+    profile.add_extra_info_number(
+        SECTION,
+        "User CPU time",
+        MarkerNumberFieldFormat::Duration,
+        resource_usage.user_time.as_secs_f64() * 1000.0,
+    );
+    profile.add_extra_info_number(
+        SECTION,
+        "System CPU time",
+        MarkerNumberFieldFormat::Duration,
+        resource_usage.system_time.as_secs_f64() * 1000.0,
+    );
+    profile.add_extra_info_number(
+        SECTION,
+        "Voluntary context switches",
+        MarkerNumberFieldFormat::Integer,
+        resource_usage.voluntary_context_switches as f64,
+    );
+    profile.add_extra_info_number(
+        SECTION,
+        "Involuntary context switches",
+        MarkerNumberFieldFormat::Integer,
+        resource_usage.involuntary_context_switches as f64,
+    );
+}
+
 fn start_profiling_pid(
     pid: u32,
     recording_props: RecordingProps,
@@ -257,14 +453,49 @@ fn start_profiling_pid(
     let observer_thread = thread::spawn({
         move || {
             let interval = recording_props.interval;
+            let sampling_clock = recording_props.sampling_clock;
+            let sampling_period = recording_props.sampling_period;
             let time_limit = recording_props.time_limit;
+            let spill_to_disk = recording_props.spill_to_disk;
+            let kernel_stacks = recording_props.kernel_stacks;
+            let unwind_mode = recording_props.unwind_mode;
+            let power = recording_props.power;
+            let syscalls = recording_props.syscalls.clone();
+            let page_faults = recording_props.page_faults;
+            let wall_clock = recording_props.wall_clock;
+            let probes = recording_props.probes.clone();
+            let core_types = recording_props.core_types;
+            let precise_ip = recording_props.precise_ip;
+            let reader_threads = recording_props.reader_threads;
+            let flight_recorder =
+                make_flight_recorder(&recording_props, &profile_creation_props, Some(pid));
             let mut converter = make_converter(interval, profile_creation_props);
+            if core_types {
+                set_up_core_type_threads(&mut converter);
+            }
             let SamplerRequest::StartProfilingAnotherProcess(pid, attach_mode) =
                 profile_another_pid_request_receiver.recv().unwrap()
             else {
                 panic!("The first message should be a StartProfilingAnotherProcess")
             };
-            let perf_group = init_profiler(interval, pid, attach_mode, &mut converter);
+            let perf_group = init_profiler(
+                interval,
+                sampling_clock,
+                sampling_period,
+                pid,
+                attach_mode,
+                kernel_stacks,
+                unwind_mode,
+                syscalls.as_deref(),
+                page_faults,
+                wall_clock,
+                &probes,
+                precise_ip,
+                reader_threads,
+                None,
+                &mut converter,
+            );
+            let power_sampler = start_power_sampler(power, pid);
 
             // Tell the main thread that we are now executing.
             profile_another_pid_reply_sender.send(true).unwrap();
@@ -277,6 +508,10 @@ fn start_profiling_pid(
                 profile_another_pid_reply_sender,
                 ctrl_c_receiver,
                 None,
+                spill_to_disk,
+                flight_recorder,
+                power_sampler,
+                None,
             )
         }
     });
@@ -294,6 +529,7 @@ fn start_profiling_pid(
 
     // Now that we know that profiler initialization has succeeded, tell the user about it.
     eprintln!("Recording process with PID {pid} until Ctrl+C...");
+    pause::spawn_pause_key_listener();
 
     profile_another_pid_request_sender
         .send(SamplerRequest::StopProfilingOncePerfEventsExhausted)
@@ -310,12 +546,126 @@ fn start_profiling_pid(
     // dropped its CtrlC receiver by now.
 }
 
+fn start_profiling_cgroup(
+    cgroup: &Path,
+    recording_props: RecordingProps,
+    profile_creation_props: ProfileCreationProps,
+) -> Profile {
+    let cgroup = resolve_cgroup_path(cgroup);
+    if !cgroup.is_dir() {
+        eprintln!("Error: The cgroup {cgroup:?} does not exist.");
+        std::process::exit(1)
+    }
+    if recording_props.syscalls.is_some()
+        || recording_props.page_faults.is_some()
+        || recording_props.wall_clock
+        || !recording_props.probes.is_empty()
+    {
+        eprintln!("--syscalls, --page-faults, --wall-clock and --probe are not supported with --cgroup, and will be ignored.");
+    }
+
+    // When the first Ctrl+C is received, stop recording.
+    let ctrl_c_receiver = CtrlC::observe_oneshot();
+
+    // The events of a cgroup stay open when its processes exit, so we only
+    // stop once Ctrl+C is pressed or the time limit is reached.
+    let (_request_sender, request_receiver) = crossbeam_channel::bounded(2);
+    let (reply_sender, _reply_receiver) = crossbeam_channel::bounded(2);
+
+    let interval = recording_props.interval;
+    let flight_recorder = make_flight_recorder(&recording_props, &profile_creation_props, None);
+    let mut converter = make_converter(interval, profile_creation_props);
+    if recording_props.core_types {
+        set_up_core_type_threads(&mut converter);
+    }
+    let perf_group = init_profiler(
+        interval,
+        recording_props.sampling_clock,
+        recording_props.sampling_period,
+        0,
+        AttachMode::StopAttachEnableResume,
+        recording_props.kernel_stacks,
+        recording_props.unwind_mode,
+        None,
+        None,
+        false,
+        &[],
+        recording_props.precise_ip,
+        recording_props.reader_threads,
+        Some(&cgroup),
+        &mut converter,
+    );
+    // The energy counters are added to one of the cgroup's processes.
+    let power_sampler = cgroup_pids(&cgroup)
+        .first()
+        .and_then(|&pid| start_power_sampler(recording_props.power, pid));
+
+    eprintln!("Recording the processes in cgroup {cgroup:?} until Ctrl+C...");
+    pause::spawn_pause_key_listener();
+
+    run_profiler(
+        perf_group,
+        converter,
+        recording_props.time_limit,
+        request_receiver,
+        reply_sender,
+        ctrl_c_receiver,
+        None,
+        recording_props.spill_to_disk,
+        flight_recorder,
+        power_sampler,
+        None,
+    )
+}
+
+/// Turns a cgroup path like "system.slice/docker-1234.scope" into a path in the
+/// cgroup file system.
+fn resolve_cgroup_path(cgroup: &Path) -> PathBuf {
+    if cgroup.is_absolute() {
+        cgroup.to_owned()
+    } else {
+        Path::new("/sys/fs/cgroup").join(cgroup)
+    }
+}
+
+/// Returns the processes in this cgroup and in its descendant cgroups.
+fn cgroup_pids(cgroup: &Path) -> Vec<u32> {
+    let mut pids = Vec::new();
+    if let Ok(procs) = std::fs::read_to_string(cgroup.join("cgroup.procs")) {
+        pids.extend(
+            procs
+                .lines()
+                .filter_map(|line| line.trim().parse::<u32>().ok()),
+        );
+    }
+    if let Ok(entries) = std::fs::read_dir(cgroup) {
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                pids.extend(cgroup_pids(&entry.path()));
+            }
+        }
+    }
+    pids
+}
+
 fn paranoia_level() -> Option<u32> {
     let level = read_string_lossy("/proc/sys/kernel/perf_event_paranoid").ok()?;
     let level = level.trim().parse::<u32>().ok()?;
     Some(level)
 }
 
+/// Gives the converter the core type of each CPU, for `--core-types`.
+fn set_up_core_type_threads(
+    converter: &mut Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+) {
+    match core_types::detect_core_types() {
+        Some(cpu_core_types) => converter.set_core_types(cpu_core_types),
+        None => eprintln!("All CPU cores are of the same type, ignoring --core-types."),
+    }
+}
+
 fn make_converter(
     interval: Duration,
     profile_creation_props: ProfileCreationProps,
@@ -338,6 +688,7 @@ fn make_converter(
         main_event_attr_index: 0,
         main_event_name: "cycles".to_string(),
         sampling_is_time_based: Some(interval_nanos),
+        sample_cpu_time: SampleCpuTime::FixedNanos(interval_nanos),
         off_cpu_indicator: Some(OffCpuIndicator::ContextSwitches),
         sched_switch_attr_index: None,
         known_event_indices: HashMap::new(),
@@ -364,13 +715,26 @@ fn make_converter(
     if let Ok(os_release) = os_release::OsRelease::new() {
         converter.set_os_name(&os_release.pretty_name);
     }
+    converter.set_find_binaries_in_process_root(true);
     converter
 }
 
+#[allow(clippy::too_many_arguments)]
 fn init_profiler(
     interval: Duration,
+    sampling_clock: Option<SamplingClock>,
+    sampling_period: Option<SamplingPeriod>,
     pid: u32,
     attach_mode: AttachMode,
+    kernel_stacks: bool,
+    unwind_mode: UnwindMode,
+    syscalls: Option<&[String]>,
+    page_faults: Option<PageFaultMode>,
+    wall_clock: bool,
+    probes: &[String],
+    precise_ip: Option<u8>,
+    reader_threads: Option<usize>,
+    cgroup: Option<&Path>,
     converter: &mut Converter<
         framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
     >,
@@ -382,17 +746,85 @@ fn init_profiler(
     };
 
     let frequency = (1_000_000_000 / interval_nanos) as u32;
-    let stack_size = 32000;
-    let regs_mask = ConvertRegsNative::regs_mask();
+    // Only DWARF unwinding needs a copy of the user stack and registers.
+    let (stack_size, regs_mask) = match unwind_mode {
+        UnwindMode::Dwarf => (32000, ConvertRegsNative::regs_mask()),
+        UnwindMode::FramePointers | UnwindMode::Lbr => (0, 0),
+    };
+    let syscall_tracepoints = syscalls.and_then(|syscall_names| {
+        match SyscallTracepoints::new(syscall_names, ConvertRegsNative::syscall_table()) {
+            Ok(tracepoints) => Some(tracepoints),
+            Err(err) => {
+                eprintln!("Could not set up syscall tracing, syscalls will not be traced: {err}");
+                None
+            }
+        }
+    });
+    let probes: Vec<Probe> = probes
+        .iter()
+        .filter_map(|arg| match Probe::new(arg, pid) {
+            Ok(probe) => Some(probe),
+            Err(err) => {
+                eprintln!("Could not set up the probe {arg}, it will be ignored: {err}");
+                None
+            }
+        })
+        .collect();
+    let sched_switch_tracepoint_id = if wall_clock {
+        match syscall_tracing::sched_switch_tracepoint_id() {
+            Ok(id) => {
+                converter.set_annotate_off_cpu_states(true);
+                Some(id)
+            }
+            Err(err) => {
+                eprintln!("Could not set up off-CPU stack sampling for --wall-clock: {err}");
+                None
+            }
+        }
+    } else {
+        None
+    };
 
-    let perf = PerfGroup::open(
-        pid,
-        frequency,
-        stack_size,
-        EventSource::HwCpuCycles,
-        regs_mask,
-        attach_mode,
-    );
+    // Fixed periods are in nanoseconds for the clock events, so an interval
+    // can only be used as the period of a clock event. The cycles event is
+    // sampled at the matching frequency instead.
+    let sample_period_for = |event_source| match sampling_period {
+        Some(SamplingPeriod::Events(events)) => Some(events),
+        Some(SamplingPeriod::Interval(_)) if event_source != EventSource::HwCpuCycles => {
+            Some(interval_nanos)
+        }
+        _ => None,
+    };
+
+    let open_perf_group = |event_source| -> Result<PerfGroup, std::io::Error> {
+        let mut group = PerfGroup::new(
+            frequency,
+            sample_period_for(event_source),
+            stack_size,
+            regs_mask,
+            ConvertRegsNative::all_regs_mask(),
+            event_source,
+            kernel_stacks,
+            unwind_mode,
+            syscall_tracepoints.clone(),
+            page_faults,
+            probes.clone(),
+            sched_switch_tracepoint_id,
+            precise_ip.unwrap_or(0),
+            reader_threads,
+        );
+        match cgroup {
+            Some(cgroup) => group.open_cgroup(cgroup)?,
+            None => group.open_process(pid, attach_mode)?,
+        }
+        Ok(group)
+    };
+
+    let perf = open_perf_group(match sampling_clock {
+        Some(SamplingClock::TaskClock) => EventSource::SwTaskClock,
+        Some(SamplingClock::CpuClock) => EventSource::SwCpuClock,
+        None => EventSource::HwCpuCycles,
+    });
 
     if let Err(error) = &perf {
         if error.kind() == std::io::ErrorKind::PermissionDenied {
@@ -415,56 +847,143 @@ fn init_profiler(
 
     let mut perf = match perf {
         Ok(perf) => perf,
-        Err(_) => {
+        Err(error) => {
             // We've already checked for permission denied due to paranoia
             // level, and exited with a warning in that case.
 
             // Another reason for the error could be the type of perf event:
             // The "Hardware CPU cycles" event is not supported in some contexts, for example in VMs.
-            // Try a different event type.
-            let perf = PerfGroup::open(
-                pid,
-                frequency,
-                stack_size,
-                EventSource::SwCpuClock,
-                regs_mask,
-                attach_mode,
-            );
+            // Try a different event type, unless the clock was chosen explicitly.
+            let perf = match sampling_clock {
+                Some(_) => Err(error),
+                None => open_perf_group(EventSource::SwCpuClock),
+            };
             match perf {
                 Ok(perf) => perf, // Success!
                 Err(error) => {
                     eprintln!("Failed to start profiling: {error}");
+                    if unwind_mode == UnwindMode::Lbr {
+                        eprintln!("LBR unwinding needs a CPU with LBR call stack support and the hardware cycles event.");
+                        eprintln!("Try again with --unwind=fp or --unwind=dwarf.");
+                    }
+                    if syscall_tracepoints.is_some() {
+                        eprintln!("Tracing syscalls requires running samply as root, or setting");
+                        eprintln!("/proc/sys/kernel/perf_event_paranoid to -1.");
+                    }
                     std::process::exit(1);
                 }
             }
         }
     };
 
-    let (exe_name, cmdline) = get_process_cmdline(pid).expect("Couldn't read process cmdline");
-    let comm_data = std::fs::read(format!("/proc/{pid}/comm")).expect("Couldn't read process comm");
-    let length = memchr::memchr(b'\0', &comm_data).unwrap_or(comm_data.len());
-    let comm_name = std::str::from_utf8(&comm_data[..length])
-        .unwrap()
-        .trim_end();
-    converter.register_existing_process(pid as i32, comm_name, &exe_name, cmdline);
+    let event_source = perf.event_source();
+    if let (None, Some(SamplingPeriod::Events(events))) = (sampling_clock, sampling_period) {
+        if event_source != EventSource::HwCpuCycles {
+            eprintln!("The cycles event isn't available, so cpu-clock is sampled instead, every {events} nanoseconds.");
+        }
+    }
+    converter.add_recording_info("Sampling event", event_source.name());
+    let sampling_period_description = match sample_period_for(event_source) {
+        Some(period) if event_source == EventSource::HwCpuCycles => format!("{period} cycles"),
+        Some(period) => format!("{period} ns"),
+        None => format!("{frequency} Hz"),
+    };
+    converter.add_recording_info("Sampling period", &sampling_period_description);
 
-    // TODO: Gather threads / processes recursively, here and in PerfGroup setup.
-    for thread_entry in std::fs::read_dir(format!("/proc/{pid}/task"))
-        .unwrap()
-        .flatten()
-    {
-        let tid: u32 = thread_entry.file_name().to_string_lossy().parse().unwrap();
-        let comm_path = format!("/proc/{pid}/task/{tid}/comm");
-        if let Ok(buffer) = std::fs::read(comm_path) {
-            let length = memchr::memchr(b'\0', &buffer).unwrap_or(buffer.len());
-            let name = std::str::from_utf8(&buffer[..length]).unwrap().trim_end();
-            converter.register_existing_thread(pid as i32, tid as i32, name);
+    if let Some(requested_precise_ip) = precise_ip {
+        // Only the hardware cycles event supports precise sampling.
+        let precise_ip = match event_source {
+            EventSource::HwCpuCycles => perf.precise_ip(),
+            _ => 0,
+        };
+        if precise_ip < requested_precise_ip {
+            eprintln!("Precise sampling at level {requested_precise_ip} is not supported, using level {precise_ip}.");
+        }
+        converter.add_recording_info(
+            "Sampling precision",
+            &format!(
+                "precise_ip {precise_ip} ({})",
+                precise_ip_description(precise_ip)
+            ),
+        );
+    }
+
+    match cgroup {
+        Some(cgroup) => {
+            for pid in cgroup_pids(cgroup) {
+                // Skip processes which have exited since the cgroup was read.
+                let _ = add_existing_process(pid, converter);
+            }
+        }
+        None => {
+            if let Err(err) = add_existing_process(pid, converter) {
+                eprintln!("Could not read the information of process {pid}: {err}");
+            }
+        }
+    }
+
+    // Unlike perf.data files, live recordings don't get an mmap record for the
+    // kernel image, so tell the converter about it explicitly.
+    if kernel_stacks && !converter.add_running_kernel_image() {
+        eprintln!("Kernel symbols are not available, kernel frames will not be symbolicated.");
+        eprintln!("You can execute the following command to make them available:");
+        eprintln!("    echo '0' | sudo tee /proc/sys/kernel/kptr_restrict");
+    }
+
+    // eprintln!("Enabling perf events...");
+    match attach_mode {
+        AttachMode::StopAttachEnableResume => perf.enable(),
+        AttachMode::AttachWithEnableOnExec => {
+            // The perf event will get enabled automatically once the forked child process execs.
         }
     }
 
-    let maps = read_string_lossy(format!("/proc/{pid}/maps")).expect("couldn't read proc maps");
+    perf
+}
+
+/// What perf's precise_ip levels mean for the sampled instruction pointers.
+fn precise_ip_description(precise_ip: u8) -> &'static str {
+    match precise_ip {
+        0 => "arbitrary skid",
+        1 => "constant skid",
+        2 => "zero skid requested",
+        _ => "zero skid",
+    }
+}
+
+/// Tells the converter about a process which was already running before its
+/// perf events were created: its name, command line, threads and mappings.
+/// Fails if the process has exited, in which case the converter isn't told
+/// about any of it.
+fn add_existing_process(
+    pid: u32,
+    converter: &mut Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+) -> std::io::Result<()> {
+    // Read everything before registering anything, so that a process which
+    // exits in the middle isn't added halfway.
+    let (exe_name, cmdline) = get_process_cmdline(pid)?;
+    let comm_name = read_comm(format!("/proc/{pid}/comm"))?;
+    // TODO: Gather threads / processes recursively, here and in PerfGroup setup.
+    let mut threads = Vec::new();
+    for thread_entry in std::fs::read_dir(format!("/proc/{pid}/task"))?.flatten() {
+        let Ok(tid) = thread_entry.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        // Threads can exit while we're reading them.
+        if let Ok(name) = read_comm(format!("/proc/{pid}/task/{tid}/comm")) {
+            threads.push((tid, name));
+        }
+    }
+    let maps = read_string_lossy(format!("/proc/{pid}/maps"))?;
     let maps = proc_maps::parse(&maps);
 
+    converter.register_existing_process(pid as i32, &comm_name, &exe_name, cmdline);
+    for (tid, name) in threads {
+        converter.register_existing_thread(pid as i32, tid as i32, &name);
+    }
+
     let vdso_file_id = VdsoObject::shared_instance_for_this_process()
         .map(|vdso| Mmap2FileId::BuildId(vdso.build_id().to_owned()));
 
@@ -513,20 +1032,47 @@ fn init_profiler(
             0,
         );
     }
+    Ok(())
+}
 
-    // eprintln!("Enabling perf events...");
-    match attach_mode {
-        AttachMode::StopAttachEnableResume => perf.enable(),
-        AttachMode::AttachWithEnableOnExec => {
-            // The perf event will get enabled automatically once the forked child process execs.
-        }
-    }
+/// Reads a comm file from /proc, without the trailing newline.
+fn read_comm(path: String) -> std::io::Result<String> {
+    let buffer = std::fs::read(path)?;
+    let length = memchr::memchr(b'\0', &buffer).unwrap_or(buffer.len());
+    Ok(String::from_utf8_lossy(&buffer[..length])
+        .trim_end()
+        .to_owned())
+}
 
-    perf
+/// The wall-clock time of one run of the launched command, on the perf clock.
+struct IterationTiming {
+    pid: u32,
+    start: u64,
+    end: u64,
+}
+
+/// Prints how long the runs of the launched command took.
+fn print_iteration_summary(iterations: &[IterationTiming]) {
+    let mut durations_ms: Vec<f64> = iterations
+        .iter()
+        .map(|iteration| (iteration.end - iteration.start) as f64 / 1_000_000.0)
+        .collect();
+    durations_ms.sort_by(f64::total_cmp);
+    let median = durations_ms[durations_ms.len() / 2];
+    let min = durations_ms[0];
+    let max = durations_ms[durations_ms.len() - 1];
+    eprintln!(
+        "Ran {} iterations: min {min:.1} ms, median {median:.1} ms, max {max:.1} ms",
+        durations_ms.len()
+    );
 }
 
 enum SamplerRequest {
     StartProfilingAnotherProcess(u32, AttachMode),
+    /// The lines which the launched command printed, for `--capture-output`.
+    AddCapturedOutput(Vec<CapturedLine>),
+    /// The runs of the launched command, for `--iteration-count`.
+    AddIterations(Vec<IterationTiming>),
     StopProfilingOncePerfEventsExhausted,
 }
 
@@ -541,17 +1087,70 @@ fn run_profiler(
     more_processes_reply_sender: Sender<bool>,
     mut stop_receiver: oneshot::Receiver<()>,
     mut initial_exec_name_and_cmdline: Option<(String, Vec<String>)>,
+    spill_to_disk: bool,
+    mut flight_recorder: Option<FlightRecorder>,
+    power_sampler: Option<(u32, PowerSampler)>,
+    mut alloc_tracking: Option<AllocTracking>,
 ) -> Profile {
     // eprintln!("Running...");
 
+    let mut spill_writer = if spill_to_disk {
+        match RecordSpillWriter::new() {
+            Ok(spill_writer) => Some(spill_writer),
+            Err(err) => {
+                eprintln!("Could not create a temporary file for spilling perf records, converting them in memory instead: {err}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Snapshots requested with SIGUSR2 are converted and written on their own
+    // thread, like live snapshots.
+    let mut snapshot_writer = flight_recorder.as_ref().map(|_| {
+        install_snapshot_signal_handler();
+        FlightRecorderSnapshotWriter::start()
+    });
+    let mut live_snapshot_writer = flight_recorder
+        .as_ref()
+        .filter(|flight_recorder| flight_recorder.is_live())
+        .map(|flight_recorder| {
+            LiveSnapshotWriter::start(flight_recorder.live_snapshot_path().to_owned())
+        });
+    // In live mode, the records are converted as they arrive, and the flight
+    // recorder only keeps the recent ones for the live snapshots.
+    let records_are_converted_while_recording = spill_writer.is_none()
+        && flight_recorder
+            .as_ref()
+            .is_none_or(|flight_recorder| flight_recorder.is_live());
+    pause::install_pause_signal_handler();
+
     let mut should_stop_profiling_once_perf_events_exhausted = false;
-    let mut pending_lost_events = 0;
-    let mut total_lost_events = 0;
+    let mut captured_output = Vec::new();
+    let mut iterations = Vec::new();
+    let mut lost_events = LostEventsTracker::default();
     let mut last_timestamp = 0;
+    let mut is_draining = false;
+    let mut paused_ranges = PausedRanges::default();
     loop {
-        if stop_receiver.try_recv().is_ok() {
+        if is_draining {
             break;
         }
+        if pause::take_pause_toggle_request() {
+            paused_ranges.toggle(monotonic_nanos());
+            if paused_ranges.is_paused() {
+                eprintln!("Recording paused.");
+            } else {
+                eprintln!("Recording resumed.");
+            }
+        }
+        if stop_receiver.try_recv().is_ok() {
+            // Stop generating new events, but convert the events which are
+            // still sitting in the ring buffers, so that they aren't lost.
+            perf.disable();
+            is_draining = true;
+        }
 
         match more_processes_request_receiver.try_recv() {
             Ok(SamplerRequest::StartProfilingAnotherProcess(another_pid, attach_mode)) => {
@@ -565,6 +1164,12 @@ fn run_profiler(
                     }
                 }
             }
+            Ok(SamplerRequest::AddCapturedOutput(lines)) => {
+                captured_output.extend(lines);
+            }
+            Ok(SamplerRequest::AddIterations(timings)) => {
+                iterations.extend(timings);
+            }
             Ok(SamplerRequest::StopProfilingOncePerfEventsExhausted) => {
                 should_stop_profiling_once_perf_events_exhausted = true;
             }
@@ -573,7 +1178,7 @@ fn run_profiler(
             }
         }
 
-        if perf.is_empty() && !should_stop_profiling_once_perf_events_exhausted {
+        if perf.is_empty() && !should_stop_profiling_once_perf_events_exhausted && !is_draining {
             match more_processes_request_receiver.recv() {
                 Ok(SamplerRequest::StartProfilingAnotherProcess(another_pid, attach_mode)) => {
                     match perf.open_process(another_pid, attach_mode) {
@@ -586,6 +1191,12 @@ fn run_profiler(
                         }
                     }
                 }
+                Ok(SamplerRequest::AddCapturedOutput(lines)) => {
+                    captured_output.extend(lines);
+                }
+                Ok(SamplerRequest::AddIterations(timings)) => {
+                    iterations.extend(timings);
+                }
                 Ok(SamplerRequest::StopProfilingOncePerfEventsExhausted) => {
                     should_stop_profiling_once_perf_events_exhausted = true;
                 }
@@ -595,15 +1206,20 @@ fn run_profiler(
             }
         }
 
-        if perf.is_empty() && should_stop_profiling_once_perf_events_exhausted {
+        if perf.is_empty() && (should_stop_profiling_once_perf_events_exhausted || is_draining) {
             break;
         }
 
-        perf.consume_events(&mut |event_ref| {
-            let record = event_ref.get();
-            let parsed_record = record.parse().unwrap();
-            // debug!("Recording parsed_record: {:#?}", parsed_record);
+        // Allocations are added to the converter along with the perf records,
+        // unless the perf records are only converted once recording has ended.
+        if let (Some(alloc_tracking), true) =
+            (&mut alloc_tracking, records_are_converted_while_recording)
+        {
+            alloc_tracking.poll();
+        }
 
+        let mut spill_error = None;
+        perf.consume_events(&mut |record| {
             if let Some(timestamp) = record.timestamp() {
                 if timestamp < last_timestamp {
                     // eprintln!(
@@ -611,80 +1227,508 @@ fn run_profiler(
                     // );
                 }
                 last_timestamp = timestamp;
+                lost_events.handle_timestamp(timestamp);
             }
 
-            match parsed_record {
-                EventRecord::Sample(e) => {
-                    converter.handle_main_event_sample::<ConvertRegsNative>(&e);
-                    /*
-                    } else if interpretation.sched_switch_attr_index == Some(attr_index) {
-                        converter.handle_sched_switch_sample::<C>(e);
-                    }*/
-                }
-                EventRecord::Fork(e) => {
-                    converter.handle_fork(e);
+            if record.record_type == RecordType::LOST {
+                if let Ok(EventRecord::Lost(event)) = record.parse() {
+                    lost_events.handle_lost(event.count, last_timestamp);
                 }
-                EventRecord::Comm(e) => {
-                    if e.is_execve {
-                        // Try to get the command line arguments for this process.
-                        let exec_name_and_cmdline =
-                            if let Some(initial) = initial_exec_name_and_cmdline.take() {
-                                // This COMM event is the first exec that we're processing. If we get
-                                // here, it means we're in the "launch process" case and we're seeing
-                                // the exec for that initial launched process.
-                                Some(initial)
-                            } else {
-                                // Attempt to get the process cmdline from /proc/{pid}/cmdline.
-                                // This isn't very reliable because we're processing the perf event records
-                                // in batches, with a delay, so the COMM record may be old enough that the
-                                // pid no longer exists, or the pid may even refer to a different process now.
-                                // Unfortunately there are no perf event records that give us the process
-                                // command line.
-                                get_process_cmdline(e.pid as u32).ok()
-                            };
-                        converter.handle_exec(e, record.timestamp(), exec_name_and_cmdline);
-                    } else {
-                        converter.handle_thread_rename(e, record.timestamp());
+                return;
+            }
+
+            if record.record_type == RecordType::SAMPLE
+                && record
+                    .timestamp()
+                    .is_some_and(|timestamp| paused_ranges.contains(timestamp))
+            {
+                return;
+            }
+
+            let exec_name_and_cmdline = if record.record_type == RecordType::COMM
+                && record.misc & PERF_RECORD_MISC_COMM_EXEC != 0
+            {
+                // Try to get the command line arguments for this process.
+                if let Some(initial) = initial_exec_name_and_cmdline.take() {
+                    // This COMM event is the first exec that we're processing. If we get
+                    // here, it means we're in the "launch process" case and we're seeing
+                    // the exec for that initial launched process.
+                    Some(initial)
+                } else {
+                    // Attempt to get the process cmdline from /proc/{pid}/cmdline.
+                    // This isn't very reliable because we're processing the perf event records
+                    // in batches, with a delay, so the COMM record may be old enough that the
+                    // pid no longer exists, or the pid may even refer to a different process now.
+                    // Unfortunately there are no perf event records that give us the process
+                    // command line.
+                    match record.parse() {
+                        Ok(EventRecord::Comm(e)) => get_process_cmdline(e.pid as u32).ok(),
+                        _ => None,
                     }
                 }
-                EventRecord::Exit(e) => {
-                    converter.handle_exit(e);
+            } else {
+                None
+            };
+
+            if let Some(flight_recorder) = &mut flight_recorder {
+                if !flight_recorder.is_live() {
+                    flight_recorder.push_record(record, exec_name_and_cmdline);
+                    return;
                 }
-                EventRecord::Mmap(e) => {
-                    converter.handle_mmap(e, last_timestamp);
+                flight_recorder.push_record(record, exec_name_and_cmdline.clone());
+            }
+
+            match &mut spill_writer {
+                Some(spill_writer) => {
+                    // Once writing has failed, drop the rest of this batch; we stop
+                    // recording and convert what has been spilled so far.
+                    if spill_error.is_none() {
+                        if let Err(err) =
+                            spill_writer.write_record(record, exec_name_and_cmdline.as_ref())
+                        {
+                            spill_error = Some(err);
+                        }
+                    }
                 }
-                EventRecord::Mmap2(e) => {
-                    converter.handle_mmap2(e, last_timestamp);
+                None => {
+                    if let Some(alloc_tracking) = &mut alloc_tracking {
+                        add_allocations_before_record(&mut converter, alloc_tracking, record);
+                    }
+                    handle_record(
+                        &mut converter,
+                        record,
+                        exec_name_and_cmdline,
+                        last_timestamp,
+                    );
                 }
-                EventRecord::ContextSwitch(e) => {
-                    let common = match record.common_data() {
-                        Ok(common) => common,
-                        Err(_) => return,
-                    };
-                    converter.handle_context_switch(e, common);
+            }
+        });
+
+        if let Some(err) = spill_error {
+            eprintln!(
+                "Failed to write perf records to the spill file, stopping the recording: {err}"
+            );
+            break;
+        }
+
+        if let Some(flight_recorder) = &mut flight_recorder {
+            if let Some(snapshot_writer) = &mut snapshot_writer {
+                if take_snapshot_request() {
+                    snapshot_writer.write_snapshot(
+                        flight_recorder.snapshot(),
+                        flight_recorder.next_snapshot_path(),
+                    );
                 }
-                EventRecord::Lost(event) => {
-                    pending_lost_events += event.count;
-                    total_lost_events += event.count;
-                    return;
+            }
+            if let Some(live_snapshot_writer) = &mut live_snapshot_writer {
+                if flight_recorder.take_live_snapshot_request() {
+                    live_snapshot_writer.request_snapshot(flight_recorder.snapshot());
                 }
-                _ => {}
             }
+        }
+
+        perf.wait();
+    }
+
+    // Wait for the last live snapshot to be written, so that it can't replace
+    // the final profile.
+    if let Some(live_snapshot_writer) = live_snapshot_writer {
+        live_snapshot_writer.finish();
+    }
+    if let Some(snapshot_writer) = snapshot_writer {
+        snapshot_writer.finish();
+    }
+
+    if lost_events.total_count() > 0 {
+        eprintln!(
+            "Lost {} events, because the perf buffers were full. The affected time ranges have \"Lost events\" markers. On machines with many CPUs, --reader-threads can help.",
+            lost_events.total_count()
+        );
+    }
+    let lost_event_ranges = lost_events.finish(last_timestamp);
+
+    if let Some(spill_writer) = spill_writer {
+        eprintln!(
+            "Converting {} spilled perf records...",
+            spill_writer.record_count()
+        );
+        if let Err(err) =
+            replay_spilled_records(spill_writer, &mut converter, alloc_tracking.as_mut())
+        {
+            eprintln!("Failed to read back spilled perf records: {err}");
+        }
+    }
+
+    if let Some(flight_recorder) = flight_recorder.filter(|f| !f.is_live()) {
+        // The converter already knows about the attached process from init_profiler.
+        replay_flight_recorder_records(
+            &flight_recorder.snapshot(),
+            &mut converter,
+            false,
+            alloc_tracking.as_mut(),
+        );
+    }
+
+    for (start, end) in paused_ranges.finish(monotonic_nanos()) {
+        converter.add_paused_range(start, end);
+    }
+    converter.add_lost_events(&lost_event_ranges);
+
+    if let Some((pid, power_sampler)) = power_sampler {
+        for energy_samples in power_sampler.stop() {
+            converter.add_process_counter(
+                pid as i32,
+                &energy_samples.domain_name,
+                "power",
+                "Energy consumed by this RAPL domain",
+                energy_samples
+                    .samples
+                    .into_iter()
+                    .map(|(timestamp, energy_uj)| {
+                        (timestamp, microjoules_to_picowatt_hours(energy_uj))
+                    }),
+            );
+        }
+    }
+
+    for line in captured_output {
+        converter.add_output_line_marker(
+            line.pid as i32,
+            line.timestamp,
+            line.stream.name(),
+            &line.line,
+        );
+    }
+
+    for (i, iteration) in iterations.into_iter().enumerate() {
+        converter.add_iteration_marker(
+            iteration.pid as i32,
+            i as u32 + 1,
+            iteration.start,
+            iteration.end,
+        );
+    }
+
+    // The remaining allocations belong to processes which are still alive.
+    if let Some(alloc_tracking) = alloc_tracking {
+        for record in alloc_tracking.finish() {
+            add_allocation(&mut converter, record);
+        }
+    }
+
+    converter.finish()
+}
+
+/// Starts sampling the RAPL energy counters if `--power` was specified. The
+/// returned pid is the process under whose track the power counters are shown.
+fn start_power_sampler(power: bool, pid: u32) -> Option<(u32, PowerSampler)> {
+    if !power {
+        return None;
+    }
+    match PowerSampler::start() {
+        Ok(power_sampler) => Some((pid, power_sampler)),
+        Err(err) => {
+            eprintln!("Could not read the RAPL energy counters: {err}");
+            eprintln!("Reading them usually requires running samply as root.");
+            None
+        }
+    }
+}
+
+/// Creates the flight recorder for --flight-recorder or --live, if either was given.
+fn make_flight_recorder(
+    recording_props: &RecordingProps,
+    profile_creation_props: &ProfileCreationProps,
+    attached_pid: Option<u32>,
+) -> Option<FlightRecorder> {
+    let interval = recording_props.interval;
+    let output_file = &recording_props.output_file;
+    if let Some(snapshot_interval) = recording_props.live_snapshot_interval {
+        return Some(FlightRecorder::new_live(
+            snapshot_interval,
+            interval,
+            profile_creation_props.clone(),
+            attached_pid,
+            output_file,
+        ));
+    }
+    recording_props.flight_recorder_window.map(|window| {
+        FlightRecorder::new(
+            window,
+            interval,
+            profile_creation_props.clone(),
+            attached_pid,
+            output_file,
+        )
+    })
+}
+
+/// Converts the records of a flight recorder snapshot into a profile.
+fn flight_recorder_snapshot(snapshot: &FlightRecorderSnapshot) -> Profile {
+    let mut converter = make_converter(snapshot.interval, snapshot.profile_creation_props.clone());
+    converter.add_running_kernel_image();
+    replay_flight_recorder_records(snapshot, &mut converter, true, None);
+    converter.finish()
+}
+
+/// Converts live snapshots and writes them on a separate thread, so that
+/// reading the perf events isn't held up while a snapshot is created.
+struct LiveSnapshotWriter {
+    snapshot_sender: Sender<FlightRecorderSnapshot>,
+    thread: JoinHandle<()>,
+}
+
+impl LiveSnapshotWriter {
+    fn start(path: PathBuf) -> Self {
+        let (snapshot_sender, snapshot_receiver) = crossbeam_channel::bounded(1);
+        let thread = thread::spawn(move || {
+            for snapshot in snapshot_receiver {
+                write_live_snapshot(&snapshot, &path);
+            }
+        });
+        Self {
+            snapshot_sender,
+            thread,
+        }
+    }
+
+    /// Skips the snapshot if the previous one is still waiting to be written.
+    fn request_snapshot(&mut self, snapshot: FlightRecorderSnapshot) {
+        let _ = self.snapshot_sender.try_send(snapshot);
+    }
+
+    fn finish(self) {
+        drop(self.snapshot_sender);
+        let _ = self.thread.join();
+    }
+}
+
+/// Replaces the output file with a profile of the snapshot, so that the live
+/// server can serve it. The profile is written to a temporary file first, so
+/// that the server never serves a partially written file.
+fn write_live_snapshot(snapshot: &FlightRecorderSnapshot, path: &Path) {
+    let profile = flight_recorder_snapshot(snapshot);
+    let mut temp_file_name = path.file_name().unwrap_or_default().to_owned();
+    temp_file_name.push(".live-tmp");
+    let temp_path = path.with_file_name(temp_file_name);
+    let format = ProfileFileFormat::from_path(path);
+    let result = save_profile_to_file(&profile, &temp_path, format)
+        .and_then(|()| std::fs::rename(&temp_path, path));
+    if let Err(err) = result {
+        eprintln!("Could not save live snapshot to {path:?}: {err}");
+    }
+}
+
+/// Converts the snapshots which are requested with SIGUSR2 and writes them on
+/// a separate thread. Unlike live snapshots, none of them are skipped, since
+/// each one goes to its own file.
+struct FlightRecorderSnapshotWriter {
+    snapshot_sender: Sender<(FlightRecorderSnapshot, PathBuf)>,
+    thread: JoinHandle<()>,
+}
 
-            if pending_lost_events > 0 {
-                // eprintln!("Pending lost events: {pending_lost_events}");
-                pending_lost_events = 0;
+impl FlightRecorderSnapshotWriter {
+    fn start() -> Self {
+        let (snapshot_sender, snapshot_receiver) =
+            crossbeam_channel::unbounded::<(FlightRecorderSnapshot, PathBuf)>();
+        let thread = thread::spawn(move || {
+            for (snapshot, path) in snapshot_receiver {
+                write_flight_recorder_snapshot(&snapshot, &path);
             }
         });
+        Self {
+            snapshot_sender,
+            thread,
+        }
+    }
 
-        perf.wait();
+    fn write_snapshot(&mut self, snapshot: FlightRecorderSnapshot, path: PathBuf) {
+        let _ = self.snapshot_sender.send((snapshot, path));
     }
 
-    if total_lost_events > 0 {
-        eprintln!("Lost {total_lost_events} events.");
+    /// Waits for the requested snapshots to be written.
+    fn finish(self) {
+        drop(self.snapshot_sender);
+        let _ = self.thread.join();
     }
+}
 
-    converter.finish()
+/// Converts the records of a flight recorder snapshot into a profile and
+/// saves it next to the regular output file.
+fn write_flight_recorder_snapshot(snapshot: &FlightRecorderSnapshot, snapshot_path: &Path) {
+    let profile = flight_recorder_snapshot(snapshot);
+    let format = ProfileFileFormat::from_path(snapshot_path);
+    match save_profile_to_file(&profile, snapshot_path, format) {
+        Ok(()) => eprintln!("Saved flight recorder snapshot to {snapshot_path:?}."),
+        Err(err) => {
+            eprintln!("Could not save flight recorder snapshot to {snapshot_path:?}: {err}")
+        }
+    }
+}
+
+fn replay_flight_recorder_records(
+    snapshot: &FlightRecorderSnapshot,
+    converter: &mut Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+    add_attached_process: bool,
+    mut alloc_tracking: Option<&mut AllocTracking>,
+) {
+    if let (true, Some(pid)) = (add_attached_process, snapshot.attached_pid) {
+        // The process's mappings from before recording started aren't part of the
+        // recorded records, so read them again from /proc. This fails if the
+        // process has exited, and then the snapshot is made without them.
+        let _ = add_existing_process(pid, converter);
+    }
+    let mut last_timestamp = 0;
+    snapshot.for_each_record(|record, exec_name_and_cmdline| {
+        if let Some(timestamp) = record.timestamp() {
+            last_timestamp = timestamp;
+        }
+        if let Some(alloc_tracking) = alloc_tracking.as_deref_mut() {
+            add_allocations_before_record(converter, alloc_tracking, record);
+        }
+        handle_record(converter, record, exec_name_and_cmdline, last_timestamp);
+    });
+}
+
+fn replay_spilled_records(
+    spill_writer: RecordSpillWriter,
+    converter: &mut Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+    mut alloc_tracking: Option<&mut AllocTracking>,
+) -> std::io::Result<()> {
+    let mut reader = spill_writer.into_reader()?;
+    let mut last_timestamp = 0;
+    while let Some((record, exec_name_and_cmdline)) = reader.next_record()? {
+        if let Some(timestamp) = record.timestamp() {
+            last_timestamp = timestamp;
+        }
+        if let Some(alloc_tracking) = alloc_tracking.as_deref_mut() {
+            add_allocations_before_record(converter, alloc_tracking, &record);
+        }
+        handle_record(converter, &record, exec_name_and_cmdline, last_timestamp);
+    }
+    Ok(())
+}
+
+/// Adds the sampled allocations which happened up to the time of `record`.
+///
+/// The allocation files are read again before a process exits or execs, when
+/// its file is complete. This makes sure that all of the process's allocations
+/// are added before the converter removes the process.
+fn add_allocations_before_record(
+    converter: &mut Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+    alloc_tracking: &mut AllocTracking,
+    record: &RawEventRecord,
+) {
+    let Some(timestamp) = record.timestamp() else {
+        return;
+    };
+    let ends_process = match record.record_type {
+        RecordType::EXIT => matches!(record.parse(), Ok(EventRecord::Exit(e)) if e.pid == e.tid),
+        RecordType::COMM => record.misc & PERF_RECORD_MISC_COMM_EXEC != 0,
+        _ => false,
+    };
+    if ends_process {
+        alloc_tracking.poll();
+    }
+    for allocation in alloc_tracking.take_records_until(timestamp) {
+        add_allocation(converter, allocation);
+    }
+}
+
+fn add_allocation(
+    converter: &mut Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+    record: AllocationRecord,
+) {
+    converter.add_allocation_sample(
+        record.pid as i32,
+        record.timestamp,
+        record.address,
+        record.size,
+        &record.stack,
+    );
+}
+
+fn handle_record(
+    converter: &mut Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+    record: &RawEventRecord,
+    exec_name_and_cmdline: Option<ExecNameAndCmdline>,
+    last_timestamp: u64,
+) {
+    let parsed_record = record.parse().unwrap();
+    // debug!("Recording parsed_record: {:#?}", parsed_record);
+
+    match parsed_record {
+        EventRecord::Sample(mut e) => {
+            // The LBR call stack isn't part of the parsed sample, so turn it
+            // into the user part of the sample's callchain.
+            let lbr_callchain;
+            if let Some(call_stack) = lbr::lbr_call_stack(record) {
+                lbr_callchain = lbr::callchain_with_lbr_user_stack(&e, &call_stack);
+                e.callchain = Some(RawDataU64::from_raw_data::<NativeEndian>(RawData::Single(
+                    &lbr_callchain,
+                )));
+            }
+            if let Some(probe_name) = probes::probe_name(&e) {
+                converter.handle_probe_sample::<ConvertRegsNative>(&e, &probe_name);
+                return;
+            }
+            match syscall_tracing::syscall_event(&e) {
+                Some(SyscallEvent::Enter) => converter.handle_syscall_enter_sample(&e),
+                Some(SyscallEvent::Exit) => {
+                    converter.handle_syscall_exit_sample::<ConvertRegsNative>(&e)
+                }
+                Some(SyscallEvent::Munmap) => converter.handle_munmap_sample(&e),
+                Some(SyscallEvent::SchedSwitch) => {
+                    converter.handle_sched_switch_sample::<ConvertRegsNative>(&e)
+                }
+                Some(SyscallEvent::SignalDeliver) => {
+                    converter.handle_signal_deliver_sample::<ConvertRegsNative>(&e)
+                }
+                // Page fault samples are the only ones which include an address.
+                None if e.addr.is_some() => {
+                    converter.handle_page_fault_sample::<ConvertRegsNative>(&e)
+                }
+                None => converter.handle_main_event_sample::<ConvertRegsNative>(&e),
+            }
+        }
+        EventRecord::Fork(e) => {
+            converter.handle_fork(e);
+        }
+        EventRecord::Comm(e) => {
+            if e.is_execve {
+                converter.handle_exec(e, record.timestamp(), exec_name_and_cmdline);
+            } else {
+                converter.handle_thread_rename(e, record.timestamp());
+            }
+        }
+        EventRecord::Exit(e) => {
+            converter.handle_exit(e);
+        }
+        EventRecord::Mmap(e) => {
+            converter.handle_mmap(e, last_timestamp);
+        }
+        EventRecord::Mmap2(e) => {
+            converter.handle_mmap2(e, last_timestamp);
+        }
+        EventRecord::ContextSwitch(e) => {
+            let common = match record.common_data() {
+                Ok(common) => common,
+                Err(_) => return,
+            };
+            converter.handle_context_switch(e, common);
+        }
+        _ => {}
+    }
 }
 
 pub fn read_string_lossy<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
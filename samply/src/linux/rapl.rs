@@ -0,0 +1,148 @@
+//! Energy counters from RAPL (Running Average Power Limit), which Intel and AMD
+//! CPUs expose through the powercap interface in /sys/class/powercap.
+
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::{fs, io};
+
+use crossbeam_channel::{Receiver, Sender};
+
+/// How often the energy counters are read.
+const SAMPLING_INTERVAL: Duration = Duration::from_millis(100);
+
+/// One RAPL domain, for example "package-0" or "package-0 core".
+struct RaplDomain {
+    name: String,
+    energy_path: PathBuf,
+    /// The energy counter wraps around at this value.
+    max_energy_range_uj: u64,
+    last_energy_uj: u64,
+}
+
+impl RaplDomain {
+    fn read_energy_delta_uj(&mut self) -> io::Result<u64> {
+        let energy_uj = read_u64(&self.energy_path)?;
+        let delta = if energy_uj >= self.last_energy_uj {
+            energy_uj - self.last_energy_uj
+        } else {
+            self.max_energy_range_uj - self.last_energy_uj + energy_uj
+        };
+        self.last_energy_uj = energy_uj;
+        Ok(delta)
+    }
+}
+
+/// The energy consumed by one RAPL domain during the recording.
+pub struct EnergySamples {
+    pub domain_name: String,
+    /// (CLOCK_MONOTONIC timestamp in nanoseconds, energy in microjoules consumed
+    /// since the previous sample)
+    pub samples: Vec<(u64, u64)>,
+}
+
+/// Reads the RAPL energy counters at a fixed interval on a separate thread,
+/// until it is stopped.
+pub struct PowerSampler {
+    stop_sender: Sender<()>,
+    thread: JoinHandle<Vec<EnergySamples>>,
+}
+
+impl PowerSampler {
+    /// Fails if there are no RAPL domains or if their counters can't be read.
+    /// Since Linux 5.10, reading them requires root.
+    pub fn start() -> io::Result<Self> {
+        let domains = find_rapl_domains()?;
+        if domains.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no RAPL domains found in /sys/class/powercap",
+            ));
+        }
+        let (stop_sender, stop_receiver) = crossbeam_channel::bounded(1);
+        let thread = thread::spawn(move || sample_domains(domains, stop_receiver));
+        Ok(PowerSampler {
+            stop_sender,
+            thread,
+        })
+    }
+
+    pub fn stop(self) -> Vec<EnergySamples> {
+        let _ = self.stop_sender.send(());
+        self.thread.join().unwrap_or_default()
+    }
+}
+
+fn sample_domains(mut domains: Vec<RaplDomain>, stop_receiver: Receiver<()>) -> Vec<EnergySamples> {
+    let mut energy_samples: Vec<EnergySamples> = domains
+        .iter()
+        .map(|domain| EnergySamples {
+            domain_name: domain.name.clone(),
+            samples: Vec::new(),
+        })
+        .collect();
+    while stop_receiver.recv_timeout(SAMPLING_INTERVAL).is_err() {
+        let timestamp = monotonic_nanos();
+        for (domain, energy_samples) in domains.iter_mut().zip(&mut energy_samples) {
+            if let Ok(delta) = domain.read_energy_delta_uj() {
+                energy_samples.samples.push((timestamp, delta));
+            }
+        }
+    }
+    energy_samples
+}
+
+fn find_rapl_domains() -> io::Result<Vec<RaplDomain>> {
+    let mut zone_dirs: Vec<PathBuf> = fs::read_dir("/sys/class/powercap")?
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("intel-rapl:")
+        })
+        .map(|entry| entry.path())
+        .collect();
+    zone_dirs.sort();
+
+    let mut domains = Vec::new();
+    for zone_dir in zone_dirs {
+        let name = read_trimmed(&zone_dir.join("name"))?;
+        // Subzones, e.g. intel-rapl:0:0, are named after their parent package.
+        let dir_name = zone_dir.file_name().unwrap().to_string_lossy().to_string();
+        let name = match dir_name.rsplit_once(':') {
+            Some((parent, _)) if parent != "intel-rapl" => {
+                let parent_name = read_trimmed(&zone_dir.with_file_name(parent).join("name"))?;
+                format!("{parent_name} {name}")
+            }
+            _ => name,
+        };
+        let energy_path = zone_dir.join("energy_uj");
+        let last_energy_uj = read_u64(&energy_path)?;
+        let max_energy_range_uj = read_u64(&zone_dir.join("max_energy_range_uj"))?;
+        domains.push(RaplDomain {
+            name,
+            energy_path,
+            max_energy_range_uj,
+            last_energy_uj,
+        });
+    }
+    Ok(domains)
+}
+
+fn read_trimmed(path: &Path) -> io::Result<String> {
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+fn read_u64(path: &Path) -> io::Result<u64> {
+    read_trimmed(path)?
+        .parse()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Returns the current time on the clock which the perf events use.
+pub fn monotonic_nanos() -> u64 {
+    let mut ts: libc::timespec = unsafe { std::mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use byteorder::{NativeEndian, ReadBytesExt, WriteBytesExt};
+use linux_perf_data::linux_perf_event_reader::{
+    RawData, RawEventRecord, RecordParseInfo, RecordType,
+};
+
+/// Writes raw perf event records to an anonymous temporary file, so that they
+/// can be converted into a profile once recording has finished.
+///
+/// During long recordings, the converter's in-memory state (samples, stacks,
+/// per-process bookkeeping) keeps growing. Spilling the raw records instead
+/// keeps the memory usage of samply flat while the profiled workload runs.
+///
+/// Records are stored in the order in which they were received, i.e. after
+/// sorting across ring buffers, so replaying them gives the converter exactly
+/// the same sequence of events as live conversion would have.
+pub struct RecordSpillWriter {
+    writer: BufWriter<File>,
+    parse_infos: Vec<RecordParseInfo>,
+    record_count: u64,
+}
+
+/// The process name and command line of an exec, captured at recording time.
+///
+/// These have to be read from /proc while the process is still alive, so they
+/// can't be looked up when the spilled records are replayed.
+pub type ExecNameAndCmdline = (String, Vec<String>);
+
+impl RecordSpillWriter {
+    pub fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(tempfile::tempfile()?),
+            parse_infos: Vec::new(),
+            record_count: 0,
+        })
+    }
+
+    pub fn write_record(
+        &mut self,
+        record: &RawEventRecord,
+        exec_name_and_cmdline: Option<&ExecNameAndCmdline>,
+    ) -> std::io::Result<()> {
+        let parse_info_index = match self
+            .parse_infos
+            .iter()
+            .position(|p| *p == record.parse_info)
+        {
+            Some(index) => index,
+            None => {
+                self.parse_infos.push(record.parse_info);
+                self.parse_infos.len() - 1
+            }
+        };
+
+        let w = &mut self.writer;
+        w.write_u32::<NativeEndian>(record.record_type.0)?;
+        w.write_u16::<NativeEndian>(record.misc)?;
+        w.write_u32::<NativeEndian>(parse_info_index as u32)?;
+        w.write_u32::<NativeEndian>(record.data.len() as u32)?;
+        match record.data {
+            RawData::Single(data) => w.write_all(data)?,
+            RawData::Split(left, right) => {
+                w.write_all(left)?;
+                w.write_all(right)?;
+            }
+        }
+        match exec_name_and_cmdline {
+            Some((exec_name, cmdline)) => {
+                w.write_u8(1)?;
+                write_string(w, exec_name)?;
+                w.write_u32::<NativeEndian>(cmdline.len() as u32)?;
+                for arg in cmdline {
+                    write_string(w, arg)?;
+                }
+            }
+            None => w.write_u8(0)?,
+        }
+        self.record_count += 1;
+        Ok(())
+    }
+
+    /// The number of records written so far.
+    pub fn record_count(&self) -> u64 {
+        self.record_count
+    }
+
+    /// Finishes writing and rewinds the file so that the records can be read back.
+    pub fn into_reader(self) -> std::io::Result<RecordSpillReader> {
+        let mut file = self.writer.into_inner().map_err(|e| e.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(RecordSpillReader {
+            reader: BufReader::new(file),
+            parse_infos: self.parse_infos,
+            remaining_record_count: self.record_count,
+            data: Vec::new(),
+        })
+    }
+}
+
+/// Reads back the records written by a [`RecordSpillWriter`], in order.
+pub struct RecordSpillReader {
+    reader: BufReader<File>,
+    parse_infos: Vec<RecordParseInfo>,
+    remaining_record_count: u64,
+    data: Vec<u8>,
+}
+
+impl RecordSpillReader {
+    #[allow(clippy::type_complexity)]
+    pub fn next_record(
+        &mut self,
+    ) -> std::io::Result<Option<(RawEventRecord<'_>, Option<ExecNameAndCmdline>)>> {
+        if self.remaining_record_count == 0 {
+            return Ok(None);
+        }
+        self.remaining_record_count -= 1;
+
+        let r = &mut self.reader;
+        let record_type = RecordType(r.read_u32::<NativeEndian>()?);
+        let misc = r.read_u16::<NativeEndian>()?;
+        let parse_info_index = r.read_u32::<NativeEndian>()? as usize;
+        let data_len = r.read_u32::<NativeEndian>()? as usize;
+        self.data.resize(data_len, 0);
+        r.read_exact(&mut self.data)?;
+        let exec_name_and_cmdline = match r.read_u8()? {
+            0 => None,
+            _ => {
+                let exec_name = read_string(r)?;
+                let arg_count = r.read_u32::<NativeEndian>()?;
+                let cmdline = (0..arg_count)
+                    .map(|_| read_string(r))
+                    .collect::<std::io::Result<Vec<_>>>()?;
+                Some((exec_name, cmdline))
+            }
+        };
+
+        let parse_info = *self.parse_infos.get(parse_info_index).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Invalid parse info index in spilled record",
+            )
+        })?;
+        let record =
+            RawEventRecord::new(record_type, misc, RawData::Single(&self.data), parse_info);
+        Ok(Some((record, exec_name_and_cmdline)))
+    }
+}
+
+fn write_string(w: &mut impl Write, s: &str) -> std::io::Result<()> {
+    w.write_u32::<NativeEndian>(s.len() as u32)?;
+    w.write_all(s.as_bytes())
+}
+
+fn read_string(r: &mut impl Read) -> std::io::Result<String> {
+    let len = r.read_u32::<NativeEndian>()? as usize;
+    let mut bytes = vec![0; len];
+    r.read_exact(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
@@ -18,6 +18,7 @@ macro_rules! flag {
     };
 }
 
+pub const PERF_FLAG_PID_CGROUP: c_ulong = 1 << 2;
 pub const PERF_FLAG_FD_CLOEXEC: c_ulong = 1 << 3;
 
 pub const PERF_TYPE_HARDWARE: u32 = 0;
@@ -39,6 +40,8 @@ pub const PERF_ATTR_FLAG_INHERIT_STAT: u64 = flag!(11);
 pub const PERF_ATTR_FLAG_ENABLE_ON_EXEC: u64 = flag!(12);
 pub const PERF_ATTR_FLAG_TASK: u64 = flag!(13);
 pub const PERF_ATTR_FLAG_WATERMARK: u64 = flag!(14);
+/// precise_ip is a two-bit field at bits 15 and 16.
+pub const PERF_ATTR_PRECISE_IP_SHIFT: u64 = 15;
 pub const PERF_ATTR_FLAG_MMAP_DATA: u64 = flag!(17);
 pub const PERF_ATTR_FLAG_SAMPLE_ID_ALL: u64 = flag!(18);
 pub const PERF_ATTR_FLAG_EXCLUDE_HOST: u64 = flag!(19);
@@ -56,6 +59,7 @@ pub const PERF_COUNT_HW_REF_CPU_CYCLES: u64 = 9;
 pub const PERF_COUNT_SW_CPU_CLOCK: u64 = 0;
 pub const PERF_COUNT_SW_TASK_CLOCK: u64 = 1;
 pub const PERF_COUNT_SW_PAGE_FAULTS: u64 = 2;
+pub const PERF_COUNT_SW_PAGE_FAULTS_MAJ: u64 = 6;
 pub const PERF_COUNT_SW_DUMMY: u64 = 9;
 
 pub const PERF_RECORD_LOST: u32 = 2;
@@ -91,6 +95,9 @@ pub const PERF_SAMPLE_IDENTIFIER: u64 = 1 << 16;
 pub const PERF_SAMPLE_TRANSACTION: u64 = 1 << 17;
 pub const PERF_SAMPLE_REGS_INTR: u64 = 1 << 18;
 
+pub const PERF_SAMPLE_BRANCH_USER: u64 = 1 << 0;
+pub const PERF_SAMPLE_BRANCH_CALL_STACK: u64 = 1 << 11;
+
 pub const PERF_REG_X86_AX: u64 = 0;
 pub const PERF_REG_X86_BX: u64 = 1;
 pub const PERF_REG_X86_CX: u64 = 2;
@@ -222,6 +229,8 @@ mod ioctl {
         pub const IOC_SIZEBITS: c_ulong = 14;
         pub const IOC_DIRBITS: c_ulong = 2;
         pub const IOC_NONE: c_ulong = 0;
+        pub const IOC_WRITE: c_ulong = 1;
+        pub const IOC_READ: c_ulong = 2;
     }
 
     #[cfg(any(
@@ -236,6 +245,8 @@ mod ioctl {
         pub const IOC_SIZEBITS: c_ulong = 13;
         pub const IOC_DIRBITS: c_ulong = 3;
         pub const IOC_NONE: c_ulong = 1;
+        pub const IOC_WRITE: c_ulong = 4;
+        pub const IOC_READ: c_ulong = 2;
     }
 
     pub use self::arch::*;
@@ -263,8 +274,26 @@ macro_rules! io {
     };
 }
 
+macro_rules! iow {
+    ($kind:expr, $nr:expr, $size:expr) => {
+        ioc!(ioctl::IOC_WRITE, $kind, $nr, $size)
+    };
+}
+
+macro_rules! ior {
+    ($kind:expr, $nr:expr, $size:expr) => {
+        ioc!(ioctl::IOC_READ, $kind, $nr, $size)
+    };
+}
+
 pub const PERF_EVENT_IOC_ENABLE: c_ulong = io!(b'$', 0);
 pub const PERF_EVENT_IOC_DISABLE: c_ulong = io!(b'$', 1);
+pub const PERF_EVENT_IOC_SET_FILTER: c_ulong = iow!(
+    b'$',
+    6,
+    std::mem::size_of::<*const libc::c_char>() as c_ulong
+);
+pub const PERF_EVENT_IOC_ID: c_ulong = ior!(b'$', 7, std::mem::size_of::<*mut u64>() as c_ulong);
 
 #[repr(C)]
 pub struct PerfEventAttr {
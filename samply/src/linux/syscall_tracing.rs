@@ -0,0 +1,162 @@
+//! Tracing of syscalls with the raw_syscalls:sys_enter and raw_syscalls:sys_exit
+//! tracepoints, for `--syscalls`, and of munmap calls with the
+//! syscalls:sys_enter_munmap tracepoint, for ending library mappings. This also
+//! identifies the samples of the sched:sched_switch tracepoint, which give us
+//! the stacks of threads going off-CPU for `--wall-clock`, and of the
+//! signal:signal_deliver tracepoint, which gives us the stack of a thread
+//! which crashed.
+
+use std::sync::OnceLock;
+use std::{fs, io};
+
+use linux_perf_data::linux_perf_event_reader::{Endianness, SampleRecord};
+
+use crate::linux_shared::signal_deliver::CRASH_SIGNALS;
+use crate::linux_shared::syscalls::{syscall_id, RawSyscall, DEFAULT_TRACED_SYSCALLS};
+
+/// The ids of the sys_enter and sys_exit tracepoints, once they've been looked
+/// up. Samples of the tracepoints are identified by these ids.
+static TRACEPOINT_IDS: OnceLock<(u64, u64)> = OnceLock::new();
+
+/// The id of the sys_enter_munmap tracepoint, or `None` if it's not available.
+static MUNMAP_TRACEPOINT_ID: OnceLock<Option<u64>> = OnceLock::new();
+
+/// The id of the sched_switch tracepoint, once it's been looked up.
+static SCHED_SWITCH_TRACEPOINT_ID: OnceLock<u64> = OnceLock::new();
+
+/// The id of the signal_deliver tracepoint, or `None` if it's not available.
+static SIGNAL_DELIVER_TRACEPOINT_ID: OnceLock<Option<u64>> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+pub struct SyscallTracepoints {
+    pub enter_id: u64,
+    pub exit_id: u64,
+    /// The tracepoint filter which limits tracing to the requested syscalls,
+    /// or `None` to trace all syscalls.
+    pub filter: Option<String>,
+}
+
+impl SyscallTracepoints {
+    /// Looks up the tracepoints in tracefs. An empty list of syscall names
+    /// selects [`DEFAULT_TRACED_SYSCALLS`], and "all" traces every syscall.
+    pub fn new(syscall_names: &[String], syscall_table: &[(u64, &str)]) -> io::Result<Self> {
+        let enter_id = tracepoint_id("raw_syscalls/sys_enter")?;
+        let exit_id = tracepoint_id("raw_syscalls/sys_exit")?;
+        let _ = TRACEPOINT_IDS.set((enter_id, exit_id));
+
+        let filter = if syscall_names.iter().any(|name| name == "all") {
+            None
+        } else if syscall_names.is_empty() {
+            let ids: Vec<u64> = DEFAULT_TRACED_SYSCALLS
+                .iter()
+                .filter_map(|name| syscall_id(syscall_table, name))
+                .collect();
+            Some(filter_for_ids(&ids))
+        } else {
+            let mut ids = Vec::new();
+            for name in syscall_names {
+                match (syscall_id(syscall_table, name), name.parse::<u64>()) {
+                    (Some(id), _) | (None, Ok(id)) => ids.push(id),
+                    (None, Err(_)) => eprintln!("Unknown syscall {name}, it will not be traced."),
+                }
+            }
+            if ids.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "none of the requested syscalls are known",
+                ));
+            }
+            Some(filter_for_ids(&ids))
+        };
+
+        Ok(SyscallTracepoints {
+            enter_id,
+            exit_id,
+            filter,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallEvent {
+    Enter,
+    Exit,
+    Munmap,
+    SchedSwitch,
+    SignalDeliver,
+}
+
+/// Returns which of the syscall tracepoints this sample is from, if any.
+pub fn syscall_event(e: &SampleRecord) -> Option<SyscallEvent> {
+    let syscall = RawSyscall::parse(e.raw?, Endianness::NATIVE).ok()?;
+    let id = u64::from(syscall.common_type);
+    match TRACEPOINT_IDS.get() {
+        Some(&(enter_id, _)) if id == enter_id => return Some(SyscallEvent::Enter),
+        Some(&(_, exit_id)) if id == exit_id => return Some(SyscallEvent::Exit),
+        _ => {}
+    }
+    match MUNMAP_TRACEPOINT_ID.get() {
+        Some(&Some(munmap_id)) if id == munmap_id => return Some(SyscallEvent::Munmap),
+        _ => {}
+    }
+    match SIGNAL_DELIVER_TRACEPOINT_ID.get() {
+        Some(&Some(signal_deliver_id)) if id == signal_deliver_id => {
+            return Some(SyscallEvent::SignalDeliver)
+        }
+        _ => {}
+    }
+    match SCHED_SWITCH_TRACEPOINT_ID.get() {
+        Some(&sched_switch_id) if id == sched_switch_id => Some(SyscallEvent::SchedSwitch),
+        _ => None,
+    }
+}
+
+/// Looks up the id of the sys_enter_munmap tracepoint. Reading tracefs usually
+/// requires root, so this returns `None` for most unprivileged recordings.
+pub fn munmap_tracepoint_id() -> Option<u64> {
+    *MUNMAP_TRACEPOINT_ID.get_or_init(|| tracepoint_id("syscalls/sys_enter_munmap").ok())
+}
+
+/// Looks up the id of the signal_deliver tracepoint. Like for munmap, this
+/// returns `None` if tracefs can't be read.
+pub fn signal_deliver_tracepoint_id() -> Option<u64> {
+    *SIGNAL_DELIVER_TRACEPOINT_ID.get_or_init(|| tracepoint_id("signal/signal_deliver").ok())
+}
+
+/// The tracepoint filter which limits signal_deliver samples to crash signals
+/// which are delivered without a handler, i.e. which terminate the process.
+pub fn crash_signal_filter() -> String {
+    let conditions: Vec<String> = CRASH_SIGNALS
+        .iter()
+        .map(|(sig, _)| format!("sig == {sig}"))
+        .collect();
+    format!("sa_handler == 0 && ({})", conditions.join(" || "))
+}
+
+/// Looks up the id of the sched_switch tracepoint, for `--wall-clock`.
+pub fn sched_switch_tracepoint_id() -> io::Result<u64> {
+    let id = tracepoint_id("sched/sched_switch")?;
+    let _ = SCHED_SWITCH_TRACEPOINT_ID.set(id);
+    Ok(id)
+}
+
+fn filter_for_ids(ids: &[u64]) -> String {
+    let conditions: Vec<String> = ids.iter().map(|id| format!("id == {id}")).collect();
+    conditions.join(" || ")
+}
+
+fn tracepoint_id(name: &str) -> io::Result<u64> {
+    let mut last_err = None;
+    for tracefs in ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"] {
+        match fs::read_to_string(format!("{tracefs}/events/{name}/id")) {
+            Ok(id) => {
+                return id
+                    .trim()
+                    .parse()
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap())
+}
@@ -2,17 +2,46 @@ use framehop::aarch64::UnwindRegsAarch64;
 use framehop::x86_64::UnwindRegsX86_64;
 use linux_perf_data::linux_perf_event_reader;
 use linux_perf_event_reader::constants::{
-    PERF_REG_ARM64_LR, PERF_REG_ARM64_PC, PERF_REG_ARM64_SP, PERF_REG_ARM64_X29, PERF_REG_X86_BP,
-    PERF_REG_X86_IP, PERF_REG_X86_SP,
+    PERF_REG_ARM64_LR, PERF_REG_ARM64_PC, PERF_REG_ARM64_SP, PERF_REG_ARM64_X0, PERF_REG_ARM64_X29,
+    PERF_REG_X86_AX, PERF_REG_X86_BP, PERF_REG_X86_BX, PERF_REG_X86_CX, PERF_REG_X86_DI,
+    PERF_REG_X86_DX, PERF_REG_X86_FLAGS, PERF_REG_X86_IP, PERF_REG_X86_R10, PERF_REG_X86_R11,
+    PERF_REG_X86_R12, PERF_REG_X86_R13, PERF_REG_X86_R14, PERF_REG_X86_R15, PERF_REG_X86_R8,
+    PERF_REG_X86_R9, PERF_REG_X86_SI, PERF_REG_X86_SP,
 };
 use linux_perf_event_reader::Regs;
 
+use super::syscalls::{SYSCALLS_AARCH64, SYSCALLS_X86_64};
+
 pub trait ConvertRegs {
     type UnwindRegs;
     fn convert_regs(regs: &Regs) -> (u64, u64, Self::UnwindRegs);
 
     #[allow(unused)]
     fn regs_mask() -> u64;
+
+    /// The syscall numbers and names of this architecture.
+    fn syscall_table() -> &'static [(u64, &'static str)];
+
+    /// The perf register numbers and names of this architecture's general
+    /// purpose registers, in the order in which they're displayed.
+    fn register_names() -> &'static [(u64, &'static str)];
+
+    /// The mask for sampling all registers from `register_names`.
+    fn all_regs_mask() -> u64 {
+        Self::register_names()
+            .iter()
+            .fold(0, |mask, (register, _)| mask | (1 << register))
+    }
+
+    /// Formats the sampled registers as "name=0x..." pairs. Registers which
+    /// weren't sampled are left out.
+    fn format_regs(regs: &Regs) -> String {
+        let values: Vec<String> = Self::register_names()
+            .iter()
+            .filter_map(|(register, name)| Some(format!("{name}={:#x}", regs.get(*register)?)))
+            .collect();
+        values.join(" ")
+    }
 }
 
 pub struct ConvertRegsX86_64;
@@ -29,6 +58,33 @@ impl ConvertRegs for ConvertRegsX86_64 {
     fn regs_mask() -> u64 {
         (1 << PERF_REG_X86_IP) | (1 << PERF_REG_X86_SP) | (1 << PERF_REG_X86_BP)
     }
+
+    fn syscall_table() -> &'static [(u64, &'static str)] {
+        SYSCALLS_X86_64
+    }
+
+    fn register_names() -> &'static [(u64, &'static str)] {
+        &[
+            (PERF_REG_X86_IP, "rip"),
+            (PERF_REG_X86_SP, "rsp"),
+            (PERF_REG_X86_BP, "rbp"),
+            (PERF_REG_X86_AX, "rax"),
+            (PERF_REG_X86_BX, "rbx"),
+            (PERF_REG_X86_CX, "rcx"),
+            (PERF_REG_X86_DX, "rdx"),
+            (PERF_REG_X86_SI, "rsi"),
+            (PERF_REG_X86_DI, "rdi"),
+            (PERF_REG_X86_R8, "r8"),
+            (PERF_REG_X86_R9, "r9"),
+            (PERF_REG_X86_R10, "r10"),
+            (PERF_REG_X86_R11, "r11"),
+            (PERF_REG_X86_R12, "r12"),
+            (PERF_REG_X86_R13, "r13"),
+            (PERF_REG_X86_R14, "r14"),
+            (PERF_REG_X86_R15, "r15"),
+            (PERF_REG_X86_FLAGS, "rflags"),
+        ]
+    }
 }
 
 pub struct ConvertRegsAarch64;
@@ -49,4 +105,73 @@ impl ConvertRegs for ConvertRegsAarch64 {
             | (1 << PERF_REG_ARM64_SP)
             | (1 << PERF_REG_ARM64_X29)
     }
+
+    fn syscall_table() -> &'static [(u64, &'static str)] {
+        SYSCALLS_AARCH64
+    }
+
+    fn register_names() -> &'static [(u64, &'static str)] {
+        &[
+            (PERF_REG_ARM64_PC, "pc"),
+            (PERF_REG_ARM64_SP, "sp"),
+            (PERF_REG_ARM64_LR, "lr"),
+            (PERF_REG_ARM64_X29, "fp"),
+            // x0 to x28 are the perf registers 0 to 28.
+            (PERF_REG_ARM64_X0, "x0"),
+            (1, "x1"),
+            (2, "x2"),
+            (3, "x3"),
+            (4, "x4"),
+            (5, "x5"),
+            (6, "x6"),
+            (7, "x7"),
+            (8, "x8"),
+            (9, "x9"),
+            (10, "x10"),
+            (11, "x11"),
+            (12, "x12"),
+            (13, "x13"),
+            (14, "x14"),
+            (15, "x15"),
+            (16, "x16"),
+            (17, "x17"),
+            (18, "x18"),
+            (19, "x19"),
+            (20, "x20"),
+            (21, "x21"),
+            (22, "x22"),
+            (23, "x23"),
+            (24, "x24"),
+            (25, "x25"),
+            (26, "x26"),
+            (27, "x27"),
+            (28, "x28"),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::LittleEndian;
+    use linux_perf_event_reader::{RawData, RawDataU64};
+
+    use super::*;
+
+    #[test]
+    fn format_sampled_regs() {
+        let mask = ConvertRegsX86_64::regs_mask() | (1 << PERF_REG_X86_AX);
+        // The values are ordered by register number: ax, bp, sp, ip.
+        let values: Vec<u8> = [0x2a_u64, 0x7ffd_1000, 0x7ffd_0f00, 0x5555_1234]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let raw = RawDataU64::from_raw_data::<LittleEndian>(RawData::Single(&values));
+        let regs = Regs::new(mask, raw);
+        assert_eq!(
+            ConvertRegsX86_64::format_regs(&regs),
+            "rip=0x55551234 rsp=0x7ffd0f00 rbp=0x7ffd1000 rax=0x2a"
+        );
+        assert_eq!(ConvertRegsX86_64::all_regs_mask().count_ones(), 18);
+        assert_eq!(ConvertRegsAarch64::all_regs_mask(), (1 << 33) - 1);
+    }
 }
@@ -7,10 +7,10 @@ use byteorder::LittleEndian;
 use debugid::DebugId;
 use framehop::{ExplicitModuleSectionInfo, FrameAddress, Module, Unwinder};
 use fxprof_processed_profile::{
-    Category, CategoryColor, CategoryHandle, CpuDelta, FrameFlags, LibraryHandle, LibraryInfo,
-    Marker, MarkerField, MarkerTiming, PlatformSpecificReferenceTimestamp, Profile,
-    ReferenceTimestamp, SamplingInterval, Schema, StringHandle, SubcategoryHandle, SymbolTable,
-    ThreadHandle,
+    Category, CategoryColor, CategoryHandle, CpuDelta, FrameFlags, FrameHandle, LibraryBuildInfo,
+    LibraryHandle, LibraryInfo, Marker, MarkerField, MarkerNumberFieldFormat, MarkerTiming,
+    PlatformSpecificReferenceTimestamp, Profile, ReferenceTimestamp, SamplingInterval, Schema,
+    StringHandle, SubcategoryHandle, SymbolTable, ThreadHandle,
 };
 use linux_perf_data::linux_perf_event_reader::TaskWasPreempted;
 use linux_perf_data::simpleperf_dso_type::{DSO_DEX_FILE, DSO_KERNEL, DSO_KERNEL_MODULE};
@@ -32,25 +32,31 @@ use wholesym::{CodeId, ElfBuildId};
 
 use super::avma_range::AvmaRange;
 use super::convert_regs::ConvertRegs;
-use super::event_interpretation::{EventInterpretation, OffCpuIndicator};
+use super::event_interpretation::{EventInterpretation, OffCpuIndicator, SampleCpuTime};
 use super::injected_jit_object::{correct_bad_perf_jit_so_file, jit_function_name};
 use super::kernel_symbols::{kernel_module_build_id, KernelSymbols};
+use super::lost_events::LostEventsRange;
 use super::mmap_range_or_vec::MmapRangeOrVec;
+use super::mount_namespace;
 use super::pe_mappings::{PeMappings, SuspectedPeMapping};
 use super::processes::Processes;
 use super::rss_stat::{RssStat, MM_ANONPAGES, MM_FILEPAGES, MM_SHMEMPAGES, MM_SWAPENTS};
+use super::sched_switch::{OffCpuState, SchedSwitch};
+use super::signal_deliver::{crash_signal_name, SignalDeliver};
 use super::svma_file_range::compute_vma_bias;
+use super::syscalls::{syscall_name, MunmapArgs, RawSyscall};
 use super::vdso::VdsoObject;
 use crate::shared::context_switch::{ContextSwitchHandler, OffCpuSampleGroup};
 use crate::shared::jit_category_manager::JitCategoryManager;
 use crate::shared::lib_mappings::{AndroidArtInfo, LibMappingInfo};
-use crate::shared::per_cpu::Cpus;
+use crate::shared::per_cpu::{CoreType, CoreTypeThreads, CpuMigrationMarker, Cpus};
 use crate::shared::process_name::make_process_name;
 use crate::shared::process_sample_data::{
-    OtherEventMarker, RssStatMarker, RssStatMember, SchedSwitchMarkerOnCpuTrack,
-    SchedSwitchMarkerOnThreadTrack,
+    CrashMarker, IterationMarker, LostEventsMarker, OtherEventMarker, OutputLineMarker,
+    PageFaultMarker, ProbeMarker, RssStatMarker, RssStatMember, SchedSwitchMarkerOnCpuTrack,
+    SchedSwitchMarkerOnThreadTrack, SyscallMarker,
 };
-use crate::shared::prop_types::ProfileCreationProps;
+use crate::shared::prop_types::{OnlyLibFilter, ProfileCreationProps};
 use crate::shared::synthetic_jit_library::SyntheticJitLibrary;
 use crate::shared::timestamp_converter::TimestampConverter;
 use crate::shared::types::{StackFrame, StackMode};
@@ -79,6 +85,7 @@ where
     unresolved_stacks: UnresolvedStacks,
     off_cpu_weight_per_sample: i32,
     off_cpu_indicator: Option<OffCpuIndicator>,
+    sample_cpu_time: SampleCpuTime,
     event_names: Vec<String>,
     kernel_symbols: Option<KernelSymbols>,
     kernel_image_mapping: Option<KernelImageMapping>,
@@ -86,13 +93,19 @@ where
     pe_mappings: PeMappings,
     jit_category_manager: JitCategoryManager,
     arg_count_to_include_in_process_name: usize,
+    redact_env: bool,
     cpus: Option<Cpus>,
+    core_type_threads: Option<CoreTypeThreads>,
     stack_scratch: Vec<StackFrame>,
 
     /// Whether repeated frames at the base of the stack should be folded
     /// into one frame.
     fold_recursive_prefix: bool,
 
+    /// Samples whose leaf address is outside this library or address range
+    /// are dropped.
+    only_lib: Option<OnlyLibFilter>,
+
     /// Determines how the addresses in sample call chains should be interpreted.
     /// Any addresses after the first frame address are either "return addresses"
     /// (i.e. they are the address of the instruction *after* the call instruction),
@@ -110,6 +123,14 @@ where
 
     /// Whether to emit context switch markers.
     should_emit_cswitch_markers: bool,
+
+    /// Whether the samples which fill a thread's off-CPU time get a root frame
+    /// which says whether the thread was blocked or runnable (`--wall-clock`).
+    annotate_off_cpu_states: bool,
+
+    /// Whether the binaries of live processes in other mount namespaces, e.g.
+    /// in containers, are opened through /proc/<pid>/root.
+    find_binaries_in_process_root: bool,
 }
 
 struct SimpleperfConverterData {
@@ -152,6 +173,7 @@ where
             None => SamplingInterval::from_millis(1),
         };
         let mut profile = Profile::new(profile_name, reference_timestamp, interval);
+        profile_creation_props.apply_thread_name_filter(&mut profile);
         if let Some(linux_version) = linux_version {
             profile.set_os_name(&format!("Linux {linux_version}"));
         }
@@ -203,8 +225,9 @@ where
             aux_file_lookup_dirs,
             off_cpu_weight_per_sample,
             context_switch_handler: ContextSwitchHandler::new(off_cpu_sampling_interval_ns),
-            unresolved_stacks: UnresolvedStacks::default(),
+            unresolved_stacks: UnresolvedStacks::new(profile_creation_props.max_stack_depth),
             off_cpu_indicator: interpretation.off_cpu_indicator,
+            sample_cpu_time: interpretation.sample_cpu_time,
             event_names: interpretation.event_names,
             kernel_symbols,
             kernel_image_mapping: None,
@@ -212,13 +235,18 @@ where
             pe_mappings: PeMappings::new(),
             jit_category_manager: JitCategoryManager::new(),
             fold_recursive_prefix: profile_creation_props.fold_recursive_prefix,
+            only_lib: profile_creation_props.only_lib.clone(),
             arg_count_to_include_in_process_name: profile_creation_props
                 .arg_count_to_include_in_process_name,
+            redact_env: profile_creation_props.redact_env,
             cpus,
+            core_type_threads: None,
             stack_scratch: Vec::new(),
             call_chain_return_addresses_are_preadjusted,
             should_emit_jit_markers: profile_creation_props.should_emit_jit_markers,
             should_emit_cswitch_markers: profile_creation_props.should_emit_cswitch_markers,
+            annotate_off_cpu_states: false,
+            find_binaries_in_process_root: false,
         }
     }
 
@@ -244,6 +272,168 @@ where
         self.profile.set_os_name(os_name);
     }
 
+    /// Adds an entry about how the profile was recorded to the profile's info.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn add_recording_info(&mut self, label: &str, value: &str) {
+        self.profile
+            .add_extra_info_string("Recording", label, value);
+    }
+
+    /// Makes the samples which fill a thread's off-CPU time start with a frame
+    /// for the thread's state, e.g. "Blocked" or "Runnable".
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn set_annotate_off_cpu_states(&mut self, annotate_off_cpu_states: bool) {
+        self.annotate_off_cpu_states = annotate_off_cpu_states;
+    }
+
+    /// Adds a thread for each core type, with the samples which ran on cores of
+    /// that type. `cpu_core_types` is indexed by CPU number.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn set_core_types(&mut self, cpu_core_types: Vec<Option<CoreType>>) {
+        let start_time = self
+            .timestamp_converter
+            .convert_time(self.timestamp_converter.reference_raw);
+        self.core_type_threads = Some(CoreTypeThreads::new(
+            cpu_core_types,
+            start_time,
+            &mut self.profile,
+        ));
+    }
+
+    /// Makes the converter open the binaries of processes in other mount
+    /// namespaces through their /proc/<pid>/root, so that binaries in
+    /// containers are found.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn set_find_binaries_in_process_root(&mut self, find_binaries_in_process_root: bool) {
+        self.find_binaries_in_process_root = find_binaries_in_process_root;
+    }
+
+    /// Adds a counter to the process with the given pid. The samples are
+    /// (timestamp, value delta) pairs, with timestamps in the same clock as the
+    /// perf records.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn add_process_counter(
+        &mut self,
+        pid: i32,
+        name: &str,
+        category: &str,
+        description: &str,
+        samples: impl IntoIterator<Item = (u64, f64)>,
+    ) {
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let counter =
+            self.profile
+                .add_counter(process.profile_process, name, category, description);
+        for (timestamp, value_delta) in samples {
+            let timestamp = self.timestamp_converter.convert_time(timestamp);
+            self.profile
+                .add_counter_sample(counter, timestamp, value_delta, 1);
+        }
+    }
+
+    /// Marks the time between two timestamps, in the same clock as the perf
+    /// records, as paused.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn add_paused_range(&mut self, start: u64, end: u64) {
+        let start = self.timestamp_converter.convert_time(start);
+        let end = self.timestamp_converter.convert_time(end);
+        self.profile.add_paused_range(start, end);
+    }
+
+    /// Adds a marker for each range in which the kernel dropped events, on the
+    /// main thread of every live process, and adds the total number of lost
+    /// events to the profile's info.
+    pub fn add_lost_events(&mut self, ranges: &[LostEventsRange]) {
+        if ranges.is_empty() {
+            return;
+        }
+        let main_threads: Vec<ThreadHandle> = self.processes.main_threads().collect();
+        for range in ranges {
+            let timing = MarkerTiming::Interval(
+                self.timestamp_converter.convert_time(range.start),
+                self.timestamp_converter.convert_time(range.end),
+            );
+            for &thread_handle in &main_threads {
+                self.profile.add_marker(
+                    thread_handle,
+                    timing.clone(),
+                    LostEventsMarker { count: range.count },
+                );
+            }
+        }
+        let total_count: u64 = ranges.iter().map(|range| range.count).sum();
+        self.profile.add_extra_info_number(
+            "Recording",
+            "Lost events",
+            MarkerNumberFieldFormat::Integer,
+            total_count as f64,
+        );
+    }
+
+    /// Adds an interval marker on the process's main thread for one run of the
+    /// launched command, with timestamps in the same clock as the perf records.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn add_iteration_marker(&mut self, pid: i32, iteration: u32, start: u64, end: u64) {
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let thread_handle = process.threads.main_thread.profile_thread;
+        let timing = MarkerTiming::Interval(
+            self.timestamp_converter.convert_time(start),
+            self.timestamp_converter.convert_time(end),
+        );
+        self.profile
+            .add_marker(thread_handle, timing, IterationMarker { iteration });
+    }
+
+    /// Adds an instant marker on the process's main thread for a line of output
+    /// which the process printed.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn add_output_line_marker(&mut self, pid: i32, timestamp: u64, stream: &str, line: &str) {
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let thread_handle = process.threads.main_thread.profile_thread;
+        let timestamp = self.timestamp_converter.convert_time(timestamp);
+        let stream = self.profile.handle_for_string(stream);
+        let line = self.profile.handle_for_string(line);
+        self.profile.add_marker(
+            thread_handle,
+            MarkerTiming::Instant(timestamp),
+            OutputLineMarker { stream, line },
+        );
+    }
+
+    /// Adds a sampled allocation, or the free of a sampled allocation, to the
+    /// process with the given pid, and updates its live memory counter. `stack`
+    /// contains return addresses, starting with the callee-most one.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn add_allocation_sample(
+        &mut self,
+        pid: i32,
+        timestamp_mono: u64,
+        address: u64,
+        size: i64,
+        stack: &[u64],
+    ) {
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let unresolved_stack = self.unresolved_stacks.convert(
+            stack
+                .iter()
+                .rev()
+                .map(|&address| StackFrame::ReturnAddress(address, StackMode::User)),
+        );
+        process.unresolved_samples.add_allocation_sample(
+            process.profile_process,
+            process.threads.main_thread.profile_thread,
+            timestamp,
+            timestamp_mono,
+            unresolved_stack,
+            address,
+            size,
+        );
+        let (counter, delta) = process.update_sampled_live_memory(&mut self.profile, size);
+        self.profile
+            .add_counter_sample(counter, timestamp, delta as f64, 1);
+    }
+
     pub fn handle_main_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
         e: &SampleRecord,
@@ -268,6 +458,29 @@ where
             &self.timestamp_converter,
         );
 
+        if let (Some(only_lib), Some(ip)) = (&self.only_lib, e.ip) {
+            let is_in_lib = match only_lib {
+                OnlyLibFilter::Lib(_) => process
+                    .only_lib_ranges
+                    .iter()
+                    .any(|range| range.contains(&ip)),
+                OnlyLibFilter::AddressRange(start, end) => (*start..*end).contains(&ip),
+            };
+            if !is_in_lib {
+                return;
+            }
+        }
+
+        // Don't unwind the samples of threads which were filtered out with
+        // --threads, unless the samples are also shown in per-CPU tracks.
+        let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+        if self.profile.thread_is_removed(thread.profile_thread)
+            && self.cpus.is_none()
+            && self.core_type_threads.is_none()
+        {
+            return;
+        }
+
         let stack = &mut self.stack_scratch;
         Self::get_sample_stack::<C>(
             e,
@@ -288,16 +501,33 @@ where
         thread.last_sample_timestamp = Some(timestamp);
         let thread_handle = thread.profile_thread;
 
+        if let (Some(cpu), true) = (e.cpu, self.cpus.is_some()) {
+            if let Some(previous_cpu) = thread.last_sample_cpu.filter(|c| *c != cpu) {
+                self.profile.add_marker(
+                    thread_handle,
+                    MarkerTiming::Instant(profile_timestamp),
+                    CpuMigrationMarker {
+                        from_cpu: previous_cpu,
+                        to_cpu: cpu,
+                    },
+                );
+            }
+            thread.last_sample_cpu = Some(cpu);
+        }
+
         // Consume off-cpu time and clear any saved off-CPU stack.
         let off_cpu_sample = self
             .context_switch_handler
             .handle_on_cpu_sample(timestamp, &mut thread.context_switch_data);
+        let off_cpu_state = thread.off_cpu_state.take();
         if let (Some(off_cpu_sample), Some(off_cpu_stack)) =
             (off_cpu_sample, thread.off_cpu_stack.take())
         {
             let cpu_delta_ns = self
                 .context_switch_handler
                 .consume_cpu_delta(&mut thread.context_switch_data);
+            let off_cpu_state_frame = off_cpu_state
+                .map(|state| off_cpu_state_frame(&mut self.profile, thread_handle, state));
             process_off_cpu_sample_group(
                 off_cpu_sample,
                 thread_handle,
@@ -305,6 +535,7 @@ where
                 &self.timestamp_converter,
                 self.off_cpu_weight_per_sample,
                 off_cpu_stack,
+                off_cpu_state_frame,
                 &mut process.unresolved_samples,
             );
         }
@@ -314,12 +545,8 @@ where
                 self.context_switch_handler
                     .consume_cpu_delta(&mut thread.context_switch_data),
             )
-        } else if let Some(period) = e.period {
-            // If the observed perf event is one of the clock time events, or cycles, then we should convert it to a CpuDelta.
-            // TODO: Detect event type
-            CpuDelta::from_nanos(period)
         } else {
-            CpuDelta::from_nanos(0)
+            Self::sample_cpu_delta(self.sample_cpu_time, e)
         };
 
         let stack_index = self.unresolved_stacks.convert(stack.iter().rev().cloned());
@@ -333,6 +560,27 @@ where
             None,
         );
 
+        if let (Some(cpu), Some(core_type_threads)) = (e.cpu, &mut self.core_type_threads) {
+            if let Some(core_type_thread) = core_type_threads.thread_for_cpu(cpu, &mut self.profile)
+            {
+                let label_frame = self.profile.handle_for_frame_with_label(
+                    core_type_thread,
+                    thread.thread_label,
+                    CategoryHandle::OTHER,
+                    FrameFlags::empty(),
+                );
+                process.unresolved_samples.add_sample(
+                    core_type_thread,
+                    profile_timestamp,
+                    timestamp,
+                    stack_index,
+                    cpu_delta,
+                    1,
+                    Some(label_frame),
+                );
+            }
+        }
+
         if let (Some(cpu_index), Some(cpus)) = (e.cpu, &mut self.cpus) {
             let cpu = cpus.get_mut(cpu_index as usize, &mut self.profile);
 
@@ -349,7 +597,7 @@ where
                         .consume_cpu_delta(&mut cpu.context_switch_data),
                 )
             } else {
-                CpuDelta::from_nanos(0)
+                Self::sample_cpu_delta(self.sample_cpu_time, e)
             };
 
             let label_frame = self.profile.handle_for_frame_with_label(
@@ -414,6 +662,12 @@ where
             .convert_no_kernel(stack.iter().rev().cloned());
         let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
         thread.off_cpu_stack = Some(stack_index);
+        if self.annotate_off_cpu_states {
+            thread.off_cpu_state = e
+                .raw
+                .and_then(|raw| SchedSwitch::parse(raw, self.endian).ok())
+                .map(|sched_switch| sched_switch.off_cpu_state());
+        }
 
         let timestamp_mono = e
             .timestamp
@@ -544,6 +798,233 @@ where
         );
     }
 
+    /// Adds a marker for a page fault sample, with the faulting code's stack.
+    pub fn handle_page_fault_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+    ) {
+        let (Some(pid), Some(tid), Some(timestamp_mono)) = (e.pid, e.tid, e.timestamp) else {
+            return;
+        };
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        process.check_jitdump(
+            &mut self.jit_category_manager,
+            &mut self.profile,
+            &self.timestamp_converter,
+        );
+
+        let stack = &mut self.stack_scratch;
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            stack,
+            self.fold_recursive_prefix,
+            self.call_chain_return_addresses_are_preadjusted,
+        );
+        let unresolved_stack = self.unresolved_stacks.convert(stack.iter().rev().cloned());
+
+        let thread_handle = process
+            .threads
+            .get_thread_by_tid(tid, &mut self.profile)
+            .profile_thread;
+        let address = match e.addr {
+            Some(addr) => self.profile.handle_for_string(&format!("{addr:#x}")),
+            None => self.profile.handle_for_string("unknown"),
+        };
+        let marker_handle = self.profile.add_marker(
+            thread_handle,
+            MarkerTiming::Instant(timestamp),
+            PageFaultMarker {
+                address,
+                count: e.period.unwrap_or(1),
+            },
+        );
+        process.unresolved_samples.attach_stack_to_marker(
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            unresolved_stack,
+            marker_handle,
+        );
+    }
+
+    /// Adds a crash marker for a signal:signal_deliver sample, with the stack of
+    /// the code which crashed and its sampled user registers. The tracepoint is
+    /// filtered to the signals which terminate the process.
+    pub fn handle_signal_deliver_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+    ) {
+        let (Some(pid), Some(tid), Some(timestamp_mono), Some(raw)) =
+            (e.pid, e.tid, e.timestamp, e.raw)
+        else {
+            return;
+        };
+        let Ok(signal) = SignalDeliver::parse(raw, self.endian) else {
+            return;
+        };
+        let Some(signal_name) = crash_signal_name(signal.sig) else {
+            return;
+        };
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+
+        let stack = &mut self.stack_scratch;
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            stack,
+            self.fold_recursive_prefix,
+            self.call_chain_return_addresses_are_preadjusted,
+        );
+        let unresolved_stack = self.unresolved_stacks.convert(stack.iter().rev().cloned());
+
+        let thread_handle = process
+            .threads
+            .get_thread_by_tid(tid, &mut self.profile)
+            .profile_thread;
+        let signal_name = self.profile.handle_for_string(signal_name);
+        let registers = match &e.user_regs {
+            Some(regs) => C::format_regs(regs),
+            None => String::new(),
+        };
+        let registers = self.profile.handle_for_string(&registers);
+        let marker_handle = self.profile.add_marker(
+            thread_handle,
+            MarkerTiming::Instant(timestamp),
+            CrashMarker {
+                signal: signal_name,
+                code: signal.code,
+                registers,
+            },
+        );
+        process.unresolved_samples.attach_stack_to_marker(
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            unresolved_stack,
+            marker_handle,
+        );
+    }
+
+    /// Adds an instant marker with the stack for a sample of a --probe event.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn handle_probe_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        probe_name: &str,
+    ) {
+        let (Some(pid), Some(tid), Some(timestamp_mono)) = (e.pid, e.tid, e.timestamp) else {
+            return;
+        };
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+
+        let stack = &mut self.stack_scratch;
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            stack,
+            self.fold_recursive_prefix,
+            self.call_chain_return_addresses_are_preadjusted,
+        );
+        let unresolved_stack = self.unresolved_stacks.convert(stack.iter().rev().cloned());
+
+        let thread_handle = process
+            .threads
+            .get_thread_by_tid(tid, &mut self.profile)
+            .profile_thread;
+        let probe = self.profile.handle_for_string(probe_name);
+        let marker_handle = self.profile.add_marker(
+            thread_handle,
+            MarkerTiming::Instant(timestamp),
+            ProbeMarker { probe },
+        );
+        process.unresolved_samples.attach_stack_to_marker(
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            unresolved_stack,
+            marker_handle,
+        );
+    }
+
+    /// Ends the library mappings which a syscalls:sys_enter_munmap sample
+    /// unmaps, so that samples after a dlclose don't resolve to the
+    /// unloaded library.
+    pub fn handle_munmap_sample(&mut self, e: &SampleRecord) {
+        let (Some(pid), Some(timestamp), Some(raw)) = (e.pid, e.timestamp, e.raw) else {
+            return;
+        };
+        let Ok(munmap) = MunmapArgs::parse(raw, self.endian) else {
+            return;
+        };
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        process.remove_regular_lib_mappings(
+            timestamp,
+            munmap.addr,
+            munmap.addr.saturating_add(munmap.len),
+        );
+    }
+
+    pub fn handle_syscall_enter_sample(&mut self, e: &SampleRecord) {
+        let (Some(pid), Some(tid), Some(timestamp), Some(raw)) = (e.pid, e.tid, e.timestamp, e.raw)
+        else {
+            return;
+        };
+        let Ok(syscall) = RawSyscall::parse(raw, self.endian) else {
+            return;
+        };
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+        thread.pending_syscall = Some((syscall.id, timestamp));
+    }
+
+    /// Adds an interval marker for the syscall which this sys_exit sample ends,
+    /// if we've seen the matching sys_enter sample.
+    pub fn handle_syscall_exit_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+    ) {
+        let (Some(pid), Some(tid), Some(end_timestamp), Some(raw)) =
+            (e.pid, e.tid, e.timestamp, e.raw)
+        else {
+            return;
+        };
+        let Ok(syscall) = RawSyscall::parse(raw, self.endian) else {
+            return;
+        };
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+        let Some((id, start_timestamp)) = thread.pending_syscall.take() else {
+            return;
+        };
+        if id != syscall.id {
+            return;
+        }
+
+        let name = match syscall_name(C::syscall_table(), id as u64) {
+            Some(name) => self.profile.handle_for_string(name),
+            None => self.profile.handle_for_string(&format!("syscall {id}")),
+        };
+        let timing = MarkerTiming::Interval(
+            self.timestamp_converter.convert_time(start_timestamp),
+            self.timestamp_converter.convert_time(end_timestamp),
+        );
+        self.profile.add_marker(
+            thread.profile_thread,
+            timing,
+            SyscallMarker {
+                name,
+                return_value: syscall.ret_or_first_arg,
+            },
+        );
+    }
+
     pub fn handle_other_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
         e: &SampleRecord,
@@ -599,6 +1080,15 @@ where
         }
     }
 
+    /// The CPU time of a sample, for profiles without context switch records.
+    fn sample_cpu_delta(sample_cpu_time: SampleCpuTime, e: &SampleRecord) -> CpuDelta {
+        match (sample_cpu_time, e.period) {
+            (SampleCpuTime::PeriodInNanos, Some(period)) => CpuDelta::from_nanos(period),
+            (SampleCpuTime::FixedNanos(nanos), _) => CpuDelta::from_nanos(nanos),
+            _ => CpuDelta::ZERO,
+        }
+    }
+
     /// Get the stack contained in this sample, and put it into `stack`.
     ///
     /// We can have both the kernel stack and the user stack, or just one of
@@ -752,6 +1242,12 @@ where
         if e.page_offset == 0 {
             self.pe_mappings.check_mmap(&path, e.address);
         }
+        if self.pe_mappings.is_managed_image(&path) {
+            // The code in .NET ReadyToRun images is symbolicated with the
+            // runtime's jitdump file or perf map, so don't let the image's
+            // mapping hide those.
+            return;
+        }
 
         let Some(dso_key) = DsoKey::detect(&path, e.cpu_mode) else {
             return;
@@ -871,12 +1367,16 @@ where
                 let off_cpu_sample = self
                     .context_switch_handler
                     .handle_switch_in(timestamp, &mut thread.context_switch_data);
+                let off_cpu_state = thread.off_cpu_state.take();
                 if let (Some(off_cpu_sample), Some(off_cpu_stack)) =
                     (off_cpu_sample, thread.off_cpu_stack.take())
                 {
                     let cpu_delta_ns = self
                         .context_switch_handler
                         .consume_cpu_delta(&mut thread.context_switch_data);
+                    let off_cpu_state_frame = off_cpu_state.map(|state| {
+                        off_cpu_state_frame(&mut self.profile, thread.profile_thread, state)
+                    });
                     process_off_cpu_sample_group(
                         off_cpu_sample,
                         thread.profile_thread,
@@ -884,6 +1384,7 @@ where
                         &self.timestamp_converter,
                         self.off_cpu_weight_per_sample,
                         off_cpu_stack,
+                        off_cpu_state_frame,
                         &mut process.unresolved_samples,
                     );
                 }
@@ -944,6 +1445,13 @@ where
             ContextSwitchRecord::Out { preempted, .. } => {
                 self.context_switch_handler
                     .handle_switch_out(timestamp, &mut thread.context_switch_data);
+                if self.annotate_off_cpu_states && thread.off_cpu_state.is_none() {
+                    // A sched_switch sample, if there is one, has a more precise state.
+                    thread.off_cpu_state = Some(match preempted {
+                        TaskWasPreempted::Yes => OffCpuState::Runnable,
+                        TaskWasPreempted::No => OffCpuState::Blocked,
+                    });
+                }
                 if let (Some(cpus), Some(cpu_index)) = (&mut self.cpus, Some(common.cpu.unwrap())) {
                     let combined_thread = cpus.combined_thread_handle();
                     let cpu = cpus.get_mut(cpu_index as usize, &mut self.profile);
@@ -981,6 +1489,7 @@ where
                 eprintln!("Unexpected data in FORK record: If we fork into a different process, the forked child thread should be the main thread of the new process");
             }
             let parent_process_name = parent_process.name.clone();
+            let parent_profile_process = parent_process.profile_process;
             let fork_data = parent_process.clone_fork_data();
             let child_process = self.processes.recycle_or_get_new(
                 e.pid,
@@ -989,6 +1498,9 @@ where
                 &mut self.profile,
             );
             child_process.adopt_fork_data_from_parent(fork_data);
+            // List the child right after its parent in the timeline.
+            self.profile
+                .set_process_parent(child_process.profile_process, parent_profile_process);
         } else {
             // New thread within the same process.
             // eprintln!("New thread: pid={}, old_tid={}, new_tid={}", e.pid, e.ptid, e.tid);
@@ -1051,7 +1563,12 @@ where
         let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
 
         let name = if let Some((exec_name, args)) = exec_name_and_cmdline {
-            make_process_name(&exec_name, args, self.arg_count_to_include_in_process_name)
+            make_process_name(
+                &exec_name,
+                args,
+                self.arg_count_to_include_in_process_name,
+                self.redact_env,
+            )
         } else {
             comm_name.clone()
         };
@@ -1131,7 +1648,12 @@ where
         let process = self.processes.get_by_pid(pid, &mut self.profile);
         let process_handle = process.profile_process;
 
-        let name = make_process_name(exe_name, args, self.arg_count_to_include_in_process_name);
+        let name = make_process_name(
+            exe_name,
+            args,
+            self.arg_count_to_include_in_process_name,
+            self.redact_env,
+        );
         self.profile.set_process_name(process_handle, &name);
         process.name = Some(name.to_owned());
 
@@ -1162,6 +1684,33 @@ where
         self.profile.set_thread_start_time(thread_handle, time);
     }
 
+    /// Adds the image of the running kernel as a library, so that the kernel
+    /// frames of live recordings can be symbolicated with the symbols from
+    /// /proc/kallsyms. Unlike perf.data files, live recordings don't have mmap
+    /// records for the kernel.
+    ///
+    /// Returns false if the kernel symbols are not available, for example
+    /// because /proc/kallsyms only lists zero addresses due to kptr_restrict.
+    pub fn add_running_kernel_image(&mut self) -> bool {
+        let Some(kernel_symbols) = self.kernel_symbols.as_ref() else {
+            return false;
+        };
+        if kernel_symbols.base_avma == 0 {
+            return false;
+        }
+        let base_avma = kernel_symbols.base_avma;
+        let len = kernel_symbols.end_avma.saturating_sub(base_avma);
+        let build_id = kernel_symbols.build_id.clone();
+        self.add_kernel_module(
+            base_avma,
+            len,
+            DsoKey::Kernel,
+            Some(&build_id),
+            b"[kernel.kallsyms]",
+        );
+        true
+    }
+
     fn add_kernel_module(
         &mut self,
         base_address: u64,
@@ -1341,8 +1890,10 @@ where
         };
 
         let mut mapping_info = MappingInfo::new_elf(path, avma_range);
-        if path_slice.is_empty() {
-            if let Some(pe_mapping) = self.pe_mappings.find_mapping(&avma_range) {
+        if let Some(pe_mapping) = self.pe_mappings.find_mapping(&avma_range) {
+            // Wine maps the sections of PE images either anonymously or, if
+            // they're suitably aligned, from the file itself.
+            if path_slice.is_empty() || pe_mapping.path == path {
                 mapping_info = MappingInfo::new_pe(pe_mapping);
             }
         }
@@ -1350,7 +1901,22 @@ where
         let mut file = None;
         let mut path = mapping_info.path.to_string_lossy().to_string();
 
-        if let Ok((f, p)) = open_file_with_fallback(&mapping_info.path, &self.binary_lookup_dirs) {
+        // Processes in containers see their own root file system. While the
+        // process is alive, its files are available under /proc/<pid>/root.
+        let opened_file = if self.find_binaries_in_process_root
+            && self
+                .processes
+                .get_by_pid(process_pid, &mut self.profile)
+                .is_in_other_mount_namespace()
+        {
+            Self::open_file_in_process_root(process_pid, &mapping_info.path)
+        } else {
+            None
+        };
+        let opened_file = opened_file.map(Ok).unwrap_or_else(|| {
+            open_file_with_fallback(&mapping_info.path, &self.binary_lookup_dirs)
+        });
+        if let Ok((f, p)) = opened_file {
             // Fix up bad files from `perf inject --jit`.
             if let Some((fixed_file, fixed_path)) = correct_bad_perf_jit_so_file(&f, &path) {
                 file = Some(fixed_file);
@@ -1368,6 +1934,20 @@ where
 
         let process = self.processes.get_by_pid(process_pid, &mut self.profile);
 
+        if let Some(OnlyLibFilter::Lib(lib)) = &self.only_lib {
+            // Match "libxul.so" against "libxul.so" and versioned names
+            // like "libxul.so.1".
+            if name == *lib
+                || name
+                    .strip_prefix(lib.as_str())
+                    .is_some_and(|suffix| suffix.starts_with('.'))
+            {
+                process
+                    .only_lib_ranges
+                    .push(avma_range.start()..avma_range.end());
+            }
+        }
+
         // Case 1: There are symbols in the file, if we are importing a perf.data file
         // that was recorded with simpleperf.
         if let Some(symbol_table) = self.simpleperf.symbol_tables.user.get(original_path) {
@@ -1459,11 +2039,22 @@ where
 
             let module_section_info =
                 Self::module_section_info_with_object(Some(mmap.clone()), &file);
-            let Some(library_info) =
+            let Some(mut library_info) =
                 Self::library_info_with_object(&name, &path, &file, file_code_id)
             else {
                 return;
             };
+            if mapping_info.mapping_type == MappingType::Pe {
+                // Symbols for PE images are looked up by PDB name, e.g. on
+                // the Windows symbol servers.
+                if let Some(pdb_path) = pdb_path_for_object(&file) {
+                    library_info.debug_name = match pdb_path.rfind(['/', '\\']) {
+                        Some(pos) => pdb_path[pos + 1..].to_owned(),
+                        None => pdb_path.clone(),
+                    };
+                    library_info.debug_path = pdb_path;
+                }
+            }
 
             let Some(base_avma) = mapping_info.compute_base_avma(&file, mapping_start_file_offset)
             else {
@@ -1479,6 +2070,9 @@ where
             let relative_address_at_start = (mapping_start_avma - module.base_avma()) as u32;
             process.unwinder.add_module(module);
             let lib_handle = self.profile.add_lib(library_info);
+            if let Some(build_info) = Self::build_info_with_object(&file) {
+                self.profile.set_lib_build_info(lib_handle, build_info);
+            }
 
             if name.starts_with("jitted-") && name.ends_with(".so") {
                 let symbol_name = jit_function_name(&file);
@@ -1610,6 +2204,12 @@ where
         }
     }
 
+    fn open_file_in_process_root(pid: i32, path: &Path) -> Option<(std::fs::File, PathBuf)> {
+        let path = mount_namespace::path_in_process_root(pid, path)?;
+        let file = std::fs::File::open(&path).ok()?;
+        Some((file, path))
+    }
+
     fn library_info_with_object<'data, R: object::ReadRef<'data>>(
         name: &str,
         path: &str,
@@ -1624,7 +2224,46 @@ where
             debug_path: path.to_owned(),
             debug_name: name.to_owned(),
             name: name.to_owned(),
-            arch: None,
+            arch: arch_name(file.architecture()).map(ToOwned::to_owned),
+        })
+    }
+
+    /// Reads the compiler versions from the `.comment` section, and the
+    /// optimization level from the `.GCC.command.line` section, which GCC
+    /// only writes with `-frecord-gcc-switches`.
+    fn build_info_with_object<'data, R: object::ReadRef<'data>>(
+        file: &object::File<'data, R>,
+    ) -> Option<LibraryBuildInfo> {
+        let section_strings = |section_name| -> Vec<String> {
+            let Some(data) = file
+                .section_by_name(section_name)
+                .and_then(|section| section.uncompressed_data().ok())
+            else {
+                return Vec::new();
+            };
+            data.split(|b| *b == 0)
+                .map(|s| String::from_utf8_lossy(s).trim().to_owned())
+                .filter(|s| !s.is_empty())
+                .collect()
+        };
+
+        // Each object file contributes its compiler, e.g. "GCC: (GNU) 13.2.0"
+        // from the C runtime objects and "rustc version 1.80.0" from Rust code.
+        let mut compilers: Vec<String> = Vec::new();
+        for compiler in section_strings(".comment") {
+            if !compilers.contains(&compiler) {
+                compilers.push(compiler);
+            }
+        }
+        let opt_level = section_strings(".GCC.command.line")
+            .into_iter()
+            .rfind(|switch| switch.starts_with("-O"));
+        if compilers.is_empty() && opt_level.is_none() {
+            return None;
+        }
+        Some(LibraryBuildInfo {
+            compiler: (!compilers.is_empty()).then(|| compilers.join("; ")),
+            opt_level,
         })
     }
 
@@ -1824,6 +2463,7 @@ impl SimpleperfSymbolTables {
 //     dbg!(jit_function_name(&file));
 // }
 
+#[allow(clippy::too_many_arguments)]
 fn process_off_cpu_sample_group(
     off_cpu_sample: OffCpuSampleGroup,
     thread_handle: ThreadHandle,
@@ -1831,6 +2471,7 @@ fn process_off_cpu_sample_group(
     timestamp_converter: &TimestampConverter,
     off_cpu_weight_per_sample: i32,
     off_cpu_stack: UnresolvedStackHandle,
+    off_cpu_state_frame: Option<FrameHandle>,
     samples: &mut UnresolvedSamples,
 ) {
     let OffCpuSampleGroup {
@@ -1852,7 +2493,7 @@ fn process_off_cpu_sample_group(
         stack,
         cpu_delta,
         weight,
-        None,
+        off_cpu_state_frame,
     );
 
     if sample_count > 1 {
@@ -1867,11 +2508,47 @@ fn process_off_cpu_sample_group(
             stack,
             cpu_delta,
             weight,
-            None,
+            off_cpu_state_frame,
         );
     }
 }
 
+/// Returns the frame which is put at the root of the samples that fill a
+/// thread's off-CPU time, to say what the thread was doing.
+fn off_cpu_state_frame(
+    profile: &mut Profile,
+    thread_handle: ThreadHandle,
+    state: OffCpuState,
+) -> FrameHandle {
+    let label = profile.handle_for_string(state.label());
+    profile.handle_for_frame_with_label(
+        thread_handle,
+        label,
+        CategoryHandle::OTHER,
+        FrameFlags::empty(),
+    )
+}
+
+/// Returns the path of the PDB file which the PE image refers to in its debug
+/// directory, e.g. "C:\build\game.pdb".
+fn pdb_path_for_object<'data>(file: &impl Object<'data>) -> Option<String> {
+    let pdb_info = file.pdb_info().ok()??;
+    Some(std::str::from_utf8(pdb_info.path()).ok()?.to_owned())
+}
+
+/// Returns the name of the CPU architecture, with the same names which are
+/// used for macOS libraries, e.g. "arm64".
+fn arch_name(architecture: object::Architecture) -> Option<&'static str> {
+    match architecture {
+        object::Architecture::X86_64 => Some("x86_64"),
+        object::Architecture::I386 => Some("x86"),
+        object::Architecture::Aarch64 => Some("arm64"),
+        object::Architecture::Arm => Some("arm"),
+        object::Architecture::Riscv64 => Some("riscv64"),
+        _ => None,
+    }
+}
+
 /// Returns true for paths such as the following:
 ///  - "/data/local/tmp/perf.data_jit_app_cache:1039560-1040440"
 ///  - "./TemporaryFile-osHvVs" (used by older versions of simpleperf, e.g. on Android 11)
@@ -1940,6 +2617,7 @@ impl MappingInfo {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum MappingType {
     Elf,
     Pe,
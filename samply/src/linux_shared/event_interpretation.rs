@@ -9,8 +9,13 @@ pub enum KnownEvent {
     RssStat,
     MmapEnter,
     MmapExit,
+    MunmapEnter,
     MprotectEnter,
     PageFault,
+    /// The page-faults, minor-faults or major-faults software events.
+    PageFaultSample,
+    SyscallEnter,
+    SyscallExit,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -22,12 +27,27 @@ pub enum OffCpuIndicator {
     SchedSwitchAndSamples,
 }
 
+/// How much CPU time a sample of the main event stands for, for profiles without
+/// context switch information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleCpuTime {
+    /// The sample period is a duration in nanoseconds, e.g. for cpu-clock.
+    PeriodInNanos,
+    /// Every sample stands for roughly the same amount of CPU time, because the
+    /// event was sampled at a fixed frequency.
+    FixedNanos(u64),
+    /// Samples can't be translated into CPU time, e.g. for cycles sampled with
+    /// a fixed period.
+    Unknown,
+}
+
 #[derive(Debug, Clone)]
 pub struct EventInterpretation {
     pub main_event_attr_index: usize,
     #[allow(unused)]
     pub main_event_name: String,
     pub sampling_is_time_based: Option<u64>,
+    pub sample_cpu_time: SampleCpuTime,
     pub off_cpu_indicator: Option<OffCpuIndicator>,
     pub sched_switch_attr_index: Option<usize>,
     pub known_event_indices: HashMap<usize, KnownEvent>,
@@ -47,8 +67,8 @@ impl EventInterpretation {
                 panic!("Can only convert profiles with sampled events")
             }
             (_, SamplingPolicy::Frequency(freq)) => {
-                let nanos = 1_000_000_000 / freq;
-                Some(nanos)
+                // A zero frequency can only come from a malformed perf.data file.
+                1_000_000_000u64.checked_div(freq)
             }
             (
                 PerfEventType::Software(
@@ -62,6 +82,18 @@ impl EventInterpretation {
             }
             (_, SamplingPolicy::Period(_)) => None,
         };
+        let sample_cpu_time = match (attrs[0].attr.type_, attrs[0].attr.sampling_policy) {
+            (
+                PerfEventType::Software(
+                    SoftwareCounterType::CpuClock | SoftwareCounterType::TaskClock,
+                ),
+                _,
+            ) => SampleCpuTime::PeriodInNanos,
+            (_, SamplingPolicy::Frequency(freq)) if freq != 0 => {
+                SampleCpuTime::FixedNanos(1_000_000_000 / freq)
+            }
+            _ => SampleCpuTime::Unknown,
+        };
         let have_context_switches = attrs[0].attr.flags.contains(AttrFlags::CONTEXT_SWITCH);
         let sched_switch_attr_index = attrs
             .iter()
@@ -79,6 +111,13 @@ impl EventInterpretation {
             ("syscalls:sys_enter_mprotect", KnownEvent::MprotectEnter),
             ("syscalls:sys_enter_mmap", KnownEvent::MmapEnter),
             ("syscalls:sys_exit_mmap", KnownEvent::MmapExit),
+            ("syscalls:sys_enter_munmap", KnownEvent::MunmapEnter),
+            ("page-faults", KnownEvent::PageFaultSample),
+            ("faults", KnownEvent::PageFaultSample),
+            ("minor-faults", KnownEvent::PageFaultSample),
+            ("major-faults", KnownEvent::PageFaultSample),
+            ("raw_syscalls:sys_enter", KnownEvent::SyscallEnter),
+            ("raw_syscalls:sys_exit", KnownEvent::SyscallExit),
         ];
 
         for (event_name, event) in known_events {
@@ -105,6 +144,7 @@ impl EventInterpretation {
             main_event_attr_index,
             main_event_name,
             sampling_is_time_based,
+            sample_cpu_time,
             off_cpu_indicator,
             sched_switch_attr_index,
             known_event_indices,
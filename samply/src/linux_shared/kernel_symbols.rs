@@ -30,6 +30,9 @@ pub enum KernelSymbolsError {
 pub struct KernelSymbols {
     pub build_id: Vec<u8>,
     pub base_avma: u64,
+    /// The address after the highest symbol address, including the symbols of
+    /// loaded kernel modules.
+    pub end_avma: u64,
     pub symbol_table: Arc<SymbolTable>,
 }
 
@@ -44,9 +47,15 @@ impl KernelSymbols {
             .map_err(KernelSymbolsError::CouldNotReadProcKallsyms)?;
         let (base_avma, symbol_table) = parse_kallsyms(&kallsyms)?;
         let symbol_table = Arc::new(symbol_table);
+        let end_avma = KallSymIter::new(&kallsyms)
+            .map(|(address, _)| address)
+            .max()
+            .unwrap_or(base_avma)
+            + 1;
         Ok(KernelSymbols {
             build_id,
             base_avma,
+            end_avma,
             symbol_table,
         })
     }
@@ -0,0 +1,85 @@
+/// A time range in which the kernel dropped events, because a perf ring buffer
+/// was full. Timestamps are in the same clock as the perf records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LostEventsRange {
+    pub start: u64,
+    pub end: u64,
+    pub count: u64,
+}
+
+/// Keeps track of PERF_RECORD_LOST records. These records don't have a
+/// timestamp, so the lost events are attributed to the time between the last
+/// record before them and the first record after them.
+#[derive(Debug, Default)]
+pub struct LostEventsTracker {
+    /// The start time and the count of the lost events which haven't been
+    /// followed by a timestamped record yet.
+    pending: Option<(u64, u64)>,
+    ranges: Vec<LostEventsRange>,
+    total_count: u64,
+}
+
+impl LostEventsTracker {
+    /// Call this for every PERF_RECORD_LOST record, with the timestamp of the
+    /// last record before it.
+    pub fn handle_lost(&mut self, count: u64, last_timestamp: u64) {
+        let (_start, pending_count) = self.pending.get_or_insert((last_timestamp, 0));
+        *pending_count += count;
+        self.total_count += count;
+    }
+
+    /// Call this for every record which has a timestamp.
+    pub fn handle_timestamp(&mut self, timestamp: u64) {
+        if let Some((start, count)) = self.pending.take() {
+            self.ranges.push(LostEventsRange {
+                start,
+                end: timestamp.max(start),
+                count,
+            });
+        }
+    }
+
+    /// The number of lost events so far.
+    pub fn total_count(&self) -> u64 {
+        self.total_count
+    }
+
+    /// Returns the ranges with lost events. Events which were lost after the
+    /// last record are attributed to the time up to `end_timestamp`.
+    pub fn finish(mut self, end_timestamp: u64) -> Vec<LostEventsRange> {
+        self.handle_timestamp(end_timestamp);
+        self.ranges
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lost_events_between_records() {
+        let mut tracker = LostEventsTracker::default();
+        tracker.handle_timestamp(100);
+        tracker.handle_lost(3, 100);
+        tracker.handle_lost(2, 100);
+        tracker.handle_timestamp(150);
+        tracker.handle_timestamp(160);
+        tracker.handle_lost(7, 160);
+        assert_eq!(tracker.total_count(), 12);
+        assert_eq!(
+            tracker.finish(200),
+            [
+                LostEventsRange {
+                    start: 100,
+                    end: 150,
+                    count: 5
+                },
+                LostEventsRange {
+                    start: 160,
+                    end: 200,
+                    count: 7
+                },
+            ]
+        );
+    }
+}
@@ -4,14 +4,19 @@ mod converter;
 mod event_interpretation;
 mod injected_jit_object;
 mod kernel_symbols;
+pub mod lost_events;
 mod mmap_range_or_vec;
+pub mod mount_namespace;
 mod object_rewriter;
 mod pe_mappings;
 mod process;
 mod process_threads;
 mod processes;
 mod rss_stat;
+mod sched_switch;
+pub mod signal_deliver;
 mod svma_file_range;
+pub mod syscalls;
 mod thread;
 #[allow(unused)]
 pub mod vdso;
@@ -19,5 +24,5 @@ pub mod vdso;
 pub use convert_regs::{ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64};
 pub use converter::Converter;
 #[allow(unused)]
-pub use event_interpretation::{EventInterpretation, KnownEvent, OffCpuIndicator};
+pub use event_interpretation::{EventInterpretation, KnownEvent, OffCpuIndicator, SampleCpuTime};
 pub use mmap_range_or_vec::MmapRangeOrVec;
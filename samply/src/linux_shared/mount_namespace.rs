@@ -0,0 +1,25 @@
+//! Processes in containers, e.g. Docker containers, run in their own mount
+//! namespace, where paths like /usr/lib/libfoo.so can refer to different files
+//! than they do for samply. While such a process is alive, its file system is
+//! available under /proc/<pid>/root.
+
+use std::path::{Path, PathBuf};
+
+/// Whether the process with this pid is in a different mount namespace than
+/// samply. Returns false if this can't be determined, e.g. because the process
+/// is gone.
+pub fn is_in_other_mount_namespace(pid: i32) -> bool {
+    let own_namespace = std::fs::read_link("/proc/self/ns/mnt");
+    let process_namespace = std::fs::read_link(format!("/proc/{pid}/ns/mnt"));
+    match (own_namespace, process_namespace) {
+        (Ok(own_namespace), Ok(process_namespace)) => own_namespace != process_namespace,
+        _ => false,
+    }
+}
+
+/// Translates an absolute path, as seen by the process with this pid, into a
+/// path which samply can open.
+pub fn path_in_process_root(pid: i32, path: &Path) -> Option<PathBuf> {
+    let relative_path = path.strip_prefix("/").ok()?;
+    Some(Path::new(&format!("/proc/{pid}/root")).join(relative_path))
+}
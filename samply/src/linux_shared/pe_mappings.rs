@@ -21,12 +21,16 @@
 //! offset of 0, we'll add it to the list of "suspected PE images". When we see a later mapping
 //! that belongs to one of the suspected PE ranges, we'll match the mapping with the file,
 //! which allows binary correlation and unwinding to work.
+//!
+//! The same check also finds .NET assemblies, which have a CLI header. The
+//! precompiled (ReadyToRun) code in these images has no native symbols; the
+//! .NET runtime reports its methods in its jitdump file or perf map instead.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::path::{Path, PathBuf};
 
 use memmap2::Mmap;
-use object::pe::{ImageNtHeaders32, ImageNtHeaders64};
+use object::pe::{ImageNtHeaders32, ImageNtHeaders64, IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR};
 use object::read::pe::{ImageNtHeaders, ImageOptionalHeader, PeFile};
 use object::FileKind;
 use wholesym::{CodeId, PeCodeId};
@@ -44,12 +48,15 @@ pub struct PeMappings {
     /// Mapping of start address to potential mapped PE binaries.
     /// The key is equal to the start field of the value.
     suspected_pe_mappings: BTreeMap<u64, SuspectedPeMapping>,
+    /// The paths of the mapped PE images which are .NET assemblies.
+    managed_image_paths: HashSet<PathBuf>,
 }
 
 impl PeMappings {
     pub fn new() -> Self {
         Self {
             suspected_pe_mappings: BTreeMap::new(),
+            managed_image_paths: HashSet::new(),
         }
     }
 
@@ -64,6 +71,14 @@ impl PeMappings {
         Some(mapping)
     }
 
+    /// Returns whether this path belongs to a .NET assembly whose header was
+    /// mapped before.
+    pub fn is_managed_image(&self, path_slice: &[u8]) -> bool {
+        !self.managed_image_paths.is_empty()
+            && std::str::from_utf8(path_slice)
+                .is_ok_and(|path| self.managed_image_paths.contains(Path::new(path)))
+    }
+
     pub fn check_mmap(&mut self, path_slice: &[u8], mapping_start_avma: u64) {
         // Do a quick extension check first, to avoid end up trying to parse every mmapped file.
         let filename_is_pe = path_slice.ends_with(b".exe")
@@ -87,7 +102,10 @@ impl PeMappings {
         //   here.
         // - VirtualAddress of the sections are defined to be adjacent after page-alignment. This
         //   means that we can treat the image as a contiguous region.
-        if let Some((size, code_id)) = get_pe_mapping_size_and_codeid(path) {
+        if let Some((size, code_id, is_managed)) = get_pe_mapping_info(path) {
+            if is_managed {
+                self.managed_image_paths.insert(path.to_owned());
+            }
             let mapping = SuspectedPeMapping {
                 path: path.to_owned(),
                 code_id,
@@ -99,8 +117,9 @@ impl PeMappings {
     }
 }
 
-fn get_pe_mapping_size_and_codeid(path: &Path) -> Option<(u64, CodeId)> {
-    fn inner<T: ImageNtHeaders>(data: &[u8]) -> Option<(u64, CodeId)> {
+/// Returns the image size, the code ID, and whether the image is a .NET assembly.
+fn get_pe_mapping_info(path: &Path) -> Option<(u64, CodeId, bool)> {
+    fn inner<T: ImageNtHeaders>(data: &[u8]) -> Option<(u64, CodeId, bool)> {
         let file = PeFile::<T>::parse(data).ok()?;
         let image_size = file.nt_headers().optional_header().size_of_image();
         let timestamp = file
@@ -113,7 +132,11 @@ fn get_pe_mapping_size_and_codeid(path: &Path) -> Option<(u64, CodeId)> {
             image_size,
         });
 
-        Some((image_size as u64, code_id))
+        let is_managed = file
+            .data_directory(IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR)
+            .is_some();
+
+        Some((image_size as u64, code_id, is_managed))
     }
 
     let file = std::fs::File::open(path).ok()?;
@@ -1,18 +1,22 @@
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
 use framehop::Unwinder;
 use fxprof_processed_profile::{
-    CounterHandle, LibraryHandle, MarkerTiming, ProcessHandle, Profile, StringHandle, ThreadHandle,
-    Timestamp,
+    CounterGraphType, CounterHandle, LibraryHandle, MarkerTiming, ProcessHandle, Profile,
+    StringHandle, ThreadHandle, Timestamp,
 };
 
+use super::mount_namespace;
 use super::process_threads::ProcessThreads;
 use super::thread::Thread;
 use crate::shared::jit_category_manager::JitCategoryManager;
 use crate::shared::jit_function_add_marker::JitFunctionAddMarker;
 use crate::shared::jit_function_recycler::JitFunctionRecycler;
 use crate::shared::jitdump_manager::JitDumpManager;
-use crate::shared::lib_mappings::{LibMappingAdd, LibMappingInfo, LibMappingOp, LibMappingOpQueue};
+use crate::shared::lib_mappings::{
+    LibMappingAdd, LibMappingInfo, LibMappingOp, LibMappingOpQueue, LibMappingRemoveRange,
+};
 use crate::shared::marker_file::get_markers;
 use crate::shared::perf_map::try_load_perf_map;
 use crate::shared::process_sample_data::{MarkerSpanOnThread, ProcessSampleData};
@@ -38,11 +42,19 @@ pub struct Process<U> {
     pub prev_mm_swapents_size: i64,
     pub prev_mm_shmempages_size: i64,
     pub mem_counter: Option<CounterHandle>,
+    /// The counter for the sampled allocations of --alloc which haven't been
+    /// freed yet, and its current value in bytes.
+    sampled_live_memory_counter: Option<CounterHandle>,
+    sampled_live_bytes: i64,
+    /// The address ranges of the library selected with --only-lib.
+    pub only_lib_ranges: Vec<Range<u64>>,
+    in_other_mount_namespace: Option<bool>,
 }
 
 pub struct ProcessForkData<U> {
     unwinder: U,
     lib_mapping_ops: LibMappingOpQueue,
+    only_lib_ranges: Vec<Range<u64>>,
 }
 
 impl<U> Process<U>
@@ -85,6 +97,10 @@ where
             prev_mm_swapents_size: 0,
             prev_mm_shmempages_size: 0,
             mem_counter: None,
+            sampled_live_memory_counter: None,
+            sampled_live_bytes: 0,
+            only_lib_ranges: Vec::new(),
+            in_other_mount_namespace: None,
         }
     }
 
@@ -93,6 +109,7 @@ where
         ProcessForkData {
             unwinder: self.unwinder.clone(),
             lib_mapping_ops: self.lib_mapping_ops.clone(),
+            only_lib_ranges: self.only_lib_ranges.clone(),
         }
     }
 
@@ -100,6 +117,15 @@ where
     pub fn adopt_fork_data_from_parent(&mut self, fork_data: ProcessForkData<U>) {
         self.unwinder = fork_data.unwinder;
         self.lib_mapping_ops = fork_data.lib_mapping_ops;
+        self.only_lib_ranges = fork_data.only_lib_ranges;
+    }
+
+    /// Whether this live process sees a different file system than samply,
+    /// e.g. because it runs in a container.
+    pub fn is_in_other_mount_namespace(&mut self) -> bool {
+        *self
+            .in_other_mount_namespace
+            .get_or_insert_with(|| mount_namespace::is_in_other_mount_namespace(self.pid))
     }
 
     pub fn rename_with_recycling(
@@ -283,6 +309,25 @@ where
         );
     }
 
+    /// Ends the regular lib mappings in the given address range at `timestamp`.
+    /// Samples after this point no longer resolve against these libraries.
+    pub fn remove_regular_lib_mappings(
+        &mut self,
+        timestamp: u64,
+        start_address: u64,
+        end_address: u64,
+    ) {
+        self.only_lib_ranges
+            .retain(|range| range.start < start_address || range.end > end_address);
+        self.lib_mapping_ops.push(
+            timestamp,
+            LibMappingOp::RemoveRange(LibMappingRemoveRange {
+                start_avma: start_address,
+                end_avma: end_address,
+            }),
+        );
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn add_lib_mapping_for_injected_jit_lib(
         &mut self,
@@ -348,6 +393,32 @@ where
         );
     }
 
+    /// Updates the live memory counter of --alloc for a sampled allocation
+    /// (positive size) or free (negative size), and returns the counter and
+    /// the delta by which its value changed. The value never drops below
+    /// zero, e.g. for frees in a forked child of memory which the parent
+    /// allocated.
+    pub fn update_sampled_live_memory(
+        &mut self,
+        profile: &mut Profile,
+        size: i64,
+    ) -> (CounterHandle, i64) {
+        let counter = *self.sampled_live_memory_counter.get_or_insert_with(|| {
+            let counter = profile.add_counter(
+                self.profile_process,
+                "Live memory (sampled)",
+                "Memory",
+                "Bytes in sampled allocations which haven't been freed yet",
+            );
+            profile.set_counter_graph_type(counter, CounterGraphType::Stack);
+            counter
+        });
+        let new_live_bytes = (self.sampled_live_bytes + size).max(0);
+        let delta = new_live_bytes - self.sampled_live_bytes;
+        self.sampled_live_bytes = new_live_bytes;
+        (counter, delta)
+    }
+
     pub fn get_or_make_mem_counter(&mut self, profile: &mut Profile) -> CounterHandle {
         *self.mem_counter.get_or_insert_with(|| {
             profile.add_counter(
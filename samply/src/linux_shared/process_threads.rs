@@ -172,14 +172,7 @@ impl ProcessThreads {
                 false,
             );
             let thread_label = make_thread_label(profile, None, self.pid, tid);
-            Thread {
-                profile_thread,
-                context_switch_data: Default::default(),
-                last_sample_timestamp: None,
-                off_cpu_stack: None,
-                name: None,
-                thread_label,
-            }
+            Thread::new(profile_thread, thread_label, None)
         })
     }
 
@@ -2,7 +2,7 @@ use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 
 use framehop::Unwinder;
-use fxprof_processed_profile::{Category, CategoryColor, Profile, Timestamp};
+use fxprof_processed_profile::{Category, CategoryColor, Profile, ThreadHandle, Timestamp};
 
 use super::process::Process;
 use super::process_threads::make_thread_label;
@@ -174,6 +174,13 @@ where
         })
     }
 
+    /// The main threads of the processes which are currently alive.
+    pub fn main_threads(&self) -> impl Iterator<Item = ThreadHandle> + '_ {
+        self.processes_by_pid
+            .values()
+            .map(|process| process.threads.main_thread.profile_thread)
+    }
+
     pub fn remove(
         &mut self,
         pid: i32,
@@ -0,0 +1,116 @@
+use byteorder::ByteOrder;
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+/// The `prev_state` bit for a thread which is sleeping interruptibly, e.g. while
+/// waiting for a lock, a timer or network I/O.
+const TASK_INTERRUPTIBLE: i64 = 0x1;
+
+/// The `prev_state` bit for a thread which is sleeping uninterruptibly, which
+/// is usually a wait for disk I/O.
+const TASK_UNINTERRUPTIBLE: i64 = 0x2;
+
+/// What a thread was doing while it was off-CPU. This is used to label the
+/// samples which fill a thread's off-CPU time in `--wall-clock` mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffCpuState {
+    /// The thread was sleeping until something woke it up.
+    Blocked,
+    /// The thread was in an uninterruptible sleep, usually waiting for disk I/O.
+    BlockedUninterruptible,
+    /// The thread was preempted and was waiting for a CPU.
+    Runnable,
+}
+
+impl OffCpuState {
+    pub fn label(self) -> &'static str {
+        match self {
+            OffCpuState::Blocked => "Blocked",
+            OffCpuState::BlockedUninterruptible => "Blocked (uninterruptible)",
+            OffCpuState::Runnable => "Runnable",
+        }
+    }
+
+    fn from_prev_state(prev_state: i64) -> Self {
+        if prev_state & TASK_UNINTERRUPTIBLE != 0 {
+            OffCpuState::BlockedUninterruptible
+        } else if prev_state & TASK_INTERRUPTIBLE != 0 {
+            OffCpuState::Blocked
+        } else {
+            OffCpuState::Runnable
+        }
+    }
+}
+
+/// The part of the sched:sched_switch tracepoint data which we need.
+///
+/// ```text
+/// # cat /sys/kernel/tracing/events/sched/sched_switch/format
+/// name: sched_switch
+/// ID: 316
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:char prev_comm[16];       offset:8;       size:16;        signed:0;
+///         field:pid_t prev_pid;   offset:24;      size:4; signed:1;
+///         field:int prev_prio;    offset:28;      size:4; signed:1;
+///         field:long prev_state;  offset:32;      size:8; signed:1;
+///         field:char next_comm[16];       offset:40;      size:16;        signed:0;
+///         field:pid_t next_pid;   offset:56;      size:4; signed:1;
+///         field:int next_prio;    offset:60;      size:4; signed:1;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedSwitch {
+    pub prev_pid: i32,
+    pub prev_state: i64,
+}
+
+impl SchedSwitch {
+    pub fn parse(data: RawData, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    pub fn parse_impl<O: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        data.skip(24)?;
+        let prev_pid = data.read_i32::<O>()?;
+        let _prev_prio = data.read_i32::<O>()?;
+        let prev_state = data.read_u64::<O>()? as i64;
+        Ok(SchedSwitch {
+            prev_pid,
+            prev_state,
+        })
+    }
+
+    pub fn off_cpu_state(&self) -> OffCpuState {
+        OffCpuState::from_prev_state(self.prev_state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_sched_switch() {
+        let mut data = vec![0u8; 64];
+        data[8..12].copy_from_slice(b"main");
+        data[24..28].copy_from_slice(&1234i32.to_le_bytes());
+        data[28..32].copy_from_slice(&120i32.to_le_bytes());
+        data[32..40].copy_from_slice(&2i64.to_le_bytes());
+        let sched_switch =
+            SchedSwitch::parse(RawData::Single(&data), Endianness::LittleEndian).unwrap();
+        assert_eq!(sched_switch.prev_pid, 1234);
+        assert_eq!(
+            sched_switch.off_cpu_state(),
+            OffCpuState::BlockedUninterruptible
+        );
+        assert_eq!(OffCpuState::from_prev_state(1), OffCpuState::Blocked);
+        assert_eq!(OffCpuState::from_prev_state(0), OffCpuState::Runnable);
+    }
+}
@@ -0,0 +1,80 @@
+use byteorder::ByteOrder;
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+/// The signals which terminate a process with a core dump when they're not
+/// handled, i.e. the ones which usually mean that the process crashed. The
+/// numbers are the same on x86_64 and aarch64.
+pub const CRASH_SIGNALS: &[(i32, &str)] = &[
+    (4, "SIGILL"),
+    (5, "SIGTRAP"),
+    (6, "SIGABRT"),
+    (7, "SIGBUS"),
+    (8, "SIGFPE"),
+    (11, "SIGSEGV"),
+    (31, "SIGSYS"),
+];
+
+/// Returns the name of the signal if it's one of the [`CRASH_SIGNALS`].
+pub fn crash_signal_name(sig: i32) -> Option<&'static str> {
+    CRASH_SIGNALS
+        .iter()
+        .find(|(number, _)| *number == sig)
+        .map(|(_, name)| *name)
+}
+
+/// The part of the signal:signal_deliver tracepoint data which we need.
+///
+/// ```text
+/// # cat /sys/kernel/tracing/events/signal/signal_deliver/format
+/// name: signal_deliver
+/// ID: 180
+/// format:
+///         [same common fields as sched_switch]
+///
+///         field:int sig;  offset:8;       size:4; signed:1;
+///         field:int errno;        offset:12;      size:4; signed:1;
+///         field:int code; offset:16;      size:4; signed:1;
+///         field:unsigned long sa_handler; offset:24;      size:8; signed:0;
+///         field:unsigned long sa_flags;   offset:32;      size:8; signed:0;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalDeliver {
+    pub sig: i32,
+    /// The si_code, e.g. SEGV_MAPERR (1) or SEGV_ACCERR (2) for a SIGSEGV.
+    pub code: i32,
+}
+
+impl SignalDeliver {
+    pub fn parse(data: RawData, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    pub fn parse_impl<O: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        data.skip(8)?;
+        let sig = data.read_i32::<O>()?;
+        let _errno = data.read_i32::<O>()?;
+        let code = data.read_i32::<O>()?;
+        Ok(SignalDeliver { sig, code })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_signal_deliver() {
+        let mut data = vec![0u8; 40];
+        data[8..12].copy_from_slice(&11i32.to_le_bytes());
+        data[16..20].copy_from_slice(&1i32.to_le_bytes());
+        let signal =
+            SignalDeliver::parse(RawData::Single(&data), Endianness::LittleEndian).unwrap();
+        assert_eq!(signal, SignalDeliver { sig: 11, code: 1 });
+        assert_eq!(crash_signal_name(signal.sig), Some("SIGSEGV"));
+        assert_eq!(crash_signal_name(2), None);
+    }
+}
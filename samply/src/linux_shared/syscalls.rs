@@ -0,0 +1,410 @@
+use byteorder::ByteOrder;
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+/// The syscalls which `--syscalls` traces if no list of syscalls is given.
+/// Names which don't exist on the current architecture, e.g. poll on aarch64,
+/// are skipped.
+pub const DEFAULT_TRACED_SYSCALLS: &[&str] = &[
+    "read",
+    "write",
+    "pread64",
+    "pwrite64",
+    "readv",
+    "writev",
+    "openat",
+    "fsync",
+    "fdatasync",
+    "futex",
+    "poll",
+    "ppoll",
+    "select",
+    "pselect6",
+    "epoll_wait",
+    "epoll_pwait",
+    "nanosleep",
+    "clock_nanosleep",
+    "connect",
+    "accept4",
+    "sendto",
+    "recvfrom",
+    "sendmsg",
+    "recvmsg",
+];
+
+/// Syscall numbers on x86_64, from arch/x86/entry/syscalls/syscall_64.tbl.
+/// This only covers the more commonly used syscalls; others show up as
+/// "syscall <number>".
+pub const SYSCALLS_X86_64: &[(u64, &str)] = &[
+    (0, "read"),
+    (1, "write"),
+    (2, "open"),
+    (3, "close"),
+    (4, "stat"),
+    (5, "fstat"),
+    (6, "lstat"),
+    (7, "poll"),
+    (8, "lseek"),
+    (9, "mmap"),
+    (10, "mprotect"),
+    (11, "munmap"),
+    (12, "brk"),
+    (13, "rt_sigaction"),
+    (14, "rt_sigprocmask"),
+    (15, "rt_sigreturn"),
+    (16, "ioctl"),
+    (17, "pread64"),
+    (18, "pwrite64"),
+    (19, "readv"),
+    (20, "writev"),
+    (21, "access"),
+    (22, "pipe"),
+    (23, "select"),
+    (24, "sched_yield"),
+    (25, "mremap"),
+    (26, "msync"),
+    (27, "mincore"),
+    (28, "madvise"),
+    (32, "dup"),
+    (33, "dup2"),
+    (34, "pause"),
+    (35, "nanosleep"),
+    (39, "getpid"),
+    (40, "sendfile"),
+    (41, "socket"),
+    (42, "connect"),
+    (43, "accept"),
+    (44, "sendto"),
+    (45, "recvfrom"),
+    (46, "sendmsg"),
+    (47, "recvmsg"),
+    (48, "shutdown"),
+    (49, "bind"),
+    (50, "listen"),
+    (51, "getsockname"),
+    (52, "getpeername"),
+    (53, "socketpair"),
+    (54, "setsockopt"),
+    (55, "getsockopt"),
+    (56, "clone"),
+    (57, "fork"),
+    (58, "vfork"),
+    (59, "execve"),
+    (60, "exit"),
+    (61, "wait4"),
+    (62, "kill"),
+    (63, "uname"),
+    (72, "fcntl"),
+    (73, "flock"),
+    (74, "fsync"),
+    (75, "fdatasync"),
+    (76, "truncate"),
+    (77, "ftruncate"),
+    (78, "getdents"),
+    (79, "getcwd"),
+    (80, "chdir"),
+    (82, "rename"),
+    (83, "mkdir"),
+    (84, "rmdir"),
+    (87, "unlink"),
+    (89, "readlink"),
+    (90, "chmod"),
+    (96, "gettimeofday"),
+    (97, "getrlimit"),
+    (131, "sigaltstack"),
+    (137, "statfs"),
+    (138, "fstatfs"),
+    (157, "prctl"),
+    (158, "arch_prctl"),
+    (186, "gettid"),
+    (200, "tkill"),
+    (202, "futex"),
+    (203, "sched_setaffinity"),
+    (204, "sched_getaffinity"),
+    (217, "getdents64"),
+    (218, "set_tid_address"),
+    (221, "fadvise64"),
+    (228, "clock_gettime"),
+    (230, "clock_nanosleep"),
+    (231, "exit_group"),
+    (232, "epoll_wait"),
+    (233, "epoll_ctl"),
+    (234, "tgkill"),
+    (247, "waitid"),
+    (257, "openat"),
+    (262, "newfstatat"),
+    (263, "unlinkat"),
+    (270, "pselect6"),
+    (271, "ppoll"),
+    (273, "set_robust_list"),
+    (275, "splice"),
+    (281, "epoll_pwait"),
+    (285, "fallocate"),
+    (288, "accept4"),
+    (290, "eventfd2"),
+    (291, "epoll_create1"),
+    (292, "dup3"),
+    (293, "pipe2"),
+    (299, "recvmmsg"),
+    (302, "prlimit64"),
+    (307, "sendmmsg"),
+    (318, "getrandom"),
+    (319, "memfd_create"),
+    (326, "copy_file_range"),
+    (332, "statx"),
+    (334, "rseq"),
+    (425, "io_uring_setup"),
+    (426, "io_uring_enter"),
+    (427, "io_uring_register"),
+    (434, "pidfd_open"),
+    (435, "clone3"),
+    (436, "close_range"),
+    (439, "faccessat2"),
+    (441, "epoll_pwait2"),
+];
+
+/// Syscall numbers on aarch64, from include/uapi/asm-generic/unistd.h.
+/// This only covers the more commonly used syscalls; others show up as
+/// "syscall <number>".
+pub const SYSCALLS_AARCH64: &[(u64, &str)] = &[
+    (17, "getcwd"),
+    (19, "eventfd2"),
+    (20, "epoll_create1"),
+    (21, "epoll_ctl"),
+    (22, "epoll_pwait"),
+    (23, "dup"),
+    (24, "dup3"),
+    (25, "fcntl"),
+    (29, "ioctl"),
+    (32, "flock"),
+    (34, "mkdirat"),
+    (35, "unlinkat"),
+    (38, "renameat"),
+    (43, "statfs"),
+    (44, "fstatfs"),
+    (45, "truncate"),
+    (46, "ftruncate"),
+    (47, "fallocate"),
+    (48, "faccessat"),
+    (49, "chdir"),
+    (56, "openat"),
+    (57, "close"),
+    (59, "pipe2"),
+    (61, "getdents64"),
+    (62, "lseek"),
+    (63, "read"),
+    (64, "write"),
+    (65, "readv"),
+    (66, "writev"),
+    (67, "pread64"),
+    (68, "pwrite64"),
+    (71, "sendfile"),
+    (72, "pselect6"),
+    (73, "ppoll"),
+    (76, "splice"),
+    (78, "readlinkat"),
+    (79, "newfstatat"),
+    (80, "fstat"),
+    (82, "fsync"),
+    (83, "fdatasync"),
+    (93, "exit"),
+    (94, "exit_group"),
+    (95, "waitid"),
+    (96, "set_tid_address"),
+    (98, "futex"),
+    (99, "set_robust_list"),
+    (101, "nanosleep"),
+    (113, "clock_gettime"),
+    (115, "clock_nanosleep"),
+    (122, "sched_setaffinity"),
+    (123, "sched_getaffinity"),
+    (124, "sched_yield"),
+    (129, "kill"),
+    (130, "tkill"),
+    (131, "tgkill"),
+    (132, "sigaltstack"),
+    (134, "rt_sigaction"),
+    (135, "rt_sigprocmask"),
+    (139, "rt_sigreturn"),
+    (160, "uname"),
+    (163, "getrlimit"),
+    (167, "prctl"),
+    (169, "gettimeofday"),
+    (172, "getpid"),
+    (178, "gettid"),
+    (198, "socket"),
+    (199, "socketpair"),
+    (200, "bind"),
+    (201, "listen"),
+    (202, "accept"),
+    (203, "connect"),
+    (204, "getsockname"),
+    (205, "getpeername"),
+    (206, "sendto"),
+    (207, "recvfrom"),
+    (208, "setsockopt"),
+    (209, "getsockopt"),
+    (210, "shutdown"),
+    (211, "sendmsg"),
+    (212, "recvmsg"),
+    (214, "brk"),
+    (215, "munmap"),
+    (216, "mremap"),
+    (220, "clone"),
+    (221, "execve"),
+    (222, "mmap"),
+    (223, "fadvise64"),
+    (226, "mprotect"),
+    (227, "msync"),
+    (232, "mincore"),
+    (233, "madvise"),
+    (242, "accept4"),
+    (243, "recvmmsg"),
+    (260, "wait4"),
+    (261, "prlimit64"),
+    (269, "sendmmsg"),
+    (278, "getrandom"),
+    (279, "memfd_create"),
+    (285, "copy_file_range"),
+    (291, "statx"),
+    (293, "rseq"),
+    (425, "io_uring_setup"),
+    (426, "io_uring_enter"),
+    (427, "io_uring_register"),
+    (434, "pidfd_open"),
+    (435, "clone3"),
+    (436, "close_range"),
+    (439, "faccessat2"),
+    (441, "epoll_pwait2"),
+];
+
+pub fn syscall_name(table: &[(u64, &'static str)], id: u64) -> Option<&'static str> {
+    let index = table.binary_search_by_key(&id, |(id, _)| *id).ok()?;
+    Some(table[index].1)
+}
+
+#[allow(unused)]
+pub fn syscall_id(table: &[(u64, &str)], name: &str) -> Option<u64> {
+    table
+        .iter()
+        .find(|(_, syscall_name)| *syscall_name == name)
+        .map(|(id, _)| *id)
+}
+
+/// The fields of the raw_syscalls:sys_enter and raw_syscalls:sys_exit
+/// tracepoints that we care about.
+///
+/// ```
+/// # cat /sys/kernel/tracing/events/raw_syscalls/sys_enter/format
+/// name: sys_enter
+/// ID: 22
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:long id;  offset:8;       size:8; signed:1;
+///         field:unsigned long args[6];    offset:16;      size:48;        signed:0;
+///
+/// # cat /sys/kernel/tracing/events/raw_syscalls/sys_exit/format
+/// name: sys_exit
+/// ID: 21
+/// format:
+///         [same common fields]
+///
+///         field:long id;  offset:8;       size:8; signed:1;
+///         field:long ret; offset:16;      size:8; signed:1;
+/// ```
+#[derive(Debug)]
+pub struct RawSyscall {
+    pub common_type: u16,
+    pub id: i64,
+    /// The second field after the id. This is the return value for sys_exit,
+    /// and the first argument for sys_enter.
+    pub ret_or_first_arg: i64,
+}
+
+impl RawSyscall {
+    pub fn parse(data: RawData, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    pub fn parse_impl<O: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        let common_type = data.read_u16::<O>()?;
+        let _common_flags = data.read_u8()?;
+        let _common_preempt_count = data.read_u8()?;
+        let _common_pid = data.read_i32::<O>()?;
+        let id = data.read_u64::<O>()? as i64;
+        let ret_or_first_arg = data.read_u64::<O>()? as i64;
+        Ok(RawSyscall {
+            common_type,
+            id,
+            ret_or_first_arg,
+        })
+    }
+}
+
+/// The fields of the syscalls:sys_enter_munmap tracepoint that we care about.
+///
+/// ```
+/// # cat /sys/kernel/tracing/events/syscalls/sys_enter_munmap/format
+/// name: sys_enter_munmap
+/// ID: 758
+/// format:
+///         [same common fields]
+///
+///         field:int __syscall_nr; offset:8;       size:4; signed:1;
+///         field:unsigned long addr;       offset:16;      size:8; signed:0;
+///         field:size_t len;       offset:24;      size:8; signed:0;
+/// ```
+#[derive(Debug)]
+pub struct MunmapArgs {
+    pub addr: u64,
+    pub len: u64,
+}
+
+impl MunmapArgs {
+    pub fn parse(data: RawData, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    pub fn parse_impl<O: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        // Skip the common fields and __syscall_nr, which is padded to 8 bytes.
+        data.skip(16)?;
+        let addr = data.read_u64::<O>()?;
+        let len = data.read_u64::<O>()?;
+        Ok(MunmapArgs { addr, len })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn syscall_tables_are_sorted() {
+        for table in [SYSCALLS_X86_64, SYSCALLS_AARCH64] {
+            assert!(table.windows(2).all(|w| w[0].0 < w[1].0));
+        }
+        assert_eq!(syscall_name(SYSCALLS_X86_64, 202), Some("futex"));
+        assert_eq!(syscall_name(SYSCALLS_AARCH64, 98), Some("futex"));
+        assert_eq!(syscall_id(SYSCALLS_AARCH64, "poll"), None);
+    }
+
+    #[test]
+    fn parse_munmap_args() {
+        let mut bytes = vec![0; 16];
+        bytes.extend_from_slice(&0x7f00_0000_1000u64.to_le_bytes());
+        bytes.extend_from_slice(&0x2000u64.to_le_bytes());
+        let args = MunmapArgs::parse(RawData::Single(&bytes), Endianness::LittleEndian).unwrap();
+        assert_eq!(args.addr, 0x7f00_0000_1000);
+        assert_eq!(args.len, 0x2000);
+    }
+}
@@ -2,6 +2,7 @@ use std::fmt::Debug;
 
 use fxprof_processed_profile::{Profile, StringHandle, ThreadHandle, Timestamp};
 
+use super::sched_switch::OffCpuState;
 use crate::shared::context_switch::ThreadContextSwitchData;
 use crate::shared::unresolved_samples::UnresolvedStackHandle;
 
@@ -10,11 +11,19 @@ pub struct Thread {
     pub profile_thread: ThreadHandle,
     pub context_switch_data: ThreadContextSwitchData,
     pub last_sample_timestamp: Option<u64>,
+    /// The CPU on which the most recent sample of this thread was taken.
+    pub last_sample_cpu: Option<u32>,
+    /// The id and the start timestamp of the syscall that this thread is
+    /// currently in, between raw_syscalls:sys_enter and sys_exit.
+    pub pending_syscall: Option<(i64, u64)>,
 
     /// Some() between sched_switch and the next context switch IN
     ///
     /// Refers to a stack in the containing Process's UnresolvedSamples stack table.
     pub off_cpu_stack: Option<UnresolvedStackHandle>,
+    /// Some() between a switch-out and the next switch-in, if off-CPU states
+    /// are annotated (`--wall-clock`).
+    pub off_cpu_state: Option<OffCpuState>,
     pub name: Option<String>,
     pub thread_label: StringHandle,
 }
@@ -29,7 +38,10 @@ impl Thread {
             profile_thread: thread_handle,
             context_switch_data: Default::default(),
             last_sample_timestamp: None,
+            last_sample_cpu: None,
+            pending_syscall: None,
             off_cpu_stack: None,
+            off_cpu_state: None,
             name,
             thread_label,
         }
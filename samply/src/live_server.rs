@@ -0,0 +1,152 @@
+//! The server for `samply record --live`, which serves the profile while it is
+//! still being recorded. The recorder regularly replaces the output file with
+//! a snapshot of everything recorded so far, and the profiler tab shows the
+//! latest snapshot when it is reloaded.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::oneshot;
+use wholesym::SymbolManager;
+
+use crate::profile_json_preparse::parse_libinfo_map_from_profile_file;
+use crate::server::{start_server, RunningServerInfo, ServerProps};
+use crate::shared::ctrl_c::CtrlC;
+use crate::shared::prop_types::SymbolProps;
+use crate::symbols::create_symbol_manager_and_quota_manager;
+
+pub struct LiveServer {
+    thread: JoinHandle<()>,
+    stop_sender: oneshot::Sender<()>,
+}
+
+impl LiveServer {
+    /// Starts serving the profile at `profile_path` on a background thread,
+    /// before the recording has started.
+    ///
+    /// Every `refresh_interval`, the server checks whether the file has been
+    /// replaced, and learns about the libraries in it, so that it can
+    /// symbolicate them.
+    pub fn start(
+        profile_path: &Path,
+        server_props: ServerProps,
+        symbol_props: SymbolProps,
+        refresh_interval: Duration,
+    ) -> Self {
+        // A profile from an earlier recording would be served until the first
+        // snapshot exists. The recording replaces the file anyway.
+        let _ = std::fs::remove_file(profile_path);
+
+        let profile_path = profile_path.to_owned();
+        let (stop_sender, stop_receiver) = oneshot::channel();
+        let thread = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+
+            runtime.block_on(async {
+                let (symbol_manager, quota_manager) =
+                    create_symbol_manager_and_quota_manager(symbol_props, server_props.verbose);
+                let symbol_manager = Arc::new(symbol_manager);
+                let open_in_browser = server_props.open_in_browser;
+
+                let RunningServerInfo {
+                    server_join_handle,
+                    server_origin,
+                    profiler_urls,
+                    ..
+                } = start_server(
+                    std::slice::from_ref(&profile_path),
+                    server_props,
+                    symbol_manager.clone(),
+                    stop_receiver,
+                )
+                .await;
+
+                eprintln!("Live server listening at {server_origin}");
+                let profiler_url = profiler_urls.into_iter().next();
+                if !open_in_browser {
+                    if let Some(profiler_url) = &profiler_url {
+                        println!("{profiler_url}");
+                    }
+                }
+
+                let refresh_task = tokio::spawn(refresh_known_libraries(
+                    profile_path,
+                    symbol_manager,
+                    refresh_interval,
+                    profiler_url.filter(|_| open_in_browser),
+                ));
+
+                // Run this server until it stops.
+                if let Err(e) = server_join_handle.await {
+                    eprintln!("server error: {e}");
+                }
+                refresh_task.abort();
+
+                if let Some(quota_manager) = quota_manager {
+                    quota_manager.finish().await;
+                }
+            });
+        });
+
+        Self {
+            thread,
+            stop_sender,
+        }
+    }
+
+    /// Keeps serving the final profile after the recording has stopped, until
+    /// Ctrl+C is pressed.
+    pub fn run_until_ctrl_c(self) {
+        let ctrl_c_receiver = CtrlC::observe_oneshot();
+        eprintln!("Press Ctrl+C to stop.");
+        let _ = ctrl_c_receiver.blocking_recv();
+        let _ = self.stop_sender.send(());
+        let _ = self.thread.join();
+    }
+}
+
+/// Adds the libraries of each new snapshot of the profile to the symbol
+/// manager's known libraries. Opens `profiler_url_to_open` in the browser once
+/// the first snapshot exists, so that the profiler doesn't load a missing file.
+async fn refresh_known_libraries(
+    profile_path: PathBuf,
+    symbol_manager: Arc<SymbolManager>,
+    refresh_interval: Duration,
+    mut profiler_url_to_open: Option<String>,
+) {
+    let mut last_modified: Option<SystemTime> = None;
+    loop {
+        tokio::time::sleep(refresh_interval).await;
+
+        let Ok(modified) = std::fs::metadata(&profile_path).and_then(|m| m.modified()) else {
+            continue;
+        };
+        if last_modified == Some(modified) {
+            continue;
+        }
+
+        let path = profile_path.clone();
+        let libinfo_map = tokio::task::spawn_blocking(move || {
+            parse_libinfo_map_from_profile_file(File::open(path)?)
+        })
+        .await;
+        let Ok(Ok(libinfo_map)) = libinfo_map else {
+            // The file may have been replaced while we read it. Try again later.
+            continue;
+        };
+        last_modified = Some(modified);
+        for lib_info in libinfo_map.into_values() {
+            symbol_manager.add_known_library(lib_info);
+        }
+
+        if let Some(profiler_url) = profiler_url_to_open.take() {
+            let _ = opener::open_browser(profiler_url);
+        }
+    }
+}
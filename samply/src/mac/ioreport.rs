@@ -0,0 +1,308 @@
+//! Energy counters from IOReport, the private framework which powermetrics uses
+//! to read the "Energy Model" channels on Apple Silicon, for example "CPU Energy"
+//! and "GPU Energy". Unlike powermetrics, reading these channels doesn't require
+//! root.
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::{io, mem};
+
+use crossbeam_channel::{Receiver, Sender};
+
+use super::time::get_monotonic_timestamp;
+
+/// How often the energy counters are read.
+const SAMPLING_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The IOReport group which contains the energy counters.
+const ENERGY_MODEL_GROUP: &str = "Energy Model";
+
+type CFTypeRef = *const c_void;
+type CFStringRef = *const c_void;
+type CFArrayRef = *const c_void;
+type CFDictionaryRef = *const c_void;
+type CFMutableDictionaryRef = *mut c_void;
+type CFAllocatorRef = *const c_void;
+type CFIndex = isize;
+type IOReportSubscriptionRef = *const c_void;
+
+const K_CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRelease(cf: CFTypeRef);
+    fn CFStringCreateWithCString(
+        alloc: CFAllocatorRef,
+        c_str: *const c_char,
+        encoding: u32,
+    ) -> CFStringRef;
+    fn CFStringGetCString(
+        string: CFStringRef,
+        buffer: *mut c_char,
+        buffer_size: CFIndex,
+        encoding: u32,
+    ) -> u8;
+    fn CFDictionaryGetValue(dict: CFDictionaryRef, key: *const c_void) -> *const c_void;
+    fn CFDictionaryGetCount(dict: CFDictionaryRef) -> CFIndex;
+    fn CFDictionaryCreateMutableCopy(
+        alloc: CFAllocatorRef,
+        capacity: CFIndex,
+        dict: CFDictionaryRef,
+    ) -> CFMutableDictionaryRef;
+    fn CFArrayGetCount(array: CFArrayRef) -> CFIndex;
+    fn CFArrayGetValueAtIndex(array: CFArrayRef, index: CFIndex) -> *const c_void;
+}
+
+#[link(name = "IOReport")]
+extern "C" {
+    fn IOReportCopyChannelsInGroup(
+        group: CFStringRef,
+        subgroup: CFStringRef,
+        a: u64,
+        b: u64,
+        c: u64,
+    ) -> CFDictionaryRef;
+    fn IOReportCreateSubscription(
+        a: *const c_void,
+        desired_channels: CFMutableDictionaryRef,
+        subscribed_channels: *mut CFMutableDictionaryRef,
+        channel_id: u64,
+        b: CFTypeRef,
+    ) -> IOReportSubscriptionRef;
+    fn IOReportCreateSamples(
+        subscription: IOReportSubscriptionRef,
+        subscribed_channels: CFMutableDictionaryRef,
+        a: CFTypeRef,
+    ) -> CFDictionaryRef;
+    fn IOReportCreateSamplesDelta(
+        previous: CFDictionaryRef,
+        current: CFDictionaryRef,
+        a: CFTypeRef,
+    ) -> CFDictionaryRef;
+    fn IOReportChannelGetGroup(channel: CFDictionaryRef) -> CFStringRef;
+    fn IOReportChannelGetChannelName(channel: CFDictionaryRef) -> CFStringRef;
+    fn IOReportChannelGetUnitLabel(channel: CFDictionaryRef) -> CFStringRef;
+    fn IOReportSimpleGetIntegerValue(channel: CFDictionaryRef, a: i32) -> i64;
+}
+
+/// Releases the wrapped CoreFoundation object when dropped.
+struct CfOwned(CFTypeRef);
+
+impl CfOwned {
+    fn new(cf: CFTypeRef) -> Option<Self> {
+        if cf.is_null() {
+            None
+        } else {
+            Some(CfOwned(cf))
+        }
+    }
+}
+
+impl Drop for CfOwned {
+    fn drop(&mut self) {
+        unsafe { CFRelease(self.0) };
+    }
+}
+
+fn cf_string(s: &str) -> CfOwned {
+    let c_string = CString::new(s).unwrap();
+    let cf = unsafe {
+        CFStringCreateWithCString(ptr::null(), c_string.as_ptr(), K_CF_STRING_ENCODING_UTF8)
+    };
+    CfOwned::new(cf).expect("CFStringCreateWithCString failed")
+}
+
+fn string_from_cf(cf: CFStringRef) -> Option<String> {
+    if cf.is_null() {
+        return None;
+    }
+    let mut buffer = [0 as c_char; 128];
+    let ok = unsafe {
+        CFStringGetCString(
+            cf,
+            buffer.as_mut_ptr(),
+            buffer.len() as CFIndex,
+            K_CF_STRING_ENCODING_UTF8,
+        )
+    };
+    if ok == 0 {
+        return None;
+    }
+    let c_str = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+    Some(c_str.to_string_lossy().into_owned())
+}
+
+/// An IOReport subscription to the channels of the "Energy Model" group.
+struct EnergyModelSubscription {
+    subscription: CfOwned,
+    subscribed_channels: CfOwned,
+    last_sample: CfOwned,
+}
+
+impl EnergyModelSubscription {
+    fn new() -> io::Result<Self> {
+        let group = cf_string(ENERGY_MODEL_GROUP);
+        let channels =
+            CfOwned::new(unsafe { IOReportCopyChannelsInGroup(group.0, ptr::null(), 0, 0, 0) })
+                .filter(|channels| unsafe { CFDictionaryGetCount(channels.0) } > 0)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "IOReport has no \"Energy Model\" channels on this machine",
+                    )
+                })?;
+        let desired_channels = CfOwned::new(unsafe {
+            CFDictionaryCreateMutableCopy(ptr::null(), 0, channels.0) as CFTypeRef
+        })
+        .ok_or_else(|| io::Error::other("CFDictionaryCreateMutableCopy failed"))?;
+
+        let mut subscribed_channels: CFMutableDictionaryRef = ptr::null_mut();
+        let subscription = CfOwned::new(unsafe {
+            IOReportCreateSubscription(
+                ptr::null(),
+                desired_channels.0 as CFMutableDictionaryRef,
+                &mut subscribed_channels,
+                0,
+                ptr::null(),
+            )
+        })
+        .ok_or_else(|| io::Error::other("IOReportCreateSubscription failed"))?;
+        let subscribed_channels = CfOwned::new(subscribed_channels as CFTypeRef)
+            .ok_or_else(|| io::Error::other("IOReportCreateSubscription returned no channels"))?;
+
+        let last_sample = Self::create_sample(&subscription, &subscribed_channels)?;
+        Ok(EnergyModelSubscription {
+            subscription,
+            subscribed_channels,
+            last_sample,
+        })
+    }
+
+    fn create_sample(subscription: &CfOwned, subscribed_channels: &CfOwned) -> io::Result<CfOwned> {
+        CfOwned::new(unsafe {
+            IOReportCreateSamples(
+                subscription.0,
+                subscribed_channels.0 as CFMutableDictionaryRef,
+                ptr::null(),
+            )
+        })
+        .ok_or_else(|| io::Error::other("IOReportCreateSamples failed"))
+    }
+
+    /// Calls `f` with the name and the energy in microjoules of every channel,
+    /// consumed since the previous call.
+    fn read_energy_deltas_uj(&mut self, mut f: impl FnMut(String, u64)) -> io::Result<()> {
+        let sample = Self::create_sample(&self.subscription, &self.subscribed_channels)?;
+        let last_sample = mem::replace(&mut self.last_sample, sample);
+        let delta = CfOwned::new(unsafe {
+            IOReportCreateSamplesDelta(last_sample.0, self.last_sample.0, ptr::null())
+        })
+        .ok_or_else(|| io::Error::other("IOReportCreateSamplesDelta failed"))?;
+
+        let channels_key = cf_string("IOReportChannels");
+        let channels = unsafe { CFDictionaryGetValue(delta.0, channels_key.0) };
+        if channels.is_null() {
+            return Ok(());
+        }
+        for i in 0..unsafe { CFArrayGetCount(channels) } {
+            let channel = unsafe { CFArrayGetValueAtIndex(channels, i) };
+            let group = string_from_cf(unsafe { IOReportChannelGetGroup(channel) });
+            if group.as_deref() != Some(ENERGY_MODEL_GROUP) {
+                continue;
+            }
+            let Some(name) = string_from_cf(unsafe { IOReportChannelGetChannelName(channel) })
+            else {
+                continue;
+            };
+            let unit = string_from_cf(unsafe { IOReportChannelGetUnitLabel(channel) });
+            let value = unsafe { IOReportSimpleGetIntegerValue(channel, 0) }.max(0) as u64;
+            let energy_uj = match unit.as_deref().map(str::trim) {
+                Some("mJ") => value * 1000,
+                Some("uJ") => value,
+                Some("nJ") => value / 1000,
+                _ => continue,
+            };
+            f(name, energy_uj);
+        }
+        Ok(())
+    }
+}
+
+/// The energy consumed by one IOReport channel during the recording.
+pub struct EnergySamples {
+    pub channel_name: String,
+    /// (mach_absolute_time timestamp in nanoseconds, energy in microjoules
+    /// consumed since the previous sample)
+    pub samples: Vec<(u64, u64)>,
+}
+
+/// Reads the IOReport energy counters at a fixed interval on a separate thread,
+/// until it is stopped.
+pub struct PowerSampler {
+    stop_sender: Sender<()>,
+    thread: JoinHandle<Vec<EnergySamples>>,
+}
+
+impl PowerSampler {
+    /// Fails if IOReport has no energy channels, which is the case on Intel Macs.
+    pub fn start() -> io::Result<Self> {
+        let (stop_sender, stop_receiver) = crossbeam_channel::bounded(1);
+        let (started_sender, started_receiver) = crossbeam_channel::bounded(1);
+        // The subscription is created on the sampling thread because the
+        // CoreFoundation objects it consists of aren't Send.
+        let thread = thread::spawn(move || {
+            let subscription = match EnergyModelSubscription::new() {
+                Ok(subscription) => {
+                    let _ = started_sender.send(Ok(()));
+                    subscription
+                }
+                Err(err) => {
+                    let _ = started_sender.send(Err(err));
+                    return Vec::new();
+                }
+            };
+            sample_channels(subscription, stop_receiver)
+        });
+        started_receiver
+            .recv()
+            .unwrap_or_else(|_| Err(io::Error::other("the IOReport thread panicked")))?;
+        Ok(PowerSampler {
+            stop_sender,
+            thread,
+        })
+    }
+
+    pub fn stop(self) -> Vec<EnergySamples> {
+        let _ = self.stop_sender.send(());
+        self.thread.join().unwrap_or_default()
+    }
+}
+
+fn sample_channels(
+    mut subscription: EnergyModelSubscription,
+    stop_receiver: Receiver<()>,
+) -> Vec<EnergySamples> {
+    let mut energy_samples: Vec<EnergySamples> = Vec::new();
+    while stop_receiver.recv_timeout(SAMPLING_INTERVAL).is_err() {
+        let timestamp = get_monotonic_timestamp();
+        let _ = subscription.read_energy_deltas_uj(|channel_name, energy_uj| {
+            let index = match energy_samples
+                .iter()
+                .position(|samples| samples.channel_name == channel_name)
+            {
+                Some(index) => index,
+                None => {
+                    energy_samples.push(EnergySamples {
+                        channel_name,
+                        samples: Vec::new(),
+                    });
+                    energy_samples.len() - 1
+                }
+            };
+            energy_samples[index].samples.push((timestamp, energy_uj));
+        });
+    }
+    energy_samples
+}
@@ -3,6 +3,7 @@ mod dyld_bindings;
 
 pub mod codesign_setup;
 mod error;
+mod ioreport;
 pub mod kernel_error;
 mod mach_ipc;
 mod proc_maps;
@@ -12,5 +13,6 @@ mod sampler;
 mod task_profiler;
 pub mod thread_act;
 pub mod thread_info;
+pub mod thread_policy;
 mod thread_profiler;
 mod time;
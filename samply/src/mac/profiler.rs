@@ -38,8 +38,15 @@ pub fn run(
                 args,
                 iteration_count,
                 ignore_exit_code,
+                capture_output: _,
+                alloc_sample_interval,
             } = process_launch_props;
 
+            if alloc_sample_interval.is_some() {
+                eprintln!("Error: Recording allocations with --alloc is not supported on macOS.");
+                std::process::exit(1)
+            }
+
             let task_launcher = if profile_creation_props.coreclr.any_enabled() {
                 // We need to set DOTNET_PerfMapEnabled=3 in the environment if it's not already set.
                 // If we set it, we'll also set unlink_aux_files=true to avoid leaving files
@@ -1,3 +1,9 @@
+//! The macOS sampler. It only uses public Mach APIs: each sampled thread is
+//! suspended with `thread_suspend`, its registers are read with
+//! `thread_get_state`, and its stack is walked with framehop, which uses the
+//! compact unwind info and __eh_frame of each image and falls back to frame
+//! pointers. No private frameworks are involved.
+
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
@@ -5,17 +11,20 @@ use std::{mem, thread};
 
 use crossbeam_channel::Receiver;
 use fxprof_processed_profile::{
-    Category, CategoryColor, Profile, ReferenceTimestamp, SubcategoryHandle,
+    Category, CategoryColor, MarkerNumberFieldFormat, Profile, ReferenceTimestamp,
+    SubcategoryHandle,
 };
 use mach2::port::mach_port_t;
 
 use super::error::SamplingError;
+use super::ioreport::PowerSampler;
 use super::task_profiler::TaskProfiler;
 use super::time::get_monotonic_timestamp;
 use crate::shared::prop_types::{ProfileCreationProps, RecordingProps};
 use crate::shared::recycling::ProcessRecycler;
 use crate::shared::timestamp_converter::TimestampConverter;
 use crate::shared::unresolved_samples::UnresolvedStacks;
+use crate::shared::utils::microjoules_to_picowatt_hours;
 
 pub enum ProcessSpecificPath {
     Jitdump(PathBuf),
@@ -71,6 +80,8 @@ impl Sampler {
             ReferenceTimestamp::from_system_time(reference_system_time),
             self.recording_props.interval.into(),
         );
+        self.profile_creation_props
+            .apply_thread_name_filter(&mut profile);
         if let Some(macos_name_and_version) = get_macos_name_and_version() {
             profile.set_os_name(&macos_name_and_version);
         }
@@ -108,13 +119,28 @@ impl Sampler {
             self.profile_creation_props.clone(),
         )
         .expect("couldn't create root TaskProfiler");
+        let root_process = root_task.process_handle();
+        let power_sampler = if self.recording_props.power {
+            match PowerSampler::start() {
+                Ok(power_sampler) => Some(power_sampler),
+                Err(err) => {
+                    eprintln!("Could not read the IOReport energy counters: {err}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
 
         let mut process_sample_datas = Vec::new();
         let mut stack_scratch_buffer = Vec::new();
         let mut live_tasks = vec![root_task];
         let mut unwinder_cache = Default::default();
-        let mut unresolved_stacks = UnresolvedStacks::default();
+        let mut unresolved_stacks =
+            UnresolvedStacks::new(self.profile_creation_props.max_stack_depth);
         let mut last_sleep_overshoot = 0;
+        let mut last_sample_mono = None;
+        let mut missed_sample_count = 0;
         let mut stop_profiling = false;
 
         loop {
@@ -175,6 +201,28 @@ impl Sampler {
 
             let sample_timestamp = timestamp_converter.convert_time(sample_mono);
 
+            // We're supposed to wake up once per interval. If we woke up much later,
+            // for example because the machine was too busy, the samples in between
+            // are missing. Mark that time range so that it's not mistaken for idle time.
+            let interval_ns = self.recording_props.interval.as_nanos() as u64;
+            if let Some(last_sample_mono) = last_sample_mono {
+                let missed_count =
+                    ((sample_mono - last_sample_mono) / interval_ns).saturating_sub(1);
+                if missed_count > 0 {
+                    let last_sample_timestamp = timestamp_converter.convert_time(last_sample_mono);
+                    for task in &live_tasks {
+                        task.add_missed_samples_marker(
+                            last_sample_timestamp,
+                            sample_timestamp,
+                            missed_count,
+                            &mut profile,
+                        );
+                    }
+                    missed_sample_count += missed_count;
+                }
+            }
+            last_sample_mono = Some(sample_mono);
+
             let mut tasks = Vec::with_capacity(live_tasks.capacity());
             mem::swap(&mut live_tasks, &mut tasks);
             for mut task in tasks.into_iter() {
@@ -205,8 +253,7 @@ impl Sampler {
                 }
             }
 
-            let intended_wakeup_time =
-                sample_mono + self.recording_props.interval.as_nanos() as u64;
+            let intended_wakeup_time = sample_mono + interval_ns;
             let before_sleep = get_monotonic_timestamp();
             let indended_wait_time = intended_wakeup_time.saturating_sub(before_sleep);
             let sleep_time = indended_wait_time.saturating_sub(last_sleep_overshoot);
@@ -215,6 +262,37 @@ impl Sampler {
             last_sleep_overshoot = actual_sleep_duration.saturating_sub(sleep_time);
         }
 
+        if missed_sample_count > 0 {
+            eprintln!(
+                "Missed {missed_sample_count} samples, because the sampler fell behind. The affected time ranges have \"Lost events\" markers."
+            );
+            profile.add_extra_info_number(
+                "Recording",
+                "Missed samples",
+                MarkerNumberFieldFormat::Integer,
+                missed_sample_count as f64,
+            );
+        }
+
+        if let Some(power_sampler) = power_sampler {
+            for energy_samples in power_sampler.stop() {
+                let counter = profile.add_counter(
+                    root_process,
+                    &energy_samples.channel_name,
+                    "power",
+                    "Energy consumed by this IOReport channel",
+                );
+                for (timestamp_mono, energy_uj) in energy_samples.samples {
+                    profile.add_counter_sample(
+                        counter,
+                        timestamp_converter.convert_time(timestamp_mono),
+                        microjoules_to_picowatt_hours(energy_uj),
+                        1,
+                    );
+                }
+            }
+        }
+
         // Gather the sample data from the remaining live tasks.
         // `live_tasks` can be non-empty if we stopped profiling before all tasks ended,
         // for example because the time limit was reached,
@@ -12,7 +12,7 @@ use framehop::{
 };
 use fxprof_processed_profile::debugid::DebugId;
 use fxprof_processed_profile::{
-    LibraryInfo, ProcessHandle, Profile, StringHandle, ThreadHandle, Timestamp,
+    LibraryInfo, MarkerTiming, ProcessHandle, Profile, StringHandle, ThreadHandle, Timestamp,
 };
 use mach2::mach_types::{thread_act_port_array_t, thread_act_t};
 use mach2::message::mach_msg_type_number_t;
@@ -44,7 +44,7 @@ use crate::shared::marker_file;
 use crate::shared::marker_file::get_markers;
 use crate::shared::perf_map::try_load_perf_map;
 use crate::shared::process_name::make_process_name;
-use crate::shared::process_sample_data::{MarkerSpanOnThread, ProcessSampleData};
+use crate::shared::process_sample_data::{LostEventsMarker, MarkerSpanOnThread, ProcessSampleData};
 use crate::shared::prop_types::ProfileCreationProps;
 use crate::shared::recycling::{ProcessRecycler, ProcessRecyclingData, ThreadRecycler};
 use crate::shared::timestamp_converter::TimestampConverter;
@@ -161,6 +161,7 @@ impl TaskProfiler {
             &executable_name,
             cmdline,
             profile_creation_props.arg_count_to_include_in_process_name,
+            profile_creation_props.redact_env,
         );
 
         let thread_acts = get_thread_list(task, profile_creation_props.main_thread_only)?;
@@ -413,6 +414,7 @@ impl TaskProfiler {
             // Grab a sample from the thread.
             let stackwalker = StackwalkerRef::new(&self.unwinder, unwinder_cache);
             thread.check_thread_name(profile, self.thread_recycler.as_mut());
+            thread.check_scheduling(now, profile);
             let still_alive = thread.sample(
                 stackwalker,
                 now,
@@ -568,6 +570,10 @@ impl TaskProfiler {
         self.unwinder.add_module(module);
     }
 
+    pub fn process_handle(&self) -> ProcessHandle {
+        self.profile_process
+    }
+
     pub fn check_received_paths(&mut self) {
         while let Ok(process_specific_path) = self.path_receiver.try_recv() {
             match process_specific_path {
@@ -632,12 +638,31 @@ impl TaskProfiler {
         self.lib_info_manager.unmap_memory();
     }
 
+    /// Adds a marker on the main thread for a time range in which the sampler
+    /// missed `count` samples.
+    pub fn add_missed_samples_marker(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+        count: u64,
+        profile: &mut Profile,
+    ) {
+        profile.add_marker(
+            self.main_thread_handle,
+            MarkerTiming::Interval(start, end),
+            LostEventsMarker { count },
+        );
+    }
+
     /// Called when a process has exited or at the end of the profiling run.
     pub fn finish(
         mut self,
         jit_category_manager: &mut JitCategoryManager,
         profile: &mut Profile,
     ) -> (ProcessSampleData, Option<(String, ProcessRecyclingData)>) {
+        for thread in self.live_threads.values_mut() {
+            thread.flush_scheduling_marker(profile);
+        }
         let perf_map_mappings = if !self.unresolved_samples.is_empty() {
             try_load_perf_map(self.pid, profile, jit_category_manager, None)
         } else {
@@ -3,12 +3,14 @@
 #![allow(non_camel_case_types)]
 #![allow(non_upper_case_globals)]
 
+use mach2::boolean::boolean_t;
 use mach2::kern_return::kern_return_t;
 use mach2::message::mach_msg_type_number_t;
 use mach2::port::mach_port_t;
 use mach2::vm_types::natural_t;
 
 use super::thread_info::thread_info_t;
+use super::thread_policy::{thread_policy_flavor_t, thread_policy_t};
 
 pub type thread_flavor_t = natural_t;
 pub type thread_inspect_t = mach_port_t;
@@ -20,3 +22,12 @@ extern "C" {
         thread_info_outCnt: *mut mach_msg_type_number_t,
     ) -> kern_return_t;
 }
+extern "C" {
+    pub fn thread_policy_get(
+        thread: thread_inspect_t,
+        flavor: thread_policy_flavor_t,
+        policy_info: thread_policy_t,
+        policy_infoCnt: *mut mach_msg_type_number_t,
+        get_default: *mut boolean_t,
+    ) -> kern_return_t;
+}
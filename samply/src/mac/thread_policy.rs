@@ -0,0 +1,33 @@
+// Definitions from mach/thread_policy.h which aren't part of mach2.
+
+#![allow(non_camel_case_types)]
+#![allow(non_upper_case_globals)]
+
+use std::mem::size_of;
+
+use mach2::message::mach_msg_type_number_t;
+use mach2::vm_types::{integer_t, natural_t};
+
+pub type thread_policy_flavor_t = natural_t;
+pub type thread_policy_t = *mut integer_t;
+
+pub const THREAD_QOS_POLICY: thread_policy_flavor_t = 9;
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct thread_qos_policy {
+    pub qos_tier: integer_t,
+    pub tier_importance: integer_t,
+}
+pub type thread_qos_policy_data_t = thread_qos_policy;
+
+pub const THREAD_QOS_POLICY_COUNT: mach_msg_type_number_t =
+    (size_of::<thread_qos_policy_data_t>() / size_of::<integer_t>()) as _;
+
+pub const THREAD_QOS_UNSPECIFIED: integer_t = 0;
+pub const THREAD_QOS_MAINTENANCE: integer_t = 1;
+pub const THREAD_QOS_BACKGROUND: integer_t = 2;
+pub const THREAD_QOS_UTILITY: integer_t = 3;
+pub const THREAD_QOS_LEGACY: integer_t = 4;
+pub const THREAD_QOS_USER_INITIATED: integer_t = 5;
+pub const THREAD_QOS_USER_INTERACTIVE: integer_t = 6;
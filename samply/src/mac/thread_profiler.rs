@@ -1,7 +1,10 @@
 use std::mem;
 
 use framehop::FrameAddress;
-use fxprof_processed_profile::{CpuDelta, Profile, StringHandle, ThreadHandle, Timestamp};
+use fxprof_processed_profile::{
+    CpuDelta, Marker, MarkerField, MarkerTiming, Profile, Schema, StringHandle, ThreadHandle,
+    Timestamp,
+};
 use mach2::mach_types::thread_act_t;
 use mach2::port::mach_port_t;
 use time::get_monotonic_timestamp;
@@ -9,12 +12,17 @@ use time::get_monotonic_timestamp;
 use super::error::SamplingError;
 use super::kernel_error::{self, IntoResult, KernelError};
 use super::proc_maps::{get_backtrace, ForeignMemory, StackwalkerRef};
-use super::thread_act::thread_info; // https://github.com/JohnTitor/mach2/issues/31
+use super::thread_act::{thread_info, thread_policy_get}; // https://github.com/JohnTitor/mach2/issues/31
 use super::thread_info::{
     thread_basic_info_data_t, thread_extended_info_data_t, thread_identifier_info_data_t,
     thread_info_t, time_value, THREAD_BASIC_INFO, THREAD_BASIC_INFO_COUNT, THREAD_EXTENDED_INFO,
     THREAD_EXTENDED_INFO_COUNT, THREAD_IDENTIFIER_INFO, THREAD_IDENTIFIER_INFO_COUNT,
 };
+use super::thread_policy::{
+    thread_policy_t, thread_qos_policy_data_t, THREAD_QOS_BACKGROUND, THREAD_QOS_LEGACY,
+    THREAD_QOS_MAINTENANCE, THREAD_QOS_POLICY, THREAD_QOS_POLICY_COUNT, THREAD_QOS_UNSPECIFIED,
+    THREAD_QOS_USER_INITIATED, THREAD_QOS_USER_INTERACTIVE, THREAD_QOS_UTILITY,
+};
 use crate::mac::time;
 use crate::shared::recycling::ThreadRecycler;
 use crate::shared::types::{StackFrame, StackMode};
@@ -30,6 +38,20 @@ pub struct ThreadProfiler {
     stack_memory: ForeignMemory,
     previous_sample_cpu_time_us: u64,
     ignored_errors: Vec<SamplingError>,
+    scheduling: Option<SchedulingPeriod>,
+}
+
+/// A stretch of time during which a thread kept the same QoS class and priority.
+struct SchedulingPeriod {
+    scheduling: ThreadScheduling,
+    start: Timestamp,
+    last_seen: Timestamp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadScheduling {
+    pub qos_tier: i32,
+    pub base_priority: i32,
 }
 
 impl ThreadProfiler {
@@ -51,6 +73,7 @@ impl ThreadProfiler {
             stack_memory: ForeignMemory::new(task),
             previous_sample_cpu_time_us: 0,
             ignored_errors: Vec::new(),
+            scheduling: None,
         }
     }
 
@@ -75,6 +98,42 @@ impl ThreadProfiler {
         }
     }
 
+    /// Called before every call to `sample`. Adds a marker for the previous
+    /// QoS class and priority whenever they change.
+    pub fn check_scheduling(&mut self, now: Timestamp, profile: &mut Profile) {
+        if let Some(period) = &mut self.scheduling {
+            period.last_seen = now;
+        }
+        if self.tick_count % 10 != 0 {
+            return;
+        }
+        let Ok(scheduling) = get_thread_scheduling(self.thread_act) else {
+            return;
+        };
+        if self.scheduling.as_ref().map(|period| period.scheduling) == Some(scheduling) {
+            return;
+        }
+        self.flush_scheduling_marker(profile);
+        self.scheduling = Some(SchedulingPeriod {
+            scheduling,
+            start: now,
+            last_seen: now,
+        });
+    }
+
+    /// Adds the marker for the current QoS class and priority, ending at the
+    /// last time this thread was sampled.
+    pub fn flush_scheduling_marker(&mut self, profile: &mut Profile) {
+        if let Some(period) = self.scheduling.take() {
+            let marker = ThreadSchedulingMarker::new(period.scheduling, profile);
+            profile.add_marker(
+                self.profile_thread,
+                MarkerTiming::Interval(period.start, period.last_seen),
+                marker,
+            );
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn sample(
         &mut self,
@@ -198,6 +257,10 @@ impl ThreadProfiler {
     }
 
     pub fn notify_dead(&mut self, end_time: Timestamp, profile: &mut Profile) {
+        if let Some(period) = &mut self.scheduling {
+            period.last_seen = end_time;
+        }
+        self.flush_scheduling_marker(profile);
         profile.set_thread_end_time(self.profile_thread, end_time);
     }
 
@@ -259,6 +322,96 @@ pub fn get_thread_name(thread_act: thread_act_t) -> Result<Option<String>, Sampl
     Ok(if name.is_empty() { None } else { Some(name) })
 }
 
+/// Returns the thread's requested QoS tier and its base scheduling priority.
+pub fn get_thread_scheduling(thread_act: thread_act_t) -> kernel_error::Result<ThreadScheduling> {
+    let mut extended_info_data: thread_extended_info_data_t = unsafe { mem::zeroed() };
+    let mut count = THREAD_EXTENDED_INFO_COUNT;
+    unsafe {
+        thread_info(
+            thread_act,
+            THREAD_EXTENDED_INFO,
+            &mut extended_info_data as *mut _ as thread_info_t,
+            &mut count,
+        )
+    }
+    .into_result()?;
+
+    let mut qos_policy_data: thread_qos_policy_data_t = unsafe { mem::zeroed() };
+    let mut count = THREAD_QOS_POLICY_COUNT;
+    let mut get_default = 0;
+    unsafe {
+        thread_policy_get(
+            thread_act,
+            THREAD_QOS_POLICY,
+            &mut qos_policy_data as *mut _ as thread_policy_t,
+            &mut count,
+            &mut get_default,
+        )
+    }
+    .into_result()?;
+
+    Ok(ThreadScheduling {
+        qos_tier: qos_policy_data.qos_tier,
+        base_priority: extended_info_data.pth_priority,
+    })
+}
+
+fn qos_class_name(qos_tier: i32) -> &'static str {
+    match qos_tier {
+        THREAD_QOS_UNSPECIFIED => "Unspecified",
+        THREAD_QOS_MAINTENANCE => "Maintenance",
+        THREAD_QOS_BACKGROUND => "Background",
+        THREAD_QOS_UTILITY => "Utility",
+        THREAD_QOS_LEGACY => "Default",
+        THREAD_QOS_USER_INITIATED => "User Initiated",
+        THREAD_QOS_USER_INTERACTIVE => "User Interactive",
+        _ => "Unknown",
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ThreadSchedulingMarker {
+    qos_class: StringHandle,
+    base_priority: i32,
+}
+
+impl ThreadSchedulingMarker {
+    fn new(scheduling: ThreadScheduling, profile: &mut Profile) -> Self {
+        Self {
+            qos_class: profile.handle_for_string(qos_class_name(scheduling.qos_tier)),
+            base_priority: scheduling.base_priority,
+        }
+    }
+}
+
+impl Marker for ThreadSchedulingMarker {
+    type FieldsType = (StringHandle, f64);
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "ThreadScheduling";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted for each period during which a thread kept the same QoS class and priority.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.qos}");
+    const TOOLTIP_LABEL: Option<&'static str> =
+        Some("QoS: {marker.data.qos}, priority: {marker.data.priority}");
+    const TABLE_LABEL: Option<&'static str> =
+        Some("QoS: {marker.data.qos}, priority: {marker.data.priority}");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema((
+        MarkerField::string("qos", "QoS class"),
+        MarkerField::integer("priority", "Base priority"),
+    ));
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Thread QoS")
+    }
+
+    fn field_values(&self) -> (StringHandle, f64) {
+        (self.qos_class, self.base_priority.into())
+    }
+}
+
 // (user time, system time) in microseconds
 fn get_thread_cpu_time_since_thread_start(
     thread_act: thread_act_t,
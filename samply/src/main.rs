@@ -9,22 +9,36 @@ mod windows;
 
 mod cli;
 mod cli_utils;
+mod config;
+mod diff;
+mod dump_syms;
+mod export;
 mod import;
 mod linux_shared;
+#[cfg(target_os = "linux")]
+mod live_server;
+mod merge;
 mod name;
 mod profile_json_preparse;
 mod server;
 mod shared;
+mod symbolicate;
+mod symbolication_cache;
 mod symbols;
+mod upload;
 
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::future::Future;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use fxprof_processed_profile::Profile;
+use shared::capture_window::retain_samples_in_capture_windows;
 use shared::ctrl_c::CtrlC;
+use shared::profile_size::downsample_to_size_budget;
+use shared::recording_stats::RecordingStats;
 
 #[cfg(any(target_os = "android", target_os = "linux"))]
 use linux::profiler;
@@ -36,17 +50,28 @@ use windows::profiler;
 use profile_json_preparse::parse_libinfo_map_from_profile_file;
 use server::{start_server, RunningServerInfo, ServerProps};
 use shared::prop_types::{ImportProps, SymbolProps};
-use shared::save_profile::save_profile_to_file;
+use shared::save_profile::{save_precompressed_copies, save_profile_to_file};
 use symbols::create_symbol_manager_and_quota_manager;
 
 fn main() {
-    env_logger::init();
+    use clap::{CommandFactory, FromArgMatches};
+    let config = config::Config::load(std::env::args_os());
+    let matches = config.apply_to_command(cli::Opt::command()).get_matches();
+    let opt = cli::Opt::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    init_logger(opt.log_format);
 
-    use clap::Parser;
-    let opt = cli::Opt::parse();
     match opt.action {
         cli::Action::Load(load_args) => do_load_action(load_args),
         cli::Action::Import(import_args) => do_import_action(import_args),
+        cli::Action::Upload(upload_args) => do_upload_action(upload_args),
+        cli::Action::Merge(merge_args) => do_merge_action(merge_args),
+        cli::Action::Diff(diff_args) => do_diff_action(diff_args),
+        cli::Action::Export(export_args) => do_export_action(export_args),
+        cli::Action::SymbolServer(symbol_server_args) => {
+            do_symbol_server_action(symbol_server_args)
+        }
+        cli::Action::Symbolicate(symbolicate_args) => do_symbolicate_action(symbolicate_args),
+        cli::Action::DumpSyms(dump_syms_args) => do_dump_syms_action(dump_syms_args),
 
         #[cfg(any(
             target_os = "android",
@@ -54,7 +79,7 @@ fn main() {
             target_os = "linux",
             target_os = "windows"
         ))]
-        cli::Action::Record(record_args) => do_record_action(record_args),
+        cli::Action::Record(record_args) => do_record_action(record_args, opt.log_format),
 
         #[cfg(target_os = "windows")]
         cli::Action::RunElevatedHelper(args) => {
@@ -66,14 +91,362 @@ fn main() {
     }
 }
 
+fn init_logger(log_format: cli::LogFormatArg) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if log_format == cli::LogFormatArg::Json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({
+                "type": "log",
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{line}")
+        });
+    }
+    builder.init();
+}
+
 fn do_load_action(load_args: cli::LoadArgs) {
-    run_server_serving_profile(
-        &load_args.file,
+    run_server_serving_profiles(
+        &load_args.files,
         load_args.server_props(),
         load_args.symbol_props(),
     );
 }
 
+fn do_symbol_server_action(symbol_server_args: cli::SymbolServerArgs) {
+    let server_props = symbol_server_args.server_props();
+    let symbol_props = symbol_server_args.symbol_props();
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        // The symbol manager lives as long as the server, so that symbol files
+        // which were loaded for one request are still in memory for the next.
+        let (symbol_manager, quota_manager) =
+            create_symbol_manager_and_quota_manager(symbol_props, server_props.verbose);
+
+        let RunningServerInfo {
+            server_join_handle,
+            symbol_server_url,
+            ..
+        } = start_server(
+            &[],
+            server_props,
+            Arc::new(symbol_manager),
+            CtrlC::observe_oneshot(),
+        )
+        .await;
+
+        eprintln!("Symbol server listening at {symbol_server_url}");
+        println!("{symbol_server_url}");
+        eprintln!("Press Ctrl+C to stop.");
+
+        // Run this server until it stops.
+        if let Err(e) = server_join_handle.await {
+            eprintln!("server error: {e}");
+        }
+
+        if let Some(quota_manager) = quota_manager {
+            quota_manager.finish().await;
+        }
+    });
+}
+
+fn do_symbolicate_action(symbolicate_args: cli::SymbolicateArgs) {
+    let options = symbolicate::OutputOptions {
+        functions: symbolicate_args.functions,
+        inlines: symbolicate_args.inlines,
+        addresses: symbolicate_args.addresses,
+        pretty_print: symbolicate_args.pretty_print,
+        basenames: symbolicate_args.basenames,
+    };
+    // Addresses are relative to the image base if the binary is not at hand.
+    let relative = symbolicate_args.relative || symbolicate_args.exe.is_none();
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let (symbol_manager, quota_manager) =
+            create_symbol_manager_and_quota_manager(symbolicate_args.symbol_props(), false);
+        let symbol_map = match (&symbolicate_args.exe, &symbolicate_args.debug_name) {
+            (Some(exe), _) => {
+                symbol_manager
+                    .load_symbol_map_for_binary_at_path(exe, None)
+                    .await
+            }
+            (None, Some(debug_name)) => {
+                let breakpad_id = symbolicate_args.debug_id.as_deref().unwrap_or_default();
+                let Ok(debug_id) = wholesym::debugid::DebugId::from_breakpad(breakpad_id) else {
+                    eprintln!("Error: Invalid breakpad ID {breakpad_id:?}");
+                    std::process::exit(1);
+                };
+                symbol_manager.load_symbol_map(debug_name, debug_id).await
+            }
+            (None, None) => unreachable!("clap requires --exe or --debug-name"),
+        };
+        let symbol_map = match symbol_map {
+            Ok(symbol_map) => symbol_map,
+            Err(err) => {
+                eprintln!("Error: Could not load symbols: {err}");
+                std::process::exit(1);
+            }
+        };
+
+        // Like addr2line, read the addresses from stdin if none are given, and
+        // answer each line right away so that it can be used as a coprocess.
+        let addresses: Box<dyn Iterator<Item = String>> = if symbolicate_args.addrs.is_empty() {
+            Box::new(std::io::stdin().lock().lines().map_while(Result::ok))
+        } else {
+            Box::new(symbolicate_args.addrs.clone().into_iter())
+        };
+        let mut stdout = std::io::stdout().lock();
+        for text in addresses {
+            let address = symbolicate::parse_address(&text);
+            let lookup_address = match address {
+                Some(address) if !relative => Some(wholesym::LookupAddress64::Svma(address)),
+                Some(address) => Some(wholesym::LookupAddress64::Relative(address)),
+                None => {
+                    eprintln!("Error: Invalid address {text:?}");
+                    None
+                }
+            };
+            let frames = match lookup_address {
+                Some(lookup_address) => {
+                    symbolicate::lookup_frames(&symbol_map, lookup_address).await
+                }
+                None => Vec::new(),
+            };
+            let result =
+                symbolicate::write_frames(&mut stdout, address.unwrap_or(0), &frames, options)
+                    .and_then(|()| stdout.flush());
+            if result.is_err() {
+                // stdout was closed.
+                break;
+            }
+        }
+
+        if let Some(quota_manager) = quota_manager {
+            quota_manager.finish().await;
+        }
+    });
+}
+
+fn do_dump_syms_action(dump_syms_args: cli::DumpSymsArgs) {
+    let disambiguator = dump_syms_args
+        .arch
+        .clone()
+        .map(wholesym::MultiArchDisambiguator::Arch);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let (symbol_manager, quota_manager) =
+            create_symbol_manager_and_quota_manager(dump_syms_args.symbol_props(), false);
+        let binary = &dump_syms_args.binary;
+        let library_info =
+            wholesym::SymbolManager::library_info_for_binary_at_path(binary, disambiguator.clone())
+                .await;
+        let symbol_map = match library_info {
+            Ok(library_info) => symbol_manager
+                .load_symbol_map_for_binary_at_path(binary, disambiguator)
+                .await
+                .map(|symbol_map| (library_info, symbol_map)),
+            Err(err) => Err(err),
+        };
+        let (library_info, symbol_map) = match symbol_map {
+            Ok(result) => result,
+            Err(err) => {
+                eprintln!("Error: Could not load symbols for {binary:?}: {err}");
+                std::process::exit(1);
+            }
+        };
+        let sym_file =
+            dump_syms::BreakpadSymFile::from_symbol_map(&library_info, &symbol_map).await;
+
+        let mut writer: Box<dyn Write> = match &dump_syms_args.output {
+            Some(output) => match File::create(output) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(err) => {
+                    eprintln!("Error: Could not create {output:?}: {err}");
+                    std::process::exit(1);
+                }
+            },
+            None => Box::new(BufWriter::new(std::io::stdout().lock())),
+        };
+        if let Err(err) = sym_file.write(&mut writer).and_then(|()| writer.flush()) {
+            eprintln!("Error: Could not write the .sym file: {err}");
+            std::process::exit(1);
+        }
+
+        if let Some(quota_manager) = quota_manager {
+            quota_manager.finish().await;
+        }
+    });
+}
+
+fn do_merge_action(merge_args: cli::MergeArgs) {
+    let profile = match merge::merge_profile_files(&merge_args.files, merge_args.time_alignment()) {
+        Ok(profile) => profile,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    save_profile_to_file(&profile, &merge_args.output, merge_args.output_format())
+        .expect("Couldn't write JSON");
+    if merge_args.server_props().is_some() {
+        save_precompressed_profile_copies(&merge_args.output);
+    }
+
+    // Drop the profile so that it doesn't take up memory while the server is running.
+    drop(profile);
+
+    if let Some(server_props) = merge_args.server_props() {
+        run_server_serving_profile(&merge_args.output, server_props, merge_args.symbol_props());
+    }
+}
+
+fn do_diff_action(diff_args: cli::DiffArgs) {
+    let read_profile = |path: &Path| match merge::read_processed_profile(path) {
+        Ok(profile) => profile,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            std::process::exit(1);
+        }
+    };
+    let before = read_profile(&diff_args.before);
+    let after = read_profile(&diff_args.after);
+
+    if before["meta"]["symbolicated"] != true || after["meta"]["symbolicated"] != true {
+        eprintln!("Warning: Comparing unsymbolicated profiles only matches functions by address.");
+        eprintln!("Record with --presymbolicate to get function names.");
+    }
+
+    let before_weights = diff::compute_function_weights(&before);
+    let after_weights = diff::compute_function_weights(&after);
+    let diffs = diff::diff_function_weights(&before_weights, &after_weights);
+    diff::print_function_diffs(&diffs, diff_args.top);
+
+    if let Some(output) = &diff_args.output {
+        let profile = diff::make_diff_profile(before, after);
+        save_profile_to_file(&profile, output, diff_args.output_format(output))
+            .expect("Couldn't write JSON");
+        eprintln!("Saved the comparison profile to {output:?}. Open it with samply load.");
+    }
+}
+
+fn do_export_action(export_args: cli::ExportArgs) {
+    let profile = match merge::read_processed_profile(&export_args.file) {
+        Ok(profile) => profile,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            std::process::exit(1);
+        }
+    };
+    let symbols = export::symbols::symbolicate_profile(
+        &profile,
+        &export_args.file,
+        export_args.symbol_props(),
+    );
+    let export_profile = export::ExportProfile::from_processed_profile(&profile, &symbols);
+    drop(profile);
+
+    let mut writer: Box<dyn Write> = match &export_args.output {
+        Some(output) => match File::create(output) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(err) => {
+                eprintln!("Error: Could not create {output:?}: {err}");
+                std::process::exit(1);
+            }
+        },
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+    let result = match export_args.format {
+        cli::ExportFormatArg::Collapsed => {
+            export::collapsed::write_collapsed_stacks(&export_profile, &mut writer)
+        }
+        cli::ExportFormatArg::Pprof => export::pprof::write_pprof(&export_profile, &mut writer),
+        cli::ExportFormatArg::Chrome => {
+            export::chrome::write_chrome_trace(&export_profile, &mut writer)
+        }
+    };
+    if let Err(err) = result.and_then(|()| writer.flush()) {
+        eprintln!("Error: Could not write the exported profile: {err}");
+        std::process::exit(1);
+    }
+}
+
+fn do_upload_action(upload_args: cli::UploadArgs) {
+    let is_symbolicated = match upload::profile_file_is_symbolicated(&upload_args.file) {
+        Ok(is_symbolicated) => is_symbolicated,
+        Err(err) => {
+            eprintln!("Could not read profile {:?}: {err}", upload_args.file);
+            std::process::exit(1)
+        }
+    };
+    if !upload_args.yes && !upload::confirm_upload() {
+        eprintln!("Not uploading the profile.");
+        std::process::exit(1)
+    }
+
+    if is_symbolicated {
+        upload_profile_and_print_url(&upload_args.file);
+        return;
+    }
+
+    // Nobody can supply symbols for the shared profile later, so look them up now.
+    let mut profile = match merge::read_processed_profile(&upload_args.file) {
+        Ok(profile) => profile,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            std::process::exit(1);
+        }
+    };
+    eprintln!("Symbolicating...");
+    let symbols = export::symbols::symbolicate_profile(
+        &profile,
+        &upload_args.file,
+        upload_args.symbol_props(),
+    );
+    upload::apply_frame_symbols(&mut profile, &symbols);
+    run_upload_and_print_url(upload::upload_profile_json(&profile));
+}
+
+fn upload_profile_and_print_url(profile_path: &Path) {
+    run_upload_and_print_url(upload::upload_profile_file(profile_path));
+}
+
+fn run_upload_and_print_url(upload: impl Future<Output = Result<String, upload::UploadError>>) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    eprintln!("Uploading profile...");
+    match runtime.block_on(upload) {
+        Ok(url) => {
+            eprintln!("Profile uploaded:");
+            println!("{url}");
+        }
+        Err(err) => {
+            eprintln!("Uploading the profile failed: {err}");
+            std::process::exit(1)
+        }
+    }
+}
+
 fn do_import_action(import_args: cli::ImportArgs) {
     let input_path = &import_args.file;
     let input_file = match File::open(input_path) {
@@ -86,8 +459,18 @@ fn do_import_action(import_args: cli::ImportArgs) {
 
     let import_props = import_args.import_props();
     let presymbolicate = import_props.profile_creation_props.presymbolicate;
+    let capture_window = import_props.profile_creation_props.capture_window.clone();
+    let max_profile_size = import_props.profile_creation_props.max_profile_size;
     let mut profile = convert_file_to_profile(&input_file, input_path, import_props);
 
+    if let Some(capture_window) = &capture_window {
+        retain_samples_in_capture_windows(&mut profile, capture_window);
+    }
+
+    if let Some(max_profile_size) = max_profile_size {
+        downsample_to_size_budget(&mut profile, max_profile_size);
+    }
+
     if presymbolicate {
         eprintln!("Symbolicating...");
         let symbol_info = crate::shared::presymbolicate::get_presymbolicate_info(
@@ -98,7 +481,11 @@ fn do_import_action(import_args: cli::ImportArgs) {
         profile.set_symbolicated(true);
     }
 
-    save_profile_to_file(&profile, &import_args.output).expect("Couldn't write JSON");
+    save_profile_to_file(&profile, &import_args.output, import_args.output_format())
+        .expect("Couldn't write JSON");
+    if import_args.server_props().is_some() {
+        save_precompressed_profile_copies(&import_args.output);
+    }
 
     // Drop the profile so that it doesn't take up memory while the server is running.
     drop(profile);
@@ -118,11 +505,28 @@ fn do_import_action(import_args: cli::ImportArgs) {
     target_os = "linux",
     target_os = "windows"
 ))]
-fn do_record_action(record_args: cli::RecordArgs) {
+fn do_record_action(record_args: cli::RecordArgs, log_format: cli::LogFormatArg) {
     let recording_props = record_args.recording_props();
     let recording_mode = record_args.recording_mode();
     let profile_creation_props = record_args.profile_creation_props();
     let presymbolicate = profile_creation_props.presymbolicate;
+    let capture_window = profile_creation_props.capture_window.clone();
+    let max_profile_size = profile_creation_props.max_profile_size;
+
+    // In live mode, the server runs during the recording.
+    #[cfg(target_os = "linux")]
+    let live_server = match (
+        recording_props.live_snapshot_interval,
+        record_args.server_props(),
+    ) {
+        (Some(snapshot_interval), Some(server_props)) => Some(live_server::LiveServer::start(
+            &record_args.output,
+            server_props,
+            record_args.symbol_props(),
+            snapshot_interval,
+        )),
+        _ => None,
+    };
 
     let (mut profile, exit_status) =
         match profiler::run(recording_mode, recording_props, profile_creation_props) {
@@ -133,21 +537,58 @@ fn do_record_action(record_args: cli::RecordArgs) {
             }
         };
 
+    // Describe what was recorded, before any samples are filtered out below.
+    let mut recording_stats = RecordingStats::new(&profile);
+
+    if let Some(capture_window) = &capture_window {
+        retain_samples_in_capture_windows(&mut profile, capture_window);
+    }
+
+    if let Some(max_profile_size) = max_profile_size {
+        downsample_to_size_budget(&mut profile, max_profile_size);
+    }
+
     if presymbolicate {
         eprintln!("Symbolicating...");
         let symbol_info = crate::shared::presymbolicate::get_presymbolicate_info(
             &profile,
             record_args.symbol_props(),
         );
+        recording_stats.add_symbolication_stats(&profile, &symbol_info);
         profile = profile.make_symbolicated_profile(&symbol_info);
         profile.set_symbolicated(true);
     }
 
-    save_profile_to_file(&profile, &record_args.output).expect("Couldn't write JSON");
+    save_profile_to_file(&profile, &record_args.output, record_args.output_format())
+        .expect("Couldn't write JSON");
+    if record_args.server_props().is_some() {
+        save_precompressed_profile_copies(&record_args.output);
+    }
+
+    if log_format == cli::LogFormatArg::Json {
+        eprintln!("{}", recording_stats.to_json());
+    }
 
     // Drop the profile so that it doesn't take up memory while the server is running.
     drop(profile);
 
+    if record_args.upload {
+        if record_args.yes || upload::confirm_upload() {
+            upload_profile_and_print_url(&record_args.output);
+        } else {
+            eprintln!(
+                "Not uploading the profile. It was saved to {:?}.",
+                record_args.output
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(live_server) = live_server {
+        live_server.run_until_ctrl_c();
+        std::process::exit(exit_status.code().unwrap_or(0));
+    }
+
     // then fire up the server for the profiler front end, if not save-only
     if let Some(server_props) = record_args.server_props() {
         run_server_serving_profile(
@@ -182,20 +623,36 @@ fn convert_file_to_profile(
         }
     }
 
-    // Treat all other files as perf.data files from Linux perf / Android simpleperf.
-
     let path = input_path
         .canonicalize()
         .expect("Couldn't form absolute path");
-    let file_meta = input_file.metadata().ok();
-    let file_mod_time = file_meta.and_then(|metadata| metadata.modified().ok());
     let mut binary_lookup_dirs = import_props.symbol_props.symbol_dir;
     let mut aux_file_lookup_dirs = import_props.aux_file_dir;
     if let Some(parent_dir) = path.parent() {
         binary_lookup_dirs.push(parent_dir.into());
         aux_file_lookup_dirs.push(parent_dir.into());
     }
-    let reader = BufReader::new(input_file);
+
+    let mut reader = BufReader::new(input_file);
+    let is_dtrace = reader.fill_buf().is_ok_and(import::dtrace::is_dtrace_data);
+    if is_dtrace {
+        match import::dtrace::convert(
+            reader,
+            binary_lookup_dirs,
+            import_props.profile_creation_props,
+        ) {
+            Ok(profile) => return profile,
+            Err(error) => {
+                eprintln!("Error importing DTrace output: {error}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Treat all other files as perf.data files from Linux perf / Android simpleperf.
+
+    let file_meta = input_file.metadata().ok();
+    let file_mod_time = file_meta.and_then(|metadata| metadata.modified().ok());
     match import::perf::convert(
         reader,
         file_mod_time,
@@ -211,23 +668,43 @@ fn convert_file_to_profile(
     }
 }
 
+/// Saves compressed copies of a profile which is about to be served, so that
+/// the server doesn't need to compress it for every request.
+fn save_precompressed_profile_copies(profile_path: &Path) {
+    if let Err(err) = save_precompressed_copies(profile_path) {
+        eprintln!("Could not save compressed copies of {profile_path:?}: {err}");
+    }
+}
+
 fn run_server_serving_profile(
     profile_path: &Path,
     server_props: ServerProps,
     symbol_props: SymbolProps,
 ) {
-    let libinfo_map = {
-        let profile_file = match File::open(profile_path) {
-            Ok(file) => file,
-            Err(err) => {
-                eprintln!("Could not open file {profile_path:?}: {err}");
-                std::process::exit(1)
-            }
-        };
+    run_server_serving_profiles(&[profile_path.to_owned()], server_props, symbol_props);
+}
 
-        parse_libinfo_map_from_profile_file(profile_file, profile_path)
-            .expect("Couldn't parse libinfo map from profile file")
-    };
+/// Serves all given profiles from one server, each under its own URL.
+fn run_server_serving_profiles(
+    profile_paths: &[PathBuf],
+    server_props: ServerProps,
+    symbol_props: SymbolProps,
+) {
+    let libinfo_maps: Vec<_> = profile_paths
+        .iter()
+        .map(|profile_path| {
+            let profile_file = match File::open(profile_path) {
+                Ok(file) => file,
+                Err(err) => {
+                    eprintln!("Could not open file {profile_path:?}: {err}");
+                    std::process::exit(1)
+                }
+            };
+
+            parse_libinfo_map_from_profile_file(profile_file)
+                .expect("Couldn't parse libinfo map from profile file")
+        })
+        .collect();
 
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -237,12 +714,16 @@ fn run_server_serving_profile(
     runtime.block_on(async {
         let (mut symbol_manager, quota_manager) =
             create_symbol_manager_and_quota_manager(symbol_props, server_props.verbose);
-        for lib_info in libinfo_map.into_values() {
+        for lib_info in libinfo_maps.into_iter().flat_map(|map| map.into_values()) {
             symbol_manager.add_known_library(lib_info);
         }
 
-        let precog_path = profile_path.with_extension("syms.json");
-        if let Some(precog_info) = shared::symbol_precog::PrecogSymbolInfo::try_load(&precog_path) {
+        for profile_path in profile_paths {
+            let precog_path = profile_path.with_extension("syms.json");
+            let Some(precog_info) = shared::symbol_precog::PrecogSymbolInfo::try_load(&precog_path)
+            else {
+                continue;
+            };
             for symbol_map in precog_info.into_iter() {
                 let lib_info = symbol_map.library_info();
                 symbol_manager.add_known_library_symbols(lib_info, Arc::new(symbol_map));
@@ -256,25 +737,26 @@ fn run_server_serving_profile(
         let RunningServerInfo {
             server_join_handle,
             server_origin,
-            profiler_url,
+            profiler_urls,
+            ..
         } = start_server(
-            Some(profile_path),
+            profile_paths,
             server_props,
-            symbol_manager,
+            Arc::new(symbol_manager),
             ctrl_c_receiver,
         )
         .await;
 
         eprintln!("Local server listening at {server_origin}");
         if !open_in_browser {
-            if let Some(profiler_url) = &profiler_url {
+            for profiler_url in &profiler_urls {
                 println!("{profiler_url}");
             }
         }
         eprintln!("Press Ctrl+C to stop.");
 
         if open_in_browser {
-            if let Some(profiler_url) = &profiler_url {
+            for profiler_url in &profiler_urls {
                 let _ = opener::open_browser(profiler_url);
             }
         }
@@ -0,0 +1,606 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::shared::save_profile::read_profile_json_file;
+
+#[derive(thiserror::Error, Debug)]
+pub enum MergeError {
+    #[error("Could not read {0:?}: {1}")]
+    Io(PathBuf, std::io::Error),
+
+    #[error("{0:?} is not a processed profile as written by samply: {1}")]
+    UnsupportedFormat(PathBuf, &'static str),
+}
+
+/// How the timelines of the merged profiles relate to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeTimeAlignment {
+    /// Keep each profile at its original wall-clock time.
+    WallClock,
+    /// Move all profiles so that they start at the same time. This is useful for
+    /// comparing repeated runs of the same program.
+    Start,
+}
+
+/// Reads the profile files at `paths` and merges them into one profile.
+pub fn merge_profile_files(
+    paths: &[PathBuf],
+    alignment: MergeTimeAlignment,
+) -> Result<Value, MergeError> {
+    let profiles = paths
+        .iter()
+        .map(|path| read_processed_profile(path))
+        .collect::<Result<Vec<_>, MergeError>>()?;
+    Ok(merge_profiles(profiles, alignment))
+}
+
+/// Reads a profile file and checks that it's in the format written by samply.
+pub fn read_processed_profile(path: &Path) -> Result<Value, MergeError> {
+    let profile = read_profile_json_file(path).map_err(|e| MergeError::Io(path.to_owned(), e))?;
+    check_profile_format(&profile)
+        .map_err(|reason| MergeError::UnsupportedFormat(path.to_owned(), reason))?;
+    Ok(profile)
+}
+
+fn check_profile_format(profile: &Value) -> Result<(), &'static str> {
+    if profile["meta"]["preprocessedProfileVersion"]
+        .as_u64()
+        .is_none()
+    {
+        return Err("meta.preprocessedProfileVersion is missing");
+    }
+    if !profile["shared"]["stringArray"].is_array() {
+        return Err("shared.stringArray is missing");
+    }
+    if !profile["threads"].is_array() || !profile["libs"].is_array() {
+        return Err("the threads or libs list is missing");
+    }
+    Ok(())
+}
+
+/// Merges several processed profiles into one multi-process profile.
+///
+/// The profiles are expected to be in the format that fxprof-processed-profile
+/// writes. Threads from all profiles end up in the merged profile, with their
+/// string, library and category indexes remapped to the merged tables, and their
+/// timestamps adjusted to the start time of the merged profile.
+pub fn merge_profiles(profiles: Vec<Value>, alignment: MergeTimeAlignment) -> Value {
+    let start_time = profiles
+        .iter()
+        .filter_map(|p| p["meta"]["startTime"].as_f64())
+        .reduce(f64::min)
+        .unwrap_or(0.0);
+
+    let mut merger = ProfileMerger {
+        strings: Vec::new(),
+        string_indexes: HashMap::new(),
+        libs: Vec::new(),
+        categories: Vec::new(),
+        marker_schemas: Vec::new(),
+        threads: Vec::new(),
+        counters: Vec::new(),
+        paused_ranges: Vec::new(),
+        used_pids: HashMap::new(),
+        initial_visible_threads: Vec::new(),
+        initial_selected_threads: Vec::new(),
+    };
+
+    let mut meta = profiles
+        .first()
+        .map(|p| p["meta"].clone())
+        .unwrap_or_else(|| json!({}));
+    let mut interval = f64::INFINITY;
+    let mut symbolicated = true;
+    let mut uses_only_one_stack_type = true;
+    let mut products = Vec::new();
+
+    for (profile_index, profile) in profiles.into_iter().enumerate() {
+        let time_offset = match alignment {
+            MergeTimeAlignment::WallClock => {
+                profile["meta"]["startTime"].as_f64().unwrap_or(start_time) - start_time
+            }
+            MergeTimeAlignment::Start => 0.0,
+        };
+        let profile_meta = &profile["meta"];
+        if let Some(profile_interval) = profile_meta["interval"].as_f64() {
+            interval = interval.min(profile_interval);
+        }
+        symbolicated &= profile_meta["symbolicated"].as_bool().unwrap_or(false);
+        uses_only_one_stack_type &= profile_meta["usesOnlyOneStackType"]
+            .as_bool()
+            .unwrap_or(false);
+        if let Some(product) = profile_meta["product"].as_str() {
+            if !products.iter().any(|p| p == product) {
+                products.push(product.to_string());
+            }
+        }
+        merger.add_profile(profile, profile_index, time_offset);
+    }
+
+    meta["startTime"] = json!(start_time);
+    if interval.is_finite() {
+        meta["interval"] = json!(interval);
+    }
+    meta["symbolicated"] = json!(symbolicated);
+    meta["usesOnlyOneStackType"] = json!(uses_only_one_stack_type);
+    meta["product"] = json!(products.join(" + "));
+    meta["categories"] = Value::Array(merger.categories);
+    meta["markerSchema"] = Value::Array(merger.marker_schemas);
+    meta["pausedRanges"] = Value::Array(merger.paused_ranges);
+    if let Some(meta) = meta.as_object_mut() {
+        // Platform-specific start times only make sense for a single machine and boot.
+        meta.retain(|key, _| !key.starts_with("startTimeAs"));
+        meta.remove("initialVisibleThreads");
+        meta.remove("initialSelectedThreads");
+    }
+    if !merger.initial_visible_threads.is_empty() {
+        meta["initialVisibleThreads"] = json!(merger.initial_visible_threads);
+    }
+    if !merger.initial_selected_threads.is_empty() {
+        meta["initialSelectedThreads"] = json!(merger.initial_selected_threads);
+    }
+
+    json!({
+        "meta": meta,
+        "libs": merger.libs,
+        "shared": { "stringArray": merger.strings },
+        "threads": merger.threads,
+        "pages": [],
+        "profilerOverhead": [],
+        "counters": merger.counters,
+    })
+}
+
+struct ProfileMerger {
+    strings: Vec<String>,
+    string_indexes: HashMap<String, usize>,
+    libs: Vec<Value>,
+    categories: Vec<Value>,
+    marker_schemas: Vec<Value>,
+    threads: Vec<Value>,
+    counters: Vec<Value>,
+    /// The paused ranges of all profiles, adjusted to the merged start time.
+    paused_ranges: Vec<Value>,
+    /// Maps a pid to the index of the profile which used it first.
+    used_pids: HashMap<String, usize>,
+    initial_visible_threads: Vec<usize>,
+    initial_selected_threads: Vec<usize>,
+}
+
+/// The index translations for one of the profiles that are being merged.
+struct IndexMaps {
+    strings: Vec<usize>,
+    libs: Vec<usize>,
+    /// For each category, the new category index and the new subcategory indexes.
+    categories: Vec<(usize, Vec<usize>)>,
+    /// For each marker type, the keys of fields whose values are string indexes.
+    string_fields_per_marker_type: HashMap<String, Vec<String>>,
+    time_offset: f64,
+}
+
+impl IndexMaps {
+    fn string(&self, index: &Value) -> Value {
+        match index.as_u64() {
+            Some(index) => json!(self.strings.get(index as usize).copied().unwrap_or(0)),
+            None => index.clone(),
+        }
+    }
+
+    fn lib(&self, index: &Value) -> Value {
+        match index.as_u64() {
+            Some(index) => json!(self.libs.get(index as usize).copied().unwrap_or(0)),
+            None => index.clone(),
+        }
+    }
+
+    fn category(&self, index: &Value) -> Value {
+        match index.as_u64() {
+            Some(index) => json!(self
+                .categories
+                .get(index as usize)
+                .map(|c| c.0)
+                .unwrap_or(0)),
+            None => index.clone(),
+        }
+    }
+
+    fn subcategory(&self, category: &Value, subcategory: &Value) -> Value {
+        match (category.as_u64(), subcategory.as_u64()) {
+            (Some(category), Some(subcategory)) => json!(self
+                .categories
+                .get(category as usize)
+                .and_then(|c| c.1.get(subcategory as usize))
+                .copied()
+                .unwrap_or(0)),
+            _ => subcategory.clone(),
+        }
+    }
+
+    fn time(&self, time: &Value) -> Value {
+        match time.as_f64() {
+            Some(time) => json!(time + self.time_offset),
+            None => time.clone(),
+        }
+    }
+}
+
+impl ProfileMerger {
+    fn add_profile(&mut self, mut profile: Value, profile_index: usize, time_offset: f64) {
+        let index_maps = IndexMaps {
+            strings: self.add_strings(&profile["shared"]["stringArray"]),
+            libs: self.add_libs(&profile["libs"]),
+            categories: self.add_categories(&profile["meta"]["categories"]),
+            string_fields_per_marker_type: self
+                .add_marker_schemas(&profile["meta"]["markerSchema"]),
+            time_offset,
+        };
+
+        let thread_index_offset = self.threads.len();
+        let mut pid_map = HashMap::new();
+        if let Value::Array(threads) = profile["threads"].take() {
+            for mut thread in threads {
+                let pid = thread["pid"].as_str().unwrap_or_default().to_string();
+                let new_pid = pid_map
+                    .entry(pid.clone())
+                    .or_insert_with(|| self.unique_pid(&pid, profile_index))
+                    .clone();
+                thread["pid"] = json!(new_pid);
+                remap_thread(&mut thread, &index_maps);
+                self.threads.push(thread);
+            }
+        }
+
+        if let Value::Array(counters) = profile["counters"].take() {
+            for mut counter in counters {
+                if let Some(index) = counter["mainThreadIndex"].as_u64() {
+                    counter["mainThreadIndex"] = json!(index as usize + thread_index_offset);
+                }
+                if let Some(new_pid) = counter["pid"].as_str().and_then(|pid| pid_map.get(pid)) {
+                    counter["pid"] = json!(new_pid);
+                }
+                shift_time_deltas(&mut counter["samples"], &index_maps);
+                self.counters.push(counter);
+            }
+        }
+
+        shift_paused_ranges(&mut profile["meta"]["pausedRanges"], &index_maps);
+        if let Value::Array(paused_ranges) = profile["meta"]["pausedRanges"].take() {
+            self.paused_ranges.extend(paused_ranges);
+        }
+
+        for (key, list) in [
+            ("initialVisibleThreads", &mut self.initial_visible_threads),
+            ("initialSelectedThreads", &mut self.initial_selected_threads),
+        ] {
+            if let Some(indexes) = profile["meta"][key].as_array() {
+                list.extend(
+                    indexes
+                        .iter()
+                        .filter_map(Value::as_u64)
+                        .map(|i| i as usize + thread_index_offset),
+                );
+            }
+        }
+    }
+
+    /// Returns a pid that doesn't collide with the pids of previously added profiles.
+    /// The same pid on two different machines (or in two runs) refers to different
+    /// processes, and the Firefox Profiler groups threads into processes by pid.
+    fn unique_pid(&mut self, pid: &str, profile_index: usize) -> String {
+        let mut candidate = pid.to_string();
+        let mut suffix = 1;
+        while let Some(&owner) = self.used_pids.get(&candidate) {
+            if owner == profile_index {
+                break;
+            }
+            suffix += 1;
+            candidate = format!("{pid}.{suffix}");
+        }
+        self.used_pids.insert(candidate.clone(), profile_index);
+        candidate
+    }
+
+    fn add_strings(&mut self, string_array: &Value) -> Vec<usize> {
+        let strings = string_array.as_array().map(Vec::as_slice).unwrap_or(&[]);
+        strings
+            .iter()
+            .map(|s| {
+                let s = s.as_str().unwrap_or_default();
+                *self.string_indexes.entry(s.to_string()).or_insert_with(|| {
+                    self.strings.push(s.to_string());
+                    self.strings.len() - 1
+                })
+            })
+            .collect()
+    }
+
+    fn add_libs(&mut self, libs: &Value) -> Vec<usize> {
+        let libs = libs.as_array().map(Vec::as_slice).unwrap_or(&[]);
+        libs.iter()
+            .map(|lib| match self.libs.iter().position(|l| l == lib) {
+                Some(index) => index,
+                None => {
+                    self.libs.push(lib.clone());
+                    self.libs.len() - 1
+                }
+            })
+            .collect()
+    }
+
+    fn add_categories(&mut self, categories: &Value) -> Vec<(usize, Vec<usize>)> {
+        let categories = categories.as_array().map(Vec::as_slice).unwrap_or(&[]);
+        categories
+            .iter()
+            .map(|category| {
+                let category_index = match self
+                    .categories
+                    .iter()
+                    .position(|c| c["name"] == category["name"])
+                {
+                    Some(index) => index,
+                    None => {
+                        let mut new_category = category.clone();
+                        new_category["subcategories"] = json!([]);
+                        self.categories.push(new_category);
+                        self.categories.len() - 1
+                    }
+                };
+                let merged_category = &mut self.categories[category_index];
+                let subcategories = category["subcategories"]
+                    .as_array()
+                    .map(Vec::as_slice)
+                    .unwrap_or(&[]);
+                let subcategory_indexes = subcategories
+                    .iter()
+                    .map(|subcategory| {
+                        let merged_subcategories =
+                            merged_category["subcategories"].as_array_mut().unwrap();
+                        match merged_subcategories.iter().position(|s| s == subcategory) {
+                            Some(index) => index,
+                            None => {
+                                merged_subcategories.push(subcategory.clone());
+                                merged_subcategories.len() - 1
+                            }
+                        }
+                    })
+                    .collect();
+                (category_index, subcategory_indexes)
+            })
+            .collect()
+    }
+
+    fn add_marker_schemas(&mut self, marker_schemas: &Value) -> HashMap<String, Vec<String>> {
+        let marker_schemas = marker_schemas.as_array().map(Vec::as_slice).unwrap_or(&[]);
+        let mut string_fields_per_marker_type = HashMap::new();
+        for schema in marker_schemas {
+            let Some(name) = schema["name"].as_str() else {
+                continue;
+            };
+            if !self.marker_schemas.iter().any(|s| s["name"] == name) {
+                self.marker_schemas.push(schema.clone());
+            }
+            let fields = schema["fields"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or(&[]);
+            let string_field_keys = fields
+                .iter()
+                .filter(|field| {
+                    matches!(
+                        field["format"].as_str(),
+                        Some("unique-string" | "flow-id" | "terminating-flow-id")
+                    )
+                })
+                .filter_map(|field| field["key"].as_str().map(ToString::to_string))
+                .collect();
+            string_fields_per_marker_type.insert(name.to_string(), string_field_keys);
+        }
+        string_fields_per_marker_type
+    }
+}
+
+fn remap_thread(thread: &mut Value, maps: &IndexMaps) {
+    map_column(&mut thread["funcTable"], "name", |v| maps.string(v));
+    map_column(&mut thread["funcTable"], "fileName", |v| maps.string(v));
+    map_column(&mut thread["nativeSymbols"], "name", |v| maps.string(v));
+    map_column(&mut thread["nativeSymbols"], "libIndex", |v| maps.lib(v));
+    map_column(&mut thread["resourceTable"], "name", |v| maps.string(v));
+    map_column(&mut thread["resourceTable"], "lib", |v| maps.lib(v));
+
+    let frame_categories = thread["frameTable"]["category"].clone();
+    if let Some(subcategories) = thread["frameTable"]["subcategory"].as_array_mut() {
+        for (subcategory, category) in subcategories.iter_mut().zip(
+            frame_categories
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or(&[]),
+        ) {
+            *subcategory = maps.subcategory(category, subcategory);
+        }
+    }
+    map_column(&mut thread["frameTable"], "category", |v| maps.category(v));
+
+    let markers = &mut thread["markers"];
+    map_column(markers, "name", |v| maps.string(v));
+    map_column(markers, "category", |v| maps.category(v));
+    let phases: Vec<u64> = markers["phase"]
+        .as_array()
+        .map(|phases| phases.iter().map(|p| p.as_u64().unwrap_or(0)).collect())
+        .unwrap_or_default();
+    // Only shift the times which are meaningful for the marker's phase; unused
+    // times are serialized as zero.
+    const INSTANT: u64 = 0;
+    const INTERVAL: u64 = 1;
+    const INTERVAL_START: u64 = 2;
+    const INTERVAL_END: u64 = 3;
+    for (key, phases_with_time) in [
+        ("startTime", &[INSTANT, INTERVAL, INTERVAL_START][..]),
+        ("endTime", &[INTERVAL, INTERVAL_END][..]),
+    ] {
+        if let Some(times) = markers[key].as_array_mut() {
+            for (time, phase) in times.iter_mut().zip(&phases) {
+                if phases_with_time.contains(phase) {
+                    *time = maps.time(time);
+                }
+            }
+        }
+    }
+    if let Some(data) = markers["data"].as_array_mut() {
+        for data in data {
+            if let Some(cause_time) = data.pointer_mut("/cause/time") {
+                *cause_time = maps.time(cause_time);
+            }
+            let Some(string_field_keys) = data["type"]
+                .as_str()
+                .and_then(|t| maps.string_fields_per_marker_type.get(t))
+            else {
+                continue;
+            };
+            for key in string_field_keys {
+                if let Some(value) = data.get_mut(key) {
+                    *value = maps.string(value);
+                }
+            }
+        }
+    }
+
+    shift_time_deltas(&mut thread["samples"], maps);
+    shift_paused_ranges(&mut thread["pausedRanges"], maps);
+    for allocations_key in ["nativeAllocations", "jsAllocations"] {
+        if let Some(times) = thread[allocations_key]["time"].as_array_mut() {
+            for time in times {
+                *time = maps.time(time);
+            }
+        }
+    }
+    for key in [
+        "registerTime",
+        "unregisterTime",
+        "processStartupTime",
+        "processShutdownTime",
+    ] {
+        thread[key] = maps.time(&thread[key]);
+    }
+}
+
+/// Shifts a sample table's timestamps, which are stored either as absolute
+/// times or as deltas. For deltas, only the first value needs to be adjusted.
+fn shift_time_deltas(samples: &mut Value, maps: &IndexMaps) {
+    if let Some(time_deltas) = samples["timeDeltas"].as_array_mut() {
+        if let Some(first) = time_deltas.first_mut() {
+            *first = maps.time(first);
+        }
+    } else if let Some(times) = samples["time"].as_array_mut() {
+        for time in times {
+            *time = maps.time(time);
+        }
+    }
+}
+
+/// Shifts the start and end times of a list of paused ranges.
+fn shift_paused_ranges(paused_ranges: &mut Value, maps: &IndexMaps) {
+    if let Some(paused_ranges) = paused_ranges.as_array_mut() {
+        for paused_range in paused_ranges {
+            for key in ["startTime", "endTime"] {
+                if let Some(time) = paused_range.get_mut(key) {
+                    *time = maps.time(time);
+                }
+            }
+        }
+    }
+}
+
+fn map_column(table: &mut Value, key: &str, f: impl Fn(&Value) -> Value) {
+    if let Some(column) = table.get_mut(key).and_then(Value::as_array_mut) {
+        for value in column {
+            *value = f(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_profile(start_time: f64, func_name: &str) -> Value {
+        json!({
+            "meta": {
+                "startTime": start_time,
+                "interval": 1.0,
+                "preprocessedProfileVersion": 57,
+                "product": "app",
+                "symbolicated": true,
+                "categories": [{ "name": "Other", "color": "grey", "subcategories": ["Other"] }],
+                "markerSchema": [],
+                "pausedRanges": [{ "startTime": 3.0, "endTime": 4.0, "reason": "profiler" }],
+            },
+            "libs": [],
+            "shared": { "stringArray": ["shared-name", func_name] },
+            "threads": [{
+                "pid": "100",
+                "tid": 100,
+                "funcTable": { "name": [1, 0], "fileName": [null, null] },
+                "frameTable": { "category": [0], "subcategory": [0] },
+                "markers": {
+                    "name": [0],
+                    "category": [0],
+                    "phase": [1],
+                    "startTime": [6.0],
+                    "endTime": [8.0],
+                    "data": [{ "type": "IO", "cause": { "stack": 0, "time": 6.5 } }],
+                },
+                "samples": { "timeDeltas": [5.0, 1.0] },
+                "nativeAllocations": { "time": [5.5], "length": 1 },
+                "jsAllocations": { "time": [5.25], "length": 1 },
+                "pausedRanges": [{ "startTime": 3.0, "endTime": 4.0, "reason": "profiler" }],
+                "registerTime": 2.0,
+                "unregisterTime": null,
+                "processStartupTime": 0.0,
+                "processShutdownTime": null,
+            }],
+            "counters": [],
+        })
+    }
+
+    #[test]
+    fn merge_two_profiles() {
+        let merged = merge_profiles(
+            vec![make_profile(1000.0, "foo"), make_profile(1010.0, "bar")],
+            MergeTimeAlignment::WallClock,
+        );
+        assert_eq!(
+            merged["shared"]["stringArray"],
+            json!(["shared-name", "foo", "bar"])
+        );
+        assert_eq!(merged["meta"]["startTime"], json!(1000.0));
+        assert_eq!(merged["meta"]["categories"].as_array().unwrap().len(), 1);
+
+        let threads = merged["threads"].as_array().unwrap();
+        assert_eq!(threads[0]["pid"], json!("100"));
+        assert_eq!(threads[1]["pid"], json!("100.2"));
+        assert_eq!(threads[1]["funcTable"]["name"], json!([2, 0]));
+        assert_eq!(threads[1]["samples"]["timeDeltas"], json!([15.0, 1.0]));
+        assert_eq!(threads[1]["registerTime"], json!(12.0));
+        assert_eq!(threads[1]["nativeAllocations"]["time"], json!([15.5]));
+        assert_eq!(threads[1]["jsAllocations"]["time"], json!([15.25]));
+        assert_eq!(threads[1]["markers"]["startTime"], json!([16.0]));
+        assert_eq!(
+            threads[1]["markers"]["data"][0]["cause"]["time"],
+            json!(16.5)
+        );
+        assert_eq!(
+            threads[1]["pausedRanges"],
+            json!([{ "startTime": 13.0, "endTime": 14.0, "reason": "profiler" }])
+        );
+        assert_eq!(
+            merged["meta"]["pausedRanges"],
+            json!([
+                { "startTime": 3.0, "endTime": 4.0, "reason": "profiler" },
+                { "startTime": 13.0, "endTime": 14.0, "reason": "profiler" },
+            ])
+        );
+        assert_eq!(threads[1]["unregisterTime"], Value::Null);
+    }
+}
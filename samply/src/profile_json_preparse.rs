@@ -1,8 +1,6 @@
 use std::collections::HashMap;
-use std::ffi::OsString;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::Path;
 use std::str::FromStr;
 
 use debugid::DebugId;
@@ -10,6 +8,8 @@ use flate2::bufread::GzDecoder;
 use serde_derive::Deserialize;
 use wholesym::{CodeId, LibraryInfo};
 
+use crate::shared::save_profile::is_gzip_data;
+
 #[derive(Deserialize, Default, Clone, Debug, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 struct ProfileJsonProcess {
@@ -42,15 +42,15 @@ struct ProfileJsonLib {
 
 pub fn parse_libinfo_map_from_profile_file(
     file: File,
-    filename: &Path,
 ) -> Result<HashMap<(String, DebugId), LibraryInfo>, std::io::Error> {
     // Read the profile.json file and parse it as JSON.
     // Build a map (debugName, breakpadID) -> debugPath from the information
     // in profile(\.processes\[\d+\])*(\.threads\[\d+\])?\.libs.
-    let reader = BufReader::new(file);
+    let mut reader = BufReader::new(file);
 
-    // Handle .gz profiles
-    if filename.extension() == Some(&OsString::from("gz")) {
+    // Handle .gz profiles. Check the file contents rather than the extension,
+    // because --output-format can write compressed files with any name.
+    if is_gzip_data(&mut reader)? {
         let decoder = GzDecoder::new(reader);
         let reader = BufReader::new(decoder);
         parse_libinfo_map_from_profile(reader)
@@ -102,6 +102,7 @@ fn libinfo_map_entry_for_lib(lib: &ProfileJsonLib) -> Option<LibraryInfo> {
         code_id,
         path,
         arch,
+        gnu_build_id: None,
     };
     Some(lib_info)
 }
@@ -7,7 +7,10 @@ use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::SystemTime;
 
+use async_compression::tokio::bufread::{GzipDecoder, GzipEncoder};
+use async_compression::Level;
 use futures_util::TryStreamExt;
 use http_body_util::combinators::BoxBody;
 use http_body_util::{BodyExt, Either, Full, StreamBody};
@@ -18,12 +21,14 @@ use hyper::{header, Method, Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
 use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 use rand::RngCore;
-use tokio::io::BufReader;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::net::TcpListener;
 use tokio_util::io::ReaderStream;
 use wholesym::SymbolManager;
 
 use crate::shared::ctrl_c;
+use crate::shared::save_profile::{precompressed_path, ContentEncoding, GZIP_COMPRESSION_LEVEL};
+use crate::symbolication_cache::{self, SymbolicationCache};
 
 #[derive(Clone, Debug)]
 pub struct ServerProps {
@@ -31,6 +36,40 @@ pub struct ServerProps {
     pub port_selection: PortSelection,
     pub verbose: bool,
     pub open_in_browser: bool,
+    pub cors: CorsPolicy,
+    /// The session token to use instead of a random one.
+    pub token: Option<String>,
+    /// The directory in which symbolication results are cached across runs.
+    pub symbolication_cache_dir: Option<PathBuf>,
+}
+
+/// Which web pages may use the server's API from the browser.
+#[derive(Clone, Debug)]
+pub struct CorsPolicy {
+    /// The origins, e.g. "https://profiler.firefox.com", which may make
+    /// requests. If empty, any origin may make requests.
+    pub allowed_origins: Vec<String>,
+    /// How many seconds browsers may cache the answer to a preflight request.
+    pub preflight_max_age: u32,
+}
+
+impl CorsPolicy {
+    /// The value of the Access-Control-Allow-Origin header for a request with
+    /// the given Origin header, or None if the origin is not allowed.
+    fn allow_origin_header(
+        &self,
+        origin: Option<&header::HeaderValue>,
+    ) -> Option<header::HeaderValue> {
+        if self.allowed_origins.is_empty() {
+            return Some(header::HeaderValue::from_static("*"));
+        }
+        let origin = origin?;
+        let origin_str = origin.to_str().ok()?;
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed.trim_end_matches('/') == origin_str)
+            .then(|| origin.clone())
+    }
 }
 
 const BAD_CHARS: &AsciiSet = &CONTROLS.add(b':').add(b'/');
@@ -57,69 +96,138 @@ pub struct RunningServerInfo {
     pub server_join_handle:
         tokio::task::JoinHandle<Result<(), Box<dyn std::error::Error + Send + Sync>>>,
     pub server_origin: String,
-    pub profiler_url: Option<String>,
+    /// The URL to give to the profiler as the symbol server, including the token.
+    pub symbol_server_url: String,
+    /// The URL which opens each served profile in the profiler, in the order
+    /// in which the profiles were given.
+    pub profiler_urls: Vec<String>,
 }
 
 pub async fn start_server(
-    profile_filename: Option<&Path>,
+    profile_filenames: &[PathBuf],
     server_props: ServerProps,
-    symbol_manager: SymbolManager,
+    symbol_manager: Arc<SymbolManager>,
     stop_signal: ctrl_c::Receiver,
 ) -> RunningServerInfo {
     let (listener, addr) = make_listener(server_props.address, server_props.port_selection).await;
 
-    let token = generate_token();
+    let token = server_props.token.unwrap_or_else(generate_token);
     let path_prefix = format!("/{token}");
     let env_server_override = std::env::var("SAMPLY_SERVER_URL").ok();
     let server_origin = match &env_server_override {
         Some(s) => s.trim_end_matches('/').to_string(),
-        None => format!("http://{addr}"),
+        None => {
+            if addr.ip().is_unspecified() {
+                eprintln!(
+                    "Note: The server is listening on all interfaces. Set the SAMPLY_SERVER_URL \
+                     environment variable to the URL under which other machines can reach this \
+                     server, so that the profiler URL works from a remote browser."
+                );
+            }
+            format!("http://{addr}")
+        }
     };
     let symbol_server_url = format!("{server_origin}{path_prefix}");
     let mut template_values: HashMap<&'static str, String> = HashMap::new();
     template_values.insert("SAMPLY_SERVER_URL", server_origin.clone());
     template_values.insert("PATH_PREFIX", path_prefix.clone());
 
-    let profiler_url = if profile_filename.is_some() {
-        let profile_url = format!("{symbol_server_url}/profile.json");
-
-        let env_profiler_override = std::env::var("PROFILER_URL").ok();
-        let profiler_origin = match &env_profiler_override {
-            Some(s) => s.trim_end_matches('/'),
-            None => "https://profiler.firefox.com",
-        };
+    let env_profiler_override = std::env::var("PROFILER_URL").ok();
+    let profiler_origin = match &env_profiler_override {
+        Some(s) => s.trim_end_matches('/'),
+        None => "https://profiler.firefox.com",
+    };
+    let encoded_symbol_server_url = utf8_percent_encode(&symbol_server_url, BAD_CHARS).to_string();
 
+    let mut profiler_urls = Vec::new();
+    let mut profile_links = String::new();
+    for (index, profile_filename) in profile_filenames.iter().enumerate() {
+        let profile_url = format!("{symbol_server_url}{}", profile_path(index));
         let encoded_profile_url = utf8_percent_encode(&profile_url, BAD_CHARS).to_string();
-        let encoded_symbol_server_url =
-            utf8_percent_encode(&symbol_server_url, BAD_CHARS).to_string();
         let profiler_url = format!(
             "{profiler_origin}/from-url/{encoded_profile_url}/?symbolServer={encoded_symbol_server_url}"
         );
-        template_values.insert("PROFILER_URL", profiler_url.clone());
-        template_values.insert("PROFILE_URL", profile_url);
-        Some(profiler_url)
-    } else {
-        None
-    };
-
-    let template_values = Arc::new(template_values);
+        let name = profile_filename
+            .file_name()
+            .unwrap_or(profile_filename.as_os_str())
+            .to_string_lossy();
+        profile_links.push_str(&format!(
+            "    <li><a href=\"{profiler_url}\">Open {}</a> in the profiler UI, or <a download href=\"{profile_url}\">download the raw profile JSON</a></li>\n",
+            escape_html(&name)
+        ));
+        if index == 0 {
+            template_values.insert("PROFILER_URL", profiler_url.clone());
+            template_values.insert("PROFILE_URL", profile_url);
+        }
+        profiler_urls.push(profiler_url);
+    }
+    template_values.insert("PROFILE_LINKS", profile_links);
+
+    // The index page at "/" contains the token. Only serve it without the token
+    // if the server can't be reached from other machines.
+    let serve_index_without_token = addr.ip().is_loopback();
+    if !serve_index_without_token {
+        eprintln!(
+            "Note: The server can be reached from other machines, so all requests need the \
+             session token, in the URL path or as an \"Authorization: Bearer\" header. The \
+             index page is at {symbol_server_url}/"
+        );
+    }
 
-    let server_join_handle = tokio::task::spawn(run_server(
-        listener,
-        symbol_manager,
-        profile_filename.map(PathBuf::from),
+    let state = Arc::new(ServerState {
         template_values,
-        path_prefix,
-        stop_signal,
-    ));
+        symbol_manager,
+        profile_filenames: profile_filenames.to_vec(),
+        auth: ServerAuth {
+            path_prefix: format!("/{token}"),
+            authorization_header: format!("Bearer {token}"),
+            serve_index_without_token,
+        },
+        cors: server_props.cors,
+        symbolication_cache: server_props
+            .symbolication_cache_dir
+            .map(|dir| Arc::new(SymbolicationCache::new(dir))),
+    });
+    let server_join_handle = tokio::task::spawn(run_server(listener, state, stop_signal));
 
     RunningServerInfo {
         server_join_handle,
         server_origin,
-        profiler_url,
+        symbol_server_url,
+        profiler_urls,
     }
 }
 
+/// The path, after the token prefix, at which the profile with the given index
+/// is served. The first profile is served at "/profile.json" so that its URL
+/// doesn't change when only one profile is served.
+fn profile_path(index: usize) -> String {
+    match index {
+        0 => "/profile.json".to_string(),
+        index => format!("/profile-{}.json", index + 1),
+    }
+}
+
+/// The inverse of `profile_path`.
+fn profile_index_for_path(path: &str) -> Option<usize> {
+    if path == "/profile.json" {
+        return Some(0);
+    }
+    let number: usize = path
+        .strip_prefix("/profile-")?
+        .strip_suffix(".json")?
+        .parse()
+        .ok()?;
+    number.checked_sub(1).filter(|index| *index > 0)
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 // Returns a base32 string for 24 random bytes.
 fn generate_token() -> String {
     let mut bytes = [0u8; 24];
@@ -179,6 +287,20 @@ const TEMPLATE_WITH_PROFILE: &str = r#"
 </ul>
 "#;
 
+const TEMPLATE_WITH_PROFILES: &str = r#"
+<!DOCTYPE html>
+<html lang="en">
+<meta charset="utf-8">
+<title>Profiler Symbol Server</title>
+<body>
+
+<p>This is the profiler symbol server, running at <code>SAMPLY_SERVER_URL</code>. You can:</p>
+<ul>
+PROFILE_LINKS    <li>Obtain symbols by POSTing to <code>PATH_PREFIX/symbolicate/v5</code>, with the format specified by the <a href="https://tecken.readthedocs.io/en/latest/symbolication.html">Mozilla symbolication API documentation</a>.</li>
+    <li>Obtain source code by POSTing to <code>PATH_PREFIX/source/v1</code>, with the format specified in this <a href="https://github.com/mstange/profiler-get-symbols/issues/24#issuecomment-989985588">github comment</a>.</li>
+</ul>
+"#;
+
 const TEMPLATE_WITHOUT_PROFILE: &str = r#"
 <!DOCTYPE html>
 <html lang="en">
@@ -193,16 +315,21 @@ const TEMPLATE_WITHOUT_PROFILE: &str = r#"
 </ul>
 "#;
 
+/// What the server's request handlers share.
+struct ServerState {
+    template_values: HashMap<&'static str, String>,
+    symbol_manager: Arc<SymbolManager>,
+    profile_filenames: Vec<PathBuf>,
+    auth: ServerAuth,
+    cors: CorsPolicy,
+    symbolication_cache: Option<Arc<SymbolicationCache>>,
+}
+
 async fn run_server(
     listener: TcpListener,
-    symbol_manager: SymbolManager,
-    profile_filename: Option<PathBuf>,
-    template_values: Arc<HashMap<&'static str, String>>,
-    path_prefix: String,
+    state: Arc<ServerState>,
     mut stop_signal: ctrl_c::Receiver,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let symbol_manager = Arc::new(symbol_manager);
-
     // We start a loop to continuously accept incoming connections
     loop {
         let (stream, _) = tokio::select! {
@@ -216,10 +343,7 @@ async fn run_server(
         // `hyper::rt` IO traits.
         let io = TokioIo::new(stream);
 
-        let symbol_manager = symbol_manager.clone();
-        let profile_filename = profile_filename.clone();
-        let template_values = template_values.clone();
-        let path_prefix = path_prefix.clone();
+        let state = state.clone();
 
         // Spawn a tokio task to serve multiple connections concurrently
         tokio::task::spawn(async move {
@@ -228,15 +352,7 @@ async fn run_server(
                 // `service_fn` converts our function in a `Service`
                 .serve_connection(
                     io,
-                    service_fn(move |req| {
-                        symbolication_service(
-                            req,
-                            template_values.clone(),
-                            symbol_manager.clone(),
-                            profile_filename.clone(),
-                            path_prefix.clone(),
-                        )
-                    }),
+                    service_fn(move |req| symbolication_service(req, state.clone())),
                 )
                 .await
             {
@@ -246,51 +362,152 @@ async fn run_server(
     }
 }
 
+/// Opens the copy of the profile which was compressed with `encoding` when
+/// the profile was saved, if there is one and if it's at least as new as the
+/// profile, i.e. if the profile hasn't been replaced since.
+async fn open_precompressed_copy(
+    profile_path: &Path,
+    profile_modified: Option<SystemTime>,
+    encoding: ContentEncoding,
+) -> Option<tokio::fs::File> {
+    let path = precompressed_path(profile_path, encoding);
+    if path == profile_path {
+        return None;
+    }
+    let file = tokio::fs::File::open(&path).await.ok()?;
+    let modified = file.metadata().await.ok()?.modified().ok()?;
+    if modified < profile_modified? {
+        return None;
+    }
+    Some(file)
+}
+
+fn stream_body(reader: impl AsyncRead + Send + Sync + 'static) -> MyBody {
+    let reader_stream = ReaderStream::new(reader);
+    let stream_body = StreamBody::new(reader_stream.map_ok(Frame::data));
+    Either::Right(Either::Left(stream_body.boxed()))
+}
+
+/// Whether the request's Accept-Encoding header allows a response with the
+/// given content coding, e.g. "gzip" for "gzip, deflate, br" or "*". Browsers
+/// always send it.
+fn accepts_encoding(headers: &header::HeaderMap, encoding: ContentEncoding) -> bool {
+    let Some(accept_encoding) = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    accept_encoding.split(',').any(|item| {
+        let mut parts = item.split(';').map(str::trim);
+        let coding = parts.next().unwrap_or_default();
+        let is_refused = parts.any(|param| {
+            param
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+        (coding.eq_ignore_ascii_case(encoding.name()) || coding == "*") && !is_refused
+    })
+}
+
 type MyBody = Either<String, Either<BoxBody<Bytes, std::io::Error>, BoxBody<Bytes, Infallible>>>;
 
+/// How requests prove that they come from someone who was given the server's
+/// URL.
+struct ServerAuth {
+    /// The secret token as a path prefix, e.g. "/abc123". Browsers use this,
+    /// because the profiler can't add headers to its requests.
+    path_prefix: String,
+    /// The secret token as an "Authorization" header value, "Bearer abc123",
+    /// as an alternative for other clients.
+    authorization_header: String,
+    /// Whether the index page at "/", which links to the URLs with the token,
+    /// is served without the token.
+    serve_index_without_token: bool,
+}
+
+impl ServerAuth {
+    /// Returns the request's path without the token prefix, if the request
+    /// carries the token in its path or in its "Authorization" header.
+    fn authenticated_path<'a>(&self, req: &'a Request<hyper::body::Incoming>) -> Option<&'a str> {
+        let path = req.uri().path();
+        if let Some(path_without_prefix) = path.strip_prefix(&self.path_prefix) {
+            return Some(path_without_prefix);
+        }
+        let authorization = req.headers().get(header::AUTHORIZATION)?;
+        if authorization.as_bytes() == self.authorization_header.as_bytes() {
+            return Some(path);
+        }
+        None
+    }
+}
+
 async fn symbolication_service(
     req: Request<hyper::body::Incoming>,
-    template_values: Arc<HashMap<&'static str, String>>,
-    symbol_manager: Arc<SymbolManager>,
-    profile_filename: Option<PathBuf>,
-    path_prefix: String,
+    state: Arc<ServerState>,
 ) -> Result<Response<MyBody>, hyper::Error> {
-    let has_profile = profile_filename.is_some();
+    let ServerState {
+        template_values,
+        symbol_manager,
+        profile_filenames,
+        auth,
+        cors,
+        symbolication_cache,
+    } = &*state;
     let method = req.method();
     let path = req.uri().path();
     let mut response = Response::new(Either::Left(String::new()));
+    let index_page = || -> MyBody {
+        let template = match profile_filenames.len() {
+            0 => TEMPLATE_WITHOUT_PROFILE,
+            1 => TEMPLATE_WITH_PROFILE,
+            _ => TEMPLATE_WITH_PROFILES,
+        };
+        Either::Left(substitute_template(template, template_values))
+    };
 
-    let Some(path_without_prefix) = path.strip_prefix(&path_prefix) else {
-        // The secret prefix was not part of the URL. Do not send CORS headers.
+    let Some(path_without_prefix) = auth.authenticated_path(&req) else {
+        // The request doesn't have the token. Do not send CORS headers.
         match (method, path) {
-            (&Method::GET, "/") => {
+            (&Method::GET, "/") if auth.serve_index_without_token => {
                 response.headers_mut().insert(
                     header::CONTENT_TYPE,
                     header::HeaderValue::from_static("text/html"),
                 );
-                let template = match has_profile {
-                    true => TEMPLATE_WITH_PROFILE,
-                    false => TEMPLATE_WITHOUT_PROFILE,
-                };
-                *response.body_mut() =
-                    Either::Left(substitute_template(template, &template_values));
+                *response.body_mut() = index_page();
             }
             _ => {
-                *response.status_mut() = StatusCode::NOT_FOUND;
+                *response.status_mut() = StatusCode::UNAUTHORIZED;
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    header::HeaderValue::from_static("Bearer"),
+                );
             }
         }
         return Ok(response);
     };
 
-    // If we get here, then the secret prefix was part of the URL.
-    // This part is open to the public: we allow requests across origins.
+    // If we get here, then the request had the token.
+    // This part is open to the public: we allow requests across origins, by
+    // default from any origin, or from the origins in the CORS policy.
     // For background on CORS, see this document:
     // https://w3c.github.io/webappsec-cors-for-developers/#cors
-    response.headers_mut().insert(
-        header::ACCESS_CONTROL_ALLOW_ORIGIN,
-        header::HeaderValue::from_static("*"),
-    );
+    if let Some(allow_origin) = cors.allow_origin_header(req.headers().get(header::ORIGIN)) {
+        response
+            .headers_mut()
+            .insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+    }
+    if !cors.allowed_origins.is_empty() {
+        // The response depends on the request's origin, so caches must not
+        // reuse it for other origins.
+        response
+            .headers_mut()
+            .insert(header::VARY, header::HeaderValue::from_static("Origin"));
+    }
 
+    let profile_filename =
+        profile_index_for_path(path_without_prefix).and_then(|index| profile_filenames.get(index));
     match (method, path_without_prefix, profile_filename) {
         (&Method::OPTIONS, _, _) => {
             // https://developer.mozilla.org/en-US/docs/Web/HTTP/Methods/OPTIONS
@@ -307,7 +524,7 @@ async fn symbolication_service(
                 );
                 response.headers_mut().insert(
                     header::ACCESS_CONTROL_MAX_AGE,
-                    header::HeaderValue::from(86400),
+                    header::HeaderValue::from(cors.preflight_max_age),
                 );
                 if let Some(req_headers) = req.headers().get(header::ACCESS_CONTROL_REQUEST_HEADERS)
                 {
@@ -324,30 +541,82 @@ async fn symbolication_service(
                 );
             }
         }
-        (&Method::GET, "/profile.json", Some(profile_filename)) => {
-            if profile_filename.extension() == Some(OsStr::new("gz")) {
-                response.headers_mut().insert(
-                    header::CONTENT_ENCODING,
-                    header::HeaderValue::from_static("gzip"),
-                );
-            }
+        (&Method::GET, "" | "/", _) => {
             response.headers_mut().insert(
                 header::CONTENT_TYPE,
-                header::HeaderValue::from_static("application/json; charset=UTF-8"),
+                header::HeaderValue::from_static("text/html"),
             );
-
+            *response.body_mut() = index_page();
+        }
+        (&Method::GET, _, Some(profile_filename)) => {
             // Stream the file. This follows the send_file example from the hyper repo.
             // https://github.com/hyperium/hyper/blob/7206fe30302937075c51c16a69d1eb3bbce6a671/examples/send_file.rs
-            let file = tokio::fs::File::open(&profile_filename)
+            // The file may not exist yet if it is written during a live recording.
+            let Ok(file) = tokio::fs::File::open(&profile_filename).await else {
+                *response.status_mut() = StatusCode::NOT_FOUND;
+                return Ok(response);
+            };
+            let modified = file
+                .metadata()
                 .await
-                .expect("couldn't open profile file");
+                .and_then(|metadata| metadata.modified())
+                .ok();
 
             // Wrap in a buffered tokio_util::io::ReaderStream
-            let reader = BufReader::with_capacity(64 * 1024, file);
-            let reader_stream = ReaderStream::new(reader);
+            let mut reader = BufReader::with_capacity(64 * 1024, file);
+
+            // The file name doesn't tell us reliably whether the file is compressed,
+            // so peek at the first bytes and check for the gzip magic.
+            let is_gz = match reader.fill_buf().await {
+                Ok(buf) => buf.starts_with(&[0x1f, 0x8b]),
+                Err(_) => profile_filename.extension() == Some(OsStr::new("gz")),
+            };
+            let accepts_br = accepts_encoding(req.headers(), ContentEncoding::Brotli);
+            let accepts_gzip = accepts_encoding(req.headers(), ContentEncoding::Gzip);
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/json; charset=UTF-8"),
+            );
+            response.headers_mut().append(
+                header::VARY,
+                header::HeaderValue::from_static("Accept-Encoding"),
+            );
 
-            let stream_body = StreamBody::new(reader_stream.map_ok(Frame::data));
-            *response.body_mut() = Either::Right(Either::Left(stream_body.boxed()));
+            // Prefer the copies which were compressed when the profile was saved.
+            for (accepted, encoding) in [
+                (accepts_br, ContentEncoding::Brotli),
+                (accepts_gzip && !is_gz, ContentEncoding::Gzip),
+            ] {
+                if !accepted {
+                    continue;
+                }
+                if let Some(file) =
+                    open_precompressed_copy(profile_filename, modified, encoding).await
+                {
+                    response.headers_mut().insert(
+                        header::CONTENT_ENCODING,
+                        header::HeaderValue::from_static(encoding.name()),
+                    );
+                    *response.body_mut() = stream_body(file);
+                    return Ok(response);
+                }
+            }
+
+            if accepts_gzip {
+                response.headers_mut().insert(
+                    header::CONTENT_ENCODING,
+                    header::HeaderValue::from_static("gzip"),
+                );
+            }
+            *response.body_mut() = match (is_gz, accepts_gzip) {
+                // Send the file as it is.
+                (true, true) | (false, false) => stream_body(reader),
+                (false, true) => stream_body(GzipEncoder::with_quality(
+                    reader,
+                    Level::Precise(GZIP_COMPRESSION_LEVEL as i32),
+                )),
+                (true, false) => stream_body(GzipDecoder::new(reader)),
+            };
         }
         (&Method::POST, path, _) => {
             response.headers_mut().insert(
@@ -360,10 +629,18 @@ async fn symbolication_service(
             // Convert the `Collected<Bytes>` into a `String`.
             let request_body =
                 String::from_utf8(request_body.to_bytes().to_vec()).expect("invalid utf-8");
-            let response_json = symbol_manager.query_json_api(&path, &request_body).await;
-            let mut response_bytes = Vec::new();
-            let response_writer = BufWriter::new(&mut response_bytes);
-            serde_json::to_writer(response_writer, &response_json).expect("json writing error");
+            let response_bytes = match symbolication_cache {
+                Some(cache) if path == "/symbolicate/v5" => {
+                    query_symbolicate_api_with_cache(
+                        cache.clone(),
+                        symbol_manager,
+                        &path,
+                        request_body,
+                    )
+                    .await
+                }
+                _ => query_json_api(symbol_manager, &path, &request_body).await,
+            };
             let response_body = Full::new(Bytes::from(response_bytes));
 
             *response.body_mut() = Either::Right(Either::Right(response_body.boxed()));
@@ -376,6 +653,45 @@ async fn symbolication_service(
     Ok(response)
 }
 
+async fn query_json_api(symbol_manager: &SymbolManager, path: &str, request_body: &str) -> Vec<u8> {
+    let response_json = symbol_manager.query_json_api(path, request_body).await;
+    let mut response_bytes = Vec::new();
+    let response_writer = BufWriter::new(&mut response_bytes);
+    serde_json::to_writer(response_writer, &response_json).expect("json writing error");
+    response_bytes
+}
+
+/// Answers a /symbolicate/v5 request from the symbolication cache if it has
+/// the results for all frames. Otherwise, queries the symbol manager and adds
+/// the results to the cache.
+async fn query_symbolicate_api_with_cache(
+    cache: Arc<SymbolicationCache>,
+    symbol_manager: &SymbolManager,
+    path: &str,
+    request_body: String,
+) -> Vec<u8> {
+    let Ok(request) = serde_json::from_str::<symbolication_cache::Request>(&request_body) else {
+        // Let the symbol manager report the error.
+        return query_json_api(symbol_manager, path, &request_body).await;
+    };
+    let request = Arc::new(request);
+
+    let cached_response = {
+        let (cache, request) = (cache.clone(), request.clone());
+        tokio::task::spawn_blocking(move || cache.lookup(&request)).await
+    };
+    if let Ok(Some(response)) = cached_response {
+        return serde_json::to_vec(&response).expect("json writing error");
+    }
+
+    let response_json = symbol_manager.query_json_api(path, &request_body).await;
+    let response = serde_json::to_value(&response_json).expect("json writing error");
+    let response_bytes = serde_json::to_vec(&response).expect("json writing error");
+    // Don't make the client wait for the cache files to be written.
+    tokio::task::spawn_blocking(move || cache.insert(&request, &response));
+    response_bytes
+}
+
 fn substitute_template(template: &str, template_values: &HashMap<&'static str, String>) -> String {
     let mut s = template.to_string();
     for (key, value) in template_values {
@@ -383,3 +699,53 @@ fn substitute_template(template: &str, template_values: &HashMap<&'static str, S
     }
     s
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn profile_paths() {
+        assert_eq!(profile_path(0), "/profile.json");
+        assert_eq!(profile_path(2), "/profile-3.json");
+        for index in 0..3 {
+            assert_eq!(profile_index_for_path(&profile_path(index)), Some(index));
+        }
+        assert_eq!(profile_index_for_path("/profile-1.json"), None);
+        assert_eq!(profile_index_for_path("/profile-x.json"), None);
+        assert_eq!(profile_index_for_path("/symbolicate/v5"), None);
+    }
+
+    #[test]
+    fn accept_encoding() {
+        let accepts = |value: &str| {
+            let mut headers = header::HeaderMap::new();
+            headers.insert(
+                header::ACCEPT_ENCODING,
+                header::HeaderValue::from_str(value).unwrap(),
+            );
+            accepts_encoding(&headers, ContentEncoding::Gzip)
+        };
+        assert!(accepts("gzip, deflate, br, zstd"));
+        assert!(accepts("br;q=1.0, GZIP;q=0.5"));
+        assert!(accepts("*"));
+        assert!(!accepts("identity"));
+        assert!(!accepts("gzip;q=0"));
+        assert!(!accepts_encoding(
+            &header::HeaderMap::new(),
+            ContentEncoding::Gzip
+        ));
+
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            header::HeaderValue::from_static("gzip, br;q=0.9"),
+        );
+        assert!(accepts_encoding(&headers, ContentEncoding::Brotli));
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            header::HeaderValue::from_static("gzip, deflate"),
+        );
+        assert!(!accepts_encoding(&headers, ContentEncoding::Brotli));
+    }
+}
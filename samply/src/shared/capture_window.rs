@@ -0,0 +1,108 @@
+//! Support for `--capture-start-marker`, `--capture-stop-marker` and
+//! `--capture-duration`: once the profile is complete, only the samples
+//! inside the capture windows described by these options are kept. Markers
+//! are left alone, so that the markers which opened and closed each window
+//! are still visible.
+
+use std::time::Duration;
+
+use fxprof_processed_profile::{Profile, Timestamp};
+
+use super::prop_types::CaptureWindowProps;
+
+/// Removes all samples which are outside the capture windows. If the start
+/// marker is never seen, the profile is left untouched.
+pub fn retain_samples_in_capture_windows(profile: &mut Profile, props: &CaptureWindowProps) {
+    let starts = profile.marker_spans_with_name(&props.start_marker);
+    if starts.is_empty() {
+        eprintln!(
+            "No marker named {:?} was found, keeping all samples.",
+            props.start_marker
+        );
+        return;
+    }
+    let stops = match &props.stop_marker {
+        Some(stop_marker) => profile.marker_spans_with_name(stop_marker),
+        None => Vec::new(),
+    };
+    let windows = capture_windows(&starts, &stops, props.max_duration);
+    profile.retain_samples(|timestamp| is_in_windows(&windows, timestamp));
+}
+
+/// A capture window. An end of `None` means that the window lasts until the
+/// end of the profile.
+type CaptureWindow = (Timestamp, Option<Timestamp>);
+
+/// Computes the capture windows from the (sorted) start and stop marker spans.
+/// A start marker which is seen while a window is still open doesn't start
+/// another one. The returned windows are sorted and don't overlap.
+fn capture_windows(
+    starts: &[(Timestamp, Timestamp)],
+    stops: &[(Timestamp, Timestamp)],
+    max_duration: Option<Duration>,
+) -> Vec<CaptureWindow> {
+    let mut windows: Vec<CaptureWindow> = Vec::new();
+    for &(start, _) in starts {
+        match windows.last() {
+            Some((_, None)) => break,
+            Some((_, Some(end))) if start < *end => continue,
+            _ => {}
+        }
+        let stop = stops
+            .iter()
+            .find(|(stop_start, _)| *stop_start >= start)
+            .map(|(_, stop_end)| *stop_end);
+        let timeout = max_duration.map(|duration| start + duration);
+        let end = match (stop, timeout) {
+            (Some(stop), Some(timeout)) => Some(stop.min(timeout)),
+            (stop, timeout) => stop.or(timeout),
+        };
+        windows.push((start, end));
+    }
+    windows
+}
+
+fn is_in_windows(windows: &[CaptureWindow], timestamp: Timestamp) -> bool {
+    let following_window_index = windows.partition_point(|(start, _)| *start <= timestamp);
+    match following_window_index.checked_sub(1) {
+        Some(index) => windows[index].1.is_none_or(|end| timestamp < end),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ms(ms: u64) -> Timestamp {
+        Timestamp::from_nanos_since_reference(ms * 1_000_000)
+    }
+
+    #[test]
+    fn windows() {
+        let starts = [(ms(10), ms(10)), (ms(15), ms(16)), (ms(50), ms(50))];
+        let stops = [(ms(20), ms(25)), (ms(70), ms(70))];
+
+        let windows = capture_windows(&starts, &stops, None);
+        assert_eq!(
+            windows,
+            vec![(ms(10), Some(ms(25))), (ms(50), Some(ms(70)))]
+        );
+        assert!(!is_in_windows(&windows, ms(5)));
+        assert!(is_in_windows(&windows, ms(10)));
+        assert!(is_in_windows(&windows, ms(24)));
+        assert!(!is_in_windows(&windows, ms(25)));
+        assert!(is_in_windows(&windows, ms(60)));
+        assert!(!is_in_windows(&windows, ms(80)));
+
+        let windows = capture_windows(&starts, &stops, Some(Duration::from_millis(8)));
+        assert_eq!(
+            windows,
+            vec![(ms(10), Some(ms(18))), (ms(50), Some(ms(58)))]
+        );
+
+        let windows = capture_windows(&starts, &[], None);
+        assert_eq!(windows, vec![(ms(10), None)]);
+        assert!(is_in_windows(&windows, ms(1000)));
+    }
+}
@@ -24,6 +24,7 @@ pub struct JitCategoryManager {
     ion_ic_category: LazilyCreatedCategory,
     wasm_liftoff_category: LazilyCreatedCategory,
     wasm_turbofan_category: LazilyCreatedCategory,
+    dotnet_tier_categories: Vec<LazilyCreatedCategory>,
     generic_jit_category: LazilyCreatedCategory,
 }
 
@@ -135,6 +136,66 @@ impl JitCategoryManager {
         ),
         ("JS:*", Category("Turbofan", CategoryColor::Green), true),
         ("JS:?", Category("JavaScript", CategoryColor::Blue), true),
+        // Older V8 versions, e.g. in Node 16 and earlier, use the code event
+        // name as the prefix, followed by the same markers as above.
+        (
+            "LazyCompile:~",
+            Category("Interpreter", CategoryColor::Magenta),
+            true,
+        ),
+        (
+            "LazyCompile:^",
+            Category("Baseline", CategoryColor::Blue),
+            true,
+        ),
+        (
+            "LazyCompile:*",
+            Category("Turbofan", CategoryColor::Green),
+            true,
+        ),
+        (
+            "LazyCompile:",
+            Category("JavaScript", CategoryColor::Blue),
+            true,
+        ),
+        (
+            "Function:~",
+            Category("Interpreter", CategoryColor::Magenta),
+            true,
+        ),
+        (
+            "Function:^",
+            Category("Baseline", CategoryColor::Blue),
+            true,
+        ),
+        (
+            "Function:*",
+            Category("Turbofan", CategoryColor::Green),
+            true,
+        ),
+        (
+            "Function:",
+            Category("JavaScript", CategoryColor::Blue),
+            true,
+        ),
+        (
+            "Eval:~",
+            Category("Interpreter", CategoryColor::Magenta),
+            true,
+        ),
+        (
+            "Script:",
+            Category("Interpreter", CategoryColor::Magenta),
+            true,
+        ),
+        // The per-function copies of the interpreter entry trampoline, with
+        // --interpreted-frames-native-stack.
+        (
+            "InterpretedFunction:",
+            Category("Interpreter", CategoryColor::Magenta),
+            true,
+        ),
+        ("RegExp:", Category("RegExp", CategoryColor::Red), false),
         ("py::", Category("Python", CategoryColor::Blue), true),
         ("Builtin:", Category("Builtin", CategoryColor::Brown), false),
         (
@@ -192,6 +253,33 @@ impl JitCategoryManager {
         ),
     ];
 
+    /// The optimization tiers which .NET appends to method names with
+    /// `DOTNET_PerfMapShowOptimizationTiers=1`, e.g.
+    /// "void [System.Private.CoreLib] System.Threading.Thread::StartCallback()[OptimizedTier1]".
+    /// Precompiled ReadyToRun code is reported as "[PreJIT]".
+    const DOTNET_TIERS: &'static [(&'static str, Category<'static>)] = &[
+        ("[PreJIT]", Category("ReadyToRun", CategoryColor::Blue)),
+        ("[ReadyToRun]", Category("ReadyToRun", CategoryColor::Blue)),
+        ("[Tier0]", Category("Tier0", CategoryColor::Magenta)),
+        ("[Tier0-instr]", Category("Tier0", CategoryColor::Magenta)),
+        ("[Instrumented]", Category("Tier0", CategoryColor::Magenta)),
+        ("[QuickJitted]", Category("Tier0", CategoryColor::Magenta)),
+        ("[MinOptJitted]", Category("Tier0", CategoryColor::Magenta)),
+        ("[Tier1]", Category("Tier1", CategoryColor::Green)),
+        ("[Tier1-OSR]", Category("Tier1", CategoryColor::Green)),
+        ("[Tier1-instr]", Category("Tier1", CategoryColor::Green)),
+        (
+            "[InstrumentedTier1]",
+            Category("Tier1", CategoryColor::Green),
+        ),
+        ("[Optimized]", Category("Tier1", CategoryColor::Green)),
+        ("[OptimizedTier1]", Category("Tier1", CategoryColor::Green)),
+        (
+            "[OptimizedTier1OSR]",
+            Category("Tier1", CategoryColor::Green),
+        ),
+    ];
+
     pub fn new() -> Self {
         Self {
             categories: Self::CATEGORIES
@@ -203,6 +291,10 @@ impl JitCategoryManager {
             ion_ic_category: Category("IonIC", CategoryColor::Brown).into(),
             wasm_liftoff_category: Category("Liftoff (wasm)", CategoryColor::Blue).into(),
             wasm_turbofan_category: Category("Turbofan (wasm)", CategoryColor::Green).into(),
+            dotnet_tier_categories: Self::DOTNET_TIERS
+                .iter()
+                .map(|(_suffix, category)| (*category).into())
+                .collect(),
             generic_jit_category: Category("JIT", CategoryColor::Purple).into(),
         }
     }
@@ -302,6 +394,15 @@ impl JitCategoryManager {
             }
         }
 
+        for (&(suffix, _category), lazy_category_handle) in Self::DOTNET_TIERS
+            .iter()
+            .zip(self.dotnet_tier_categories.iter_mut())
+        {
+            if name.ends_with(suffix) {
+                return (lazy_category_handle.get(profile).into(), None);
+            }
+        }
+
         // "run_wasm_sm.js line 41 > WebAssembly.Module:916249: Function Element.updateChild"
         // "run_wasm_sm.js line 41 > WebAssembly.Module:825626: Function wasm-function[1491]"
 
@@ -310,6 +411,14 @@ impl JitCategoryManager {
     }
 
     fn handle_for_js_name(profile: &mut Profile, func_name: &str) -> JsName {
+        if let Some(location) = func_name.strip_prefix(' ') {
+            // V8 leaves the function name empty for anonymous functions, e.g.
+            // "JS:~ /home/.../index.js:12:34".
+            return JsName::NonSelfHosted(
+                profile.handle_for_string(&format!("(anonymous) {location}")),
+            );
+        }
+
         if let Some((before, after)) = func_name
             .split_once("[Call")
             .or_else(|| func_name.split_once("[Construct"))
@@ -388,4 +497,69 @@ mod test {
             _ => panic!(),
         }
     }
+
+    #[test]
+    fn dotnet_tiers() {
+        let mut manager = JitCategoryManager::new();
+        let mut profile = Profile::new(
+            "",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let (category, js_name) = manager.classify_jit_symbol(
+            "instance void [System.Private.CoreLib] System.Threading.Thread::StartCallback()[PreJIT]",
+            &mut profile,
+        );
+        let ready_to_run = profile.handle_for_category(Category("ReadyToRun", CategoryColor::Blue));
+        assert_eq!(category, ready_to_run.into());
+        assert!(js_name.is_none());
+        let (category, _js_name) = manager.classify_jit_symbol(
+            "int32 [Example] Example.Program::Fib(int32)[OptimizedTier1]",
+            &mut profile,
+        );
+        let tier1 = profile.handle_for_category(Category("Tier1", CategoryColor::Green));
+        assert_eq!(category, tier1.into());
+    }
+
+    #[test]
+    fn v8_names() {
+        let mut manager = JitCategoryManager::new();
+        let mut profile = Profile::new(
+            "",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        for (symbol, expected_category, expected_name) in [
+            (
+                "JS:~render /app/main.js:12:3",
+                Category("Interpreter", CategoryColor::Magenta),
+                "render /app/main.js:12:3",
+            ),
+            (
+                "LazyCompile:*render /app/main.js:12",
+                Category("Turbofan", CategoryColor::Green),
+                "render /app/main.js:12",
+            ),
+            (
+                "InterpretedFunction:render /app/main.js:12:3",
+                Category("Interpreter", CategoryColor::Magenta),
+                "render /app/main.js:12:3",
+            ),
+            (
+                "JS:^ /app/main.js:40:18",
+                Category("Baseline", CategoryColor::Blue),
+                "(anonymous) /app/main.js:40:18",
+            ),
+        ] {
+            let (category, js_name) = manager.classify_jit_symbol(symbol, &mut profile);
+            let expected_category = profile.handle_for_category(expected_category);
+            assert_eq!(category, expected_category.into(), "{symbol}");
+            match js_name {
+                Some(JsFrame::RegularInAdditionToNativeFrame(JsName::NonSelfHosted(s))) => {
+                    assert_eq!(profile.get_string(s), expected_name)
+                }
+                _ => panic!("no JS name for {symbol}"),
+            }
+        }
+    }
 }
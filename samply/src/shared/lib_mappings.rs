@@ -102,15 +102,22 @@ impl LibMappingsHierarchy {
         self.perf_map = Some(mappings);
     }
 
-    pub fn process_ops(&mut self, timestamp: u64) {
+    /// Applies all ops up to and including `timestamp`, so that addresses are
+    /// resolved against the mappings which were live at that time. Calls must
+    /// have non-decreasing timestamps. Returns whether any op was applied.
+    pub fn process_ops(&mut self, timestamp: u64) -> bool {
+        let mut applied_any = false;
         while let Some(op) = self.regular_libs.1.next_op_if_at_or_before(timestamp) {
             op.apply_to(&mut self.regular_libs.0);
+            applied_any = true;
         }
         for (mappings, ops) in &mut self.jitdumps {
             while let Some(op) = ops.next_op_if_at_or_before(timestamp) {
                 op.apply_to(mappings);
+                applied_any = true;
             }
         }
+        applied_any
     }
 
     pub fn convert_address(&self, address: u64) -> Option<(u32, &LibMappingInfo)> {
@@ -143,7 +150,10 @@ impl LibMappingOpQueue {
         self.0.is_empty()
     }
 
-    pub fn into_iter(self) -> LibMappingOpQueueIter {
+    /// Returns the ops in timestamp order. Ops with the same timestamp stay in
+    /// the order in which they were pushed.
+    pub fn into_iter(mut self) -> LibMappingOpQueueIter {
+        self.0.sort_by_key(|(timestamp, _op)| *timestamp);
         LibMappingOpQueueIter(self.0.into_iter().peekable())
     }
 }
@@ -167,6 +177,7 @@ pub enum LibMappingOp {
     Move(LibMappingMove),
     #[allow(unused)]
     Remove(LibMappingRemove),
+    RemoveRange(LibMappingRemoveRange),
     Clear,
 }
 
@@ -196,6 +207,9 @@ impl LibMappingOp {
             LibMappingOp::Remove(op) => {
                 lib_mappings.remove_mapping(op.start_avma);
             }
+            LibMappingOp::RemoveRange(op) => {
+                lib_mappings.remove_mappings_in_range(op.start_avma, op.end_avma);
+            }
             LibMappingOp::Clear => {
                 lib_mappings.clear();
             }
@@ -223,3 +237,81 @@ pub struct LibMappingMove {
 pub struct LibMappingRemove {
     pub start_avma: u64,
 }
+
+/// Removes all mappings which lie entirely within the range, e.g. when
+/// a library is unloaded with munmap.
+#[derive(Debug, Clone)]
+pub struct LibMappingRemoveRange {
+    pub start_avma: u64,
+    pub end_avma: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{LibraryInfo, Profile, ReferenceTimestamp, SamplingInterval};
+
+    use super::*;
+
+    fn add_lib(profile: &mut Profile, name: &str) -> LibraryHandle {
+        profile.add_lib(LibraryInfo {
+            name: name.to_string(),
+            debug_name: name.to_string(),
+            path: name.to_string(),
+            debug_path: name.to_string(),
+            debug_id: Default::default(),
+            code_id: None,
+            arch: None,
+        })
+    }
+
+    fn add_op(ops: &mut LibMappingOpQueue, timestamp: u64, lib_handle: LibraryHandle) {
+        ops.push(
+            timestamp,
+            LibMappingOp::Add(LibMappingAdd {
+                start_avma: 0x1000,
+                end_avma: 0x2000,
+                relative_address_at_start: 0,
+                info: LibMappingInfo::new_lib(lib_handle),
+            }),
+        );
+    }
+
+    #[test]
+    fn reused_address_range_resolves_by_time() {
+        let mut profile = Profile::new(
+            "",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let old_lib = add_lib(&mut profile, "old.so");
+        let new_lib = add_lib(&mut profile, "new.so");
+
+        // The ops are pushed out of order, e.g. from different sources.
+        let mut ops = LibMappingOpQueue::default();
+        add_op(&mut ops, 30, new_lib);
+        add_op(&mut ops, 10, old_lib);
+        ops.push(
+            20,
+            LibMappingOp::RemoveRange(LibMappingRemoveRange {
+                start_avma: 0x1000,
+                end_avma: 0x2000,
+            }),
+        );
+
+        let mut hierarchy = LibMappingsHierarchy::new(ops);
+        let lib_at = |hierarchy: &LibMappingsHierarchy| {
+            hierarchy
+                .convert_address(0x1800)
+                .map(|(_, info)| info.lib_handle)
+        };
+        assert!(!hierarchy.process_ops(5));
+        assert_eq!(lib_at(&hierarchy), None);
+        assert!(hierarchy.process_ops(15));
+        assert_eq!(lib_at(&hierarchy), Some(old_lib));
+        assert!(!hierarchy.process_ops(16));
+        hierarchy.process_ops(25);
+        assert_eq!(lib_at(&hierarchy), None);
+        hierarchy.process_ops(35);
+        assert_eq!(lib_at(&hierarchy), Some(new_lib));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod capture_window;
 pub mod context_switch;
 pub mod ctrl_c;
 pub mod included_processes;
@@ -12,7 +13,9 @@ pub mod perf_map;
 pub mod presymbolicate;
 pub mod process_name;
 pub mod process_sample_data;
+pub mod profile_size;
 pub mod prop_types;
+pub mod recording_stats;
 pub mod recycling;
 pub mod save_profile;
 pub mod stack_converter;
@@ -148,6 +148,63 @@ impl Cpus {
     }
 }
 
+/// The type of a core on a hybrid CPU, e.g. one of Intel's P-cores or E-cores,
+/// or a big or LITTLE core on ARM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreType {
+    Performance,
+    Efficiency,
+}
+
+impl CoreType {
+    pub fn track_name(&self) -> &'static str {
+        match self {
+            CoreType::Performance => "P-cores",
+            CoreType::Efficiency => "E-cores",
+        }
+    }
+}
+
+/// One thread per core type, which gets the samples that ran on cores of
+/// that type.
+pub struct CoreTypeThreads {
+    start_time: Timestamp,
+    process_handle: ProcessHandle,
+    /// The core type of each CPU, indexed by CPU number.
+    cpu_core_types: Vec<Option<CoreType>>,
+    threads: Vec<(CoreType, ThreadHandle)>,
+}
+
+impl CoreTypeThreads {
+    pub fn new(
+        cpu_core_types: Vec<Option<CoreType>>,
+        start_time: Timestamp,
+        profile: &mut Profile,
+    ) -> Self {
+        let process_handle = profile.add_process("Core types", 0, start_time);
+        Self {
+            start_time,
+            process_handle,
+            cpu_core_types,
+            threads: Vec::new(),
+        }
+    }
+
+    /// Returns the thread for the core type of this CPU, if the CPU's core
+    /// type is known.
+    pub fn thread_for_cpu(&mut self, cpu: u32, profile: &mut Profile) -> Option<ThreadHandle> {
+        let core_type = (*self.cpu_core_types.get(cpu as usize)?)?;
+        if let Some((_, thread)) = self.threads.iter().find(|(t, _)| *t == core_type) {
+            return Some(*thread);
+        }
+        let tid = self.threads.len() as u32;
+        let thread = profile.add_thread(self.process_handle, tid, self.start_time, false);
+        profile.set_thread_name(thread, core_type.track_name());
+        self.threads.push((core_type, thread));
+        Some(thread)
+    }
+}
+
 /// An example marker type with some text content.
 #[derive(Debug, Clone)]
 pub struct ThreadNameMarkerForCpuTrack(pub StringHandle, pub StringHandle);
@@ -202,3 +259,38 @@ impl Marker for OnCpuMarkerForThreadTrack {
         (self.cpu_name, self.switch_out_reason)
     }
 }
+
+/// Emitted on a thread's track when two consecutive samples of the thread ran
+/// on different CPUs.
+#[derive(Debug, Clone)]
+pub struct CpuMigrationMarker {
+    pub from_cpu: u32,
+    pub to_cpu: u32,
+}
+
+impl Marker for CpuMigrationMarker {
+    type FieldsType = (f64, f64);
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "CpuMigration";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted when a thread was sampled on a different CPU than in its previous sample.");
+
+    const CHART_LABEL: Option<&'static str> = Some("CPU {marker.data.from} → {marker.data.to}");
+    const TOOLTIP_LABEL: Option<&'static str> =
+        Some("Migrated from CPU {marker.data.from} to CPU {marker.data.to}");
+    const TABLE_LABEL: Option<&'static str> = Some("CPU {marker.data.from} → CPU {marker.data.to}");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema((
+        MarkerField::integer("from", "From CPU"),
+        MarkerField::integer("to", "To CPU"),
+    ));
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("CPU migration")
+    }
+
+    fn field_values(&self) -> (f64, f64) {
+        (self.from_cpu.into(), self.to_cpu.into())
+    }
+}
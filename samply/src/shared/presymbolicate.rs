@@ -97,7 +97,7 @@ pub fn get_presymbolicate_info(
     let rt = tokio::runtime::Runtime::new().unwrap();
 
     rt.block_on(async {
-        let (mut symbol_manager, quota_manager) =
+        let (symbol_manager, quota_manager) =
             create_symbol_manager_and_quota_manager(symbol_props, false);
 
         let native_frame_addresses_per_library = profile.native_frame_addresses_per_library();
@@ -116,6 +116,7 @@ pub fn get_presymbolicate_info(
                         .code_id
                         .as_ref()
                         .map(|id| wholesym::CodeId::from_str(id).expect("bad codeid")),
+                    gnu_build_id: None,
                 };
                 let rvas: Vec<u32> = rvas.into_iter().collect();
                 (lib_handle, lib_info, rvas)
@@ -151,10 +152,22 @@ pub fn get_presymbolicate_info(
             quota_manager.finish().await;
         }
 
-        let lib_symbols: Vec<_> = symbolication_results
-            .into_iter()
-            .filter_map(|x| x.unwrap())
-            .collect();
+        let mut lib_symbols = Vec::new();
+        let mut libs_without_symbols = Vec::new();
+        for result in symbolication_results {
+            match result.unwrap() {
+                Ok(symbols) => lib_symbols.push(symbols),
+                Err(debug_name) => libs_without_symbols.push(debug_name),
+            }
+        }
+        if !libs_without_symbols.is_empty() {
+            libs_without_symbols.sort();
+            eprintln!(
+                "Warning: Could not find symbols for {} libraries, their frames will only have addresses: {}",
+                libs_without_symbols.len(),
+                libs_without_symbols.join(", ")
+            );
+        }
         let string_table = match Arc::try_unwrap(string_table) {
             Ok(string_table) => string_table.into_inner().unwrap(),
             Err(_string_table) => panic!("String table Arc still in use"),
@@ -173,14 +186,15 @@ async fn get_lib_symbols(
     rvas: &[u32],
     symbol_manager: &SymbolManager,
     string_table: Arc<Mutex<SymbolStringTable>>,
-) -> Option<LibSymbolInfo> {
+) -> Result<LibSymbolInfo, String> {
     //eprintln!("Library {} ({}) has {} rvas", lib.debug_name, lib.debug_id, rvas.len());
+    let debug_name = lib.debug_name.as_deref().unwrap();
     let Ok(symbol_map) = symbol_manager
-        .load_symbol_map(lib.debug_name.as_deref().unwrap(), lib.debug_id.unwrap())
+        .load_symbol_map(debug_name, lib.debug_id.unwrap())
         .await
     else {
         //eprintln!("Couldn't load symbol map for {} at {} {} ({})", lib.debug_name, lib.path, lib.debug_path, lib.debug_id);
-        return None;
+        return Err(debug_name.to_string());
     };
 
     let mut sorted_addresses = Vec::new();
@@ -207,7 +221,7 @@ async fn get_lib_symbols(
         address_infos.push(address_info);
     }
 
-    Some(LibSymbolInfo {
+    Ok(LibSymbolInfo {
         lib_handle,
         sorted_addresses,
         address_infos,
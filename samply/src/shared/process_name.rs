@@ -1,16 +1,70 @@
+use std::borrow::Cow;
+
 #[allow(dead_code)]
 pub fn make_process_name(
     executable: &str,
     args: Vec<String>,
     arg_count_to_include: usize,
+    redact_env: bool,
 ) -> String {
     let mut args = args.iter().map(std::ops::Deref::deref);
     let _executable = args.next();
-    let mut included_args = args.take(arg_count_to_include).peekable();
-    if included_args.peek().is_some() {
-        let joined_args = shlex::try_join(included_args).unwrap_or_default();
+    let included_args: Vec<Cow<str>> = args
+        .take(arg_count_to_include)
+        .map(|arg| {
+            if redact_env {
+                redact_env_assignment(arg)
+            } else {
+                Cow::Borrowed(arg)
+            }
+        })
+        .collect();
+    if !included_args.is_empty() {
+        let joined_args =
+            shlex::try_join(included_args.iter().map(|arg| arg.as_ref())).unwrap_or_default();
         format!("{executable} {joined_args}")
     } else {
         executable.to_owned()
     }
 }
+
+/// Replaces the value of an environment variable assignment like "TOKEN=abc",
+/// as passed to commands like `env`, with "<redacted>".
+fn redact_env_assignment(arg: &str) -> Cow<'_, str> {
+    match arg.split_once('=') {
+        Some((name, _value))
+            if !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+        {
+            Cow::Owned(format!("{name}=<redacted>"))
+        }
+        _ => Cow::Borrowed(arg),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn process_names() {
+        let args = || {
+            vec![
+                "/usr/bin/env".to_string(),
+                "TOKEN=abc".to_string(),
+                "--flag=x".to_string(),
+                "ls".to_string(),
+            ]
+        };
+        assert_eq!(make_process_name("env", args(), 0, false), "env");
+        assert_eq!(
+            make_process_name("env", args(), 2, false),
+            "env 'TOKEN=abc' '--flag=x'"
+        );
+        assert_eq!(
+            make_process_name("env", args(), 100, true),
+            "env 'TOKEN=<redacted>' '--flag=x' ls"
+        );
+    }
+}
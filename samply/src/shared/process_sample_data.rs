@@ -1,14 +1,15 @@
 use fxprof_processed_profile::{
-    LibMappings, Marker, MarkerField, MarkerTiming, Profile, Schema, StringHandle,
-    SubcategoryHandle, ThreadHandle, Timestamp,
+    FrameFlags, FrameHandle, LibMappings, Marker, MarkerField, MarkerTiming, Profile, Schema,
+    StackHandle, StringHandle, SubcategoryHandle, ThreadHandle, Timestamp,
 };
 
 use super::lib_mappings::{LibMappingInfo, LibMappingOpQueue, LibMappingsHierarchy};
 use super::stack_converter::StackConverter;
 use super::stack_depth_limiting_frame_iter::StackDepthLimitingFrameIter;
-use super::types::StackFrame;
+use super::types::{FastHashMap, StackFrame};
 use super::unresolved_samples::{
-    SampleData, SampleOrMarker, UnresolvedSampleOrMarker, UnresolvedSamples, UnresolvedStacks,
+    AllocationData, SampleData, SampleOrMarker, UnresolvedSampleOrMarker, UnresolvedSamples,
+    UnresolvedStackHandle, UnresolvedStacks,
 };
 
 #[derive(Debug, Clone)]
@@ -81,9 +82,24 @@ impl ProcessSampleData {
             lib_mappings_hierarchy.add_perf_map_mappings(perf_map_mappings);
         }
         let mut stack_converter = StackConverter::new(user_category, kernel_category);
-        let samples = unresolved_samples.into_inner();
+        let mut truncation_frame_string = None;
+        // Resolve the samples in time order, so that each sample is resolved
+        // against the lib mappings which were live at its time, even if an
+        // address range was reused by a later mapping. The sort is stable, so
+        // samples with the same timestamp keep their order.
+        let mut samples = unresolved_samples.into_inner();
+        samples.sort_by_key(|sample| sample.timestamp_mono);
+        // Most samples repeat a stack which was seen before. Converting a stack
+        // interns each of its frames and stack nodes, so the resulting stack
+        // handle is cached for as long as the lib mappings stay the same.
+        let mut stack_handle_cache: FastHashMap<
+            (ThreadHandle, UnresolvedStackHandle, Option<FrameHandle>),
+            Option<StackHandle>,
+        > = FastHashMap::default();
         for sample in samples {
-            lib_mappings_hierarchy.process_ops(sample.timestamp_mono);
+            if lib_mappings_hierarchy.process_ops(sample.timestamp_mono) {
+                stack_handle_cache.clear();
+            }
             let UnresolvedSampleOrMarker {
                 thread_handle,
                 timestamp,
@@ -93,18 +109,47 @@ impl ProcessSampleData {
                 ..
             } = sample;
 
-            stack_frame_scratch_buf.clear();
-            stacks.convert_back(stack, stack_frame_scratch_buf);
-            let frames = stack_converter.convert_stack(
-                thread_handle,
-                stack_frame_scratch_buf,
-                &lib_mappings_hierarchy,
-                extra_label_frame,
-            );
-            let mut frames =
-                StackDepthLimitingFrameIter::new(profile, frames, thread_handle, user_category);
-            let stack_handle =
-                profile.handle_for_stack_frames(thread_handle, move |p| frames.next(p));
+            let cache_key = (thread_handle, stack, extra_label_frame);
+            let stack_handle = match stack_handle_cache.get(&cache_key) {
+                Some(stack_handle) => *stack_handle,
+                None => {
+                    stack_frame_scratch_buf.clear();
+                    let was_truncated = stacks.convert_back(stack, stack_frame_scratch_buf);
+
+                    // Stacks which were deeper than the maximum stack depth had
+                    // their outer frames cut off when they were recorded.
+                    let truncation_frame = if was_truncated {
+                        let truncation_frame_string = *truncation_frame_string
+                            .get_or_insert_with(|| profile.handle_for_string("(truncated)"));
+                        Some(profile.handle_for_frame_with_label(
+                            thread_handle,
+                            truncation_frame_string,
+                            user_category,
+                            FrameFlags::empty(),
+                        ))
+                    } else {
+                        None
+                    };
+
+                    let frames = stack_converter.convert_stack(
+                        thread_handle,
+                        stack_frame_scratch_buf,
+                        &lib_mappings_hierarchy,
+                        extra_label_frame,
+                        truncation_frame,
+                    );
+                    let mut frames = StackDepthLimitingFrameIter::new(
+                        profile,
+                        frames,
+                        thread_handle,
+                        user_category,
+                    );
+                    let stack_handle =
+                        profile.handle_for_stack_frames(thread_handle, move |p| frames.next(p));
+                    stack_handle_cache.insert(cache_key, stack_handle);
+                    stack_handle
+                }
+            };
             match sample_or_marker {
                 SampleOrMarker::Sample(SampleData { cpu_delta, weight }) => {
                     profile.add_sample(thread_handle, timestamp, stack_handle, cpu_delta, weight);
@@ -112,6 +157,13 @@ impl ProcessSampleData {
                 SampleOrMarker::MarkerHandle(mh) => {
                     profile.set_marker_stack(thread_handle, mh, stack_handle);
                 }
+                SampleOrMarker::Allocation(AllocationData {
+                    process,
+                    address,
+                    size,
+                }) => {
+                    profile.add_allocation_sample(process, timestamp, stack_handle, address, size);
+                }
             }
         }
 
@@ -190,6 +242,241 @@ impl Marker for OtherEventMarker {
     fn field_values(&self) {}
 }
 
+/// An interval marker for the time a thread spent in a syscall, from the
+/// raw_syscalls:sys_enter and raw_syscalls:sys_exit tracepoints.
+#[derive(Debug, Clone)]
+pub struct SyscallMarker {
+    pub name: StringHandle,
+    pub return_value: i64,
+}
+
+impl Marker for SyscallMarker {
+    type FieldsType = f64;
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Syscall";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted for the duration of a traced syscall, when recording with --syscalls.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.name}");
+    const TOOLTIP_LABEL: Option<&'static str> =
+        Some("{marker.name} returned {marker.data.returnValue}");
+    const TABLE_LABEL: Option<&'static str> = Some("returned {marker.data.returnValue}");
+
+    const FIELDS: Schema<Self::FieldsType> =
+        Schema(MarkerField::integer("returnValue", "Return value"));
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn field_values(&self) -> f64 {
+        self.return_value as f64
+    }
+}
+
+/// An instant marker for a sampled page fault, with the faulting address.
+#[derive(Debug, Clone)]
+pub struct PageFaultMarker {
+    pub address: StringHandle,
+    /// The number of page faults which this sample stands for.
+    pub count: u64,
+}
+
+impl Marker for PageFaultMarker {
+    type FieldsType = (StringHandle, f64);
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "PageFault";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted for sampled page faults. The marker's stack is the code which faulted.");
+
+    const TOOLTIP_LABEL: Option<&'static str> = Some("Page fault at {marker.data.address}");
+    const TABLE_LABEL: Option<&'static str> =
+        Some("{marker.data.address}, {marker.data.count} faults");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema((
+        MarkerField::string("address", "Address"),
+        MarkerField::integer("count", "Fault count"),
+    ));
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Page fault")
+    }
+
+    fn field_values(&self) -> (StringHandle, f64) {
+        (self.address, self.count as f64)
+    }
+}
+
+/// An instant marker for a crash, i.e. a signal like SIGSEGV which terminated
+/// the process. The marker's stack is the code which crashed.
+#[derive(Debug, Clone)]
+pub struct CrashMarker {
+    /// The signal name, e.g. "SIGSEGV".
+    pub signal: StringHandle,
+    /// The signal's si_code, e.g. 1 (SEGV_MAPERR) for a SIGSEGV.
+    pub code: i32,
+    /// The crashing thread's user registers, e.g. "rip=0x55d0c1a2 rsp=...".
+    /// Empty if the registers weren't sampled.
+    pub registers: StringHandle,
+}
+
+impl Marker for CrashMarker {
+    type FieldsType = (StringHandle, f64, StringHandle);
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Crash";
+
+    const DESCRIPTION: Option<&'static str> = Some(
+        "Emitted when a thread received a signal which terminated the process. The marker's stack is the code which crashed, and the registers are the crashing thread's registers at that point.",
+    );
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.signal}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("Crash: {marker.data.signal}");
+    const TABLE_LABEL: Option<&'static str> = Some("{marker.data.signal}, code {marker.data.code}");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema((
+        MarkerField::string("signal", "Signal"),
+        MarkerField::integer("code", "Signal code"),
+        MarkerField::string("registers", "Registers"),
+    ));
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Crash")
+    }
+
+    fn field_values(&self) -> (StringHandle, f64, StringHandle) {
+        (self.signal, self.code as f64, self.registers)
+    }
+}
+
+/// An instant marker for a hit of a function which was probed with --probe.
+/// The marker's stack is the stack of the call.
+#[derive(Debug, Clone)]
+pub struct ProbeMarker {
+    /// The probe, e.g. "libc.so.6:malloc".
+    pub probe: StringHandle,
+}
+
+impl Marker for ProbeMarker {
+    type FieldsType = StringHandle;
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Probe";
+
+    const DESCRIPTION: Option<&'static str> = Some(
+        "Emitted whenever a function which was probed with --probe was called. The marker's stack is the stack of the call.",
+    );
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.probe}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("Probe: {marker.data.probe}");
+    const TABLE_LABEL: Option<&'static str> = Some("{marker.data.probe}");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema(MarkerField::string("probe", "Probe"));
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.probe
+    }
+
+    fn field_values(&self) -> StringHandle {
+        self.probe
+    }
+}
+
+/// An instant marker for a line which the launched command printed, when
+/// recording with --capture-output.
+#[derive(Debug, Clone)]
+pub struct OutputLineMarker {
+    /// "stdout" or "stderr".
+    pub stream: StringHandle,
+    pub line: StringHandle,
+}
+
+impl Marker for OutputLineMarker {
+    type FieldsType = StringHandle;
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "OutputLine";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted for each line which the launched command wrote to stdout or stderr.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.line}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("{marker.name}: {marker.data.line}");
+    const TABLE_LABEL: Option<&'static str> = Some("{marker.data.line}");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema(MarkerField::string("line", "Line"));
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.stream
+    }
+
+    fn field_values(&self) -> StringHandle {
+        self.line
+    }
+}
+
+/// An interval marker for one run of the launched command, when recording
+/// with --iteration-count.
+#[derive(Debug, Clone)]
+pub struct IterationMarker {
+    /// 1 for the first run.
+    pub iteration: u32,
+}
+
+impl Marker for IterationMarker {
+    type FieldsType = f64;
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Iteration";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted for each run of the launched command, from its launch until it exited.");
+
+    const CHART_LABEL: Option<&'static str> = Some("Iteration {marker.data.iteration}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("Iteration {marker.data.iteration}");
+    const TABLE_LABEL: Option<&'static str> = Some("Iteration {marker.data.iteration}");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema(MarkerField::integer("iteration", "Iteration"));
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Iteration")
+    }
+
+    fn field_values(&self) -> f64 {
+        self.iteration as f64
+    }
+}
+
+/// An interval marker for a time range in which the profiler lost data, so
+/// that the samples and markers in it are incomplete.
+#[derive(Debug, Clone)]
+pub struct LostEventsMarker {
+    /// The number of lost events, or the number of missed samples.
+    pub count: u64,
+}
+
+impl Marker for LostEventsMarker {
+    type FieldsType = f64;
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "LostEvents";
+
+    const DESCRIPTION: Option<&'static str> = Some(
+        "Emitted when the profiler lost data in this time range, for example because the kernel's buffers were full or because the sampler fell behind. Samples and markers in this range are incomplete.",
+    );
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.count} lost");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("{marker.data.count} events lost");
+    const TABLE_LABEL: Option<&'static str> = Some("{marker.data.count} events lost");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema(MarkerField::integer("count", "Lost"));
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Lost events")
+    }
+
+    fn field_values(&self) -> f64 {
+        self.count as f64
+    }
+}
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct UserTimingMarker(pub StringHandle);
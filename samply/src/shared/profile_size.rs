@@ -0,0 +1,57 @@
+//! Support for `--max-profile-size`: profiles whose JSON would be bigger than
+//! the budget get fewer samples, by merging consecutive samples, until they
+//! fit. Markers, libraries and counters are kept as they are, so the profile
+//! stays loadable in the front-end, just with a coarser sampling resolution.
+
+use std::io::Write;
+
+use fxprof_processed_profile::Profile;
+
+/// Merges samples until the profile's JSON is at most `max_size` bytes, or
+/// until merging doesn't make it any smaller.
+pub fn downsample_to_size_budget(profile: &mut Profile, max_size: u64) {
+    let original_size = serialized_size(profile);
+    let mut size = original_size;
+    let mut factor = 1;
+    while size > max_size {
+        profile.merge_consecutive_samples(2);
+        let new_size = serialized_size(profile);
+        if new_size >= size {
+            break;
+        }
+        factor *= 2;
+        size = new_size;
+    }
+    if factor > 1 {
+        eprintln!(
+            "The profile would have been {} MB, merged every {factor} samples into one to reduce it to {} MB.",
+            original_size / 1_000_000,
+            size / 1_000_000
+        );
+    }
+    if size > max_size {
+        eprintln!(
+            "The profile is still larger than the maximum size, because most of it is not samples."
+        );
+    }
+}
+
+fn serialized_size(profile: &Profile) -> u64 {
+    let mut counter = ByteCounter(0);
+    serde_json::to_writer(&mut counter, profile).expect("Couldn't serialize the profile");
+    counter.0
+}
+
+/// A writer which only counts the bytes that are written to it.
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
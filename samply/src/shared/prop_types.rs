@@ -2,6 +2,8 @@ use std::ffi::OsString;
 use std::path::PathBuf;
 use std::time::Duration;
 
+use fxprof_processed_profile::Profile;
+use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
 
 use super::included_processes::IncludedProcesses;
@@ -25,6 +27,17 @@ impl CoreClrProfileProps {
     }
 }
 
+/// Describes which parts of a profile should be kept, based on its markers.
+#[derive(Debug, Clone)]
+pub struct CaptureWindowProps {
+    /// A window starts at each marker with this name.
+    pub start_marker: String,
+    /// A window ends at the end of the next marker with this name.
+    pub stop_marker: Option<String>,
+    /// A window ends at most this long after it started.
+    pub max_duration: Option<Duration>,
+}
+
 /// Properties which are meaningful both for recording a fresh process
 /// as well as for recording an existing process.
 #[derive(Debug, Clone)]
@@ -33,6 +46,14 @@ pub struct RecordingProps {
     pub output_file: PathBuf,
     pub time_limit: Option<Duration>,
     pub interval: Duration,
+    /// Sample this software clock instead of the hardware cycles event
+    /// (Linux only).
+    #[allow(dead_code)]
+    pub sampling_clock: Option<SamplingClock>,
+    /// How often the main event is sampled, if given with --period instead of
+    /// --rate (Linux only).
+    #[allow(dead_code)]
+    pub sampling_period: Option<SamplingPeriod>,
     #[allow(dead_code)]
     pub vm_hack: bool,
     #[allow(dead_code)]
@@ -41,6 +62,106 @@ pub struct RecordingProps {
     pub browsers: bool,
     #[allow(dead_code)]
     pub keep_etl: bool,
+    /// Write raw records to a temporary file during recording and only convert
+    /// them into a profile once recording has stopped (Linux only).
+    #[allow(dead_code)]
+    pub spill_to_disk: bool,
+    /// Only keep the most recent records of this duration, and write a snapshot
+    /// profile whenever SIGUSR2 is received (Linux only).
+    #[allow(dead_code)]
+    pub flight_recorder_window: Option<Duration>,
+    /// Replace the output file with a profile of everything recorded so far
+    /// at this interval, so that it can be viewed while recording (Linux only).
+    #[allow(dead_code)]
+    pub live_snapshot_interval: Option<Duration>,
+    /// Record kernel stacks in addition to user stacks (Linux only).
+    #[allow(dead_code)]
+    pub kernel_stacks: bool,
+    /// How user stacks are unwound (Linux only).
+    #[allow(dead_code)]
+    pub unwind_mode: UnwindMode,
+    /// Sample the energy counters and add them as power tracks (Linux and macOS only).
+    #[allow(dead_code)]
+    pub power: bool,
+    /// Trace these syscalls and add markers for them; an empty list selects a
+    /// default set (Linux only).
+    #[allow(dead_code)]
+    pub syscalls: Option<Vec<String>>,
+    /// Record page faults with their stacks and add markers for them (Linux only).
+    #[allow(dead_code)]
+    pub page_faults: Option<PageFaultMode>,
+    /// Record stacks when threads go off-CPU, and label the samples for their
+    /// off-CPU time with what the thread was waiting for (Linux only).
+    #[allow(dead_code)]
+    pub wall_clock: bool,
+    /// Probe these functions, given as "LIB:SYMBOL" or "kernel:SYMBOL", and add
+    /// a marker with the stack for each call (Linux only).
+    #[allow(dead_code)]
+    pub probes: Vec<String>,
+    /// Add a track per core type on hybrid CPUs, with the samples which ran on
+    /// cores of that type (Linux only).
+    #[allow(dead_code)]
+    pub core_types: bool,
+    /// Sample the hardware cycles event with this precise_ip level, from 0 to 3
+    /// (Linux only).
+    #[allow(dead_code)]
+    pub precise_ip: Option<u8>,
+    /// Drain the perf ring buffers on this many background threads, instead of
+    /// on the thread which converts the records (Linux only).
+    #[allow(dead_code)]
+    pub reader_threads: Option<usize>,
+    /// Only record the processes in this cgroup when recording all processes
+    /// (Linux only).
+    #[allow(dead_code)]
+    pub cgroup: Option<PathBuf>,
+}
+
+/// A software clock event which can be sampled instead of the hardware cycles
+/// event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub enum SamplingClock {
+    /// Counts while the recorded tasks are running.
+    TaskClock,
+    /// Counts while the CPU is running, based on a per-CPU timer.
+    CpuClock,
+}
+
+/// How often the main event is sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub enum SamplingPeriod {
+    /// This many samples per second; the kernel adjusts the period to match.
+    Frequency(u64),
+    /// A sample every time this much time has passed on the sampled clock.
+    Interval(Duration),
+    /// A sample every this many events, e.g. cycles, or nanoseconds for the
+    /// clock events.
+    Events(u64),
+}
+
+/// The way in which the user part of the stack of each sample is obtained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub enum UnwindMode {
+    /// The kernel walks the frame pointer chain while taking the sample.
+    FramePointers,
+    /// A copy of the user stack is taken with each sample and unwound with
+    /// DWARF CFI afterwards.
+    Dwarf,
+    /// The CPU's last branch record (LBR) keeps track of the call stack.
+    Lbr,
+}
+
+/// Which page faults are recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub enum PageFaultMode {
+    /// All page faults, sampled at the sampling rate.
+    All,
+    /// Only major page faults, i.e. the ones which needed I/O. Every major
+    /// fault is recorded.
+    Major,
 }
 
 /// Which process(es) to record.
@@ -74,16 +195,31 @@ pub struct ProfileCreationProps {
     /// Only include the main thread of each process.
     #[allow(dead_code)]
     pub main_thread_only: bool,
+    /// Only keep threads whose names match this pattern.
+    pub thread_name_filter: Option<Regex>,
+    /// Only keep the samples inside the capture windows described by these markers.
+    pub capture_window: Option<CaptureWindowProps>,
+    /// Merge samples until the profile JSON is at most this many bytes.
+    pub max_profile_size: Option<u64>,
     /// Merge non-overlapping threads of the same name.
     pub reuse_threads: bool,
     /// Fold repeated frames at the base of the stack.
     pub fold_recursive_prefix: bool,
+    /// Truncate stacks to at most this many frames, keeping the innermost frames.
+    pub max_stack_depth: Option<usize>,
+    /// Only keep the samples whose leaf address is in this library or address
+    /// range (Linux and perf.data only).
+    #[allow(dead_code)]
+    pub only_lib: Option<OnlyLibFilter>,
     /// Unlink jitdump/marker files
     pub unlink_aux_files: bool,
     /// Create a separate thread for each CPU.
     pub create_per_cpu_threads: bool,
     /// Include up to N command line arguments in the process name
     pub arg_count_to_include_in_process_name: usize,
+    /// Replace the values of environment variable assignments like "TOKEN=abc"
+    /// in the included command line arguments.
+    pub redact_env: bool,
     /// Override system architecture.
     #[allow(dead_code)]
     pub override_arch: Option<String>,
@@ -109,6 +245,27 @@ impl ProfileCreationProps {
             .as_deref()
             .unwrap_or(&self.fallback_profile_name)
     }
+
+    /// Makes `profile` drop the threads whose names don't match --threads, as
+    /// soon as they are created or renamed, so that no samples are kept for them.
+    pub fn apply_thread_name_filter(&self, profile: &mut Profile) {
+        if let Some(thread_name_filter) = self.thread_name_filter.clone() {
+            profile.set_thread_name_filter(move |name| {
+                name.is_some_and(|name| thread_name_filter.is_match(name))
+            });
+        }
+    }
+}
+
+/// Which samples are kept with --only-lib, based on the address of their leaf
+/// frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub enum OnlyLibFilter {
+    /// Samples in the library with this file name, e.g. "libxul.so".
+    Lib(String),
+    /// Samples in this address range, with an exclusive end.
+    AddressRange(u64, u64),
 }
 
 /// Properties which are meaningful for launching and recording a fresh process.
@@ -119,6 +276,14 @@ pub struct ProcessLaunchProps {
     pub args: Vec<OsString>,
     pub iteration_count: u32,
     pub ignore_exit_code: bool,
+    /// Tee the launched command's stdout and stderr and add a marker for each
+    /// line (Linux only).
+    #[allow(dead_code)]
+    pub capture_output: bool,
+    /// Sample the launched command's heap allocations through a preloaded
+    /// library, once per this many allocated bytes (Linux only).
+    #[allow(dead_code)]
+    pub alloc_sample_interval: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -138,6 +303,12 @@ pub struct ImportProps {
 pub struct SymbolProps {
     /// Extra directories containing symbol files
     pub symbol_dir: Vec<PathBuf>,
+    /// Additional URLs of symbol servers, used for all kinds of symbol lookups
+    pub symbol_server: Vec<String>,
+    /// Overrides the default base cache directory for downloaded symbol files
+    pub symbol_cache: Option<PathBuf>,
+    /// Only use local symbol files, don't download anything
+    pub no_network_symbols: bool,
     /// Additional URLs of symbol servers serving PDB / DLL / EXE files
     pub windows_symbol_server: Vec<String>,
     /// Overrides the default cache directory for Windows symbol files which were downloaded from a symbol server
@@ -150,4 +321,8 @@ pub struct SymbolProps {
     pub breakpad_symbol_cache: Option<PathBuf>,
     /// Extra directory containing symbol files, with the directory structure used by simpleperf's scripts
     pub simpleperf_binary_cache: Option<PathBuf>,
+    /// Path prefix replacements (from, to) for the source file paths from the debug info
+    pub source_path_prefix_map: Vec<(String, String)>,
+    /// How many loaded symbol maps are kept in memory for later requests
+    pub symbol_map_cache_size: usize,
 }
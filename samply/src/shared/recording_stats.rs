@@ -0,0 +1,69 @@
+//! The summary which `samply record --log-format json` prints at the end of a
+//! recording, so that wrapper tooling and CI can check the recording's quality.
+
+use fxprof_processed_profile::symbol_info::ProfileSymbolInfo;
+use fxprof_processed_profile::{Profile, ProfileStats};
+use serde_json::{json, Value};
+
+pub struct RecordingStats {
+    profile_stats: ProfileStats,
+    lost_events: u64,
+    /// The number of frame addresses which symbolication looked up, and how
+    /// many of them it found symbols for. Only known with --presymbolicate.
+    symbolication: Option<(usize, usize)>,
+}
+
+impl RecordingStats {
+    pub fn new(profile: &Profile) -> Self {
+        // The Linux recorder stores the number of lost events in the profile,
+        // if any events were lost.
+        let lost_events = profile
+            .get_extra_info_number("Recording", "Lost events")
+            .unwrap_or(0.0) as u64;
+        Self {
+            profile_stats: profile.stats(),
+            lost_events,
+            symbolication: None,
+        }
+    }
+
+    pub fn add_symbolication_stats(&mut self, profile: &Profile, symbol_info: &ProfileSymbolInfo) {
+        let address_count = profile
+            .native_frame_addresses_per_library()
+            .iter()
+            .map(|(_lib, addresses)| addresses.len())
+            .sum();
+        let symbolicated_address_count = symbol_info
+            .lib_symbols
+            .iter()
+            .map(|lib_symbols| lib_symbols.sorted_addresses.len())
+            .sum();
+        self.symbolication = Some((address_count, symbolicated_address_count));
+    }
+
+    pub fn to_json(&self) -> Value {
+        let symbolication =
+            self.symbolication
+                .map(|(address_count, symbolicated_address_count)| {
+                    let hit_rate = if address_count == 0 {
+                        1.0
+                    } else {
+                        symbolicated_address_count as f64 / address_count as f64
+                    };
+                    json!({
+                        "addresses": address_count,
+                        "symbolicatedAddresses": symbolicated_address_count,
+                        "hitRate": hit_rate,
+                    })
+                });
+        json!({
+            "type": "recording-stats",
+            "samples": self.profile_stats.sample_count,
+            "lostEvents": self.lost_events,
+            "threads": self.profile_stats.thread_count,
+            "markers": self.profile_stats.marker_count,
+            "libs": self.profile_stats.lib_count,
+            "symbolication": symbolication,
+        })
+    }
+}
@@ -1,16 +1,152 @@
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::BufWriter;
-use std::path::Path;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
 use flate2::{Compression, GzBuilder};
-use fxprof_processed_profile::Profile;
+use serde::Serialize;
 
 // Level two has an acceptable trade-off between how long compression
 // takes and how much data it saves on the profile JSONs I tested with.
-const GZIP_COMPRESSION_LEVEL: u32 = 2;
+pub const GZIP_COMPRESSION_LEVEL: u32 = 2;
 
-pub fn save_profile_to_file(profile: &Profile, output_path: &Path) -> std::io::Result<()> {
+// Brotli is only used for the copies which the server sends to browsers.
+// Quality five makes them a lot smaller than the gzip-compressed profile
+// while keeping the compression time acceptable for large profiles.
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW_BITS: u32 = 22;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The on-disk format of a saved profile file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFileFormat {
+    /// Plain, uncompressed JSON.
+    Json,
+    /// Gzip-compressed JSON.
+    JsonGz,
+}
+
+impl ProfileFileFormat {
+    /// Picks the format based on the file extension: ".gz" files are compressed.
+    pub fn from_path(path: &Path) -> Self {
+        if path.extension() == Some(OsStr::new("gz")) {
+            ProfileFileFormat::JsonGz
+        } else {
+            ProfileFileFormat::Json
+        }
+    }
+}
+
+/// A content encoding in which the server can send a profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+    Brotli,
+}
+
+impl ContentEncoding {
+    /// The name of the encoding in the Accept-Encoding and Content-Encoding
+    /// headers.
+    pub fn name(self) -> &'static str {
+        match self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Brotli => "br",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ContentEncoding::Gzip => "gz",
+            ContentEncoding::Brotli => "br",
+        }
+    }
+}
+
+/// Returns the path of the copy of the profile at `path` which is compressed
+/// with `encoding`, e.g. "profile.json.br" for "profile.json.gz". For a
+/// gzip-compressed profile, the gzip path can be the profile's own path.
+pub fn precompressed_path(path: &Path, encoding: ContentEncoding) -> PathBuf {
+    let path_without_gz = if path.extension() == Some(OsStr::new("gz")) {
+        path.with_extension("")
+    } else {
+        path.to_owned()
+    };
+    let mut file_name = path_without_gz.file_name().unwrap_or_default().to_owned();
+    file_name.push(".");
+    file_name.push(encoding.extension());
+    path.with_file_name(file_name)
+}
+
+/// Writes gzip- and brotli-compressed copies of the saved profile at `path`
+/// next to it, so that the server can send them without compressing the
+/// profile for every request. A gzip-compressed profile only gets a brotli
+/// copy.
+pub fn save_precompressed_copies(path: &Path) -> std::io::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let is_gzip = is_gzip_data(&mut reader)?;
+    let gzip_path = precompressed_path(path, ContentEncoding::Gzip);
+    if !is_gzip && gzip_path != path {
+        write_file_atomically(&gzip_path, |writer| {
+            let mut encoder = GzEncoder::new(writer, Compression::new(GZIP_COMPRESSION_LEVEL));
+            std::io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?.flush()
+        })?;
+        reader = BufReader::new(File::open(path)?);
+    }
+
+    let brotli_path = precompressed_path(path, ContentEncoding::Brotli);
+    write_file_atomically(&brotli_path, |writer| {
+        let mut encoder =
+            brotli::CompressorWriter::new(writer, 64 * 1024, BROTLI_QUALITY, BROTLI_WINDOW_BITS);
+        if is_gzip {
+            std::io::copy(&mut GzDecoder::new(reader), &mut encoder)?;
+        } else {
+            std::io::copy(&mut reader, &mut encoder)?;
+        }
+        encoder.into_inner().flush()
+    })
+}
+
+/// Writes the file to a temporary path first, so that the server never sends
+/// a partially written file.
+fn write_file_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let mut temp_file_name = path.file_name().unwrap_or_default().to_owned();
+    temp_file_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_file_name);
+    write(&mut BufWriter::new(File::create(&temp_path)?))?;
+    std::fs::rename(&temp_path, path)
+}
+
+/// Returns whether the data in `reader` starts with the gzip magic bytes.
+///
+/// This only peeks at the buffered data and does not consume anything, so
+/// the reader can be used afterwards to read the whole file.
+pub fn is_gzip_data(reader: &mut impl BufRead) -> std::io::Result<bool> {
+    Ok(reader.fill_buf()?.starts_with(&GZIP_MAGIC))
+}
+
+/// Reads a profile JSON file, which may be gzip-compressed.
+pub fn read_profile_json_file(path: &Path) -> std::io::Result<serde_json::Value> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let profile = if is_gzip_data(&mut reader)? {
+        serde_json::from_reader(BufReader::new(GzDecoder::new(reader)))
+    } else {
+        serde_json::from_reader(reader)
+    }?;
+    Ok(profile)
+}
+
+pub fn save_profile_to_file(
+    profile: &impl Serialize,
+    output_path: &Path,
+    format: ProfileFileFormat,
+) -> std::io::Result<()> {
     let output_file = match File::create(output_path) {
         Ok(output_file) => output_file,
         Err(err) => {
@@ -20,15 +156,62 @@ pub fn save_profile_to_file(profile: &Profile, output_path: &Path) -> std::io::R
     };
 
     let writer = BufWriter::new(output_file);
-    let is_gz = output_path.extension() == Some(OsStr::new("gz"));
-    if is_gz {
-        let name_without_gz = output_path.file_stem().unwrap().to_string_lossy();
-        let builder = GzBuilder::new().filename(name_without_gz.as_bytes());
-        let gz = builder.write(writer, Compression::new(GZIP_COMPRESSION_LEVEL));
-        let gz = BufWriter::new(gz);
-        serde_json::to_writer(gz, &profile)?;
-    } else {
-        serde_json::to_writer(writer, &profile)?;
+    match format {
+        ProfileFileFormat::JsonGz => {
+            let name_without_gz = if output_path.extension() == Some(OsStr::new("gz")) {
+                output_path.file_stem()
+            } else {
+                output_path.file_name()
+            };
+            let name_without_gz = name_without_gz.unwrap_or_default().to_string_lossy();
+            let builder = GzBuilder::new().filename(name_without_gz.as_bytes());
+            let gz = builder.write(writer, Compression::new(GZIP_COMPRESSION_LEVEL));
+            let gz = BufWriter::new(gz);
+            serde_json::to_writer(gz, &profile)?;
+        }
+        ProfileFileFormat::Json => {
+            serde_json::to_writer(writer, &profile)?;
+        }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn precompressed_paths() {
+        let path = Path::new("/tmp/profile.json");
+        let gz_path = Path::new("/tmp/profile.json.gz");
+        assert_eq!(precompressed_path(path, ContentEncoding::Gzip), gz_path);
+        assert_eq!(
+            precompressed_path(path, ContentEncoding::Brotli),
+            Path::new("/tmp/profile.json.br")
+        );
+        assert_eq!(precompressed_path(gz_path, ContentEncoding::Gzip), gz_path);
+        assert_eq!(
+            precompressed_path(gz_path, ContentEncoding::Brotli),
+            Path::new("/tmp/profile.json.br")
+        );
+    }
+
+    #[test]
+    fn precompressed_copies_decompress_to_the_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profile.json.gz");
+        let profile = serde_json::json!({ "meta": { "version": 1 }, "threads": [] });
+        save_profile_to_file(&profile, &path, ProfileFileFormat::JsonGz).unwrap();
+        save_precompressed_copies(&path).unwrap();
+
+        let brotli_path = precompressed_path(&path, ContentEncoding::Brotli);
+        let mut decompressed = Vec::new();
+        brotli::Decompressor::new(File::open(brotli_path).unwrap(), 4096)
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        let decompressed: serde_json::Value = serde_json::from_slice(&decompressed).unwrap();
+        assert_eq!(decompressed, profile);
+    }
+}
@@ -49,6 +49,7 @@ struct ConvertedStackIterD<I: Iterator<Item = SecondPassFrameInfo>> {
     inner: I,
     thread: ThreadHandle,
     pending_frame_handle: Option<FrameHandle>,
+    truncation_frame_handle: Option<FrameHandle>,
     js_name_for_baseline_interpreter: Option<JsName>,
 }
 
@@ -206,6 +207,9 @@ impl<I: Iterator<Item = SecondPassFrameInfo>> ConvertedStackIterD<I> {
         if let Some(pending_frame_handle) = self.pending_frame_handle.take() {
             return Some(pending_frame_handle);
         }
+        if let Some(truncation_frame_handle) = self.truncation_frame_handle.take() {
+            return Some(truncation_frame_handle);
+        }
         let SecondPassFrameInfo {
             location,
             category,
@@ -285,12 +289,17 @@ impl StackConverter {
     /// Takes a stack going from callee to root caller.
     ///
     /// Returns an iterator going from root caller to callee.
+    ///
+    /// If `truncation_frame` is set, it is emitted between `extra_first_frame`
+    /// and the first frame of `stack`, to indicate that the stack was cut off
+    /// at the root end.
     pub fn convert_stack<'a>(
         &'a mut self,
         thread: ThreadHandle,
         stack: &'a [StackFrame],
         lib_mappings: &'a LibMappingsHierarchy,
         extra_first_frame: Option<FrameHandle>,
+        truncation_frame: Option<FrameHandle>,
     ) -> ConvertedStackIter<'a> {
         let pass1 = FirstPassIter(stack.iter().cloned().rev());
         let pass2 = SecondPassIter {
@@ -309,6 +318,7 @@ impl StackConverter {
             inner: pass3,
             thread,
             pending_frame_handle: extra_first_frame,
+            truncation_frame_handle: truncation_frame,
             js_name_for_baseline_interpreter: None,
         };
         ConvertedStackIter(pass4)
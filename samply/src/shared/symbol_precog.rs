@@ -180,6 +180,7 @@ impl wholesym::samply_symbols::SymbolMapTrait for PrecogLibraySymbolMap {
                         address: info.rva,
                         size: info.size,
                         name: self.generation.symbol_name_handle(info.symbol.into()),
+                        kind: wholesym::SymbolKind::Function,
                     },
                     frames: info.frames.as_ref().map(|frames| {
                         wholesym::FramesLookupResult::Available(
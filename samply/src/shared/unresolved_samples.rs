@@ -1,7 +1,9 @@
 use std::collections::hash_map::Entry;
 use std::collections::BTreeMap;
 
-use fxprof_processed_profile::{CpuDelta, FrameHandle, MarkerHandle, ThreadHandle, Timestamp};
+use fxprof_processed_profile::{
+    CpuDelta, FrameHandle, MarkerHandle, ProcessHandle, ThreadHandle, Timestamp,
+};
 use rustc_hash::FxBuildHasher;
 use schnellru::{ByLength, LruMap};
 
@@ -133,6 +135,35 @@ impl UnresolvedSamples {
             sample_or_marker: SampleOrMarker::MarkerHandle(marker_handle),
         });
     }
+
+    /// Adds an allocation, or the free of an allocation. Allocations are
+    /// per-process, so `main_thread_handle` must be the main thread of
+    /// `process`; the stack is resolved for that thread.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn add_allocation_sample(
+        &mut self,
+        process: ProcessHandle,
+        main_thread_handle: ThreadHandle,
+        timestamp: Timestamp,
+        timestamp_mono: u64,
+        stack: UnresolvedStackHandle,
+        address: u64,
+        size: i64,
+    ) {
+        self.samples_and_markers.push(UnresolvedSampleOrMarker {
+            thread_handle: main_thread_handle,
+            timestamp,
+            timestamp_mono,
+            stack,
+            extra_label_frame: None,
+            sample_or_marker: SampleOrMarker::Allocation(AllocationData {
+                process,
+                address,
+                size,
+            }),
+        });
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -149,6 +180,7 @@ pub struct UnresolvedSampleOrMarker {
 pub enum SampleOrMarker {
     Sample(SampleData),
     MarkerHandle(MarkerHandle),
+    Allocation(AllocationData),
 }
 
 #[derive(Debug, Clone)]
@@ -157,12 +189,24 @@ pub struct SampleData {
     pub weight: i32,
 }
 
+#[derive(Debug, Clone)]
+pub struct AllocationData {
+    pub process: ProcessHandle,
+    pub address: u64,
+    /// Negative for deallocations.
+    pub size: i64,
+}
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct UnresolvedStackHandle(u32);
 
 impl UnresolvedStackHandle {
     /// Represents the empty stack / the root stack node
     pub const EMPTY: Self = Self(u32::MAX);
+
+    /// The root of stacks whose outer frames were cut off because the stack
+    /// was deeper than the maximum stack depth.
+    const TRUNCATED: Self = Self(u32::MAX - 1);
 }
 
 /// An interner that recursively interns stacks in the form of (prefix, frame).
@@ -175,6 +219,10 @@ pub struct UnresolvedStacks {
     stack_lookup: BTreeMap<(UnresolvedStackHandle, StackFrame), UnresolvedStackHandle>, // (prefix, frame) -> stack index
     stack_cache:
         LruMap<(UnresolvedStackHandle, StackFrame), UnresolvedStackHandle, ByLength, FxBuildHasher>,
+    /// Stacks with more frames than this are truncated at the root end when
+    /// they are added, keeping the innermost frames.
+    max_stack_depth: Option<usize>,
+    scratch_buf: Vec<StackFrame>,
 }
 
 impl UnresolvedStacks {
@@ -189,15 +237,21 @@ impl UnresolvedStacks {
 
 impl Default for UnresolvedStacks {
     fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl UnresolvedStacks {
+    pub fn new(max_stack_depth: Option<usize>) -> Self {
         Self {
             stacks: Vec::new(),
             stack_cache: LruMap::with_hasher(ByLength::new(Self::CACHE_SIZE), FxBuildHasher),
             stack_lookup: BTreeMap::new(),
+            max_stack_depth,
+            scratch_buf: Vec::new(),
         }
     }
-}
 
-impl UnresolvedStacks {
     /// Get the `UnresolvedStackHandle` for a stack. The stack must be ordered from
     /// caller-most to callee-most ("outside to inside").
     pub fn convert(&mut self, frames: impl Iterator<Item = StackFrame>) -> UnresolvedStackHandle {
@@ -205,6 +259,35 @@ impl UnresolvedStacks {
     }
 
     pub fn convert_with_prefix(
+        &mut self,
+        prefix: UnresolvedStackHandle,
+        frames: impl Iterator<Item = StackFrame>,
+    ) -> UnresolvedStackHandle {
+        let Some(max_stack_depth) = self.max_stack_depth else {
+            return self.intern(prefix, frames);
+        };
+
+        // Gather the full stack, so that we know how many of the outer frames
+        // need to be dropped.
+        let mut buf = std::mem::take(&mut self.scratch_buf);
+        buf.clear();
+        let prefix_was_truncated = self.convert_back(prefix, &mut buf);
+        buf.reverse();
+        buf.extend(frames);
+        let stack = if prefix_was_truncated || buf.len() > max_stack_depth {
+            let first_kept_frame = buf.len().saturating_sub(max_stack_depth);
+            self.intern(
+                UnresolvedStackHandle::TRUNCATED,
+                buf[first_kept_frame..].iter().cloned(),
+            )
+        } else {
+            self.intern(UnresolvedStackHandle::EMPTY, buf.iter().cloned())
+        };
+        self.scratch_buf = buf;
+        stack
+    }
+
+    fn intern(
         &mut self,
         mut prefix: UnresolvedStackHandle,
         frames: impl Iterator<Item = StackFrame>,
@@ -240,11 +323,68 @@ impl UnresolvedStacks {
     }
 
     // Appends the stack to `buf`, starting with the callee-most frame.
-    pub fn convert_back(&self, mut stack_index: UnresolvedStackHandle, buf: &mut Vec<StackFrame>) {
-        while stack_index != UnresolvedStackHandle::EMPTY {
+    // Returns whether the stack's outer frames were cut off when it was added.
+    pub fn convert_back(
+        &self,
+        mut stack_index: UnresolvedStackHandle,
+        buf: &mut Vec<StackFrame>,
+    ) -> bool {
+        loop {
+            match stack_index {
+                UnresolvedStackHandle::EMPTY => return false,
+                UnresolvedStackHandle::TRUNCATED => return true,
+                _ => {}
+            }
             let (prefix, frame) = self.stacks[stack_index.0 as usize];
             buf.push(frame);
             stack_index = prefix;
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shared::types::StackMode;
+
+    fn frames(addresses: std::ops::Range<u64>) -> impl DoubleEndedIterator<Item = StackFrame> {
+        addresses.map(|address| StackFrame::ReturnAddress(address, StackMode::User))
+    }
+
+    #[test]
+    fn stacks_deeper_than_the_limit_are_truncated_when_added() {
+        let mut stacks = UnresolvedStacks::new(Some(3));
+
+        let shallow = stacks.convert(frames(0..3));
+        let deep = stacks.convert(frames(0..1000));
+
+        // Only the innermost frames of the deep stack were interned.
+        assert_eq!(stacks.stacks.len(), 3 + 3);
+
+        let mut buf = Vec::new();
+        assert!(!stacks.convert_back(shallow, &mut buf));
+        assert_eq!(buf, frames(0..3).rev().collect::<Vec<_>>());
+
+        buf.clear();
+        assert!(stacks.convert_back(deep, &mut buf));
+        assert_eq!(buf, frames(997..1000).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn prefix_counts_towards_the_limit() {
+        let mut stacks = UnresolvedStacks::new(Some(4));
+
+        let user_stack = stacks.convert(frames(0..3));
+        let full_stack = stacks.convert_with_prefix(user_stack, frames(100..102));
+
+        let mut buf = Vec::new();
+        assert!(stacks.convert_back(full_stack, &mut buf));
+        assert_eq!(
+            buf,
+            frames(1..3)
+                .chain(frames(100..102))
+                .rev()
+                .collect::<Vec<_>>()
+        );
+    }
+}
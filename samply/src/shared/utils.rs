@@ -51,3 +51,10 @@ pub fn lib_handle_for_jitdump(
         arch: None,
     })
 }
+
+/// The profiler expects the values of power counters in picowatt-hours.
+#[allow(dead_code)]
+pub fn microjoules_to_picowatt_hours(energy_uj: u64) -> f64 {
+    // 1 pWh = 3600 * 10^-12 J = 3.6 * 10^-3 µJ
+    energy_uj as f64 / 3.6e-3
+}
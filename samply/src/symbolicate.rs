@@ -0,0 +1,199 @@
+//! `samply symbolicate`, which prints the function, file and line for
+//! addresses in the same format as addr2line, so that it can replace addr2line
+//! and atos in scripts.
+
+use std::io::Write;
+use std::path::Path;
+
+use wholesym::{LookupAddress64, SymbolMap};
+
+/// Which information is printed for each address, like the addr2line flags
+/// of the same names.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutputOptions {
+    pub functions: bool,
+    pub inlines: bool,
+    pub addresses: bool,
+    pub pretty_print: bool,
+    pub basenames: bool,
+}
+
+/// One frame at an address, starting with the innermost inlined function.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Frame {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+/// Parses a hex address with or without "0x" prefix.
+pub fn parse_address(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u64::from_str_radix(hex, 16).ok()
+}
+
+/// Looks up the frames at the address. Returns no frames if the address is
+/// not covered by a symbol, and a single frame without file and line if
+/// the symbol file has no debug info.
+pub async fn lookup_frames(symbol_map: &SymbolMap, address: LookupAddress64) -> Vec<Frame> {
+    let Some(address_info) = symbol_map.lookup_u64(address).await else {
+        return Vec::new();
+    };
+    let symbol_name = symbol_map
+        .resolve_symbol_name(address_info.symbol.name)
+        .into_owned();
+    let frames = match address_info.frames {
+        Some(frames) if !frames.is_empty() => frames,
+        _ => {
+            return vec![Frame {
+                function: Some(symbol_name),
+                ..Default::default()
+            }]
+        }
+    };
+    let outer_index = frames.len() - 1;
+    frames
+        .into_iter()
+        .enumerate()
+        .map(|(i, frame)| Frame {
+            // Use the symbol name if the debug info doesn't name the outer function.
+            function: frame
+                .function
+                .map(|f| symbol_map.resolve_function_name(f).into_owned())
+                .or_else(|| (i == outer_index).then(|| symbol_name.clone())),
+            file: frame.file_path.map(|p| {
+                symbol_map
+                    .resolve_source_file_path(p)
+                    .display_path()
+                    .into_owned()
+            }),
+            line: frame.line_number,
+        })
+        .collect()
+}
+
+/// Writes the information for one address, in the format of addr2line.
+pub fn write_frames(
+    out: &mut impl Write,
+    address: u64,
+    frames: &[Frame],
+    options: OutputOptions,
+) -> std::io::Result<()> {
+    if options.addresses {
+        write!(out, "0x{address:016x}")?;
+        if options.pretty_print {
+            write!(out, ": ")?;
+        } else {
+            writeln!(out)?;
+        }
+    }
+
+    let unknown_frame = [Frame::default()];
+    let frames = match frames {
+        [] => &unknown_frame[..],
+        frames if options.inlines => frames,
+        frames => &frames[..1],
+    };
+    for (i, frame) in frames.iter().enumerate() {
+        if options.pretty_print && i != 0 {
+            write!(out, " (inlined by) ")?;
+        }
+        if options.functions {
+            write!(out, "{}", frame.function.as_deref().unwrap_or("??"))?;
+            if options.pretty_print {
+                write!(out, " at ")?;
+            } else {
+                writeln!(out)?;
+            }
+        }
+        match &frame.file {
+            Some(file) => {
+                let file = match options.basenames {
+                    true => Path::new(file)
+                        .file_name()
+                        .map_or(file.into(), |name| name.to_string_lossy()),
+                    false => file.into(),
+                };
+                match frame.line {
+                    Some(line) => write!(out, "{file}:{line}")?,
+                    None => write!(out, "{file}:?")?,
+                }
+            }
+            None => write!(out, "??:0")?,
+        }
+        if !options.pretty_print {
+            writeln!(out)?;
+        }
+    }
+    if options.pretty_print {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn format(frames: &[Frame], options: OutputOptions) -> String {
+        let mut out = Vec::new();
+        write_frames(&mut out, 0x1f3a, frames, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn addr2line_format() {
+        let frames = [
+            Frame {
+                function: Some("inlined".into()),
+                file: Some("/src/inlined.h".into()),
+                line: Some(12),
+            },
+            Frame {
+                function: Some("outer".into()),
+                file: Some("/src/outer.cpp".into()),
+                line: None,
+            },
+        ];
+        assert_eq!(
+            format(&frames, OutputOptions::default()),
+            "/src/inlined.h:12\n"
+        );
+        let options = OutputOptions {
+            functions: true,
+            inlines: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            format(&frames, options),
+            "inlined\n/src/inlined.h:12\nouter\n/src/outer.cpp:?\n"
+        );
+        let options = OutputOptions {
+            functions: true,
+            inlines: true,
+            addresses: true,
+            pretty_print: true,
+            basenames: true,
+        };
+        assert_eq!(
+            format(&frames, options),
+            "0x0000000000001f3a: inlined at inlined.h:12 (inlined by) outer at outer.cpp:?\n"
+        );
+        let options = OutputOptions {
+            functions: true,
+            ..Default::default()
+        };
+        assert_eq!(format(&[], options), "??\n??:0\n");
+    }
+
+    #[test]
+    fn addresses() {
+        assert_eq!(parse_address("0x1f3a"), Some(0x1f3a));
+        assert_eq!(parse_address("1F3A\n"), Some(0x1f3a));
+        assert_eq!(parse_address("main"), None);
+    }
+}
@@ -0,0 +1,336 @@
+//! A cache of symbolication results on disk, for `--symbolication-cache`. It
+//! lets the server answer /symbolicate/v5 requests for libraries it has seen
+//! before without finding and parsing their debug files again, also after the
+//! server has been restarted.
+//!
+//! The results for each library are stored in
+//! `<dir>/<debug_name>/<breakpad_id>.jsonl`. Each line maps the hex addresses
+//! which were added by one request to the frame objects of the response, e.g.
+//! `{"0x1f3a": {"function": "main", "function_offset": "0x1a", ...}}`. New
+//! results are appended to the file, so the existing entries are never
+//! rewritten. A library is identified by its debug ID, so its entries never
+//! become stale. Delete the directory to get rid of the cached results, for
+//! example after better symbol files have become available.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde_derive::Deserialize;
+use serde_json::{Map, Value};
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Request {
+    WithJobsList { jobs: Vec<Job> },
+    JustOneJob(Job),
+}
+
+impl Request {
+    fn jobs(&self) -> &[Job] {
+        match self {
+            Request::WithJobsList { jobs } => jobs,
+            Request::JustOneJob(job) => std::slice::from_ref(job),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    memory_map: Vec<(String, String)>,
+    /// Each frame is a (module index, address) pair.
+    stacks: Vec<Vec<(usize, u64)>>,
+}
+
+/// A library's debug name and breakpad ID.
+type LibKey = (String, String);
+
+pub struct SymbolicationCache {
+    dir: PathBuf,
+    /// The cached frames of the libraries which were used so far, by address.
+    /// Libraries are read from the disk when they are first used.
+    libs: Mutex<HashMap<LibKey, Map<String, Value>>>,
+}
+
+impl SymbolicationCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            libs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the response to the request if the results for all of its
+    /// frames are in the cache.
+    pub fn lookup(&self, request: &Request) -> Option<Value> {
+        let mut libs = self.libs.lock().unwrap();
+        let requested_libs = requested_libs(request)?;
+        let mut results = Vec::new();
+        for job in request.jobs() {
+            let mut stacks = Vec::new();
+            for stack in &job.stacks {
+                let mut frames = Vec::new();
+                for (index, (module_index, address)) in stack.iter().enumerate() {
+                    let lib = &job.memory_map[*module_index];
+                    let cached_frames = self.cached_frames(&mut libs, lib)?;
+                    let mut frame = cached_frames.get(&hex(*address))?.as_object()?.clone();
+                    frame.insert("frame".to_string(), Value::from(index));
+                    frames.push(Value::Object(frame));
+                }
+                stacks.push(Value::Array(frames));
+            }
+            // Like the uncached response, list the libraries which were
+            // requested by any job.
+            let found_modules: Map<String, Value> = job
+                .memory_map
+                .iter()
+                .filter(|lib| requested_libs.contains(lib))
+                .map(|(debug_name, breakpad_id)| {
+                    (format!("{debug_name}/{breakpad_id}"), Value::Bool(true))
+                })
+                .collect();
+            results.push(serde_json::json!({
+                "stacks": stacks,
+                "found_modules": found_modules,
+            }));
+        }
+        Some(serde_json::json!({ "results": results }))
+    }
+
+    /// Adds the symbolicated frames of the response to the cache, and appends
+    /// the new frames to the files of their libraries.
+    pub fn insert(&self, request: &Request, response: &Value) {
+        let Some(results) = response["results"].as_array() else {
+            return;
+        };
+        let mut new_frames: HashMap<LibKey, Map<String, Value>> = HashMap::new();
+        {
+            let mut libs = self.libs.lock().unwrap();
+            for (job, result) in request.jobs().iter().zip(results) {
+                let Some(stacks) = result["stacks"].as_array() else {
+                    continue;
+                };
+                for (stack, response_stack) in job.stacks.iter().zip(stacks) {
+                    let Some(response_frames) = response_stack.as_array() else {
+                        continue;
+                    };
+                    for ((module_index, address), response_frame) in
+                        stack.iter().zip(response_frames)
+                    {
+                        let Some(lib) = job.memory_map.get(*module_index) else {
+                            continue;
+                        };
+                        let (debug_name, breakpad_id) = lib;
+                        let module_key = format!("{debug_name}/{breakpad_id}");
+                        if result["found_modules"][&module_key] != true {
+                            continue;
+                        }
+                        let Some(mut frame) = response_frame.as_object().cloned() else {
+                            continue;
+                        };
+                        // Addresses without a function may be found with other
+                        // symbol files later, e.g. those which were only looked
+                        // up in the profile's .syms.json file.
+                        if !frame.contains_key("function") {
+                            continue;
+                        }
+                        frame.remove("frame");
+                        let Some(cached_frames) = self.cached_frames(&mut libs, lib) else {
+                            continue;
+                        };
+                        let address = hex(*address);
+                        if cached_frames.contains_key(&address) {
+                            continue;
+                        }
+                        cached_frames.insert(address.clone(), Value::Object(frame.clone()));
+                        new_frames
+                            .entry(lib.clone())
+                            .or_default()
+                            .insert(address, Value::Object(frame));
+                    }
+                }
+            }
+        }
+
+        // The files are written without holding the lock, so that lookups
+        // and inserts for other requests don't wait for the disk.
+        for (lib, frames) in new_frames {
+            let path = self.lib_path(&lib).unwrap();
+            if let Err(err) = append_to_cache_file(&path, &frames) {
+                eprintln!("Could not write the symbolication cache file {path:?}: {err}");
+            }
+        }
+    }
+
+    fn cached_frames<'a>(
+        &self,
+        libs: &'a mut HashMap<LibKey, Map<String, Value>>,
+        lib: &LibKey,
+    ) -> Option<&'a mut Map<String, Value>> {
+        let path = self.lib_path(lib)?;
+        let cached_frames = libs
+            .entry(lib.clone())
+            .or_insert_with(|| read_cache_file(&path));
+        Some(cached_frames)
+    }
+
+    /// The path of the library's cache file, or None if the debug name or the
+    /// breakpad ID can't be used in a path.
+    fn lib_path(&self, (debug_name, breakpad_id): &LibKey) -> Option<PathBuf> {
+        let is_valid_debug_name = !debug_name.is_empty()
+            && debug_name != "."
+            && debug_name != ".."
+            && !debug_name.contains(['/', '\\', ':']);
+        let is_valid_breakpad_id =
+            !breakpad_id.is_empty() && breakpad_id.chars().all(|c| c.is_ascii_alphanumeric());
+        if !is_valid_debug_name || !is_valid_breakpad_id {
+            return None;
+        }
+        Some(
+            self.dir
+                .join(debug_name)
+                .join(format!("{breakpad_id}.jsonl")),
+        )
+    }
+}
+
+/// The libraries which are used by the frames of the request. Returns None if
+/// a frame's module index is not in the memory map.
+fn requested_libs(request: &Request) -> Option<HashSet<&LibKey>> {
+    let mut libs = HashSet::new();
+    for job in request.jobs() {
+        for (module_index, _) in job.stacks.iter().flatten() {
+            libs.insert(job.memory_map.get(*module_index)?);
+        }
+    }
+    Some(libs)
+}
+
+fn hex(address: u64) -> String {
+    format!("{address:#x}")
+}
+
+/// Reads all lines of a library's cache file. Lines which can't be parsed are
+/// skipped, e.g. a line which another samply process is still writing.
+fn read_cache_file(path: &Path) -> Map<String, Value> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return Map::new();
+    };
+    let mut cached_frames = Map::new();
+    for line in contents.lines() {
+        if let Ok(frames) = serde_json::from_str::<Map<String, Value>>(line) {
+            cached_frames.extend(frames);
+        }
+    }
+    cached_frames
+}
+
+/// Appends the frames as one line with a single write, so that concurrent
+/// writers, also from other samply processes, don't interleave their lines.
+fn append_to_cache_file(path: &Path, frames: &Map<String, Value>) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut line = serde_json::to_vec(frames)?;
+    line.push(b'\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn answers_cached_requests() {
+        let dir = std::env::temp_dir().join(format!(
+            "samply-symbolication-cache-test-{}",
+            std::process::id()
+        ));
+        let request: Request = serde_json::from_str(
+            r#"{"memoryMap": [["libfoo.so", "ABCD0"]], "stacks": [[[0, 4096], [0, 8192]]]}"#,
+        )
+        .unwrap();
+        let response = serde_json::json!({ "results": [{
+            "stacks": [[
+                {"frame": 0, "module_offset": "0x1000", "module": "libfoo.so", "function": "foo"},
+                {"frame": 1, "module_offset": "0x2000", "module": "libfoo.so", "function": "bar"},
+            ]],
+            "found_modules": {"libfoo.so/ABCD0": true},
+        }]});
+
+        assert_eq!(SymbolicationCache::new(dir.clone()).lookup(&request), None);
+        SymbolicationCache::new(dir.clone()).insert(&request, &response);
+        // A new cache reads the results from the disk, like after a restart.
+        let cached_response = SymbolicationCache::new(dir.clone()).lookup(&request);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(cached_response, Some(response));
+    }
+
+    #[test]
+    fn appends_new_frames() {
+        let dir = std::env::temp_dir().join(format!(
+            "samply-symbolication-cache-append-test-{}",
+            std::process::id()
+        ));
+        let request_for = |address: u64| -> Request {
+            serde_json::from_str(&format!(
+                r#"{{"memoryMap": [["libfoo.so", "ABCD0"]], "stacks": [[[0, {address}]]]}}"#
+            ))
+            .unwrap()
+        };
+        let response_for = |address: u64, function: &str| {
+            serde_json::json!({ "results": [{
+                "stacks": [[
+                    {"frame": 0, "module_offset": hex(address), "module": "libfoo.so", "function": function},
+                ]],
+                "found_modules": {"libfoo.so/ABCD0": true},
+            }]})
+        };
+
+        let cache = SymbolicationCache::new(dir.clone());
+        cache.insert(&request_for(4096), &response_for(4096, "foo"));
+        cache.insert(&request_for(8192), &response_for(8192, "bar"));
+        // Frames which are already cached are not written again.
+        cache.insert(&request_for(4096), &response_for(4096, "foo"));
+        let file_contents =
+            std::fs::read_to_string(dir.join("libfoo.so").join("ABCD0.jsonl")).unwrap();
+
+        let restarted_cache = SymbolicationCache::new(dir.clone());
+        let first = restarted_cache.lookup(&request_for(4096));
+        let second = restarted_cache.lookup(&request_for(8192));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(file_contents.lines().count(), 2);
+        assert_eq!(first, Some(response_for(4096, "foo")));
+        assert_eq!(second, Some(response_for(8192, "bar")));
+    }
+
+    #[test]
+    fn addresses_above_4_gib() {
+        let dir = std::env::temp_dir().join(format!(
+            "samply-symbolication-cache-large-test-{}",
+            std::process::id()
+        ));
+        let address = u64::from(u32::MAX) + 0x1000;
+        let request: Request = serde_json::from_str(&format!(
+            r#"{{"memoryMap": [["libhuge.so", "ABCD0"]], "stacks": [[[0, {address}]]]}}"#
+        ))
+        .unwrap();
+        let response = serde_json::json!({ "results": [{
+            "stacks": [[
+                {"frame": 0, "module_offset": "0x100000fff", "module": "libhuge.so", "function": "far"},
+            ]],
+            "found_modules": {"libhuge.so/ABCD0": true},
+        }]});
+
+        SymbolicationCache::new(dir.clone()).insert(&request, &response);
+        let cached_response = SymbolicationCache::new(dir.clone()).lookup(&request);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(cached_response, Some(response));
+    }
+}
@@ -37,20 +37,25 @@ fn create_symbol_manager_config_and_quota_manager(
     symbol_props: SymbolProps,
 ) -> (SymbolManagerConfig, Option<QuotaManager>) {
     let _config_dir = AppDirs::new(Some(SAMPLY_NAME), true).map(|dirs| dirs.config_dir);
-    let cache_base_dir = AppDirs::new(Some(SAMPLY_NAME), false).map(|dirs| dirs.cache_dir);
-    let symbols_dir = cache_base_dir.map(|cache_base_dir| cache_base_dir.join("symbols"));
+    let default_symbols_dir =
+        AppDirs::new(Some(SAMPLY_NAME), false).map(|dirs| dirs.cache_dir.join("symbols"));
+    // The quota manager only looks after our default cache directory. A cache
+    // directory which was specified explicitly is left alone.
+    let quota_manager = match (&symbol_props.symbol_cache, &default_symbols_dir) {
+        (None, Some(symbols_dir)) => create_quota_manager(symbols_dir),
+        _ => None,
+    };
+    let symbols_dir = symbol_props.symbol_cache.or(default_symbols_dir);
     let symbols_dir = symbols_dir.as_deref();
 
+    let use_network = !symbol_props.no_network_symbols;
     let mut config = SymbolManagerConfig::new()
-        .respect_nt_symbol_path(true)
-        .use_debuginfod(std::env::var("SAMPLY_USE_DEBUGINFOD").is_ok())
+        .symbol_map_cache_capacity(symbol_props.symbol_map_cache_size)
+        // _NT_SYMBOL_PATH usually refers to symbol servers.
+        .respect_nt_symbol_path(use_network)
+        .use_debuginfod(use_network && std::env::var("SAMPLY_USE_DEBUGINFOD").is_ok())
         .use_spotlight(true);
 
-    let quota_manager = match &symbols_dir {
-        Some(symbols_dir) => create_quota_manager(symbols_dir),
-        None => None,
-    };
-
     if let Some(symbols_dir) = symbols_dir {
         config = config.debuginfod_cache_dir_if_not_installed(symbols_dir.join("debuginfod"));
     }
@@ -60,11 +65,27 @@ fn create_symbol_manager_config_and_quota_manager(
 
     // Configure symbol servers and cache directories based on the information in the SymbolProps.
 
+    let (breakpad_symbol_servers, windows_symbol_servers, debuginfod_servers) = if use_network {
+        let with_general_servers = |servers: Vec<String>| -> Vec<String> {
+            servers
+                .into_iter()
+                .chain(symbol_props.symbol_server.iter().cloned())
+                .collect()
+        };
+        (
+            with_general_servers(symbol_props.breakpad_symbol_server),
+            with_general_servers(symbol_props.windows_symbol_server),
+            symbol_props.symbol_server.clone(),
+        )
+    } else {
+        (Vec::new(), Vec::new(), Vec::new())
+    };
+
     let breakpad_symbol_cache_dir = symbol_props
         .breakpad_symbol_cache
         .or_else(|| Some(symbols_dir?.join("breakpad")));
     if let Some(cache_dir) = breakpad_symbol_cache_dir {
-        for base_url in symbol_props.breakpad_symbol_server {
+        for base_url in breakpad_symbol_servers {
             config = config.breakpad_symbol_server(base_url, &cache_dir)
         }
         for dir in symbol_props.breakpad_symbol_dir {
@@ -80,11 +101,18 @@ fn create_symbol_manager_config_and_quota_manager(
         .windows_symbol_cache
         .or_else(|| Some(symbols_dir?.join("windows")));
     if let Some(cache_dir) = windows_symbol_cache_dir {
-        for base_url in symbol_props.windows_symbol_server {
+        for base_url in windows_symbol_servers {
             config = config.windows_symbol_server(base_url, &cache_dir)
         }
     }
 
+    if let Some(symbols_dir) = symbols_dir {
+        let debuginfod_cache_dir = symbols_dir.join("debuginfod");
+        for base_url in debuginfod_servers {
+            config = config.extra_debuginfod_server(base_url, &debuginfod_cache_dir);
+        }
+    }
+
     if let Some(binary_cache) = symbol_props.simpleperf_binary_cache {
         config = config.simpleperf_binary_cache_dir(binary_cache);
     }
@@ -93,6 +121,10 @@ fn create_symbol_manager_config_and_quota_manager(
         config = config.extra_symbol_directory(dir);
     }
 
+    for (from, to) in symbol_props.source_path_prefix_map {
+        config = config.remap_source_path_prefix(from, to);
+    }
+
     (config, quota_manager)
 }
 
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use base64::Engine;
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+use crate::export::symbols::FrameSymbols;
+use crate::shared::save_profile::is_gzip_data;
+
+/// The endpoint of the Firefox Profiler's profile storage service. It accepts
+/// a gzipped profile JSON and returns a JWT which contains the profile token.
+const DEFAULT_UPLOAD_URL: &str = "https://api.profiler.firefox.com/compressed-store";
+const DEFAULT_PROFILER_ORIGIN: &str = "https://profiler.firefox.com";
+const UPLOAD_ACCEPT_HEADER: &str = "application/vnd.firefox-profiler+json;version=1.0";
+
+#[derive(thiserror::Error, Debug)]
+pub enum UploadError {
+    #[error("Could not read the profile file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("The upload request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("The server responded with status {0}: {1}")]
+    ServerError(reqwest::StatusCode, String),
+
+    #[error("The server returned a token that could not be decoded")]
+    InvalidToken,
+}
+
+#[derive(Deserialize)]
+struct ProfileJsonWithMeta {
+    meta: ProfileJsonMeta,
+}
+
+#[derive(Deserialize)]
+struct ProfileJsonMeta {
+    #[serde(default)]
+    symbolicated: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadTokenPayload {
+    profile_token: String,
+}
+
+/// Returns whether the profile file at `path` has `meta.symbolicated` set.
+///
+/// Unsymbolicated profiles can still be uploaded, but the shared profile will
+/// only show addresses because nobody can supply symbols for it later.
+pub fn profile_file_is_symbolicated(path: &Path) -> Result<bool, UploadError> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    let profile: ProfileJsonWithMeta = if is_gzip_data(&mut reader)? {
+        serde_json::from_reader(BufReader::new(GzDecoder::new(reader)))
+    } else {
+        serde_json::from_reader(reader)
+    }
+    .map_err(std::io::Error::from)?;
+    Ok(profile.meta.symbolicated)
+}
+
+/// Asks on the terminal whether the profile may be published, and returns
+/// whether the user agreed. Anything other than "y" or "yes" declines.
+pub fn confirm_upload() -> bool {
+    eprintln!("The profile will be uploaded to {}.", upload_url());
+    eprintln!("Anyone with the link will be able to view it. Profiles can contain file paths,");
+    eprintln!("command lines, thread names and library names from this machine.");
+    eprint!("Upload the profile? [y/N] ");
+    let mut input = String::new();
+    if std::io::stdin().read_line(&mut input).is_err() {
+        return false;
+    }
+    matches!(input.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// Uploads the profile file at `path` to the Firefox Profiler's public storage
+/// and returns the URL under which the shared profile can be viewed.
+///
+/// The upload endpoint can be overridden with the `SAMPLY_UPLOAD_URL` environment
+/// variable, and the origin of the returned URL with `PROFILER_URL`.
+pub async fn upload_profile_file(path: &Path) -> Result<String, UploadError> {
+    upload_compressed_profile(read_as_gzip(path)?).await
+}
+
+/// Like [`upload_profile_file`], but for a profile which has already been
+/// parsed, for example because it was symbolicated before the upload.
+pub async fn upload_profile_json(profile: &Value) -> Result<String, UploadError> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    serde_json::to_writer(&mut encoder, profile).map_err(std::io::Error::from)?;
+    upload_compressed_profile(encoder.finish()?).await
+}
+
+async fn upload_compressed_profile(compressed_profile: Vec<u8>) -> Result<String, UploadError> {
+    let response = reqwest::Client::new()
+        .post(upload_url())
+        .header(reqwest::header::ACCEPT, UPLOAD_ACCEPT_HEADER)
+        .body(compressed_profile)
+        .send()
+        .await?;
+
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(UploadError::ServerError(status, body));
+    }
+
+    let profile_token = profile_token_from_jwt(body.trim()).ok_or(UploadError::InvalidToken)?;
+    let profiler_origin = std::env::var("PROFILER_URL").ok();
+    let profiler_origin = match &profiler_origin {
+        Some(s) => s.trim_end_matches('/'),
+        None => DEFAULT_PROFILER_ORIGIN,
+    };
+    Ok(format!("{profiler_origin}/public/{profile_token}/"))
+}
+
+fn upload_url() -> String {
+    std::env::var("SAMPLY_UPLOAD_URL").unwrap_or_else(|_| DEFAULT_UPLOAD_URL.to_string())
+}
+
+/// Replaces the address-based function names of the native frames in an
+/// unsymbolicated processed profile with the names in `symbols`, and marks
+/// the profile as symbolicated. Inlined functions are not added; each frame
+/// gets the name of its outer function.
+pub fn apply_frame_symbols(profile: &mut Value, symbols: &FrameSymbols) {
+    let mut strings = match profile["shared"]["stringArray"].take() {
+        Value::Array(strings) => strings,
+        _ => Vec::new(),
+    };
+    let mut string_indexes: HashMap<String, usize> = HashMap::new();
+
+    if let Some(threads) = profile["threads"].as_array_mut() {
+        for thread in threads {
+            let frame_count = thread["frameTable"]["func"].as_array().map_or(0, Vec::len);
+            for frame in 0..frame_count {
+                let Some(address) = thread["frameTable"]["address"][frame].as_i64() else {
+                    continue;
+                };
+                let Some(func) = thread["frameTable"]["func"][frame].as_u64() else {
+                    continue;
+                };
+                let func = func as usize;
+                let lib = thread["funcTable"]["resource"][func]
+                    .as_i64()
+                    .filter(|resource| *resource >= 0)
+                    .and_then(|resource| {
+                        thread["resourceTable"]["lib"][resource as usize].as_u64()
+                    });
+                let (Some(lib), Ok(address)) = (lib, u64::try_from(address)) else {
+                    continue;
+                };
+                let Some(outer_frame) = symbols
+                    .lookup(lib as usize, address)
+                    .and_then(|frames| frames.first())
+                else {
+                    continue;
+                };
+                let string_index = *string_indexes
+                    .entry(outer_frame.function.clone())
+                    .or_insert_with(|| {
+                        strings.push(Value::from(outer_frame.function.clone()));
+                        strings.len() - 1
+                    });
+                if let Some(name) = thread["funcTable"]["name"].get_mut(func) {
+                    *name = Value::from(string_index);
+                }
+            }
+        }
+    }
+
+    profile["shared"]["stringArray"] = Value::Array(strings);
+    profile["meta"]["symbolicated"] = Value::Bool(true);
+}
+
+/// Returns the file contents gzip-compressed, compressing them first if needed.
+fn read_as_gzip(path: &Path) -> Result<Vec<u8>, UploadError> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    let mut bytes = Vec::new();
+    if is_gzip_data(&mut reader)? {
+        reader.read_to_end(&mut bytes)?;
+    } else {
+        let mut encoder = GzEncoder::new(&mut bytes, Compression::default());
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            encoder.write_all(buf)?;
+            let len = buf.len();
+            reader.consume(len);
+        }
+        encoder.finish()?;
+    }
+    Ok(bytes)
+}
+
+/// The upload response is a JWT. Its payload contains the profile token.
+/// We don't need to verify the signature; we only need the token.
+fn profile_token_from_jwt(jwt: &str) -> Option<String> {
+    let payload = jwt.split('.').nth(1)?;
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let payload: UploadTokenPayload = serde_json::from_slice(&payload).ok()?;
+    Some(payload.profile_token)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extract_profile_token() {
+        // {"alg":"HS256","typ":"JWT"} . {"profileToken":"abc123"} . signature
+        let jwt = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJwcm9maWxlVG9rZW4iOiJhYmMxMjMifQ.c2ln";
+        assert_eq!(profile_token_from_jwt(jwt).as_deref(), Some("abc123"));
+        assert_eq!(profile_token_from_jwt("not-a-jwt"), None);
+    }
+
+    #[test]
+    fn apply_symbols_to_native_frames() {
+        let mut profile = serde_json::json!({
+            "meta": { "symbolicated": false },
+            "shared": { "stringArray": ["0x1234", "0x5678", "libfoo.so"] },
+            "threads": [{
+                "frameTable": { "address": [0x1234, 0x5678], "func": [0, 1] },
+                "funcTable": { "name": [0, 1], "resource": [0, 0] },
+                "resourceTable": { "lib": [0], "name": [2] },
+            }],
+        });
+        let mut symbols = FrameSymbols::default();
+        symbols.insert(
+            0,
+            0x1234,
+            vec![crate::export::ExportFrame {
+                function: "main".to_string(),
+                file: None,
+                line: None,
+                lib: None,
+            }],
+        );
+
+        apply_frame_symbols(&mut profile, &symbols);
+
+        assert_eq!(profile["meta"]["symbolicated"], true);
+        assert_eq!(
+            profile["threads"][0]["funcTable"]["name"],
+            serde_json::json!([3, 1])
+        );
+        assert_eq!(profile["shared"]["stringArray"][3], "main");
+    }
+}
@@ -35,9 +35,11 @@ pub fn process_etl_files(
         &mut schema_locator,
         &mut core_clr_context,
     );
-    if result.is_err() {
-        dbg!(etl_file);
-        dbg!(&result);
+    if let Err(err) = result {
+        eprintln!(
+            "Error: Could not process ETL trace {}: {err}",
+            etl_file.to_string_lossy()
+        );
         std::process::exit(1);
     }
 
@@ -48,9 +50,11 @@ pub fn process_etl_files(
             &mut schema_locator,
             &mut core_clr_context,
         );
-        if result.is_err() {
-            dbg!(extra_etl_file);
-            dbg!(&result);
+        if let Err(err) = result {
+            eprintln!(
+                "Error: Could not process ETL trace {}: {err}",
+                extra_etl_file.to_string_lossy()
+            );
             std::process::exit(1);
         }
     }
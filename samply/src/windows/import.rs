@@ -11,11 +11,14 @@ pub fn convert_etl_file_to_profile(filename: &Path, import_props: ImportProps) -
     let timebase = ReferenceTimestamp::from_system_time(timebase);
 
     let interval_8khz = SamplingInterval::from_nanos(122100); // 8192Hz // only with the higher recording rate?
-    let profile = Profile::new(
+    let mut profile = Profile::new(
         import_props.profile_creation_props.profile_name(),
         timebase,
         interval_8khz,
     );
+    import_props
+        .profile_creation_props
+        .apply_thread_name_filter(&mut profile);
 
     let arch = get_native_arch(); // TODO: Detect arch from file
 
@@ -559,13 +559,15 @@ impl ProfileContext {
             None
         };
 
+        let unresolved_stacks = UnresolvedStacks::new(profile_creation_props.max_stack_depth);
+
         Self {
             profile,
             profile_creation_props,
             processes: Processes::new(),
             threads: Threads::new(),
             thread_handles: BTreeMap::new(),
-            unresolved_stacks: UnresolvedStacks::default(),
+            unresolved_stacks,
             process_recycler,
             gpu_thread_handle: None,
             included_processes,
@@ -729,6 +731,7 @@ impl ProfileContext {
             Shlex::new(cmdline).collect(),
             self.profile_creation_props
                 .arg_count_to_include_in_process_name,
+            self.profile_creation_props.redact_env,
         )
     }
 
@@ -44,11 +44,12 @@ pub fn run(
     let timebase = std::time::SystemTime::now();
     let timebase = ReferenceTimestamp::from_system_time(timebase);
 
-    let profile = Profile::new(
+    let mut profile = Profile::new(
         profile_creation_props.profile_name(),
         timebase,
         SamplingInterval::from_nanos(1000000), // will be replaced with correct interval from file later
     );
+    profile_creation_props.apply_thread_name_filter(&mut profile);
 
     // Start xperf.
     let mut elevated_helper = ElevatedHelperSession::new(recording_props.output_file.clone())
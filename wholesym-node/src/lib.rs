@@ -0,0 +1,268 @@
+//! Node.js bindings for [`wholesym`](https://docs.rs/wholesym), built with
+//! napi-rs. They give Electron devtools and JavaScript crash pipelines
+//! in-process symbolication, with the same symbol file and symbol server
+//! support as samply.
+//!
+//! ```js
+//! const { SymbolManager } = require("wholesym-node");
+//!
+//! const symbolManager = new SymbolManager({
+//!   breakpadSymbolServers: [{ url: "https://symbols.mozilla.org/", cacheDir: "/tmp/symbols" }],
+//! });
+//! const results = await symbolManager.lookupAddresses("xul.pdb", breakpadId, [0x1234]);
+//! const response = await symbolManager.queryJsonApi("/symbolicate/v5", requestJson);
+//! ```
+
+use std::path::Path;
+use std::sync::Arc;
+
+use napi::{Error, Result};
+use napi_derive::napi;
+use wholesym::debugid::DebugId;
+use wholesym::{
+    AddressInfo64, CodeId, LibraryInfo, LookupAddress64, SymbolManagerConfig, SymbolMap,
+};
+
+/// A symbol server and the directory in which its files are cached.
+#[napi(object)]
+pub struct SymbolServer {
+    pub url: String,
+    pub cache_dir: String,
+}
+
+/// Where the symbol manager finds symbol files, in addition to the paths
+/// stored in the binaries.
+#[napi(object)]
+pub struct SymbolManagerOptions {
+    /// Directories which may contain symbol files, as `<dir>/<name>`.
+    pub symbol_dirs: Option<Vec<String>>,
+    /// Directories with Breakpad symbol files.
+    pub breakpad_symbol_dirs: Option<Vec<String>>,
+    pub breakpad_symbol_servers: Option<Vec<SymbolServer>>,
+    /// Servers for PDB, DLL and EXE files.
+    pub windows_symbol_servers: Option<Vec<SymbolServer>>,
+    pub debuginfod_servers: Option<Vec<SymbolServer>>,
+    /// Whether to respect the `_NT_SYMBOL_PATH` environment variable.
+    pub respect_nt_symbol_path: Option<bool>,
+    /// How many loaded symbol maps to keep in memory for repeated lookups in
+    /// the same library. Defaults to 0.
+    pub symbol_map_cache_capacity: Option<u32>,
+}
+
+/// A library, as known from the profile or crash report. The JSON API only
+/// gets the debug name and breakpad ID, so libraries which are only found via
+/// their path need to be added with `addKnownLibrary` first.
+#[napi(object, js_name = "LibraryInfo")]
+pub struct JsLibraryInfo {
+    pub debug_name: Option<String>,
+    pub breakpad_id: Option<String>,
+    pub debug_path: Option<String>,
+    pub name: Option<String>,
+    pub code_id: Option<String>,
+    pub path: Option<String>,
+    pub arch: Option<String>,
+}
+
+/// The result of looking up a library-relative address. Addresses and sizes
+/// are JS numbers, which are exact up to 2^53.
+#[napi(object)]
+pub struct LookupResult {
+    pub symbol_name: String,
+    /// The relative address at which the symbol starts.
+    pub symbol_address: f64,
+    pub symbol_size: Option<f64>,
+    /// The frames at this address from the debug info, if available, starting
+    /// with the innermost inlined function. The last frame is the outer
+    /// function.
+    pub frames: Option<Vec<LookupFrame>>,
+}
+
+#[napi(object)]
+pub struct LookupFrame {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+#[napi(js_name = "SymbolManager")]
+pub struct JsSymbolManager {
+    symbol_manager: Arc<wholesym::SymbolManager>,
+}
+
+#[napi]
+impl JsSymbolManager {
+    #[napi(constructor)]
+    pub fn new(options: Option<SymbolManagerOptions>) -> Self {
+        let config = match options {
+            Some(options) => config_from_options(options),
+            None => SymbolManagerConfig::new(),
+        };
+        Self {
+            symbol_manager: Arc::new(wholesym::SymbolManager::with_config(config)),
+        }
+    }
+
+    /// Tells the symbol manager where to find the library, so that JSON API
+    /// requests for its debug name and breakpad ID can be answered.
+    #[napi]
+    pub fn add_known_library(&self, library: JsLibraryInfo) -> Result<()> {
+        let library_info = library_info_from_js(library)?;
+        self.symbol_manager.add_known_library(library_info);
+        Ok(())
+    }
+
+    /// Runs a query against the symbolication JSON API, e.g. "/symbolicate/v5",
+    /// and resolves to the JSON response. The API is described in API.md.
+    #[napi]
+    pub async fn query_json_api(&self, url: String, request_json: String) -> Result<String> {
+        let symbol_manager = self.symbol_manager.clone();
+        let response = symbol_manager.query_json_api(&url, &request_json).await;
+        serde_json::to_string(&response).map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Looks up library-relative addresses in the library with the given
+    /// debug name and breakpad ID. Resolves to one result per address, or
+    /// null for addresses without a symbol. Addresses must be non-negative
+    /// integers, and can be above 4 GiB for large libraries.
+    #[napi]
+    pub async fn lookup_addresses(
+        &self,
+        debug_name: String,
+        breakpad_id: String,
+        addresses: Vec<f64>,
+    ) -> Result<Vec<Option<LookupResult>>> {
+        let debug_id = parse_breakpad_id(&breakpad_id)?;
+        let addresses = parse_addresses(&addresses)?;
+        let symbol_manager = self.symbol_manager.clone();
+        let symbol_map = symbol_manager
+            .load_symbol_map(&debug_name, debug_id)
+            .await
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+        Ok(lookup_addresses(&symbol_map, &addresses).await)
+    }
+
+    /// Like `lookupAddresses`, for the binary at the given path.
+    #[napi]
+    pub async fn lookup_addresses_for_binary(
+        &self,
+        path: String,
+        addresses: Vec<f64>,
+    ) -> Result<Vec<Option<LookupResult>>> {
+        let addresses = parse_addresses(&addresses)?;
+        let symbol_manager = self.symbol_manager.clone();
+        let symbol_map = symbol_manager
+            .load_symbol_map_for_binary_at_path(Path::new(&path), None)
+            .await
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+        Ok(lookup_addresses(&symbol_map, &addresses).await)
+    }
+}
+
+fn config_from_options(options: SymbolManagerOptions) -> SymbolManagerConfig {
+    let mut config = SymbolManagerConfig::new()
+        .respect_nt_symbol_path(options.respect_nt_symbol_path.unwrap_or(false))
+        .symbol_map_cache_capacity(options.symbol_map_cache_capacity.unwrap_or(0) as usize);
+    for dir in options.symbol_dirs.unwrap_or_default() {
+        config = config.extra_symbol_directory(dir);
+    }
+    for dir in options.breakpad_symbol_dirs.unwrap_or_default() {
+        config = config.breakpad_symbol_dir(dir);
+    }
+    for server in options.breakpad_symbol_servers.unwrap_or_default() {
+        config = config.breakpad_symbol_server(server.url, server.cache_dir);
+    }
+    for server in options.windows_symbol_servers.unwrap_or_default() {
+        config = config.windows_symbol_server(server.url, server.cache_dir);
+    }
+    for server in options.debuginfod_servers.unwrap_or_default() {
+        config = config.extra_debuginfod_server(server.url, server.cache_dir);
+    }
+    config
+}
+
+fn parse_breakpad_id(breakpad_id: &str) -> Result<DebugId> {
+    DebugId::from_breakpad(breakpad_id)
+        .map_err(|_| Error::from_reason(format!("Invalid breakpad ID {breakpad_id:?}")))
+}
+
+/// The largest integer which a JS number represents exactly, 2^53 - 1.
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+fn parse_addresses(addresses: &[f64]) -> Result<Vec<u64>> {
+    addresses
+        .iter()
+        .map(|&address| {
+            if (0.0..=MAX_SAFE_INTEGER).contains(&address) && address.fract() == 0.0 {
+                Ok(address as u64)
+            } else {
+                Err(Error::from_reason(format!("Invalid address {address}")))
+            }
+        })
+        .collect()
+}
+
+fn library_info_from_js(library: JsLibraryInfo) -> Result<LibraryInfo> {
+    let debug_id = library
+        .breakpad_id
+        .as_deref()
+        .map(parse_breakpad_id)
+        .transpose()?;
+    let code_id = library
+        .code_id
+        .as_deref()
+        .map(|code_id| {
+            code_id
+                .parse::<CodeId>()
+                .map_err(|_| Error::from_reason(format!("Invalid code ID {code_id:?}")))
+        })
+        .transpose()?;
+    Ok(LibraryInfo {
+        debug_name: library.debug_name,
+        debug_id,
+        debug_path: library.debug_path,
+        name: library.name,
+        code_id,
+        path: library.path,
+        arch: library.arch,
+        gnu_build_id: None,
+    })
+}
+
+async fn lookup_addresses(symbol_map: &SymbolMap, addresses: &[u64]) -> Vec<Option<LookupResult>> {
+    let mut results = Vec::with_capacity(addresses.len());
+    for &address in addresses {
+        let address_info = symbol_map
+            .lookup_u64(LookupAddress64::Relative(address))
+            .await;
+        results.push(address_info.map(|info| lookup_result(symbol_map, info)));
+    }
+    results
+}
+
+fn lookup_result(symbol_map: &SymbolMap, info: AddressInfo64) -> LookupResult {
+    let frames = info.frames.map(|frames| {
+        frames
+            .into_iter()
+            .map(|frame| LookupFrame {
+                function: frame
+                    .function
+                    .map(|f| symbol_map.resolve_function_name(f).into_owned()),
+                file: frame.file_path.map(|p| {
+                    symbol_map
+                        .resolve_source_file_path(p)
+                        .display_path()
+                        .into_owned()
+                }),
+                line: frame.line_number,
+            })
+            .collect()
+    });
+    LookupResult {
+        symbol_name: symbol_map
+            .resolve_symbol_name(info.symbol.name)
+            .into_owned(),
+        symbol_address: info.symbol.address as f64,
+        symbol_size: info.symbol.size.map(|size| size as f64),
+        frames,
+    }
+}
@@ -1,8 +1,11 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+use samply_symbols::{MappedPathRules, ParseLimits, SourcePathPrefixMap};
 use symsrv::{parse_nt_symbol_path, NtSymbolPathEntry};
 
+use crate::SymbolCache;
+
 /// The configuration of a [`SymbolManager`](crate::SymbolManager).
 ///
 /// Allows specifying various sources of symbol files.
@@ -21,6 +24,11 @@ pub struct SymbolManagerConfig {
     pub(crate) debuginfod_servers: Vec<(String, PathBuf)>,
     pub(crate) extra_symbol_directories: Vec<PathBuf>,
     pub(crate) simpleperf_binary_cache_directories: Vec<PathBuf>,
+    pub(crate) symbol_map_cache_capacity: usize,
+    pub(crate) parse_limits: ParseLimits,
+    pub(crate) source_path_prefix_map: SourcePathPrefixMap,
+    pub(crate) mapped_path_rules: MappedPathRules,
+    pub(crate) symbol_cache: Option<SymbolCache>,
 }
 
 impl SymbolManagerConfig {
@@ -184,4 +192,55 @@ impl SymbolManagerConfig {
         self.simpleperf_binary_cache_directories.push(dir.into());
         self
     }
+
+    /// Keep up to this many loaded symbol maps in memory, so that repeated
+    /// [`load_symbol_map`](crate::SymbolManager::load_symbol_map) calls and
+    /// symbolication API requests for the same library are served without
+    /// reading its debug file again. Defaults to 0, i.e. no caching.
+    ///
+    /// This is ignored if a shared [`symbol_cache`](Self::symbol_cache) is
+    /// set; use [`SymbolCache::with_limits`] to limit its number of entries.
+    pub fn symbol_map_cache_capacity(mut self, capacity: usize) -> Self {
+        self.symbol_map_cache_capacity = capacity;
+        self
+    }
+
+    /// Limit the memory which parsing a symbol file may use, so that malformed
+    /// files fail to load with [`Error::ParseLimitExceeded`](crate::Error::ParseLimitExceeded)
+    /// instead of exhausting memory. Recommended when symbolicating files from
+    /// untrusted sources. Defaults to no limits.
+    pub fn parse_limits(mut self, limits: ParseLimits) -> Self {
+        self.parse_limits = limits;
+        self
+    }
+
+    /// Replace the path prefix `from` with `to` in the source file paths from
+    /// the debug info. This is useful if the code was compiled somewhere else,
+    /// e.g. with `-fdebug-prefix-map` or on a build machine, and the sources
+    /// are available locally under `to`. Can be called multiple times; the
+    /// first matching prefix is used.
+    pub fn remap_source_path_prefix(
+        mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+    ) -> Self {
+        self.source_path_prefix_map.add_rule(from, to);
+        self
+    }
+
+    /// Custom rules for mapping source file paths to [`MappedPath`](crate::MappedPath)s,
+    /// e.g. to map paths in a build directory to files in a repository. These
+    /// rules are tried before the built-in rules.
+    pub fn mapped_path_rules(mut self, rules: MappedPathRules) -> Self {
+        self.mapped_path_rules = rules;
+        self
+    }
+
+    /// Share parsed symbol files with other [`SymbolManager`](crate::SymbolManager)s
+    /// which use the same [`SymbolCache`]. This is useful if many short-lived
+    /// symbol managers are created, e.g. one per request in a server.
+    pub fn symbol_cache(mut self, cache: SymbolCache) -> Self {
+        self.symbol_cache = Some(cache);
+        self
+    }
 }
@@ -333,6 +333,11 @@ impl Helper {
             }
             _ => {}
         }
+        if let Some(gnu_build_id) = lib_info.gnu_build_id.as_ref() {
+            known_libs
+                .by_elf_build_id
+                .insert(gnu_build_id.clone(), lib_info.clone());
+        }
     }
 
     pub fn add_precog_symbol_map(
@@ -464,6 +469,10 @@ impl Helper {
             (Some(CodeId::MachoUuid(uuid)), _) => known_libs.by_mach_uuid.get(uuid),
             _ => None,
         };
+        let known_info = known_info.or_else(|| {
+            let gnu_build_id = info.gnu_build_id.as_ref()?;
+            known_libs.by_elf_build_id.get(gnu_build_id)
+        });
         if let Some(known_info) = known_info {
             info.absorb(known_info);
         }
@@ -485,7 +494,7 @@ impl FileAndPathHelper for Helper {
 
         let mut got_dsym = false;
 
-        if let (Some(debug_path), Some(debug_name)) = (&info.debug_path, &info.debug_name) {
+        if let (Some(debug_path), Some(_)) = (&info.debug_path, &info.debug_name) {
             if let Some(debug_id) = info.debug_id {
                 // First, see if we can find a dSYM file for the binary.
                 if let Some(dsym_path) =
@@ -493,16 +502,7 @@ impl FileAndPathHelper for Helper {
                 {
                     got_dsym = true;
                     paths.push(CandidatePathInfo::SingleFile(
-                        WholesymFileLocation::LocalFile(dsym_path.clone()),
-                    ));
-                    paths.push(CandidatePathInfo::SingleFile(
-                        WholesymFileLocation::LocalFile(
-                            dsym_path
-                                .join("Contents")
-                                .join("Resources")
-                                .join("DWARF")
-                                .join(debug_name),
-                        ),
+                        WholesymFileLocation::LocalFile(dsym_path),
                     ));
                 }
             }
@@ -544,26 +544,15 @@ impl FileAndPathHelper for Helper {
                     crate::moria_mac::locate_dsym_using_spotlight(debug_id.uuid())
                 {
                     paths.push(CandidatePathInfo::SingleFile(
-                        WholesymFileLocation::LocalFile(dsym_path.clone()),
+                        WholesymFileLocation::LocalFile(dsym_path),
                     ));
-                    if let Some(dsym_file_name) = dsym_path.file_name().and_then(|s| s.to_str()) {
-                        paths.push(CandidatePathInfo::SingleFile(
-                            WholesymFileLocation::LocalFile(
-                                dsym_path
-                                    .join("Contents")
-                                    .join("Resources")
-                                    .join("DWARF")
-                                    .join(dsym_file_name.trim_end_matches(".dSYM")),
-                            ),
-                        ));
-                    }
                 }
             }
         }
 
         // Find debuginfo in /usr/lib/debug/.build-id/ etc.
         // <https://sourceware.org/gdb/onlinedocs/gdb/Separate-Debug-Files.html>
-        if let Some(CodeId::ElfBuildId(build_id)) = &info.code_id {
+        if let Some(build_id) = info.elf_build_id() {
             let build_id = build_id.to_string();
             if build_id.len() > 2 {
                 let (two_chars, rest) = build_id.split_at(2);
@@ -623,12 +612,22 @@ impl FileAndPathHelper for Helper {
                 paths.push(CandidatePathInfo::SingleFile(
                     WholesymFileLocation::LocalFile(p),
                 ));
+                let dsym_dir = symbol_dir.join(format!("{debug_name}.dSYM"));
+                if let Some(file) = crate::moria_mac::resolve_dsym_bundle(
+                    &dsym_dir,
+                    info.debug_id,
+                    Some(debug_name),
+                ) {
+                    paths.push(CandidatePathInfo::SingleFile(
+                        WholesymFileLocation::LocalFile(file.dwarf_path),
+                    ));
+                }
             }
         }
 
         if !might_be_fake_jit_file(&info) {
-            if let (Some(_debuginfod_symbol_cache), Some(CodeId::ElfBuildId(build_id))) =
-                (self.debuginfod_downloader.as_ref(), &info.code_id)
+            if let (Some(_debuginfod_symbol_cache), Some(build_id)) =
+                (self.debuginfod_downloader.as_ref(), info.elf_build_id())
             {
                 paths.push(CandidatePathInfo::SingleFile(
                     WholesymFileLocation::DebuginfodDebugFile(build_id.to_owned()),
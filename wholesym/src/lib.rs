@@ -151,15 +151,18 @@ mod verbose_symbol_manager_observer;
 
 pub use config::SymbolManagerConfig;
 pub use download_error::DownloadError;
+pub use moria_mac::{resolve_dsym_bundle, DsymBundleFile};
 pub use samply_debugid::{CodeId, ElfBuildId, PeCodeId};
 pub use samply_symbols;
 pub use samply_symbols::{
-    AddressInfo, Error, ExternalFileAddressInFileRef, ExternalFileAddressRef, ExternalFileRef,
-    ExternalFileSymbolMap, FrameDebugInfo, FramesLookupResult, FunctionNameHandle,
-    FunctionNameIndex, LibraryInfo, LookupAddress, MappedPath, MultiArchDisambiguator,
-    SourceFilePath, SourceFilePathHandle, SourceFilePathIndex, SymbolInfo, SymbolMapGeneration,
-    SymbolNameHandle, SymbolNameIndex, SyncAddressInfo,
+    AddressInfo, AddressInfo64, AddressKind, DyldCacheImage, Error, ExternalFileAddressInFileRef,
+    ExternalFileAddressRef, ExternalFileRef, ExternalFileSymbolMap, FatArchiveMember,
+    FrameDebugInfo, FramesLookupResult, FunctionNameHandle, FunctionNameIndex, LibraryInfo,
+    LookupAddress, LookupAddress64, MappedPath, MappedPathRules, MultiArchDisambiguator,
+    ParseLimits, SourceFilePath, SourceFilePathHandle, SourceFilePathIndex, SourcePathPrefixMap,
+    SymbolInfo, SymbolInfo64, SymbolKind, SymbolMapGeneration, SymbolNameHandle, SymbolNameIndex,
+    SyncAddressInfo, SyncAddressInfo64,
 };
-pub use symbol_manager::{SymbolFileOrigin, SymbolManager, SymbolMap};
+pub use symbol_manager::{SymbolCache, SymbolFileOrigin, SymbolManager, SymbolMap};
 pub use symbol_manager_observer::SymbolManagerObserver;
 pub use verbose_symbol_manager_observer::VerboseSymbolManagerObserver;
@@ -3,10 +3,13 @@
 
 #![warn(clippy::all)]
 
+use std::ffi::OsStr;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use object::Object;
+use debugid::DebugId;
+use object::read::macho::{FatArch, MachOFatFile32, MachOFatFile64};
+use object::{FileKind, Object};
 use samply_symbols::object;
 use uuid::Uuid;
 
@@ -77,23 +80,152 @@ fn try_match_dsym_in_dir(dir: &Path, uuid: Uuid) -> Option<PathBuf> {
 }
 
 fn try_match_dsym(dsym_dir: &Path, uuid: Uuid) -> Option<PathBuf> {
-    // Get path to inner object file.
-    let mut dir_iter = fs::read_dir(dsym_dir.join("Contents/Resources/DWARF")).ok()?;
+    let debug_id = DebugId::from_uuid(uuid);
+    resolve_dsym_bundle(dsym_dir, Some(debug_id), None).map(|file| file.dwarf_path)
+}
 
-    let debug_file_name = dir_iter.next()?.ok()?.path();
+/// A DWARF file in a dSYM bundle, found with [`resolve_dsym_bundle`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DsymBundleFile {
+    /// The path of the DWARF file, `<bundle>/Contents/Resources/DWARF/<name>`.
+    pub dwarf_path: PathBuf,
+    /// The relocations which dsymutil wrote for this file, at
+    /// `<bundle>/Contents/Resources/Relocations/<arch>/<name>.yml`, if present.
+    pub relocations_path: Option<PathBuf>,
+}
 
-    if dir_iter.next().is_some() {
-        return None; // There should only be one file in the `DWARF` directory.
+/// Finds the DWARF file in the dSYM bundle at `dsym_dir`.
+///
+/// A bundle can contain DWARF files for multiple binaries. If `debug_id` is
+/// given, the file whose mach-O UUID matches it is returned, also if it is a
+/// universal binary with multiple architectures. The file called `name` is
+/// checked first. If the bundle's Info.plist lists the UUIDs of its files, as
+/// the dSYMs from Xcode archives do, bundles without the UUID are rejected
+/// without reading their files.
+///
+/// Without `debug_id`, the file called `name` is returned, or the only file in
+/// the bundle.
+pub fn resolve_dsym_bundle(
+    dsym_dir: &Path,
+    debug_id: Option<DebugId>,
+    name: Option<&str>,
+) -> Option<DsymBundleFile> {
+    let resources_dir = dsym_dir.join("Contents").join("Resources");
+    let mut dwarf_paths: Vec<PathBuf> = fs::read_dir(resources_dir.join("DWARF"))
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect();
+    dwarf_paths.sort();
+    if let Some(name) = name {
+        if let Some(index) = dwarf_paths
+            .iter()
+            .position(|path| path.file_name() == Some(OsStr::new(name)))
+        {
+            let path = dwarf_paths.remove(index);
+            dwarf_paths.insert(0, path);
+        }
     }
 
-    // Parse inner object file.
-    let file = fs::read(&debug_file_name).ok()?;
-    let dsym = object::File::parse(&file[..]).ok()?;
+    let (dwarf_path, arch) = match debug_id {
+        Some(debug_id) => {
+            if let Some(plist_uuids) = info_plist_uuids(dsym_dir) {
+                if !plist_uuids.contains(&debug_id.uuid()) {
+                    return None;
+                }
+            }
+            dwarf_paths.into_iter().find_map(|path| {
+                let arch = matching_arch(&path, debug_id)?;
+                Some((path, arch))
+            })?
+        }
+        None => {
+            let is_named = name.is_some_and(|name| {
+                dwarf_paths.first().and_then(|path| path.file_name()) == Some(OsStr::new(name))
+            });
+            if !is_named && dwarf_paths.len() != 1 {
+                return None;
+            }
+            (dwarf_paths.into_iter().next()?, None)
+        }
+    };
+
+    let relocations_path = relocations_path(&resources_dir, &dwarf_path, arch);
+    Some(DsymBundleFile {
+        dwarf_path,
+        relocations_path,
+    })
+}
+
+/// Checks whether the mach-O file, or a member of the universal binary,
+/// has the debug ID. Returns the member's architecture, in the spelling of
+/// dsymutil's relocation directories.
+fn matching_arch(path: &Path, debug_id: DebugId) -> Option<Option<&'static str>> {
+    let file = fs::File::open(path).ok()?;
+    let mmap = unsafe { memmap2::MmapOptions::new().map(&file).ok()? };
+    let data = &mmap[..];
+    let members: Vec<&[u8]> = match FileKind::parse(data).ok()? {
+        FileKind::MachOFat32 => MachOFatFile32::parse(data)
+            .ok()?
+            .arches()
+            .iter()
+            .filter_map(|arch| arch.data(data).ok())
+            .collect(),
+        FileKind::MachOFat64 => MachOFatFile64::parse(data)
+            .ok()?
+            .arches()
+            .iter()
+            .filter_map(|arch| arch.data(data).ok())
+            .collect(),
+        _ => vec![data],
+    };
+    members.into_iter().find_map(|member| {
+        let object = object::File::parse(member).ok()?;
+        let uuid = Uuid::from_bytes(object.mach_uuid().ok()??);
+        if DebugId::from_uuid(uuid) != debug_id {
+            return None;
+        }
+        let arch = match object.architecture() {
+            object::Architecture::Aarch64 => Some("aarch64"),
+            object::Architecture::X86_64 => Some("x86_64"),
+            object::Architecture::I386 => Some("i386"),
+            object::Architecture::Arm => Some("arm"),
+            _ => None,
+        };
+        Some(arch)
+    })
+}
+
+/// The UUIDs which the bundle's Info.plist mentions, or None if it doesn't
+/// mention any. dsymutil doesn't write them, but Xcode archives list the UUID
+/// of each file in the bundle.
+fn info_plist_uuids(dsym_dir: &Path) -> Option<Vec<Uuid>> {
+    let plist = fs::read_to_string(dsym_dir.join("Contents").join("Info.plist")).ok()?;
+    let uuids: Vec<Uuid> = plist
+        .split(|c: char| !c.is_ascii_hexdigit() && c != '-')
+        .filter(|word| word.len() == 36)
+        .filter_map(|word| Uuid::parse_str(word).ok())
+        .collect();
+    (!uuids.is_empty()).then_some(uuids)
+}
 
-    // Make sure the dSYM file matches the object file to find debuginfo for.
-    if dsym.mach_uuid() == Ok(Some(*uuid.as_bytes())) {
-        Some(debug_file_name)
-    } else {
-        None
+fn relocations_path(
+    resources_dir: &Path,
+    dwarf_path: &Path,
+    arch: Option<&str>,
+) -> Option<PathBuf> {
+    let mut file_name = dwarf_path.file_name()?.to_owned();
+    file_name.push(".yml");
+    let relocations_dir = resources_dir.join("Relocations");
+    if let Some(arch) = arch {
+        let path = relocations_dir.join(arch).join(&file_name);
+        return path.is_file().then_some(path);
     }
+    // Without a known architecture, only use the relocations if there's
+    // exactly one file for this DWARF file.
+    let mut paths = fs::read_dir(relocations_dir)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path().join(&file_name)))
+        .filter(|path| path.is_file());
+    let path = paths.next()?;
+    paths.next().is_none().then_some(path)
 }
@@ -161,5 +161,13 @@ fn spotlight_get_dsym_path(bundle: &str) -> Result<String, Error> {
 
 pub fn locate_dsym_using_spotlight(uuid: uuid::Uuid) -> Result<PathBuf, Error> {
     let bundle = spotlight_locate_dsym_bundle(uuid)?;
+    // The bundle can contain the DWARF files of multiple binaries, and the
+    // com_apple_xcode_dsym_paths attribute doesn't say which one has the UUID.
+    let debug_id = debugid::DebugId::from_uuid(uuid);
+    if let Some(file) =
+        crate::moria_mac::resolve_dsym_bundle(Path::new(&bundle), Some(debug_id), None)
+    {
+        return Ok(file.dwarf_path);
+    }
     Ok(Path::new(&bundle).join(spotlight_get_dsym_path(&bundle)?))
 }
@@ -1,13 +1,15 @@
 use std::borrow::Cow;
+use std::ffi::OsStr;
 use std::path::Path;
 use std::sync::Arc;
 
 use debugid::DebugId;
 use samply_symbols::{
-    self, AddressInfo, Error, ExternalFileAddressInFileRef, ExternalFileAddressRef, FrameDebugInfo,
-    FunctionNameHandle, LibraryInfo, LookupAddress, MultiArchDisambiguator, SourceFilePath,
-    SourceFilePathHandle, SymbolMapStringInterner, SymbolMapTrait, SymbolNameHandle,
-    SyncAddressInfo,
+    self, AddressInfo, AddressInfo64, AddressKind, DyldCacheImage, Error,
+    ExternalFileAddressInFileRef, ExternalFileAddressRef, FatArchiveMember, FrameDebugInfo,
+    FunctionNameHandle, LibraryInfo, LookupAddress, LookupAddress64, MultiArchDisambiguator,
+    SourceFilePath, SourceFilePathHandle, SymbolMapStringInterner, SymbolMapTrait,
+    SymbolNameHandle, SyncAddressInfo, SyncAddressInfo64,
 };
 
 use crate::config::SymbolManagerConfig;
@@ -77,10 +79,45 @@ impl SymbolMap {
     /// addresses that need the same `.o` file are grouped together. Then, call
     /// `lookup_external` for each `ExternalFileAddressRef` in the sorted order.
     /// The `SymbolMap` only caches a single `.o` file at a time.
+    ///
+    /// Alternatively, if you load the `.o` files yourself with
+    /// [`SymbolManager::load_external_file`] and register them with
+    /// [`SymbolMap::add_external_file`], `lookup_sync` resolves the addresses
+    /// in these files directly and no `lookup_external` calls are needed.
     pub fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
         self.0.lookup_sync(address)
     }
 
+    /// Returns true if this `SymbolMap` has relative addresses which don't fit into a `u32`.
+    ///
+    /// This is only the case for modules which are larger than 4 GiB. For those,
+    /// use [`SymbolMap::lookup_u64`], [`SymbolMap::lookup_sync_u64`] and
+    /// [`SymbolMap::iter_symbols_u64`]; the `u32` methods don't see symbols
+    /// beyond 4 GiB.
+    pub fn has_u64_relative_addresses(&self) -> bool {
+        self.0.has_u64_relative_addresses()
+    }
+
+    /// Says whether the address is in a code section, a data section, or outside
+    /// of all sections of the binary. This is much cheaper than a lookup, and can
+    /// be used to discard bogus addresses before looking them up.
+    ///
+    /// Returns `None` if the symbol information doesn't include the binary's
+    /// sections, for example if it came from a PDB file or a Breakpad file.
+    pub fn classify_address(&self, address: impl Into<LookupAddress64>) -> Option<AddressKind> {
+        self.0.classify_address(address)
+    }
+
+    /// Like [`SymbolMap::lookup`], but with `u64` relative addresses.
+    pub async fn lookup_u64(&self, address: LookupAddress64) -> Option<AddressInfo64> {
+        self.0.lookup_u64(address).await
+    }
+
+    /// Like [`SymbolMap::lookup_sync`], but with `u64` relative addresses.
+    pub fn lookup_sync_u64(&self, address: LookupAddress64) -> Option<SyncAddressInfo64> {
+        self.0.lookup_sync_u64(address)
+    }
+
     /// Resolve a debug info lookup for which `SymbolMap::lookup_*` returned
     /// [`FramesLookupResult::External`](crate::FramesLookupResult::External).
     ///
@@ -101,6 +138,15 @@ impl SymbolMap {
         self.0.lookup_external(external).await
     }
 
+    /// Register an external file which you loaded with [`SymbolManager::load_external_file`].
+    ///
+    /// Subsequent lookups for addresses in this file, both via [`SymbolMap::lookup_sync`]
+    /// and via [`SymbolMap::lookup`], use the registered file and don't load anything.
+    /// Registered files stay in the `SymbolMap` until it is dropped.
+    pub fn add_external_file(&self, external_file: ExternalFileSymbolMap) {
+        self.0.add_external_file(external_file.0)
+    }
+
     /// Returns an abstract "origin token" which can be passed to [`SymbolManager::load_external_file`]
     /// when resolving [`FramesLookupResult::External`](crate::FramesLookupResult::External) addresses.
     ///
@@ -128,6 +174,11 @@ impl SymbolMap {
         self.0.iter_symbols()
     }
 
+    /// Like [`SymbolMap::iter_symbols`], but with `u64` relative addresses.
+    pub fn iter_symbols_u64(&self) -> Box<dyn Iterator<Item = (u64, Cow<'_, str>)> + '_> {
+        self.0.iter_symbols_u64()
+    }
+
     pub fn resolve_source_file_path(&self, handle: SourceFilePathHandle) -> SourceFilePath<'_> {
         self.0.resolve_source_file_path(handle)
     }
@@ -160,6 +211,73 @@ impl ExternalFileSymbolMap {
     }
 }
 
+/// A cache for parsed symbol files which can be shared between multiple
+/// [`SymbolManager`]s, see [`SymbolManagerConfig::symbol_cache`].
+///
+/// Entries are evicted once the total size of the cached files exceeds the
+/// maximum size, or once the number of entries exceeds the maximum count.
+/// Cloning a `SymbolCache` is cheap; the clones refer to the
+/// same cache.
+#[derive(Clone)]
+pub struct SymbolCache(Arc<samply_symbols::SymbolCache<Helper>>);
+
+impl SymbolCache {
+    /// Create an empty cache which holds files with a total size of up to
+    /// `max_size` bytes.
+    pub fn new(max_size: u64) -> Self {
+        Self(Arc::new(samply_symbols::SymbolCache::new(max_size)))
+    }
+
+    /// Create an empty cache which holds files with a total size of up to
+    /// `max_size` bytes, and up to `max_count` entries. `None` means no limit.
+    pub fn with_limits(max_size: Option<u64>, max_count: Option<usize>) -> Self {
+        Self(Arc::new(samply_symbols::SymbolCache::with_limits(
+            max_size, max_count,
+        )))
+    }
+
+    /// The maximum total size of the cached files, in bytes.
+    pub fn max_size(&self) -> u64 {
+        self.0.max_size()
+    }
+
+    /// The maximum number of cached entries.
+    pub fn max_count(&self) -> usize {
+        self.0.max_count()
+    }
+
+    /// The current number of cached entries.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The current total size of the cached files, in bytes.
+    pub fn size(&self) -> u64 {
+        self.0.size()
+    }
+
+    /// Remove all entries from the cache.
+    pub fn clear(&self) {
+        self.0.clear()
+    }
+}
+
+impl std::fmt::Debug for SymbolCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SymbolCache")
+            .field("max_size", &self.max_size())
+            .field("max_count", &self.max_count())
+            .field("size", &self.size())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
 /// Allows obtaining [`SymbolMap`]s.
 pub struct SymbolManager {
     symbol_manager: samply_symbols::SymbolManager<Helper>,
@@ -168,8 +286,20 @@ pub struct SymbolManager {
 impl SymbolManager {
     /// Create a new `SymbolManager` with the given config.
     pub fn with_config(config: SymbolManagerConfig) -> Self {
+        let symbol_map_cache_capacity = config.symbol_map_cache_capacity;
+        let parse_limits = config.parse_limits;
+        let source_path_prefix_map = config.source_path_prefix_map.clone();
+        let mapped_path_rules = config.mapped_path_rules.clone();
+        let symbol_cache = config.symbol_cache.clone();
         let helper = Helper::with_config(config);
-        let symbol_manager = samply_symbols::SymbolManager::with_helper(helper);
+        let mut symbol_manager = samply_symbols::SymbolManager::with_helper(helper);
+        symbol_manager.set_symbol_map_cache_capacity(symbol_map_cache_capacity);
+        symbol_manager.set_parse_limits(parse_limits);
+        symbol_manager.set_source_path_prefix_map(source_path_prefix_map);
+        symbol_manager.set_mapped_path_rules(mapped_path_rules);
+        if let Some(symbol_cache) = symbol_cache {
+            symbol_manager.set_symbol_cache(symbol_cache.0);
+        }
         Self { symbol_manager }
     }
 
@@ -204,10 +334,29 @@ impl SymbolManager {
     /// On Linux and macOS, this reads the ELF build ID / mach-O UUID, which can
     /// also be used to identify the correct debug file later, or to obtain such a
     /// file from a server (e.g. debuginfod for Linux).
+    ///
+    /// `path` can also be a .dSYM bundle, in which case its DWARF file is used.
+    /// If the bundle contains multiple files, pass a
+    /// [`MultiArchDisambiguator::DebugId`] to pick one.
     pub async fn library_info_for_binary_at_path(
         path: &Path,
         disambiguator: Option<MultiArchDisambiguator>,
     ) -> Result<LibraryInfo, Error> {
+        let dsym_file;
+        let path = if path.extension() == Some(OsStr::new("dSYM")) && path.is_dir() {
+            let debug_id = match &disambiguator {
+                Some(MultiArchDisambiguator::DebugId(debug_id)) => Some(*debug_id),
+                _ => None,
+            };
+            let bundle_name = path.file_stem().and_then(OsStr::to_str);
+            dsym_file = crate::moria_mac::resolve_dsym_bundle(path, debug_id, bundle_name)
+                .ok_or_else(|| {
+                    Error::InvalidInputError("No matching DWARF file in the dSYM bundle")
+                })?;
+            dsym_file.dwarf_path.as_path()
+        } else {
+            path
+        };
         let might_be_in_dyld_shared_cache =
             path.starts_with("/usr/") || path.starts_with("/System/");
 
@@ -239,6 +388,29 @@ impl SymbolManager {
         Ok(binary.library_info())
     }
 
+    /// Lists the members of the fat archive ("universal binary") at the given
+    /// path, with their architecture, UUID and file range. This can be used to
+    /// index all architectures of a macOS binary, and to pick the
+    /// [`MultiArchDisambiguator`] for each of them.
+    pub async fn list_fat_archive_members(path: &Path) -> Result<Vec<FatArchiveMember>, Error> {
+        let symbol_manager = samply_symbols::SymbolManager::with_helper(FileReadOnlyHelper);
+        symbol_manager
+            .list_fat_archive_members(WholesymFileLocation::LocalFile(path.to_owned()))
+            .await
+    }
+
+    /// Lists the images in the dyld shared cache at the given path, e.g.
+    /// `/System/Volumes/Preboot/Cryptexes/OS/System/Library/dyld/dyld_shared_cache_arm64e`,
+    /// with their path, architecture, UUID and location in the cache files.
+    /// Each image's path can be passed to
+    /// [`load_symbol_map_for_binary_at_path`](SymbolManager::load_symbol_map_for_binary_at_path).
+    pub async fn list_dyld_cache_images(path: &Path) -> Result<Vec<DyldCacheImage>, Error> {
+        let symbol_manager = samply_symbols::SymbolManager::with_helper(FileReadOnlyHelper);
+        symbol_manager
+            .list_dyld_cache_images(WholesymFileLocation::LocalFile(path.to_owned()))
+            .await
+    }
+
     pub fn set_observer(&mut self, observer: Option<Arc<dyn SymbolManagerObserver>>) {
         self.symbol_manager.helper().set_observer(observer);
     }
@@ -252,7 +424,7 @@ impl SymbolManager {
     /// information which allows us to find the right debug files for the request.
     /// The list of "known libraries" is this auxiliary information.
     #[cfg(feature = "api")]
-    pub fn add_known_library(&mut self, lib_info: LibraryInfo) {
+    pub fn add_known_library(&self, lib_info: LibraryInfo) {
         self.symbol_manager.helper().add_known_lib(lib_info);
     }
 
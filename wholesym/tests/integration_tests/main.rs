@@ -2,7 +2,7 @@ use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use debugid::DebugId;
-use wholesym::{CodeId, FramesLookupResult, LookupAddress};
+use wholesym::{CodeId, ExternalFileRef, FramesLookupResult, LookupAddress};
 
 fn fixtures_dir() -> PathBuf {
     let this_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -109,6 +109,151 @@ fn exe() {
     assert_eq!(info.arch.as_deref(), Some("x86_64"));
 }
 
+#[test]
+fn fat_archive_members() {
+    let path = fixtures_dir().join("macos-ci").join("firefox");
+    let members =
+        futures::executor::block_on(wholesym::SymbolManager::list_fat_archive_members(&path))
+            .unwrap();
+
+    let arches: Vec<_> = members.iter().map(|m| m.arch.as_deref()).collect();
+    assert_eq!(arches, [Some("x86_64"), Some("arm64")]);
+    for member in &members {
+        let info =
+            futures::executor::block_on(wholesym::SymbolManager::library_info_for_binary_at_path(
+                &path,
+                Some(wholesym::MultiArchDisambiguator::Arch(
+                    member.arch.clone().unwrap(),
+                )),
+            ))
+            .unwrap();
+        assert_eq!(info.debug_id, member.debug_id());
+    }
+
+    let dll_path = fixtures_dir().join("win64-ci").join("mozglue.dll");
+    assert!(
+        futures::executor::block_on(wholesym::SymbolManager::list_fat_archive_members(&dll_path))
+            .is_err()
+    );
+}
+
+#[test]
+fn dsym_bundle() {
+    let dir = fixtures_dir()
+        .join("other")
+        .join("simple-example")
+        .join("out")
+        .join("mac-dsym");
+    let binary_info = futures::executor::block_on(
+        wholesym::SymbolManager::library_info_for_binary_at_path(&dir.join("main"), None),
+    )
+    .unwrap();
+    let bundle = dir.join("main.dSYM");
+    let resources = bundle.join("Contents").join("Resources");
+    let expected = wholesym::DsymBundleFile {
+        dwarf_path: resources.join("DWARF").join("main"),
+        relocations_path: Some(
+            resources
+                .join("Relocations")
+                .join("aarch64")
+                .join("main.yml"),
+        ),
+    };
+    assert_eq!(
+        wholesym::resolve_dsym_bundle(&bundle, binary_info.debug_id, None),
+        Some(expected.clone())
+    );
+    assert_eq!(
+        wholesym::resolve_dsym_bundle(&bundle, None, Some("main")),
+        Some(expected)
+    );
+    assert_eq!(
+        wholesym::resolve_dsym_bundle(&bundle, Some(wholesym::debugid::DebugId::nil()), None),
+        None
+    );
+
+    let dsym_info = futures::executor::block_on(
+        wholesym::SymbolManager::library_info_for_binary_at_path(&bundle, None),
+    )
+    .unwrap();
+    assert_eq!(dsym_info.debug_id, binary_info.debug_id);
+}
+
+#[tokio::test]
+async fn parse_limits() {
+    async fn load(limits: wholesym::ParseLimits) -> Result<wholesym::SymbolMap, wholesym::Error> {
+        let dll_path = fixtures_dir().join("win64-local").join("mozglue.dll");
+        let config = wholesym::SymbolManagerConfig::default().parse_limits(limits);
+        let symbol_manager = wholesym::SymbolManager::with_config(config);
+        symbol_manager
+            .load_symbol_map_for_binary_at_path(&dll_path, None)
+            .await
+    }
+
+    async fn max_frame_count(symbol_map: &wholesym::SymbolMap) -> usize {
+        let addresses: Vec<u32> = symbol_map.iter_symbols().map(|(a, _)| a).collect();
+        let mut max_frame_count = 0;
+        for address in addresses {
+            let info = symbol_map
+                .lookup(LookupAddress::Relative(address + 1))
+                .await;
+            let frame_count = info.and_then(|info| info.frames).map_or(0, |f| f.len());
+            max_frame_count = max_frame_count.max(frame_count);
+        }
+        max_frame_count
+    }
+
+    let too_few_symbols = wholesym::ParseLimits {
+        max_symbol_count: Some(10),
+        ..Default::default()
+    };
+    let err = load(too_few_symbols).await.err().unwrap();
+    assert!(err.to_string().contains("Symbol count"), "{err}");
+
+    // Without limits, some addresses have inlined frames; with an inline depth
+    // of 1, only the outer function is returned.
+    let unlimited = load(wholesym::ParseLimits::default()).await.unwrap();
+    assert!(max_frame_count(&unlimited).await > 1);
+    let shallow_inlines = wholesym::ParseLimits {
+        max_inline_depth: Some(1),
+        ..Default::default()
+    };
+    let limited = load(shallow_inlines).await.unwrap();
+    assert_eq!(max_frame_count(&limited).await, 1);
+}
+
+#[tokio::test]
+async fn symbol_map_cache() {
+    // Load the symbols from a copy of mozglue.pdb, then remove the copy. With a
+    // symbol map cache, the second load doesn't need to read the file again.
+    let symbol_dir = std::env::temp_dir().join(format!("wholesym-cache-{}", std::process::id()));
+    std::fs::create_dir_all(&symbol_dir).unwrap();
+    let pdb_path = symbol_dir.join("mozglue.pdb");
+    std::fs::copy(
+        fixtures_dir().join("win64-ci").join("mozglue.pdb"),
+        &pdb_path,
+    )
+    .unwrap();
+
+    let config = wholesym::SymbolManagerConfig::default()
+        .extra_symbol_directory(&symbol_dir)
+        .symbol_map_cache_capacity(1);
+    let symbol_manager = wholesym::SymbolManager::with_config(config);
+    let debug_id = DebugId::from_breakpad("63C609072D3499F64C4C44205044422E1").unwrap();
+
+    let symbol_map = symbol_manager
+        .load_symbol_map("mozglue.pdb", debug_id)
+        .await
+        .unwrap();
+    std::fs::remove_dir_all(&symbol_dir).unwrap();
+    let cached_symbol_map = symbol_manager
+        .load_symbol_map("mozglue.pdb", debug_id)
+        .await
+        .unwrap();
+    assert_eq!(cached_symbol_map.debug_id(), debug_id);
+    assert_eq!(cached_symbol_map.symbol_count(), symbol_map.symbol_count());
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
 async fn dwz_symbolication() {
     let ls_dir = fixtures_dir().join("other").join("ls-linux");
@@ -428,6 +573,49 @@ mod simple_example {
         .await;
     }
 
+    #[tokio::test]
+    async fn preloaded_external_file() {
+        let mac_oso_dir = fixtures_dir().join("other/simple-example/out/mac-oso");
+        let file1_path =
+            "/Users/mstange/code/samply/fixtures/other/simple-example/out/mac-oso/file1.o";
+        let config = wholesym::SymbolManagerConfig::default()
+            .redirect_path_for_testing(file1_path, mac_oso_dir.join("file1.o"));
+        let symbol_manager = wholesym::SymbolManager::with_config(config);
+        let symbol_map = symbol_manager
+            .load_symbol_map_for_binary_at_path(&mac_oso_dir.join("main"), None)
+            .await
+            .unwrap();
+
+        let address_info = symbol_map
+            .lookup_sync(LookupAddress::Relative(0x3ac0))
+            .unwrap();
+        let Some(FramesLookupResult::External(external)) = address_info.frames else {
+            panic!("Expected an external lookup, got {:?}", address_info.frames);
+        };
+        let ExternalFileRef::MachoExternalObject { file_path } = &external.file_ref else {
+            panic!("Expected an external object file");
+        };
+        assert_eq!(file_path, file1_path);
+
+        let external_file = symbol_manager
+            .load_external_file(&symbol_map.symbol_file_origin(), file_path)
+            .await
+            .unwrap();
+        symbol_map.add_external_file(external_file);
+
+        let address_info = symbol_map
+            .lookup_sync(LookupAddress::Relative(0x3ac0))
+            .unwrap();
+        let Some(FramesLookupResult::Available(frames)) = address_info.frames else {
+            panic!("Expected available frames, got {:?}", address_info.frames);
+        };
+        let function_names: Vec<_> = frames
+            .iter()
+            .map(|frame| symbol_map.resolve_function_name(frame.function.unwrap()))
+            .collect();
+        assert_eq!(function_names, ["file1_func2(int)", "file1_func1(int)"]);
+    }
+
     #[tokio::test]
     async fn run_test_mac_dsym() {
         let mac_dsym_dir = fixtures_dir().join("other/simple-example/out/mac-dsym");